@@ -40,6 +40,16 @@ app.kubernetes.io/version: {{ .Chart.AppVersion | quote }}
 app.kubernetes.io/managed-by: {{ .Release.Service }}
 {{- end }}
 
+{{/*
+Whether agents discover this release's consumer through its Service
+*/}}
+{{- define "vita-agent.consumerDiscovery" -}}
+{{- $d := .Values.agent.consumerDiscovery }}
+{{- if and $d.enabled .Values.consumer.enabled (not .Values.agent.consumerEndpoints) (not .Values.agent.consumerSocket) (not .Values.consumer.tls.secretName) -}}
+true
+{{- end }}
+{{- end }}
+
 {{/*
 Selector labels
 */}}
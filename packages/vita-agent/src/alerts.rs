@@ -0,0 +1,226 @@
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::condition::Condition;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// Longest a hook may run before it is killed
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hook runs waiting for the one before; past it, alerts skip their hook
+const HOOK_QUEUE: usize = 32;
+
+/// A condition the agent alerts on by itself, so a node cut off from the
+/// consumer still says when something is wrong. `when` has the burst rule
+/// form, "type.key < value" or "type.key/other < value".
+#[derive(Clone, Debug, PartialEq, Hash, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub when: String,
+    // Seconds the condition must hold before the alert fires
+    #[serde(default, rename = "for")]
+    pub for_secs: Option<u64>,
+    // Whether ALERT_HOOK runs for this alert; it does when unset
+    #[serde(default)]
+    pub hook: Option<bool>,
+}
+
+impl AlertRule {
+    /// ALERT_RULES, comma-separated `name=condition` (the condition names
+    /// itself when unnamed), each holding ALERT_FOR seconds (0) to fire
+    pub fn from_env() -> Vec<Self> {
+        let for_secs = env::var("ALERT_FOR").ok().and_then(|v| v.parse().ok());
+        env::var("ALERT_RULES").unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(|r| {
+                let (name, when) = match r.split_once('=').filter(|(name, _)| !name.contains(['<', '>'])) {
+                    Some((name, when)) => (name.trim(), when.trim()),
+                    None => (r, r),
+                };
+                Self { name: name.to_string(), when: when.to_string(), for_secs, hook: None }
+            })
+            .collect()
+    }
+}
+
+struct Rule {
+    name: String,
+    condition: Condition,
+    for_: Duration,
+    hook: bool,
+}
+
+/// pod_id, pod_uid, volume, container_id and device of a series
+type SeriesLabels = [Option<Arc<str>>; 5];
+
+struct State {
+    since: Instant,
+    firing: bool,
+}
+
+/// Alert rules checked every cycle. Each series a rule's condition holds
+/// for is its own alert: it fires once the condition has held `for`
+/// seconds and resolves when it stops holding or the series goes away.
+/// Both are node events, and run ALERT_HOOK when one is set.
+pub struct Alerts {
+    rules: Vec<Rule>,
+    states: HashMap<(String, SeriesLabels), State>,
+    hook: Option<SyncSender<Vec<(&'static str, String)>>>,
+}
+
+impl Alerts {
+    /// ALERT_HOOK is the command run on every change, without a shell,
+    /// with the alert in ALERT_* environment variables
+    pub fn new(rules: &[AlertRule]) -> Self {
+        let hook = env::var("ALERT_HOOK").ok().filter(|h| !h.trim().is_empty()).map(|command| {
+            info!("Alert hook: {}", command);
+            let (tx, rx) = mpsc::sync_channel::<Vec<(&'static str, String)>>(HOOK_QUEUE);
+            std::thread::spawn(move || {
+                for vars in rx {
+                    if let Err(e) = run_hook(&command, &vars) {
+                        warn!("⚠️  Alert hook failed: {}", e);
+                    }
+                }
+            });
+            tx
+        });
+        let mut alerts = Self { rules: Vec::new(), states: HashMap::new(), hook };
+        alerts.set_rules(rules);
+        alerts
+    }
+
+    /// Replaces the rules; alerts of rules that are gone end without a
+    /// resolution
+    pub fn set_rules(&mut self, rules: &[AlertRule]) {
+        self.rules = rules.iter()
+            .filter_map(|rule| {
+                let Some(condition) = Condition::parse(&rule.when) else {
+                    warn!("Ignoring alert {:?}, expected type.key < value: {:?}", rule.name, rule.when);
+                    return None;
+                };
+                let for_ = Duration::from_secs(rule.for_secs.unwrap_or(0));
+                info!("Alert {}: {} for {:?}", rule.name, condition.when, for_);
+                Some(Rule { name: rule.name.clone(), condition, for_, hook: rule.hook.unwrap_or(true) })
+            })
+            .collect();
+        let names: HashSet<&str> = self.rules.iter().map(|r| r.name.as_str()).collect();
+        self.states.retain(|(name, _), _| names.contains(name.as_str()));
+    }
+
+    /// Checks this cycle's metrics against the rules and reports what
+    /// started or stopped firing
+    pub fn evaluate(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        let now = Instant::now();
+        let mut changes: Vec<(&'static str, usize, SeriesLabels, Option<f64>)> = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            // A type this cycle didn't collect says nothing either way
+            if !sender.cycle_metrics().any(|m| *m.metric_type == *rule.condition.metric_type()) {
+                continue;
+            }
+            let mut seen = HashSet::new();
+            for (m, value) in rule.condition.values(sender) {
+                let labels: SeriesLabels = [m.pod_id.clone(), m.pod_uid.clone(), m.volume.clone(), m.container_id.clone(), m.device.clone()];
+                seen.insert(labels.clone());
+                let key = (rule.name.clone(), labels.clone());
+                if rule.condition.holds(value) {
+                    let state = self.states.entry(key).or_insert(State { since: now, firing: false });
+                    if !state.firing && now.duration_since(state.since) >= rule.for_ {
+                        state.firing = true;
+                        changes.push(("alert_firing", i, labels, Some(value)));
+                    }
+                } else if self.states.remove(&key).is_some_and(|s| s.firing) {
+                    changes.push(("alert_resolved", i, labels, Some(value)));
+                }
+            }
+            self.states.retain(|(name, labels), state| {
+                if *name != rule.name || seen.contains(labels) {
+                    return true;
+                }
+                if state.firing {
+                    changes.push(("alert_resolved", i, labels.clone(), None));
+                }
+                false
+            });
+        }
+
+        for (kind, i, labels, value) in changes {
+            let rule = &self.rules[i];
+            let [pod_id, pod_uid, volume, container_id, device] = labels.each_ref().map(|l| l.as_deref());
+            let message = match (kind, value) {
+                ("alert_firing", Some(value)) if rule.name == rule.condition.when => format!("{} at {}", rule.name, value),
+                ("alert_firing", Some(value)) => format!("{}: {} at {}", rule.name, rule.condition.when, value),
+                (_, Some(value)) => format!("{}: resolved at {}", rule.name, value),
+                (_, None) => format!("{}: resolved, series gone", rule.name),
+            };
+            sender.event(kind, &Labels { pod_id, pod_uid, volume, container_id, device }, message.clone());
+
+            let Some(hook) = self.hook.as_ref().filter(|_| rule.hook) else { continue };
+            let mut vars = vec![
+                ("ALERT_NAME", rule.name.clone()),
+                ("ALERT_STATE", if kind == "alert_firing" { "firing" } else { "resolved" }.to_string()),
+                ("ALERT_CONDITION", rule.condition.when.clone()),
+                ("ALERT_MESSAGE", message),
+                ("ALERT_NODE", node_name.to_string()),
+            ];
+            if let Some(value) = value {
+                vars.push(("ALERT_VALUE", value.to_string()));
+            }
+            for (name, label) in [("ALERT_POD_ID", pod_id), ("ALERT_POD_UID", pod_uid), ("ALERT_VOLUME", volume),
+                ("ALERT_CONTAINER_ID", container_id), ("ALERT_DEVICE", device)] {
+                if let Some(label) = label {
+                    vars.push((name, label.to_string()));
+                }
+            }
+            if let Err(TrySendError::Full(_)) = hook.try_send(vars) {
+                warn!("Alert hook is {} runs behind, not running it for {}", HOOK_QUEUE, rule.name);
+            }
+        }
+
+        for rule in &self.rules {
+            let firing = self.states.iter().filter(|((name, _), s)| *name == rule.name && s.firing).count();
+            info!("METRIC_TYPE=agent_alert node={} device={} firing={}", node_name, rule.name, firing);
+            sender.add("agent_alert", &Labels { device: Some(&rule.name), ..Default::default() }, "firing", firing as f64);
+        }
+    }
+}
+
+/// Runs the hook with nothing of the agent's environment but PATH, and no
+/// stdin, killing it past HOOK_TIMEOUT
+fn run_hook(command: &str, vars: &[(&'static str, String)]) -> anyhow::Result<()> {
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let mut child = Command::new(program)
+        .args(words)
+        .env_clear()
+        .env("PATH", env::var_os("PATH").unwrap_or_default())
+        .envs(vars.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= HOOK_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("still running after {:?}, killed", HOOK_TIMEOUT);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    if !status.success() {
+        anyhow::bail!("exited with {}", status);
+    }
+    debug!("Alert hook ran in {:?}", started.elapsed());
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::capabilities::Capabilities;
+use crate::container_metrics::ContainerCollector;
+use crate::errors;
+use crate::metrics_sender::MetricsSender;
+use crate::pvc_metrics::VolumeCollector;
+use crate::system_metrics::SystemCollector;
+
+/// System allocator that counts allocations, so `bench` can report them.
+/// Counting is two relaxed atomic adds per allocation.
+pub struct CountingAlloc;
+
+static ALLOCS: AtomicU64 = AtomicU64::new(0);
+static ALLOC_BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        ALLOC_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        ALLOC_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Read and write syscalls made by the calling thread so far. pread counts
+/// as a read; opens, stats and directory reads are not included.
+fn thread_syscalls() -> Option<u64> {
+    let io = fs::read_to_string("/proc/thread-self/io").ok()?;
+    let field = |name: &str| {
+        io.lines()
+            .find_map(|l| l.strip_prefix(name))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+    Some(field("syscr:")? + field("syscw:")?)
+}
+
+struct Sample {
+    allocs: u64,
+    alloc_bytes: u64,
+    syscalls: Option<u64>,
+}
+
+impl Sample {
+    fn now() -> Self {
+        Self {
+            allocs: ALLOCS.load(Ordering::Relaxed),
+            alloc_bytes: ALLOC_BYTES.load(Ordering::Relaxed),
+            syscalls: thread_syscalls(),
+        }
+    }
+}
+
+/// `vita-agent bench [seconds]`: runs each collector back to back for the
+/// given time and prints per-cycle cost. Collected metrics are discarded.
+pub fn run(node_name: &str, secs: u64) -> Result<()> {
+    let duration = Duration::from_secs(secs);
+    let mut sender = MetricsSender::new(String::new(), node_name.to_string());
+
+    println!("Benchmarking collectors for {}s each on node {}", secs, node_name);
+    println!("{:<12} {:>10} {:>12} {:>10} {:>14} {:>10} {:>14}",
+        "collector", "cycles", "wall/cycle", "metrics", "syscalls/cycle", "allocs", "alloc_bytes");
+
+    let mut system = SystemCollector::new(&Capabilities::detect(false));
+    bench_one("system", duration, &mut sender, |s| system.collect(node_name, s))?;
+
+    let mut containers = ContainerCollector::new();
+    bench_one("containers", duration, &mut sender, |s| containers.collect(node_name, None, s))?;
+
+    let mut volumes = VolumeCollector::new();
+    bench_one("volumes", duration, &mut sender, |s| volumes.collect(node_name, None, s))?;
+
+    Ok(())
+}
+
+fn bench_one(
+    name: &str,
+    duration: Duration,
+    sender: &mut MetricsSender,
+    mut collect: impl FnMut(&mut MetricsSender) -> errors::Result<()>,
+) -> Result<()> {
+    // Warm-up cycle: discovery, first opens and buffer growth
+    collect(sender)?;
+    sender.discard();
+
+    let before = Sample::now();
+    let start = Instant::now();
+    let mut cycles = 0u64;
+    let mut metrics = 0u64;
+
+    while start.elapsed() < duration {
+        collect(sender)?;
+        metrics += sender.pending() as u64;
+        sender.discard();
+        cycles += 1;
+    }
+
+    let wall = start.elapsed();
+    let after = Sample::now();
+    let cycles = cycles.max(1);
+
+    let syscalls = match (before.syscalls, after.syscalls) {
+        (Some(b), Some(a)) => format!("{:.1}", (a - b) as f64 / cycles as f64),
+        _ => "n/a".to_string(),
+    };
+
+    println!("{:<12} {:>10} {:>12} {:>10} {:>14} {:>10.1} {:>14.0}",
+        name,
+        cycles,
+        format!("{:.1?}", Duration::from_secs_f64(wall.as_secs_f64() / cycles as f64)),
+        metrics / cycles,
+        syscalls,
+        (after.allocs - before.allocs) as f64 / cycles as f64,
+        (after.alloc_bytes - before.alloc_bytes) as f64 / cycles as f64);
+
+    Ok(())
+}
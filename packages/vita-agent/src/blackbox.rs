@@ -0,0 +1,365 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::dns;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One target to probe from every selected node. `target` picks the probe
+/// by scheme: icmp://host, tcp://host:port, an http:// or https:// URL, or
+/// dns://server/name to resolve name against server.
+#[derive(Clone, Debug, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeTarget {
+    // Reported as the device label; the target when empty
+    #[serde(default)]
+    pub name: String,
+    pub target: String,
+    // Seconds between runs; BLACKBOX_INTERVAL when unset
+    #[serde(default)]
+    pub interval: Option<u64>,
+    // Seconds before a probe counts as failed; 5 when unset
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+impl ProbeTarget {
+    /// BLACKBOX_PROBES, comma-separated targets, each optionally named as
+    /// `name=target`
+    pub fn from_env() -> Vec<Self> {
+        std::env::var("BLACKBOX_PROBES").unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (name, target) = match p.split_once('=') {
+                    // '=' in a URL's query isn't a name
+                    Some((name, target)) if !name.contains("://") => (name, target),
+                    _ => ("", p),
+                };
+                Self { name: name.to_string(), target: target.to_string(), interval: None, timeout: None }
+            })
+            .collect()
+    }
+
+    fn name(&self) -> &str {
+        if self.name.is_empty() { &self.target } else { &self.name }
+    }
+}
+
+enum Probe {
+    Icmp(String),
+    Tcp(String),
+    Http(String),
+    Dns(String, String),
+}
+
+impl Probe {
+    fn parse(target: &str) -> Option<Self> {
+        let (scheme, rest) = target.split_once("://")?;
+        match scheme {
+            "icmp" => Some(Self::Icmp(rest.trim_end_matches('/').to_string())),
+            "tcp" => Some(Self::Tcp(rest.trim_end_matches('/').to_string())),
+            "http" | "https" => Some(Self::Http(target.to_string())),
+            "dns" => {
+                let (server, name) = rest.split_once('/')?;
+                Some(Self::Dns(server.to_string(), name.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the target is up once it answered, and the status it
+    /// answered with for HTTP and DNS probes
+    async fn run(&self, client: &reqwest::Client, timeout: Duration) -> anyhow::Result<(bool, Option<Status>)> {
+        match self {
+            Self::Tcp(address) => {
+                TcpStream::connect(address.as_str()).await?;
+                Ok((true, None))
+            }
+            Self::Http(url) => {
+                let status = client.get(url).send().await?.status().as_u16();
+                Ok((status < 400, Some(("http_status", status))))
+            }
+            // An answer without records counts as down: the name is meant to resolve
+            Self::Dns(server, name) => {
+                let server = server.parse::<SocketAddr>()
+                    .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+                    .map_err(|_| anyhow::anyhow!("{} isn't an address", server))?;
+                let (rcode, answers) = dns::query(server, name).await?;
+                Ok((rcode == 0 && answers > 0, Some(("dns_rcode", rcode as u16))))
+            }
+            Self::Icmp(host) => {
+                let ip = match host.parse::<IpAddr>() {
+                    Ok(ip) => ip,
+                    Err(_) => tokio::net::lookup_host((host.as_str(), 0)).await?
+                        .map(|a: SocketAddr| a.ip())
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("{} doesn't resolve", host))?,
+                };
+                ping(ip, timeout).await?;
+                Ok((true, None))
+            }
+        }
+    }
+}
+
+/// Key and value of what a target answered: the HTTP status, or the DNS
+/// response code
+type Status = (&'static str, u16);
+
+/// What one probe run found
+struct Outcome {
+    target: String,
+    up: bool,
+    latency: Duration,
+    // HTTP and DNS probes only
+    status: Option<Status>,
+}
+
+/// Blackbox probes of configured targets, run from the node so the
+/// DaemonSet as a whole sees which nodes can reach what. Probes run on a
+/// task of their own, each on its own interval, and every result is
+/// reported once, by the next collection cycle after it lands.
+pub struct Blackbox {
+    // Run whatever the configured targets are, as the DNS probes
+    fixed: Vec<ProbeTarget>,
+    targets: watch::Sender<Vec<ProbeTarget>>,
+    results: Arc<Mutex<BTreeMap<String, Outcome>>>,
+}
+
+impl Blackbox {
+    pub fn spawn(fixed: Vec<ProbeTarget>, targets: Vec<ProbeTarget>, interval: Duration) -> Self {
+        let results: Arc<Mutex<BTreeMap<String, Outcome>>> = Arc::default();
+        let (tx, mut rx) = watch::channel(Vec::<ProbeTarget>::new());
+        let shared = results.clone();
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap_or_default();
+        tokio::spawn(async move {
+            let mut last: BTreeMap<String, Instant> = BTreeMap::new();
+            loop {
+                let targets = rx.borrow_and_update().clone();
+                last.retain(|name, _| targets.iter().any(|t| t.name() == name));
+                for target in targets {
+                    let every = target.interval.filter(|&s| s > 0).map_or(interval, Duration::from_secs);
+                    if last.get(target.name()).is_some_and(|t| t.elapsed() < every) {
+                        continue;
+                    }
+                    last.insert(target.name().to_string(), Instant::now());
+                    let (client, shared) = (client.clone(), shared.clone());
+                    tokio::spawn(async move {
+                        if let Some(outcome) = run(&client, &target).await {
+                            shared.lock().unwrap().insert(target.name().to_string(), outcome);
+                        }
+                    });
+                }
+                // Changed targets are picked up at once
+                let _ = tokio::time::timeout(Duration::from_secs(1), rx.changed()).await;
+            }
+        });
+        let blackbox = Self { fixed, targets: tx, results };
+        blackbox.set_targets(targets);
+        blackbox
+    }
+
+    pub fn set_targets(&self, targets: Vec<ProbeTarget>) {
+        let targets: Vec<ProbeTarget> = self.fixed.iter().cloned().chain(targets)
+            .filter(|t| {
+                let known = Probe::parse(&t.target).is_some();
+                if !known {
+                    warn!("Blackbox probe {}: unknown target {}", t.name(), t.target);
+                }
+                known
+            })
+            .collect();
+        info!("Blackbox probes: {}", targets.iter().map(|t| t.name()).collect::<Vec<_>>().join(" "));
+        self.targets.send_if_modified(|current| {
+            if *current == targets {
+                return false;
+            }
+            *current = targets;
+            true
+        });
+    }
+
+    pub fn collect(&self, node_name: &str, sender: &mut MetricsSender) {
+        let results = std::mem::take(&mut *self.results.lock().unwrap());
+        for (name, outcome) in &results {
+            let latency_ms = outcome.latency.as_secs_f64() * 1000.0;
+            info!("METRIC_TYPE=probe node={} probe={} target={} up={} latency_ms={:.1} status={}",
+                node_name, name, outcome.target, outcome.up as u8, latency_ms,
+                outcome.status.map_or("-".to_string(), |(_, s)| s.to_string()));
+
+            let labels = Labels { device: Some(name), ..Default::default() };
+            sender.add("probe", &labels, "up", outcome.up as u8 as f64);
+            if outcome.up {
+                sender.add("probe", &labels, "latency_ms", latency_ms);
+            }
+            if let Some((key, status)) = outcome.status {
+                sender.add("probe", &labels, key, status as f64);
+            }
+        }
+    }
+}
+
+async fn run(client: &reqwest::Client, target: &ProbeTarget) -> Option<Outcome> {
+    let probe = Probe::parse(&target.target)?;
+    let timeout = target.timeout.filter(|&s| s > 0).map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, probe.run(client, timeout)).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("no answer in {:?}", timeout)),
+    };
+    let latency = started.elapsed();
+    let (up, status) = match result {
+        Ok(answered) => answered,
+        Err(e) => {
+            debug!("Blackbox probe {} failed: {}", target.name(), e);
+            (false, None)
+        }
+    };
+    Some(Outcome { target: target.target.clone(), up, latency, status })
+}
+
+#[cfg(unix)]
+async fn ping(ip: IpAddr, timeout: Duration) -> anyhow::Result<()> {
+    Ok(tokio::task::spawn_blocking(move || icmp::echo(ip, timeout)).await??)
+}
+
+#[cfg(windows)]
+async fn ping(_ip: IpAddr, _timeout: Duration) -> anyhow::Result<()> {
+    anyhow::bail!("ICMP probes aren't supported on Windows")
+}
+
+/// One ICMP echo over a ping socket (SOCK_DGRAM, allowed by
+/// net.ipv4.ping_group_range), or a raw one where that is refused and the
+/// agent has CAP_NET_RAW
+#[cfg(unix)]
+mod icmp {
+    use std::io;
+    use std::net::IpAddr;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::time::{Duration, Instant};
+
+    const ECHO_REQUEST_V4: u8 = 8;
+    const ECHO_REPLY_V4: u8 = 0;
+    const ECHO_REQUEST_V6: u8 = 128;
+    const ECHO_REPLY_V6: u8 = 129;
+
+    static SEQ: AtomicU16 = AtomicU16::new(0);
+
+    pub fn echo(ip: IpAddr, timeout: Duration) -> io::Result<()> {
+        let (domain, protocol, request, reply) = match ip {
+            IpAddr::V4(_) => (libc::AF_INET, libc::IPPROTO_ICMP, ECHO_REQUEST_V4, ECHO_REPLY_V4),
+            IpAddr::V6(_) => (libc::AF_INET6, libc::IPPROTO_ICMPV6, ECHO_REQUEST_V6, ECHO_REPLY_V6),
+        };
+        let (socket, raw) = match open(domain, libc::SOCK_DGRAM, protocol) {
+            Ok(socket) => (socket, false),
+            Err(_) => (open(domain, libc::SOCK_RAW, protocol)?, true),
+        };
+        let wait = libc::timeval { tv_sec: timeout.as_secs() as _, tv_usec: timeout.subsec_micros() as _ };
+        unsafe {
+            libc::setsockopt(socket.as_raw_fd(), libc::SOL_SOCKET, libc::SO_RCVTIMEO,
+                (&wait as *const libc::timeval).cast(), std::mem::size_of::<libc::timeval>() as _);
+        }
+
+        // Ping sockets replace the identifier with their own
+        let id = std::process::id() as u16;
+        let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+        let mut packet = [0u8; 24];
+        packet[0] = request;
+        packet[4..6].copy_from_slice(&id.to_be_bytes());
+        packet[6..8].copy_from_slice(&seq.to_be_bytes());
+        packet[8..].copy_from_slice(b"vita-agent probe");
+        // The kernel fills in ICMPv6 checksums
+        if ip.is_ipv4() {
+            let sum = checksum(&packet);
+            packet[2..4].copy_from_slice(&sum.to_be_bytes());
+        }
+        send_to(&socket, &packet, ip)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0u8; 1500];
+        while Instant::now() < deadline {
+            let n = unsafe { libc::recv(socket.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::WouldBlock {
+                    break;
+                }
+                return Err(e);
+            }
+            let mut message = &buf[..n as usize];
+            // Raw IPv4 sockets, and ping sockets on macOS, keep the IP header
+            if ip.is_ipv4() && message.first().is_some_and(|b| b >> 4 == 4) {
+                let header = (message[0] & 0x0f) as usize * 4;
+                message = message.get(header..).unwrap_or_default();
+            }
+            if message.len() < 8 || message[0] != reply {
+                continue;
+            }
+            let same_seq = message[6..8] == seq.to_be_bytes();
+            let same_id = !raw || message[4..6] == id.to_be_bytes();
+            if same_seq && same_id {
+                return Ok(());
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "no echo reply"))
+    }
+
+    fn open(domain: libc::c_int, kind: libc::c_int, protocol: libc::c_int) -> io::Result<OwnedFd> {
+        let fd = unsafe { libc::socket(domain, kind, protocol) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn send_to(socket: &OwnedFd, packet: &[u8], ip: IpAddr) -> io::Result<()> {
+        let sent = match ip {
+            IpAddr::V4(v4) => {
+                let mut addr: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+                addr.sin_family = libc::AF_INET as _;
+                addr.sin_addr = libc::in_addr { s_addr: u32::from_ne_bytes(v4.octets()) };
+                unsafe {
+                    libc::sendto(socket.as_raw_fd(), packet.as_ptr().cast(), packet.len(), 0,
+                        (&addr as *const libc::sockaddr_in).cast(), std::mem::size_of::<libc::sockaddr_in>() as _)
+                }
+            }
+            IpAddr::V6(v6) => {
+                let mut addr: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+                addr.sin6_family = libc::AF_INET6 as _;
+                addr.sin6_addr = libc::in6_addr { s6_addr: v6.octets() };
+                unsafe {
+                    libc::sendto(socket.as_raw_fd(), packet.as_ptr().cast(), packet.len(), 0,
+                        (&addr as *const libc::sockaddr_in6).cast(), std::mem::size_of::<libc::sockaddr_in6>() as _)
+                }
+            }
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// RFC 1071 internet checksum
+    fn checksum(data: &[u8]) -> u16 {
+        let mut sum: u32 = data.chunks(2)
+            .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+            .sum();
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+}
@@ -0,0 +1,243 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::condition::Condition;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(250);
+const DEFAULT_DURATION: Duration = Duration::from_secs(120);
+
+/// Fastest burst sampling allowed, whatever a rule asks for
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A sample this close to the next cycle is left to the cycle
+const SLACK: Duration = Duration::from_millis(50);
+
+/// Pods bursting at once; triggers past it wait for one to end
+const MAX_PODS: usize = 16;
+
+/// A condition that switches to fast sampling while it holds. `when` is
+/// "type.key < value", or "type.key/other < value" for the ratio of two
+/// keys of the same series, and is checked against every series of the type
+/// each cycle. A pod's series burst that pod's cgroups, others the node.
+#[derive(Clone, Debug, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BurstRule {
+    pub when: String,
+    #[serde(default)]
+    pub interval_ms: Option<u64>,
+    // Seconds the burst lasts after the condition last held
+    #[serde(default)]
+    pub duration: Option<u64>,
+}
+
+impl BurstRule {
+    /// BURST_RULES, conditions separated by commas, each sampling every
+    /// BURST_INTERVAL_MS (250) for BURST_DURATION seconds (120)
+    pub fn from_env() -> Vec<Self> {
+        let interval_ms = env::var("BURST_INTERVAL_MS").ok().and_then(|v| v.parse().ok());
+        let duration = env::var("BURST_DURATION").ok().and_then(|v| v.parse().ok());
+        env::var("BURST_RULES").unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(|when| Self { when: when.to_string(), interval_ms, duration })
+            .collect()
+    }
+}
+
+struct BurstCondition {
+    condition: Condition,
+    interval: Duration,
+    duration: Duration,
+}
+
+impl BurstCondition {
+    fn parse(rule: &BurstRule) -> Option<Self> {
+        let condition = Condition::parse(&rule.when)?;
+        let interval = rule.interval_ms.map_or(DEFAULT_INTERVAL, Duration::from_millis).max(MIN_INTERVAL);
+        let duration = rule.duration.filter(|&d| d > 0).map_or(DEFAULT_DURATION, Duration::from_secs);
+        Some(Self { condition, interval, duration })
+    }
+}
+
+/// What a burst samples: the node's /proc counters, or one pod's cgroups
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Scope {
+    Node,
+    Pod(Arc<str>),
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Scope::Node => write!(f, "the node"),
+            Scope::Pod(pod_id) => write!(f, "pod {}", pod_id),
+        }
+    }
+}
+
+struct Active {
+    when: String,
+    interval: Duration,
+    until: Instant,
+    next: Instant,
+}
+
+/// Scopes due a burst sample
+#[derive(Default)]
+#[cfg_attr(windows, allow(dead_code))]
+pub struct BurstSample {
+    pub node: bool,
+    pub pods: HashSet<String>,
+}
+
+/// Fast sampling of whatever a rule caught misbehaving, for a while after,
+/// so incidents are captured at high resolution without paying for it all
+/// the time. Samples are taken between cycles and go out with the next
+/// cycle's batch, carrying the previous cycle's timestamp and their offset
+/// into it.
+pub struct Bursts {
+    conditions: Vec<BurstCondition>,
+    active: BTreeMap<Scope, Active>,
+    full_warned: bool,
+}
+
+impl Bursts {
+    pub fn new(rules: &[BurstRule]) -> Self {
+        let mut bursts = Self { conditions: Vec::new(), active: BTreeMap::new(), full_warned: false };
+        bursts.set_rules(rules);
+        bursts
+    }
+
+    /// Replaces the rules; bursts under way run their course
+    pub fn set_rules(&mut self, rules: &[BurstRule]) {
+        self.conditions = rules.iter()
+            .filter_map(|rule| {
+                let condition = BurstCondition::parse(rule);
+                if condition.is_none() {
+                    warn!("Ignoring burst rule {:?}, expected type.key < value", rule.when);
+                }
+                condition
+            })
+            .collect();
+        for c in &self.conditions {
+            info!("Burst rule: {} samples every {:?} for {:?}", c.condition.when, c.interval, c.duration);
+        }
+    }
+
+    /// Checks this cycle's metrics against the rules, starting or extending
+    /// bursts where one holds, and reports the bursts under way
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn evaluate(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        let now = Instant::now();
+        self.active.retain(|scope, active| {
+            let over = active.until <= now;
+            if over {
+                info!("Burst over for {}", scope);
+            }
+            !over
+        });
+        // Samples are spaced from the cycle, which reads everything anyway
+        for active in self.active.values_mut() {
+            active.next = now + active.interval;
+        }
+
+        let mut triggered: Vec<(Scope, usize, f64)> = Vec::new();
+        for (i, c) in self.conditions.iter().enumerate() {
+            for (m, value) in c.condition.values(sender) {
+                if c.condition.holds(value) {
+                    let scope = m.pod_id.clone().map_or(Scope::Node, Scope::Pod);
+                    triggered.push((scope, i, value));
+                }
+            }
+        }
+
+        for (scope, i, value) in triggered {
+            let c = &self.conditions[i];
+            let pods = self.active.keys().filter(|s| matches!(s, Scope::Pod(_))).count();
+            match self.active.get_mut(&scope) {
+                Some(active) => {
+                    active.until = active.until.max(now + c.duration);
+                    active.next = active.next.min(now + c.interval);
+                    if c.interval < active.interval {
+                        active.interval = c.interval;
+                        active.when = c.condition.when.clone();
+                    }
+                }
+                None if matches!(scope, Scope::Pod(_)) && pods >= MAX_PODS => {
+                    if !self.full_warned {
+                        warn!("{} pods are bursting already, not starting more", MAX_PODS);
+                        self.full_warned = true;
+                    }
+                }
+                None => {
+                    info!("Burst for {}: {} at {}, sampling every {:?} for {:?}", scope, c.condition.when, value, c.interval, c.duration);
+                    self.active.insert(scope, Active {
+                        when: c.condition.when.clone(),
+                        interval: c.interval,
+                        until: now + c.duration,
+                        next: now + c.interval,
+                    });
+                }
+            }
+        }
+
+        for (scope, active) in &self.active {
+            let seconds_left = active.until.saturating_duration_since(now).as_secs();
+            let labels = match scope {
+                Scope::Node => {
+                    info!("METRIC_TYPE=agent_burst node={} scope=node rule={:?} interval_ms={} seconds_left={}",
+                        node_name, active.when, active.interval.as_millis(), seconds_left);
+                    Labels::default()
+                }
+                Scope::Pod(pod_id) => {
+                    info!("METRIC_TYPE=agent_burst node={} pod_id={} rule={:?} interval_ms={} seconds_left={}",
+                        node_name, pod_id, active.when, active.interval.as_millis(), seconds_left);
+                    Labels { pod_id: Some(pod_id), ..Default::default() }
+                }
+            };
+            sender.add("agent_burst", &labels, "interval_ms", active.interval.as_millis() as f64);
+            sender.add("agent_burst", &labels, "seconds_left", seconds_left as f64);
+        }
+        if self.active.keys().filter(|s| matches!(s, Scope::Pod(_))).count() < MAX_PODS {
+            self.full_warned = false;
+        }
+    }
+
+    /// When the next burst sample is due, if one is before the cycle at
+    /// `next_cycle`
+    pub fn next_sample(&self, next_cycle: Instant) -> Option<Instant> {
+        self.active.values()
+            .filter(|a| a.next < a.until && a.next + SLACK < next_cycle)
+            .map(|a| a.next)
+            .min()
+    }
+
+    /// Scopes whose sample is due now, scheduling their next one
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn take_due(&mut self) -> BurstSample {
+        let now = Instant::now();
+        let mut sample = BurstSample::default();
+        for (scope, active) in &mut self.active {
+            if active.next > now || active.next >= active.until {
+                continue;
+            }
+            // A slow sample skips the ones it overran rather than catching up
+            while active.next <= now {
+                active.next += active.interval;
+            }
+            match scope {
+                Scope::Node => sample.node = true,
+                Scope::Pod(pod_id) => {
+                    sample.pods.insert(pod_id.to_string());
+                }
+            }
+        }
+        sample
+    }
+}
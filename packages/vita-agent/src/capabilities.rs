@@ -0,0 +1,100 @@
+use std::fs::{self, File};
+use std::io::Read;
+use tracing::{info, warn};
+
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// What the agent can read on this node, probed once at startup. Collectors
+/// whose sources are unreadable are switched off instead of failing (and
+/// warning) every cycle, so the agent also runs with reduced privileges.
+pub struct Capabilities {
+    pub proc_stat: bool,
+    pub meminfo: bool,
+    pub diskstats: bool,
+    pub net_dev: bool,
+    pub cgroups: bool,
+    pub kubelet_pods: bool,
+}
+
+fn readable_file(path: &str) -> bool {
+    let mut byte = [0u8; 1];
+    File::open(host::path(path)).and_then(|mut f| f.read(&mut byte)).is_ok()
+}
+
+fn readable_dir(path: &str) -> bool {
+    fs::read_dir(host::path(path)).is_ok()
+}
+
+impl Capabilities {
+    /// In host mode cgroups are the host's services and containers, and
+    /// there are no pod volumes to look for
+    pub fn detect(host_mode: bool) -> Self {
+        // Same layouts the container collector walks
+        #[cfg(not(windows))]
+        let host_cgroups = host_mode.then(crate::host_cgroups::HostCgroupCollector::readable);
+        #[cfg(windows)]
+        let host_cgroups = None;
+        let cgroups = if let Some(readable) = host_cgroups {
+            readable
+        } else if host::path("/sys/fs/cgroup/cgroup.controllers").exists() {
+            readable_dir("/sys/fs/cgroup/kubepods.slice") || readable_dir("/sys/fs/cgroup/kubepods")
+        } else {
+            readable_dir("/sys/fs/cgroup/cpu/kubepods") || readable_dir("/sys/fs/cgroup/cpu/kubepods.slice")
+        };
+
+        let caps = Self {
+            proc_stat: readable_file("/proc/stat"),
+            meminfo: readable_file("/proc/meminfo"),
+            diskstats: readable_file("/proc/diskstats"),
+            net_dev: readable_file("/proc/net/dev"),
+            cgroups,
+            kubelet_pods: !host_mode && readable_dir("/var/lib/kubelet/pods"),
+        };
+
+        for (name, _, ok, effect) in caps.entries() {
+            // Windows nodes have none of these; their collectors use Win32 and CRI
+            if !ok && cfg!(not(windows)) && !(host_mode && name == "kubelet_pods") {
+                warn!("Capability {} unavailable, {} disabled", name, effect);
+            }
+        }
+        info!("Capabilities: {}", caps.entries()
+            .map(|(name, _, ok, _)| format!("{}={}", name, ok as u8))
+            .collect::<Vec<_>>()
+            .join(" "));
+
+        caps
+    }
+
+    /// Nothing readable, for `--local-dev` where no node sources are probed
+    pub fn none() -> Self {
+        Self {
+            proc_stat: false,
+            meminfo: false,
+            diskstats: false,
+            net_dev: false,
+            cgroups: false,
+            kubelet_pods: false,
+        }
+    }
+
+    /// (name, metric key, available, what it disables)
+    fn entries(&self) -> impl Iterator<Item = (&'static str, &'static str, bool, &'static str)> {
+        [
+            ("proc_stat", "cap_proc_stat", self.proc_stat, "node CPU metrics"),
+            ("meminfo", "cap_meminfo", self.meminfo, "node memory metrics"),
+            ("diskstats", "cap_diskstats", self.diskstats, "node disk metrics"),
+            ("net_dev", "cap_net_dev", self.net_dev, "node network metrics"),
+            ("cgroups", "cap_cgroups", self.cgroups, "container metrics"),
+            ("kubelet_pods", "cap_kubelet_pods", self.kubelet_pods, "PVC metrics"),
+        ].into_iter()
+    }
+
+    /// Queues the capability report; 1 = available
+    pub fn report(&self, sender: &mut MetricsSender) {
+        let labels = Labels::default();
+        for (_, key, ok, _) in self.entries() {
+            sender.add("agent_capability", &labels, key, ok as u8 as f64);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use chrono::{NaiveDateTime, Utc};
+use std::env;
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// Where the kubelet keeps its client and serving certificates: the
+/// rotated pairs as *-current.pem symlinks, or a self-signed kubelet.crt
+const KUBELET_PKI: &str = "/var/lib/kubelet/pki";
+
+/// How often certificates are read again; expiry is counted every cycle
+const REREAD: Duration = Duration::from_secs(3600);
+
+/// DER tags
+const SEQUENCE: u8 = 0x30;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const EXPLICIT_VERSION: u8 = 0xa0;
+
+/// Expiry of the certificates node components present, so a kubelet whose
+/// client certificate wasn't rotated is caught before it falls off the API
+/// server. Each file reports its soonest-expiring certificate, since one
+/// expired link breaks a whole chain.
+pub struct CertExpiry {
+    paths: Vec<PathBuf>,
+    // notAfter of each file, as unix seconds
+    expiry: Vec<(String, i64)>,
+    last: Option<Instant>,
+}
+
+impl CertExpiry {
+    /// The kubelet's certificates, plus the files and directories in
+    /// CERT_PATHS (comma-separated); directories count their .crt and .pem
+    /// files
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn from_env() -> Self {
+        let mut paths = vec![host::path(KUBELET_PKI)];
+        paths.extend(env::var("CERT_PATHS").unwrap_or_default()
+            .split(',').map(str::trim).filter(|p| !p.is_empty()).map(host::path));
+        info!("Certificate expiry: {}", paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" "));
+        Self { paths, expiry: Vec::new(), last: None }
+    }
+
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        if self.last.is_none_or(|t| t.elapsed() >= REREAD) {
+            self.last = Some(Instant::now());
+            self.expiry = self.read();
+        }
+        let now = Utc::now().timestamp();
+        for (path, not_after) in &self.expiry {
+            let days_left = (not_after - now) as f64 / 86400.0;
+            info!("METRIC_TYPE=node_cert node={} path={} days_left={:.1} expired={}",
+                node_name, path, days_left, (days_left <= 0.0) as u8);
+
+            let labels = Labels { device: Some(path), ..Default::default() };
+            sender.add("node_cert", &labels, "days_left", days_left);
+            sender.add("node_cert", &labels, "expired", (days_left <= 0.0) as u8 as f64);
+            sender.add("node_cert", &labels, "not_after", *not_after as f64);
+        }
+    }
+
+    fn read(&self) -> Vec<(String, i64)> {
+        let mut files = Vec::new();
+        for path in &self.paths {
+            if path.is_dir() {
+                let mut found: Vec<PathBuf> = fs::read_dir(path).into_iter().flatten().flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|e| e == "crt" || e == "pem"))
+                    .collect();
+                found.sort();
+                files.extend(found);
+            } else {
+                files.push(path.clone());
+            }
+        }
+
+        let mut expiry = Vec::new();
+        for file in files {
+            // Rotated certificates are reached through *-current.pem, the
+            // dated files behind it are history
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with("kubelet-") && !name.ends_with("-current.pem") && file.starts_with(host::path(KUBELET_PKI)) {
+                continue;
+            }
+            // Reported by their path on the host, replayed or not
+            let shown = match file.strip_prefix(host::path("/")) {
+                Ok(relative) => format!("/{}", relative.display()),
+                Err(_) => file.display().to_string(),
+            };
+            match soonest_expiry(&file) {
+                Some(not_after) => expiry.push((shown, not_after)),
+                None => debug!("No certificate in {}", shown),
+            }
+        }
+        expiry
+    }
+}
+
+/// Earliest notAfter among the PEM certificates in a file; None if it
+/// holds none, as key files don't
+fn soonest_expiry(file: &Path) -> Option<i64> {
+    let mut reader = BufReader::new(fs::File::open(file).ok()?);
+    rustls_pemfile::certs(&mut reader).ok()?
+        .iter()
+        .filter_map(|der| not_after(der))
+        .min()
+}
+
+/// notAfter of a DER certificate: Certificate is SEQUENCE { tbsCertificate
+/// SEQUENCE { [0] version OPTIONAL, serialNumber, signature, issuer,
+/// validity SEQUENCE { notBefore, notAfter }, ... }, ... }
+fn not_after(der: &[u8]) -> Option<i64> {
+    let (SEQUENCE, certificate, _) = tlv(der)? else { return None };
+    let (SEQUENCE, mut tbs, _) = tlv(certificate)? else { return None };
+    if tbs.first() == Some(&EXPLICIT_VERSION) {
+        tbs = tlv(tbs)?.2;
+    }
+    // serialNumber, signature, issuer
+    for _ in 0..3 {
+        tbs = tlv(tbs)?.2;
+    }
+    let (SEQUENCE, validity, _) = tlv(tbs)? else { return None };
+    let (_, _, rest) = tlv(validity)?;
+    let (tag, time, _) = tlv(rest)?;
+    let time = std::str::from_utf8(time).ok()?;
+    let parsed = match tag {
+        // Two-digit years: 50 and up are 19xx (RFC 5280)
+        UTC_TIME => {
+            let year: i32 = time.get(..2)?.parse().ok()?;
+            let century = if year >= 50 { "19" } else { "20" };
+            NaiveDateTime::parse_from_str(&format!("{}{}", century, time), "%Y%m%d%H%M%SZ").ok()?
+        }
+        GENERALIZED_TIME => NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M%SZ").ok()?,
+        _ => return None,
+    };
+    Some(parsed.and_utc().timestamp())
+}
+
+/// One DER element: its tag, contents, and what follows it
+fn tlv(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *buf.first()?;
+    let first = *buf.get(1)?;
+    let (len, header) = if first < 0x80 {
+        (first as usize, 2)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let len = buf.get(2..2 + count)?.iter().fold(0usize, |len, b| len << 8 | *b as usize);
+        (len, 2 + count)
+    };
+    let contents = buf.get(header..header + len)?;
+    Some((tag, contents, &buf[header + len..]))
+}
@@ -0,0 +1,250 @@
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::{Event, Namespace, Node, PersistentVolume, PersistentVolumeClaim, Pod, Service};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::{Api, ListParams};
+use kube::Resource;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::errors::Result;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::parsers;
+use crate::workloads;
+
+/// Items per page when listing objects
+const PAGE_SIZE: u32 = 500;
+
+/// Cluster-scoped metrics from the API server: object counts, overall and
+/// per namespace, the PV and PVC inventory and, unless turned off, workload
+/// status. Only the elected leader runs this, and at a slower pace than
+/// node collection since the API server is shared.
+pub struct ClusterCollector {
+    client: kube::Client,
+    interval: Duration,
+    last: Option<Instant>,
+    workloads: bool,
+}
+
+impl ClusterCollector {
+    pub fn new(client: kube::Client, interval: Duration) -> Self {
+        Self { client, interval, last: None, workloads: true }
+    }
+
+    /// Whether to report replicas and pod phases
+    pub fn set_workloads(&mut self, enabled: bool) {
+        self.workloads = enabled;
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    pub async fn collect(&mut self, sender: &mut MetricsSender) -> Result<()> {
+        self.last = Some(Instant::now());
+        self.collect_object_counts(sender).await?;
+        // Listed once for the claims' pods and the pod phases
+        let pods = list_all::<Pod>(&self.client).await?;
+        self.collect_volumes(&pods, sender).await?;
+        if self.workloads {
+            workloads::collect(&self.client, &pods, sender).await?;
+        }
+        Ok(())
+    }
+
+    async fn collect_object_counts(&self, sender: &mut MetricsSender) -> Result<()> {
+        let mut namespaces: BTreeMap<String, BTreeMap<&str, u64>> = BTreeMap::new();
+        let counts = [
+            ("nodes", count::<Node>(&self.client).await?),
+            ("namespaces", count::<Namespace>(&self.client).await?),
+            ("pods", count_by_namespace::<Pod>(&self.client, "pods", &mut namespaces).await?),
+            ("services", count::<Service>(&self.client).await?),
+            ("deployments", count_by_namespace::<Deployment>(&self.client, "deployments", &mut namespaces).await?),
+            ("statefulsets", count::<StatefulSet>(&self.client).await?),
+            ("daemonsets", count::<DaemonSet>(&self.client).await?),
+            ("pvcs", count_by_namespace::<PersistentVolumeClaim>(&self.client, "pvcs", &mut namespaces).await?),
+            ("events", count_by_namespace::<Event>(&self.client, "events", &mut namespaces).await?),
+        ];
+
+        info!("METRIC_TYPE=cluster_objects {}", counts.iter()
+            .map(|(kind, n)| format!("{}={}", kind, n))
+            .collect::<Vec<_>>()
+            .join(" "));
+
+        let labels = Labels::default();
+        for (kind, n) in counts {
+            sender.add("cluster_objects", &labels, kind, n as f64);
+        }
+
+        for (namespace, counts) in &namespaces {
+            info!("METRIC_TYPE=cluster_namespace namespace={} {}", namespace, counts.iter()
+                .map(|(kind, n)| format!("{}={}", kind, n))
+                .collect::<Vec<_>>()
+                .join(" "));
+            let labels = Labels { device: Some(namespace), ..Default::default() };
+            for (kind, n) in counts {
+                sender.add("cluster_namespace", &labels, kind, *n as f64);
+            }
+        }
+        Ok(())
+    }
+
+    /// PVs and PVCs with their capacity, storage class and the pod using
+    /// them. Both carry the PV's name as `volume`, the label node-local
+    /// volume metrics have, so usage joins the claim it belongs to.
+    async fn collect_volumes(&self, pods: &[Pod], sender: &mut MetricsSender) -> Result<()> {
+        // Running or pending pod mounting each claim, by namespace and name
+        let mut users: HashMap<(&str, &str), &str> = HashMap::new();
+        for pod in pods {
+            let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+            if matches!(phase, Some("Succeeded" | "Failed")) {
+                continue;
+            }
+            let (Some(namespace), Some(uid)) = (pod.metadata.namespace.as_deref(), pod.metadata.uid.as_deref()) else { continue };
+            for claim in pod.spec.iter().flat_map(|s| s.volumes.iter().flatten()).filter_map(|v| v.persistent_volume_claim.as_ref()) {
+                users.insert((namespace, &claim.claim_name), uid);
+            }
+        }
+
+        let pvs = list_all::<PersistentVolume>(&self.client).await?;
+        let mut phases: BTreeMap<String, u32> = BTreeMap::new();
+        let mut classes: BTreeMap<Option<&str>, BTreeMap<&str, f64>> = BTreeMap::new();
+        for pv in &pvs {
+            let Some(name) = pv.metadata.name.as_deref() else { continue };
+            let phase = pv.status.as_ref().and_then(|s| s.phase.as_deref()).unwrap_or("Unknown");
+            *phases.entry(phase.to_ascii_lowercase()).or_default() += 1;
+
+            let spec = pv.spec.as_ref();
+            let capacity = storage(spec.and_then(|s| s.capacity.as_ref()));
+            let class = spec.and_then(|s| s.storage_class_name.as_deref()).filter(|c| !c.is_empty());
+            let pod_uid = spec.and_then(|s| s.claim_ref.as_ref())
+                .and_then(|c| users.get(&(c.namespace.as_deref()?, c.name.as_deref()?)).copied());
+            let class_totals = classes.entry(class).or_default();
+            *class_totals.entry("pvs").or_default() += 1.0;
+            *class_totals.entry("capacity_bytes").or_default() += capacity;
+
+            let labels = Labels { volume: Some(name), device: class, pod_uid, ..Default::default() };
+            sender.add("cluster_pv", &labels, "capacity_bytes", capacity);
+            sender.add("cluster_pv", &labels, "bound", (phase == "Bound") as u8 as f64);
+        }
+
+        info!("METRIC_TYPE=cluster_pv total={} {}", pvs.len(), phases.iter()
+            .map(|(phase, n)| format!("{}={}", phase, n))
+            .collect::<Vec<_>>()
+            .join(" "));
+
+        let labels = Labels::default();
+        for (phase, n) in &phases {
+            sender.add("cluster_pv", &labels, &format!("phase_{}", phase), *n as f64);
+        }
+
+        let pvcs = list_all::<PersistentVolumeClaim>(&self.client).await?;
+        let mut pending = 0;
+        for pvc in &pvcs {
+            let (Some(namespace), Some(name)) = (pvc.metadata.namespace.as_deref(), pvc.metadata.name.as_deref()) else { continue };
+            let spec = pvc.spec.as_ref();
+            let status = pvc.status.as_ref();
+            let phase = status.and_then(|s| s.phase.as_deref()).unwrap_or("Unknown");
+            let requested = storage(spec.and_then(|s| s.resources.as_ref()).and_then(|r| r.requests.as_ref()));
+            let capacity = storage(status.and_then(|s| s.capacity.as_ref()));
+            let class = spec.and_then(|s| s.storage_class_name.as_deref()).filter(|c| !c.is_empty());
+            pending += (phase == "Pending") as u32;
+            let class_totals = classes.entry(class).or_default();
+            *class_totals.entry("pvcs").or_default() += 1.0;
+            *class_totals.entry("pending_pvcs").or_default() += (phase == "Pending") as u8 as f64;
+            *class_totals.entry("requested_bytes").or_default() += requested;
+
+            let claim = format!("{}/{}", namespace, name);
+            let labels = Labels {
+                device: Some(&claim),
+                volume: spec.and_then(|s| s.volume_name.as_deref()).filter(|v| !v.is_empty()),
+                pod_uid: users.get(&(namespace, name)).copied(),
+                ..Default::default()
+            };
+            sender.add("cluster_pvc", &labels, "requested_bytes", requested);
+            sender.add("cluster_pvc", &labels, "capacity_bytes", capacity);
+            sender.add("cluster_pvc", &labels, "bound", (phase == "Bound") as u8 as f64);
+            sender.add("cluster_pvc", &labels, "pending", (phase == "Pending") as u8 as f64);
+            sender.add("cluster_pvc", &labels, "lost", (phase == "Lost") as u8 as f64);
+        }
+        info!("METRIC_TYPE=cluster_pvc total={} pending={} in_use={}", pvcs.len(), pending, users.len());
+
+        for (class, totals) in &classes {
+            info!("METRIC_TYPE=cluster_storage_class storage_class={} {}", class.unwrap_or_default(), totals.iter()
+                .map(|(key, n)| format!("{}={}", key, n))
+                .collect::<Vec<_>>()
+                .join(" "));
+            let labels = Labels { device: *class, ..Default::default() };
+            for (key, n) in totals {
+                sender.add("cluster_storage_class", &labels, key, *n);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bytes of the `storage` entry of a capacity or request
+fn storage(resources: Option<&BTreeMap<String, Quantity>>) -> f64 {
+    resources.and_then(|r| r.get("storage")).and_then(|q| parsers::quantity(&q.0)).unwrap_or(0.0)
+}
+
+/// Every object of a kind, listed a page at a time
+pub async fn list_all<K>(client: &kube::Client) -> Result<Vec<K>>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let api = Api::<K>::all(client.clone());
+    let mut params = ListParams::default().limit(PAGE_SIZE);
+    let mut items = Vec::new();
+    loop {
+        let page = api.list(&params).await?;
+        items.extend(page.items);
+        match page.metadata.continue_.filter(|c| !c.is_empty()) {
+            Some(token) => params = params.continue_token(&token),
+            None => return Ok(items),
+        }
+    }
+}
+
+/// Object count from a one-item metadata list: the API server reports how
+/// many items remain, so nothing else is transferred
+async fn count<K>(client: &kube::Client) -> Result<u64>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let list = Api::<K>::all(client.clone())
+        .list_metadata(&ListParams::default().limit(1))
+        .await?;
+    let remaining = list.metadata.remaining_item_count.unwrap_or(0).max(0) as u64;
+    Ok(list.items.len() as u64 + remaining)
+}
+
+/// Object count, adding each namespace's share to `namespaces` under
+/// `kind`. Namespaces need every item, so metadata is listed a page at a
+/// time rather than all at once.
+async fn count_by_namespace<K>(
+    client: &kube::Client,
+    kind: &'static str,
+    namespaces: &mut BTreeMap<String, BTreeMap<&'static str, u64>>,
+) -> Result<u64>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let api = Api::<K>::all(client.clone());
+    let mut params = ListParams::default().limit(PAGE_SIZE);
+    let mut total = 0;
+    loop {
+        let page = api.list_metadata(&params).await?;
+        for item in &page.items {
+            let namespace = item.metadata.namespace.clone().unwrap_or_default();
+            *namespaces.entry(namespace).or_default().entry(kind).or_default() += 1;
+        }
+        total += page.items.len() as u64;
+        match page.metadata.continue_.filter(|c| !c.is_empty()) {
+            Some(token) => params = params.continue_token(&token),
+            None => return Ok(total),
+        }
+    }
+}
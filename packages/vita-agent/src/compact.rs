@@ -0,0 +1,134 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::metrics_sender::{RawMetric, SeriesKey};
+
+/// Content type of compact batches; the consumer keys its series dictionary
+/// on (node, session)
+pub const CONTENT_TYPE: &str = "application/x-vita-compact+json";
+
+struct Series {
+    id: u32,
+    // The consumer has confirmed a batch carrying this definition
+    acked: bool,
+    // Last encode that referenced the series, for pruning
+    last_used: u64,
+}
+
+#[derive(Serialize)]
+struct SeriesDef<'a> {
+    id: u32,
+    #[serde(flatten)]
+    labels: &'a SeriesKey,
+}
+
+#[derive(Serialize)]
+struct CompactBatch<'a> {
+    node: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    node_labels: &'a BTreeMap<String, String>,
+    session: &'a str,
+    series: &'a [SeriesDef<'a>],
+    // [series id, value, ts, offset_us]
+    points: &'a [(u32, f64, i64, u32)],
+}
+
+/// Encodes batches as a label dictionary plus (id, value, ts, offset) points. At
+/// high frequency nearly every label repeats, so after the first batch of a
+/// session only new series carry their labels.
+pub struct CompactEncoder {
+    session: String,
+    series: HashMap<SeriesKey, Series>,
+    next_id: u32,
+    encodes: u64,
+    // Definitions in the last encoded batch, acked together with it
+    sent: Vec<SeriesKey>,
+    points: Vec<(u32, f64, i64, u32)>,
+}
+
+impl CompactEncoder {
+    pub fn new() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+
+        Self {
+            session: format!("{:x}-{:x}", nanos, std::process::id()),
+            series: HashMap::new(),
+            next_id: 0,
+            encodes: 0,
+            sent: Vec::new(),
+            points: Vec::new(),
+        }
+    }
+
+    /// Serializes the batch, defining any series the consumer hasn't acked yet
+    pub fn encode<W: std::io::Write>(
+        &mut self,
+        node: &str,
+        node_labels: &BTreeMap<String, String>,
+        metrics: &[RawMetric],
+        writer: W,
+    ) -> serde_json::Result<()> {
+        self.encodes += 1;
+        self.sent.clear();
+        self.points.clear();
+
+        for m in metrics {
+            let key = SeriesKey::of(m);
+            let series = match self.series.get_mut(&key) {
+                Some(series) => series,
+                None => {
+                    self.next_id += 1;
+                    self.series.entry(key.clone()).or_insert(Series {
+                        id: self.next_id,
+                        acked: false,
+                        last_used: 0,
+                    })
+                }
+            };
+            // Unacked series are defined once per batch
+            if !series.acked && series.last_used != self.encodes {
+                self.sent.push(key);
+            }
+            series.last_used = self.encodes;
+            self.points.push((series.id, m.value, m.ts, m.offset_us));
+        }
+
+        let defs: Vec<SeriesDef> = self.sent.iter()
+            .map(|k| SeriesDef { id: self.series[k].id, labels: k })
+            .collect();
+
+        serde_json::to_writer(writer, &CompactBatch {
+            node,
+            node_labels,
+            session: &self.session,
+            series: &defs,
+            points: &self.points,
+        })
+    }
+
+    /// The last encoded batch was accepted
+    pub fn ack(&mut self) {
+        for key in self.sent.drain(..) {
+            if let Some(series) = self.series.get_mut(&key) {
+                series.acked = true;
+            }
+        }
+    }
+
+    /// The consumer lost the dictionary (restart or a different replica)
+    pub fn resend_all(&mut self) {
+        for series in self.series.values_mut() {
+            series.acked = false;
+        }
+    }
+
+    /// Forgets series unused in the last `window` encodes. Their ids are
+    /// never reused, so a later reappearance just defines a new series.
+    pub fn prune(&mut self, window: u64) {
+        let cutoff = self.encodes.saturating_sub(window);
+        self.series.retain(|_, s| s.last_used > cutoff);
+    }
+}
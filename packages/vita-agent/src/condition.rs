@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::metrics_sender::{MetricsSender, RawMetric};
+
+#[derive(Clone, Copy)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Op::Lt => value < threshold,
+            Op::Le => value <= threshold,
+            Op::Gt => value > threshold,
+            Op::Ge => value >= threshold,
+        }
+    }
+}
+
+/// "type.key < value", or "type.key/other < value" for the ratio of two
+/// keys of the same series, with <, <=, > or >=; checked against every
+/// series of the type in a cycle
+pub struct Condition {
+    pub when: String,
+    metric_type: String,
+    key: String,
+    per: Option<String>,
+    op: Op,
+    threshold: f64,
+}
+
+impl Condition {
+    pub fn parse(when: &str) -> Option<Self> {
+        let when = when.trim();
+        let (at, op, len) = [("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)]
+            .into_iter()
+            .find_map(|(s, op)| when.find(s).map(|at| (at, op, s.len())))?;
+        let threshold = when[at + len..].trim().parse().ok()?;
+        let (metric_type, key) = when[..at].trim().split_once('.')?;
+        let (key, per) = match key.split_once('/') {
+            Some((key, per)) => (key.trim(), Some(per.trim().to_string())),
+            None => (key.trim(), None),
+        };
+        if metric_type.is_empty() || key.is_empty() || per.as_deref() == Some("") {
+            return None;
+        }
+        Some(Self {
+            when: when.to_string(),
+            metric_type: metric_type.trim().to_string(),
+            key: key.to_string(),
+            per,
+            op,
+            threshold,
+        })
+    }
+
+    pub fn metric_type(&self) -> &str {
+        &self.metric_type
+    }
+
+    pub fn holds(&self, value: f64) -> bool {
+        self.op.holds(value, self.threshold)
+    }
+
+    /// This cycle's series the condition applies to, with the value it
+    /// compares; ratios skip series without the other key, or where it is 0
+    pub fn values<'a>(&self, sender: &'a MetricsSender) -> Vec<(&'a RawMetric, f64)> {
+        // Denominators by series, for ratios
+        let mut per: HashMap<[Option<&Arc<str>>; 5], f64> = HashMap::new();
+        if let Some(per_key) = &self.per {
+            for m in sender.cycle_metrics().filter(|m| *m.metric_type == *self.metric_type && *m.key == **per_key) {
+                per.insert([m.pod_id.as_ref(), m.pod_uid.as_ref(), m.volume.as_ref(), m.container_id.as_ref(), m.device.as_ref()], m.value);
+            }
+        }
+        sender.cycle_metrics()
+            .filter(|m| *m.metric_type == *self.metric_type && *m.key == *self.key)
+            .filter_map(|m| match &self.per {
+                Some(_) => {
+                    let series = [m.pod_id.as_ref(), m.pod_uid.as_ref(), m.volume.as_ref(), m.container_id.as_ref(), m.device.as_ref()];
+                    match per.get(&series) {
+                        Some(&d) if d != 0.0 => Some((m, m.value / d)),
+                        _ => None,
+                    }
+                }
+                None => Some((m, m.value)),
+            })
+            .collect()
+    }
+}
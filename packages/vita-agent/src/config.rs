@@ -0,0 +1,262 @@
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Node;
+use kube::api::{Api, ApiResource, DynamicObject, GroupVersionKind};
+use kube::runtime::{reflector, watcher, WatchStreamExt};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::alerts::AlertRule;
+use crate::blackbox::ProbeTarget;
+use crate::burst::BurstRule;
+use crate::derived::DerivedMetric;
+use crate::tiers::{Tier, Tiers};
+
+pub const GROUP: &str = "vitakube.io";
+pub const VERSION: &str = "v1alpha1";
+pub const KIND: &str = "VitaAgentConfig";
+
+/// Effective agent configuration: the environment, overlaid by any
+/// VitaAgentConfig resources selecting this node
+#[derive(Clone, Debug, PartialEq, Hash)]
+pub struct AgentConfig {
+    pub endpoint: String,
+    pub interval_secs: u64,
+    // Per-collector intervals; unset ones run every cycle
+    pub intervals: Intervals,
+    pub compact_wire: bool,
+    pub system: bool,
+    pub containers: bool,
+    pub volumes: bool,
+    // Device and interface name prefixes to skip, on top of the built-in ones
+    pub exclude_devices: Vec<String>,
+    pub exclude_interfaces: Vec<String>,
+    // What gets sampled less and dropped first under pressure
+    pub tiers: Tiers,
+    // Blackbox probes to run from this node
+    pub probes: Vec<ProbeTarget>,
+    // Conditions that switch to fast sampling for a while
+    pub burst: Vec<BurstRule>,
+    // Metrics computed from the collected ones each cycle
+    pub derived: Vec<DerivedMetric>,
+    // Conditions the agent alerts on by itself
+    pub alerts: Vec<AlertRule>,
+}
+
+impl AgentConfig {
+    pub fn from_env() -> Self {
+        Self {
+            endpoint: env::var("CONSUMER_ENDPOINT")
+                .unwrap_or_else(|_| "http://vita-consumer:8080/api/v1/ingest".to_string()),
+            // Default: 1 second for high-frequency monitoring
+            interval_secs: env::var("COLLECTION_INTERVAL")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(1),
+            intervals: Intervals {
+                system: env_interval("SYSTEM_INTERVAL"),
+                containers: env_interval("CONTAINER_INTERVAL"),
+                volumes: env_interval("VOLUME_INTERVAL"),
+                devices: env_interval("DEVICE_INTERVAL"),
+            },
+            compact_wire: env::var("COMPACT_WIRE")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            system: true,
+            containers: true,
+            volumes: true,
+            exclude_devices: Vec::new(),
+            exclude_interfaces: Vec::new(),
+            tiers: Tiers::from_env(),
+            probes: ProbeTarget::from_env(),
+            burst: BurstRule::from_env(),
+            derived: DerivedMetric::from_env(),
+            alerts: AlertRule::from_env(),
+        }
+    }
+
+    /// Seconds between runs of each collector: system, containers, volumes, devices
+    pub fn collector_intervals(&self) -> [u64; 4] {
+        let i = &self.intervals;
+        [i.system, i.containers, i.volumes, i.devices].map(|i| i.unwrap_or(self.interval_secs))
+    }
+
+    fn overlay(&mut self, spec: &ConfigSpec) {
+        if let Some(interval) = spec.collection_interval.filter(|&i| i > 0) {
+            self.interval_secs = interval;
+        }
+        let i = &spec.intervals;
+        for (ours, theirs) in [
+            (&mut self.intervals.system, i.system),
+            (&mut self.intervals.containers, i.containers),
+            (&mut self.intervals.volumes, i.volumes),
+            (&mut self.intervals.devices, i.devices),
+        ] {
+            if let Some(interval) = theirs.filter(|&i| i > 0) {
+                *ours = Some(interval);
+            }
+        }
+        let c = &spec.collectors;
+        self.system = c.system.unwrap_or(self.system);
+        self.containers = c.containers.unwrap_or(self.containers);
+        self.volumes = c.volumes.unwrap_or(self.volumes);
+        if let Some(devices) = &spec.filters.exclude_devices {
+            self.exclude_devices = devices.clone();
+        }
+        if let Some(interfaces) = &spec.filters.exclude_interfaces {
+            self.exclude_interfaces = interfaces.clone();
+        }
+        if let Some(endpoint) = &spec.sink.endpoint {
+            self.endpoint = endpoint.clone();
+        }
+        self.compact_wire = spec.sink.compact_wire.unwrap_or(self.compact_wire);
+        self.tiers.extend(&spec.tiers);
+        if let Some(probes) = &spec.probes {
+            self.probes = probes.clone();
+        }
+        if let Some(burst) = &spec.burst {
+            self.burst = burst.clone();
+        }
+        if let Some(derived) = &spec.derived {
+            self.derived = derived.clone();
+        }
+        if let Some(alerts) = &spec.alerts {
+            self.alerts = alerts.clone();
+        }
+    }
+}
+
+/// Seconds between runs of one collector, when not every cycle
+#[derive(Clone, Debug, Default, PartialEq, Hash, Deserialize)]
+#[serde(default)]
+pub struct Intervals {
+    pub system: Option<u64>,
+    pub containers: Option<u64>,
+    pub volumes: Option<u64>,
+    pub devices: Option<u64>,
+}
+
+fn env_interval(name: &str) -> Option<u64> {
+    env::var(name).ok().and_then(|v| v.parse().ok()).filter(|&i| i > 0)
+}
+
+/// `spec` of a VitaAgentConfig; unset fields leave the value unchanged
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct ConfigSpec {
+    // Node labels that must all match; empty selects every node
+    node_selector: BTreeMap<String, String>,
+    collection_interval: Option<u64>,
+    intervals: Intervals,
+    collectors: Collectors,
+    filters: Filters,
+    sink: Sink,
+    // Tier by metric type, on top of METRIC_TIERS
+    tiers: BTreeMap<String, Tier>,
+    // Replaces BLACKBOX_PROBES
+    probes: Option<Vec<ProbeTarget>>,
+    // Replaces BURST_RULES
+    burst: Option<Vec<BurstRule>>,
+    // Replaces DERIVED_METRICS
+    derived: Option<Vec<DerivedMetric>>,
+    // Replaces ALERT_RULES; the hook stays ALERT_HOOK
+    alerts: Option<Vec<AlertRule>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Collectors {
+    system: Option<bool>,
+    containers: Option<bool>,
+    volumes: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Filters {
+    exclude_devices: Option<Vec<String>>,
+    exclude_interfaces: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Sink {
+    endpoint: Option<String>,
+    compact_wire: Option<bool>,
+}
+
+/// Watches VitaAgentConfig resources in the background. The receiver holds
+/// `base` until a matching resource appears; resources apply in name order,
+/// so a later one overrides the fields it sets.
+pub fn watch(client: kube::Client, base: AgentConfig, node_name: String) -> watch::Receiver<AgentConfig> {
+    let (tx, rx) = watch::channel(base.clone());
+    tokio::spawn(async move {
+        if let Err(e) = run(client, base, &node_name, tx).await {
+            warn!("VitaAgentConfig watch stopped: {}", e);
+        }
+    });
+    rx
+}
+
+async fn run(client: kube::Client, base: AgentConfig, node_name: &str, tx: watch::Sender<AgentConfig>) -> anyhow::Result<()> {
+    // Labels are read once; a relabelled node picks up selectors on restart
+    let node_labels = match Api::<Node>::all(client.clone()).get(node_name).await {
+        Ok(node) => node.metadata.labels.unwrap_or_default(),
+        Err(e) => {
+            warn!("Failed to read labels of node {}, only unselective configs apply: {}", node_name, e);
+            BTreeMap::new()
+        }
+    };
+
+    let resource = ApiResource::from_gvk(&GroupVersionKind::gvk(GROUP, VERSION, KIND));
+    let api = Api::<DynamicObject>::all_with(client, &resource);
+    let writer = reflector::store::Writer::new(resource);
+    let store = writer.as_reader();
+
+    let mut events = reflector(writer, watcher(api, watcher::Config::default()))
+        .default_backoff()
+        .boxed();
+
+    info!("Watching {} resources", KIND);
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(watcher::Event::Init | watcher::Event::InitApply(_)) => continue,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("{} watch error: {}", KIND, e);
+                continue;
+            }
+        }
+
+        let mut objects = store.state();
+        objects.sort_by(|a, b| a.metadata.name.cmp(&b.metadata.name));
+
+        let mut config = base.clone();
+        for obj in &objects {
+            let name = obj.metadata.name.as_deref().unwrap_or_default();
+            let spec = match obj.data.get("spec").cloned().map(serde_json::from_value::<ConfigSpec>) {
+                Some(Ok(spec)) => spec,
+                Some(Err(e)) => {
+                    warn!("Ignoring {} {}: {}", KIND, name, e);
+                    continue;
+                }
+                None => continue,
+            };
+            if spec.node_selector.iter().all(|(k, v)| node_labels.get(k) == Some(v)) {
+                config.overlay(&spec);
+            }
+        }
+
+        tx.send_if_modified(|current| {
+            if *current == config {
+                return false;
+            }
+            *current = config;
+            true
+        });
+    }
+
+    Ok(())
+}
@@ -1,92 +1,660 @@
-use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tracing::{info, warn};
 
-pub fn collect_container_metrics(node_name: &str, _sender: &mut crate::metrics_sender::MetricsSender) -> Result<()> {
-    // Try to detect cgroup version
-    let cgroup_v2 = Path::new("/sys/fs/cgroup/cgroup.controllers").exists();
-    
-    if cgroup_v2 {
-        info!("Generations: Cgroup v2 detected");
-        collect_cgroup_v2_metrics(node_name)?;
-    } else {
-        // info!("Generations: Cgroup v1 detected");
-        collect_cgroup_v1_metrics(node_name)?;
+use crate::cpu_manager::CpuManagerState;
+use crate::host;
+use crate::inotify::DirWatcher;
+use crate::errors::Result;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::parsers;
+use crate::pod_roles::{self, PodRoles};
+use crate::statfile::{self, StatFile};
+
+/// Rediscover the cgroup tree at least this often, even without inotify events
+const REFRESH_EVERY: u32 = 30;
+
+/// Cgroups gone within this long of appearing count as short-lived
+const SHORT_LIVED: Duration = Duration::from_secs(60);
+
+/// A cgroup whose stat files are read every cycle. The files stay open
+/// for as long as the cgroup is tracked.
+enum CgroupTarget {
+    V2Pod {
+        path: PathBuf,
+        pod_id: String,
+        cpu_stat: StatFile,
+        memory_current: StatFile,
+        memory_max: StatFile,
+        memory_stat: StatFile,
+        swap: SwapFiles,
+        cpuset: StatFile,
+        oom: OomCount,
+        freezer: Freezer,
+        seen: Instant,
+    },
+    V1Container {
+        cpu_path: PathBuf,
+        pod_id: String,
+        container_id: String,
+        cpuacct_usage: StatFile,
+        memory_usage: StatFile,
+        memory_limit: StatFile,
+        memory_stat: StatFile,
+        cpuset: StatFile,
+        oom: OomCount,
+        freezer: Freezer,
+        // When discovered, for telling pause containers from new ones, and
+        // short-lived ones once gone
+        seen: Instant,
+    },
+}
+
+impl CgroupTarget {
+    fn v2_pod(path: PathBuf, pod_id: String) -> Self {
+        CgroupTarget::V2Pod {
+            cpu_stat: StatFile::new(path.join("cpu.stat")),
+            memory_current: StatFile::new(path.join("memory.current")),
+            memory_max: StatFile::new(path.join("memory.max")),
+            memory_stat: StatFile::new(path.join("memory.stat")),
+            swap: SwapFiles {
+                current: StatFile::new(path.join("memory.swap.current")),
+                max: StatFile::new(path.join("memory.swap.max")),
+                zswap: StatFile::new(path.join("memory.zswap.current")),
+            },
+            cpuset: StatFile::new(path.join("cpuset.cpus.effective")),
+            oom: OomCount::new(path.join("memory.events")),
+            freezer: Freezer::new(path.join("cgroup.events")),
+            seen: Instant::now(),
+            path,
+            pod_id,
+        }
+    }
+
+    fn v1_container(cpu_path: PathBuf, pod_id: String, container_id: String) -> Self {
+        // Memory lives in the matching memory controller hierarchy
+        let mem_path = cpu_path.to_string_lossy().replace("/cpu/", "/memory/");
+        let mem_path = Path::new(&mem_path);
+        let cpuset_path = cpu_path.to_string_lossy().replace("/cpu/", "/cpuset/");
+        let freezer_path = cpu_path.to_string_lossy().replace("/cpu/", "/freezer/");
+        CgroupTarget::V1Container {
+            cpuacct_usage: StatFile::new(cpu_path.join("cpuacct.usage")),
+            memory_usage: StatFile::new(mem_path.join("memory.usage_in_bytes")),
+            memory_limit: StatFile::new(mem_path.join("memory.limit_in_bytes")),
+            memory_stat: StatFile::new(mem_path.join("memory.stat")),
+            cpuset: StatFile::new(Path::new(&cpuset_path).join("cpuset.effective_cpus")),
+            oom: OomCount::new(mem_path.join("memory.oom_control")),
+            freezer: Freezer::new(Path::new(&freezer_path).join("freezer.state")),
+            seen: Instant::now(),
+            cpu_path,
+            pod_id,
+            container_id,
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            CgroupTarget::V2Pod { path, .. } => path,
+            CgroupTarget::V1Container { cpu_path, .. } => cpu_path,
+        }
+    }
+
+    fn seen(&self) -> Instant {
+        match self {
+            CgroupTarget::V2Pod { seen, .. } | CgroupTarget::V1Container { seen, .. } => *seen,
+        }
+    }
+
+    fn pod_id(&self) -> &str {
+        match self {
+            CgroupTarget::V2Pod { pod_id, .. } | CgroupTarget::V1Container { pod_id, .. } => pod_id,
+        }
     }
 
-    Ok(())
+    fn labels(&self) -> Labels<'_> {
+        match self {
+            CgroupTarget::V2Pod { pod_id, .. } => Labels { pod_id: Some(pod_id), ..Default::default() },
+            CgroupTarget::V1Container { pod_id, container_id, .. } => {
+                Labels { pod_id: Some(pod_id), container_id: Some(container_id), ..Default::default() }
+            }
+        }
+    }
+
+    /// Labels of the target's metrics, along with its cpuset file
+    fn cpuset(&mut self) -> (Labels<'_>, &mut StatFile) {
+        match self {
+            CgroupTarget::V2Pod { pod_id, cpuset, .. } => {
+                (Labels { pod_id: Some(pod_id), ..Default::default() }, cpuset)
+            }
+            CgroupTarget::V1Container { pod_id, container_id, cpuset, .. } => {
+                (Labels { pod_id: Some(pod_id), container_id: Some(container_id), ..Default::default() }, cpuset)
+            }
+        }
+    }
+
+    fn oom(&mut self) -> (Labels<'_>, &mut OomCount) {
+        match self {
+            CgroupTarget::V2Pod { pod_id, oom, .. } => {
+                (Labels { pod_id: Some(pod_id), ..Default::default() }, oom)
+            }
+            CgroupTarget::V1Container { pod_id, container_id, oom, .. } => {
+                (Labels { pod_id: Some(pod_id), container_id: Some(container_id), ..Default::default() }, oom)
+            }
+        }
+    }
+
+    fn freezer(&mut self) -> (Labels<'_>, &mut Freezer) {
+        match self {
+            CgroupTarget::V2Pod { pod_id, freezer, .. } => {
+                (Labels { pod_id: Some(pod_id), ..Default::default() }, freezer)
+            }
+            CgroupTarget::V1Container { pod_id, container_id, freezer, .. } => {
+                (Labels { pod_id: Some(pod_id), container_id: Some(container_id), ..Default::default() }, freezer)
+            }
+        }
+    }
 }
 
-fn collect_cgroup_v2_metrics(node_name: &str) -> Result<()> {
-    let base_path = Path::new("/sys/fs/cgroup");
-    
-    // Find pod cgroups
-    let kubepods = base_path.join("kubepods.slice");
-    if !kubepods.exists() {
-        // ... debug logs ...
+/// The cgroup's oom_kill count, from memory.events on v2 and
+/// memory.oom_control on v1 (Linux 4.13 and later), so a kill is reported
+/// as it happens rather than inferred from a restart
+struct OomCount {
+    file: StatFile,
+    last: Option<u64>,
+}
+
+impl OomCount {
+    fn new(path: PathBuf) -> Self {
+        Self { file: StatFile::new(path), last: None }
+    }
+}
+
+/// Whether the cgroup is frozen, from `frozen` in cgroup.events on v2 and
+/// the freezer controller's freezer.state on v1, where FREEZING counts too:
+/// its tasks are already stopped. A frozen pod shows no CPU use, like an
+/// idle one.
+struct Freezer {
+    file: StatFile,
+    last: Option<bool>,
+}
+
+impl Freezer {
+    fn new(path: PathBuf) -> Self {
+        Self { file: StatFile::new(path), last: None }
+    }
+}
+
+/// Swap accounting of a v2 cgroup. The files only exist with swap
+/// accounting in the kernel, and memory.zswap.current since Linux 5.19.
+struct SwapFiles {
+    current: StatFile,
+    max: StatFile,
+    zswap: StatFile,
+}
+
+/// Directories visited and cgroups found by one walk of the kubepods tree.
+/// Targets still present from the previous walk are carried over with their
+/// open files.
+struct Discovery<'a> {
+    watcher: Option<&'a DirWatcher>,
+    previous: HashMap<PathBuf, CgroupTarget>,
+    targets: Vec<CgroupTarget>,
+    // Indexes of targets not in the previous walk
+    appeared: Vec<usize>,
+}
+
+impl Discovery<'_> {
+    /// Watch a directory before listing it, so entries created mid-walk still trigger a refresh
+    fn visit(&self, dir: &Path) {
+        if let Some(w) = self.watcher {
+            w.watch(dir);
+        }
+    }
+
+    fn add(&mut self, path: PathBuf, create: impl FnOnce(PathBuf) -> CgroupTarget) {
+        let target = match self.previous.remove(&path) {
+            Some(target) => target,
+            None => {
+                self.appeared.push(self.targets.len());
+                create(path)
+            }
+        };
+        self.targets.push(target);
     }
+}
+
+/// Collects per-pod/container cgroup stats. Walking the kubepods tree is
+/// the expensive part on dense nodes, so discovered cgroup paths are cached
+/// and only rediscovered when inotify reports a change in the tree, a cached
+/// cgroup disappears, or every REFRESH_EVERY cycles as a safety net.
+pub struct ContainerCollector {
+    targets: Vec<CgroupTarget>,
+    watcher: Option<DirWatcher>,
+    cycles_since_refresh: u32,
+    dirty: bool,
+    // Reread with every rediscovery; pinning happens at container start
+    cpu_manager: Option<CpuManagerState>,
+    buf: Vec<u8>,
+    // When the first walk was, whose cgroups aren't news
+    discovered: Option<Instant>,
+    churn: Churn,
+}
 
-    if let Ok(entries) = fs::read_dir(&kubepods) {
+/// Cgroups started and stopped since the agent started, by rediscovery.
+/// A container crash-looping restarts into a new cgroup each time, so this
+/// shows the loop without asking the API server.
+#[derive(Default)]
+struct Churn {
+    started: u64,
+    stopped: u64,
+    short_lived: u64,
+}
+
+impl ContainerCollector {
+    pub fn new() -> Self {
+        Self {
+            targets: Vec::new(),
+            watcher: None,
+            cycles_since_refresh: 0,
+            dirty: true,
+            cpu_manager: None,
+            buf: Vec::new(),
+            discovered: None,
+            churn: Churn::default(),
+        }
+    }
+
+    /// With `roles`, v1 containers are reported with their role and pause
+    /// containers left out; v2 is read per pod, so none apply there
+    pub fn collect(&mut self, node_name: &str, roles: Option<&PodRoles>, sender: &mut MetricsSender) -> Result<()> {
+        let changed = self.watcher.as_ref().is_some_and(|w| w.changed());
+        if self.dirty || changed || self.cycles_since_refresh >= REFRESH_EVERY {
+            self.refresh(sender);
+        }
+        self.cycles_since_refresh += 1;
+        self.dirty = self.read(node_name, roles, None, sender)?;
+
+        if let Some(state) = &self.cpu_manager {
+            report_pinned_cpus(state, node_name, sender);
+        }
+
+        let Churn { started, stopped, short_lived } = self.churn;
+        info!("METRIC_TYPE=container_churn node={} started={} stopped={} short_lived={}", node_name, started, stopped, short_lived);
+        sender.add_counter("container_churn", &Labels::default(), "started", started as f64);
+        sender.add_counter("container_churn", &Labels::default(), "stopped", stopped as f64);
+        sender.add_counter("container_churn", &Labels::default(), "short_lived", short_lived as f64);
+
+        Ok(())
+    }
+
+    /// Reads the cgroups of `pods` alone, for burst samples between cycles;
+    /// the tree is left for the next cycle to rediscover
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn collect_pods(&mut self, node_name: &str, roles: Option<&PodRoles>, pods: &HashSet<String>, sender: &mut MetricsSender) -> Result<()> {
+        let vanished = self.read(node_name, roles, Some(pods), sender)?;
+        self.dirty |= vanished;
+        Ok(())
+    }
+
+    /// Reads every target, or those of `pods` without their cpusets; true if
+    /// one has vanished
+    fn read(&mut self, node_name: &str, roles: Option<&PodRoles>, pods: Option<&HashSet<String>>, sender: &mut MetricsSender) -> Result<bool> {
+        let mut vanished = false;
+        for target in &mut self.targets {
+            if pods.is_some_and(|pods| !pods.contains(target.pod_id())) {
+                continue;
+            }
+            let present = match target {
+                CgroupTarget::V2Pod { pod_id, cpu_stat, memory_current, memory_max, memory_stat, swap, .. } => {
+                    collect_pod_cgroup_v2(pod_id, cpu_stat, memory_current, memory_max, memory_stat, swap, &mut self.buf, node_name, sender)?
+                }
+                CgroupTarget::V1Container { pod_id, container_id, cpuacct_usage, memory_usage, memory_limit, memory_stat, seen, .. } => {
+                    let role = roles.and_then(|r| r.role(runtime_id(container_id), *seen, || pod_uid(pod_id)));
+                    if roles.is_some_and(|r| !r.reported(role)) {
+                        continue;
+                    }
+                    let present = collect_container_cgroup_v1(pod_id, container_id, cpuacct_usage, memory_usage, memory_limit, memory_stat, &mut self.buf, node_name, sender)?;
+                    if let (true, Some(role)) = (present, role) {
+                        let labels = Labels { pod_id: Some(pod_id), container_id: Some(container_id), ..Default::default() };
+                        pod_roles::report(role, &labels, sender);
+                    }
+                    present
+                }
+            };
+            vanished |= !present;
+            if present && pods.is_none() {
+                collect_cpuset(target, self.cpu_manager.as_ref(), &mut self.buf, node_name, sender);
+                check_oom_kills(target, &mut self.buf, sender);
+                check_freezer(target, &mut self.buf, node_name, sender);
+            }
+        }
+        Ok(vanished)
+    }
+
+    fn refresh(&mut self, sender: &mut MetricsSender) {
+        let watcher = DirWatcher::new();
+        let mut discovery = Discovery {
+            watcher: watcher.as_ref(),
+            previous: self.targets.drain(..).map(|t| (t.path().to_path_buf(), t)).collect(),
+            targets: Vec::new(),
+            appeared: Vec::new(),
+        };
+
+        // Try to detect cgroup version
+        let cgroup_v2 = host::path("/sys/fs/cgroup/cgroup.controllers").exists();
+
+        if cgroup_v2 {
+            info!("Generations: Cgroup v2 detected");
+            discover_cgroup_v2(&mut discovery);
+        } else {
+            // info!("Generations: Cgroup v1 detected");
+            discover_cgroup_v1(&mut discovery);
+        }
+
+        if let Some(first_walk) = self.discovered {
+            for target in discovery.previous.values() {
+                sender.event("container_gone", &target.labels(), "cgroup removed".to_string());
+            }
+            self.churn.started += discovery.appeared.len() as u64;
+            self.churn.stopped += discovery.previous.len() as u64;
+            self.churn.short_lived += discovery.previous.values()
+                .filter(|t| t.seen() > first_walk && t.seen().elapsed() < SHORT_LIVED)
+                .count() as u64;
+            for &i in &discovery.appeared {
+                let target = &discovery.targets[i];
+                sender.event("container_appeared", &target.labels(), "cgroup created".to_string());
+            }
+        }
+        self.discovered.get_or_insert_with(Instant::now);
+
+        self.targets = discovery.targets;
+        self.watcher = watcher;
+        self.cpu_manager = CpuManagerState::read();
+        self.cycles_since_refresh = 0;
+        self.dirty = false;
+    }
+}
+
+/// Reports an OOM kill when the cgroup's count went up since the last cycle
+fn check_oom_kills(target: &mut CgroupTarget, buf: &mut Vec<u8>, sender: &mut MetricsSender) {
+    let (labels, oom) = target.oom();
+    let Some(count) = oom.file.read(buf).ok().and_then(|c| parsers::cgroup_key(c, "oom_kill")) else { return };
+    if let Some(last) = oom.last.filter(|&last| count > last) {
+        let message = format!("{} process(es) OOM-killed, {} in total", count - last, count);
+        sender.event("oom_kill", &labels, message);
+    }
+    oom.last = Some(count);
+}
+
+/// Reports the freezer state, and as an event when it changed since the
+/// last cycle; nothing without a freezer
+fn check_freezer(target: &mut CgroupTarget, buf: &mut Vec<u8>, node_name: &str, sender: &mut MetricsSender) {
+    let (labels, freezer) = target.freezer();
+    let Ok(content) = freezer.file.read(buf) else { return };
+    let frozen = match content.trim() {
+        "FROZEN" | "FREEZING" => true,
+        "THAWED" => false,
+        events => match parsers::cgroup_key(events, "frozen") {
+            Some(frozen) => frozen == 1,
+            None => return,
+        },
+    };
+
+    info!("METRIC_TYPE=container_freezer node={} pod_id={} container_id={} frozen={}",
+        node_name, labels.pod_id.unwrap_or_default(), labels.container_id.unwrap_or_default(), frozen as u8);
+    sender.add("container", &labels, "frozen", frozen as u8 as f64);
+
+    if freezer.last.is_some_and(|last| last != frozen) {
+        let (kind, message) = if frozen { ("cgroup_frozen", "cgroup frozen") } else { ("cgroup_thawed", "cgroup thawed") };
+        sender.event(kind, &labels, message.to_string());
+    }
+    freezer.last = Some(frozen);
+}
+
+fn discover_cgroup_v2(discovery: &mut Discovery) {
+    let base_path = host::path("/sys/fs/cgroup");
+
+    // kubepods.slice under the systemd driver, kubepods under cgroupfs (k3s)
+    for kubepods in [base_path.join("kubepods.slice"), base_path.join("kubepods")] {
+        if kubepods.is_dir() {
+            discover_v2_dir(&kubepods, discovery);
+        }
+    }
+}
+
+fn discover_v2_dir(dir: &Path, discovery: &mut Discovery) {
+    discovery.visit(dir);
+    if let Ok(entries) = fs::read_dir(dir) {
         for entry in entries.flatten() {
             let path = entry.path();
             if path.is_dir() {
                 if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                    if name.starts_with("kubepods-") {
-                        collect_pod_cgroup_v2(&path, name, node_name)?;
+                    // Pods first, as kubepods-burstable-pod<uid>.slice names its QoS class too;
+                    // the cgroupfs driver names them plain pod<uid>
+                    if (name.starts_with("kubepods-") && name.contains("-pod")) || name.starts_with("pod") {
+                        let pod_id = name.to_string();
+                        discovery.add(path, |path| CgroupTarget::v2_pod(path, pod_id));
+                    } else if matches!(name, "kubepods-burstable.slice" | "kubepods-besteffort.slice" | "burstable" | "besteffort") {
+                        // Recurse into QoS slices; guaranteed pods sit directly under kubepods
+                        discover_v2_dir(&path, discovery);
                     }
                 }
             }
         }
     }
-
-    Ok(())
 }
 
-fn collect_pod_cgroup_v2(path: &Path, name: &str, node_name: &str) -> Result<()> {
+/// Reads one pod cgroup; returns false if the cgroup no longer exists
+#[allow(clippy::too_many_arguments)]
+fn collect_pod_cgroup_v2(
+    name: &str,
+    cpu_stat: &mut StatFile,
+    memory_current: &mut StatFile,
+    memory_max: &mut StatFile,
+    memory_stat: &mut StatFile,
+    swap: &mut SwapFiles,
+    buf: &mut Vec<u8>,
+    node_name: &str,
+    sender: &mut MetricsSender,
+) -> Result<bool> {
     let mut cpu_ms = 0u64;
     let mut mem_mb = 0u64;
     let mut mem_limit_mb = 0u64;
     
     // Read CPU stats
-    if let Ok(cpu_stat) = fs::read_to_string(path.join("cpu.stat")) {
-        for line in cpu_stat.lines() {
-            if line.starts_with("usage_usec") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() == 2 {
-                    if let Ok(usec) = parts[1].parse::<u64>() {
-                        cpu_ms = usec / 1000;
-                    }
-                }
-            }
-        }
+    let cpu_stat = match cpu_stat.read(buf) {
+        Ok(content) => Some(content),
+        Err(e) if statfile::is_gone(&e) => return Ok(false),
+        Err(_) => None,
+    };
+    if let Some(usec) = cpu_stat.and_then(|c| parsers::cgroup_key(c, "usage_usec")) {
+        cpu_ms = usec / 1000;
     }
     
     // Read memory stats
-    if let Ok(mem_current) = fs::read_to_string(path.join("memory.current")) {
-        if let Ok(bytes) = mem_current.trim().parse::<u64>() {
-            mem_mb = bytes / 1024 / 1024;
-        }
+    if let Some(bytes) = memory_current.read(buf).ok().and_then(parsers::cgroup_value) {
+        mem_mb = bytes / 1024 / 1024;
     }
     
-    if let Ok(mem_max) = fs::read_to_string(path.join("memory.max")) {
-        if mem_max.trim() != "max" {
-            if let Ok(bytes) = mem_max.trim().parse::<u64>() {
-                mem_limit_mb = bytes / 1024 / 1024;
-            }
-        }
+    // "max" (no limit) parses as None and reports 0
+    if let Some(bytes) = memory_max.read(buf).ok().and_then(parsers::cgroup_value) {
+        mem_limit_mb = bytes / 1024 / 1024;
     }
 
     info!("METRIC_TYPE=container node={} pod_id={} cpu_ms={} mem_mb={} mem_limit_mb={}", 
         node_name, name, cpu_ms, mem_mb, mem_limit_mb);
 
-    Ok(())
+    let labels = Labels { pod_id: Some(name), ..Default::default() };
+    sender.add_counter("container", &labels, "cpu_ms", cpu_ms as f64);
+    sender.add("container", &labels, "mem_mb", mem_mb as f64);
+    sender.add("container", &labels, "mem_limit_mb", mem_limit_mb as f64);
+
+    collect_memory_stat(node_name, memory_stat, &V2_MEMORY_STAT, buf, sender, &labels);
+    collect_pod_swap_v2(name, swap, buf, node_name, sender, &labels);
+
+    Ok(true)
+}
+
+/// Names of what's read from memory.stat: v1 counts the cgroup alone under
+/// the plain names and its whole subtree under total_*, while v2 is
+/// hierarchical under the plain names.
+struct MemoryStatKeys {
+    // Minor and major page faults
+    faults: [&'static str; 2],
+    // Key reported, in MB, and its memory.stat name
+    breakdown: &'static [(&'static str, &'static str)],
+}
+
+const V2_MEMORY_STAT: MemoryStatKeys = MemoryStatKeys {
+    faults: ["pgfault", "pgmajfault"],
+    breakdown: &[
+        ("anon_mb", "anon"),
+        ("file_mb", "file"),
+        ("mapped_mb", "file_mapped"),
+        ("shmem_mb", "shmem"),
+        ("slab_mb", "slab"),
+        ("kernel_stack_mb", "kernel_stack"),
+    ],
+};
+
+// v1 accounts kernel memory apart, so there is no slab or kernel stack
+const V1_MEMORY_STAT: MemoryStatKeys = MemoryStatKeys {
+    faults: ["total_pgfault", "total_pgmajfault"],
+    breakdown: &[
+        ("anon_mb", "total_rss"),
+        ("file_mb", "total_cache"),
+        ("mapped_mb", "total_mapped_file"),
+        ("shmem_mb", "total_shmem"),
+    ],
+};
+
+/// Page faults and what the cgroup's memory is made of, from memory.stat:
+/// anonymous memory against page cache (of which `mapped` is mapped into
+/// processes and `shmem` is tmpfs and shared memory, neither of which can
+/// simply be dropped), and on v2 the kernel's slab and stacks, which
+/// count toward the limit too
+fn collect_memory_stat(
+    node_name: &str,
+    memory_stat: &mut StatFile,
+    keys: &MemoryStatKeys,
+    buf: &mut Vec<u8>,
+    sender: &mut MetricsSender,
+    labels: &Labels,
+) {
+    let Ok(content) = memory_stat.read(buf) else {
+        return;
+    };
+    let [minor, major] = keys.faults;
+    if let (Some(pgfault), Some(pgmajfault)) = (parsers::cgroup_key(content, minor), parsers::cgroup_key(content, major)) {
+        info!("METRIC_TYPE=container_faults node={} pod_id={} container_id={} pgfault={} pgmajfault={}",
+            node_name, labels.pod_id.unwrap_or_default(), labels.container_id.unwrap_or_default(), pgfault, pgmajfault);
+
+        sender.add_counter("container", labels, "pgfault", pgfault as f64);
+        sender.add_counter("container", labels, "pgmajfault", pgmajfault as f64);
+    }
+
+    let mut line = String::new();
+    for &(key, name) in keys.breakdown {
+        let Some(bytes) = parsers::cgroup_key(content, name) else { continue };
+        let mb = bytes as f64 / 1024.0 / 1024.0;
+        let _ = write!(line, " {}={:.1}", key, mb);
+        sender.add("container", labels, key, mb);
+    }
+    if !line.is_empty() {
+        info!("METRIC_TYPE=container_memory node={} pod_id={} container_id={}{}",
+            node_name, labels.pod_id.unwrap_or_default(), labels.container_id.unwrap_or_default(), line);
+    }
+}
+
+/// Number of CPUs the cgroup may run on, and whether they are exclusive to
+/// its pod under the static CPU manager
+fn collect_cpuset(
+    target: &mut CgroupTarget,
+    cpu_manager: Option<&CpuManagerState>,
+    buf: &mut Vec<u8>,
+    node_name: &str,
+    sender: &mut MetricsSender,
+) {
+    let (labels, cpuset) = target.cpuset();
+    let Some(cpus) = cpuset.read(buf).ok().and_then(parsers::cpu_count) else {
+        return;
+    };
+    let exclusive = cpu_manager.filter(|s| !s.is_empty()).is_some_and(|s| {
+        labels.pod_id.and_then(pod_uid).is_some_and(|uid| s.pod_cpus(&uid).is_some())
+    });
+
+    info!("METRIC_TYPE=container_cpuset node={} pod_id={} container_id={} cpus={} exclusive={}",
+        node_name, labels.pod_id.unwrap_or_default(), labels.container_id.unwrap_or_default(), cpus, exclusive);
+
+    sender.add("container", &labels, "cpuset_cpus", cpus as f64);
+    sender.add("container", &labels, "cpuset_exclusive", exclusive as u8 as f64);
 }
 
-fn collect_cgroup_v1_metrics(node_name: &str) -> Result<()> {
+/// One series per core pinned to a pod, under its UID, so per-core usage
+/// can be joined to the pod that owns the core
+fn report_pinned_cpus(state: &CpuManagerState, node_name: &str, sender: &mut MetricsSender) {
+    let mut device = String::new();
+    for (uid, cpus) in state.pods() {
+        info!("METRIC_TYPE=container_cpuset node={} pod_uid={} pinned={:?}", node_name, uid, cpus);
+        for cpu in cpus {
+            device.clear();
+            let _ = write!(device, "cpu{}", cpu);
+            let labels = Labels { pod_uid: Some(uid), device: Some(&device), ..Default::default() };
+            sender.add("container_cpuset", &labels, "pinned", 1.0);
+        }
+    }
+}
+
+/// Runtime container ID from its cgroup name: the name itself under
+/// cgroupfs, cri-containerd-<id>.scope (or docker-, crio-) under systemd
+fn runtime_id(name: &str) -> &str {
+    let name = name.strip_suffix(".scope").unwrap_or(name);
+    name.rsplit('-').next().unwrap_or(name)
+}
+
+/// Pod UID from its cgroup name: kubepods-burstable-pod<uid>.slice with
+/// dashes turned to underscores under systemd, pod<uid> under cgroupfs
+fn pod_uid(pod_id: &str) -> Option<String> {
+    let name = pod_id.strip_suffix(".slice").unwrap_or(pod_id);
+    let uid = &name[name.rfind("pod")? + 3..];
+    (uid.len() == 36).then(|| uid.replace('_', "-"))
+}
+
+/// Swap and zswap usage of one pod cgroup; nothing when the kernel does
+/// not account swap
+fn collect_pod_swap_v2(
+    name: &str,
+    swap: &mut SwapFiles,
+    buf: &mut Vec<u8>,
+    node_name: &str,
+    sender: &mut MetricsSender,
+    labels: &Labels,
+) {
+    let Some(bytes) = swap.current.read(buf).ok().and_then(parsers::cgroup_value) else {
+        return;
+    };
+    let swap_mb = bytes / 1024 / 1024;
+    // "max" (no limit) reports 0, as for memory.max
+    let swap_limit_mb = swap.max.read(buf).ok().and_then(parsers::cgroup_value).unwrap_or(0) / 1024 / 1024;
+    let zswap_mb = swap.zswap.read(buf).ok().and_then(parsers::cgroup_value).map(|bytes| bytes / 1024 / 1024);
+
+    info!("METRIC_TYPE=container_swap node={} pod_id={} swap_mb={} swap_limit_mb={} zswap_mb={}",
+        node_name, name, swap_mb, swap_limit_mb, zswap_mb.unwrap_or(0));
+
+    sender.add("container", labels, "swap_mb", swap_mb as f64);
+    sender.add("container", labels, "swap_limit_mb", swap_limit_mb as f64);
+    if let Some(zswap_mb) = zswap_mb {
+        sender.add("container", labels, "zswap_mb", zswap_mb as f64);
+    }
+}
+
+fn discover_cgroup_v1(discovery: &mut Discovery) {
     // Common k8s cgroup v1 paths
-    let cpu_base = Path::new("/sys/fs/cgroup/cpu/kubepods");
-    let cpu_base_slice = Path::new("/sys/fs/cgroup/cpu/kubepods.slice"); // Systemd driver
+    let cpu_base = host::path("/sys/fs/cgroup/cpu/kubepods");
+    let cpu_base_slice = host::path("/sys/fs/cgroup/cpu/kubepods.slice"); // Systemd driver
     
     let search_path = if cpu_base.exists() {
         cpu_base
@@ -94,15 +662,15 @@ fn collect_cgroup_v1_metrics(node_name: &str) -> Result<()> {
         cpu_base_slice
     } else {
         // ... debug ...
-        return Ok(());
+        return;
     };
     
     // Start processing from the base path
-    process_v1_dir(search_path, node_name)?;
-    Ok(())
+    discover_v1_dir(&search_path, discovery);
 }
 
-fn process_v1_dir(dir: &Path, node_name: &str) -> Result<()> {
+fn discover_v1_dir(dir: &Path, discovery: &mut Discovery) {
+    discovery.visit(dir);
     match fs::read_dir(dir) {
         Ok(entries) => {
             for entry in entries.flatten() {
@@ -112,10 +680,10 @@ fn process_v1_dir(dir: &Path, node_name: &str) -> Result<()> {
                         // Prioritize POD detection because pod names might contain qos keywords like 'burstable'
                         if name.starts_with("pod") || name.contains("-pod") {
                             // Found a POD directory
-                            process_v1_pod(&path, name, node_name)?;
+                            discover_v1_pod(&path, name, discovery);
                         } else if name.contains("burstable") || name.contains("besteffort") || name.contains("guaranteed") {
                             // Recurse into QoS slices
-                            process_v1_dir(&path, node_name)?;
+                            discover_v1_dir(&path, discovery);
                         } 
                     }
                 }
@@ -128,11 +696,11 @@ fn process_v1_dir(dir: &Path, node_name: &str) -> Result<()> {
            }
         }
     }
-    Ok(())
 }
 
-fn process_v1_pod(pod_path: &Path, pod_name: &str, node_name: &str) -> Result<()> {
+fn discover_v1_pod(pod_path: &Path, pod_name: &str, discovery: &mut Discovery) {
     let mut found_container = false;
+    discovery.visit(pod_path);
     match fs::read_dir(pod_path) {
         Ok(entries) => {
             for entry in entries.flatten() {
@@ -143,7 +711,10 @@ fn process_v1_pod(pod_path: &Path, pod_name: &str, node_name: &str) -> Result<()
                         
                         if is_container {
                             // info!("Found container candidate: {}", name);
-                            collect_container_cgroup_v1(&path, pod_name, name, node_name)?;
+                            let container_id = name.to_string();
+                            discovery.add(path, |path| {
+                                CgroupTarget::v1_container(path, pod_name.to_string(), container_id)
+                            });
                             found_container = true;
                         }
                     }
@@ -165,36 +736,45 @@ fn process_v1_pod(pod_path: &Path, pod_name: &str, node_name: &str) -> Result<()
             warn!("Failed to read pod dir {:?}: {}", pod_path, e);
         }
     }
-    Ok(())
 }
 
-fn collect_container_cgroup_v1(cpu_path: &Path, pod_id: &str, container_id: &str, node_name: &str) -> Result<()> {
+/// Reads one container cgroup; returns false if the cgroup no longer exists
+#[allow(clippy::too_many_arguments)]
+fn collect_container_cgroup_v1(
+    pod_id: &str,
+    container_id: &str,
+    cpuacct_usage: &mut StatFile,
+    memory_usage: &mut StatFile,
+    memory_limit: &mut StatFile,
+    memory_stat: &mut StatFile,
+    buf: &mut Vec<u8>,
+    node_name: &str,
+    sender: &mut MetricsSender,
+) -> Result<bool> {
     let mut cpu_ms = 0u64;
     let mut mem_mb = 0u64;
     let mut mem_limit_mb = 0u64;
     
     // Read CPU usage
-    if let Ok(cpu_usage) = fs::read_to_string(cpu_path.join("cpuacct.usage")) {
-        if let Ok(nanosecs) = cpu_usage.trim().parse::<u64>() {
-            cpu_ms = nanosecs / 1_000_000;
+    match cpuacct_usage.read(buf) {
+        Ok(cpu_usage) => {
+            if let Some(nanosecs) = parsers::cgroup_value(cpu_usage) {
+                cpu_ms = nanosecs / 1_000_000;
+            }
         }
+        Err(e) if statfile::is_gone(&e) => return Ok(false),
+        Err(_) => {}
     }
     
     // Read memory from corresponding memory cgroup
-    let mem_path = cpu_path.to_string_lossy().replace("/cpu/", "/memory/");
-    let mem_path = Path::new(&mem_path);
-    
-    if let Ok(mem_usage) = fs::read_to_string(mem_path.join("memory.usage_in_bytes")) {
-        if let Ok(bytes) = mem_usage.trim().parse::<u64>() {
-            mem_mb = bytes / 1024 / 1024;
-        }
+    if let Some(bytes) = memory_usage.read(buf).ok().and_then(parsers::cgroup_value) {
+        mem_mb = bytes / 1024 / 1024;
     }
     
-    if let Ok(mem_limit) = fs::read_to_string(mem_path.join("memory.limit_in_bytes")) {
-        if let Ok(bytes) = mem_limit.trim().parse::<u64>() {
-            if bytes < u64::MAX / 2 {
-                mem_limit_mb = bytes / 1024 / 1024;
-            }
+    // Unlimited v1 cgroups report a page-rounded i64::MAX
+    if let Some(bytes) = memory_limit.read(buf).ok().and_then(parsers::cgroup_value) {
+        if bytes < u64::MAX / 2 {
+            mem_limit_mb = bytes / 1024 / 1024;
         }
     }
 
@@ -204,5 +784,12 @@ fn collect_container_cgroup_v1(cpu_path: &Path, pod_id: &str, container_id: &str
         container_id,
         cpu_ms, mem_mb, mem_limit_mb);
 
-    Ok(())
+    let labels = Labels { pod_id: Some(pod_id), container_id: Some(container_id), ..Default::default() };
+    sender.add_counter("container", &labels, "cpu_ms", cpu_ms as f64);
+    sender.add("container", &labels, "mem_mb", mem_mb as f64);
+    sender.add("container", &labels, "mem_limit_mb", mem_limit_mb as f64);
+
+    collect_memory_stat(node_name, memory_stat, &V1_MEMORY_STAT, buf, sender, &labels);
+
+    Ok(true)
 }
@@ -0,0 +1,59 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+use crate::host;
+use crate::parsers;
+
+const STATE_PATH: &str = "/var/lib/kubelet/cpu_manager_state";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StateFile {
+    policy_name: String,
+    #[serde(default)]
+    entries: HashMap<String, HashMap<String, String>>,
+}
+
+/// Exclusive CPU assignments of the kubelet's static CPU manager, from its
+/// checkpoint file. Under the none policy every container shares the pool
+/// and nothing is exclusive.
+pub struct CpuManagerState {
+    // Pod UID to the cores its containers own, sorted
+    pods: HashMap<String, Vec<u32>>,
+}
+
+impl CpuManagerState {
+    /// None if the file is missing or unreadable
+    pub fn read() -> Option<Self> {
+        let content = fs::read_to_string(host::path(STATE_PATH)).ok()?;
+        let state: StateFile = serde_json::from_str(&content).ok()?;
+        if state.policy_name != "static" {
+            return Some(Self { pods: HashMap::new() });
+        }
+
+        let pods = state.entries.into_iter().filter_map(|(uid, containers)| {
+            let mut cpus: Vec<u32> = containers.values()
+                .filter_map(|list| parsers::cpu_list(list))
+                .flatten()
+                .collect();
+            cpus.sort_unstable();
+            cpus.dedup();
+            (!cpus.is_empty()).then_some((uid, cpus))
+        }).collect();
+        Some(Self { pods })
+    }
+
+    /// Cores pinned to the pod, if it has any
+    pub fn pod_cpus(&self, uid: &str) -> Option<&[u32]> {
+        self.pods.get(uid).map(Vec::as_slice)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pods.is_empty()
+    }
+
+    pub fn pods(&self) -> impl Iterator<Item = (&str, &[u32])> {
+        self.pods.iter().map(|(uid, cpus)| (uid.as_str(), cpus.as_slice()))
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use std::time::Duration;
+use tokio::net::windows::named_pipe::ClientOptions;
+
+use crate::grpc::{self, Fields, Value};
+
+/// containerd's pipe on Windows nodes
+pub const DEFAULT_ENDPOINT: &str = r"\\.\pipe\containerd-containerd";
+
+/// Longest wait for the runtime, connection included
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Usage of one Kubernetes container as the runtime reports it
+#[derive(Default)]
+pub struct ContainerStats {
+    pub id: String,
+    pub pod_uid: String,
+    pub cpu_ns: u64,
+    pub working_set_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Calls ListContainerStats on the CRI runtime behind `pipe`. Only the few
+/// fields we report are decoded; containers without a pod are skipped.
+pub async fn list_container_stats(pipe: &str) -> Result<Vec<ContainerStats>> {
+    let call = async {
+        let io = ClientOptions::new().open(pipe)
+            .with_context(|| format!("connecting to {}", pipe))?;
+        grpc::call(io, "/runtime.v1.RuntimeService/ListContainerStats", &[]).await
+    };
+    let body = tokio::time::timeout(TIMEOUT, call).await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out calling the CRI runtime")))?;
+
+    let mut list = Vec::new();
+    // ListContainerStatsResponse: repeated ContainerStats stats = 1
+    for field in Fields(&body) {
+        if let (1, Value::Bytes(stats)) = field? {
+            if let Some(stats) = decode_stats(stats)? {
+                list.push(stats);
+            }
+        }
+    }
+    Ok(list)
+}
+
+fn decode_stats(buf: &[u8]) -> Result<Option<ContainerStats>> {
+    let mut stats = ContainerStats::default();
+    // ContainerStats: attributes = 1, cpu = 2, memory = 3
+    for field in Fields(buf) {
+        match field? {
+            (1, Value::Bytes(attributes)) => decode_attributes(attributes, &mut stats)?,
+            // CpuUsage: usage_core_nano_seconds = 2
+            (2, Value::Bytes(cpu)) => stats.cpu_ns = wrapped_u64(cpu, 2)?,
+            // MemoryUsage: working_set_bytes = 2, available_bytes = 3
+            (3, Value::Bytes(memory)) => {
+                stats.working_set_bytes = wrapped_u64(memory, 2)?;
+                stats.available_bytes = wrapped_u64(memory, 3)?;
+            }
+            _ => {}
+        }
+    }
+    // Only containers started by kubelet carry a pod
+    Ok((!stats.pod_uid.is_empty()).then_some(stats))
+}
+
+fn decode_attributes(buf: &[u8], stats: &mut ContainerStats) -> Result<()> {
+    // ContainerAttributes: id = 1, labels = 3 (map entries of key = 1, value = 2)
+    for field in Fields(buf) {
+        match field? {
+            (1, Value::Bytes(id)) => stats.id = String::from_utf8_lossy(id).into_owned(),
+            (3, Value::Bytes(entry)) => {
+                let (mut key, mut value) = (&[][..], &[][..]);
+                for field in Fields(entry) {
+                    match field? {
+                        (1, Value::Bytes(k)) => key = k,
+                        (2, Value::Bytes(v)) => value = v,
+                        _ => {}
+                    }
+                }
+                if key == b"io.kubernetes.pod.uid" {
+                    stats.pod_uid = String::from_utf8_lossy(value).into_owned();
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reads the google.protobuf.UInt64Value in `field` of a message; 0 if unset
+fn wrapped_u64(buf: &[u8], field: u64) -> Result<u64> {
+    for f in Fields(buf) {
+        if let (n, Value::Bytes(wrapper)) = f? {
+            if n == field {
+                for inner in Fields(wrapper) {
+                    if let (1, Value::Varint(v)) = inner? {
+                        return Ok(v);
+                    }
+                }
+            }
+        }
+    }
+    Ok(0)
+}
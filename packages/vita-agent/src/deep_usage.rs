@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Outcome of one walk of a volume
+#[derive(Clone, Copy)]
+pub struct Scan {
+    // Allocated on disk, the way du counts it
+    pub bytes: u64,
+    pub files: u64,
+    pub took: Duration,
+    // Directories below the depth limit went uncounted
+    pub truncated: bool,
+}
+
+#[derive(Default)]
+struct Entry {
+    last: Option<Scan>,
+    requested: Option<Instant>,
+    pending: bool,
+}
+
+/// Limits of every walk
+#[derive(Clone, Copy)]
+struct Throttle {
+    max_depth: usize,
+    entries_per_sec: u64,
+}
+
+/// Recursive size scans of selected volumes, for those where statvfs says
+/// nothing about the pod (a subPath of a big shared mount, hostPath
+/// directories). Walks run one at a time on a thread of their own, at most
+/// once per interval for each volume and at a capped rate of entries per
+/// second, so a huge tree costs time rather than I/O bandwidth.
+pub struct DeepUsage {
+    // Volume names to scan, "*" for all
+    volumes: Vec<String>,
+    interval: Duration,
+    jobs: mpsc::Sender<(PathBuf, Vec<PathBuf>)>,
+    entries: Arc<Mutex<HashMap<PathBuf, Entry>>>,
+}
+
+impl DeepUsage {
+    /// Reads DEEP_USAGE_VOLUMES, DEEP_USAGE_INTERVAL, DEEP_USAGE_MAX_DEPTH and
+    /// DEEP_USAGE_ENTRIES_PER_SEC; None unless volumes are named
+    pub fn from_env() -> Option<Self> {
+        let volumes: Vec<String> = env::var("DEEP_USAGE_VOLUMES").unwrap_or_default()
+            .split(',').map(str::trim).filter(|v| !v.is_empty()).map(String::from).collect();
+        if volumes.is_empty() {
+            return None;
+        }
+        let number = |name: &str, default: u64| {
+            env::var(name).ok().and_then(|v| v.parse().ok()).filter(|&v| v > 0).unwrap_or(default)
+        };
+        let interval = Duration::from_secs(number("DEEP_USAGE_INTERVAL", 300));
+        let throttle = Throttle {
+            max_depth: number("DEEP_USAGE_MAX_DEPTH", 32) as usize,
+            entries_per_sec: number("DEEP_USAGE_ENTRIES_PER_SEC", 2000),
+        };
+        info!("Deep volume usage scans enabled | volumes={:?} interval={:?} max_depth={} entries_per_sec={}",
+            volumes, interval, throttle.max_depth, throttle.entries_per_sec);
+
+        let (jobs, queue) = mpsc::channel::<(PathBuf, Vec<PathBuf>)>();
+        let entries: Arc<Mutex<HashMap<PathBuf, Entry>>> = Arc::default();
+        let results = entries.clone();
+        std::thread::spawn(move || {
+            for (volume, roots) in queue {
+                let scan = walk(&roots, throttle);
+                debug!("Deep usage of {}: {} bytes in {} files, {:?}", volume.display(), scan.bytes, scan.files, scan.took);
+                if let Some(entry) = results.lock().unwrap().get_mut(&volume) {
+                    entry.last = Some(scan);
+                    entry.pending = false;
+                }
+            }
+        });
+
+        Some(Self { volumes, interval, jobs, entries })
+    }
+
+    pub fn wanted(&self, volume: &str) -> bool {
+        self.volumes.iter().any(|v| v == "*" || v == volume)
+    }
+
+    /// Queues a walk of the volume at `volume` when one is due and returns
+    /// the last result. `roots` are the directories that belong to the pod:
+    /// the volume itself, or the subPaths its containers mount.
+    pub fn poll(&self, volume: &Path, roots: impl FnOnce() -> Vec<PathBuf>) -> Option<Scan> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(volume.to_path_buf()).or_default();
+        if !entry.pending && entry.requested.is_none_or(|t| t.elapsed() >= self.interval) {
+            entry.requested = Some(Instant::now());
+            entry.pending = self.jobs.send((volume.to_path_buf(), roots())).is_ok();
+        }
+        entry.last
+    }
+
+    /// Forgets volumes that are gone
+    pub fn retain(&self, mut keep: impl FnMut(&Path) -> bool) {
+        self.entries.lock().unwrap().retain(|volume, entry| entry.pending || keep(volume));
+    }
+}
+
+/// du -sx over `roots`: symlinks aren't followed, filesystems other than a
+/// root's own aren't entered, and hard-linked files and roots mounted twice
+/// count once
+fn walk(roots: &[PathBuf], throttle: Throttle) -> Scan {
+    let started = Instant::now();
+    let mut scan = Scan { bytes: 0, files: 0, took: Duration::ZERO, truncated: false };
+    let mut linked = HashSet::new();
+    let mut seen: u64 = 0;
+
+    for root in roots {
+        let Ok(meta) = fs::symlink_metadata(root) else { continue };
+        if cfg!(unix) && !linked.insert((device(&meta), inode(&meta))) {
+            continue;
+        }
+        walk_root(root, device(&meta), throttle, started, &mut seen, &mut linked, &mut scan);
+    }
+    scan.took = started.elapsed();
+    scan
+}
+
+fn walk_root(root: &Path, root_device: u64, throttle: Throttle, started: Instant, seen: &mut u64,
+    linked: &mut HashSet<(u64, u64)>, scan: &mut Scan) {
+    let mut stack = vec![(root.to_path_buf(), 0)];
+    while let Some((dir, depth)) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            *seen += 1;
+            // Sleep off whatever is ahead of the allowed rate
+            let due = Duration::from_secs_f64(*seen as f64 / throttle.entries_per_sec as f64);
+            if let Some(ahead) = due.checked_sub(started.elapsed()) {
+                std::thread::sleep(ahead);
+            }
+
+            let Ok(meta) = entry.metadata() else { continue };
+            if device(&meta) != root_device {
+                continue;
+            }
+            if meta.is_dir() {
+                scan.bytes += allocated(&meta);
+                if depth + 1 < throttle.max_depth {
+                    stack.push((entry.path(), depth + 1));
+                } else {
+                    scan.truncated = true;
+                }
+                continue;
+            }
+            if hard_linked(&meta) && !linked.insert((root_device, inode(&meta))) {
+                continue;
+            }
+            scan.bytes += allocated(&meta);
+            scan.files += 1;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn device(meta: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::dev(meta)
+}
+
+#[cfg(unix)]
+fn allocated(meta: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::blocks(meta) * 512
+}
+
+#[cfg(unix)]
+fn inode(meta: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(meta)
+}
+
+#[cfg(unix)]
+fn hard_linked(meta: &fs::Metadata) -> bool {
+    std::os::unix::fs::MetadataExt::nlink(meta) > 1
+}
+
+// Windows volumes are walked by apparent size, without link detection
+#[cfg(not(unix))]
+fn device(_meta: &fs::Metadata) -> u64 {
+    0
+}
+
+#[cfg(not(unix))]
+fn allocated(meta: &fs::Metadata) -> u64 {
+    meta.len()
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &fs::Metadata) -> u64 {
+    0
+}
+
+#[cfg(not(unix))]
+fn hard_linked(_meta: &fs::Metadata) -> bool {
+    false
+}
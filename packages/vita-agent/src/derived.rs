@@ -0,0 +1,218 @@
+use rhai::{Dynamic, Engine, EvalAltResult, ImmutableString, Scope, AST};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
+
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// Operations one evaluation may take, loops included
+const MAX_OPERATIONS: u64 = 10_000;
+
+/// A metric computed from others each cycle. `name` is the type.key of the
+/// result; `script` is a Rhai expression that runs once per series of that
+/// type, with the series' keys and labels as variables, and adds its value
+/// to the series under the new key. sum, avg and count("type", "key") read
+/// across all series of the cycle, value("type", "key") the one without
+/// labels. A script returning () or a value that isn't finite adds nothing.
+#[derive(Clone, Debug, PartialEq, Hash, Deserialize)]
+pub struct DerivedMetric {
+    pub name: String,
+    pub script: String,
+}
+
+impl DerivedMetric {
+    /// DERIVED_METRICS, one `type.key = script` per line
+    pub fn from_env() -> Vec<Self> {
+        env::var("DERIVED_METRICS").unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .filter_map(|line| {
+                let parsed = line.split_once('=').map(|(name, script)| Self {
+                    name: name.trim().to_string(),
+                    script: script.trim().to_string(),
+                });
+                if parsed.is_none() {
+                    warn!("Ignoring derived metric {:?}, expected type.key = script", line);
+                }
+                parsed
+            })
+            .collect()
+    }
+}
+
+struct Rule {
+    name: String,
+    metric_type: String,
+    key: String,
+    ast: AST,
+    // Failures are logged once per rule, then counted
+    warned: bool,
+}
+
+/// Sum, count and unlabelled value of one type.key this cycle
+#[derive(Default)]
+struct Total {
+    sum: f64,
+    count: i64,
+    unlabelled: Option<f64>,
+}
+
+type Totals = HashMap<(Arc<str>, Arc<str>), Total>;
+
+/// pod_id, pod_uid, volume, container_id and device of a series
+type SeriesLabels = [Option<Arc<str>>; 5];
+
+/// Derived metrics, evaluated in order after collection so later rules see
+/// what earlier ones added, and burst rules see them all
+pub struct Derived {
+    engine: Engine,
+    rules: Vec<Rule>,
+    totals: Arc<Mutex<Totals>>,
+}
+
+impl Derived {
+    pub fn new(metrics: &[DerivedMetric]) -> Self {
+        let totals: Arc<Mutex<Totals>> = Arc::default();
+
+        // Scripts see numbers and strings only: no modules, no clock, and
+        // bounded work, so a typo can't stall the cycle
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(8);
+        engine.set_max_expr_depths(32, 16);
+        engine.set_max_string_size(1024);
+        engine.set_max_array_size(1000);
+        engine.set_max_map_size(1000);
+        engine.on_print(|s| debug!("Derived metric script: {}", s));
+        engine.on_debug(|s, _, pos| debug!("Derived metric script at {}: {}", pos, s));
+
+        // Over no series, sums and counts are 0 and the rest not a number
+        let lookup = |totals: &Arc<Mutex<Totals>>, none: f64, f: fn(&Total) -> f64| {
+            let totals = totals.clone();
+            move |metric_type: ImmutableString, key: ImmutableString| -> f64 {
+                let totals = totals.lock().unwrap();
+                totals.get(&(Arc::from(metric_type.as_str()), Arc::from(key.as_str()))).map_or(none, f)
+            }
+        };
+        engine.register_fn("sum", lookup(&totals, 0.0, |t| t.sum));
+        engine.register_fn("count", lookup(&totals, 0.0, |t| t.count as f64));
+        engine.register_fn("avg", lookup(&totals, f64::NAN, |t| t.sum / t.count as f64));
+        engine.register_fn("value", lookup(&totals, f64::NAN, |t| t.unlabelled.unwrap_or(f64::NAN)));
+
+        let mut derived = Self { engine, rules: Vec::new(), totals };
+        derived.set_metrics(metrics);
+        derived
+    }
+
+    /// Replaces the rules
+    pub fn set_metrics(&mut self, metrics: &[DerivedMetric]) {
+        self.rules = metrics.iter()
+            .filter_map(|m| {
+                let Some((metric_type, key)) = m.name.split_once('.').filter(|(t, k)| !t.is_empty() && !k.is_empty()) else {
+                    warn!("Ignoring derived metric {:?}, expected a type.key name", m.name);
+                    return None;
+                };
+                match self.engine.compile(&m.script) {
+                    Ok(ast) => {
+                        info!("Derived metric {} = {}", m.name, m.script);
+                        Some(Rule { name: m.name.clone(), metric_type: metric_type.to_string(), key: key.to_string(), ast, warned: false })
+                    }
+                    Err(e) => {
+                        warn!("Ignoring derived metric {}: {}", m.name, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+    }
+
+    /// Adds each rule's values to this cycle's metrics
+    pub fn evaluate(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        if self.rules.is_empty() {
+            return;
+        }
+        {
+            let mut totals = self.totals.lock().unwrap();
+            totals.clear();
+            for m in sender.cycle_metrics() {
+                let unlabelled = [&m.pod_id, &m.pod_uid, &m.volume, &m.container_id, &m.device].iter().all(|l| l.is_none());
+                add_total(&mut totals, &m.metric_type, &m.key, m.value, unlabelled);
+            }
+        }
+
+        for rule in &mut self.rules {
+            // Series of the rule's type by labels, with their keys
+            let mut series: BTreeMap<SeriesLabels, Vec<(Arc<str>, f64)>> = BTreeMap::new();
+            for m in sender.cycle_metrics().filter(|m| *m.metric_type == *rule.metric_type) {
+                let labels = [m.pod_id.clone(), m.pod_uid.clone(), m.volume.clone(), m.container_id.clone(), m.device.clone()];
+                series.entry(labels).or_default().push((m.key.clone(), m.value));
+            }
+
+            let mut values = 0;
+            let mut errors = 0;
+            for (labels, keys) in &series {
+                let mut scope = Scope::new();
+                for (name, label) in ["pod_id", "pod_uid", "volume", "container_id", "device"].into_iter().zip(labels) {
+                    if let Some(label) = label {
+                        scope.push_constant(name, ImmutableString::from(&**label));
+                    }
+                }
+                for (key, value) in keys {
+                    scope.push_constant(&**key, *value);
+                }
+
+                let value = match self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &rule.ast) {
+                    Ok(result) => number(&result),
+                    // A series without one of the keys, like a container without a limit
+                    Err(e) if matches!(*e, EvalAltResult::ErrorVariableNotFound(..)) => None,
+                    Err(e) => {
+                        errors += 1;
+                        if !rule.warned {
+                            warn!("Derived metric {} failed: {}", rule.name, e);
+                            rule.warned = true;
+                        }
+                        None
+                    }
+                };
+                let Some(value) = value.filter(|v| v.is_finite()) else { continue };
+
+                let [pod_id, pod_uid, volume, container_id, device] = labels.each_ref().map(|l| l.as_deref());
+                let labels = Labels { pod_id, pod_uid, volume, container_id, device };
+                let mut line = format!("METRIC_TYPE={} node={}", rule.metric_type, node_name);
+                for (name, label) in [("pod_id", pod_id), ("pod_uid", pod_uid), ("volume", volume), ("container_id", container_id), ("device", device)] {
+                    if let Some(label) = label {
+                        line.push_str(&format!(" {}={}", name, label));
+                    }
+                }
+                info!("{} {}={}", line, rule.key, value);
+                sender.add(&rule.metric_type, &labels, &rule.key, value);
+                let unlabelled = pod_id.is_none() && pod_uid.is_none() && volume.is_none() && container_id.is_none() && device.is_none();
+                add_total(&mut self.totals.lock().unwrap(), &rule.metric_type, &rule.key, value, unlabelled);
+                values += 1;
+            }
+
+            let labels = Labels { device: Some(&rule.name), ..Default::default() };
+            sender.add("agent_derived", &labels, "values", values as f64);
+            sender.add("agent_derived", &labels, "errors", errors as f64);
+        }
+    }
+}
+
+fn add_total(totals: &mut Totals, metric_type: &str, key: &str, value: f64, unlabelled: bool) {
+    let total = totals.entry((Arc::from(metric_type), Arc::from(key))).or_default();
+    total.sum += value;
+    total.count += 1;
+    if unlabelled {
+        total.unlabelled = Some(value);
+    }
+}
+
+/// A script's result as a value: numbers as they are, booleans as 1 or 0
+fn number(result: &Dynamic) -> Option<f64> {
+    result.as_float().ok()
+        .or_else(|| result.as_int().ok().map(|i| i as f64))
+        .or_else(|| result.as_bool().ok().map(|b| b as u8 as f64))
+}
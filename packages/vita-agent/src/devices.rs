@@ -0,0 +1,136 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tracing::info;
+
+use crate::errors::{CollectorError, Result};
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+const CHECKPOINT_PATH: &str = "/var/lib/kubelet/device-plugins/kubelet_internal_checkpoint";
+
+#[derive(Deserialize)]
+struct Checkpoint {
+    #[serde(rename = "Data")]
+    data: CheckpointData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CheckpointData {
+    #[serde(default)]
+    pod_device_entries: Vec<PodDeviceEntry>,
+    #[serde(default)]
+    registered_devices: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodDeviceEntry {
+    #[serde(rename = "PodUID")]
+    pod_uid: String,
+    container_name: String,
+    resource_name: String,
+    #[serde(rename = "DeviceIDs")]
+    device_ids: DeviceIds,
+}
+
+/// Since Kubernetes 1.20 device IDs are grouped by NUMA node
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DeviceIds {
+    ByNuma(BTreeMap<String, Vec<String>>),
+    Flat(Vec<String>),
+}
+
+impl DeviceIds {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            DeviceIds::ByNuma(ids) => ids.into_values().flatten().collect(),
+            DeviceIds::Flat(ids) => ids,
+        }
+    }
+}
+
+/// One device held by a container
+struct Allocation {
+    pod_uid: String,
+    container: String,
+    resource: String,
+    device: String,
+}
+
+/// Which pods hold which devices (GPUs and anything else a device plugin
+/// advertises), from the kubelet's device manager checkpoint. Devices go
+/// out under the ID the plugin registered, which for the NVIDIA plugin is
+/// the GPU UUID NVML reports, so per-GPU data joins on the device label.
+pub struct DeviceCollector {
+    path: PathBuf,
+    // Checkpoint modification time the cached state was parsed at
+    modified: Option<SystemTime>,
+    allocations: Vec<Allocation>,
+    registered: BTreeMap<String, usize>,
+}
+
+impl DeviceCollector {
+    pub fn new() -> Self {
+        Self { path: host::path(CHECKPOINT_PATH), modified: None, allocations: Vec::new(), registered: BTreeMap::new() }
+    }
+
+    /// The kubelet writes the checkpoint on every allocation, so it's only
+    /// parsed again when its mtime moves. Nodes without device plugins have
+    /// no checkpoint and report nothing.
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        let Ok(meta) = fs::metadata(&self.path) else {
+            self.allocations.clear();
+            self.registered.clear();
+            return Ok(());
+        };
+        let modified = meta.modified().ok();
+        if modified.is_none() || modified != self.modified {
+            self.reload()?;
+            self.modified = modified;
+        }
+
+        for (resource, count) in &self.registered {
+            let allocated = self.allocations.iter().filter(|a| &a.resource == resource).count();
+            info!("METRIC_TYPE=node_device node={} resource={} registered={} allocated={}",
+                node_name, resource, count, allocated);
+            let labels = Labels { device: Some(resource), ..Default::default() };
+            sender.add("node_device", &labels, "registered", *count as f64);
+            sender.add("node_device", &labels, "allocated", allocated as f64);
+        }
+
+        for a in &self.allocations {
+            info!("METRIC_TYPE=pod_device node={} pod_uid={} container={} resource={} device={}",
+                node_name, a.pod_uid, a.container, a.resource, a.device);
+            let labels = Labels { pod_uid: Some(&a.pod_uid), device: Some(&a.device), ..Default::default() };
+            sender.add("pod_device", &labels, &a.resource, 1.0);
+        }
+        Ok(())
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let content = fs::read(&self.path).map_err(|e| CollectorError::read(&self.path, e))?;
+        let checkpoint: Checkpoint = serde_json::from_slice(&content)
+            .map_err(|_| CollectorError::Parse(self.path.display().to_string()))?;
+
+        self.registered = checkpoint.data.registered_devices.into_iter()
+            .map(|(resource, devices)| (resource, devices.len()))
+            .collect();
+        self.allocations = checkpoint.data.pod_device_entries.into_iter()
+            .flat_map(|entry| {
+                let PodDeviceEntry { pod_uid, container_name, resource_name, device_ids } = entry;
+                device_ids.into_vec().into_iter().map(move |device| Allocation {
+                    pod_uid: pod_uid.clone(),
+                    container: container_name.clone(),
+                    resource: resource_name.clone(),
+                    device,
+                })
+            })
+            .collect();
+        Ok(())
+    }
+}
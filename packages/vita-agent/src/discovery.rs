@@ -0,0 +1,181 @@
+use futures::StreamExt;
+use k8s_openapi::api::discovery::v1::EndpointSlice;
+use kube::api::Api;
+use kube::runtime::{reflector, watcher, WatchStreamExt};
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::dns;
+
+/// DNS lookups that take longer are retried next interval
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The consumer's Service, whose ready pods the agent sends to. Found by
+/// name (CONSUMER_SERVICE, `[namespace/]name`) or by the Service's labels
+/// (CONSUMER_SERVICE_SELECTOR, API discovery only), in POD_NAMESPACE unless
+/// given.
+#[derive(Clone, Debug)]
+pub struct ConsumerService {
+    namespace: String,
+    name: Option<String>,
+    selector: Option<String>,
+    // Named port of the Service (CONSUMER_SERVICE_PORT, http)
+    port: String,
+    dns: bool,
+    interval: Duration,
+}
+
+impl ConsumerService {
+    /// None unless CONSUMER_SERVICE or CONSUMER_SERVICE_SELECTOR is set.
+    /// CONSUMER_DISCOVERY is api (the default), watching the Service's
+    /// EndpointSlices, or dns, resolving its SRV records every
+    /// CONSUMER_DISCOVERY_INTERVAL seconds (30).
+    pub fn from_env() -> Option<Self> {
+        let service = env::var("CONSUMER_SERVICE").ok().filter(|s| !s.is_empty());
+        let selector = env::var("CONSUMER_SERVICE_SELECTOR").ok().filter(|s| !s.is_empty());
+        if service.is_none() && selector.is_none() {
+            return None;
+        }
+        let mut namespace = env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_string());
+        let name = service.map(|s| match s.split_once('/') {
+            Some((ns, name)) => {
+                namespace = ns.to_string();
+                name.to_string()
+            }
+            None => s,
+        });
+        Some(Self {
+            namespace,
+            name,
+            selector,
+            port: env::var("CONSUMER_SERVICE_PORT").unwrap_or_else(|_| "http".to_string()),
+            dns: env::var("CONSUMER_DISCOVERY").is_ok_and(|m| m == "dns"),
+            interval: Duration::from_secs(
+                env::var("CONSUMER_DISCOVERY_INTERVAL").ok().and_then(|v| v.parse().ok()).filter(|&s| s > 0).unwrap_or(30),
+            ),
+        })
+    }
+}
+
+/// Keeps a comma-separated list of the consumer's ready pods, as endpoints
+/// like `template` with their addresses in place of its host, updated as
+/// pods come and go. The list starts at a different pod on each node so
+/// load spreads, and isn't emptied while no pod is ready: the last
+/// addresses stay the best guess.
+pub fn watch(client: Option<kube::Client>, service: ConsumerService, template: String, node_name: String) -> watch::Receiver<String> {
+    let (tx, rx) = watch::channel(String::new());
+    let publish = move |mut addresses: Vec<SocketAddr>| {
+        if addresses.is_empty() {
+            return;
+        }
+        addresses.sort();
+        addresses.dedup();
+        let mut hasher = DefaultHasher::new();
+        node_name.hash(&mut hasher);
+        let start = hasher.finish() as usize % addresses.len();
+        addresses.rotate_left(start);
+        let endpoints = addresses.iter().map(|a| with_address(&template, *a)).collect::<Vec<_>>().join(",");
+        tx.send_if_modified(|current| {
+            if *current == endpoints {
+                return false;
+            }
+            info!("Discovered consumers: {}", endpoints);
+            *current = endpoints;
+            true
+        });
+    };
+
+    tokio::spawn(async move {
+        let result = match client {
+            Some(client) if !service.dns => watch_endpoint_slices(client, &service, publish).await,
+            _ => poll_srv(&service, publish).await,
+        };
+        if let Err(e) = result {
+            warn!("Consumer discovery stopped: {}", e);
+        }
+    });
+    rx
+}
+
+async fn watch_endpoint_slices(client: kube::Client, service: &ConsumerService, mut publish: impl FnMut(Vec<SocketAddr>)) -> anyhow::Result<()> {
+    // Slices carry their Service's name and a copy of its labels
+    let selector = service.name.as_ref()
+        .map(|name| format!("kubernetes.io/service-name={}", name))
+        .or_else(|| service.selector.clone())
+        .unwrap_or_default();
+    let api = Api::<EndpointSlice>::namespaced(client, &service.namespace);
+    let writer = reflector::store::Writer::default();
+    let store = writer.as_reader();
+    let mut events = reflector(writer, watcher(api, watcher::Config::default().labels(&selector)))
+        .default_backoff()
+        .boxed();
+
+    info!("Discovering consumers from EndpointSlices {} in {}", selector, service.namespace);
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(watcher::Event::Init | watcher::Event::InitApply(_)) => continue,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("EndpointSlice watch error: {}", e);
+                continue;
+            }
+        }
+        let mut addresses = Vec::new();
+        for slice in store.state() {
+            let port = slice.ports.iter().flatten()
+                .find(|p| p.name.as_deref() == Some(service.port.as_str()))
+                .or_else(|| slice.ports.iter().flatten().next())
+                .and_then(|p| p.port);
+            let Some(port) = port.and_then(|p| u16::try_from(p).ok()) else { continue };
+            for endpoint in &slice.endpoints {
+                // Unset means ready
+                if endpoint.conditions.as_ref().and_then(|c| c.ready) == Some(false) {
+                    continue;
+                }
+                addresses.extend(endpoint.addresses.iter()
+                    .filter_map(|a| a.parse().ok())
+                    .map(|ip| SocketAddr::new(ip, port)));
+            }
+        }
+        publish(addresses);
+    }
+    Ok(())
+}
+
+/// Resolves `_<port>._tcp.<service>.<namespace>.svc.<domain>` through the
+/// cluster DNS Service; a headless Service answers with a record per pod
+async fn poll_srv(service: &ConsumerService, mut publish: impl FnMut(Vec<SocketAddr>)) -> anyhow::Result<()> {
+    let name = service.name.as_ref().ok_or_else(|| anyhow::anyhow!("DNS discovery needs CONSUMER_SERVICE, not a selector"))?;
+    let server = env::var("DNS_PROBE_SERVER").ok().filter(|s| !s.is_empty()).or_else(dns::cluster_dns)
+        .ok_or_else(|| anyhow::anyhow!("no cluster DNS server found, set DNS_PROBE_SERVER"))?;
+    let server: SocketAddr = match server.parse() {
+        Ok(addr) => addr,
+        Err(_) => SocketAddr::new(server.parse()?, 53),
+    };
+    let srv = format!("_{}._tcp.{}.{}.svc.{}", service.port, name, service.namespace, dns::cluster_domain());
+
+    info!("Discovering consumers from {} via {} every {:?}", srv, server, service.interval);
+    let mut ticker = tokio::time::interval(service.interval);
+    loop {
+        ticker.tick().await;
+        match tokio::time::timeout(DNS_TIMEOUT, dns::resolve_srv(server, &srv)).await {
+            Ok(Ok(addresses)) => publish(addresses),
+            Ok(Err(e)) => warn!("Failed to resolve {}: {}", srv, e),
+            Err(_) => warn!("Failed to resolve {}: no answer in {:?}", srv, DNS_TIMEOUT),
+        }
+    }
+}
+
+/// `template` with its host and port replaced by `address`, keeping the
+/// scheme and path
+fn with_address(template: &str, address: SocketAddr) -> String {
+    let template = template.split(',').next().unwrap_or_default();
+    let (scheme, rest) = template.split_once("://").unwrap_or(("http", template));
+    let path = rest.find('/').map_or("/api/v1/ingest", |i| &rest[i..]);
+    format!("{}://{}{}", scheme, address, path)
+}
@@ -0,0 +1,220 @@
+use std::env;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+use crate::blackbox::ProbeTarget;
+use crate::host;
+
+/// Carries clusterDNS, the Service IP pods are told to resolve through
+const KUBELET_CONFIG: &str = "/var/lib/kubelet/config.yaml";
+
+/// The node's resolvers: a hostNetwork pod without ClusterFirstWithHostNet
+/// gets the node's resolv.conf
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+
+/// Probes resolving DNS_PROBE_NAME through the cluster DNS Service
+/// (DNS_PROBE_SERVER, or the kubelet's clusterDNS) and through the node's
+/// resolver, every DNS_PROBE_INTERVAL seconds (10); none without a name.
+pub fn probe_targets() -> Vec<ProbeTarget> {
+    let Some(name) = env::var("DNS_PROBE_NAME").ok().filter(|n| !n.is_empty()) else { return Vec::new() };
+    let interval = env::var("DNS_PROBE_INTERVAL").ok().and_then(|v| v.parse().ok()).filter(|&s| s > 0).unwrap_or(10);
+    let cluster = env::var("DNS_PROBE_SERVER").ok().filter(|s| !s.is_empty()).or_else(cluster_dns);
+    let node = node_resolver();
+    if cluster.is_none() {
+        warn!("DNS probe: no cluster DNS server found in {}, set DNS_PROBE_SERVER", KUBELET_CONFIG);
+    }
+
+    let mut targets = Vec::new();
+    for (probe, server) in [("cluster-dns", cluster), ("node-dns", node)] {
+        if let Some(server) = server {
+            info!("DNS probe {}: {} via {} every {}s", probe, name, server, interval);
+            targets.push(ProbeTarget {
+                name: probe.to_string(),
+                target: format!("dns://{}/{}", server, name),
+                interval: Some(interval),
+                timeout: None,
+            });
+        }
+    }
+    targets
+}
+
+/// First clusterDNS address in the kubelet's config, in either YAML list
+/// form
+pub fn cluster_dns() -> Option<String> {
+    let config = std::fs::read_to_string(host::path(KUBELET_CONFIG)).ok()?;
+    let mut lines = config.lines();
+    while let Some(line) = lines.next() {
+        let Some(value) = line.trim_start().strip_prefix("clusterDNS:") else { continue };
+        let value = value.trim();
+        if let Some(inline) = value.strip_prefix('[') {
+            return inline.trim_end_matches(']').split(',').next().map(|s| unquote(s).to_string());
+        }
+        return lines.next()?.trim().strip_prefix('-').map(|s| unquote(s).to_string());
+    }
+    None
+}
+
+/// The kubelet's clusterDomain, the suffix of Service names
+pub fn cluster_domain() -> String {
+    std::fs::read_to_string(host::path(KUBELET_CONFIG)).ok()
+        .and_then(|config| config.lines().find_map(|l| l.trim_start().strip_prefix("clusterDomain:").map(|v| unquote(v).to_string())))
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "cluster.local".to_string())
+}
+
+fn node_resolver() -> Option<String> {
+    std::fs::read_to_string(RESOLV_CONF).ok()?
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("nameserver").map(|s| s.trim().to_string()))
+}
+
+fn unquote(s: &str) -> &str {
+    s.trim().trim_matches(|c| c == '"' || c == '\'')
+}
+
+/// Resolves `name` against `server` over UDP, asking for AAAA records from
+/// IPv6 servers and A records otherwise; returns the response code and the
+/// number of answers
+pub async fn query(server: SocketAddr, name: &str) -> io::Result<(u8, u16)> {
+    let kind = if server.is_ipv6() { TYPE_AAAA } else { TYPE_A };
+    let reply = exchange(server, name, kind).await?;
+    Ok((reply[3] & 0x0f, u16::from_be_bytes([reply[6], reply[7]])))
+}
+
+/// Resolves the SRV records of `name` against `server` to the addresses of
+/// their targets, in order of priority. Targets come with A records in the
+/// additional section from cluster DNS; others are looked up.
+pub async fn resolve_srv(server: SocketAddr, name: &str) -> io::Result<Vec<SocketAddr>> {
+    let reply = exchange(server, name, TYPE_SRV).await?;
+    let records = parse_records(&reply).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed DNS reply"))?;
+    if reply[3] & 0x0f != 0 {
+        return Err(io::Error::other(format!("DNS response code {}", reply[3] & 0x0f)));
+    }
+
+    let mut targets: Vec<(u16, u16, String)> = records.iter()
+        .filter(|r| r.kind == TYPE_SRV && r.data.len() > 6)
+        .filter_map(|r| {
+            let (target, _) = read_name(&reply, r.offset + 6)?;
+            let d = &r.data;
+            Some((u16::from_be_bytes([d[0], d[1]]), u16::from_be_bytes([d[4], d[5]]), target))
+        })
+        .collect();
+    targets.sort_by_key(|(priority, _, _)| *priority);
+
+    let mut addresses = Vec::new();
+    for (_, port, target) in targets {
+        let mut ips: Vec<IpAddr> = records.iter()
+            .filter(|r| r.kind == TYPE_A && r.data.len() == 4 && r.name.eq_ignore_ascii_case(&target))
+            .map(|r| IpAddr::from([r.data[0], r.data[1], r.data[2], r.data[3]]))
+            .collect();
+        if ips.is_empty() {
+            let reply = exchange(server, &target, TYPE_A).await?;
+            ips = parse_records(&reply).unwrap_or_default().iter()
+                .filter(|r| r.kind == TYPE_A && r.data.len() == 4)
+                .map(|r| IpAddr::from([r.data[0], r.data[1], r.data[2], r.data[3]]))
+                .collect();
+        }
+        addresses.extend(ips.into_iter().map(|ip| SocketAddr::new(ip, port)));
+    }
+    Ok(addresses)
+}
+
+/// A resource record of a reply; offset is where its data starts, for
+/// names compressed against the whole message
+struct Record {
+    name: String,
+    kind: u16,
+    offset: usize,
+    data: Vec<u8>,
+}
+
+/// The answer, authority and additional records of a reply
+fn parse_records(msg: &[u8]) -> Option<Vec<Record>> {
+    let count = |i: usize| u16::from_be_bytes([msg[i], msg[i + 1]]) as usize;
+    if msg.len() < 12 {
+        return None;
+    }
+    let (questions, records) = (count(4), count(6) + count(8) + count(10));
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(msg, pos)?.1 + 4;
+    }
+
+    let mut parsed = Vec::with_capacity(records);
+    for _ in 0..records {
+        let (name, next) = read_name(msg, pos)?;
+        let header = msg.get(next..next + 10)?;
+        let kind = u16::from_be_bytes([header[0], header[1]]);
+        let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let offset = next + 10;
+        parsed.push(Record { name, kind, offset, data: msg.get(offset..offset + len)?.to_vec() });
+        pos = offset + len;
+    }
+    Some(parsed)
+}
+
+/// Reads a possibly compressed name at pos, returning it and the position
+/// after it
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds pointer loops in a malicious reply
+    for _ in 0..128 {
+        let len = *msg.get(pos)? as usize;
+        if len & 0xc0 == 0xc0 {
+            end.get_or_insert(pos + 2);
+            pos = ((len & 0x3f) << 8) | *msg.get(pos + 1)? as usize;
+            continue;
+        }
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        }
+        labels.push(String::from_utf8_lossy(msg.get(pos + 1..pos + 1 + len)?).into_owned());
+        pos += 1 + len;
+    }
+    None
+}
+
+/// Sends one query of `kind` for `name` and returns the reply to it
+async fn exchange(server: SocketAddr, name: &str, kind: u16) -> io::Result<Vec<u8>> {
+    let local: SocketAddr = match server.ip() {
+        IpAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        IpAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+
+    let id = (std::process::id() as u16) ^ (std::time::UNIX_EPOCH.elapsed().unwrap_or_default().subsec_nanos() as u16);
+    // Header: id, flags (recursion desired), one question
+    let mut request = Vec::with_capacity(18 + name.len());
+    request.extend_from_slice(&id.to_be_bytes());
+    request.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("bad name {}", name)));
+        }
+        request.push(label.len() as u8);
+        request.extend_from_slice(label.as_bytes());
+    }
+    request.push(0);
+    request.extend_from_slice(&kind.to_be_bytes());
+    request.extend_from_slice(&1u16.to_be_bytes());
+    socket.send(&request).await?;
+
+    let mut buf = [0u8; 1500];
+    loop {
+        let n = socket.recv(&mut buf).await?;
+        // Anything but the reply to this query is ignored
+        if n < 12 || buf[0..2] != id.to_be_bytes() || buf[2] & 0x80 == 0 {
+            continue;
+        }
+        return Ok(buf[..n].to_vec());
+    }
+}
@@ -0,0 +1,180 @@
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::errors::{CollectorError, Result};
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::unix_http;
+
+/// Where dockerd listens unless DOCKER_HOST says otherwise
+const SOCKET: &str = "/var/run/docker.sock";
+
+/// Longest wait for one API call, connection included
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stats calls in flight at once; each is a cgroup read on the daemon's side
+const PARALLEL: usize = 8;
+
+/// One entry of GET /containers/json
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Container {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    state: String,
+}
+
+/// The parts of GET /containers/{id}/stats used; the rest differs between
+/// cgroup versions and daemon releases
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Stats {
+    cpu_stats: CpuStats,
+    memory_stats: MemoryStats,
+    networks: HashMap<String, NetworkStats>,
+    pids_stats: PidsStats,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct CpuStats {
+    cpu_usage: CpuUsage,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct CpuUsage {
+    // Nanoseconds
+    total_usage: u64,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct MemoryStats {
+    usage: u64,
+    limit: u64,
+    stats: HashMap<String, u64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct NetworkStats {
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct PidsStats {
+    current: Option<u64>,
+}
+
+/// Containers by name from the Docker Engine API, for Docker hosts without
+/// a CRI runtime (docker-compose, plain `docker run`), where cgroups only
+/// name containers by ID. Stats come from the daemon, which reads the same
+/// cgroups, plus the network counters of each container's namespace.
+pub struct DockerCollector {
+    socket: PathBuf,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl DockerCollector {
+    /// DOCKER_HOST when it's a unix:// socket, else /var/run/docker.sock.
+    /// None if the socket isn't there.
+    pub fn new(interval: Duration) -> Option<Self> {
+        let socket = match std::env::var("DOCKER_HOST").ok().filter(|h| !h.is_empty()) {
+            Some(docker_host) => host::path(docker_host.strip_prefix("unix://")?),
+            None => host::path(SOCKET),
+        };
+        if !socket.exists() {
+            return None;
+        }
+        info!("Docker Engine API on {} | interval={:?}", socket.display(), interval);
+        Some(Self { socket, interval, last: None })
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    pub async fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        self.last = Some(Instant::now());
+
+        let containers: Vec<Container> = self.get("/containers/json?all=true").await?;
+        let running: Vec<&Container> = containers.iter().filter(|c| c.state == "running").collect();
+        info!("METRIC_TYPE=docker node={} containers={} running={}", node_name, containers.len(), running.len());
+        sender.add("docker", &Labels::default(), "containers", containers.len() as f64);
+        sender.add("docker", &Labels::default(), "running", running.len() as f64);
+
+        // one-shot skips the second sample daemons otherwise wait a second for
+        let stats: Vec<Result<Stats>> = futures::stream::iter(&running)
+            .map(|c| self.get(format!("/containers/{}/stats?stream=false&one-shot=true", c.id)))
+            .buffered(PARALLEL)
+            .collect()
+            .await;
+        let stats: HashMap<&str, Stats> = running.iter().zip(stats)
+            .filter_map(|(c, stats)| match stats {
+                Ok(stats) => Some((c.id.as_str(), stats)),
+                // Stopped since it was listed, most likely
+                Err(e) => {
+                    debug!("Docker: no stats for {}: {}", c.id, e);
+                    None
+                }
+            })
+            .collect();
+
+        for container in &containers {
+            let name = container.names.first().map_or(container.id.as_str(), |n| n.trim_start_matches('/'));
+            let labels = Labels { container_id: Some(&container.id), device: Some(name), ..Default::default() };
+            let Some(stats) = stats.get(container.id.as_str()) else {
+                sender.add("docker_container", &labels, "running", 0.0);
+                continue;
+            };
+            let cpu_ms = stats.cpu_stats.cpu_usage.total_usage / 1_000_000;
+            // As `docker stats` counts it: page cache that could be dropped isn't usage
+            let memory = &stats.memory_stats;
+            let inactive = memory.stats.get("inactive_file").or_else(|| memory.stats.get("total_inactive_file")).copied().unwrap_or(0);
+            let mem_mb = memory.usage.saturating_sub(inactive) / 1024 / 1024;
+            let mem_limit_mb = memory.limit / 1024 / 1024;
+            let rx_bytes: u64 = stats.networks.values().map(|n| n.rx_bytes).sum();
+            let tx_bytes: u64 = stats.networks.values().map(|n| n.tx_bytes).sum();
+            info!("METRIC_TYPE=docker_container node={} container={} container_id={} cpu_ms={} mem_mb={} mem_limit_mb={} rx_bytes={} tx_bytes={} pids={}",
+                node_name, name, container.id, cpu_ms, mem_mb, mem_limit_mb, rx_bytes, tx_bytes, stats.pids_stats.current.unwrap_or(0));
+
+            sender.add("docker_container", &labels, "running", 1.0);
+            sender.add_counter("docker_container", &labels, "cpu_ms", cpu_ms as f64);
+            sender.add("docker_container", &labels, "mem_mb", mem_mb as f64);
+            sender.add("docker_container", &labels, "mem_limit_mb", mem_limit_mb as f64);
+            sender.add_counter("docker_container", &labels, "rx_bytes", rx_bytes as f64);
+            sender.add_counter("docker_container", &labels, "tx_bytes", tx_bytes as f64);
+            if let Some(pids) = stats.pids_stats.current {
+                sender.add("docker_container", &labels, "pids", pids as f64);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: impl AsRef<str>) -> Result<T> {
+        let path = path.as_ref();
+        let url = reqwest::Url::parse(&format!("http://docker{}", path)).map_err(anyhow::Error::from)?;
+        let request = reqwest::Request::new(reqwest::Method::GET, url);
+        let response = match tokio::time::timeout(TIMEOUT, unix_http::send(&self.socket, request)).await {
+            Ok(response) => response?,
+            Err(_) => return Err(CollectorError::Timeout(format!("GET {} on the Docker API", path))),
+        };
+        let status = response.status();
+        let body = response.bytes().await.map_err(anyhow::Error::from)?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("GET {}: HTTP {}: {}", path, status, String::from_utf8_lossy(&body).trim()).into());
+        }
+        serde_json::from_slice(&body).map_err(|e| CollectorError::Parse(format!("GET {}: {}", path, e)))
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// Why a collector failed, which decides what the main loop does about it
+#[derive(Debug, thiserror::Error)]
+pub enum CollectorError {
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("{0} is not mounted")]
+    NotMounted(String),
+    #[error("cannot parse {0}")]
+    Parse(String),
+    #[error("timed out: {0}")]
+    Timeout(String),
+    #[error("{0}")]
+    Io(io::Error),
+    #[error("Kubernetes API: {0}")]
+    Api(kube::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, CollectorError>;
+
+/// What to do after a failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Won't fix itself while we run: stop calling the collector
+    Disable,
+    /// Transient, and retrying hard makes it worse: wait before the next call
+    Backoff,
+    /// Same result every cycle, but the rest of the data is still good
+    LogOnce,
+    /// Try again next cycle
+    Retry,
+}
+
+impl CollectorError {
+    /// A failed read of the host file at `path`
+    pub fn read(path: &Path, e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(path.display().to_string()),
+            // Present at startup, so its filesystem went away
+            io::ErrorKind::NotFound => Self::NotMounted(path.display().to_string()),
+            io::ErrorKind::TimedOut => Self::Timeout(path.display().to_string()),
+            _ => Self::Io(e),
+        }
+    }
+
+    /// Variant name, grouping failures for log throttling
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::NotMounted(_) => "not_mounted",
+            Self::Parse(_) => "parse",
+            Self::Timeout(_) => "timeout",
+            Self::Io(_) => "io",
+            Self::Api(_) => "api",
+            Self::Other(_) => "other",
+        }
+    }
+
+    pub fn policy(&self) -> Policy {
+        match self {
+            Self::PermissionDenied(_) | Self::NotMounted(_) => Policy::Disable,
+            Self::Parse(_) => Policy::LogOnce,
+            Self::Timeout(_) => Policy::Backoff,
+            Self::Api(kube::Error::Api(response)) if response.code == 429 || response.code >= 500 => Policy::Backoff,
+            Self::Io(_) | Self::Api(_) | Self::Other(_) => Policy::Retry,
+        }
+    }
+}
+
+impl From<io::Error> for CollectorError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(e.to_string()),
+            io::ErrorKind::TimedOut => Self::Timeout(e.to_string()),
+            _ => Self::Io(e),
+        }
+    }
+}
+
+impl From<kube::Error> for CollectorError {
+    fn from(e: kube::Error) -> Self {
+        match &e {
+            // RBAC is granted at install time; a forbidden list stays forbidden
+            kube::Error::Api(response) if response.code == 401 || response.code == 403 => {
+                Self::PermissionDenied(response.message.clone())
+            }
+            kube::Error::Api(response) if response.code == 504 => Self::Timeout(response.message.clone()),
+            _ => Self::Api(e),
+        }
+    }
+}
+
+const FIRST_BACKOFF: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+/// After the first warning of a kind, how often repeats are summarized
+const SUMMARY_EVERY: Duration = Duration::from_secs(300);
+
+/// Repeats of one kind of failure since it was last logged
+struct Suppressed {
+    since: Instant,
+    count: u64,
+}
+
+/// Applies the failure policy of one collector across cycles
+pub struct CollectorState {
+    name: &'static str,
+    disabled: bool,
+    retry_at: Option<Instant>,
+    backoff: Duration,
+    // Messages already logged under LogOnce
+    logged: HashSet<String>,
+    // Retry and Backoff failures by kind, logged first then summarized
+    suppressed: HashMap<&'static str, Suppressed>,
+    failures: u64,
+}
+
+impl CollectorState {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            disabled: false,
+            retry_at: None,
+            backoff: FIRST_BACKOFF,
+            logged: HashSet::new(),
+            suppressed: HashMap::new(),
+            failures: 0,
+        }
+    }
+
+    /// Whether the collector should run this cycle
+    pub fn ready(&self) -> bool {
+        !self.disabled && self.retry_at.is_none_or(|t| Instant::now() >= t)
+    }
+
+    pub fn disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub fn succeeded(&mut self) {
+        self.retry_at = None;
+        self.backoff = FIRST_BACKOFF;
+        let repeats: u64 = self.suppressed.drain().map(|(_, s)| s.count).sum();
+        if repeats > 0 {
+            info!("{} collector recovered after {} more failures", self.name, repeats);
+        }
+    }
+
+    pub fn observe<T>(&mut self, result: &Result<T>) {
+        match result {
+            Ok(_) => self.succeeded(),
+            Err(e) => self.failed(e),
+        }
+    }
+
+    /// Logs `e` as its policy says and schedules the next attempt
+    pub fn failed(&mut self, e: &CollectorError) {
+        self.failures += 1;
+        match e.policy() {
+            Policy::Disable => {
+                self.disabled = true;
+                warn!("⚠️  {} collector disabled until restart: {}", self.name, e);
+            }
+            Policy::Backoff => {
+                if let Some(repeats) = self.throttle(e) {
+                    warn!("⚠️  {} collector failed{}, retrying in {:?}: {}", self.name, repeats, self.backoff, e);
+                }
+                self.retry_at = Some(Instant::now() + self.backoff);
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+            }
+            Policy::LogOnce => {
+                let message = e.to_string();
+                if self.logged.insert(message.clone()) {
+                    warn!("⚠️  {} collector: {} (logged once)", self.name, message);
+                } else {
+                    debug!("{} collector: {}", self.name, message);
+                }
+            }
+            Policy::Retry => {
+                if let Some(repeats) = self.throttle(e) {
+                    warn!("⚠️  {} collector failed{}: {}", self.name, repeats, e);
+                }
+            }
+        }
+    }
+
+    /// Whether to log this failure: the first of its kind is, later ones
+    /// are only counted until SUMMARY_EVERY has passed. Returns what to
+    /// append to the message, the count of those held back.
+    fn throttle(&mut self, e: &CollectorError) -> Option<String> {
+        let now = Instant::now();
+        let Some(s) = self.suppressed.get_mut(e.kind()) else {
+            self.suppressed.insert(e.kind(), Suppressed { since: now, count: 0 });
+            return Some(String::new());
+        };
+        if now.duration_since(s.since) < SUMMARY_EVERY {
+            s.count += 1;
+            debug!("{} collector failed: {}", self.name, e);
+            return None;
+        }
+        let repeats = format!(" {} more times in {}s", s.count + 1, now.duration_since(s.since).as_secs());
+        *s = Suppressed { since: now, count: 0 };
+        Some(repeats)
+    }
+
+    /// Queues the failure count and whether the collector was disabled
+    pub fn report(&self, sender: &mut MetricsSender) {
+        let labels = Labels::default();
+        sender.add_counter("agent_collector", &labels, &format!("{}_failures", self.name), self.failures as f64);
+        sender.add("agent_collector", &labels, &format!("{}_disabled", self.name), self.disabled as u8 as f64);
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Event;
+use kube::api::Api;
+use kube::runtime::{watcher, WatchStreamExt};
+use serde::Serialize;
+use tokio::sync::{mpsc, watch};
+use tracing::{info, warn};
+
+use crate::metrics_sender::MetricsSender;
+
+/// Events kept while the consumer is unreachable; the oldest go first
+const MAX_PENDING: usize = 5000;
+
+/// On taking over, events seen this recently are shipped again in case the
+/// previous leader died before sending them; the consumer drops duplicates
+const TAKEOVER_REPLAY_SECS: i64 = 60;
+
+/// One observation of a Kubernetes event, as stored by the consumer
+#[derive(Debug, Serialize)]
+pub struct EventRecord {
+    pub uid: String,
+    pub count: i64,
+    pub namespace: String,
+    pub kind: String,
+    pub name: String,
+    pub reason: String,
+    pub message: String,
+    // "Normal" or "Warning"
+    #[serde(rename = "type")]
+    pub severity: String,
+    pub source: String,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+impl EventRecord {
+    fn from_event(ev: &Event) -> Option<Self> {
+        let uid = ev.metadata.uid.clone()?;
+        let created = ev.metadata.creation_timestamp.as_ref().map(|t| t.0.timestamp());
+
+        // events.k8s.io writers fill series/event_time, older ones count/last_timestamp
+        let last_seen = ev.series.as_ref().and_then(|s| s.last_observed_time.as_ref()).map(|t| t.0.timestamp())
+            .or_else(|| ev.last_timestamp.as_ref().map(|t| t.0.timestamp()))
+            .or_else(|| ev.event_time.as_ref().map(|t| t.0.timestamp()))
+            .or(created)?;
+        let first_seen = ev.first_timestamp.as_ref().map(|t| t.0.timestamp())
+            .or_else(|| ev.event_time.as_ref().map(|t| t.0.timestamp()))
+            .unwrap_or(last_seen);
+        let count = ev.series.as_ref().and_then(|s| s.count)
+            .or(ev.count)
+            .unwrap_or(1);
+        let source = ev.reporting_component.clone()
+            .filter(|c| !c.is_empty())
+            .or_else(|| ev.source.as_ref().and_then(|s| s.component.clone()))
+            .unwrap_or_default();
+
+        let involved = &ev.involved_object;
+        Some(Self {
+            uid,
+            count: count as i64,
+            namespace: involved.namespace.clone()
+                .or_else(|| ev.metadata.namespace.clone())
+                .unwrap_or_default(),
+            kind: involved.kind.clone().unwrap_or_default(),
+            name: involved.name.clone().unwrap_or_default(),
+            reason: ev.reason.clone().unwrap_or_default(),
+            message: ev.message.clone().unwrap_or_default(),
+            severity: ev.type_.clone().unwrap_or_else(|| "Normal".to_string()),
+            source,
+            first_seen,
+            last_seen,
+        })
+    }
+}
+
+/// Watches core/v1 Events cluster-wide while this agent holds the cluster
+/// collector lease, and ships them to the consumer after each flush
+pub struct EventCollector {
+    rx: mpsc::Receiver<EventRecord>,
+    pending: Vec<EventRecord>,
+}
+
+impl EventCollector {
+    pub fn spawn(client: kube::Client, mut leading: watch::Receiver<bool>) -> Self {
+        // A full channel drops new events rather than blocking the watch
+        let (tx, rx) = mpsc::channel(MAX_PENDING);
+        let api = Api::<Event>::all(client);
+
+        tokio::spawn(async move {
+            loop {
+                // Idle until elected
+                while !*leading.borrow_and_update() {
+                    if leading.changed().await.is_err() {
+                        return;
+                    }
+                }
+
+                let since = chrono::Utc::now().timestamp() - TAKEOVER_REPLAY_SECS;
+                info!("Watching Kubernetes events");
+                let mut events = watcher(api.clone(), watcher::Config::default())
+                    .default_backoff()
+                    .boxed();
+
+                loop {
+                    tokio::select! {
+                        changed = leading.changed() => {
+                            if changed.is_err() {
+                                return;
+                            }
+                            if !*leading.borrow() {
+                                info!("Stopped watching Kubernetes events");
+                                break;
+                            }
+                        }
+                        event = events.next() => match event {
+                            Some(Ok(watcher::Event::Apply(ev) | watcher::Event::InitApply(ev))) => {
+                                let Some(record) = EventRecord::from_event(&ev) else { continue };
+                                if record.last_seen < since {
+                                    continue;
+                                }
+                                if let Err(mpsc::error::TrySendError::Closed(_)) = tx.try_send(record) {
+                                    return;
+                                }
+                            }
+                            Some(Ok(_)) => {}
+                            Some(Err(e)) => warn!("Event watch error: {}", e),
+                            None => break,
+                        }
+                    }
+                }
+            }
+        });
+
+        Self { rx, pending: Vec::new() }
+    }
+
+    /// Sends events received since the last call. Unsent events are kept for
+    /// the next attempt, up to MAX_PENDING.
+    pub async fn flush(&mut self, sender: &mut MetricsSender) -> Result<()> {
+        while let Ok(record) = self.rx.try_recv() {
+            self.pending.push(record);
+        }
+        if self.pending.len() > MAX_PENDING {
+            let excess = self.pending.len() - MAX_PENDING;
+            warn!("Dropping {} unsent Kubernetes events", excess);
+            self.pending.drain(..excess);
+        }
+        if self.pending.is_empty() || sender.backing_off() {
+            return Ok(());
+        }
+
+        sender.send_events(&self.pending).await?;
+        self.pending.clear();
+        Ok(())
+    }
+}
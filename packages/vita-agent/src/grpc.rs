@@ -0,0 +1,146 @@
+//! Just enough gRPC and protobuf for the node-local APIs we call: the CRI
+//! runtime and the kubelet's pod-resources socket. Messages are decoded by
+//! hand, field by field, instead of generating code for whole protos.
+
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// One unary gRPC call over a fresh HTTP/2 connection on `io`; returns
+/// the reply message
+pub async fn call<T>(io: T, method: &str, message: &[u8]) -> Result<Vec<u8>>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (client, conn) = h2::client::handshake(io).await?;
+    // Drives the connection; it ends once the client is dropped
+    tokio::spawn(async move {
+        let _ = conn.await;
+    });
+
+    let request = http::Request::builder()
+        .method("POST")
+        .uri(format!("http://localhost{}", method))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(())?;
+    let mut client = client.ready().await?;
+    let (response, mut stream) = client.send_request(request, false)?;
+
+    // Length-prefixed message: uncompressed flag, then big-endian length
+    let mut frame = Vec::with_capacity(5 + message.len());
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    stream.send_data(Bytes::from(frame), true)?;
+
+    let (head, mut body) = response.await?.into_parts();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        data.extend_from_slice(&chunk);
+    }
+
+    // Status comes in trailers, or in the headers of a reply without a body
+    let trailers = body.trailers().await?;
+    let header = |name: &str| {
+        trailers.as_ref().and_then(|t| t.get(name))
+            .or_else(|| head.headers.get(name))
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    match header("grpc-status").as_str() {
+        "0" => {}
+        "" => bail!("gRPC reply without status (HTTP {})", head.status),
+        code => bail!("gRPC call failed: status {} {}", code, header("grpc-message")),
+    }
+
+    if data.len() < 5 {
+        bail!("truncated gRPC reply");
+    }
+    if data[0] != 0 {
+        bail!("compressed gRPC reply");
+    }
+    let len = u32::from_be_bytes([data[1], data[2], data[3], data[4]]) as usize;
+    let message = data.get(5..5 + len).context("truncated gRPC reply")?;
+    Ok(message.to_vec())
+}
+
+pub enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Walks the fields of one protobuf message as (number, value)
+pub struct Fields<'a>(pub &'a [u8]);
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, Value<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        if field.is_err() {
+            // Stop after a malformed field
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+impl<'a> Fields<'a> {
+    fn field(&mut self) -> Result<(u64, Value<'a>)> {
+        let tag = self.varint()?;
+        let value = match tag & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            wire => bail!("unsupported protobuf wire type {}", wire),
+        };
+        Ok((tag >> 3, value))
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.0.split_first().context("truncated protobuf")?;
+            self.0 = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("invalid protobuf varint")
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("truncated protobuf");
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+}
+
+/// The values of a packed repeated integer field
+#[cfg_attr(windows, allow(dead_code))]
+pub fn packed(buf: &[u8]) -> impl Iterator<Item = Result<u64>> + '_ {
+    let mut fields = Fields(buf);
+    std::iter::from_fn(move || (!fields.0.is_empty()).then(|| fields.varint()))
+}
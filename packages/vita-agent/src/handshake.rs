@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+
+/// Ingest protocol versions this agent speaks, newest first
+pub const PROTOCOLS: [u32; 1] = [1];
+
+/// Most of the late window given up to clock skew
+const BACKFILL_MARGIN_SECS: i64 = 600;
+
+/// Sent to the consumer at startup; every list is in order of preference
+#[derive(Debug, Serialize)]
+pub struct Offer<'a> {
+    pub node: &'a str,
+    pub version: &'a str,
+    pub protocols: &'a [u32],
+    pub wire_formats: &'a [&'a str],
+    pub compression: &'a [&'a str],
+}
+
+/// What the consumer agreed to
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Agreement {
+    pub protocol: u32,
+    pub wire_format: String,
+    pub compression: String,
+    // 0 when the consumer sets no limit
+    #[serde(default)]
+    pub max_batch_metrics: usize,
+    // Optional payloads besides metrics ("events", "heartbeat", ...)
+    #[serde(default)]
+    pub kinds: Option<Vec<String>>,
+    // Live metrics further behind are clamped or rejected; 0 if they aren't
+    #[serde(default)]
+    pub late_tolerance_secs: i64,
+}
+
+impl Agreement {
+    /// What a consumer from before the handshake accepts: whatever it was
+    /// configured for, uncompressed and of any size
+    pub fn legacy(compact: bool) -> Self {
+        Self {
+            protocol: PROTOCOLS[PROTOCOLS.len() - 1],
+            wire_format: if compact { "compact" } else { "json" }.to_string(),
+            compression: "identity".to_string(),
+            max_batch_metrics: 0,
+            kinds: None,
+            late_tolerance_secs: 0,
+        }
+    }
+
+    pub fn compact(&self) -> bool {
+        self.wire_format == "compact"
+    }
+
+    pub fn gzip(&self) -> bool {
+        self.compression == "gzip"
+    }
+
+    /// Whether the consumer takes this kind of payload. Without a list, as
+    /// from an old consumer, sending is attempted and failures are logged.
+    pub fn accepts(&self, kind: &str) -> bool {
+        self.kinds.as_ref().is_none_or(|kinds| kinds.iter().any(|k| k == kind))
+    }
+
+    /// Timestamp before which metrics go to the backfill endpoint rather than
+    /// live ingest, which would clamp or reject them. Backfilling starts a
+    /// little early so clock skew doesn't push a metric over the edge.
+    pub fn backfill_before(&self, now: i64) -> Option<i64> {
+        let listed = self.kinds.as_ref().is_some_and(|kinds| kinds.iter().any(|k| k == "backfill"));
+        (listed && self.late_tolerance_secs > 0)
+            .then(|| now - self.late_tolerance_secs + (self.late_tolerance_secs / 10).min(BACKFILL_MARGIN_SECS))
+    }
+}
@@ -0,0 +1,50 @@
+use serde::Serialize;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// Sent on its own schedule whether or not collection succeeds, so the
+/// consumer can tell an agent that is down from a node with nothing to report
+#[derive(Debug, Serialize)]
+pub struct HeartbeatRecord<'a> {
+    pub node: &'a str,
+    pub version: &'a str,
+    pub collectors: &'a [&'a str],
+    pub config_hash: String,
+    pub uptime_secs: u64,
+    // Lets the consumer judge staleness without knowing our settings
+    pub interval_secs: u64,
+}
+
+pub struct Heartbeat {
+    started: Instant,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Heartbeat {
+    pub fn new(interval: Duration) -> Self {
+        Self { started: Instant::now(), interval, last: None }
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    /// Builds the next heartbeat; `config` is anything identifying the
+    /// effective configuration, hashed so rollouts can be tracked per node
+    pub fn record<'a>(&mut self, node: &'a str, collectors: &'a [&'a str], config: &impl Hash) -> HeartbeatRecord<'a> {
+        self.last = Some(Instant::now());
+
+        let mut hasher = DefaultHasher::new();
+        config.hash(&mut hasher);
+
+        HeartbeatRecord {
+            node,
+            version: env!("CARGO_PKG_VERSION"),
+            collectors,
+            config_hash: format!("{:016x}", hasher.finish()),
+            uptime_secs: self.started.elapsed().as_secs(),
+            interval_secs: self.interval.as_secs(),
+        }
+    }
+}
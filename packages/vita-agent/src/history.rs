@@ -0,0 +1,199 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+use crate::metrics_sender::{get_timestamp, RawMetric, SeriesKey};
+
+/// Labels a query can filter on, as named on the wire
+const FILTERS: [&str; 7] = ["type", "pod_id", "pod_uid", "volume", "container_id", "device", "key"];
+
+/// Largest request read; queries are a path and a few parameters
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// The last minutes of every series the agent sent, kept in memory so they
+/// can be looked at on the node while the consumer is down. Points are
+/// (cycle timestamp, value); past `max_series`, new series aren't kept.
+pub struct History {
+    window: Duration,
+    max_series: usize,
+    series: HashMap<SeriesKey, VecDeque<(i64, f64)>>,
+    // Cycle last recorded, so a batch held back for the consumer isn't
+    // recorded again when it's retried
+    recorded_ts: i64,
+    full_warned: bool,
+}
+
+impl History {
+    pub fn new(window: Duration, max_series: usize) -> Self {
+        Self { window, max_series, series: HashMap::new(), recorded_ts: 0, full_warned: false }
+    }
+
+    /// Adds the metrics of the cycle stamped `ts`, and forgets points that
+    /// fell out of the window
+    pub fn record<'a>(&mut self, ts: i64, metrics: impl Iterator<Item = &'a RawMetric>) {
+        if ts == self.recorded_ts {
+            return;
+        }
+        self.recorded_ts = ts;
+        for metric in metrics.filter(|m| m.ts == ts) {
+            let key = SeriesKey::of(metric);
+            let at = self.series.len();
+            match self.series.get_mut(&key) {
+                Some(points) => points.push_back((ts, metric.value)),
+                None if at < self.max_series => {
+                    self.series.insert(key, VecDeque::from([(ts, metric.value)]));
+                }
+                None if !self.full_warned => {
+                    warn!("History holds {} series, not keeping new ones", self.max_series);
+                    self.full_warned = true;
+                }
+                None => {}
+            }
+        }
+
+        let oldest = ts - self.window.as_secs() as i64;
+        self.series.retain(|_, points| {
+            while points.front().is_some_and(|(t, _)| *t < oldest) {
+                points.pop_front();
+            }
+            !points.is_empty()
+        });
+    }
+
+    /// Series matching every filter, with their points since `since`
+    fn query(&self, filters: &[(String, String)], since: i64) -> Vec<Series<'_>> {
+        let mut found: Vec<Series> = self.series.iter()
+            .filter(|(key, _)| filters.iter().all(|(name, value)| key.label(name) == Some(value.as_str())))
+            .map(|(key, points)| Series {
+                key,
+                points: points.iter().filter(|(t, _)| *t >= since).copied().collect(),
+            })
+            .filter(|s| !s.points.is_empty())
+            .collect();
+        found.sort_by(|a, b| FILTERS.iter().map(|f| a.key.label(f)).cmp(FILTERS.iter().map(|f| b.key.label(f))));
+        found
+    }
+}
+
+#[derive(Serialize)]
+struct Series<'a> {
+    #[serde(flatten)]
+    key: &'a SeriesKey,
+    points: Vec<(i64, f64)>,
+}
+
+/// Serves `GET /api/v1/local/query` on `addr`. Parameters are the label
+/// filters (type=node_cpu&device=eth0) and `minutes`, how far back to go;
+/// without a type, only the series are listed, not their points.
+pub async fn serve(addr: SocketAddr, history: Arc<Mutex<History>>) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Local query endpoint unavailable on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("Local query endpoint on http://{}/api/v1/local/query", addr);
+    loop {
+        let Ok((stream, peer)) = listener.accept().await else { continue };
+        let history = history.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &history).await {
+                debug!("Local query from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle(mut stream: TcpStream, history: &Mutex<History>) -> anyhow::Result<()> {
+    let mut raw = Vec::new();
+    let target = loop {
+        let mut chunk = [0u8; 1024];
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut chunk)).await??;
+        if n == 0 {
+            return Ok(());
+        }
+        raw.extend_from_slice(&chunk[..n]);
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut request = httparse::Request::new(&mut headers);
+        if let httparse::Status::Complete(_) = request.parse(&raw)? {
+            if request.method != Some("GET") {
+                return respond(&mut stream, "405 Method Not Allowed", b"{\"error\":\"GET only\"}").await;
+            }
+            break request.path.unwrap_or("/").to_string();
+        }
+        if raw.len() > MAX_REQUEST {
+            return respond(&mut stream, "431 Request Header Fields Too Large", b"{}").await;
+        }
+    };
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    if path != "/api/v1/local/query" {
+        return respond(&mut stream, "404 Not Found", b"{\"error\":\"not found\"}").await;
+    }
+    let mut filters = Vec::new();
+    let mut minutes = None;
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode(value);
+        match name {
+            "minutes" => minutes = value.parse::<u64>().ok(),
+            name if FILTERS.contains(&name) => filters.push((name.to_string(), value)),
+            _ => {
+                let body = format!("{{\"error\":\"unknown parameter {}\"}}", name.replace('"', ""));
+                return respond(&mut stream, "400 Bad Request", body.as_bytes()).await;
+            }
+        }
+    }
+    let listing = !filters.iter().any(|(name, _)| name == "type");
+
+    let body = {
+        let history = history.lock().unwrap();
+        let since = minutes.map_or(0, |m| get_timestamp() - m as i64 * 60);
+        let series = history.query(&filters, since);
+        if listing {
+            let keys: Vec<&SeriesKey> = series.iter().map(|s| s.key).collect();
+            serde_json::to_vec(&serde_json::json!({ "series": keys }))?
+        } else {
+            serde_json::to_vec(&serde_json::json!({ "series": series }))?
+        }
+    };
+    respond(&mut stream, "200 OK", &body).await
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &[u8]) -> anyhow::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    Ok(stream.shutdown().await?)
+}
+
+/// Percent-decodes a query value; '+' is a space
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => out.push(b' '),
+            (b, _) => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,26 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// Set once at startup when replaying a snapshot
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Reads host files from under `root` instead of `/` from now on
+pub fn set_root(root: PathBuf) {
+    let _ = ROOT.set(root);
+}
+
+/// Whether host files come from a snapshot, which leaves nothing to ask
+/// the live kernel about
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn replaying() -> bool {
+    ROOT.get().is_some()
+}
+
+/// Where the host file at absolute `path` (/proc, /sys or /var/lib/kubelet)
+/// is read from
+pub fn path(path: &str) -> PathBuf {
+    match ROOT.get() {
+        Some(root) => root.join(path.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::errors::Result;
+use crate::host;
+use crate::inotify::DirWatcher;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::parsers;
+use crate::statfile::{self, StatFile};
+
+/// Rediscover the cgroups at least this often, even without inotify events
+const REFRESH_EVERY: u32 = 30;
+
+/// Trees walked under the cgroup root in host mode: systemd services,
+/// users' sessions, and containers of Docker's cgroupfs driver
+const HOST_ROOTS: &[&str] = &["system.slice", "user.slice", "docker"];
+
+/// Top-level trees summed up in node_overhead, by key prefix: what the
+/// node's own services, logged-in users and pods take
+const TOTALS: &[(&str, &str)] = &[
+    ("system", "system.slice"),
+    ("user", "user.slice"),
+    ("pods", "kubepods.slice"),
+    ("pods", "kubepods"),
+];
+
+/// One service, user or container cgroup read every cycle
+struct Target {
+    // Path under the cgroup root, e.g. system.slice/sshd.service
+    name: String,
+    container_id: Option<String>,
+    cpu: StatFile,
+    memory_current: StatFile,
+    memory_max: StatFile,
+    pids: StatFile,
+}
+
+struct Usage {
+    cpu_ms: u64,
+    mem_mb: u64,
+}
+
+impl Target {
+    fn labels(&self) -> Labels<'_> {
+        Labels { device: Some(&self.name), container_id: self.container_id.as_deref(), ..Default::default() }
+    }
+
+    /// CPU time and memory in use; None once the cgroup is gone
+    fn usage(&mut self, v2: bool, buf: &mut Vec<u8>) -> Option<Usage> {
+        let cpu_ms = match self.cpu.read(buf) {
+            // usage_usec in cpu.stat on v2, nanoseconds in cpuacct.usage on v1
+            Ok(content) if v2 => parsers::cgroup_key(content, "usage_usec").map(|usec| usec / 1000),
+            Ok(content) => parsers::cgroup_value(content).map(|nsec| nsec / 1_000_000),
+            Err(e) if statfile::is_gone(&e) => return None,
+            Err(_) => None,
+        }.unwrap_or(0);
+        let mem_mb = self.memory_current.read(buf).ok().and_then(parsers::cgroup_value).unwrap_or(0) / 1024 / 1024;
+        Some(Usage { cpu_ms, mem_mb })
+    }
+}
+
+/// Cgroups outside the pods: each unit under system.slice (nested slices
+/// walked into), and in host mode, where they replace the pod cgroups,
+/// each user-N.slice as a whole and each container under Docker's systemd
+/// or cgroupfs driver, named by its ID. The top-level trees are also
+/// totalled, so on a node the services' share can be told from the pods'.
+/// Like the container collector, the tree is only rewalked when inotify
+/// reports a change, a cgroup disappears, or every REFRESH_EVERY cycles.
+pub struct HostCgroupCollector {
+    roots: &'static [&'static str],
+    targets: Vec<Target>,
+    // Key prefix and cgroup of each top-level tree present
+    totals: Vec<(&'static str, Target)>,
+    watcher: Option<DirWatcher>,
+    cycles_since_refresh: u32,
+    dirty: bool,
+    buf: Vec<u8>,
+}
+
+impl HostCgroupCollector {
+    /// Services, users and Docker containers, for host mode
+    pub fn new() -> Self {
+        Self::with_roots(HOST_ROOTS)
+    }
+
+    /// The services next to the pods of a Kubernetes node: the kubelet,
+    /// the container runtime, sshd, journald
+    pub fn system_slice() -> Self {
+        Self::with_roots(&["system.slice"])
+    }
+
+    fn with_roots(roots: &'static [&'static str]) -> Self {
+        Self {
+            roots,
+            targets: Vec::new(),
+            totals: Vec::new(),
+            watcher: None,
+            cycles_since_refresh: 0,
+            dirty: true,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Whether any of host mode's trees is readable, for the capability probe
+    pub fn readable() -> bool {
+        let base = if cgroup_v2() { "/sys/fs/cgroup" } else { "/sys/fs/cgroup/cpuacct" };
+        HOST_ROOTS.iter().any(|root| fs::read_dir(host::path(base).join(root)).is_ok())
+    }
+
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        let changed = self.watcher.as_ref().is_some_and(|w| w.changed());
+        if self.dirty || changed || self.cycles_since_refresh >= REFRESH_EVERY {
+            self.refresh();
+        }
+        self.cycles_since_refresh += 1;
+
+        let v2 = cgroup_v2();
+        let mut vanished = false;
+        for target in &mut self.targets {
+            let Some(Usage { cpu_ms, mem_mb }) = target.usage(v2, &mut self.buf) else {
+                vanished = true;
+                continue;
+            };
+            // "max" on v2 and a page-rounded i64::MAX on v1 mean no limit, reported as 0
+            let mem_limit_mb = target.memory_max.read(&mut self.buf).ok().and_then(parsers::cgroup_value)
+                .filter(|&bytes| bytes < 1 << 62)
+                .unwrap_or(0) / 1024 / 1024;
+            let tasks = target.pids.read(&mut self.buf).ok().and_then(parsers::cgroup_value);
+
+            info!("METRIC_TYPE=cgroup node={} cgroup={} cpu_ms={} mem_mb={} mem_limit_mb={} tasks={}",
+                node_name, target.name, cpu_ms, mem_mb, mem_limit_mb, tasks.unwrap_or(0));
+
+            let labels = target.labels();
+            sender.add_counter("cgroup", &labels, "cpu_ms", cpu_ms as f64);
+            sender.add("cgroup", &labels, "mem_mb", mem_mb as f64);
+            sender.add("cgroup", &labels, "mem_limit_mb", mem_limit_mb as f64);
+            if let Some(tasks) = tasks {
+                sender.add("cgroup", &labels, "tasks", tasks as f64);
+            }
+        }
+
+        let mut line = format!("METRIC_TYPE=node_overhead node={}", node_name);
+        for (prefix, target) in &mut self.totals {
+            // Top-level trees stay; an unreadable one is skipped
+            let Some(Usage { cpu_ms, mem_mb }) = target.usage(v2, &mut self.buf).filter(|u| u.cpu_ms > 0) else { continue };
+            let _ = write!(line, " {prefix}_cpu_ms={cpu_ms} {prefix}_mem_mb={mem_mb}");
+            sender.add_counter("node_overhead", &Labels::default(), &format!("{}_cpu_ms", prefix), cpu_ms as f64);
+            sender.add("node_overhead", &Labels::default(), &format!("{}_mem_mb", prefix), mem_mb as f64);
+        }
+        if !self.totals.is_empty() {
+            info!("{}", line);
+        }
+        self.dirty = vanished;
+        Ok(())
+    }
+
+    fn refresh(&mut self) {
+        let watcher = DirWatcher::new();
+        let v2 = cgroup_v2();
+        // v1 spreads a cgroup over one hierarchy per controller
+        let (cpu_base, memory_base, pids_base) = if v2 {
+            let base = host::path("/sys/fs/cgroup");
+            (base.clone(), base.clone(), base)
+        } else {
+            (host::path("/sys/fs/cgroup/cpuacct"), host::path("/sys/fs/cgroup/memory"), host::path("/sys/fs/cgroup/pids"))
+        };
+
+        let mut found = Vec::new();
+        for root in self.roots {
+            discover(&cpu_base, Path::new(root), watcher.as_ref(), &mut found);
+        }
+
+        let (cpu, current, max) = if v2 {
+            ("cpu.stat", "memory.current", "memory.max")
+        } else {
+            ("cpuacct.usage", "memory.usage_in_bytes", "memory.limit_in_bytes")
+        };
+        let target = |name: String, container_id| Target {
+            cpu: StatFile::new(cpu_base.join(&name).join(cpu)),
+            memory_current: StatFile::new(memory_base.join(&name).join(current)),
+            memory_max: StatFile::new(memory_base.join(&name).join(max)),
+            pids: StatFile::new(pids_base.join(&name).join("pids.current")),
+            name,
+            container_id,
+        };
+
+        // Cgroups still there keep their open files
+        let mut previous: HashMap<String, Target> = self.targets.drain(..).map(|t| (t.name.clone(), t)).collect();
+        self.targets = found.into_iter()
+            .map(|(name, container_id)| previous.remove(&name).unwrap_or_else(|| target(name, container_id)))
+            .collect();
+        self.totals = TOTALS.iter()
+            .filter(|(_, tree)| cpu_base.join(tree).is_dir())
+            .map(|&(prefix, tree)| (prefix, target(tree.to_string(), None)))
+            .collect();
+        self.watcher = watcher;
+        self.cycles_since_refresh = 0;
+        self.dirty = false;
+    }
+}
+
+fn cgroup_v2() -> bool {
+    host::path("/sys/fs/cgroup/cgroup.controllers").exists()
+}
+
+/// Adds the cgroups to report under `dir`, relative to `base`, with the
+/// container ID of those that are Docker containers
+fn discover(base: &Path, dir: &Path, watcher: Option<&DirWatcher>, found: &mut Vec<(String, Option<String>)>) {
+    let path = base.join(dir);
+    // Watch before listing, so cgroups created mid-walk still trigger a refresh
+    if let Some(w) = watcher {
+        w.watch(&path);
+    }
+    let Ok(entries) = fs::read_dir(&path) else { return };
+    let top = dir.to_string_lossy();
+    for entry in entries.flatten().filter(|e| e.file_type().is_ok_and(|t| t.is_dir())) {
+        let Some(name) = entry.file_name().to_str().map(String::from) else { continue };
+        let relative: PathBuf = dir.join(&name);
+        let container_id = docker_id(&name);
+        if top == "docker" && container_id.is_none() {
+            continue;
+        }
+        // Slices under system.slice only group services; users are reported whole
+        if top != "user.slice" && name.ends_with(".slice") {
+            discover(base, &relative, watcher, found);
+            continue;
+        }
+        found.push((relative.to_string_lossy().into_owned(), container_id));
+    }
+}
+
+/// Container ID from a cgroup name: docker-<id>.scope under the systemd
+/// driver, the ID itself under cgroupfs
+fn docker_id(name: &str) -> Option<String> {
+    let id = name.strip_prefix("docker-").and_then(|n| n.strip_suffix(".scope")).unwrap_or(name);
+    (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then(|| id.to_string())
+}
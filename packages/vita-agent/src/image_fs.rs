@@ -0,0 +1,195 @@
+use anyhow::Context;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::net::UnixStream;
+use tracing::{debug, info};
+
+use crate::errors::{CollectorError, Result};
+use crate::grpc::{self, Fields, Value};
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::pvc_metrics;
+
+/// Where containerd and CRI-O serve CRI; the first one present is used
+const SOCKETS: [&str; 2] = ["/run/containerd/containerd.sock", "/var/run/crio/crio.sock"];
+
+/// Longest wait for the runtime, connection included
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One image the runtime keeps
+#[derive(Default)]
+struct Image {
+    id: String,
+    // First tag, or the digest for untagged images
+    name: String,
+    size: u64,
+    // Exempt from image garbage collection
+    pinned: bool,
+}
+
+/// A filesystem the runtime keeps images on
+#[derive(Default)]
+struct FsUsage {
+    mountpoint: String,
+    used_bytes: u64,
+    inodes_used: u64,
+}
+
+/// Image storage from the CRI runtime's image service: what each image
+/// takes and how full the filesystem holding them is. The kubelet garbage
+/// collects images, and finally evicts pods, by the same numbers.
+pub struct ImageFsCollector {
+    socket: PathBuf,
+    // Statted when the runtime names a mountpoint we can't see
+    root: PathBuf,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl ImageFsCollector {
+    /// IMAGE_FS_ROOT names the runtime's state directory. None if no
+    /// runtime serves CRI here.
+    pub fn new(interval: Duration) -> Option<Self> {
+        let socket = runtime_socket()?;
+        let root = host::path(&std::env::var("IMAGE_FS_ROOT").unwrap_or_else(|_| "/var/lib/containerd".to_string()));
+        Some(Self { socket, root, interval, last: None })
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    pub async fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        self.last = Some(Instant::now());
+
+        // ImageFsInfoResponse: repeated FilesystemUsage image_filesystems = 1
+        let reply = self.call("/runtime.v1.ImageService/ImageFsInfo").await?;
+        for field in Fields(&reply) {
+            let (1, Value::Bytes(usage)) = field? else { continue };
+            let usage = decode_fs_usage(usage)?;
+            let reported = (!usage.mountpoint.is_empty()).then(|| host::path(&usage.mountpoint));
+            let (total, free) = reported.iter().chain([&self.root])
+                .find_map(|dir| space(dir))
+                .unwrap_or((0, 0));
+
+            let mb = |bytes: u64| bytes / 1024 / 1024;
+            info!("METRIC_TYPE=node_imagefs node={} mountpoint={} used_mb={} inodes_used={} total_mb={} free_mb={}",
+                node_name, usage.mountpoint, mb(usage.used_bytes), usage.inodes_used, mb(total), mb(free));
+
+            let labels = Labels { device: Some(&usage.mountpoint), ..Default::default() };
+            sender.add("node_imagefs", &labels, "used_mb", mb(usage.used_bytes) as f64);
+            sender.add("node_imagefs", &labels, "inodes_used", usage.inodes_used as f64);
+            if total > 0 {
+                sender.add("node_imagefs", &labels, "total_mb", mb(total) as f64);
+                sender.add("node_imagefs", &labels, "free_mb", mb(free) as f64);
+            }
+        }
+
+        // ListImagesResponse: repeated Image images = 1
+        let reply = self.call("/runtime.v1.ImageService/ListImages").await?;
+        let mut images = Vec::new();
+        for field in Fields(&reply) {
+            if let (1, Value::Bytes(image)) = field? {
+                images.push(decode_image(image)?);
+            }
+        }
+        // Layers shared between images count toward each, so the sum
+        // exceeds what the filesystem holds
+        let size: u64 = images.iter().map(|i| i.size).sum();
+        let pinned = images.iter().filter(|i| i.pinned).count();
+        info!("METRIC_TYPE=node_images node={} images={} pinned={} size_mb={}",
+            node_name, images.len(), pinned, size / 1024 / 1024);
+
+        let labels = Labels::default();
+        sender.add("node_images", &labels, "images", images.len() as f64);
+        sender.add("node_images", &labels, "pinned", pinned as f64);
+        sender.add("node_images", &labels, "size_mb", (size / 1024 / 1024) as f64);
+        for image in &images {
+            debug!("Image {} ({}): {} bytes", image.name, image.id, image.size);
+            let labels = Labels { device: Some(&image.name), ..Default::default() };
+            sender.add("image_usage", &labels, "size_mb", image.size as f64 / 1024.0 / 1024.0);
+        }
+        Ok(())
+    }
+
+    async fn call(&self, method: &str) -> Result<Vec<u8>> {
+        let call = async {
+            let io = UnixStream::connect(&self.socket).await
+                .with_context(|| format!("connecting to {}", self.socket.display()))?;
+            grpc::call(io, method, &[]).await
+        };
+        match tokio::time::timeout(TIMEOUT, call).await {
+            Ok(reply) => Ok(reply?),
+            Err(_) => Err(CollectorError::Timeout(format!("calling {} on the CRI runtime", method))),
+        }
+    }
+}
+
+/// The CRI runtime's socket: CRI_ENDPOINT, as a path or the unix:// URL
+/// kubelet takes, or the first default present. None if it doesn't exist.
+pub fn runtime_socket() -> Option<PathBuf> {
+    let socket = match std::env::var("CRI_ENDPOINT").ok().filter(|e| !e.is_empty()) {
+        Some(e) => host::path(e.strip_prefix("unix://").unwrap_or(&e)),
+        None => SOCKETS.iter().map(|s| host::path(s)).find(|s| s.exists())?,
+    };
+    socket.exists().then_some(socket)
+}
+
+/// Total and free bytes of the filesystem holding `dir`
+fn space(dir: &Path) -> Option<(u64, u64)> {
+    let dir = CString::new(dir.to_string_lossy().as_bytes()).ok()?;
+    pvc_metrics::filesystem_space(&dir).ok()
+}
+
+/// FilesystemUsage: fs_id = 2 (FilesystemIdentifier: mountpoint = 1),
+/// used_bytes = 3, inodes_used = 4, both UInt64Value
+fn decode_fs_usage(buf: &[u8]) -> anyhow::Result<FsUsage> {
+    let mut usage = FsUsage::default();
+    for field in Fields(buf) {
+        match field? {
+            (2, Value::Bytes(id)) => {
+                for field in Fields(id) {
+                    if let (1, Value::Bytes(v)) = field? {
+                        usage.mountpoint = String::from_utf8_lossy(v).into_owned();
+                    }
+                }
+            }
+            (3, Value::Bytes(v)) => usage.used_bytes = wrapped(v)?,
+            (4, Value::Bytes(v)) => usage.inodes_used = wrapped(v)?,
+            _ => {}
+        }
+    }
+    Ok(usage)
+}
+
+/// Image: id = 1, repeated repo_tags = 2, repeated repo_digests = 3,
+/// size = 4, pinned = 8
+fn decode_image(buf: &[u8]) -> anyhow::Result<Image> {
+    let mut image = Image::default();
+    let mut digest = String::new();
+    for field in Fields(buf) {
+        match field? {
+            (1, Value::Bytes(v)) => image.id = String::from_utf8_lossy(v).into_owned(),
+            (2, Value::Bytes(v)) if image.name.is_empty() => image.name = String::from_utf8_lossy(v).into_owned(),
+            (3, Value::Bytes(v)) if digest.is_empty() => digest = String::from_utf8_lossy(v).into_owned(),
+            (4, Value::Varint(v)) => image.size = v,
+            (8, Value::Varint(v)) => image.pinned = v != 0,
+            _ => {}
+        }
+    }
+    if image.name.is_empty() {
+        image.name = if digest.is_empty() { image.id.clone() } else { digest };
+    }
+    Ok(image)
+}
+
+/// Value of a google.protobuf.UInt64Value; 0 if unset
+fn wrapped(buf: &[u8]) -> anyhow::Result<u64> {
+    for field in Fields(buf) {
+        if let (1, Value::Varint(v)) = field? {
+            return Ok(v);
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::statfile::StatFile;
+
+const CLASS_DIR: &str = "/sys/class/infiniband";
+
+/// Counter directories of each port: the standard IB port counters, and
+/// the driver's own, which hold the RoCE congestion counters (CNPs sent and
+/// handled, ECN-marked packets) and retransmission counts
+const COUNTER_DIRS: [&str; 2] = ["counters", "hw_counters"];
+
+/// Counter files of one port, kept open
+struct Port {
+    // <device>/<port>, e.g. mlx5_0/1
+    name: String,
+    counters: Vec<(String, StatFile)>,
+}
+
+/// Port counters of the node's InfiniBand and RoCE adapters from sysfs,
+/// for clusters running RDMA. Ports are found once, at startup; every
+/// counter they expose is reported under its file name.
+pub struct InfinibandCounters {
+    ports: Vec<Port>,
+    buf: Vec<u8>,
+}
+
+impl InfinibandCounters {
+    pub fn discover() -> Self {
+        let mut ports = Vec::new();
+        for device in read_dir_names(&host::path(CLASS_DIR)) {
+            let ports_dir = host::path(CLASS_DIR).join(&device).join("ports");
+            for port in read_dir_names(&ports_dir) {
+                let port_dir = ports_dir.join(&port);
+                let counters = COUNTER_DIRS.iter()
+                    .flat_map(|dir| {
+                        let dir = port_dir.join(dir);
+                        read_dir_names(&dir).into_iter().map(move |name| {
+                            let file = StatFile::new(dir.join(&name));
+                            (name, file)
+                        })
+                    })
+                    .collect();
+                ports.push(Port { name: format!("{}/{}", device, port), counters });
+            }
+        }
+        info!("InfiniBand counters: {} ports ({})", ports.len(), ports.iter()
+            .map(|p| format!("{} with {} counters", p.name, p.counters.len()))
+            .collect::<Vec<_>>()
+            .join(", "));
+        Self { ports, buf: Vec::new() }
+    }
+
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        for port in &mut self.ports {
+            let labels = Labels { device: Some(&port.name), ..Default::default() };
+            let mut values = Vec::with_capacity(port.counters.len());
+            for (name, file) in &mut port.counters {
+                let Some(value) = file.read(&mut self.buf).ok().and_then(|v| v.trim().parse::<u64>().ok()) else { continue };
+                sender.add_counter("node_infiniband", &labels, name, value as f64);
+                values.push((name.as_str(), value));
+            }
+            let get = |key: &str| values.iter().find(|(n, _)| *n == key).map_or(0, |(_, v)| *v);
+            // Data counters count 4-byte words
+            info!("METRIC_TYPE=node_infiniband node={} port={} rcv_bytes={} xmit_bytes={} symbol_errors={} link_downed={} rcv_errors={} xmit_discards={} xmit_wait={}",
+                node_name, port.name, get("port_rcv_data") * 4, get("port_xmit_data") * 4, get("symbol_error"),
+                get("link_downed"), get("port_rcv_errors"), get("port_xmit_discards"), get("port_xmit_wait"));
+        }
+    }
+}
+
+/// Sorted entry names of a directory; none if it can't be read
+fn read_dir_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).into_iter().flatten().flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
@@ -0,0 +1,83 @@
+#[cfg(target_os = "linux")]
+use std::ffi::CString;
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Non-blocking inotify watch on a set of directories, used to notice when
+/// entries are created or removed without re-walking the tree every cycle.
+/// Watches are not recursive; callers add every directory they care about.
+#[cfg(target_os = "linux")]
+pub struct DirWatcher {
+    fd: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+const DIR_EVENTS: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_ONLYDIR;
+
+#[cfg(target_os = "linux")]
+impl DirWatcher {
+    /// Returns None when inotify is unavailable (e.g. no free instances),
+    /// in which case callers fall back to periodic rescans.
+    pub fn new() -> Option<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+        Some(Self { fd })
+    }
+
+    pub fn watch(&self, dir: &Path) -> bool {
+        let Ok(c_path) = CString::new(dir.as_os_str().as_bytes()) else {
+            return false;
+        };
+        unsafe { libc::inotify_add_watch(self.fd, c_path.as_ptr(), DIR_EVENTS) >= 0 }
+    }
+
+    /// Drains pending events and reports whether there were any
+    pub fn changed(&self) -> bool {
+        let mut buf = [0u8; 4096];
+        let mut changed = false;
+        loop {
+            let n = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n <= 0 {
+                break;
+            }
+            changed = true;
+        }
+        changed
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for DirWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Other platforms have no inotify; collectors always rescan
+#[cfg(not(target_os = "linux"))]
+pub struct DirWatcher;
+
+#[cfg(not(target_os = "linux"))]
+impl DirWatcher {
+    pub fn new() -> Option<Self> {
+        None
+    }
+
+    pub fn watch(&self, _dir: &Path) -> bool {
+        false
+    }
+
+    pub fn changed(&self) -> bool {
+        false
+    }
+}
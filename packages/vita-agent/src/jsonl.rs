@@ -0,0 +1,141 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::events::EventRecord;
+use crate::metrics_sender::RawMetric;
+use crate::node_events::NodeEvent;
+
+#[derive(Serialize)]
+struct MetricLine<'a> {
+    record: &'static str,
+    node: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    node_labels: &'a BTreeMap<String, String>,
+    #[serde(flatten)]
+    metric: &'a RawMetric,
+}
+
+#[derive(Serialize)]
+struct EventLine<'a> {
+    record: &'static str,
+    node: &'a str,
+    #[serde(flatten)]
+    event: &'a EventRecord,
+}
+
+#[derive(Serialize)]
+struct NodeEventLine<'a> {
+    record: &'static str,
+    node: &'a str,
+    #[serde(flatten)]
+    event: &'a NodeEvent,
+}
+
+enum Output {
+    Stdout(BufWriter<io::Stdout>),
+    File { path: PathBuf, file: BufWriter<File> },
+}
+
+/// Writes metrics and events as one JSON object per line, to stdout or to a
+/// file rotated by size, for clusters that ship them through an existing log
+/// pipeline (Fluent Bit, Vector) instead of a consumer. Every line has a
+/// `record` field, "metric" or "event".
+pub struct JsonLinesSink {
+    out: Output,
+    // Rotation applies to files only
+    max_bytes: u64,
+    keep: usize,
+    written: u64,
+}
+
+impl JsonLinesSink {
+    pub fn stdout() -> Self {
+        Self { out: Output::Stdout(BufWriter::new(io::stdout())), max_bytes: 0, keep: 0, written: 0 }
+    }
+
+    /// Appends to `path`, which is renamed to `path.1` once it reaches
+    /// `max_bytes`; `keep` rotated files are kept
+    pub fn file(path: PathBuf, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self { out: Output::File { path, file: BufWriter::new(file) }, max_bytes, keep, written })
+    }
+
+    pub fn write_metrics(&mut self, node: &str, node_labels: &BTreeMap<String, String>, metrics: &[RawMetric]) -> Result<()> {
+        for metric in metrics {
+            self.write(&MetricLine { record: "metric", node, node_labels, metric })?;
+        }
+        self.flush()
+    }
+
+    pub fn write_events(&mut self, node: &str, events: &[EventRecord]) -> Result<()> {
+        for event in events {
+            self.write(&EventLine { record: "event", node, event })?;
+        }
+        self.flush()
+    }
+
+    pub fn write_node_events(&mut self, node: &str, events: &[NodeEvent]) -> Result<()> {
+        for event in events {
+            self.write(&NodeEventLine { record: "node_event", node, event })?;
+        }
+        self.flush()
+    }
+
+    fn write<T: Serialize>(&mut self, line: &T) -> Result<()> {
+        let mut buf = serde_json::to_vec(line)?;
+        buf.push(b'\n');
+
+        if self.max_bytes > 0 && self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        match &mut self.out {
+            Output::Stdout(out) => out.write_all(&buf)?,
+            Output::File { file, .. } => file.write_all(&buf)?,
+        }
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.out {
+            Output::Stdout(out) => out.flush()?,
+            Output::File { file, .. } => file.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Shifts path.N to path.N+1, dropping the oldest, and starts a new file
+    fn rotate(&mut self) -> io::Result<()> {
+        let Output::File { path, file } = &mut self.out else { return Ok(()) };
+        file.flush()?;
+
+        let rotated = |n: usize| {
+            let mut name = path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        if self.keep == 0 {
+            fs::remove_file(&*path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                let from = rotated(n);
+                if from.exists() {
+                    fs::rename(&from, rotated(n + 1))?;
+                }
+            }
+            fs::rename(&*path, rotated(1))?;
+        }
+
+        *file = BufWriter::new(OpenOptions::new().create(true).append(true).open(&*path)?);
+        self.written = 0;
+        Ok(())
+    }
+}
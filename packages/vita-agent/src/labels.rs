@@ -0,0 +1,26 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Interning pool for label strings (node name, pod slices, volume names, keys).
+/// The same few thousand strings are seen every cycle, so metrics share one
+/// `Arc<str>` per distinct value instead of allocating a fresh `String` each time.
+#[derive(Default)]
+pub struct LabelPool {
+    labels: HashSet<Arc<str>>,
+}
+
+impl LabelPool {
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(existing) = self.labels.get(s) {
+            return existing.clone();
+        }
+        let label: Arc<str> = Arc::from(s);
+        self.labels.insert(label.clone());
+        label
+    }
+
+    /// Drop labels no metric refers to anymore (e.g. deleted pods)
+    pub fn prune(&mut self) {
+        self.labels.retain(|l| Arc::strong_count(l) > 1);
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use kube::api::{Api, ObjectMeta, PostParams};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+const LEASE_NAME: &str = "vita-agent-cluster-collectors";
+const LEASE_DURATION: Duration = Duration::from_secs(15);
+const RENEW_EVERY: Duration = Duration::from_secs(5);
+
+/// Lease-based election among agents, so cluster-scoped collectors run on
+/// exactly one node. A held lease is renewed every few seconds; another
+/// agent takes over once it has gone unrenewed for the lease duration.
+struct Elector {
+    api: Api<Lease>,
+    identity: String,
+    // Last lease version seen and when, on our own clock: expiry is judged
+    // locally so clock skew between nodes cannot cause two leaders
+    observed: Option<(String, Instant)>,
+    last_renewed: Option<Instant>,
+}
+
+/// Starts campaigning in the background; the receiver is true while this
+/// agent holds the lease
+pub fn spawn(client: kube::Client, namespace: &str, identity: String) -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+    let mut elector = Elector {
+        api: Api::namespaced(client, namespace),
+        identity,
+        observed: None,
+        last_renewed: None,
+    };
+
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(RENEW_EVERY);
+        loop {
+            tick.tick().await;
+            let leading = match elector.try_acquire().await {
+                Ok(leading) => leading,
+                Err(e) => {
+                    warn!("Leader election failed: {}", e);
+                    // Keep leading only while the last renewal is still valid
+                    elector.last_renewed.is_some_and(|t| t.elapsed() < LEASE_DURATION)
+                }
+            };
+            if leading != *tx.borrow() {
+                info!("{} cluster collector leadership", if leading { "Acquired" } else { "Lost" });
+                tx.send_replace(leading);
+            }
+            if tx.is_closed() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+impl Elector {
+    async fn try_acquire(&mut self) -> Result<bool> {
+        let now = MicroTime(chrono::Utc::now());
+
+        let Some(mut lease) = self.api.get_opt(LEASE_NAME).await? else {
+            let lease = Lease {
+                metadata: ObjectMeta { name: Some(LEASE_NAME.to_string()), ..Default::default() },
+                spec: Some(LeaseSpec {
+                    holder_identity: Some(self.identity.clone()),
+                    lease_duration_seconds: Some(LEASE_DURATION.as_secs() as i32),
+                    acquire_time: Some(now.clone()),
+                    renew_time: Some(now),
+                    lease_transitions: Some(0),
+                    ..Default::default()
+                }),
+            };
+            return self.write(self.api.create(&PostParams::default(), &lease).await);
+        };
+
+        let version = lease.metadata.resource_version.clone().unwrap_or_default();
+        let changed = self.observed.as_ref().is_none_or(|(v, _)| *v != version);
+        if changed {
+            self.observed = Some((version, Instant::now()));
+        }
+
+        let spec = lease.spec.get_or_insert_with(Default::default);
+        let ours = spec.holder_identity.as_deref() == Some(self.identity.as_str());
+        let duration = spec.lease_duration_seconds
+            .map_or(LEASE_DURATION, |s| Duration::from_secs(s.max(1) as u64));
+        let expired = spec.holder_identity.is_none()
+            || self.observed.as_ref().is_some_and(|(_, seen)| seen.elapsed() >= duration);
+
+        if !ours && !expired {
+            self.last_renewed = None;
+            return Ok(false);
+        }
+        if !ours {
+            spec.holder_identity = Some(self.identity.clone());
+            spec.acquire_time = Some(now.clone());
+            spec.lease_transitions = Some(spec.lease_transitions.unwrap_or(0) + 1);
+        }
+        spec.renew_time = Some(now);
+        spec.lease_duration_seconds = Some(LEASE_DURATION.as_secs() as i32);
+
+        // The resourceVersion makes this a compare-and-swap against other agents
+        self.write(self.api.replace(LEASE_NAME, &PostParams::default(), &lease).await)
+    }
+
+    fn write(&mut self, result: kube::Result<Lease>) -> Result<bool> {
+        match result {
+            Ok(lease) => {
+                let version = lease.metadata.resource_version.unwrap_or_default();
+                self.observed = Some((version, Instant::now()));
+                self.last_renewed = Some(Instant::now());
+                Ok(true)
+            }
+            // Another agent wrote the lease first
+            Err(kube::Error::Api(e)) if e.code == 409 => {
+                self.last_renewed = None;
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::time::Instant;
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, Networks, RefreshKind, System};
+use tracing::info;
+
+use crate::errors::Result;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// Default consumer for `--local-dev`, one started with `go run` next to it
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:8080/api/v1/ingest";
+
+/// Node metrics from a portable backend, for `--local-dev` on machines
+/// without /proc or cgroups (macOS laptops). Reported under the same types
+/// and keys as on a node so the consumer and dashboards work unchanged;
+/// there are no container, volume or disk I/O metrics.
+pub struct LocalCollector {
+    system: System,
+    networks: Networks,
+    // CPU ticks accumulated from usage samples, see collect_cpu_metrics
+    last_sample: Instant,
+    busy_ticks: f64,
+    idle_ticks: f64,
+    exclude_interfaces: Vec<String>,
+}
+
+impl LocalCollector {
+    pub fn new() -> Self {
+        let refresh = RefreshKind::nothing()
+            .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
+            .with_memory(MemoryRefreshKind::everything());
+        Self {
+            system: System::new_with_specifics(refresh),
+            networks: Networks::new_with_refreshed_list(),
+            last_sample: Instant::now(),
+            busy_ticks: 0.0,
+            idle_ticks: 0.0,
+            exclude_interfaces: Vec::new(),
+        }
+    }
+
+    /// Host name, standing in for NODE_NAME
+    pub fn host_name() -> String {
+        System::host_name().unwrap_or_else(|| "localhost".to_string())
+    }
+
+    /// Devices are ignored: no disk I/O counters here
+    pub fn set_filters(&mut self, _devices: &[String], interfaces: &[String]) {
+        self.exclude_interfaces = interfaces.to_vec();
+    }
+
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        self.collect_cpu_metrics(node_name, sender);
+        self.collect_memory_metrics(node_name, sender);
+        self.collect_network_metrics(node_name, sender);
+        Ok(())
+    }
+
+    fn collect_cpu_metrics(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        let labels = Labels::default();
+        let boot_time = System::boot_time();
+        info!("METRIC_TYPE=node_boot node={} boot_time={}", node_name, boot_time);
+        sender.add("node_boot", &labels, "boot_time", boot_time as f64);
+
+        // Only usage since the last refresh is portable, not cumulative
+        // times; it is turned into the 1/100 s ticks /proc/stat counts,
+        // all of the busy share as user time
+        self.system.refresh_cpu_usage();
+        let now = Instant::now();
+        let cpus = self.system.cpus().len().max(1) as f64;
+        let ticks = now.duration_since(self.last_sample).as_secs_f64() * 100.0 * cpus;
+        let busy = (self.system.global_cpu_usage() as f64 / 100.0).clamp(0.0, 1.0);
+        self.last_sample = now;
+        self.busy_ticks += ticks * busy;
+        self.idle_ticks += ticks * (1.0 - busy);
+
+        let (user, idle) = (self.busy_ticks as u64, self.idle_ticks as u64);
+        info!("METRIC_TYPE=node_cpu node={} user={} sys=0 idle={}", node_name, user, idle);
+        sender.add_counter("node_cpu", &labels, "user", user as f64);
+        sender.add_counter("node_cpu", &labels, "sys", 0.0);
+        sender.add_counter("node_cpu", &labels, "idle", idle as f64);
+    }
+
+    fn collect_memory_metrics(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        self.system.refresh_memory();
+        let mb = |bytes: u64| bytes / 1024 / 1024;
+        let total = mb(self.system.total_memory());
+        let free = mb(self.system.free_memory());
+        let available = mb(self.system.available_memory());
+        let used = total.saturating_sub(free);
+        info!("METRIC_TYPE=node_mem node={} total_mb={} used_mb={} free_mb={} avail_mb={}",
+            node_name, total, used, free, available);
+
+        let labels = Labels::default();
+        sender.add("node_mem", &labels, "total_mb", total as f64);
+        sender.add("node_mem", &labels, "used_mb", used as f64);
+        sender.add("node_mem", &labels, "free_mb", free as f64);
+        sender.add("node_mem", &labels, "avail_mb", available as f64);
+
+        let swap_total = mb(self.system.total_swap());
+        if swap_total > 0 {
+            let swap_used = mb(self.system.used_swap());
+            info!("METRIC_TYPE=node_swap node={} total_mb={} used_mb={}", node_name, swap_total, swap_used);
+
+            sender.add("node_swap", &labels, "total_mb", swap_total as f64);
+            sender.add("node_swap", &labels, "used_mb", swap_used as f64);
+        }
+    }
+
+    fn collect_network_metrics(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        self.networks.refresh(true);
+        for (name, net) in &self.networks {
+            // lo on Linux, lo0 on macOS
+            if name.starts_with("lo") || self.exclude_interfaces.iter().any(|p| name.starts_with(p.as_str())) {
+                continue;
+            }
+            let (rx_bytes, tx_bytes) = (net.total_received(), net.total_transmitted());
+            if rx_bytes == 0 && tx_bytes == 0 {
+                continue;
+            }
+            let (rx_pkts, tx_pkts) = (net.total_packets_received(), net.total_packets_transmitted());
+            let (rx_errs, tx_errs) = (net.total_errors_on_received(), net.total_errors_on_transmitted());
+            info!("METRIC_TYPE=node_net node={} interface={} rx_bytes={} tx_bytes={} rx_pkts={} tx_pkts={} rx_errs={} tx_errs={}",
+                node_name, name, rx_bytes, tx_bytes, rx_pkts, tx_pkts, rx_errs, tx_errs);
+
+            let labels = Labels { device: Some(name), ..Default::default() };
+            sender.add_counter("node_net", &labels, "rx_bytes", rx_bytes as f64);
+            sender.add_counter("node_net", &labels, "tx_bytes", tx_bytes as f64);
+            sender.add_counter("node_net", &labels, "rx_pkts", rx_pkts as f64);
+            sender.add_counter("node_net", &labels, "tx_pkts", tx_pkts as f64);
+            sender.add_counter("node_net", &labels, "rx_errs", rx_errs as f64);
+            sender.add_counter("node_net", &labels, "tx_errs", tx_errs as f64);
+        }
+    }
+}
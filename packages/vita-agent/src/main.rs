@@ -1,70 +1,838 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use tracing::{info, warn};
 use std::env;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-mod system_metrics;
+mod alerts;
+mod bench;
+mod blackbox;
+mod burst;
+mod capabilities;
+mod certs;
+mod cluster_metrics;
+mod compact;
+mod condition;
+mod config;
 mod container_metrics;
-mod pvc_metrics;
+mod cpu_manager;
+#[cfg(windows)]
+mod cri;
+mod deep_usage;
+mod derived;
+mod devices;
+mod discovery;
+mod dns;
+#[cfg(unix)]
+mod docker;
+mod errors;
+mod events;
+mod grpc;
+mod handshake;
+mod heartbeat;
+mod history;
+mod host;
+#[cfg(not(windows))]
+mod host_cgroups;
+#[cfg(unix)]
+mod image_fs;
+mod infiniband;
+mod inotify;
+mod jsonl;
+mod labels;
+mod leader;
+mod local_dev;
 mod metrics_sender;
+#[cfg(not(windows))]
+mod mounts;
+mod nats;
+mod node_events;
+#[cfg(unix)]
+mod node_probes;
+mod node_status;
+mod otel;
+mod parsers;
+#[cfg(feature = "plugins")]
+mod plugins;
+#[cfg(unix)]
+mod pod_resources;
+mod pod_roles;
+mod project_quota;
+mod pvc_metrics;
+#[cfg(target_os = "linux")]
+mod qdisc;
+mod schedule;
+mod secret;
+mod signing;
+mod simulate;
+mod smart;
+mod snapshot;
+mod statfile;
+mod sysctl;
+mod system_metrics;
+#[cfg(unix)]
+mod systemd;
+#[cfg(target_os = "linux")]
+mod thin_pool;
+mod tiers;
+#[cfg(unix)]
+mod unix_http;
+mod volume_claims;
+mod watchdog;
+#[cfg(windows)]
+mod windows_metrics;
+mod workloads;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt()
+    let args: Vec<String> = env::args().collect();
+    let bench_mode = args.get(1).map(String::as_str) == Some("bench");
+    let simulate_mode = args.get(1).map(String::as_str) == Some("simulate");
+    // `vita-agent --local-dev` runs the pipeline on a workstation: portable
+    // host metrics, no Kubernetes, a consumer on localhost
+    let local_dev = args.iter().any(|a| a == "--local-dev");
+
+    // Initialize logging; per-metric lines would drown out bench and simulate results
+    let default_filter = if bench_mode || simulate_mode { "warn" } else { "info" };
+    // With metrics on stdout, logs move to stderr so the pipeline sees only records
+    let sink = env::var("SINK").unwrap_or_default();
+    let log_writer = if sink == "stdout" {
+        tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stderr)
+    } else {
+        tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stdout)
+    };
+    let logs = tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"))
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default_filter))
         )
-        .with_target(false)
-        .compact()
-        .init();
+        .with_writer(log_writer);
+    // Under systemd, journald timestamps lines and wants their priority
+    #[cfg(unix)]
+    if systemd::logs_to_journal(if sink == "stdout" { 2 } else { 1 }) {
+        logs.event_format(systemd::JournalFormat).init();
+    } else {
+        logs.with_target(false).compact().init();
+    }
+    #[cfg(not(unix))]
+    logs.with_target(false).compact().init();
+
+    // `vita-agent replay <snapshot>` runs the collectors against a recorded host
+    let replay = match args.get(1).map(String::as_str) {
+        Some("replay") => {
+            let path = args.get(2).context("usage: vita-agent replay <snapshot>")?;
+            let snapshot = snapshot::open(path)?;
+            info!("Replaying {} from {}", path, snapshot.root.display());
+            host::set_root(snapshot.root.clone());
+            Some(snapshot)
+        }
+        _ => None,
+    };
+    // Neither needs nor has a cluster to talk to
+    let offline = local_dev || replay.is_some();
+    // MODE=host: a Docker or bare host rather than a node, with service and
+    // container cgroups and mounted filesystems in place of pods and volumes
+    let host_mode = !local_dev && env::var("MODE").is_ok_and(|m| m == "host");
+    if host_mode && cfg!(windows) {
+        warn!("⚠️  MODE=host needs cgroups, collecting node metrics only");
+    }
+    let host_mode = host_mode && cfg!(not(windows));
+    // Every pod gets KUBERNETES_SERVICE_HOST; without it or a KUBECONFIG we
+    // run on a plain host (under systemd, say), where kubelet and CRI
+    // collectors find nothing
+    let standalone = !offline && (host_mode || env::var_os("KUBERNETES_SERVICE_HOST").is_none() && env::var_os("KUBECONFIG").is_none());
 
     // Get node name from environment (set by Kubernetes)
-    let node_name = env::var("NODE_NAME").unwrap_or_else(|_| "unknown".to_string());
+    let node_name = env::var("NODE_NAME").unwrap_or_else(|_| {
+        match replay.and_then(|s| s.node) {
+            Some(node) => node,
+            None if local_dev || standalone => local_dev::LocalCollector::host_name(),
+            None => "unknown".to_string(),
+        }
+    });
+
+    // `vita-agent record [file]` snapshots this host for replay and exits
+    if args.get(1).map(String::as_str) == Some("record") {
+        return snapshot::record(args.get(2).map(String::as_str), &node_name);
+    }
+
+    // `vita-agent bench [seconds]` measures collection cost and exits
+    if bench_mode {
+        let secs = args.get(2).and_then(|v| v.parse().ok()).unwrap_or(10);
+        return bench::run(&node_name, secs);
+    }
 
-    // Get consumer endpoint
-    let consumer_endpoint = env::var("CONSUMER_ENDPOINT")
-        .unwrap_or_else(|_| "http://vita-consumer:8080/api/v1/ingest".to_string());
+    // Endpoint, interval and wire mode from the environment
+    let mut config = config::AgentConfig::from_env();
+    if local_dev && env::var("CONSUMER_ENDPOINT").is_err() {
+        config.endpoint = local_dev::DEFAULT_ENDPOINT.to_string();
+    }
 
-    // Get collection interval (default: 1 second for high-frequency monitoring)
-    let interval_secs = env::var("COLLECTION_INTERVAL")
-        .ok()
-        .and_then(|v| v.parse::<u64>().ok())
-        .unwrap_or(1);
+    // `vita-agent simulate [options]` sends synthetic nodes to the consumer
+    if simulate_mode {
+        return simulate::run(&args[2..], &config).await;
+    }
 
     info!("🚀 VitaAgent starting | node={} interval={}s endpoint={}", 
-          node_name, interval_secs, consumer_endpoint);
+          node_name, config.interval_secs, config.endpoint);
+
+    // Cgroup stat files are kept open between cycles
+    if let Some(limit) = statfile::raise_nofile_limit() {
+        info!("Open file limit: {}", limit);
+    }
 
     // Initialize metrics sender
-    let mut sender = metrics_sender::MetricsSender::new(consumer_endpoint, node_name.clone());
+    let mut sender = metrics_sender::MetricsSender::new(config.endpoint.clone(), node_name.clone());
+
+    // Compact wire mode sends each series' labels once per session
+    if config.compact_wire {
+        info!("Compact wire mode enabled");
+    }
+    sender.set_compact(config.compact_wire);
+    sender.set_tiers(config.tiers.clone());
+
+    // Bounded timeouts keep a hanging consumer from stalling collection
+    let defaults = metrics_sender::HttpSettings::default();
+    sender.set_http(metrics_sender::HttpSettings {
+        connect_timeout: env_secs("CONSUMER_CONNECT_TIMEOUT", defaults.connect_timeout.as_secs()),
+        request_timeout: env_secs("CONSUMER_TIMEOUT", defaults.request_timeout.as_secs()),
+        pool_max_idle: env::var("CONSUMER_POOL_IDLE").ok().and_then(|v| v.parse().ok()).unwrap_or(defaults.pool_max_idle),
+        pool_idle_timeout: env_secs("CONSUMER_POOL_IDLE_TIMEOUT", defaults.pool_idle_timeout.as_secs()),
+        keepalive: env_secs("CONSUMER_KEEPALIVE", defaults.keepalive.as_secs()),
+        dns_refresh: env_secs("CONSUMER_DNS_REFRESH", defaults.dns_refresh.as_secs()),
+        version: match env::var("CONSUMER_HTTP_VERSION").as_deref() {
+            Ok("1") => metrics_sender::HttpVersion::Http1,
+            Ok("2") => metrics_sender::HttpVersion::Http2,
+            _ => metrics_sender::HttpVersion::Auto,
+        },
+        proxy: env::var("CONSUMER_PROXY").ok().filter(|p| !p.is_empty()),
+        unix_socket: env::var("CONSUMER_SOCKET").ok().filter(|p| !p.is_empty()).map(Into::into),
+    });
+    if let Ok(socket) = env::var("CONSUMER_SOCKET") {
+        info!("Sending to the consumer over unix socket {}", socket);
+    }
+
+    // A log pipeline can pick metrics up instead of a consumer
+    match sink.as_str() {
+        "stdout" => {
+            info!("Writing metrics as JSON lines to stdout");
+            sender.set_jsonl(Some(jsonl::JsonLinesSink::stdout()));
+        }
+        "file" => {
+            let path = env::var("SINK_FILE").unwrap_or_else(|_| "/var/log/vita-agent/metrics.jsonl".to_string());
+            let max_mb = env::var("SINK_FILE_MAX_MB").ok().and_then(|v| v.parse().ok()).unwrap_or(100u64);
+            let keep = env::var("SINK_FILE_KEEP").ok().and_then(|v| v.parse().ok()).unwrap_or(5);
+            match jsonl::JsonLinesSink::file(path.clone().into(), max_mb * 1024 * 1024, keep) {
+                Ok(file) => {
+                    info!("Writing metrics as JSON lines to {} | max={}MB keep={}", path, max_mb, keep);
+                    sender.set_jsonl(Some(file));
+                }
+                Err(e) => warn!("⚠️  Cannot write {}, sending to the consumer instead: {}", path, e),
+            }
+        }
+        "nats" => {
+            let url = env::var("NATS_URL").unwrap_or_else(|_| "nats://nats:4222".to_string());
+            let prefix = env::var("NATS_SUBJECT_PREFIX").unwrap_or_else(|_| "vitakube".to_string());
+            let jetstream = env::var("NATS_JETSTREAM").map_or(true, |v| v == "true" || v == "1");
+            match nats::NatsSink::new(&url, prefix.clone(), jetstream) {
+                Ok(nats) => {
+                    info!("Publishing metrics to NATS | subjects={}.* jetstream={}", prefix, jetstream);
+                    sender.set_nats(Some(nats));
+                }
+                Err(e) => warn!("⚠️  Invalid NATS sink, sending to the consumer instead: {}", e),
+            }
+        }
+        _ => {}
+    }
+
+    // Credentials come from NAME_FILE (re-read on rotation) or NAME
+    sender.set_token(secret::Secret::from_env("INGEST_TOKEN"));
+    sender.set_ca(secret::Secret::from_env("CONSUMER_CA"));
+    sender.set_signing_key(secret::Secret::from_env("INGEST_HMAC_KEY"));
+
+    // Recent history, queryable on the node while the consumer is away
+    let history_minutes: u64 = env::var("HISTORY_MINUTES").ok().and_then(|v| v.parse().ok()).unwrap_or(0);
+    if history_minutes > 0 {
+        let max_series = env::var("HISTORY_MAX_SERIES").ok().and_then(|v| v.parse().ok()).unwrap_or(50_000);
+        let window = Duration::from_secs(history_minutes * 60);
+        let history = Arc::new(Mutex::new(history::History::new(window, max_series)));
+        let addr = env::var("LOCAL_QUERY_ADDR").unwrap_or_else(|_| "127.0.0.1:9102".to_string());
+        match addr.parse() {
+            Ok(addr) => {
+                tokio::spawn(history::serve(addr, history.clone()));
+            }
+            Err(e) => warn!("⚠️  Invalid LOCAL_QUERY_ADDR {}, history isn't served: {}", addr, e),
+        }
+        sender.set_history(Some(history));
+    }
+
+    // Probe once which sources are readable and skip the rest
+    let caps = if local_dev {
+        info!("Local dev mode: portable host metrics only");
+        capabilities::Capabilities::none()
+    } else {
+        capabilities::Capabilities::detect(host_mode)
+    };
+
+    #[cfg(not(windows))]
+    let mut system = system_metrics::SystemCollector::new(&caps);
+    #[cfg(not(windows))]
+    let mut containers = container_metrics::ContainerCollector::new();
+    // Windows nodes have neither /proc nor cgroups
+    #[cfg(not(windows))]
+    system.set_infiniband(env_flag("INFINIBAND_COUNTERS"));
+    #[cfg(not(windows))]
+    if !local_dev {
+        system.set_sysctls(sysctl::SysctlGauges::from_env());
+        system.set_smart(smart::SmartHealth::from_env());
+        system.set_certs(certs::CertExpiry::from_env());
+    }
+    #[cfg(windows)]
+    let mut system = windows_metrics::WindowsCollector::new();
+    #[cfg(windows)]
+    let mut containers = windows_metrics::WindowsCollector::new();
+    let mut volumes = pvc_metrics::VolumeCollector::new();
+    // Caps statvfs time per cycle on nodes with thousands of volumes
+    volumes.set_scan_budget(env::var("VOLUME_SCAN_BUDGET_MS").ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&ms| ms > 0)
+        .map(Duration::from_millis));
+    // Opt-in recursive size scans, for volumes statvfs can't tell apart
+    volumes.set_deep_usage(deep_usage::DeepUsage::from_env());
+    // Volumes to check for writability, beyond the read-only mount flag
+    volumes.set_write_probes(env::var("VOLUME_WRITE_PROBE").unwrap_or_default()
+        .split(',').map(str::trim).filter(|v| !v.is_empty()).map(String::from).collect());
+    let mut devices = devices::DeviceCollector::new();
+    // Site-specific collectors from WASM modules in PLUGIN_DIR
+    #[cfg(feature = "plugins")]
+    let mut plugins = plugins::Plugins::from_env(env_secs("PLUGIN_INTERVAL", 10));
+    #[cfg(not(feature = "plugins"))]
+    if env::var("PLUGIN_DIR").is_ok_and(|d| !d.is_empty()) {
+        warn!("⚠️  PLUGIN_DIR is set but this agent was built without the plugins feature");
+    }
+    let mut local = local_dev.then(local_dev::LocalCollector::new);
+    #[cfg(not(windows))]
+    let mut host = host_mode.then(|| (host_cgroups::HostCgroupCollector::new(), mounts::MountCollector::new()));
+    // The kubelet, runtime and other services next to the pods, and what
+    // they take of the node against the pods' share
+    #[cfg(not(windows))]
+    let mut system_slice = (!host_mode && caps.cgroups && env::var("SYSTEM_SLICE_METRICS").map_or(true, |v| v == "true" || v == "1"))
+        .then(host_cgroups::HostCgroupCollector::system_slice);
+
+    // Optional self resource budget
+    let mut watchdog = watchdog::Watchdog::from_env();
+    if watchdog.is_some() {
+        info!("Resource budget watchdog enabled");
+    }
+
+    if host_mode {
+        info!("Host mode: service and container cgroups and mounts instead of pods and volumes");
+    } else if standalone {
+        info!("Not running in Kubernetes, Kubernetes collectors disabled; MODE=host collects cgroups and mounts instead");
+    }
+
+    // Kubernetes API access, for everything not readable from the host
+    let kube_client = if offline || standalone {
+        None
+    } else {
+        match kube::Client::try_default().await {
+            Ok(client) => Some(client),
+            Err(e) => {
+                warn!("⚠️  No Kubernetes client, API-based collectors disabled: {}", e);
+                None
+            }
+        }
+    };
+
+    // Conditions and allocatable resources of this node
+    let mut node_status = kube_client.clone().map(|client| {
+        let interval = env_secs("NODE_STATUS_INTERVAL", 30);
+        node_status::NodeStatusCollector::new(client, node_name.clone(), interval)
+    });
+
+    // Which containers are init, sidecar, app or pause
+    let mut roles = kube_client.clone().map(|client| {
+        let interval = env_secs("POD_ROLES_INTERVAL", 30);
+        pod_roles::PodRoles::new(client, node_name.clone(), interval)
+    });
+
+    // Claims behind the volumes mounted here, to check their size against
+    let mut claims = kube_client.clone().map(|client| {
+        let interval = env_secs("VOLUME_CLAIMS_INTERVAL", 60);
+        volume_claims::VolumeClaims::new(client, node_name.clone(), interval)
+    });
+
+    // CPU, device and memory assignments from the kubelet, by pod UID
+    #[cfg(unix)]
+    let mut pod_resources = roles.as_ref()
+        .and_then(|_| pod_resources::PodResourcesCollector::new(env_secs("POD_RESOURCES_INTERVAL", 30)));
+
+    // Image storage from the CRI runtime
+    #[cfg(unix)]
+    let mut image_fs = (!offline && !standalone).then(|| image_fs::ImageFsCollector::new(env_secs("IMAGE_FS_INTERVAL", 60))).flatten();
+
+    // Named containers from the Docker Engine API, where no CRI runtime
+    // answers for them; on a host, Docker's containerd socket doesn't count
+    #[cfg(unix)]
+    let mut docker = (!offline && (host_mode || image_fs::runtime_socket().is_none()))
+        .then(|| docker::DockerCollector::new(env_secs("DOCKER_INTERVAL", 10))).flatten();
+
+    // Kubelet and runtime liveness, straight from their endpoints
+    #[cfg(unix)]
+    let mut probes = (!offline && !standalone).then(|| node_probes::NodeProbes::new(env_secs("NODE_PROBE_INTERVAL", 15))).flatten();
+
+    // Cluster-scoped collectors run on whichever agent holds the lease
+    let mut cluster = kube_client.clone().filter(|_| env_flag("LEADER_ELECTION")).map(|client| {
+        let namespace = env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_string());
+        let interval = env_secs("CLUSTER_INTERVAL", 30);
+        info!("Leader election enabled | namespace={} cluster_interval={:?}", namespace, interval);
+        let leading = leader::spawn(client.clone(), &namespace, node_name.clone());
+        let mut collector = cluster_metrics::ClusterCollector::new(client.clone(), interval);
+        collector.set_workloads(env::var("WORKLOAD_METRICS").map_or(true, |v| v == "true" || v == "1"));
+        (
+            leading.clone(),
+            collector,
+            events::EventCollector::spawn(client, leading),
+        )
+    });
+
+    // The consumer's pods, found through its Service, replace the endpoint
+    let mut consumers = (!offline).then(discovery::ConsumerService::from_env).flatten()
+        .map(|service| discovery::watch(kube_client.clone(), service, config.endpoint.clone(), node_name.clone()));
+
+    // VitaAgentConfig resources override the environment while running
+    let mut updates = kube_client.filter(|_| env_flag("CONFIG_CRD"))
+        .map(|client| config::watch(client, config.clone(), node_name.clone()));
+
+    // Reachability of configured targets from this node
+    let blackbox = (!offline).then(|| blackbox::Blackbox::spawn(dns::probe_targets(), config.probes.clone(), env_secs("BLACKBOX_INTERVAL", 30)));
+
+    // Metrics computed from each cycle's values
+    let mut derived = derived::Derived::new(&config.derived);
+
+    // Alerts raised on the node, for when the consumer can't be reached
+    let mut alerts = alerts::Alerts::new(&config.alerts);
+
+    // Fast sampling of whatever a burst rule catches, between cycles
+    let mut bursts = burst::Bursts::new(&config.burst);
+
+    let mut heartbeat = heartbeat::Heartbeat::new(env_secs("HEARTBEAT_INTERVAL", 15));
+
+    // Failure policy per collector: disabled, backing off or running
+    let mut health = Health::default();
+
+    // Sampled cycles are traced when an OTLP endpoint is configured
+    let tracer = otel::Tracer::from_env(&node_name);
+    if let Some(t) = &tracer {
+        info!("Exporting traces to {}", t.endpoint());
+    }
+
+    // Readiness and watchdog pings for a Type=notify systemd unit
+    #[cfg(unix)]
+    let mut notifier = systemd::Notifier::from_env();
+
+    // Collectors on intervals of their own share the loop's cycles
+    let mut schedule = schedule::Schedule::new();
 
     // Main collection loop
     loop {
+        if let Some(rx) = &mut updates {
+            // Compared by value: the sleep below may already have consumed the change
+            if *rx.borrow() != config {
+                let next = rx.borrow().clone();
+                info!("Config updated: {:?}", next);
+                if next.endpoint != config.endpoint && consumers.is_none() {
+                    sender.set_endpoint(next.endpoint.clone());
+                }
+                if next.compact_wire != config.compact_wire {
+                    sender.set_compact(next.compact_wire);
+                }
+                sender.set_tiers(next.tiers.clone());
+                system.set_filters(&next.exclude_devices, &next.exclude_interfaces);
+                if let Some(local) = &mut local {
+                    local.set_filters(&next.exclude_devices, &next.exclude_interfaces);
+                }
+                if let Some(blackbox) = &blackbox {
+                    blackbox.set_targets(next.probes.clone());
+                }
+                if next.derived != config.derived {
+                    derived.set_metrics(&next.derived);
+                }
+                if next.alerts != config.alerts {
+                    alerts.set_rules(&next.alerts);
+                }
+                if next.burst != config.burst {
+                    bursts.set_rules(&next.burst);
+                }
+                config = next;
+            }
+        }
+        if let Some(rx) = &mut consumers {
+            if rx.has_changed().unwrap_or(false) {
+                sender.set_endpoint(rx.borrow_and_update().clone());
+            }
+        }
+
+        let intervals = config.collector_intervals();
+        let tick = schedule.plan(&intervals);
+        let [system_due, containers_due, volumes_due, devices_due] = intervals.map(|i| schedule.due(i));
+
+        let collect_system = config.system && !health.system.disabled();
+        let collect_containers = config.containers && !local_dev && (caps.cgroups || cfg!(windows))
+            && !health.containers.disabled()
+            && watchdog.as_ref().is_none_or(|w| w.collect_containers());
+        let collect_volumes = config.volumes && (caps.kubelet_pods || host_mode) && !health.volumes.disabled()
+            && watchdog.as_ref().is_none_or(|w| w.collect_volumes());
+        let collect_devices = config.containers && !local_dev && !standalone && !health.devices.disabled();
+        let leading = cluster.as_ref().is_some_and(|(leading, _, _)| *leading.borrow());
+
+        if let Some(w) = &watchdog {
+            sender.set_pressure(w.level());
+        }
+        sender.begin_cycle();
+        caps.report(&mut sender);
+        health.report(&mut sender);
+
+        // Child spans of the cycle, when this one is traced
+        let mut cycle = tracer.as_ref().and_then(|t| t.start_trace("collection_cycle"));
+        let span = |name| Some(tracer.as_ref()?.start_child(cycle.as_ref()?.context(), name));
+        let end = |span, result: Result<(), String>| end_span(tracer.as_ref(), span, result);
+
         // Collect system-wide metrics from /proc and /sys
-        match system_metrics::collect_system_metrics(&node_name, &mut sender) {
-            Ok(_) => {},
-            Err(e) => warn!("⚠️  System metrics failed: {}", e),
+        if collect_system && system_due && health.system.ready() {
+            let s = span("collect_system");
+            let result = match &mut local {
+                Some(local) => local.collect(&node_name, &mut sender),
+                None => system.collect(&node_name, &mut sender),
+            };
+            health.system.observe(&result);
+            end(s, result.map_err(|e| e.to_string()));
         }
 
         // Collect container metrics from cgroups
-        match container_metrics::collect_container_metrics(&node_name, &mut sender) {
-            Ok(_) => {},
-            Err(e) => warn!("⚠️  Container metrics failed: {}", e),
+        if collect_containers && containers_due && health.containers.ready() && !host_mode {
+            if let Some(roles) = roles.as_mut().filter(|r| r.due() && health.pod_roles.ready()) {
+                let result = roles.refresh().await;
+                health.pod_roles.observe(&result);
+            }
+            let roles = roles.as_ref().filter(|_| !health.pod_roles.disabled());
+
+            let s = span("collect_containers");
+            #[cfg(not(windows))]
+            let result = containers.collect(&node_name, roles, &mut sender).map(|_| ());
+            #[cfg(windows)]
+            let result = containers.collect_containers(&node_name, roles, &mut sender).await;
+            health.containers.observe(&result);
+            end(s, result.map_err(|e| e.to_string()));
         }
 
         // Collect PVC metrics
-        match pvc_metrics::collect_pvc_metrics(&node_name, &mut sender) {
-            Ok(_) => {},
-            Err(e) => warn!("⚠️  PVC metrics failed: {}", e),
+        if collect_volumes && volumes_due && health.volumes.ready() && !host_mode {
+            if let Some(claims) = claims.as_mut().filter(|c| c.due() && health.volume_claims.ready()) {
+                let result = claims.refresh().await;
+                health.volume_claims.observe(&result);
+            }
+            let claims = claims.as_ref().filter(|_| !health.volume_claims.disabled());
+
+            let s = span("collect_volumes");
+            let result = volumes.collect(&node_name, claims, &mut sender).map(|_| ());
+            health.volumes.observe(&result);
+            end(s, result.map_err(|e| e.to_string()));
+        }
+
+        // Host mode's services, containers and filesystems, on the container and volume schedules
+        #[cfg(not(windows))]
+        if let Some((cgroups, mounts)) = &mut host {
+            if collect_containers && containers_due && health.containers.ready() {
+                let s = span("collect_cgroups");
+                let result = cgroups.collect(&node_name, &mut sender);
+                health.containers.observe(&result);
+                end(s, result.map_err(|e| e.to_string()));
+            }
+            if collect_volumes && volumes_due && health.volumes.ready() {
+                let s = span("collect_mounts");
+                let result = mounts.collect(&node_name, &mut sender);
+                health.volumes.observe(&result);
+                end(s, result.map_err(|e| e.to_string()));
+            }
+        }
+
+        // Node services and overhead totals, on the container schedule
+        #[cfg(not(windows))]
+        if let Some(collector) = &mut system_slice {
+            if collect_containers && containers_due && health.system_slice.ready() {
+                let s = span("collect_system_slice");
+                let result = collector.collect(&node_name, &mut sender);
+                health.system_slice.observe(&result);
+                end(s, result.map_err(|e| e.to_string()));
+            }
+        }
+
+        // Devices held by pods, from the device manager checkpoint
+        if collect_devices && devices_due && health.devices.ready() {
+            let s = span("collect_devices");
+            let result = devices.collect(&node_name, &mut sender);
+            health.devices.observe(&result);
+            end(s, result.map_err(|e| e.to_string()));
+        }
+
+        // Plugins on their own interval; each is sandboxed and time boxed
+        #[cfg(feature = "plugins")]
+        if let Some(p) = plugins.as_mut().filter(|p| p.due()) {
+            let s = span("collect_plugins");
+            p.collect(&node_name, &mut sender);
+            end(s, Ok(()));
+        }
+
+        // Kubelet resource assignments, at the same pace as node status
+        #[cfg(unix)]
+        if let Some(collector) = &mut pod_resources {
+            if collector.due() && health.pod_resources.ready() {
+                let s = span("collect_pod_resources");
+                let roles = roles.as_ref().filter(|_| !health.pod_roles.disabled());
+                let result = collector.collect(&node_name, roles, &mut sender).await;
+                health.pod_resources.observe(&result);
+                end(s, result.map_err(|e| e.to_string()));
+            }
         }
 
-        // Flush metrics to consumer
-        if let Err(e) = sender.flush().await {
+        // Image sizes and imagefs usage, which drive image GC and eviction
+        #[cfg(unix)]
+        if let Some(collector) = &mut image_fs {
+            if collector.due() && health.image_fs.ready() {
+                let s = span("collect_image_fs");
+                let result = collector.collect(&node_name, &mut sender).await;
+                health.image_fs.observe(&result);
+                end(s, result.map_err(|e| e.to_string()));
+            }
+        }
+
+        // Container names and network counters only the Docker daemon knows
+        #[cfg(unix)]
+        if let Some(collector) = &mut docker {
+            if config.containers && collector.due() && health.docker.ready() {
+                let s = span("collect_docker");
+                let result = collector.collect(&node_name, &mut sender).await;
+                health.docker.observe(&result);
+                end(s, result.map_err(|e| e.to_string()));
+            }
+        }
+
+        // Probe results that landed since the last cycle
+        if let Some(blackbox) = &blackbox {
+            blackbox.collect(&node_name, &mut sender);
+        }
+
+        // A down component is a reading, not a collector failure
+        #[cfg(unix)]
+        if let Some(p) = probes.as_mut().filter(|p| p.due()) {
+            let s = span("probe_node_components");
+            p.collect(&node_name, &mut sender).await;
+            end(s, Ok(()));
+        }
+
+        // Node conditions and allocatable, at a slower pace than /proc
+        if let Some(collector) = &mut node_status {
+            if collector.due() && health.node_status.ready() {
+                let s = span("collect_node_status");
+                let result = collector.collect(&mut sender).await;
+                health.node_status.observe(&result);
+                end(s, result.map_err(|e| e.to_string()));
+            }
+        }
+
+        // Collect cluster-wide metrics if this agent is the leader
+        if let Some((_, collector, _)) = &mut cluster {
+            if leading && collector.due() && health.cluster.ready() {
+                let s = span("collect_cluster");
+                let result = collector.collect(&mut sender).await;
+                health.cluster.observe(&result);
+                end(s, result.map_err(|e| e.to_string()));
+            }
+        }
+
+        // Check our own usage against the budget
+        if let Some(w) = &mut watchdog {
+            w.check(&node_name, &mut sender);
+        }
+
+        // Derived metrics, which burst rules can use too
+        derived.evaluate(&node_name, &mut sender);
+
+        // Alerts on what this cycle read, derived metrics included
+        alerts.evaluate(&node_name, &mut sender);
+
+        // Start bursts on what this cycle read
+        #[cfg(not(windows))]
+        bursts.evaluate(&node_name, &mut sender);
+
+        // Flush metrics to consumer; its requests carry the flush span
+        let mut s = span("flush");
+        if let Some(s) = &mut s {
+            s.set("metrics", sender.pending());
+        }
+        sender.set_trace(s.as_ref().map(otel::Span::context));
+        let result = sender.flush().await;
+        if let Err(e) = &result {
             warn!("⚠️  Failed to flush metrics: {}", e);
         }
+        #[cfg(unix)]
+        if let Some(n) = &mut notifier {
+            match &result {
+                Ok(()) => n.status(&format!("Collecting every {}s", tick)),
+                Err(e) => n.status(&format!("Collecting every {}s, failing to send: {}", tick, e)),
+            }
+            n.ping();
+        }
+        end(s, result.map_err(|e| e.to_string()));
+
+        // State changes seen on this node during the cycle
+        if let Err(e) = sender.send_node_events().await {
+            warn!("⚠️  Failed to send node events: {}", e);
+        }
+
+        // Ship Kubernetes events gathered by the leader
+        if let Some((_, _, events)) = &mut cluster {
+            if let Err(e) = events.flush(&mut sender).await {
+                warn!("⚠️  Failed to send events: {}", e);
+            }
+        }
+
+        // Sent regardless of how collection went this cycle
+        if heartbeat.due() {
+            let enabled = [
+                ("system", collect_system),
+                ("containers", collect_containers),
+                ("volumes", collect_volumes),
+                ("devices", collect_devices),
+                ("node_status", node_status.is_some() && !health.node_status.disabled()),
+                ("cluster", leading && !health.cluster.disabled()),
+                ("events", leading),
+            ];
+            let collectors: Vec<&str> = enabled.iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect();
+            let record = heartbeat.record(&node_name, &collectors, &config);
+            if let Err(e) = sender.send_heartbeat(&record).await {
+                warn!("⚠️  Failed to send heartbeat: {}", e);
+            }
+        }
+        sender.set_trace(None);
+
+        if let (Some(t), Some(mut cycle)) = (&tracer, cycle.take()) {
+            cycle.set("leading", leading);
+            t.end(cycle);
+        }
+        if let Some(t) = &tracer {
+            t.export_if_due().await;
+        }
+
+        // Wait before next collection cycle; a config change starts one early
+        let multiplier = watchdog.as_ref().map_or(1, |w| w.interval_multiplier());
+        schedule.advance();
+        let next_cycle = tokio::time::Instant::now() + Duration::from_secs(tick * multiplier);
+        let closed = loop {
+            // Burst samples are queued for the next cycle's flush
+            let until = bursts.next_sample(next_cycle.into_std()).map_or(next_cycle, tokio::time::Instant::from_std);
+            // Watchdog pings come from this loop, so they stop if it hangs
+            #[cfg(unix)]
+            let until = notifier.as_ref().and_then(|n| n.next_ping())
+                .map_or(until, |ping| until.min(tokio::time::Instant::from_std(ping)));
+            let wait = tokio::time::sleep_until(until);
+            let woken = match &mut updates {
+                Some(rx) => tokio::select! {
+                    _ = wait => None,
+                    changed = rx.changed() => Some(changed.is_err()),
+                },
+                None => {
+                    wait.await;
+                    None
+                }
+            };
+            if let Some(closed) = woken {
+                break closed;
+            }
+            if until >= next_cycle {
+                break false;
+            }
+            #[cfg(unix)]
+            if let Some(n) = &mut notifier {
+                n.ping();
+            }
+
+            #[cfg(not(windows))]
+            {
+                let sample = bursts.take_due();
+                if sample.node && collect_system && local.is_none() && health.system.ready() {
+                    let result = system.collect_core(&node_name, &mut sender);
+                    health.system.observe(&result);
+                }
+                if !sample.pods.is_empty() && collect_containers && health.containers.ready() {
+                    let roles = roles.as_ref().filter(|_| !health.pod_roles.disabled());
+                    let result = containers.collect_pods(&node_name, roles, &sample.pods, &mut sender);
+                    health.containers.observe(&result);
+                }
+            }
+        };
+        if closed {
+            updates = None;
+        }
+    }
+}
+
+struct Health {
+    system: errors::CollectorState,
+    containers: errors::CollectorState,
+    volumes: errors::CollectorState,
+    devices: errors::CollectorState,
+    node_status: errors::CollectorState,
+    cluster: errors::CollectorState,
+    pod_roles: errors::CollectorState,
+    pod_resources: errors::CollectorState,
+    volume_claims: errors::CollectorState,
+    image_fs: errors::CollectorState,
+    docker: errors::CollectorState,
+    system_slice: errors::CollectorState,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            system: errors::CollectorState::new("system"),
+            containers: errors::CollectorState::new("containers"),
+            volumes: errors::CollectorState::new("volumes"),
+            devices: errors::CollectorState::new("devices"),
+            node_status: errors::CollectorState::new("node_status"),
+            cluster: errors::CollectorState::new("cluster"),
+            pod_roles: errors::CollectorState::new("pod_roles"),
+            pod_resources: errors::CollectorState::new("pod_resources"),
+            volume_claims: errors::CollectorState::new("volume_claims"),
+            image_fs: errors::CollectorState::new("image_fs"),
+            docker: errors::CollectorState::new("docker"),
+            system_slice: errors::CollectorState::new("system_slice"),
+        }
+    }
+}
+
+impl Health {
+    fn report(&self, sender: &mut metrics_sender::MetricsSender) {
+        for state in [&self.system, &self.containers, &self.volumes, &self.devices, &self.node_status, &self.cluster, &self.pod_roles, &self.pod_resources, &self.volume_claims, &self.image_fs, &self.docker, &self.system_slice] {
+            state.report(sender);
+        }
+    }
+}
 
-        // Wait before next collection cycle
-        tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+fn end_span(tracer: Option<&otel::Tracer>, span: Option<otel::Span>, result: Result<(), String>) {
+    if let (Some(t), Some(mut span)) = (tracer, span) {
+        if let Err(e) = result {
+            span.set_error(e);
+        }
+        t.end(span);
     }
 }
+
+fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|v| v == "true" || v == "1")
+}
+
+fn env_secs(name: &str, default: u64) -> Duration {
+    Duration::from_secs(env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default))
+}
@@ -1,44 +1,523 @@
 use anyhow::Result;
+use bytes::{BufMut, BytesMut};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use flate2::write::GzEncoder;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MetricBatch {
-    pub node: String,
-    pub metrics: Vec<RawMetric>,
+use crate::compact::{self, CompactEncoder};
+use crate::events::EventRecord;
+use crate::handshake::{self, Agreement, Offer};
+use crate::heartbeat::HeartbeatRecord;
+use crate::history::History;
+use crate::jsonl::JsonLinesSink;
+use crate::nats::NatsSink;
+use crate::node_events::{self, NodeEvent, NodeEventBatch};
+use crate::otel::{self, SpanContext};
+use crate::labels::LabelPool;
+use crate::secret::Secret;
+use crate::signing::{self, Signer};
+use crate::tiers::{Tier, Tiers};
+#[cfg(unix)]
+use crate::unix_http;
+
+#[derive(Debug, Serialize)]
+pub struct MetricBatch<'a> {
+    pub node: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_labels: &'a BTreeMap<String, String>,
+    pub metrics: &'a [RawMetric],
+}
+
+#[derive(Serialize)]
+pub struct EventBatch<'a> {
+    pub node: &'a str,
+    pub events: &'a [EventRecord],
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RawMetric {
     #[serde(rename = "type")]
-    pub metric_type: String,
+    pub metric_type: Arc<str>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub pod_id: Option<String>,
+    pub pod_id: Option<Arc<str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub pod_uid: Option<String>,
+    pub pod_uid: Option<Arc<str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub volume: Option<String>,
+    pub volume: Option<Arc<str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub container_id: Option<String>,
-    pub key: String,
+    pub container_id: Option<Arc<str>>,
+    // Block device or network interface for node_disk/node_net
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<Arc<str>>,
+    pub key: Arc<str>,
     pub value: f64,
+    // Collection cycle timestamp, shared by every metric of the cycle
     pub ts: i64,
+    // When the value was read, in microseconds after the cycle started
+    #[serde(rename = "off", default)]
+    pub offset_us: u32,
+}
+
+/// Identifying labels of a metric, borrowed from the collector
+#[derive(Default)]
+pub struct Labels<'a> {
+    pub pod_id: Option<&'a str>,
+    pub pod_uid: Option<&'a str>,
+    pub volume: Option<&'a str>,
+    pub container_id: Option<&'a str>,
+    pub device: Option<&'a str>,
+}
+
+/// Full identity of one series: metric type, labels and key
+#[derive(Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct SeriesKey {
+    #[serde(rename = "type")]
+    metric_type: Arc<str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pod_id: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pod_uid: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    volume: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container_id: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<Arc<str>>,
+    key: Arc<str>,
+}
+
+impl SeriesKey {
+    pub fn of(m: &RawMetric) -> Self {
+        Self {
+            metric_type: m.metric_type.clone(),
+            pod_id: m.pod_id.clone(),
+            pod_uid: m.pod_uid.clone(),
+            volume: m.volume.clone(),
+            container_id: m.container_id.clone(),
+            device: m.device.clone(),
+            key: m.key.clone(),
+        }
+    }
+
+    /// A label by its wire name, the metric type and key included
+    pub fn label(&self, name: &str) -> Option<&str> {
+        match name {
+            "type" => Some(&self.metric_type),
+            "pod_id" => self.pod_id.as_deref(),
+            "pod_uid" => self.pod_uid.as_deref(),
+            "volume" => self.volume.as_deref(),
+            "container_id" => self.container_id.as_deref(),
+            "device" => self.device.as_deref(),
+            "key" => Some(&self.key),
+            _ => None,
+        }
+    }
+}
+
+/// Which HTTP version to speak to the consumer
+#[derive(Clone, Copy, Debug)]
+pub enum HttpVersion {
+    // HTTP/2 when TLS negotiates it, HTTP/1.1 otherwise
+    Auto,
+    Http1,
+    // HTTP/2 without negotiation, for cleartext h2c proxies
+    Http2,
+}
+
+/// Connection settings of the consumer client
+#[derive(Clone, Debug)]
+pub struct HttpSettings {
+    pub connect_timeout: Duration,
+    // Whole request including the response, so a hung consumer can't stall flushes
+    pub request_timeout: Duration,
+    pub pool_max_idle: usize,
+    pub pool_idle_timeout: Duration,
+    pub keepalive: Duration,
+    // The client is rebuilt this often, so pooled connections to consumer
+    // pods that moved are dropped and names resolved again
+    pub dns_refresh: Duration,
+    pub version: HttpVersion,
+    // Overrides HTTP_PROXY/HTTPS_PROXY/NO_PROXY, which apply otherwise
+    pub proxy: Option<String>,
+    // Send over this unix socket instead; endpoint URLs then only give the path
+    pub unix_socket: Option<PathBuf>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+            pool_max_idle: 4,
+            pool_idle_timeout: Duration::from_secs(90),
+            keepalive: Duration::from_secs(60),
+            dns_refresh: Duration::from_secs(60),
+            version: HttpVersion::Auto,
+            proxy: None,
+            unix_socket: None,
+        }
+    }
+}
+
+/// Header naming the sending node, so a sharded consumer can redirect early
+const NODE_HEADER: &str = "X-Vita-Node";
+
+/// After failing over, the preferred endpoint is tried again this often
+const FAILBACK_AFTER: Duration = Duration::from_secs(60);
+
+/// Flushes between label pool prunes
+const PRUNE_EVERY: u32 = 60;
+
+/// The handshake is repeated this often, so a consumer rolled back or
+/// forward is noticed without restarting the agent
+const RENEGOTIATE_EVERY: Duration = Duration::from_secs(600);
+
+/// Retry delay after a failed handshake; defaults apply meanwhile
+const HANDSHAKE_RETRY: Duration = Duration::from_secs(30);
+
+/// Backoff bounds when the consumer pushes back without saying for how long;
+/// the delay doubles with every rejection in a row
+const MIN_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Metrics held while backing off, about ten minutes of a busy node; the
+/// oldest of the lowest tier are dropped beyond that
+const MAX_SPOOLED: usize = 200_000;
+
+/// While backing off, best-effort types are only collected every this many
+/// cycles, or less often still under a degraded budget
+const BACKOFF_SAMPLE_EVERY: u64 = 4;
+
+/// Body of a consumer 429/503
+#[derive(Deserialize)]
+struct BackpressureHint {
+    retry_after_ms: u64,
+}
+
+/// Body of an accepted batch; consumers from before partial acceptance send none
+#[derive(Deserialize)]
+struct IngestResult {
+    #[serde(default)]
+    reasons: BTreeMap<String, u64>,
+    // The first few rejections, by position in the batch
+    #[serde(default)]
+    rejections: Vec<Rejection>,
+}
+
+#[derive(Deserialize)]
+struct Rejection {
+    index: usize,
+    reason: String,
+    // The field at fault, from consumers that validate metrics
+    #[serde(default)]
+    field: String,
+}
+
+/// Body of a 400 for a batch that failed validation as a whole
+#[derive(Deserialize)]
+struct BatchError {
+    reason: String,
+    field: String,
+    detail: String,
 }
 
 pub struct MetricsSender {
     client: reqwest::Client,
-    endpoint: String,
+    client_built: Instant,
+    http: HttpSettings,
+    // Consumer ingest URLs in order of preference, and the one in use
+    endpoints: Vec<String>,
+    active: usize,
+    failed_over_at: Option<Instant>,
+    // Replica that owns this node, learned from a consumer redirect
+    shard_endpoint: Option<String>,
     node_name: String,
+    // Selected Node object labels, sent once per batch for every metric in it
+    node_labels: BTreeMap<String, String>,
+    labels: LabelPool,
+    // Batch and body buffers keep their capacity across cycles
     batch: Vec<RawMetric>,
+    body: BytesMut,
+    flushes: u32,
+    // Set while compact wire mode is enabled and agreed to
+    compact: Option<CompactEncoder>,
+    compact_wanted: bool,
+    agreement: Agreement,
+    // Next handshake; None means before the next flush
+    renegotiate_at: Option<Instant>,
+    // Set while the consumer asked us to hold off; the batch keeps growing
+    backoff_until: Option<Instant>,
+    backoff: Duration,
+    // Metrics the consumer refused since startup, by reason
+    rejected: BTreeMap<String, u64>,
+    tiers: Tiers,
+    // Spooled metrics dropped since startup, by tier
+    dropped: BTreeMap<Tier, u64>,
+    // Watchdog degrade level, and whether best-effort types sit this cycle out
+    pressure: u32,
+    cycles: u64,
+    skip_best_effort: bool,
+    // Last value of each counter and the prune generation it was seen in
+    counters: HashMap<SeriesKey, (f64, u32)>,
+    counter_gen: u32,
+    cycle_ts: i64,
+    cycle_start: Instant,
+    // Bearer token for the consumer and extra CA to trust, both rotatable
+    token: Option<Secret>,
+    ca: Option<Secret>,
+    signer: Option<Signer>,
+    // Either replaces the consumer when set
+    jsonl: Option<JsonLinesSink>,
+    nats: Option<NatsSink>,
+    // Span the current requests belong to, passed on as a traceparent
+    trace: Option<SpanContext>,
+    // Recent values of every series, for the local query endpoint
+    history: Option<Arc<Mutex<History>>>,
+    // State changes waiting for send_node_events
+    node_events: Vec<NodeEvent>,
 }
 
 impl MetricsSender {
     pub fn new(endpoint: String, node_name: String) -> Self {
+        let http = HttpSettings::default();
         Self {
-            client: reqwest::Client::new(),
-            endpoint,
+            client: build_client(&http, None),
+            client_built: Instant::now(),
+            http,
+            endpoints: parse_endpoints(&endpoint),
+            active: 0,
+            failed_over_at: None,
+            shard_endpoint: None,
             node_name,
+            node_labels: BTreeMap::new(),
+            labels: LabelPool::default(),
             batch: Vec::with_capacity(100),
+            body: BytesMut::with_capacity(16 * 1024),
+            flushes: 0,
+            compact: None,
+            compact_wanted: false,
+            agreement: Agreement::legacy(false),
+            renegotiate_at: None,
+            backoff_until: None,
+            backoff: MIN_BACKOFF,
+            rejected: BTreeMap::new(),
+            tiers: Tiers::default(),
+            dropped: BTreeMap::new(),
+            pressure: 0,
+            cycles: 0,
+            skip_best_effort: false,
+            counters: HashMap::new(),
+            counter_gen: 0,
+            cycle_ts: get_timestamp(),
+            cycle_start: Instant::now(),
+            token: None,
+            ca: None,
+            signer: None,
+            jsonl: None,
+            nats: None,
+            trace: None,
+            history: None,
+            node_events: Vec::new(),
+        }
+    }
+
+    /// Send to a different consumer, or a comma-separated list of them in
+    /// order of preference; a learned shard is forgotten
+    pub fn set_endpoint(&mut self, endpoint: String) {
+        self.endpoints = parse_endpoints(&endpoint);
+        self.active = 0;
+        self.failed_over_at = None;
+        self.shard_endpoint = None;
+        self.renegotiate_at = None;
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.endpoints[self.active]
+    }
+
+    /// Moves on to the next endpoint after the one in use failed
+    fn fail_over(&mut self) {
+        if self.endpoints.len() < 2 {
+            return;
+        }
+        self.active = (self.active + 1) % self.endpoints.len();
+        self.failed_over_at = Some(Instant::now());
+        self.shard_endpoint = None;
+        self.renegotiate_at = None;
+        tracing::warn!("Failing over to consumer {}", self.endpoint());
+    }
+
+    /// Returns to the preferred endpoint after a while, and rebuilds the
+    /// client when it is due for fresh connections
+    fn refresh_connections(&mut self) {
+        if self.active != 0 && self.failed_over_at.is_some_and(|t| t.elapsed() >= FAILBACK_AFTER) {
+            self.active = 0;
+            self.failed_over_at = None;
+            self.shard_endpoint = None;
+            self.renegotiate_at = None;
+            tracing::info!("Trying preferred consumer {} again", self.endpoint());
+        }
+        if self.client_built.elapsed() >= self.http.dns_refresh {
+            self.rebuild_client();
+        }
+    }
+
+    fn rebuild_client(&mut self) {
+        self.client = build_client(&self.http, self.ca.as_ref().map(|c| c.value()));
+        self.client_built = Instant::now();
+    }
+
+    /// Topology labels (zone, region, ...) the consumer stores with every metric
+    pub fn set_node_labels(&mut self, labels: BTreeMap<String, String>) {
+        self.node_labels = labels;
+    }
+
+    /// Authenticate to the consumer with this bearer token
+    pub fn set_token(&mut self, token: Option<Secret>) {
+        self.token = token;
+    }
+
+    /// Sign every batch with this shared HMAC key
+    pub fn set_signing_key(&mut self, key: Option<Secret>) {
+        self.signer = key.map(Signer::new);
+    }
+
+    /// Trust this PEM CA (e.g. a cluster-internal issuer) for a TLS consumer
+    pub fn set_ca(&mut self, ca: Option<Secret>) {
+        self.ca = ca;
+        self.rebuild_client();
+    }
+
+    /// Write everything as JSON lines instead of sending it to a consumer
+    pub fn set_jsonl(&mut self, sink: Option<JsonLinesSink>) {
+        self.jsonl = sink;
+    }
+
+    /// Publish everything to NATS instead of sending it to a consumer
+    pub fn set_nats(&mut self, sink: Option<NatsSink>) {
+        self.nats = sink;
+    }
+
+    /// Span that requests until the next call are made on behalf of
+    pub fn set_trace(&mut self, trace: Option<SpanContext>) {
+        self.trace = trace;
+    }
+
+    /// Recent values of every series, kept for the local query endpoint
+    pub fn set_history(&mut self, history: Option<Arc<Mutex<History>>>) {
+        self.history = history;
+    }
+
+    /// Timeouts, pooling, HTTP version and proxy for talking to the consumer
+    pub fn set_http(&mut self, http: HttpSettings) {
+        self.http = http;
+        self.rebuild_client();
+    }
+
+    /// Picks up rotated credentials; the client is rebuilt for a new CA
+    fn refresh_secrets(&mut self) {
+        if let Some(token) = &mut self.token {
+            token.refresh();
+        }
+        if let Some(signer) = &mut self.signer {
+            signer.refresh();
+        }
+        if self.ca.as_mut().is_some_and(|ca| ca.refresh()) {
+            self.rebuild_client();
+        }
+    }
+
+    /// Tier of each metric type, for sampling and dropping under pressure
+    pub fn set_tiers(&mut self, tiers: Tiers) {
+        self.tiers = tiers;
+    }
+
+    /// Degrade level of the resource budget; best-effort types are sampled
+    /// every 2^level cycles
+    pub fn set_pressure(&mut self, level: u32) {
+        self.pressure = level;
+    }
+
+    /// Send a label dictionary once per session and reference series by id
+    pub fn set_compact(&mut self, enabled: bool) {
+        self.compact_wanted = enabled;
+        self.compact = enabled.then(CompactEncoder::new);
+        self.renegotiate_at = None;
+    }
+
+    /// Agrees on wire format, compression and batch size with the consumer.
+    /// A consumer without the handshake endpoint gets what it always took.
+    async fn negotiate(&mut self) {
+        let wire_formats: &[&str] = if self.compact_wanted { &["compact", "json"] } else { &["json"] };
+        let offer = Offer {
+            node: &self.node_name,
+            version: env!("CARGO_PKG_VERSION"),
+            protocols: &handshake::PROTOCOLS,
+            wire_formats,
+            compression: &["gzip", "identity"],
+        };
+
+        let (agreement, retry) = match self.request_agreement(&offer).await {
+            Ok(Some(agreement)) => (agreement, RENEGOTIATE_EVERY),
+            Ok(None) => (Agreement::legacy(self.compact_wanted), RENEGOTIATE_EVERY),
+            Err(e) => {
+                tracing::warn!("Handshake with consumer failed, using defaults: {}", e);
+                (Agreement::legacy(self.compact_wanted), HANDSHAKE_RETRY)
+            }
+        };
+        self.renegotiate_at = Some(Instant::now() + retry);
+
+        if agreement != self.agreement {
+            tracing::info!("Consumer agreed to protocol {} | wire={} compression={} max_batch={}",
+                agreement.protocol, agreement.wire_format, agreement.compression, agreement.max_batch_metrics);
+        }
+        if agreement.compact() != self.compact.is_some() {
+            self.compact = agreement.compact().then(CompactEncoder::new);
+        }
+        self.agreement = agreement;
+    }
+
+    /// Posts the offer; None if the consumer predates the handshake
+    async fn request_agreement(&self, offer: &Offer<'_>) -> Result<Option<Agreement>> {
+        let url = reqwest::Url::parse(self.endpoint())?.join("handshake")?;
+        let body = bytes::Bytes::from(serde_json::to_vec(offer)?);
+
+        let resp = self.post(url.as_str(), "application/json", None, body).await?;
+        match resp.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(resp.json().await?)),
+            status => anyhow::bail!("HTTP {}", status),
+        }
+    }
+
+    /// Starts a collection cycle. Everything queued until the next call is
+    /// stamped with this instant, so CPU, memory and network from one cycle
+    /// line up exactly on the consumer.
+    pub fn begin_cycle(&mut self) {
+        self.cycle_ts = get_timestamp();
+        self.cycle_start = Instant::now();
+
+        let mut every = 1u64 << self.pressure.min(16);
+        if self.backing_off() {
+            every = every.max(BACKOFF_SAMPLE_EVERY);
+        }
+        self.skip_best_effort = !self.cycles.is_multiple_of(every);
+        self.cycles += 1;
+
+        // Running totals, so rejections can be graphed like any other metric
+        let totals: Vec<(String, f64)> = self.rejected.iter()
+            .map(|(reason, n)| (format!("rejected_{}", reason), *n as f64))
+            .collect();
+        let dropped: Vec<(String, f64)> = self.dropped.iter()
+            .map(|(tier, n)| (format!("dropped_{}", tier.as_str()), *n as f64))
+            .collect();
+        for (key, total) in totals.into_iter().chain(dropped) {
+            self.add("agent_ingest", &Labels::default(), &key, total);
         }
     }
 
@@ -46,34 +525,566 @@ impl MetricsSender {
         self.batch.push(metric);
     }
 
+    /// Whether values of this type are left out of the current cycle
+    fn sampled_out(&self, metric_type: &str) -> bool {
+        self.skip_best_effort && self.tiers.tier(metric_type) == Tier::BestEffort
+    }
+
+    /// Queue one value, interning its labels
+    pub fn add(&mut self, metric_type: &str, labels: &Labels, key: &str, value: f64) {
+        if self.sampled_out(metric_type) {
+            return;
+        }
+        let metric = RawMetric {
+            metric_type: self.labels.intern(metric_type),
+            pod_id: labels.pod_id.map(|l| self.labels.intern(l)),
+            pod_uid: labels.pod_uid.map(|l| self.labels.intern(l)),
+            volume: labels.volume.map(|l| self.labels.intern(l)),
+            container_id: labels.container_id.map(|l| self.labels.intern(l)),
+            device: labels.device.map(|l| self.labels.intern(l)),
+            key: self.labels.intern(key),
+            value,
+            ts: self.cycle_ts,
+            offset_us: self.cycle_start.elapsed().as_micros().min(u32::MAX as u128) as u32,
+        };
+        self.add_metric(metric);
+    }
+
+    /// Queue a monotonically increasing counter. If it went backwards since
+    /// the last sample (cgroup recreated, interface re-added, counter wrap),
+    /// a `<key>_reset` marker is queued too, so rate calculations restart from
+    /// the new baseline instead of producing a huge negative spike.
+    pub fn add_counter(&mut self, metric_type: &str, labels: &Labels, key: &str, value: f64) {
+        if self.sampled_out(metric_type) {
+            return;
+        }
+        self.add(metric_type, labels, key, value);
+        let series = match self.batch.last() {
+            Some(metric) => SeriesKey::of(metric),
+            None => return,
+        };
+
+        if let Some((previous, _)) = self.counters.insert(series, (value, self.counter_gen)) {
+            if value < previous {
+                tracing::info!("Counter reset: {} {} went from {} to {}", metric_type, key, previous, value);
+                self.add(metric_type, labels, &format!("{}_reset", key), 1.0);
+            }
+        }
+    }
+
+    /// Metrics queued in the current cycle, leaving out earlier ones held
+    /// for the consumer
+    pub fn cycle_metrics(&self) -> impl Iterator<Item = &RawMetric> {
+        self.batch.iter().filter(|m| m.ts == self.cycle_ts)
+    }
+
+    /// Number of metrics queued for the next flush
+    pub fn pending(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Drops queued metrics without sending them
+    pub fn discard(&mut self) {
+        self.batch.clear();
+    }
+
+    /// Whether the consumer asked us to hold off sending for now
+    pub fn backing_off(&self) -> bool {
+        self.backoff_until.is_some_and(|t| Instant::now() < t)
+    }
+
+    /// Holds off sending for `retry_after`, or the next doubling delay when the
+    /// consumer gave none
+    fn back_off(&mut self, status: reqwest::StatusCode, retry_after: Option<Duration>) {
+        let delay = retry_after.unwrap_or(self.backoff).min(MAX_BACKOFF);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        self.backoff_until = Some(Instant::now() + delay);
+        tracing::warn!("Consumer is overloaded (HTTP {}), holding metrics for {:?}", status, delay);
+    }
+
     pub async fn flush(&mut self) -> Result<()> {
         if self.batch.is_empty() {
             return Ok(());
         }
+        // Before any of it is sent, so the history doesn't depend on the consumer
+        if let Some(history) = &self.history {
+            history.lock().unwrap().record(self.cycle_ts, self.batch.iter());
+        }
+
+        if let Some(sink) = &mut self.jsonl {
+            let written = sink.write_metrics(&self.node_name, &self.node_labels, &self.batch);
+            self.batch.clear();
+            self.prune_if_due();
+            return written;
+        }
+        if let Some(nats) = &mut self.nats {
+            let published = nats.publish_metrics(&self.node_name, &self.node_labels, &self.batch).await;
+            self.batch.clear();
+            self.prune_if_due();
+            return published;
+        }
+
+        // Spool until the consumer is ready again
+        if self.backing_off() {
+            if self.batch.len() > MAX_SPOOLED {
+                self.shed(self.batch.len() - MAX_SPOOLED);
+            }
+            return Ok(());
+        }
+        self.refresh_secrets();
+        self.refresh_connections();
+        if self.renegotiate_at.is_none_or(|t| Instant::now() >= t) {
+            self.negotiate().await;
+        }
 
-        let payload = MetricBatch {
-            node: self.node_name.clone(),
-            metrics: std::mem::replace(&mut self.batch, Vec::with_capacity(100)),
+        // Spooled metrics past the consumer's late window would be clamped or
+        // rejected live; they go to backfill first, oldest first
+        let mut backfill_end = 0;
+        if let Some(cutoff) = self.agreement.backfill_before(get_timestamp()) {
+            if self.batch.iter().any(|m| m.ts < cutoff) {
+                self.batch.sort_by_key(|m| m.ts >= cutoff);
+                backfill_end = self.batch.partition_point(|m| m.ts < cutoff);
+            }
+        }
+
+        // Batches above the consumer's limit go out in several requests
+        let chunk = match self.agreement.max_batch_metrics {
+            0 => self.batch.len(),
+            max => max,
         };
+        let mut start = 0;
+        while start < self.batch.len() {
+            let mut backfill = start < backfill_end;
+            let range = start..(start + chunk).min(if backfill { backfill_end } else { self.batch.len() });
+            start = range.end;
+
+            let first = range.start;
+            let mut status = self.send(range.clone(), backfill).await?;
 
-        match self.client
-            .post(&self.endpoint)
-            .json(&payload)
-            .send()
-            .await
-        {
-            Ok(resp) => {
-                if !resp.status().is_success() {
-                    tracing::warn!("Failed to send metrics: HTTP {}", resp.status());
+            // Backfill was turned off since the handshake; late metrics are
+            // better clamped than lost
+            if backfill && status == Some(reqwest::StatusCode::NOT_FOUND) {
+                tracing::info!("Consumer has backfill disabled, sending spooled metrics live");
+                self.renegotiate_at = None;
+                backfill_end = 0;
+                backfill = false;
+                status = self.send(range.clone(), false).await?;
+            }
+
+            // The consumer doesn't know this session's series; define them all again
+            if status == Some(reqwest::StatusCode::CONFLICT) && !backfill {
+                if let Some(encoder) = &mut self.compact {
+                    encoder.resend_all();
+                    status = self.send(range, false).await?;
                 }
             }
-            Err(e) => {
-                tracing::warn!("Failed to send metrics: {}", e);
+
+            // Keep this chunk and the rest for when the backoff ends
+            if self.backing_off() {
+                self.batch.drain(..first);
+                return Ok(());
+            }
+
+            let sent = status.is_some_and(|s| s.is_success());
+            if let Some(encoder) = &mut self.compact {
+                if sent && !backfill {
+                    encoder.ack();
+                }
+            }
+            if !sent {
+                break;
+            }
+            self.backoff = MIN_BACKOFF;
+        }
+        self.batch.clear();
+        self.prune_if_due();
+
+        Ok(())
+    }
+
+    /// Drops `excess` spooled metrics, the oldest of the lowest tier first
+    fn shed(&mut self, excess: usize) {
+        let mut queued: BTreeMap<Tier, usize> = BTreeMap::new();
+        for m in &self.batch {
+            *queued.entry(self.tiers.tier(&m.metric_type)).or_default() += 1;
+        }
+        let mut left = excess;
+        let mut drop: BTreeMap<Tier, usize> = BTreeMap::new();
+        for tier in Tier::ALL {
+            let n = queued.get(&tier).copied().unwrap_or(0).min(left);
+            if n > 0 {
+                drop.insert(tier, n);
+                left -= n;
+            }
+        }
+
+        let mut remaining = drop.clone();
+        let tiers = &self.tiers;
+        self.batch.retain(|m| match remaining.get_mut(&tiers.tier(&m.metric_type)) {
+            Some(n) if *n > 0 => {
+                *n -= 1;
+                false
+            }
+            _ => true,
+        });
+        for (tier, n) in drop {
+            tracing::warn!("Dropping {} spooled {} metrics", n, tier.as_str());
+            *self.dropped.entry(tier).or_default() += n as u64;
+        }
+    }
+
+    fn prune_if_due(&mut self) {
+        self.flushes += 1;
+        if self.flushes >= PRUNE_EVERY {
+            self.flushes = 0;
+            self.labels.prune();
+            // Forget counters not sampled since the previous prune
+            let gen = self.counter_gen;
+            self.counters.retain(|_, (_, seen)| *seen == gen);
+            self.counter_gen = gen.wrapping_add(1);
+            if let Some(encoder) = &mut self.compact {
+                encoder.prune(PRUNE_EVERY as u64);
+            }
+        }
+    }
+
+    /// Encodes part of the pending batch and posts it to live ingest, or as
+    /// JSON to backfill, following a shard redirect. Returns the final
+    /// status, or None if the request itself failed.
+    async fn send(&mut self, range: Range<usize>, backfill: bool) -> Result<Option<reqwest::StatusCode>> {
+        // Serialize into the reused buffer. The frozen body shares its
+        // allocation, which the next reserve() reclaims once the request is done.
+        self.body.clear();
+        let content_type = match &mut self.compact {
+            Some(encoder) if !backfill => {
+                encoder.encode(&self.node_name, &self.node_labels, &self.batch[range.clone()], (&mut self.body).writer())?;
+                compact::CONTENT_TYPE
+            }
+            _ => {
+                let payload = MetricBatch {
+                    node: &self.node_name,
+                    node_labels: &self.node_labels,
+                    metrics: &self.batch[range.clone()],
+                };
+                serde_json::to_writer((&mut self.body).writer(), &payload)?;
+                "application/json"
+            }
+        };
+        let mut body = self.body.split().freeze();
+        let len = body.len();
+
+        let encoding = self.agreement.gzip().then_some("gzip");
+        if encoding.is_some() {
+            let mut gz = GzEncoder::new(Vec::with_capacity(len / 4), flate2::Compression::fast());
+            gz.write_all(&body)?;
+            body = gz.finish()?.into();
+        }
+
+        let mut target = self.shard_endpoint.clone().unwrap_or_else(|| self.endpoint().to_string());
+        if backfill {
+            target = format!("{}/backfill", target.trim_end_matches('/'));
+        }
+        let mut status = None;
+
+        // One hop is enough: every replica computes the same shard ring
+        for _ in 0..2 {
+            match self.post(&target, content_type, encoding, body.clone()).await {
+                Ok(resp) if resp.status().is_redirection() => {
+                    let location = resp.headers()
+                        .get(reqwest::header::LOCATION)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|loc| resp.url().join(loc).ok());
+
+                    match location {
+                        Some(url) => {
+                            tracing::info!("Consumer shard for node {} is {}", self.node_name, url);
+                            target = url.to_string();
+                            // A backfill redirect names the shard's backfill endpoint
+                            if !backfill {
+                                self.shard_endpoint = Some(target.clone());
+                            }
+                        }
+                        None => {
+                            tracing::warn!("Failed to send metrics: HTTP {} without Location", resp.status());
+                            status = Some(resp.status());
+                            break;
+                        }
+                    }
+                }
+                Ok(resp) if matches!(resp.status(), reqwest::StatusCode::TOO_MANY_REQUESTS | reqwest::StatusCode::SERVICE_UNAVAILABLE) => {
+                    let status = resp.status();
+                    let header = resp.headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.trim().parse().ok())
+                        .map(Duration::from_secs);
+                    let hint = resp.json::<BackpressureHint>().await.ok()
+                        .map(|h| Duration::from_millis(h.retry_after_ms));
+                    self.back_off(status, hint.or(header));
+                    break;
+                }
+                Ok(resp) if resp.status().is_success() => {
+                    status = Some(resp.status());
+                    if let Ok(result) = resp.json::<IngestResult>().await {
+                        self.record_rejections(range.start, result);
+                    }
+                    break;
+                }
+                Ok(resp) if resp.status() == reqwest::StatusCode::BAD_REQUEST => {
+                    status = Some(resp.status());
+                    match resp.json::<BatchError>().await {
+                        Ok(e) => tracing::warn!("Consumer rejected the batch as {} ({}): {}", e.reason, e.field, e.detail),
+                        Err(_) => tracing::warn!("Failed to send metrics: HTTP 400"),
+                    }
+                    break;
+                }
+                Ok(resp) => {
+                    // A compact-mode conflict is handled by the caller
+                    if !resp.status().is_success() && resp.status() != reqwest::StatusCode::CONFLICT {
+                        tracing::warn!("Failed to send metrics: HTTP {}", resp.status());
+                    }
+                    // The consumer changed under us; agree again before the next batch
+                    if matches!(resp.status(), reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE | reqwest::StatusCode::PAYLOAD_TOO_LARGE) {
+                        self.renegotiate_at = None;
+                    }
+                    // A proxy or load balancer in front of a consumer that is gone
+                    if matches!(resp.status(), reqwest::StatusCode::BAD_GATEWAY | reqwest::StatusCode::GATEWAY_TIMEOUT) {
+                        self.fail_over();
+                    }
+                    status = Some(resp.status());
+                    break;
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to send metrics: {}", e);
+                    // The shard may have moved; ask the configured endpoint
+                    // again next time, or the next one if that was it
+                    if self.shard_endpoint.take().is_none() {
+                        self.fail_over();
+                    }
+                    break;
+                }
+            }
+        }
+        // Reclaim the allocation for the next cycle's payload of similar size
+        drop(body);
+        self.body.reserve(len);
+
+        Ok(status)
+    }
+
+    /// Counts metrics the consumer dropped from an accepted batch starting at
+    /// `offset`, logging one example per reason
+    fn record_rejections(&mut self, offset: usize, result: IngestResult) {
+        for (reason, n) in &result.reasons {
+            *self.rejected.entry(reason.clone()).or_default() += n;
+        }
+
+        let mut logged = BTreeSet::new();
+        for rejection in &result.rejections {
+            if !logged.insert(rejection.reason.as_str()) {
+                continue;
             }
+            let Some(metric) = self.batch.get(offset + rejection.index) else { continue };
+            let count = result.reasons.get(&rejection.reason).copied().unwrap_or(1);
+            // Blocked series were dropped on purpose by an admin rule
+            if rejection.reason == "blocked" {
+                tracing::debug!("Consumer blocked {} metrics, e.g. {} {}", count, metric.metric_type, metric.key);
+            } else if !rejection.field.is_empty() {
+                tracing::warn!("Consumer rejected {} metrics as {} ({}), e.g. {} {}",
+                    count, rejection.reason, rejection.field, metric.metric_type, metric.key);
+            } else {
+                tracing::warn!("Consumer rejected {} metrics as {}, e.g. {} {}",
+                    count, rejection.reason, metric.metric_type, metric.key);
+            }
+        }
+    }
+
+    /// Posts Kubernetes events to the consumer's events endpoint. A consumer
+    /// with events disabled drops them here.
+    pub async fn send_events(&mut self, events: &[EventRecord]) -> Result<()> {
+        if let Some(sink) = &mut self.jsonl {
+            return sink.write_events(&self.node_name, events);
+        }
+        if let Some(nats) = &mut self.nats {
+            return nats.publish_events(&self.node_name, events).await;
+        }
+        if !self.agreement.accepts("events") {
+            return Ok(());
         }
+        let body = serde_json::to_vec(&EventBatch { node: &self.node_name, events })?;
+        self.post_beside("events", body).await
+    }
 
+    /// Queues a state change seen on this node, for send_node_events
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn event(&mut self, kind: &'static str, labels: &Labels, message: String) {
+        let mut line = format!("NODE_EVENT={} node={}", kind, self.node_name);
+        for (name, value) in [("pod_id", labels.pod_id), ("pod_uid", labels.pod_uid), ("volume", labels.volume),
+            ("container_id", labels.container_id), ("device", labels.device)] {
+            if let Some(value) = value {
+                line.push_str(&format!(" {}={}", name, value));
+            }
+        }
+        tracing::info!("{} message={:?}", line, message);
+        self.node_events.push(NodeEvent::new(get_timestamp(), kind, labels, message));
+    }
+
+    /// Sends the node events queued since the last call. Unsent ones are
+    /// kept for the next attempt, up to node_events::MAX_PENDING.
+    pub async fn send_node_events(&mut self) -> Result<()> {
+        if self.node_events.len() > node_events::MAX_PENDING {
+            let excess = self.node_events.len() - node_events::MAX_PENDING;
+            tracing::warn!("Dropping {} unsent node events", excess);
+            self.node_events.drain(..excess);
+        }
+        if self.node_events.is_empty() {
+            return Ok(());
+        }
+        if let Some(sink) = &mut self.jsonl {
+            let written = sink.write_node_events(&self.node_name, &self.node_events);
+            self.node_events.clear();
+            return written;
+        }
+        if let Some(nats) = &mut self.nats {
+            let published = nats.publish_node_events(&self.node_name, &self.node_events).await;
+            self.node_events.clear();
+            return published;
+        }
+        if self.backing_off() {
+            return Ok(());
+        }
+        if !self.agreement.accepts("node-events") {
+            self.node_events.clear();
+            return Ok(());
+        }
+        let body = serde_json::to_vec(&NodeEventBatch { node: &self.node_name, events: &self.node_events })?;
+        self.post_beside("node-events", body).await?;
+        self.node_events.clear();
         Ok(())
     }
+
+    /// Posts this agent's heartbeat to the consumer
+    pub async fn send_heartbeat(&mut self, heartbeat: &HeartbeatRecord<'_>) -> Result<()> {
+        if let Some(nats) = &mut self.nats {
+            return nats.publish_heartbeat(&self.node_name, heartbeat).await;
+        }
+        // Heartbeats only mean something to a consumer tracking agents
+        if self.jsonl.is_some() || !self.agreement.accepts("heartbeat") {
+            return Ok(());
+        }
+        let body = serde_json::to_vec(heartbeat)?;
+        self.post_beside("heartbeat", body).await
+    }
+
+    /// Posts JSON to `<ingest endpoint>/<path>`, which sits next to ingest
+    /// on the same replica, following one shard redirect
+    async fn post_beside(&mut self, path: &str, body: Vec<u8>) -> Result<()> {
+        self.refresh_secrets();
+        let body = bytes::Bytes::from(body);
+
+        let base = self.shard_endpoint.as_deref().unwrap_or(self.endpoint());
+        let mut url = format!("{}/{}", base.trim_end_matches('/'), path);
+
+        for _ in 0..2 {
+            let resp = self.post(&url, "application/json", None, body.clone()).await?;
+            let status = resp.status();
+            if status.is_redirection() {
+                if let Some(location) = resp.headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|loc| resp.url().join(loc).ok())
+                {
+                    url = location.to_string();
+                    continue;
+                }
+            }
+            if !status.is_success() {
+                anyhow::bail!("HTTP {}", status);
+            }
+            return Ok(());
+        }
+        anyhow::bail!("too many redirects")
+    }
+
+    async fn post(&self, url: &str, content_type: &str, encoding: Option<&str>, body: bytes::Bytes) -> Result<reqwest::Response> {
+        let mut request = self.client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .header(NODE_HEADER, &self.node_name);
+        if let Some(encoding) = encoding {
+            request = request.header(reqwest::header::CONTENT_ENCODING, encoding);
+        }
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token.value());
+        }
+        if let Some(trace) = &self.trace {
+            request = request.header(otel::TRACEPARENT_HEADER, trace.traceparent());
+        }
+        if let Some(signer) = &self.signer {
+            let ts = get_timestamp();
+            request = request
+                .header(signing::TIMESTAMP_HEADER, ts)
+                .header(signing::SIGNATURE_HEADER, signer.sign(ts, &self.node_name, &body));
+        }
+        let request = request.body(body);
+
+        match &self.http.unix_socket {
+            #[cfg(not(unix))]
+            Some(_) => anyhow::bail!("unix sockets are not supported on this platform"),
+            #[cfg(unix)]
+            Some(socket) => tokio::time::timeout(self.http.request_timeout, unix_http::send(socket, request.build()?))
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("operation timed out"))),
+            None => Ok(request.send().await?),
+        }
+    }
+}
+
+/// Splits a comma-separated endpoint list; an empty one is kept as is so
+/// requests fail visibly rather than the agent panicking
+fn parse_endpoints(endpoints: &str) -> Vec<String> {
+    let list: Vec<String> = endpoints.split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(String::from)
+        .collect();
+    if list.is_empty() {
+        return vec![endpoints.to_string()];
+    }
+    list
+}
+
+fn build_client(http: &HttpSettings, ca_pem: Option<&str>) -> reqwest::Client {
+    // Redirects are handled in flush() so the shard location sticks
+    let mut builder = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .connect_timeout(http.connect_timeout)
+        .timeout(http.request_timeout)
+        .pool_max_idle_per_host(http.pool_max_idle)
+        .pool_idle_timeout(http.pool_idle_timeout)
+        .tcp_keepalive(http.keepalive);
+
+    builder = match http.version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+
+    if let Some(proxy) = &http.proxy {
+        match reqwest::Proxy::all(proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => tracing::warn!("Ignoring invalid consumer proxy: {}", e),
+        }
+    }
+
+    if let Some(pem) = ca_pem {
+        match reqwest::Certificate::from_pem(pem.as_bytes()) {
+            Ok(cert) => builder = builder.add_root_certificate(cert),
+            Err(e) => tracing::warn!("Ignoring invalid consumer CA: {}", e),
+        }
+    }
+
+    builder.build().unwrap_or_default()
 }
 
 pub fn get_timestamp() -> i64 {
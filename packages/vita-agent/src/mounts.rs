@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::ffi::CString;
+use tracing::{debug, info};
+
+use crate::errors::Result;
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::parsers;
+use crate::pvc_metrics::filesystem_space;
+use crate::statfile::StatFile;
+
+/// Filesystems without a block device of their own that still hold data
+const UNBACKED_DATA_FS: &[&str] = &["btrfs", "zfs", "nfs", "nfs4", "cifs", "smb3", "ceph", "glusterfs", "fuse.sshfs"];
+
+/// Read-only images that are always full: snaps, live media
+const IMAGE_FS: &[&str] = &["squashfs", "iso9660", "erofs"];
+
+/// Space on the host's filesystems, for host mode where there are no pod
+/// volumes to report. Pseudo filesystems are left out, and a filesystem
+/// mounted at several places (bind mounts, btrfs subvolumes) is reported
+/// once, at the first of them in the mount table.
+pub struct MountCollector {
+    table: StatFile,
+    buf: Vec<u8>,
+    // Mount point prefixes not to stat, from MOUNT_EXCLUDE
+    exclude: Vec<String>,
+}
+
+impl MountCollector {
+    pub fn new() -> Self {
+        let table = if host::path("/proc/1/mountinfo").exists() {
+            host::path("/proc/1/mountinfo")
+        } else {
+            host::path("/proc/self/mountinfo")
+        };
+        let exclude = std::env::var("MOUNT_EXCLUDE").unwrap_or_default()
+            .split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect();
+        Self { table: StatFile::new(table), buf: Vec::new(), exclude }
+    }
+
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        let content = self.table.read(&mut self.buf)?;
+        let mut seen = HashSet::new();
+        for m in parsers::mountinfo(content) {
+            let data = (m.major != 0 || UNBACKED_DATA_FS.contains(&m.fs_type)) && !IMAGE_FS.contains(&m.fs_type);
+            if !data || !seen.insert((m.major, m.minor)) {
+                continue;
+            }
+            let mount_point = unescape(m.mount_point);
+            if self.exclude.iter().any(|p| mount_point.starts_with(p.as_str())) {
+                continue;
+            }
+            let path = host::path(&mount_point);
+            let Ok(path) = CString::new(path.to_string_lossy().as_bytes()) else { continue };
+            let (total, free) = match filesystem_space(&path) {
+                Ok(space) => space,
+                Err(e) => {
+                    debug!("Mounts: cannot stat {}: {}", mount_point, e);
+                    continue;
+                }
+            };
+            let total_mb = total / 1024 / 1024;
+            let free_mb = free / 1024 / 1024;
+            let used_mb = total_mb.saturating_sub(free_mb);
+            info!("METRIC_TYPE=mount node={} mountpoint={} fs={} total_mb={} used_mb={} free_mb={} read_only={}",
+                node_name, mount_point, m.fs_type, total_mb, used_mb, free_mb, m.read_only as u8);
+
+            let labels = Labels { device: Some(&mount_point), ..Default::default() };
+            sender.add("mount", &labels, "total_mb", total_mb as f64);
+            sender.add("mount", &labels, "used_mb", used_mb as f64);
+            sender.add("mount", &labels, "free_mb", free_mb as f64);
+            sender.add("mount", &labels, "read_only", m.read_only as u8 as f64);
+        }
+        Ok(())
+    }
+}
+
+/// Mount points with the kernel's octal escapes (`\040` for a space) undone
+fn unescape(mount_point: &str) -> String {
+    if !mount_point.contains('\\') {
+        return mount_point.to_string();
+    }
+    let bytes = mount_point.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u8::from_str_radix(d, 8).ok());
+        match octal {
+            Some(b) => {
+                out.push(b);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
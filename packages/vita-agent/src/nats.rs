@@ -0,0 +1,300 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::events::EventRecord;
+use crate::metrics_sender::{EventBatch, MetricBatch, RawMetric};
+use crate::node_events::{NodeEvent, NodeEventBatch};
+
+/// Longest wait for the server, JetStream acks included
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Payload limit assumed when the server's INFO doesn't give one
+const DEFAULT_MAX_PAYLOAD: usize = 1024 * 1024;
+
+/// Server greeting; only the fields we use
+#[derive(Deserialize)]
+struct ServerInfo {
+    #[serde(default)]
+    max_payload: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ConnectOptions<'a> {
+    verbose: bool,
+    pedantic: bool,
+    lang: &'a str,
+    version: &'a str,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pass: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<&'a str>,
+}
+
+/// JetStream's reply to a publish
+#[derive(Deserialize)]
+struct PubAck {
+    #[serde(default)]
+    error: Option<PubAckError>,
+}
+
+#[derive(Deserialize)]
+struct PubAckError {
+    #[serde(default)]
+    code: u16,
+    #[serde(default)]
+    description: String,
+}
+
+struct Connection {
+    stream: BufReader<TcpStream>,
+    max_payload: usize,
+    // Our reply subjects are <inbox>.<n>
+    inbox: String,
+    next_reply: u64,
+}
+
+/// Publishes batches to NATS: metrics on `<prefix>.metrics.<node>`, events
+/// and heartbeats on `<prefix>.events.<node>` and `<prefix>.heartbeat.<node>`,
+/// as the same JSON the consumer ingests. With JetStream each publish waits
+/// for the stream's ack, so a batch only counts as sent once stored.
+pub struct NatsSink {
+    addr: String,
+    user: Option<String>,
+    pass: Option<String>,
+    token: Option<String>,
+    prefix: String,
+    jetstream: bool,
+    // Dropped on any error and reopened on the next publish
+    conn: Option<Connection>,
+}
+
+impl NatsSink {
+    /// `url` is nats://[user:pass@|token@]host[:port]
+    pub fn new(url: &str, prefix: String, jetstream: bool) -> Result<Self> {
+        let url = reqwest::Url::parse(url).context("invalid NATS URL")?;
+        if url.scheme() != "nats" {
+            bail!("unsupported NATS URL scheme {}, TLS is not supported", url.scheme());
+        }
+        let host = url.host_str().context("NATS URL without host")?;
+        let username = (!url.username().is_empty()).then(|| url.username().to_string());
+        let (user, pass, token) = match (username, url.password()) {
+            (Some(user), Some(pass)) => (Some(user), Some(pass.to_string()), None),
+            (token, _) => (None, None, token),
+        };
+
+        Ok(Self {
+            addr: format!("{}:{}", host, url.port().unwrap_or(4222)),
+            user,
+            pass,
+            token,
+            prefix,
+            jetstream,
+            conn: None,
+        })
+    }
+
+    pub async fn publish_metrics(&mut self, node: &str, node_labels: &BTreeMap<String, String>, metrics: &[RawMetric]) -> Result<()> {
+        let subject = self.subject("metrics", node);
+        self.publish_split(&subject, metrics, |metrics| {
+            serde_json::to_vec(&MetricBatch { node, node_labels, metrics })
+        }).await
+    }
+
+    pub async fn publish_events(&mut self, node: &str, events: &[EventRecord]) -> Result<()> {
+        let subject = self.subject("events", node);
+        self.publish_split(&subject, events, |events| {
+            serde_json::to_vec(&EventBatch { node, events })
+        }).await
+    }
+
+    pub async fn publish_node_events(&mut self, node: &str, events: &[NodeEvent]) -> Result<()> {
+        let subject = self.subject("node_events", node);
+        self.publish_split(&subject, events, |events| {
+            serde_json::to_vec(&NodeEventBatch { node, events })
+        }).await
+    }
+
+    pub async fn publish_heartbeat(&mut self, node: &str, heartbeat: &impl Serialize) -> Result<()> {
+        let subject = self.subject("heartbeat", node);
+        let body = serde_json::to_vec(heartbeat)?;
+        self.publish(&subject, &[body]).await
+    }
+
+    /// Dots separate subject tokens, so they can't appear in the node name
+    fn subject(&self, kind: &str, node: &str) -> String {
+        format!("{}.{}.{}", self.prefix, kind, node.replace(['.', ' ', '*', '>'], "_"))
+    }
+
+    /// Encodes items as one message, or several when that exceeds the
+    /// server's payload limit
+    async fn publish_split<T>(&mut self, subject: &str, items: &[T], encode: impl Fn(&[T]) -> serde_json::Result<Vec<u8>>) -> Result<()> {
+        // The limit comes with the server greeting
+        if self.conn.is_none() {
+            let conn = tokio::time::timeout(TIMEOUT, self.connect()).await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out connecting to NATS")))?;
+            self.conn = Some(conn);
+        }
+        let max_payload = self.conn.as_ref().map_or(DEFAULT_MAX_PAYLOAD, |c| c.max_payload);
+        let body = encode(items)?;
+        if body.len() <= max_payload || items.len() < 2 {
+            return self.publish(subject, &[body]).await;
+        }
+
+        let parts = body.len().div_ceil(max_payload) + 1;
+        let bodies = items.chunks(items.len().div_ceil(parts))
+            .map(&encode)
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        self.publish(subject, &bodies).await
+    }
+
+    async fn publish(&mut self, subject: &str, bodies: &[Vec<u8>]) -> Result<()> {
+        let result = tokio::time::timeout(TIMEOUT * bodies.len() as u32, self.publish_all(subject, bodies)).await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+        if result.is_err() {
+            self.conn = None;
+        }
+        result
+    }
+
+    async fn publish_all(&mut self, subject: &str, bodies: &[Vec<u8>]) -> Result<()> {
+        let conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => self.connect().await?,
+        };
+        let conn = self.conn.insert(conn);
+
+        for body in bodies {
+            if body.len() > conn.max_payload {
+                bail!("message of {} bytes exceeds the server limit of {}", body.len(), conn.max_payload);
+            }
+
+            if !self.jetstream {
+                conn.write(format!("PUB {} {}\r\n", subject, body.len()).as_bytes(), body).await?;
+                continue;
+            }
+
+            conn.next_reply += 1;
+            let reply = format!("{}.{}", conn.inbox, conn.next_reply);
+            conn.write(format!("PUB {} {} {}\r\n", subject, reply, body.len()).as_bytes(), body).await?;
+
+            let ack: PubAck = serde_json::from_slice(&conn.reply_to(&reply).await?)
+                .context("invalid JetStream ack")?;
+            if let Some(e) = ack.error {
+                bail!("JetStream rejected the message: {} {}", e.code, e.description);
+            }
+        }
+
+        // Without acks, a round trip at least confirms the server read them
+        if !self.jetstream {
+            conn.stream.get_mut().write_all(b"PING\r\n").await?;
+            conn.wait_pong().await?;
+        }
+        Ok(())
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let stream = TcpStream::connect(&self.addr).await
+            .with_context(|| format!("connecting to NATS at {}", self.addr))?;
+        let mut stream = BufReader::new(stream);
+
+        let mut line = String::new();
+        stream.read_line(&mut line).await?;
+        let Some(info) = line.strip_prefix("INFO ") else {
+            bail!("unexpected NATS greeting: {}", line.trim_end());
+        };
+        let info: ServerInfo = serde_json::from_str(info.trim_end()).context("invalid NATS INFO")?;
+
+        let options = serde_json::to_string(&ConnectOptions {
+            verbose: false,
+            pedantic: false,
+            lang: "rust",
+            version: env!("CARGO_PKG_VERSION"),
+            name: "vita-agent",
+            user: self.user.as_deref(),
+            pass: self.pass.as_deref(),
+            auth_token: self.token.as_deref(),
+        })?;
+        stream.get_mut().write_all(format!("CONNECT {}\r\nPING\r\n", options).as_bytes()).await?;
+
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let mut conn = Connection {
+            stream,
+            max_payload: info.max_payload.unwrap_or(DEFAULT_MAX_PAYLOAD),
+            inbox: format!("_INBOX.{:x}{:x}", nanos, std::process::id()),
+            next_reply: 0,
+        };
+        conn.wait_pong().await?;
+
+        if self.jetstream {
+            let subscribe = format!("SUB {}.* 1\r\n", conn.inbox);
+            conn.stream.get_mut().write_all(subscribe.as_bytes()).await?;
+        }
+        Ok(conn)
+    }
+}
+
+impl Connection {
+    async fn write(&mut self, header: &[u8], body: &[u8]) -> Result<()> {
+        let out = self.stream.get_mut();
+        out.write_all(header).await?;
+        out.write_all(body).await?;
+        out.write_all(b"\r\n").await?;
+        Ok(())
+    }
+
+    /// Reads protocol lines, answering server pings, until the next one that
+    /// isn't housekeeping. Errors from the server end the connection.
+    async fn next_line(&mut self) -> Result<String> {
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                bail!("NATS connection closed");
+            }
+            let line = line.trim_end();
+            match line.split_once(' ').map_or(line, |(op, _)| op) {
+                "PING" => self.stream.get_mut().write_all(b"PONG\r\n").await?,
+                "+OK" | "INFO" => {}
+                "-ERR" => bail!("NATS error: {}", line.trim_start_matches("-ERR ")),
+                _ => return Ok(line.to_string()),
+            }
+        }
+    }
+
+    async fn wait_pong(&mut self) -> Result<()> {
+        loop {
+            if self.next_line().await? == "PONG" {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Payload of the message delivered to `reply`; late acks of earlier,
+    /// timed out publishes are skipped
+    async fn reply_to(&mut self, reply: &str) -> Result<Vec<u8>> {
+        loop {
+            let line = self.next_line().await?;
+            // MSG <subject> <sid> [reply-to] <#bytes>
+            let parts: Vec<&str> = line.split(' ').collect();
+            if parts.first() != Some(&"MSG") || parts.len() < 4 {
+                continue;
+            }
+            let len: usize = parts[parts.len() - 1].parse().context("invalid NATS message size")?;
+            let mut payload = vec![0; len + 2];
+            self.stream.read_exact(&mut payload).await?;
+            payload.truncate(len);
+            if parts[1] == reply {
+                return Ok(payload);
+            }
+        }
+    }
+}
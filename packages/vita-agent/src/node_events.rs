@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+use crate::metrics_sender::Labels;
+
+/// Events kept while the consumer is unreachable; the oldest go first
+pub const MAX_PENDING: usize = 5000;
+
+/// A state change seen on this node, as opposed to a reading: a container
+/// appearing or going away, an OOM kill, a volume mount, a link going down
+#[derive(Debug, Serialize)]
+pub struct NodeEvent {
+    pub ts: i64,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_uid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub message: String,
+}
+
+impl NodeEvent {
+    pub fn new(ts: i64, kind: &'static str, labels: &Labels, message: String) -> Self {
+        Self {
+            ts,
+            kind,
+            pod_id: labels.pod_id.map(String::from),
+            pod_uid: labels.pod_uid.map(String::from),
+            volume: labels.volume.map(String::from),
+            container_id: labels.container_id.map(String::from),
+            device: labels.device.map(String::from),
+            message,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct NodeEventBatch<'a> {
+    pub node: &'a str,
+    pub events: &'a [NodeEvent],
+}
@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::net::UnixStream;
+use tracing::{debug, info};
+
+use crate::grpc::{self, Fields, Value};
+use crate::image_fs;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// The kubelet's healthz, served on localhost unless --healthz-bind-address
+/// says otherwise
+const KUBELET_HEALTHZ: &str = "http://127.0.0.1:10248/healthz";
+
+/// Longest wait for either component; past it, it counts as down
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Liveness of the kubelet and the CRI runtime, asked directly: the
+/// kubelet's healthz and the runtime's Version call. The API server only
+/// learns of a dead kubelet once its lease lapses, and of a stuck runtime
+/// from PLEG warnings, if at all.
+pub struct NodeProbes {
+    client: reqwest::Client,
+    kubelet_url: Option<String>,
+    runtime_socket: Option<PathBuf>,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl NodeProbes {
+    /// KUBELET_HEALTHZ_URL overrides the kubelet's endpoint, empty to skip
+    /// it; the runtime is found as for image storage. None if neither is
+    /// left to probe.
+    pub fn new(interval: Duration) -> Option<Self> {
+        let kubelet_url = std::env::var("KUBELET_HEALTHZ_URL")
+            .unwrap_or_else(|_| KUBELET_HEALTHZ.to_string());
+        let kubelet_url = (!kubelet_url.is_empty()).then_some(kubelet_url);
+        let runtime_socket = image_fs::runtime_socket();
+        if kubelet_url.is_none() && runtime_socket.is_none() {
+            return None;
+        }
+        info!("Node probes enabled | kubelet={} runtime={} interval={:?}",
+            kubelet_url.as_deref().unwrap_or("-"),
+            runtime_socket.as_ref().map_or("-".to_string(), |s| s.display().to_string()),
+            interval);
+        let client = reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Some(Self { client, kubelet_url, runtime_socket, interval, last: None })
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    pub async fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        self.last = Some(Instant::now());
+        if let Some(url) = &self.kubelet_url {
+            let started = Instant::now();
+            let result = self.kubelet(url).await;
+            report(node_name, "kubelet", started.elapsed(), result, sender);
+        }
+        if let Some(socket) = &self.runtime_socket {
+            let started = Instant::now();
+            let result = match tokio::time::timeout(TIMEOUT, runtime_version(socket)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!("no reply in {:?}", TIMEOUT)),
+            };
+            report(node_name, "runtime", started.elapsed(), result, sender);
+        }
+    }
+
+    async fn kubelet(&self, url: &str) -> anyhow::Result<String> {
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            anyhow::bail!("HTTP {}: {}", status, body.trim());
+        }
+        Ok(body.trim().to_string())
+    }
+}
+
+/// VersionResponse: runtime_name = 2, runtime_version = 3
+async fn runtime_version(socket: &Path) -> anyhow::Result<String> {
+    let io = UnixStream::connect(socket).await
+        .map_err(|e| anyhow::anyhow!("connecting to {}: {}", socket.display(), e))?;
+    let reply = grpc::call(io, "/runtime.v1.RuntimeService/Version", &[]).await?;
+    let (mut name, mut version) = (String::new(), String::new());
+    for field in Fields(&reply) {
+        match field? {
+            (2, Value::Bytes(v)) => name = String::from_utf8_lossy(v).into_owned(),
+            (3, Value::Bytes(v)) => version = String::from_utf8_lossy(v).into_owned(),
+            _ => {}
+        }
+    }
+    Ok(format!("{} {}", name, version))
+}
+
+fn report(node_name: &str, component: &str, latency: Duration, result: anyhow::Result<String>, sender: &mut MetricsSender) {
+    let latency_ms = latency.as_secs_f64() * 1000.0;
+    let up = result.is_ok();
+    match &result {
+        Ok(reply) => debug!("Probe {}: {}", component, reply),
+        Err(e) => info!("Probe {} failed: {}", component, e),
+    }
+    info!("METRIC_TYPE=node_component node={} component={} up={} latency_ms={:.1}",
+        node_name, component, up as u8, latency_ms);
+
+    let labels = Labels { device: Some(component), ..Default::default() };
+    sender.add("node_component", &labels, "up", up as u8 as f64);
+    sender.add("node_component", &labels, "latency_ms", latency_ms);
+}
@@ -0,0 +1,125 @@
+use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::{Api, ListParams};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::errors::{CollectorError, Result};
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::parsers;
+
+/// Conditions reported as 1 (True) or 0, keyed by metric key
+const CONDITIONS: [(&str, &str); 5] = [
+    ("Ready", "ready"),
+    ("MemoryPressure", "memory_pressure"),
+    ("DiskPressure", "disk_pressure"),
+    ("PIDPressure", "pid_pressure"),
+    ("NetworkUnavailable", "network_unavailable"),
+];
+
+/// Node labels attached to metrics unless NODE_LABELS says otherwise, as
+/// `name=label key`. Several keys may feed one name; the first present wins.
+const DEFAULT_NODE_LABELS: &str = "zone=topology.kubernetes.io/zone,\
+    region=topology.kubernetes.io/region,\
+    instance_type=node.kubernetes.io/instance-type,\
+    nodepool=karpenter.sh/nodepool,\
+    nodepool=eks.amazonaws.com/nodegroup,\
+    nodepool=cloud.google.com/gke-nodepool,\
+    nodepool=kubernetes.azure.com/agentpool";
+
+/// This node's conditions, capacity and allocatable resources from its Node
+/// object, so utilization can be computed against what pods may actually use
+pub struct NodeStatusCollector {
+    api: Api<Node>,
+    node_name: String,
+    interval: Duration,
+    last: Option<Instant>,
+    // (name sent to the consumer, Node label key)
+    label_allowlist: Vec<(String, String)>,
+}
+
+impl NodeStatusCollector {
+    pub fn new(client: kube::Client, node_name: String, interval: Duration) -> Self {
+        let allowlist = std::env::var("NODE_LABELS").unwrap_or_else(|_| DEFAULT_NODE_LABELS.to_string());
+        let label_allowlist = allowlist.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| match entry.split_once('=') {
+                Some((name, key)) => (name.trim().to_string(), key.trim().to_string()),
+                None => (entry.to_string(), entry.to_string()),
+            })
+            .collect();
+
+        Self { api: Api::all(client), node_name, interval, last: None, label_allowlist }
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    pub async fn collect(&mut self, sender: &mut MetricsSender) -> Result<()> {
+        self.last = Some(Instant::now());
+
+        // A field-selected list needs only list permission on the node
+        let params = ListParams::default().fields(&format!("metadata.name={}", self.node_name));
+        let Some(node) = self.api.list(&params).await?.items.into_iter().next() else {
+            return Err(CollectorError::Other(anyhow::anyhow!("node {} not found", self.node_name)));
+        };
+
+        // Re-read on every fetch so relabelled nodes are picked up
+        let node_labels = node.metadata.labels.unwrap_or_default();
+        let mut selected = BTreeMap::new();
+        for (name, key) in &self.label_allowlist {
+            if let Some(value) = node_labels.get(key) {
+                selected.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        sender.set_node_labels(selected);
+
+        let Some(status) = node.status else { return Ok(()) };
+
+        let labels = Labels::default();
+        let conditions = status.conditions.unwrap_or_default();
+        let values: Vec<(&str, bool)> = CONDITIONS.iter()
+            .filter_map(|(condition, key)| {
+                let c = conditions.iter().find(|c| c.type_ == *condition)?;
+                Some((*key, c.status == "True"))
+            })
+            .collect();
+        info!("METRIC_TYPE=node_condition node={} {}", self.node_name, values.iter()
+            .map(|(key, v)| format!("{}={}", key, *v as u8))
+            .collect::<Vec<_>>()
+            .join(" "));
+        for (key, value) in values {
+            sender.add("node_condition", &labels, key, value as u8 as f64);
+        }
+
+        for (metric_type, resources) in [("node_capacity", status.capacity), ("node_allocatable", status.allocatable)] {
+            let Some(resources) = resources else { continue };
+            let values = resource_values(&resources);
+            info!("METRIC_TYPE={} node={} {}", metric_type, self.node_name, values.iter()
+                .map(|(key, v)| format!("{}={}", key, v))
+                .collect::<Vec<_>>()
+                .join(" "));
+            for (key, value) in values {
+                sender.add(metric_type, &labels, key, value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// cpu in millicores, memory and ephemeral storage in MB, pods as a count
+fn resource_values(resources: &BTreeMap<String, Quantity>) -> Vec<(&'static str, f64)> {
+    let get = |name: &str| resources.get(name).and_then(|q| parsers::quantity(&q.0));
+    [
+        ("cpu_millicores", get("cpu").map(|v| v * 1000.0)),
+        ("mem_mb", get("memory").map(|v| v / 1048576.0)),
+        ("ephemeral_storage_mb", get("ephemeral-storage").map(|v| v / 1048576.0)),
+        ("pods", get("pods")),
+    ].into_iter()
+        .filter_map(|(key, v)| v.map(|v| (key, v.round())))
+        .collect()
+}
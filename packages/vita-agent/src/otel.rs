@@ -0,0 +1,283 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::env;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// W3C trace context header, so the consumer's spans join the agent's trace
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Finished spans are exported this often, or sooner once this many pile up
+const EXPORT_EVERY: Duration = Duration::from_secs(10);
+const EXPORT_BATCH: usize = 256;
+
+/// Spans kept while the collector is unreachable; newer ones are dropped
+const MAX_BUFFERED: usize = 4096;
+
+/// Identifies a span to its children, locally or in a traceparent header
+#[derive(Clone, Copy, Debug)]
+pub struct SpanContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+}
+
+impl SpanContext {
+    pub fn traceparent(&self) -> String {
+        // Only sampled traces have spans at all
+        format!("00-{}-{}-01", hex(&self.trace_id), hex(&self.span_id))
+    }
+}
+
+/// OTLP JSON attribute value
+#[derive(Serialize)]
+pub enum AnyValue {
+    #[serde(rename = "stringValue")]
+    String(String),
+    // int64 is a string in OTLP JSON
+    #[serde(rename = "intValue")]
+    Int(String),
+    #[serde(rename = "boolValue")]
+    Bool(bool),
+}
+
+impl From<&str> for AnyValue {
+    fn from(v: &str) -> Self {
+        AnyValue::String(v.to_string())
+    }
+}
+
+impl From<bool> for AnyValue {
+    fn from(v: bool) -> Self {
+        AnyValue::Bool(v)
+    }
+}
+
+impl From<usize> for AnyValue {
+    fn from(v: usize) -> Self {
+        AnyValue::Int(v.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct Attribute {
+    key: &'static str,
+    value: AnyValue,
+}
+
+/// A span in progress; hand it to Tracer::end when the work is done
+pub struct Span {
+    context: SpanContext,
+    parent: Option<[u8; 8]>,
+    name: &'static str,
+    start: u64,
+    attributes: Vec<Attribute>,
+    error: Option<String>,
+}
+
+impl Span {
+    pub fn context(&self) -> SpanContext {
+        self.context
+    }
+
+    pub fn set(&mut self, key: &'static str, value: impl Into<AnyValue>) {
+        self.attributes.push(Attribute { key, value: value.into() });
+    }
+
+    pub fn set_error(&mut self, error: impl ToString) {
+        self.error = Some(error.to_string());
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpanData {
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_span_id: Option<String>,
+    name: &'static str,
+    // SPAN_KIND_INTERNAL
+    kind: u8,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<Attribute>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<Status>,
+}
+
+#[derive(Serialize)]
+struct Status {
+    // STATUS_CODE_ERROR
+    code: u8,
+    message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportRequest<'a> {
+    resource_spans: [ResourceSpans<'a>; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans<'a> {
+    resource: Resource<'a>,
+    scope_spans: [ScopeSpans<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct Resource<'a> {
+    attributes: &'a [Attribute],
+}
+
+#[derive(Serialize)]
+struct ScopeSpans<'a> {
+    scope: Scope,
+    spans: &'a [SpanData],
+}
+
+#[derive(Serialize)]
+struct Scope {
+    name: &'static str,
+}
+
+/// Records spans of sampled collection cycles and exports them to an
+/// OpenTelemetry collector as OTLP/HTTP JSON. Configured with the standard
+/// OTEL_* variables; without an endpoint there is no tracer.
+pub struct Tracer {
+    client: reqwest::Client,
+    url: String,
+    resource: Vec<Attribute>,
+    ratio: f64,
+    rng: SystemRandom,
+    finished: Mutex<Vec<SpanData>>,
+    last_export: Mutex<Instant>,
+}
+
+impl Tracer {
+    pub fn from_env(node_name: &str) -> Option<Self> {
+        let url = match env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+            Ok(url) if !url.is_empty() => url,
+            _ => {
+                let base = env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok().filter(|e| !e.is_empty())?;
+                format!("{}/v1/traces", base.trim_end_matches('/'))
+            }
+        };
+        let service = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "vita-agent".to_string());
+        // Share of cycles traced, as for the traceidratio sampler
+        let ratio = env::var("OTEL_TRACES_SAMPLER_ARG").ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(1.0)
+            .clamp(0.0, 1.0);
+
+        Some(Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+            url,
+            resource: vec![
+                Attribute { key: "service.name", value: service.as_str().into() },
+                Attribute { key: "service.version", value: env!("CARGO_PKG_VERSION").into() },
+                Attribute { key: "k8s.node.name", value: node_name.into() },
+            ],
+            ratio,
+            rng: SystemRandom::new(),
+            finished: Mutex::new(Vec::new()),
+            last_export: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.url
+    }
+
+    /// Starts the root span of a new trace, or None if it isn't sampled
+    pub fn start_trace(&self, name: &'static str) -> Option<Span> {
+        let mut trace_id = [0u8; 16];
+        self.rng.fill(&mut trace_id).ok()?;
+        let draw = u64::from_be_bytes(trace_id[8..].try_into().ok()?);
+        if draw as f64 >= self.ratio * u64::MAX as f64 {
+            return None;
+        }
+        Some(self.span(trace_id, None, name))
+    }
+
+    pub fn start_child(&self, parent: SpanContext, name: &'static str) -> Span {
+        self.span(parent.trace_id, Some(parent.span_id), name)
+    }
+
+    fn span(&self, trace_id: [u8; 16], parent: Option<[u8; 8]>, name: &'static str) -> Span {
+        let mut span_id = [0u8; 8];
+        // A failed draw leaves a zero id, which collectors reject; acceptable
+        let _ = self.rng.fill(&mut span_id);
+        Span {
+            context: SpanContext { trace_id, span_id },
+            parent,
+            name,
+            start: unix_nanos(),
+            attributes: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn end(&self, span: Span) {
+        let data = SpanData {
+            trace_id: hex(&span.context.trace_id),
+            span_id: hex(&span.context.span_id),
+            parent_span_id: span.parent.as_ref().map(|p| hex(p)),
+            name: span.name,
+            kind: 1,
+            start_time_unix_nano: span.start.to_string(),
+            end_time_unix_nano: unix_nanos().to_string(),
+            attributes: span.attributes,
+            status: span.error.map(|message| Status { code: 2, message }),
+        };
+        let mut finished = self.finished.lock().unwrap();
+        if finished.len() < MAX_BUFFERED {
+            finished.push(data);
+        }
+    }
+
+    /// Sends finished spans when enough time passed or enough piled up
+    pub async fn export_if_due(&self) {
+        let spans = {
+            let mut finished = self.finished.lock().unwrap();
+            let mut last = self.last_export.lock().unwrap();
+            if finished.is_empty() || (finished.len() < EXPORT_BATCH && last.elapsed() < EXPORT_EVERY) {
+                return;
+            }
+            *last = Instant::now();
+            std::mem::take(&mut *finished)
+        };
+
+        let body = ExportRequest {
+            resource_spans: [ResourceSpans {
+                resource: Resource { attributes: &self.resource },
+                scope_spans: [ScopeSpans { scope: Scope { name: "vita-agent" }, spans: &spans }],
+            }],
+        };
+        match self.client.post(&self.url).json(&body).send().await {
+            Ok(resp) if !resp.status().is_success() => {
+                tracing::warn!("Trace export failed: HTTP {}", resp.status());
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Trace export failed: {}", e),
+        }
+    }
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
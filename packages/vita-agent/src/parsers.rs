@@ -0,0 +1,626 @@
+//! Allocation-free parsers for the /proc and cgroup formats the collectors
+//! read. Each works on the borrowed file contents and yields plain values or
+//! iterators of borrowed records.
+
+/// Parses a counter field, treating anything malformed as zero
+fn num(field: Option<&str>) -> u64 {
+    field.and_then(|s| s.parse().ok()).unwrap_or(0)
+}
+
+/// Aggregate line of /proc/stat, in USER_HZ ticks
+pub struct CpuTimes {
+    pub user: u64,
+    pub system: u64,
+    pub idle: u64,
+    pub iowait: u64,
+}
+
+/// Finds the aggregate `cpu ` line of /proc/stat
+pub fn proc_stat_cpu(content: &str) -> Option<CpuTimes> {
+    let line = content.lines().find(|l| l.starts_with("cpu "))?;
+    // cpu user nice system idle iowait irq softirq steal guest guest_nice
+    let mut fields = line.split_ascii_whitespace().skip(1);
+    let user = fields.next()?;
+    let _nice = fields.next()?;
+    let system = fields.next()?;
+    let idle = fields.next()?;
+    // iowait is missing on very old kernels
+    let iowait = fields.next();
+    Some(CpuTimes {
+        user: num(Some(user)),
+        system: num(Some(system)),
+        idle: num(Some(idle)),
+        iowait: num(iowait),
+    })
+}
+
+/// Boot time from the `btime` line of /proc/stat, in unix seconds
+pub fn proc_stat_btime(content: &str) -> Option<u64> {
+    let line = content.lines().find(|l| l.starts_with("btime "))?;
+    line["btime ".len()..].trim().parse().ok()
+}
+
+/// Fields of /proc/meminfo the agent reports, in kB
+#[derive(Default)]
+pub struct MemInfo {
+    pub total: u64,
+    pub free: u64,
+    pub available: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+pub fn meminfo(content: &str) -> MemInfo {
+    let mut info = MemInfo::default();
+    for line in content.lines() {
+        let Some((key, rest)) = line.split_once(':') else { continue };
+        let slot = match key {
+            "MemTotal" => &mut info.total,
+            "MemFree" => &mut info.free,
+            "MemAvailable" => &mut info.available,
+            "SwapTotal" => &mut info.swap_total,
+            "SwapFree" => &mut info.swap_free,
+            _ => continue,
+        };
+        *slot = num(rest.split_ascii_whitespace().next());
+    }
+    info
+}
+
+/// One device line of /proc/diskstats
+pub struct DiskStats<'a> {
+    pub major: u32,
+    pub minor: u32,
+    pub name: &'a str,
+    pub reads: u64,
+    pub sectors_read: u64,
+    pub writes: u64,
+    pub sectors_written: u64,
+}
+
+/// Iterates /proc/diskstats. Kernels append fields over time (14, 18 and 20
+/// columns exist in the wild); only the original leading columns are read.
+pub fn diskstats(content: &str) -> impl Iterator<Item = DiskStats<'_>> {
+    content.lines().filter_map(|line| {
+        // major minor name reads_success reads_merged sectors_read time_read writes_success writes_merged sectors_written ...
+        let mut fields = line.split_ascii_whitespace();
+        let major = fields.next()?.parse().ok()?;
+        let minor = fields.next()?.parse().ok()?;
+        let name = fields.next()?;
+        let reads = fields.next();
+        let sectors_read = fields.nth(1);
+        let writes = fields.nth(1);
+        let sectors_written = fields.nth(1);
+        // Require the full original 14-column layout
+        fields.nth(3)?;
+        Some(DiskStats {
+            major,
+            minor,
+            name,
+            reads: num(reads),
+            sectors_read: num(sectors_read),
+            writes: num(writes),
+            sectors_written: num(sectors_written),
+        })
+    })
+}
+
+/// One interface line of /proc/net/dev
+pub struct NetDev<'a> {
+    pub name: &'a str,
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_errs: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_errs: u64,
+}
+
+/// Iterates /proc/net/dev, skipping the two header lines. The interface name
+/// is split on ':' since older kernels print wide counters with no space
+/// after it (`eth0:123456789 ...`).
+pub fn net_dev(content: &str) -> impl Iterator<Item = NetDev<'_>> {
+    content.lines().skip(2).filter_map(|line| {
+        let (name, rest) = line.split_once(':')?;
+        // rx_bytes packets errs drop fifo frame compressed multicast | tx_bytes packets errs ...
+        let mut fields = rest.split_ascii_whitespace();
+        let rx_bytes = fields.next();
+        let rx_packets = fields.next();
+        let rx_errs = fields.next();
+        let tx_bytes = fields.nth(5);
+        let tx_packets = fields.next();
+        let tx_errs = fields.next();
+        // All 16 counters must be present
+        fields.nth(4)?;
+        Some(NetDev {
+            name: name.trim(),
+            rx_bytes: num(rx_bytes),
+            rx_packets: num(rx_packets),
+            rx_errs: num(rx_errs),
+            tx_bytes: num(tx_bytes),
+            tx_packets: num(tx_packets),
+            tx_errs: num(tx_errs),
+        })
+    })
+}
+
+/// Looks up `key` in a flat-keyed cgroup file such as cpu.stat or memory.stat
+pub fn cgroup_key(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        if k == key { v.trim().parse().ok() } else { None }
+    })
+}
+
+/// Parses a single-value cgroup file (memory.current, cpuacct.usage, ...).
+/// Returns None for "max" and anything unparseable.
+pub fn cgroup_value(content: &str) -> Option<u64> {
+    content.trim().parse().ok()
+}
+
+/// utime + stime from /proc/<pid>/stat, in clock ticks. The comm field can
+/// contain spaces and parentheses, so fields are counted from the last ')'.
+pub fn proc_pid_cpu_ticks(content: &str) -> Option<u64> {
+    let rest = &content[content.rfind(')')? + 1..];
+    // state ppid pgrp session tty_nr tpgid flags minflt cminflt majflt cmajflt utime stime ...
+    let mut fields = rest.split_ascii_whitespace();
+    let utime = fields.nth(11)?;
+    let stime = fields.next()?;
+    Some(num(Some(utime)) + num(Some(stime)))
+}
+
+/// Resident set size from /proc/<pid>/statm, in pages
+pub fn statm_resident_pages(content: &str) -> Option<u64> {
+    content.split_ascii_whitespace().nth(1)?.parse().ok()
+}
+
+/// Kubernetes resource quantity ("10Gi", "500M", "1e9") in base units
+pub fn quantity(s: &str) -> Option<f64> {
+    const SUFFIXES: [(&str, f64); 12] = [
+        ("Ki", 1024.0), ("Mi", 1048576.0), ("Gi", 1073741824.0),
+        ("Ti", 1099511627776.0), ("Pi", 1125899906842624.0), ("Ei", 1152921504606846976.0),
+        ("k", 1e3), ("M", 1e6), ("G", 1e9), ("T", 1e12), ("P", 1e15), ("E", 1e18),
+    ];
+    let s = s.trim();
+    for (suffix, factor) in SUFFIXES {
+        if let Some(n) = s.strip_suffix(suffix) {
+            return n.parse::<f64>().ok().map(|n| n * factor);
+        }
+    }
+    if let Some(n) = s.strip_suffix('m') {
+        return n.parse::<f64>().ok().map(|n| n / 1e3);
+    }
+    s.parse().ok()
+}
+
+/// Parses a kernel CPU list such as cpuset.cpus.effective ("0-3,8,10-11").
+/// An empty list is valid and yields no CPUs.
+pub fn cpu_list(content: &str) -> Option<Vec<u32>> {
+    let mut cpus = Vec::new();
+    for range in content.trim().split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<u32>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Number of CPUs in a kernel CPU list, without collecting them
+pub fn cpu_count(content: &str) -> Option<u32> {
+    let mut count = 0;
+    for range in content.trim().split(',').filter(|r| !r.is_empty()) {
+        count += match range.split_once('-') {
+            Some((first, last)) => last.parse::<u32>().ok()?.checked_sub(first.parse().ok()?)? + 1,
+            None => range.parse::<u32>().map(|_| 1).ok()?,
+        };
+    }
+    Some(count)
+}
+
+/// One line of /proc/<pid>/mountinfo
+pub struct MountInfo<'a> {
+    pub mount_point: &'a str,
+    // Device number of the mounted filesystem, as in /proc/diskstats
+    pub major: u32,
+    pub minor: u32,
+    pub read_only: bool,
+    // Read by host mode, which Windows hasn't
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fs_type: &'a str,
+}
+
+/// Iterates /proc/<pid>/mountinfo. Mount points keep the kernel's octal
+/// escapes (`\040` for a space), which kubelet volume paths never contain.
+pub fn mountinfo(content: &str) -> impl Iterator<Item = MountInfo<'_>> {
+    content.lines().filter_map(|line| {
+        // id parent major:minor root mount_point options [optional...] - fs_type source ...
+        let (line, fs) = line.split_once(" - ")?;
+        let mut fields = line.split_ascii_whitespace().skip(2);
+        let (major, minor) = fields.next()?.split_once(':')?;
+        let mount_point = fields.nth(1)?;
+        let options = fields.next()?;
+        Some(MountInfo {
+            mount_point,
+            major: major.parse().ok()?,
+            minor: minor.parse().ok()?,
+            read_only: options.split(',').any(|o| o == "ro"),
+            fs_type: fs.split_ascii_whitespace().next()?,
+        })
+    })
+}
+
+/// Totals line of /proc/net/ip_vs_stats, since the IPVS module loaded
+#[derive(Default)]
+pub struct IpvsStats {
+    pub conns: u64,
+    pub in_packets: u64,
+    pub out_packets: u64,
+    pub in_bytes: u64,
+    pub out_bytes: u64,
+}
+
+/// Parses /proc/net/ip_vs_stats: two header lines, then the totals in hex
+/// (conns, packets in and out, bytes in and out), then the rates
+pub fn ip_vs_stats(content: &str) -> Option<IpvsStats> {
+    let mut fields = content.lines().nth(2)?.split_ascii_whitespace()
+        .map(|f| u64::from_str_radix(f, 16).ok());
+    Some(IpvsStats {
+        conns: fields.next()??,
+        in_packets: fields.next()??,
+        out_packets: fields.next()??,
+        in_bytes: fields.next()??,
+        out_bytes: fields.next()??,
+    })
+}
+
+/// One virtual service of /proc/net/ip_vs, summed over its real servers
+pub struct VirtualService<'a> {
+    // TCP, UDP, SCTP or FWM
+    pub protocol: &'a str,
+    // Hex address:port as the kernel prints it (0A600001:01BB), bracketed
+    // for IPv6, or the firewall mark
+    pub address: &'a str,
+    pub backends: u32,
+    pub active_conns: u64,
+    pub inactive_conns: u64,
+}
+
+/// Iterates /proc/net/ip_vs. Each service line is followed by its real
+/// servers, indented under `->`:
+/// `  -> AC120002:192B Masq 1 3 12` (address, forward, weight, active, inactive)
+pub fn ip_vs(content: &str) -> impl Iterator<Item = VirtualService<'_>> {
+    let mut lines = content.lines().skip(3).peekable();
+    std::iter::from_fn(move || {
+        let mut fields = lines.next()?.split_ascii_whitespace();
+        let mut service = VirtualService {
+            protocol: fields.next().unwrap_or_default(),
+            address: fields.next().unwrap_or_default(),
+            backends: 0,
+            active_conns: 0,
+            inactive_conns: 0,
+        };
+        while let Some(line) = lines.next_if(|l| l.trim_start().starts_with("->")) {
+            let mut fields = line.split_ascii_whitespace().skip(4);
+            service.backends += 1;
+            service.active_conns += num(fields.next());
+            service.inactive_conns += num(fields.next());
+        }
+        Some(service)
+    })
+}
+
+/// Entries of /proc/net/arp, after its header line: (complete, incomplete).
+/// Flags are ATF_COM (0x2) once the hardware address is resolved.
+pub fn arp_entries(content: &str) -> (u64, u64) {
+    content.lines().skip(1).fold((0, 0), |(complete, incomplete), line| {
+        let flags = line.split_ascii_whitespace().nth(2)
+            .and_then(|f| u64::from_str_radix(f.trim_start_matches("0x"), 16).ok());
+        match flags {
+            Some(flags) if flags & 0x2 != 0 => (complete + 1, incomplete),
+            Some(_) => (complete, incomplete + 1),
+            None => (complete, incomplete),
+        }
+    })
+}
+
+/// One CPU's line of /proc/net/softnet_stat
+pub struct Softnet {
+    pub cpu: u32,
+    pub processed: u64,
+    // Backlog queue full
+    pub dropped: u64,
+    // NAPI budget or time ran out with work left
+    pub time_squeeze: u64,
+    pub received_rps: u64,
+    pub flow_limit: u64,
+}
+
+/// Iterates /proc/net/softnet_stat: hex columns, one line per CPU that has
+/// been online. Kernels since 5.10 print the CPU as column 13; before that
+/// a line's position is its CPU, as long as none went offline.
+pub fn softnet_stat(content: &str) -> impl Iterator<Item = Softnet> + '_ {
+    content.lines().enumerate().filter_map(|(index, line)| {
+        let mut fields = [0u64; 13];
+        let mut count = 0;
+        for field in line.split_ascii_whitespace().take(fields.len()) {
+            fields[count] = u64::from_str_radix(field, 16).ok()?;
+            count += 1;
+        }
+        if count < 11 {
+            return None;
+        }
+        Some(Softnet {
+            cpu: if count > 12 { fields[12] as u32 } else { index as u32 },
+            processed: fields[0],
+            dropped: fields[1],
+            time_squeeze: fields[2],
+            received_rps: fields[9],
+            flow_limit: fields[10],
+        })
+    })
+}
+
+/// One array of /proc/mdstat
+pub struct MdArray<'a> {
+    pub name: &'a str,
+    pub active: bool,
+    // raid1, raid5, ...; empty for an inactive array
+    pub level: &'a str,
+    pub members: u32,
+    pub failed: u32,
+    pub spares: u32,
+    // From `[n/m]`; redundancy-less levels (raid0, linear) don't print it
+    pub disks: Option<u32>,
+    pub in_sync: Option<u32>,
+    // recovery, resync, reshape or check, with its progress in percent
+    pub sync: Option<(&'a str, f64)>,
+}
+
+/// Iterates /proc/mdstat. Each array starts at a line
+/// `md0 : active raid1 sdb1[1] sda1[0](F) sdc1[2](S)`, continued by
+/// indented lines holding `[2/1] [U_]` and the sync progress bar:
+/// `[=>....]  recovery =  8.5% (89088/1046528) finish=0.5min speed=29696K/sec`
+pub fn mdstat(content: &str) -> impl Iterator<Item = MdArray<'_>> {
+    let mut lines = content.lines().peekable();
+    std::iter::from_fn(move || loop {
+        let line = lines.next()?;
+        let Some((name, rest)) = line.split_once(" : ") else { continue };
+        if !name.starts_with("md") {
+            continue;
+        }
+        let mut fields = rest.split_ascii_whitespace().peekable();
+        let active = fields.next() == Some("active");
+        // "active (auto-read-only) raid1" on arrays not yet written to
+        if fields.peek().is_some_and(|f| f.starts_with('(')) {
+            fields.next();
+        }
+        let level = if active { fields.next().unwrap_or("") } else { "" };
+        let mut array = MdArray { name, active, level, members: 0, failed: 0, spares: 0, disks: None, in_sync: None, sync: None };
+        for member in fields {
+            array.members += 1;
+            if member.ends_with("(F)") {
+                array.failed += 1;
+            } else if member.ends_with("(S)") {
+                array.spares += 1;
+            }
+        }
+
+        while let Some(line) = lines.next_if(|l| l.starts_with(' ')) {
+            let mut fields = line.split_ascii_whitespace();
+            if let Some((disks, in_sync)) = line.split_ascii_whitespace()
+                .find_map(|f| f.strip_prefix('[')?.strip_suffix(']')?.split_once('/'))
+            {
+                array.disks = disks.parse().ok();
+                array.in_sync = in_sync.parse().ok();
+            }
+            while let Some(field) = fields.next() {
+                if matches!(field, "recovery" | "resync" | "reshape" | "check") {
+                    let percent = fields.nth(1).and_then(|p| p.strip_suffix('%')?.parse().ok());
+                    array.sync = percent.map(|p| (field, p));
+                    break;
+                }
+            }
+        }
+        return Some(array);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file captured from a node running the given kernel
+    macro_rules! fixture {
+        ($kernel:literal, $file:literal) => {
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc/linux-", $kernel, "/", $file))
+        };
+    }
+
+    #[test]
+    fn proc_stat_lines() {
+        let content = "cpu  10132153 290696 3084719 46828483 16683 0 25195 0 0 0\ncpu0 1393280 32966 572056 13343292 6130 0 17875 0 0 0\nbtime 1700000000\n";
+        let cpu = proc_stat_cpu(content).unwrap();
+        assert_eq!((cpu.user, cpu.system, cpu.idle, cpu.iowait), (10132153, 3084719, 46828483, 16683));
+        assert_eq!(proc_stat_btime(content), Some(1700000000));
+        // No iowait column
+        assert_eq!(proc_stat_cpu("cpu  1 2 3 4\n").unwrap().iowait, 0);
+    }
+
+    #[test]
+    fn meminfo_fields() {
+        let info = meminfo("MemTotal:       16310128 kB\nMemFree:          582880 kB\nMemAvailable:    9871632 kB\nSwapTotal:             0 kB\nSwapFree:              0 kB\n");
+        assert_eq!((info.total, info.free, info.available, info.swap_total), (16310128, 582880, 9871632, 0));
+    }
+
+    #[test]
+    fn diskstats_14_columns() {
+        let disks: Vec<_> = diskstats(fixture!("4.14", "diskstats")).collect();
+        assert_eq!(disks.len(), 4);
+        let sda = &disks[1];
+        assert_eq!((sda.major, sda.minor, sda.name), (8, 0, "sda"));
+        assert_eq!((sda.reads, sda.sectors_read, sda.writes, sda.sectors_written), (44555, 3113164, 19042, 1818864));
+    }
+
+    #[test]
+    fn diskstats_18_columns() {
+        let disks: Vec<_> = diskstats(fixture!("4.19", "diskstats")).collect();
+        assert_eq!(disks.len(), 3);
+        let nvme = &disks[2];
+        assert_eq!(nvme.name, "nvme1n1");
+        assert_eq!((nvme.reads, nvme.sectors_read, nvme.writes, nvme.sectors_written), (2697, 139094, 1315671, 85469296));
+    }
+
+    #[test]
+    fn diskstats_20_columns() {
+        let disks: Vec<_> = diskstats(fixture!("5.15", "diskstats")).collect();
+        assert_eq!(disks.iter().map(|d| d.name).collect::<Vec<_>>(), ["sda", "sdb", "dm-0"]);
+        let sdb = &disks[1];
+        assert_eq!((sdb.major, sdb.minor), (8, 16));
+        assert_eq!((sdb.reads, sdb.sectors_read, sdb.writes, sdb.sectors_written), (1201, 68622, 300, 21544));
+    }
+
+    #[test]
+    fn diskstats_skips_short_lines() {
+        // Partitions of 2.6 kernels had only 4 counters
+        assert_eq!(diskstats("   8    1 sda1 35486 2839134 757392 6059136\n").count(), 0);
+    }
+
+    #[test]
+    fn net_dev_without_space_after_name() {
+        let interfaces: Vec<_> = net_dev(fixture!("4.14", "net_dev")).collect();
+        assert_eq!(interfaces.iter().map(|i| i.name).collect::<Vec<_>>(), ["lo", "eth0", "docker0"]);
+        let eth0 = &interfaces[1];
+        assert_eq!((eth0.rx_bytes, eth0.rx_packets, eth0.rx_errs), (4294967296, 3371023, 2));
+        assert_eq!((eth0.tx_bytes, eth0.tx_packets, eth0.tx_errs), (1091826670, 1929097, 0));
+    }
+
+    #[test]
+    fn net_dev_wide_counters() {
+        let interfaces: Vec<_> = net_dev(fixture!("5.15", "net_dev")).collect();
+        assert_eq!(interfaces.len(), 3);
+        let ens5 = &interfaces[1];
+        assert_eq!(ens5.name, "ens5");
+        assert_eq!((ens5.rx_bytes, ens5.tx_bytes, ens5.tx_errs), (27451629381, 4367512839, 3));
+        assert_eq!(interfaces[2].name, "cali1a2b3c4d5e6");
+    }
+
+    #[test]
+    fn softnet_stat_before_5_10() {
+        let cpus: Vec<_> = softnet_stat(fixture!("4.19", "softnet_stat")).collect();
+        assert_eq!(cpus.len(), 2);
+        // CPU is the line's position
+        let cpu1 = &cpus[1];
+        assert_eq!((cpu1.cpu, cpu1.processed, cpu1.dropped, cpu1.time_squeeze), (1, 0x412e6f, 0x12, 1));
+        assert_eq!((cpu1.received_rps, cpu1.flow_limit), (0x10, 2));
+    }
+
+    #[test]
+    fn softnet_stat_since_5_10() {
+        // CPU 1 is offline, so positions and CPUs differ
+        let cpus: Vec<_> = softnet_stat(fixture!("5.15", "softnet_stat")).collect();
+        assert_eq!(cpus.iter().map(|c| c.cpu).collect::<Vec<_>>(), [0, 2, 3]);
+        assert_eq!((cpus[1].dropped, cpus[1].received_rps), (4, 0x2a));
+        assert_eq!((cpus[2].time_squeeze, cpus[2].flow_limit), (3, 1));
+    }
+
+    #[test]
+    fn mdstat_arrays() {
+        let arrays: Vec<_> = mdstat(fixture!("5.15", "mdstat")).collect();
+        assert_eq!(arrays.iter().map(|a| a.name).collect::<Vec<_>>(), ["md1", "md0", "md127", "md2"]);
+
+        let md1 = &arrays[0];
+        assert!(md1.active);
+        assert_eq!((md1.level, md1.members, md1.failed, md1.spares), ("raid1", 3, 1, 0));
+        assert_eq!((md1.disks, md1.in_sync), (Some(2), Some(1)));
+        assert_eq!(md1.sync, Some(("recovery", 8.5)));
+
+        let md0 = &arrays[1];
+        assert_eq!((md0.level, md0.members, md0.spares), ("raid5", 4, 1));
+        assert_eq!((md0.disks, md0.in_sync, md0.sync), (Some(3), Some(3), None));
+
+        let md127 = &arrays[2];
+        assert_eq!((md127.level, md127.members, md127.disks), ("raid0", 2, None));
+
+        let md2 = &arrays[3];
+        assert!(!md2.active);
+        assert_eq!((md2.level, md2.members, md2.spares), ("", 1, 1));
+    }
+
+    #[test]
+    fn mountinfo_optional_fields() {
+        let mounts: Vec<_> = mountinfo(fixture!("5.15", "mountinfo")).collect();
+        assert_eq!(mounts.len(), 6);
+        // Two optional fields before the separator
+        let volume = &mounts[3];
+        assert!(volume.mount_point.ends_with("/volumes/kubernetes.io~csi/pvc-0b7e/mount"));
+        assert_eq!((volume.major, volume.minor, volume.read_only, volume.fs_type), (8, 16, false, "ext4"));
+        // None
+        let backup = &mounts[4];
+        assert_eq!((backup.mount_point, backup.read_only, backup.fs_type), ("/mnt/backup", true, "xfs"));
+        assert_eq!(mounts[5].fs_type, "overlay");
+    }
+
+    #[test]
+    fn ip_vs_services() {
+        let services: Vec<_> = ip_vs(fixture!("5.15", "ip_vs")).collect();
+        assert_eq!(services.iter().map(|s| s.protocol).collect::<Vec<_>>(), ["TCP", "UDP", "TCP", "FWM"]);
+
+        let https = &services[0];
+        assert_eq!(https.address, "0A600001:01BB");
+        assert_eq!((https.backends, https.active_conns, https.inactive_conns), (2, 5, 17));
+        // No real servers
+        assert_eq!(services[1].backends, 0);
+        assert_eq!(services[2].address, "[fd00:0000:0000:0000:0000:0000:0000:0001]:01BB");
+        assert_eq!((services[2].backends, services[2].active_conns), (1, 1));
+        assert_eq!((services[3].address, services[3].inactive_conns), ("00000001", 1));
+    }
+
+    #[test]
+    fn ip_vs_stats_totals() {
+        let stats = ip_vs_stats(fixture!("5.15", "ip_vs_stats")).unwrap();
+        assert_eq!((stats.conns, stats.in_packets, stats.out_packets), (0x1f4, 0x2a3b, 0));
+        assert_eq!((stats.in_bytes, stats.out_bytes), (0x1c9e4f, 0));
+    }
+
+    #[test]
+    fn arp_flags() {
+        let content = "IP address       HW type     Flags       HW address            Mask     Device\n\
+            10.0.0.1         0x1         0x2         0a:58:0a:00:00:01     *        eth0\n\
+            10.0.0.9         0x1         0x0         00:00:00:00:00:00     *        eth0\n";
+        assert_eq!(arp_entries(content), (1, 1));
+    }
+
+    #[test]
+    fn cgroup_files() {
+        assert_eq!(cgroup_key("usage_usec 8120814\nuser_usec 5310017\nsystem_usec 2810797\n", "user_usec"), Some(5310017));
+        assert_eq!(cgroup_key("usage_usec 8120814\n", "nr_throttled"), None);
+        assert_eq!(cgroup_value("134217728\n"), Some(134217728));
+        assert_eq!(cgroup_value("max\n"), None);
+    }
+
+    #[test]
+    fn proc_pid_files() {
+        // comm with a space and a parenthesis
+        let stat = "4321 (kube (proxy) 1) S 1 4321 4321 0 -1 4194560 15592 0 0 0 120 45 0 0 20 0 9 0 1710 ...";
+        assert_eq!(proc_pid_cpu_ticks(stat), Some(165));
+        assert_eq!(statm_resident_pages("189250 11497 6138 2 0 30385 0\n"), Some(11497));
+    }
+
+    #[test]
+    fn quantities() {
+        assert_eq!(quantity("10Gi"), Some(10.0 * 1073741824.0));
+        assert_eq!(quantity("500M"), Some(5e8));
+        assert_eq!(quantity("250m"), Some(0.25));
+        assert_eq!(quantity("1e9"), Some(1e9));
+        assert_eq!(quantity("ten"), None);
+    }
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(cpu_list("0-3,8,10-11\n"), Some(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert_eq!(cpu_list("\n"), Some(vec![]));
+        assert_eq!(cpu_count("0-3,8,10-11\n"), Some(7));
+        assert_eq!(cpu_count("3-1"), None);
+    }
+}
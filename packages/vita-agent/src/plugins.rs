@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use wasmtime::{Caller, Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// Prefix every metric type a plugin reports must carry, so plugins can't
+/// pass for the agent's own collectors
+const TYPE_PREFIX: &str = "plugin_";
+
+/// Metrics one `collect` call may report; the rest are dropped
+const MAX_METRICS: usize = 1000;
+
+/// Largest file a plugin may read in one call
+const MAX_READ: u64 = 1024 * 1024;
+
+/// Failed calls in a row after which a plugin is unloaded
+const MAX_FAILURES: u32 = 5;
+
+/// What a plugin can reach from its calls into the agent
+struct HostState {
+    name: Arc<str>,
+    limits: StoreLimits,
+    // Directories files may be read under, as configured and with
+    // symlinks resolved
+    read_paths: Arc<[PathBuf]>,
+    metrics: Vec<(String, Option<String>, String, f64)>,
+    dropped: usize,
+}
+
+struct Plugin {
+    name: Arc<str>,
+    store: Store<HostState>,
+    collect: TypedFunc<(), i32>,
+    failures: u32,
+}
+
+/// Collectors loaded from WASM modules, so a site can read what it needs
+/// without patching the agent. Each module runs in a sandbox of its own,
+/// with a memory cap, an instruction budget per call and read-only access
+/// to the files under PLUGIN_READ_PATHS; it has no network, no clock and
+/// nothing to write to but the metrics it hands back.
+///
+/// A module exports `memory` and `collect() -> i32`, called every interval
+/// (0 for success), and optionally `init() -> i32`, called once on load.
+/// It imports from `vita`:
+/// - `metric(type, type_len, device, device_len, key, key_len, value: f64) -> i32`
+/// - `read_file(path, path_len, buf, buf_len) -> i64`: bytes read into
+///   buf, or -1 when the path isn't readable by plugins, -2 when it doesn't
+///   exist and -3 on other errors
+/// - `log(msg, msg_len)`
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+    fuel: u64,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Plugins {
+    /// Loads every .wasm (or .wat) module in PLUGIN_DIR, sandboxed to
+    /// PLUGIN_MEMORY_MB (16) and PLUGIN_FUEL instructions a call (100M).
+    /// None without a directory or when no module loads.
+    pub fn from_env(interval: Duration) -> Option<Self> {
+        let dir = env::var("PLUGIN_DIR").ok().filter(|d| !d.is_empty())?;
+        let memory_mb: usize = env::var("PLUGIN_MEMORY_MB").ok().and_then(|v| v.parse().ok()).unwrap_or(16);
+        let fuel = env::var("PLUGIN_FUEL").ok().and_then(|v| v.parse().ok()).unwrap_or(100_000_000);
+        let mut read_paths: Vec<PathBuf> = env::var("PLUGIN_READ_PATHS").unwrap_or_else(|_| "/proc,/sys".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .flat_map(|p| match host::path(p).canonicalize() {
+                Ok(real) => vec![host::path(p), real],
+                Err(e) => {
+                    warn!("Plugin read path {} unavailable: {}", p, e);
+                    Vec::new()
+                }
+            })
+            .collect();
+        read_paths.dedup();
+        let read_paths: Arc<[PathBuf]> = read_paths.into();
+
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = match Engine::new(&config) {
+            Ok(engine) => engine,
+            Err(e) => {
+                warn!("⚠️  Plugins disabled, cannot start the WASM engine: {}", e);
+                return None;
+            }
+        };
+
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|e| Some(e.ok()?.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "wasm" || ext == "wat"))
+                .collect(),
+            Err(e) => {
+                warn!("⚠️  Plugins disabled, cannot read {}: {}", dir, e);
+                return None;
+            }
+        };
+        paths.sort();
+
+        let plugins: Vec<Plugin> = paths.iter()
+            .filter_map(|path| match load(&engine, path, memory_mb, fuel, read_paths.clone()) {
+                Ok(plugin) => {
+                    info!("Plugin {} loaded from {}", plugin.name, path.display());
+                    Some(plugin)
+                }
+                Err(e) => {
+                    warn!("⚠️  Plugin {} not loaded: {:#}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        if plugins.is_empty() {
+            return None;
+        }
+        info!("Plugins enabled | count={} interval={:?} memory={}MB fuel={} read_paths={:?}",
+            plugins.len(), interval, memory_mb, fuel, read_paths);
+        Some(Self { plugins, fuel, interval, last: None })
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    /// Runs every plugin's `collect` and queues what it reported. A plugin
+    /// failing only costs its own metrics, until it has failed too often.
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        self.last = Some(Instant::now());
+        let fuel = self.fuel;
+        self.plugins.retain_mut(|plugin| {
+            let started = Instant::now();
+            let result = plugin.store.set_fuel(fuel)
+                .and_then(|_| plugin.collect.call(&mut plugin.store, ()));
+            let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+            let used = fuel - plugin.store.get_fuel().unwrap_or(0);
+
+            let state = plugin.store.data_mut();
+            let metrics = std::mem::take(&mut state.metrics);
+            if state.dropped > 0 {
+                warn!("Plugin {} reported over {} metrics, dropped {}", plugin.name, MAX_METRICS, state.dropped);
+                state.dropped = 0;
+            }
+            let ok = match result {
+                Ok(0) => true,
+                Ok(code) => {
+                    warn!("⚠️  Plugin {} failed with {}", plugin.name, code);
+                    false
+                }
+                Err(e) => {
+                    warn!("⚠️  Plugin {} trapped: {}", plugin.name, e.root_cause());
+                    false
+                }
+            };
+
+            // What a failed call reported may be half done
+            let count = if ok { metrics.len() } else { 0 };
+            if ok {
+                report(node_name, metrics, sender);
+            }
+            info!("METRIC_TYPE=agent_plugin node={} device={} ok={} duration_ms={:.2} metrics={} fuel={}",
+                node_name, plugin.name, ok as u8, duration_ms, count, used);
+            let labels = Labels { device: Some(&plugin.name), ..Default::default() };
+            sender.add("agent_plugin", &labels, "ok", ok as u8 as f64);
+            sender.add("agent_plugin", &labels, "duration_ms", duration_ms);
+            sender.add("agent_plugin", &labels, "metrics", count as f64);
+            sender.add("agent_plugin", &labels, "fuel", used as f64);
+
+            plugin.failures = if ok { 0 } else { plugin.failures + 1 };
+            if plugin.failures >= MAX_FAILURES {
+                warn!("⚠️  Plugin {} failed {} times in a row, unloading it", plugin.name, MAX_FAILURES);
+                return false;
+            }
+            true
+        });
+    }
+}
+
+fn load(engine: &Engine, path: &Path, memory_mb: usize, fuel: u64, read_paths: Arc<[PathBuf]>) -> anyhow::Result<Plugin> {
+    let name: Arc<str> = path.file_stem().map_or_else(|| "plugin".into(), |s| s.to_string_lossy().into());
+    let module = Module::from_file(engine, path)?;
+
+    let mut linker: Linker<HostState> = Linker::new(engine);
+    linker.func_wrap("vita", "metric", host_metric)?;
+    linker.func_wrap("vita", "read_file", host_read_file)?;
+    linker.func_wrap("vita", "log", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+        if let Some(msg) = guest_str(&mut caller, ptr, len) {
+            info!("Plugin {}: {}", caller.data().name, msg);
+        }
+    })?;
+
+    let state = HostState {
+        name: name.clone(),
+        limits: StoreLimitsBuilder::new().memory_size(memory_mb * 1024 * 1024).instances(1).build(),
+        read_paths,
+        metrics: Vec::new(),
+        dropped: 0,
+    };
+    let mut store = Store::new(engine, state);
+    store.limiter(|state| &mut state.limits);
+    store.set_fuel(fuel)?;
+    let instance = linker.instantiate(&mut store, &module)?;
+    let collect = instance.get_typed_func::<(), i32>(&mut store, "collect")?;
+
+    if let Ok(init) = instance.get_typed_func::<(), i32>(&mut store, "init") {
+        match init.call(&mut store, ())? {
+            0 => {}
+            code => anyhow::bail!("init failed with {}", code),
+        }
+        // Whatever init reported has no cycle to go out with
+        store.data_mut().metrics.clear();
+    }
+    Ok(Plugin { name, store, collect, failures: 0 })
+}
+
+/// Queues a plugin's metrics, logging one line per series
+fn report(node_name: &str, metrics: Vec<(String, Option<String>, String, f64)>, sender: &mut MetricsSender) {
+    let mut series: BTreeMap<(&str, Option<&str>), String> = BTreeMap::new();
+    for (metric_type, device, key, value) in &metrics {
+        let labels = Labels { device: device.as_deref(), ..Default::default() };
+        sender.add(metric_type, &labels, key, *value);
+        let line = series.entry((metric_type, device.as_deref())).or_default();
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    for ((metric_type, device), values) in series {
+        match device {
+            Some(device) => info!("METRIC_TYPE={} node={} device={}{}", metric_type, node_name, device, values),
+            None => info!("METRIC_TYPE={} node={}{}", metric_type, node_name, values),
+        }
+    }
+}
+
+/// `vita.metric`: 0 when queued, -1 for a bad name or over the limit
+#[allow(clippy::too_many_arguments)]
+fn host_metric(
+    mut caller: Caller<'_, HostState>,
+    type_ptr: i32,
+    type_len: i32,
+    device_ptr: i32,
+    device_len: i32,
+    key_ptr: i32,
+    key_len: i32,
+    value: f64,
+) -> i32 {
+    let (Some(metric_type), Some(device), Some(key)) = (
+        guest_str(&mut caller, type_ptr, type_len),
+        guest_str(&mut caller, device_ptr, device_len),
+        guest_str(&mut caller, key_ptr, key_len),
+    ) else {
+        return -1;
+    };
+    let name_ok = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_');
+    if !metric_type.starts_with(TYPE_PREFIX) || !name_ok(&metric_type) || !name_ok(&key) || !value.is_finite() {
+        debug!("Plugin {} reported an invalid metric {}.{}", caller.data().name, metric_type, key);
+        return -1;
+    }
+    let state = caller.data_mut();
+    if state.metrics.len() >= MAX_METRICS {
+        state.dropped += 1;
+        return -1;
+    }
+    let device = (!device.is_empty()).then_some(device);
+    state.metrics.push((metric_type, device, key, value));
+    0
+}
+
+/// `vita.read_file`
+fn host_read_file(mut caller: Caller<'_, HostState>, path_ptr: i32, path_len: i32, buf_ptr: i32, buf_len: i32) -> i64 {
+    let Some(path) = guest_str(&mut caller, path_ptr, path_len) else { return -3 };
+    let real = match readable(&caller.data().read_paths, &path) {
+        Ok(real) => real,
+        Err(code) => {
+            debug!("Plugin {} cannot read {}: {}", caller.data().name, path, code);
+            return code;
+        }
+    };
+    let mut contents = Vec::new();
+    let result = File::open(&real)
+        .and_then(|file| file.take(MAX_READ.min(buf_len.max(0) as u64)).read_to_end(&mut contents));
+    if let Err(e) = result {
+        return if e.kind() == std::io::ErrorKind::NotFound { -2 } else { -3 };
+    }
+    let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory()) else { return -3 };
+    match memory.data_mut(&mut caller).get_mut(buf_ptr as u32 as usize..).and_then(|m| m.get_mut(..contents.len())) {
+        Some(buf) => {
+            buf.copy_from_slice(&contents);
+            contents.len() as i64
+        }
+        None => -3,
+    }
+}
+
+/// Where `path` is read from, if it is a regular file under one of the
+/// allowed directories both as named and once symlinks are resolved;
+/// otherwise the code `read_file` returns
+fn readable(read_paths: &[PathBuf], path: &str) -> Result<PathBuf, i64> {
+    let requested = host::path(path);
+    if !Path::new(path).is_absolute() || requested.components().any(|c| c == Component::ParentDir)
+        || !read_paths.iter().any(|root| requested.starts_with(root)) {
+        return Err(-1);
+    }
+    let real = requested.canonicalize()
+        .map_err(|e| if e.kind() == std::io::ErrorKind::NotFound { -2 } else { -3 })?;
+    // FIFOs and devices could block the loop or be written to by reading
+    let regular = real.metadata().is_ok_and(|m| m.is_file());
+    if !regular || !read_paths.iter().any(|root| real.starts_with(root)) {
+        return Err(-1);
+    }
+    Ok(real)
+}
+
+/// The UTF-8 string at `ptr` in the guest's memory
+fn guest_str(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller.get_export("memory")?.into_memory()?;
+    let bytes = memory.data(&*caller).get(ptr as u32 as usize..)?.get(..len as u32 as usize)?;
+    std::str::from_utf8(bytes).ok().map(String::from)
+}
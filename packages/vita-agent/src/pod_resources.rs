@@ -0,0 +1,187 @@
+use anyhow::Context;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::net::UnixStream;
+use tracing::{debug, info};
+
+use crate::errors::{CollectorError, Result};
+use crate::grpc::{self, Fields, Value};
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::pod_roles::PodRoles;
+
+const SOCKET_PATH: &str = "/var/lib/kubelet/pod-resources/kubelet.sock";
+
+/// Longest wait for the kubelet, connection included
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the kubelet assigned to one pod, summed over its containers
+#[derive(Default)]
+struct Assigned {
+    // Exclusive CPUs only: shared-pool containers list none
+    cpus: BTreeSet<u64>,
+    // Device count by resource name
+    devices: BTreeMap<String, u64>,
+    // Bytes by memory type (memory, hugepages-2Mi, ...), memory manager only
+    memory: BTreeMap<String, u64>,
+    numa_nodes: BTreeSet<u64>,
+}
+
+/// Per-pod CPU, device and memory assignments from the kubelet's
+/// pod-resources API, with NUMA placement. The cgroup tree only shows the
+/// cpuset a container ended up with; this is what the kubelet's managers
+/// handed out, and what the node can still hand out.
+pub struct PodResourcesCollector {
+    socket: PathBuf,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl PodResourcesCollector {
+    /// None if the kubelet doesn't serve the socket here
+    pub fn new(interval: Duration) -> Option<Self> {
+        let socket = host::path(SOCKET_PATH);
+        socket.exists().then_some(Self { socket, interval, last: None })
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    /// The API names pods by namespace and name; `roles` turns those into
+    /// UIDs, and pods it doesn't know yet are skipped until the next call
+    pub async fn collect(&mut self, node_name: &str, roles: Option<&PodRoles>, sender: &mut MetricsSender) -> Result<()> {
+        self.last = Some(Instant::now());
+
+        let list = self.call("/v1.PodResourcesLister/List").await?;
+        // ListPodResourcesResponse: repeated PodResources pod_resources = 1
+        for field in Fields(&list) {
+            let (1, Value::Bytes(pod)) = field? else { continue };
+            let (namespace, name, assigned) = decode_pod(pod)?;
+            let Some(uid) = roles.and_then(|r| r.uid(&namespace, &name)) else {
+                debug!("Pod resources: no UID yet for {}/{}", namespace, name);
+                continue;
+            };
+            if assigned.cpus.is_empty() && assigned.devices.is_empty() && assigned.memory.is_empty() {
+                continue;
+            }
+
+            info!("METRIC_TYPE=pod_resources node={} pod_uid={} exclusive_cpus={} devices={:?} memory={:?} numa_nodes={:?}",
+                node_name, uid, assigned.cpus.len(), assigned.devices, assigned.memory, assigned.numa_nodes);
+
+            let labels = Labels { pod_uid: Some(uid), ..Default::default() };
+            report(&assigned, "pod_resources", &labels, sender);
+            sender.add("pod_resources", &labels, "numa_nodes", assigned.numa_nodes.len() as f64);
+        }
+
+        // AllocatableResourcesResponse: devices = 1, cpu_ids = 2, memory = 3,
+        // the same fields as a container's under other numbers
+        let allocatable = self.call("/v1.PodResourcesLister/GetAllocatableResources").await?;
+        let mut node = Assigned::default();
+        decode_resources(&allocatable, [1, 2, 3], &mut node)?;
+        info!("METRIC_TYPE=node_pod_resources node={} cpus={} devices={:?} memory={:?}",
+            node_name, node.cpus.len(), node.devices, node.memory);
+        report(&node, "node_pod_resources", &Labels::default(), sender);
+
+        Ok(())
+    }
+
+    async fn call(&self, method: &str) -> Result<Vec<u8>> {
+        let call = async {
+            let io = UnixStream::connect(&self.socket).await
+                .with_context(|| format!("connecting to {}", self.socket.display()))?;
+            grpc::call(io, method, &[]).await
+        };
+        match tokio::time::timeout(TIMEOUT, call).await {
+            Ok(reply) => Ok(reply?),
+            Err(_) => Err(CollectorError::Timeout(format!("calling {} on the kubelet", method))),
+        }
+    }
+}
+
+fn report(assigned: &Assigned, metric_type: &str, labels: &Labels, sender: &mut MetricsSender) {
+    sender.add(metric_type, labels, "cpus", assigned.cpus.len() as f64);
+    for (resource, count) in &assigned.devices {
+        sender.add(metric_type, labels, resource, *count as f64);
+    }
+    for (memory_type, bytes) in &assigned.memory {
+        sender.add(metric_type, labels, &format!("{}_mb", memory_type), (bytes / 1024 / 1024) as f64);
+    }
+}
+
+/// PodResources: name = 1, namespace = 2, repeated ContainerResources containers = 3
+fn decode_pod(buf: &[u8]) -> anyhow::Result<(String, String, Assigned)> {
+    let (mut name, mut namespace) = (String::new(), String::new());
+    let mut assigned = Assigned::default();
+    for field in Fields(buf) {
+        match field? {
+            (1, Value::Bytes(v)) => name = String::from_utf8_lossy(v).into_owned(),
+            (2, Value::Bytes(v)) => namespace = String::from_utf8_lossy(v).into_owned(),
+            // ContainerResources: name = 1, devices = 2, cpu_ids = 3, memory = 4
+            (3, Value::Bytes(container)) => decode_resources(container, [2, 3, 4], &mut assigned)?,
+            _ => {}
+        }
+    }
+    Ok((namespace, name, assigned))
+}
+
+/// Adds the devices, CPU IDs and memory under field numbers `fields`
+fn decode_resources(buf: &[u8], [devices, cpu_ids, memory]: [u64; 3], into: &mut Assigned) -> anyhow::Result<()> {
+    for field in Fields(buf) {
+        match field? {
+            (n, Value::Bytes(d)) if n == devices => {
+                // ContainerDevices: resource_name = 1, repeated device_ids = 2, topology = 3
+                let mut resource = String::new();
+                let mut count = 0;
+                for field in Fields(d) {
+                    match field? {
+                        (1, Value::Bytes(v)) => resource = String::from_utf8_lossy(v).into_owned(),
+                        (2, Value::Bytes(_)) => count += 1,
+                        (3, Value::Bytes(t)) => decode_topology(t, &mut into.numa_nodes)?,
+                        _ => {}
+                    }
+                }
+                *into.devices.entry(resource).or_default() += count;
+            }
+            // Packed, though a sender may also repeat the field unpacked
+            (n, Value::Bytes(ids)) if n == cpu_ids => {
+                for id in grpc::packed(ids) {
+                    into.cpus.insert(id?);
+                }
+            }
+            (n, Value::Varint(id)) if n == cpu_ids => {
+                into.cpus.insert(id);
+            }
+            (n, Value::Bytes(m)) if n == memory => {
+                // ContainerMemory: memory_type = 1, size = 2, topology = 3
+                let (mut memory_type, mut size) = (String::new(), 0);
+                for field in Fields(m) {
+                    match field? {
+                        (1, Value::Bytes(v)) => memory_type = String::from_utf8_lossy(v).into_owned(),
+                        (2, Value::Varint(v)) => size = v,
+                        (3, Value::Bytes(t)) => decode_topology(t, &mut into.numa_nodes)?,
+                        _ => {}
+                    }
+                }
+                *into.memory.entry(memory_type).or_default() += size;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// TopologyInfo: repeated NUMANode nodes = 1, each with ID = 1
+fn decode_topology(buf: &[u8], into: &mut BTreeSet<u64>) -> anyhow::Result<()> {
+    for field in Fields(buf) {
+        if let (1, Value::Bytes(node)) = field? {
+            for field in Fields(node) {
+                if let (1, Value::Varint(id)) = field? {
+                    into.insert(id);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{Api, ListParams};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::errors::Result;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// What a container is for within its pod
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerRole {
+    App,
+    Init,
+    /// Init container with restartPolicy Always, running beside the app
+    Sidecar,
+    /// The sandbox holding the pod's namespaces
+    Pause,
+}
+
+impl ContainerRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContainerRole::App => "app",
+            ContainerRole::Init => "init",
+            ContainerRole::Sidecar => "sidecar",
+            ContainerRole::Pause => "pause",
+        }
+    }
+}
+
+/// Roles of the containers on this node, from the container IDs in the
+/// status of its pods. The sandbox is never listed there, so a container
+/// of a known pod that matches none of them is its pause container, unless
+/// it appeared after the list and is just too new for it.
+pub struct PodRoles {
+    api: Api<Pod>,
+    node_name: String,
+    interval: Duration,
+    last: Option<Instant>,
+    // Runtime container ID, without the containerd:// prefix
+    containers: HashMap<String, ContainerRole>,
+    pods: HashSet<String>,
+    // UID by (namespace, name), for APIs that name pods
+    uids: HashMap<(String, String), String>,
+    include_pause: bool,
+}
+
+impl PodRoles {
+    /// Pause containers are left out of container metrics unless
+    /// INCLUDE_PAUSE_CONTAINERS is set
+    pub fn new(client: kube::Client, node_name: String, interval: Duration) -> Self {
+        let include_pause = std::env::var("INCLUDE_PAUSE_CONTAINERS").is_ok_and(|v| v == "true" || v == "1");
+        Self {
+            api: Api::all(client),
+            node_name,
+            interval,
+            last: None,
+            containers: HashMap::new(),
+            pods: HashSet::new(),
+            uids: HashMap::new(),
+            include_pause,
+        }
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    pub async fn refresh(&mut self) -> Result<()> {
+        self.last = Some(Instant::now());
+
+        let params = ListParams::default().fields(&format!("spec.nodeName={}", self.node_name));
+        let pods = self.api.list(&params).await?;
+
+        self.containers.clear();
+        self.pods.clear();
+        self.uids.clear();
+        for pod in pods.items {
+            let (Some(uid), Some(status)) = (pod.metadata.uid, pod.status) else { continue };
+            if let (Some(namespace), Some(name)) = (pod.metadata.namespace, pod.metadata.name) {
+                self.uids.insert((namespace, name), uid.clone());
+            }
+            let sidecars: HashSet<String> = pod.spec.iter()
+                .flat_map(|spec| spec.init_containers.iter().flatten())
+                .filter(|c| c.restart_policy.as_deref() == Some("Always"))
+                .map(|c| c.name.clone())
+                .collect();
+
+            let init = status.init_container_statuses.into_iter().flatten().map(|s| {
+                let role = if sidecars.contains(&s.name) { ContainerRole::Sidecar } else { ContainerRole::Init };
+                (s, role)
+            });
+            let app = status.container_statuses.into_iter().flatten()
+                .chain(status.ephemeral_container_statuses.into_iter().flatten())
+                .map(|s| (s, ContainerRole::App));
+            for (s, role) in init.chain(app) {
+                if let Some(id) = s.container_id.as_deref().and_then(|id| id.split_once("://")) {
+                    self.containers.insert(id.1.to_string(), role);
+                }
+            }
+            self.pods.insert(uid);
+        }
+        debug!("Container roles: {} containers in {} pods", self.containers.len(), self.pods.len());
+        Ok(())
+    }
+
+    /// Role of a container by runtime ID, first seen at `seen`; None if it
+    /// is newer than the last list. The pod UID is only needed for
+    /// containers not listed themselves.
+    pub fn role(&self, container_id: &str, seen: Instant, pod_uid: impl FnOnce() -> Option<String>) -> Option<ContainerRole> {
+        if let Some(&role) = self.containers.get(container_id) {
+            return Some(role);
+        }
+        let listed = self.last.is_some_and(|t| seen <= t);
+        (listed && pod_uid().is_some_and(|uid| self.pods.contains(&uid))).then_some(ContainerRole::Pause)
+    }
+
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn uid(&self, namespace: &str, name: &str) -> Option<&str> {
+        self.uids.get(&(namespace.to_string(), name.to_string())).map(String::as_str)
+    }
+
+    /// Whether a container with this role is reported at all
+    pub fn reported(&self, role: Option<ContainerRole>) -> bool {
+        self.include_pause || role != Some(ContainerRole::Pause)
+    }
+}
+
+/// Queues the role of one container as a series of its own, keyed by role
+pub fn report(role: ContainerRole, labels: &Labels, sender: &mut MetricsSender) {
+    sender.add("container_role", labels, role.as_str(), 1.0);
+}
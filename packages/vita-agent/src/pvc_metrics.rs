@@ -1,43 +1,303 @@
-use anyhow::Result;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
-use tracing::{info, warn};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
 use std::ffi::CString;
 
-pub fn collect_pvc_metrics(node_name: &str, _sender: &mut crate::metrics_sender::MetricsSender) -> Result<()> {
-    let pods_dir = Path::new("/var/lib/kubelet/pods");
-    if !pods_dir.exists() {
-        // debug!("PVC Metrics: /var/lib/kubelet/pods does not exist");
-        return Ok(());
+use crate::deep_usage::{DeepUsage, Scan};
+use crate::host;
+use crate::inotify::DirWatcher;
+use crate::errors::Result;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::parsers;
+use crate::project_quota;
+use crate::statfile::StatFile;
+use crate::volume_claims::{Claim, VolumeClaims};
+
+/// Re-enumerate pod volumes at least this often, even without inotify events
+const REFRESH_EVERY: u32 = 30;
+
+/// Mount table of the host's init. /proc is the host's, so unlike our own
+/// it includes volumes mounted after the agent started.
+const HOST_MOUNTS: &str = "/proc/1/mountinfo";
+
+/// Each probed volume is written to this often; a probe still running after
+/// PROBE_TIMEOUT counts as failed until it returns
+const PROBE_EVERY: Duration = Duration::from_secs(60);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Created and removed again by a write probe
+const PROBE_FILE: &str = ".vita-agent-probe";
+
+/// Share of a claim's size that filesystem metadata and reserved blocks may
+/// take before the volume counts as undersized
+const FS_OVERHEAD: f64 = 0.1;
+
+/// A volume mountpoint found under a pod directory
+struct VolumeTarget {
+    mount_point: CString,
+    // The mountpoint as the host's mount table names it
+    host_path: String,
+    pod_uid: String,
+    vol_name: String,
+}
+
+/// Write probe of one volume, on a thread of its own so that a hung mount
+/// stalls the probe and not collection
+struct Probe {
+    started: Instant,
+    pending: Option<mpsc::Receiver<io::Result<Duration>>>,
+    // Whether the last finished probe could write, and how long it took
+    last: Option<(bool, Duration)>,
+}
+
+/// Collects per-volume usage for pods on this node. Pod and volume
+/// directories are enumerated once and kept; only the known mountpoints are
+/// statvfs'd each cycle. The set is rebuilt when inotify reports pods or
+/// volumes being added/removed, when a cached mountpoint disappears, or
+/// every REFRESH_EVERY cycles.
+///
+/// With a scan budget, statting stops once a cycle has spent it and the
+/// next cycle carries on from there, so thousands of volumes are covered
+/// over several cycles instead of holding up the others.
+pub struct VolumeCollector {
+    targets: Vec<VolumeTarget>,
+    watcher: Option<DirWatcher>,
+    cycles_since_refresh: u32,
+    dirty: bool,
+    budget: Option<Duration>,
+    // Target the next scan starts at
+    next: usize,
+    mounts: StatFile,
+    mounts_buf: Vec<u8>,
+    diskstats: StatFile,
+    diskstats_buf: Vec<u8>,
+    // Volume names to write-probe, "*" for all
+    probe_volumes: Vec<String>,
+    probes: HashMap<CString, Probe>,
+    // Cleared when the kernel or our privileges rule project quotas out
+    quotas: bool,
+    deep: Option<DeepUsage>,
+    // Pod volume mountpoints in the host's mount table last cycle; None
+    // until it was first read
+    mounted: Option<HashSet<String>>,
+}
+
+impl VolumeCollector {
+    pub fn new() -> Self {
+        Self {
+            targets: Vec::new(),
+            watcher: None,
+            cycles_since_refresh: 0,
+            dirty: true,
+            budget: None,
+            next: 0,
+            mounts: StatFile::new(if host::path(HOST_MOUNTS).exists() {
+                host::path(HOST_MOUNTS)
+            } else {
+                host::path("/proc/self/mountinfo")
+            }),
+            mounts_buf: Vec::new(),
+            diskstats: StatFile::new(host::path("/proc/diskstats")),
+            diskstats_buf: Vec::new(),
+            probe_volumes: Vec::new(),
+            probes: HashMap::new(),
+            quotas: true,
+            deep: None,
+            mounted: None,
+        }
+    }
+
+    /// Recursive size scans of selected volumes
+    pub fn set_deep_usage(&mut self, deep: Option<DeepUsage>) {
+        self.deep = deep;
+    }
+
+    /// Volumes, by name, to check for writability by creating a file in
+    /// them; needs the kubelet directory mounted read-write
+    pub fn set_write_probes(&mut self, volumes: Vec<String>) {
+        self.probe_volumes = volumes;
+    }
+
+    /// Longest a cycle may spend statting volumes; None for no limit
+    pub fn set_scan_budget(&mut self, budget: Option<Duration>) {
+        self.budget = budget;
+    }
+
+    /// With `claims`, volumes bound to a claim are also checked against the
+    /// size it asked for
+    pub fn collect(&mut self, node_name: &str, claims: Option<&VolumeClaims>, sender: &mut MetricsSender) -> Result<()> {
+        let changed = self.watcher.as_ref().is_some_and(|w| w.changed());
+        if self.dirty || changed || self.cycles_since_refresh >= REFRESH_EVERY {
+            self.refresh();
+        }
+        self.cycles_since_refresh += 1;
+
+        // Flags and backing device of each volume mount
+        let table = self.mounts.read(&mut self.mounts_buf).ok();
+        let mounts: HashMap<&str, parsers::MountInfo> = match table {
+            Some(content) => parsers::mountinfo(content)
+                .filter(|m| m.mount_point.starts_with("/var/lib/kubelet/pods/"))
+                .map(|m| (m.mount_point, m))
+                .collect(),
+            None => HashMap::new(),
+        };
+        if table.is_some() {
+            report_mount_changes(&mut self.mounted, &mounts, sender);
+        }
+        let disks: HashMap<(u32, u32), parsers::DiskStats> = match self.diskstats.read(&mut self.diskstats_buf) {
+            Ok(content) if !mounts.is_empty() => parsers::diskstats(content).map(|d| ((d.major, d.minor), d)).collect(),
+            _ => HashMap::new(),
+        };
+
+        let started = Instant::now();
+        let total = self.targets.len();
+        let mut scanned = 0;
+        let mut vanished = false;
+        while scanned < total {
+            if self.budget.is_some_and(|b| started.elapsed() >= b) {
+                debug!("PVC Metrics: scan budget spent after {} of {} volumes", scanned, total);
+                break;
+            }
+            let target = &self.targets[(self.next + scanned) % total];
+            let claim = claims.and_then(|c| c.get(&target.vol_name));
+            vanished |= !collect_volume_stats(target, claim, node_name, sender);
+            let probed = self.probe_volumes.iter().any(|v| v == "*" || *v == target.vol_name);
+            let probe = if probed { poll_probe(&mut self.probes, target) } else { None };
+            // Remounted read-only by the kernel after I/O errors, usually
+            // without the CSI driver noticing
+            let mount = mounts.get(target.host_path.as_str());
+            report_health(target, mount.map(|m| m.read_only), probe, node_name, sender);
+            if let Some(disk) = mount.and_then(|m| disks.get(&(m.major, m.minor))) {
+                report_io(target, disk, node_name, sender);
+            }
+            if self.quotas {
+                match project_quota::usage(&target.mount_point) {
+                    Ok(Some(usage)) => report_quota(target, &usage, node_name, sender),
+                    Ok(None) => {}
+                    Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EPERM)) => {
+                        info!("PVC Metrics: project quotas unavailable, not reading them: {}", e);
+                        self.quotas = false;
+                    }
+                    Err(e) => debug!("PVC Metrics: no project quota for {}: {}", target.vol_name, e),
+                }
+            }
+            if let Some(deep) = self.deep.as_ref().filter(|d| d.wanted(&target.vol_name)) {
+                let volume = PathBuf::from(&*target.mount_point.to_string_lossy());
+                if let Some(scan) = deep.poll(&volume, || pod_roots(target, &volume)) {
+                    report_deep_usage(target, &scan, node_name, sender);
+                }
+            }
+            scanned += 1;
+        }
+        self.next = if total == 0 { 0 } else { (self.next + scanned) % total };
+        self.dirty = vanished;
+
+        if self.budget.is_some() {
+            let labels = Labels::default();
+            sender.add("agent_volume_scan", &labels, "scanned", scanned as f64);
+            sender.add("agent_volume_scan", &labels, "volumes", total as f64);
+            sender.add("agent_volume_scan", &labels, "scan_ms", started.elapsed().as_secs_f64() * 1000.0);
+        }
+
+        Ok(())
     }
 
-    if let Ok(entries) = fs::read_dir(pods_dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_dir() {
-                if let Some(pod_uid) = path.file_name().and_then(|n| n.to_str()) {
-                    process_pod_volumes(&path, pod_uid, node_name)?;
+    fn refresh(&mut self) {
+        let watcher = DirWatcher::new();
+        let mut targets = Vec::new();
+
+        let pods_dir = host::path("/var/lib/kubelet/pods");
+        let pods_dir = pods_dir.as_path();
+        if !pods_dir.exists() {
+            // debug!("PVC Metrics: /var/lib/kubelet/pods does not exist");
+        } else {
+            // Watch before listing so pods created mid-walk still trigger a refresh
+            if let Some(w) = &watcher {
+                w.watch(pods_dir);
+            }
+            if let Ok(entries) = fs::read_dir(pods_dir) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if path.is_dir() {
+                        if let Some(pod_uid) = path.file_name().and_then(|n| n.to_str()) {
+                            discover_pod_volumes(&path, pod_uid, watcher.as_ref(), &mut targets);
+                        }
+                    }
                 }
             }
         }
+
+        self.probes.retain(|mount_point, _| targets.iter().any(|t| t.mount_point == *mount_point));
+        if let Some(deep) = &self.deep {
+            deep.retain(|volume| targets.iter().any(|t| t.mount_point.as_bytes() == volume.to_string_lossy().as_bytes()));
+        }
+        self.targets = targets;
+        self.watcher = watcher;
+        self.cycles_since_refresh = 0;
+        self.dirty = false;
+    }
+}
+
+/// Volume mounts that came or went since the last cycle, by the mount
+/// table rather than the kubelet's directories, which outlive the mount
+fn report_mount_changes(mounted: &mut Option<HashSet<String>>, mounts: &HashMap<&str, parsers::MountInfo>, sender: &mut MetricsSender) {
+    let now: HashSet<String> = mounts.keys()
+        .filter(|m| pod_volume(m).is_some())
+        .map(|m| m.to_string())
+        .collect();
+    if let Some(before) = mounted.as_ref() {
+        let changes = now.difference(before).map(|m| ("volume_mounted", m))
+            .chain(before.difference(&now).map(|m| ("volume_unmounted", m)));
+        for (kind, mount_point) in changes {
+            let Some((pod_uid, driver, volume)) = pod_volume(mount_point) else { continue };
+            let labels = Labels { pod_uid: Some(pod_uid), volume: Some(volume), ..Default::default() };
+            sender.event(kind, &labels, format!("{} volume at {}", driver, mount_point));
+        }
+    }
+    *mounted = Some(now);
+}
+
+/// Pod UID, volume plugin and volume name of a mountpoint under
+/// /var/lib/kubelet/pods/<uid>/volumes/<plugin>/<name>
+fn pod_volume(mount_point: &str) -> Option<(&str, &str, &str)> {
+    let rest = mount_point.strip_prefix("/var/lib/kubelet/pods/")?;
+    let mut parts = rest.split('/');
+    let pod_uid = parts.next()?;
+    if parts.next()? != "volumes" {
+        return None;
     }
-    Ok(())
+    Some((pod_uid, parts.next()?, parts.next()?))
 }
 
-fn process_pod_volumes(pod_path: &Path, pod_uid: &str, node_name: &str) -> Result<()> {
+fn discover_pod_volumes(pod_path: &Path, pod_uid: &str, watcher: Option<&DirWatcher>, targets: &mut Vec<VolumeTarget>) {
     // Structure: /var/lib/kubelet/pods/<UID>/volumes/<DRIVER>/<VOL_NAME>
     // e.g. .../volumes/kubernetes.io~csi/pvc-123.../mount
     // e.g. .../volumes/kubernetes.io~empty-dir/logs
     
+    // Volumes are set up after the pod directory appears, so every level
+    // down to the volume itself is watched
+    let watch = |dir: &Path| {
+        if let Some(w) = watcher {
+            w.watch(dir);
+        }
+    };
+
+    watch(pod_path);
     let volumes_path = pod_path.join("volumes");
     if !volumes_path.exists() {
-        return Ok(());
+        return;
     }
 
+    watch(&volumes_path);
     if let Ok(drivers) = fs::read_dir(volumes_path) {
         for driver_entry in drivers.flatten() {
             let driver_path = driver_entry.path();
             if driver_path.is_dir() {
+                watch(&driver_path);
                 if let Ok(volumes) = fs::read_dir(&driver_path) {
                     for vol_entry in volumes.flatten() {
                         let vol_path = vol_entry.path();
@@ -50,13 +310,24 @@ fn process_pod_volumes(pod_path: &Path, pod_uid: &str, node_name: &str) -> Resul
                                 
                                 // Let's try to find a mountpoint.
                                 // If 'mount' exists, use it. Else use vol_path.
+                                watch(&vol_path);
                                 let mount_point = if vol_path.join("mount").exists() {
                                     vol_path.join("mount")
                                 } else {
                                     vol_path.clone()
                                 };
-                                
-                                collect_volume_stats(&mount_point, pod_uid, vol_name, node_name)?;
+
+                                let path_str = mount_point.to_string_lossy();
+                                let host_path = match mount_point.strip_prefix(host::path("/")) {
+                                    Ok(relative) => format!("/{}", relative.to_string_lossy()),
+                                    Err(_) => path_str.to_string(),
+                                };
+                                targets.push(VolumeTarget {
+                                    mount_point: CString::new(path_str.as_bytes()).unwrap_or_default(),
+                                    host_path,
+                                    pod_uid: pod_uid.to_string(),
+                                    vol_name: vol_name.to_string(),
+                                });
                             }
                         }
                     }
@@ -64,35 +335,199 @@ fn process_pod_volumes(pod_path: &Path, pod_uid: &str, node_name: &str) -> Resul
             }
         }
     }
-    Ok(())
 }
 
-fn collect_volume_stats(path: &Path, pod_uid: &str, vol_name: &str, node_name: &str) -> Result<()> {
-    let path_str = path.to_string_lossy();
-    let c_path = CString::new(path_str.as_bytes()).unwrap_or_default();
+/// Stats one mountpoint; returns false if it no longer exists
+fn collect_volume_stats(target: &VolumeTarget, claim: Option<&Claim>, node_name: &str, sender: &mut MetricsSender) -> bool {
+    let (total_bytes, free_bytes) = match filesystem_space(&target.mount_point) {
+        Ok(space) => space,
+        Err(e) => return e.kind() != std::io::ErrorKind::NotFound,
+    };
+    let used_bytes = total_bytes.saturating_sub(free_bytes);
     
+    let total_mb = total_bytes / 1024 / 1024;
+    let used_mb = used_bytes / 1024 / 1024;
+    let free_mb = free_bytes / 1024 / 1024;
+
+    // Only log if meaningful size (>1MB) to avoid noise from empty dirs or proc mounts
+    if total_mb > 0 {
+         info!("METRIC_TYPE=pvc_usage node={} pod_uid={} volume={} total_mb={} used_mb={} free_mb={}", 
+            node_name, target.pod_uid, target.vol_name, total_mb, used_mb, free_mb);
+
+        let labels = Labels { pod_uid: Some(&target.pod_uid), volume: Some(&target.vol_name), ..Default::default() };
+        sender.add("pvc_usage", &labels, "total_mb", total_mb as f64);
+        sender.add("pvc_usage", &labels, "used_mb", used_mb as f64);
+        sender.add("pvc_usage", &labels, "free_mb", free_mb as f64);
+    }
+    if let Some(claim) = claim {
+        report_capacity(target, claim, total_bytes, node_name, sender);
+    }
+    
+    true
+}
+
+/// Filesystem size against the claim. An expansion shows up as the request
+/// growing first, then the provisioned capacity, and last the filesystem,
+/// which some drivers only grow when the pod restarts.
+fn report_capacity(target: &VolumeTarget, claim: &Claim, fs_bytes: u64, node_name: &str, sender: &mut MetricsSender) {
+    let short_of = |bytes: u64| (fs_bytes as f64) < bytes as f64 * (1.0 - FS_OVERHEAD);
+    let undersized = short_of(claim.requested);
+    // The backend has yet to provision what was asked for, or the
+    // filesystem to grow into what was provisioned
+    let resize_pending = claim.resizing || claim.capacity < claim.requested || short_of(claim.capacity);
+
+    let mb = |bytes: u64| bytes / 1024 / 1024;
+    info!("METRIC_TYPE=pvc_capacity node={} pod_uid={} volume={} claim={}/{} requested_mb={} capacity_mb={} fs_mb={} undersized={} resize_pending={}",
+        node_name, target.pod_uid, target.vol_name, claim.namespace, claim.name, mb(claim.requested), mb(claim.capacity),
+        mb(fs_bytes), undersized as u8, resize_pending as u8);
+
+    let labels = Labels { pod_uid: Some(&target.pod_uid), volume: Some(&target.vol_name), ..Default::default() };
+    sender.add("pvc_capacity", &labels, "requested_mb", mb(claim.requested) as f64);
+    sender.add("pvc_capacity", &labels, "capacity_mb", mb(claim.capacity) as f64);
+    sender.add("pvc_capacity", &labels, "fs_mb", mb(fs_bytes) as f64);
+    sender.add("pvc_capacity", &labels, "undersized", undersized as u8 as f64);
+    sender.add("pvc_capacity", &labels, "resize_pending", resize_pending as u8 as f64);
+}
+
+/// Starts a write probe of the volume when one is due and returns the
+/// outcome of the last: writable, and how long the write took
+fn poll_probe(probes: &mut HashMap<CString, Probe>, target: &VolumeTarget) -> Option<(bool, Duration)> {
+    let dir = PathBuf::from(&*target.mount_point.to_string_lossy());
+    let probe = probes.entry(target.mount_point.clone()).or_insert_with(|| Probe::start(dir.clone()));
+
+    if let Some(pending) = &probe.pending {
+        match pending.try_recv() {
+            Ok(result) => {
+                if let Err(e) = &result {
+                    debug!("PVC Metrics: write probe of {} failed: {}", target.vol_name, e);
+                }
+                probe.last = Some(result.map_or((false, probe.started.elapsed()), |took| (true, took)));
+                probe.pending = None;
+            }
+            Err(TryRecvError::Empty) if probe.started.elapsed() >= PROBE_TIMEOUT => {
+                probe.last = Some((false, probe.started.elapsed()));
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => probe.pending = None,
+        }
+    }
+    if probe.pending.is_none() && probe.started.elapsed() >= PROBE_EVERY {
+        let last = probe.last;
+        *probe = Probe::start(dir);
+        probe.last = last;
+    }
+    probe.last
+}
+
+impl Probe {
+    fn start(dir: PathBuf) -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(write_probe(&dir));
+        });
+        Probe { started: Instant::now(), pending: Some(rx), last: None }
+    }
+}
+
+/// Creates, syncs and removes a small file in `dir`
+fn write_probe(dir: &Path) -> io::Result<Duration> {
+    let started = Instant::now();
+    let path = dir.join(PROBE_FILE);
+    let mut file = fs::File::create(&path)?;
+    file.write_all(b"vita")?;
+    file.sync_all()?;
+    fs::remove_file(&path)?;
+    Ok(started.elapsed())
+}
+
+/// Mount flags and write probe outcome of one volume, where known
+fn report_health(target: &VolumeTarget, read_only: Option<bool>, probe: Option<(bool, Duration)>, node_name: &str, sender: &mut MetricsSender) {
+    if read_only.is_none() && probe.is_none() {
+        return;
+    }
+    let labels = Labels { pod_uid: Some(&target.pod_uid), volume: Some(&target.vol_name), ..Default::default() };
+    if let Some(read_only) = read_only {
+        sender.add("pvc_health", &labels, "read_only", read_only as u8 as f64);
+    }
+    if let Some((writable, took)) = probe {
+        sender.add("pvc_health", &labels, "writable", writable as u8 as f64);
+        sender.add("pvc_health", &labels, "write_ms", took.as_secs_f64() * 1000.0);
+    }
+    // Unknown ones are logged as "-"
+    let flag = |v: Option<bool>| v.map_or("-", |v| if v { "1" } else { "0" });
+    info!("METRIC_TYPE=pvc_health node={} pod_uid={} volume={} read_only={} writable={}",
+        node_name, target.pod_uid, target.vol_name, flag(read_only), flag(probe.map(|(w, _)| w)));
+}
+
+/// I/O of the block device a volume is mounted from. A device backs one
+/// PVC with most CSI drivers; local volumes sharing a disk all report it.
+fn report_io(target: &VolumeTarget, disk: &parsers::DiskStats, node_name: &str, sender: &mut MetricsSender) {
+    info!("METRIC_TYPE=pvc_io node={} pod_uid={} volume={} device={} reads={} writes={} sectors_r={} sectors_w={}",
+        node_name, target.pod_uid, target.vol_name, disk.name, disk.reads, disk.writes, disk.sectors_read, disk.sectors_written);
+
+    let labels = Labels { pod_uid: Some(&target.pod_uid), volume: Some(&target.vol_name), device: Some(disk.name), ..Default::default() };
+    sender.add_counter("pvc_io", &labels, "reads", disk.reads as f64);
+    sender.add_counter("pvc_io", &labels, "writes", disk.writes as f64);
+    sender.add_counter("pvc_io", &labels, "sectors_r", disk.sectors_read as f64);
+    sender.add_counter("pvc_io", &labels, "sectors_w", disk.sectors_written as f64);
+}
+
+/// Directories of a volume the pod uses: the subPaths its containers
+/// mount, which the kubelet bind-mounts under volume-subpaths, or else the
+/// whole volume
+fn pod_roots(target: &VolumeTarget, volume: &Path) -> Vec<PathBuf> {
+    let subpaths = host::path("/var/lib/kubelet/pods").join(&target.pod_uid).join("volume-subpaths").join(&target.vol_name);
+    // <container>/<index of the volume mount>
+    let roots: Vec<PathBuf> = fs::read_dir(subpaths).into_iter().flatten().flatten()
+        .flat_map(|container| fs::read_dir(container.path()).into_iter().flatten().flatten())
+        .map(|mount| mount.path())
+        .collect();
+    if roots.is_empty() { vec![volume.to_path_buf()] } else { roots }
+}
+
+fn report_deep_usage(target: &VolumeTarget, scan: &Scan, node_name: &str, sender: &mut MetricsSender) {
+    let used_mb = scan.bytes / 1024 / 1024;
+    info!("METRIC_TYPE=pvc_deep_usage node={} pod_uid={} volume={} used_mb={} files={} scan_ms={} truncated={}",
+        node_name, target.pod_uid, target.vol_name, used_mb, scan.files, scan.took.as_millis(), scan.truncated as u8);
+
+    let labels = Labels { pod_uid: Some(&target.pod_uid), volume: Some(&target.vol_name), ..Default::default() };
+    sender.add("pvc_deep_usage", &labels, "used_mb", used_mb as f64);
+    sender.add("pvc_deep_usage", &labels, "files", scan.files as f64);
+    sender.add("pvc_deep_usage", &labels, "scan_ms", scan.took.as_millis() as f64);
+    sender.add("pvc_deep_usage", &labels, "truncated", scan.truncated as u8 as f64);
+}
+
+/// What a volume holds by its filesystem project, where statvfs would only
+/// see the disk it shares
+fn report_quota(target: &VolumeTarget, usage: &project_quota::ProjectUsage, node_name: &str, sender: &mut MetricsSender) {
+    let used_mb = usage.bytes / 1024 / 1024;
+    info!("METRIC_TYPE=pvc_quota node={} pod_uid={} volume={} project={} used_mb={} inodes={} limit_mb={}",
+        node_name, target.pod_uid, target.vol_name, usage.project_id, used_mb, usage.inodes,
+        usage.limit_bytes.map_or("-".to_string(), |l| (l / 1024 / 1024).to_string()));
+
+    let labels = Labels { pod_uid: Some(&target.pod_uid), volume: Some(&target.vol_name), ..Default::default() };
+    sender.add("pvc_quota", &labels, "project_id", usage.project_id as f64);
+    sender.add("pvc_quota", &labels, "used_mb", used_mb as f64);
+    sender.add("pvc_quota", &labels, "inodes", usage.inodes as f64);
+    if let Some(limit) = usage.limit_bytes {
+        sender.add("pvc_quota", &labels, "limit_mb", (limit / 1024 / 1024) as f64);
+    }
+}
+
+/// Total and unprivileged-free bytes of the filesystem holding `path`
+#[cfg(unix)]
+pub fn filesystem_space(path: &CString) -> std::io::Result<(u64, u64)> {
     unsafe {
         let mut stat: libc::statvfs = std::mem::zeroed();
-        if libc::statvfs(c_path.as_ptr(), &mut stat) == 0 {
-            let block_size = stat.f_frsize as u64; // fundamental filesystem block size
-            let total_blocks = stat.f_blocks as u64;
-            let free_blocks = stat.f_bavail as u64; // free blocks for unprivileged users
-            
-            let total_bytes = total_blocks * block_size;
-            let free_bytes = free_blocks * block_size;
-            let used_bytes = total_bytes.saturating_sub(free_bytes);
-            
-            let total_mb = total_bytes / 1024 / 1024;
-            let used_mb = used_bytes / 1024 / 1024;
-            let free_mb = free_bytes / 1024 / 1024;
-
-            // Only log if meaningful size (>1MB) to avoid noise from empty dirs or proc mounts
-            if total_mb > 0 {
-                 info!("METRIC_TYPE=pvc_usage node={} pod_uid={} volume={} total_mb={} used_mb={} free_mb={}", 
-                    node_name, pod_uid, vol_name, total_mb, used_mb, free_mb);
-            }
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return Err(std::io::Error::last_os_error());
         }
+        let block_size = stat.f_frsize as u64; // fundamental filesystem block size
+        Ok((stat.f_blocks as u64 * block_size, stat.f_bavail as u64 * block_size))
     }
-    
-    Ok(())
+}
+
+#[cfg(windows)]
+pub fn filesystem_space(path: &CString) -> std::io::Result<(u64, u64)> {
+    crate::windows_metrics::disk_space(Path::new(&*path.to_string_lossy()))
 }
@@ -0,0 +1,189 @@
+use std::ffi::CStr;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+const RTM_GETQDISC: u16 = 38;
+const RTM_NEWQDISC: u16 = 36;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+
+const TCA_KIND: u16 = 1;
+const TCA_STATS: u16 = 3;
+const TCA_STATS2: u16 = 7;
+const TCA_STATS_BASIC: u16 = 1;
+const TCA_STATS_QUEUE: u16 = 3;
+
+/// Parent handle of a qdisc attached at the interface's egress root
+const TC_H_ROOT: u32 = 0xffff_ffff;
+
+/// nlmsghdr, then tcmsg
+const NLMSG_HDRLEN: usize = 16;
+const TCMSG_LEN: usize = 20;
+
+/// Counters of the qdisc at the root of one interface's egress
+#[derive(Default)]
+pub struct Qdisc {
+    pub interface: String,
+    pub kind: String,
+    pub bytes: u64,
+    pub packets: u64,
+    pub drops: u64,
+    pub overlimits: u64,
+    pub requeues: u64,
+    pub qlen: u64,
+    pub backlog: u64,
+}
+
+/// Root qdiscs of the host's interfaces over a NETLINK_ROUTE socket, the
+/// dump `tc -s qdisc show` does. Classful roots such as mq and htb report
+/// the sums of their children, so one per interface covers what it dropped.
+pub struct QdiscReader {
+    socket: OwnedFd,
+    seq: u32,
+    buf: Vec<u8>,
+}
+
+impl QdiscReader {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // The kernel sends dumps in chunks of up to a page or so; 32KiB
+        // takes several in one read
+        Ok(Self { socket: unsafe { OwnedFd::from_raw_fd(fd) }, seq: 0, buf: vec![0; 32 * 1024] })
+    }
+
+    pub fn read(&mut self) -> io::Result<Vec<Qdisc>> {
+        self.seq = self.seq.wrapping_add(1);
+        let mut request = [0u8; NLMSG_HDRLEN + TCMSG_LEN];
+        request[0..4].copy_from_slice(&((NLMSG_HDRLEN + TCMSG_LEN) as u32).to_ne_bytes());
+        request[4..6].copy_from_slice(&RTM_GETQDISC.to_ne_bytes());
+        request[6..8].copy_from_slice(&((libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16).to_ne_bytes());
+        request[8..12].copy_from_slice(&self.seq.to_ne_bytes());
+        // tcmsg: family AF_UNSPEC and every interface, all zero
+        let sent = unsafe { libc::send(self.socket.as_raw_fd(), request.as_ptr().cast(), request.len(), 0) };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut qdiscs = Vec::new();
+        loop {
+            let n = unsafe { libc::recv(self.socket.as_raw_fd(), self.buf.as_mut_ptr().cast(), self.buf.len(), 0) };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut rest = &self.buf[..n as usize];
+            while rest.len() >= NLMSG_HDRLEN {
+                let len = u32::from_ne_bytes(rest[0..4].try_into().unwrap()) as usize;
+                let kind = u16::from_ne_bytes(rest[4..6].try_into().unwrap());
+                let seq = u32::from_ne_bytes(rest[8..12].try_into().unwrap());
+                if len < NLMSG_HDRLEN || len > rest.len() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated netlink message"));
+                }
+                let message = &rest[NLMSG_HDRLEN..len];
+                rest = &rest[align(len).min(rest.len())..];
+                if seq != self.seq {
+                    continue;
+                }
+                match kind {
+                    NLMSG_DONE => return Ok(qdiscs),
+                    NLMSG_ERROR => {
+                        let errno = message.get(0..4).map_or(0, |e| i32::from_ne_bytes(e.try_into().unwrap()));
+                        return Err(io::Error::from_raw_os_error(-errno));
+                    }
+                    RTM_NEWQDISC => qdiscs.extend(parse(message)),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// tcmsg: family, padding, ifindex at 4, handle at 8, parent at 12; then
+/// attributes
+fn parse(message: &[u8]) -> Option<Qdisc> {
+    let tcmsg = message.get(..TCMSG_LEN)?;
+    let ifindex = u32::from_ne_bytes(tcmsg[4..8].try_into().ok()?);
+    let parent = u32::from_ne_bytes(tcmsg[12..16].try_into().ok()?);
+    if parent != TC_H_ROOT {
+        return None;
+    }
+
+    let mut qdisc = Qdisc { interface: interface_name(ifindex)?, ..Default::default() };
+    let mut stats2 = false;
+    for (kind, value) in attributes(&message[TCMSG_LEN..]) {
+        match kind {
+            TCA_KIND => qdisc.kind = CStr::from_bytes_until_nul(value).ok()?.to_string_lossy().into_owned(),
+            TCA_STATS2 => {
+                stats2 = true;
+                for (kind, value) in attributes(value) {
+                    match kind {
+                        // gnet_stats_basic: u64 bytes, u32 packets
+                        TCA_STATS_BASIC if value.len() >= 12 => {
+                            qdisc.bytes = u64::from_ne_bytes(value[0..8].try_into().ok()?);
+                            qdisc.packets = u32_at(value, 8) as u64;
+                        }
+                        // gnet_stats_queue: qlen, backlog, drops, requeues, overlimits
+                        TCA_STATS_QUEUE if value.len() >= 20 => {
+                            qdisc.qlen = u32_at(value, 0) as u64;
+                            qdisc.backlog = u32_at(value, 4) as u64;
+                            qdisc.drops = u32_at(value, 8) as u64;
+                            qdisc.requeues = u32_at(value, 12) as u64;
+                            qdisc.overlimits = u32_at(value, 16) as u64;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            // tc_stats from kernels before TCA_STATS2: bytes, packets,
+            // drops, overlimits, bps, pps, qlen, backlog
+            TCA_STATS if !stats2 && value.len() >= 36 => {
+                qdisc.bytes = u64::from_ne_bytes(value[0..8].try_into().ok()?);
+                qdisc.packets = u32_at(value, 8) as u64;
+                qdisc.drops = u32_at(value, 12) as u64;
+                qdisc.overlimits = u32_at(value, 16) as u64;
+                qdisc.qlen = u32_at(value, 28) as u64;
+                qdisc.backlog = u32_at(value, 32) as u64;
+            }
+            _ => {}
+        }
+    }
+    Some(qdisc)
+}
+
+/// rtattrs as (type, payload): u16 length including the header, u16 type,
+/// each padded to 4 bytes
+fn attributes(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < 4 {
+            return None;
+        }
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        // Nested attributes carry NLA_F_NESTED in the type
+        let kind = u16::from_ne_bytes([buf[2], buf[3]]) & 0x3fff;
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+        let value = &buf[4..len];
+        buf = &buf[align(len).min(buf.len())..];
+        Some((kind, value))
+    })
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_ne_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn interface_name(ifindex: u32) -> Option<String> {
+    let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+    let found = unsafe { libc::if_indextoname(ifindex, name.as_mut_ptr()) };
+    if found.is_null() {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned())
+}
@@ -0,0 +1,40 @@
+/// Collectors on intervals of their own within one loop. The loop ticks at
+/// the greatest common divisor of them all, so collectors whose intervals
+/// line up run in the same cycle and share its flush: containers at 1s and
+/// volumes at 30s put both into every thirtieth batch.
+pub struct Schedule {
+    tick: u64,
+    // Seconds of schedule time at the current cycle, before any slowdown
+    at: u64,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self { tick: 1, at: 0 }
+    }
+
+    /// Sets the collectors' intervals, in seconds, for the current
+    /// cycle on; returns the seconds until the next one. A different tick
+    /// starts the schedule over, so every collector runs this cycle.
+    pub fn plan(&mut self, intervals: &[u64]) -> u64 {
+        let tick = intervals.iter().copied().filter(|&i| i > 0).reduce(gcd).unwrap_or(1);
+        if tick != self.tick {
+            self.tick = tick;
+            self.at = 0;
+        }
+        self.tick
+    }
+
+    /// Whether a collector on `interval` runs this cycle
+    pub fn due(&self, interval: u64) -> bool {
+        interval == 0 || self.at.is_multiple_of(interval)
+    }
+
+    pub fn advance(&mut self) {
+        self.at += self.tick;
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
@@ -0,0 +1,89 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+/// How often a secret file is stat'ed for changes
+const RECHECK_EVERY: Duration = Duration::from_secs(10);
+
+/// A credential from the file named by NAME_FILE, re-read when it changes
+/// (projected ServiceAccount tokens rotate hourly, Secret volumes whenever
+/// the Secret is updated), or else the NAME variable captured at startup.
+pub struct Secret {
+    path: Option<PathBuf>,
+    value: String,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl Secret {
+    /// None if neither NAME_FILE nor NAME is set
+    pub fn from_env(name: &str) -> Option<Self> {
+        if let Ok(path) = env::var(format!("{}_FILE", name)) {
+            let mut secret = Self {
+                path: Some(PathBuf::from(path)),
+                value: String::new(),
+                modified: None,
+                checked: Instant::now(),
+            };
+            secret.reload();
+            return Some(secret);
+        }
+        env::var(name).ok().filter(|v| !v.is_empty()).map(|value| Self {
+            path: None,
+            value,
+            modified: None,
+            checked: Instant::now(),
+        })
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Re-reads the file if it changed, at most every RECHECK_EVERY.
+    /// Returns true if the value changed.
+    pub fn refresh(&mut self) -> bool {
+        if self.path.is_none() || self.checked.elapsed() < RECHECK_EVERY {
+            return false;
+        }
+        self.reload()
+    }
+
+    // A failed read keeps the previous value
+    fn reload(&mut self) -> bool {
+        self.checked = Instant::now();
+        let Some(path) = &self.path else { return false };
+
+        let modified = match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(e) => {
+                warn!("Secret file {:?} unavailable: {}", path, e);
+                return false;
+            }
+        };
+        if self.modified == Some(modified) {
+            return false;
+        }
+
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                self.modified = Some(modified);
+                let value = content.trim();
+                if value == self.value {
+                    return false;
+                }
+                if !self.value.is_empty() {
+                    info!("Reloaded rotated secret from {:?}", path);
+                }
+                self.value = value.to_string();
+                true
+            }
+            Err(e) => {
+                warn!("Failed to read secret file {:?}: {}", path, e);
+                false
+            }
+        }
+    }
+}
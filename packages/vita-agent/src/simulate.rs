@@ -0,0 +1,309 @@
+use anyhow::{bail, Context, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::AgentConfig;
+use crate::heartbeat::Heartbeat;
+use crate::metrics_sender::{get_timestamp, Labels, MetricsSender};
+use crate::secret::Secret;
+
+const CPUS: f64 = 8.0;
+const NODE_MEM_MB: f64 = 32768.0;
+// Memory used by the node itself, besides its pods
+const SYSTEM_MEM_MB: f64 = 2048.0;
+// Every nth pod has a persistent volume
+const PVC_EVERY: usize = 4;
+
+struct Options {
+    nodes: usize,
+    pods_per_node: usize,
+    interval: Duration,
+    duration: Option<Duration>,
+    churn: f64,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Self {
+            nodes: 10,
+            pods_per_node: 30,
+            interval: Duration::from_secs(10),
+            duration: None,
+            churn: 0.001,
+        };
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().with_context(|| format!("{} needs a value", flag))?;
+            let number = || value.parse::<f64>().with_context(|| format!("invalid {} {}", flag, value));
+            match flag.as_str() {
+                "--nodes" => options.nodes = number()? as usize,
+                "--pods-per-node" => options.pods_per_node = number()? as usize,
+                "--interval" => options.interval = Duration::from_secs_f64(number()?.max(0.1)),
+                "--duration" => options.duration = Some(Duration::from_secs_f64(number()?)),
+                "--churn" => options.churn = number()?.clamp(0.0, 1.0),
+                _ => bail!("unknown simulate option {}", flag),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Totals across simulated nodes, for the progress lines
+#[derive(Default)]
+struct Stats {
+    batches: AtomicU64,
+    metrics: AtomicU64,
+    failures: AtomicU64,
+}
+
+/// `vita-agent simulate [--nodes N] [--pods-per-node N] [--interval SECS]
+/// [--duration SECS] [--churn P]`: plays a cluster of synthetic nodes
+/// against the configured consumer, each with its own connection,
+/// handshake and heartbeat like a real agent. Counters only grow, values
+/// jitter around per-pod baselines, and every cycle each pod is replaced by
+/// a new one with probability `churn` so series come and go.
+pub async fn run(args: &[String], config: &AgentConfig) -> Result<()> {
+    let options = Options::parse(args)?;
+    println!("Simulating {} nodes x {} pods every {:?} against {}",
+        options.nodes, options.pods_per_node, options.interval, config.endpoint);
+
+    let stats = Arc::new(Stats::default());
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+    for n in 0..options.nodes {
+        let mut rng = Rng::new(seed ^ (n as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let node = SimNode::new(format!("sim-node-{:04}", n), options.pods_per_node, config, &mut rng);
+        // Nodes start spread over one interval, like agents that came up at different times
+        let offset = options.interval.mul_f64(rng.next_f64());
+        tokio::spawn(node.run(rng, offset, options.interval, options.churn, config.clone(), stats.clone()));
+    }
+
+    let started = Instant::now();
+    let mut last = (0, 0, 0);
+    loop {
+        let report = Duration::from_secs(10);
+        let remaining = options.duration.map(|d| d.saturating_sub(started.elapsed()));
+        tokio::time::sleep(remaining.map_or(report, |r| r.min(report))).await;
+
+        let now = (
+            stats.batches.load(Ordering::Relaxed),
+            stats.metrics.load(Ordering::Relaxed),
+            stats.failures.load(Ordering::Relaxed),
+        );
+        println!("{:>6}s  batches={} (+{})  metrics={} (+{})  failed={} (+{})",
+            started.elapsed().as_secs(), now.0, now.0 - last.0, now.1, now.1 - last.1, now.2, now.2 - last.2);
+        last = now;
+
+        if remaining.is_some_and(|r| r <= report) {
+            return Ok(());
+        }
+    }
+}
+
+struct SimPod {
+    pod_id: String,
+    pod_uid: String,
+    volume: Option<String>,
+    // Cumulative CPU, and the rate it grows at in ms per second
+    cpu_ms: f64,
+    cpu_rate: f64,
+    mem_mb: f64,
+    mem_limit_mb: f64,
+    pvc_used_mb: f64,
+}
+
+impl SimPod {
+    fn new(index: usize, rng: &mut Rng) -> Self {
+        let uid = rng.uuid();
+        let qos = ["burstable", "besteffort", "guaranteed"][rng.below(3)];
+        // Mostly small pods and a few heavy ones
+        let size = rng.next_f64().powi(3);
+        let mem_mb = 64.0 + size * 4096.0;
+        Self {
+            pod_id: format!("kubepods-{}-pod{}.slice", qos, uid.replace('-', "_")),
+            pod_uid: uid,
+            volume: index.is_multiple_of(PVC_EVERY).then(|| format!("pvc-{}", rng.uuid())),
+            cpu_ms: 0.0,
+            cpu_rate: 5.0 + size * 1500.0,
+            mem_mb,
+            mem_limit_mb: if qos == "besteffort" { 0.0 } else { (mem_mb * 2.0).round() },
+            pvc_used_mb: rng.next_f64() * 5000.0,
+        }
+    }
+}
+
+struct SimNode {
+    name: String,
+    sender: MetricsSender,
+    heartbeat: Heartbeat,
+    boot_time: i64,
+    pods: Vec<SimPod>,
+    // user, sys, idle, iowait in 1/100 s ticks
+    cpu: [f64; 4],
+    disk: [f64; 4],
+    net: [f64; 4],
+}
+
+impl SimNode {
+    fn new(name: String, pods: usize, config: &AgentConfig, rng: &mut Rng) -> Self {
+        let mut sender = MetricsSender::new(config.endpoint.clone(), name.clone());
+        sender.set_compact(config.compact_wire);
+        sender.set_token(Secret::from_env("INGEST_TOKEN"));
+        sender.set_ca(Secret::from_env("CONSUMER_CA"));
+        sender.set_signing_key(Secret::from_env("INGEST_HMAC_KEY"));
+        // Up for up to a month, with counters to match
+        let uptime = rng.next_f64() * 30.0 * 86400.0;
+        Self {
+            name,
+            sender,
+            heartbeat: Heartbeat::new(Duration::from_secs(15)),
+            boot_time: get_timestamp() - uptime as i64,
+            pods: (0..pods).map(|i| SimPod::new(i, rng)).collect(),
+            cpu: [uptime * 100.0 * CPUS * 0.2, uptime * 100.0 * CPUS * 0.05, uptime * 100.0 * CPUS * 0.75, 0.0],
+            disk: [uptime * 20.0, uptime * 40.0, uptime * 400.0, uptime * 1600.0],
+            net: [uptime * 2e5, uptime * 1.5e5, uptime * 300.0, uptime * 250.0],
+        }
+    }
+
+    async fn run(mut self, mut rng: Rng, offset: Duration, interval: Duration, churn: f64, config: AgentConfig, stats: Arc<Stats>) {
+        tokio::time::sleep(offset).await;
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last = Instant::now();
+
+        loop {
+            ticker.tick().await;
+            let dt = last.elapsed().as_secs_f64();
+            last = Instant::now();
+
+            self.sender.begin_cycle();
+            self.step(&mut rng, dt, churn);
+            let metrics = self.sender.pending() as u64;
+            match self.sender.flush().await {
+                Ok(()) => {
+                    stats.batches.fetch_add(1, Ordering::Relaxed);
+                    stats.metrics.fetch_add(metrics, Ordering::Relaxed);
+                }
+                Err(_) => {
+                    stats.failures.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            if self.heartbeat.due() {
+                let collectors = ["system", "containers", "volumes"];
+                let record = self.heartbeat.record(&self.name, &collectors, &config);
+                let _ = self.sender.send_heartbeat(&record).await;
+            }
+        }
+    }
+
+    /// Advances the node by `dt` seconds and queues one cycle of metrics
+    fn step(&mut self, rng: &mut Rng, dt: f64, churn: f64) {
+        for (i, pod) in self.pods.iter_mut().enumerate() {
+            if rng.next_f64() < churn {
+                *pod = SimPod::new(i, rng);
+            }
+        }
+
+        let sender = &mut self.sender;
+        let none = Labels::default();
+        sender.add("node_boot", &none, "boot_time", self.boot_time as f64);
+
+        let mut busy_ms = 0.0;
+        let mut pods_mem = 0.0;
+        for pod in &mut self.pods {
+            let used = pod.cpu_rate * dt * rng.jitter(0.3);
+            pod.cpu_ms += used;
+            busy_ms += used;
+            // Random walk around the baseline, kept under the limit
+            pod.mem_mb = (pod.mem_mb * rng.jitter(0.02)).max(16.0);
+            if pod.mem_limit_mb > 0.0 {
+                pod.mem_mb = pod.mem_mb.min(pod.mem_limit_mb * 0.95);
+            }
+            pods_mem += pod.mem_mb;
+
+            let labels = Labels { pod_id: Some(&pod.pod_id), ..Default::default() };
+            sender.add_counter("container", &labels, "cpu_ms", pod.cpu_ms.floor());
+            sender.add("container", &labels, "mem_mb", pod.mem_mb.floor());
+            sender.add("container", &labels, "mem_limit_mb", pod.mem_limit_mb);
+
+            if let Some(volume) = &pod.volume {
+                pod.pvc_used_mb = (pod.pvc_used_mb + dt * 0.01 * rng.next_f64()).min(10240.0);
+                let labels = Labels { pod_uid: Some(&pod.pod_uid), volume: Some(volume), ..Default::default() };
+                sender.add("pvc_usage", &labels, "total_mb", 10240.0);
+                sender.add("pvc_usage", &labels, "used_mb", pod.pvc_used_mb.floor());
+                sender.add("pvc_usage", &labels, "free_mb", (10240.0 - pod.pvc_used_mb).floor());
+            }
+        }
+
+        // The pods' CPU plus a little system overhead, split as /proc/stat would
+        let ticks = dt * 100.0 * CPUS;
+        let busy = (busy_ms / 10.0 + ticks * 0.02).min(ticks);
+        let iowait = (ticks - busy) * 0.01;
+        self.cpu[0] += busy * 0.8;
+        self.cpu[1] += busy * 0.2;
+        self.cpu[2] += ticks - busy - iowait;
+        self.cpu[3] += iowait;
+        for (key, value) in ["user", "sys", "idle", "iowait"].iter().zip(self.cpu) {
+            sender.add_counter("node_cpu", &none, key, value.floor());
+        }
+
+        let used = (SYSTEM_MEM_MB + pods_mem).min(NODE_MEM_MB);
+        let free = NODE_MEM_MB - used;
+        sender.add("node_mem", &none, "total_mb", NODE_MEM_MB);
+        sender.add("node_mem", &none, "used_mb", used.floor());
+        sender.add("node_mem", &none, "free_mb", free.floor());
+        sender.add("node_mem", &none, "avail_mb", (free + used * 0.1).floor());
+
+        let labels = Labels { device: Some("nvme0n1"), ..Default::default() };
+        for (i, (key, rate)) in [("reads", 20.0), ("writes", 40.0), ("sectors_r", 400.0), ("sectors_w", 1600.0)].into_iter().enumerate() {
+            self.disk[i] += rate * dt * rng.jitter(0.5);
+            sender.add_counter("node_disk", &labels, key, self.disk[i].floor());
+        }
+
+        let labels = Labels { device: Some("eth0"), ..Default::default() };
+        for (i, (key, rate)) in [("rx_bytes", 2e5), ("tx_bytes", 1.5e5), ("rx_pkts", 300.0), ("tx_pkts", 250.0)].into_iter().enumerate() {
+            self.net[i] += rate * dt * rng.jitter(0.5);
+            sender.add_counter("node_net", &labels, key, self.net[i].floor());
+        }
+        sender.add_counter("node_net", &labels, "rx_errs", 0.0);
+        sender.add_counter("node_net", &labels, "tx_errs", 0.0);
+    }
+}
+
+/// xorshift64*: synthetic data needs speed and independence per node, not
+/// unpredictability
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Factor uniform in [1 - spread, 1 + spread]
+    fn jitter(&mut self, spread: f64) -> f64 {
+        1.0 + spread * (2.0 * self.next_f64() - 1.0)
+    }
+
+    fn uuid(&mut self) -> String {
+        let (a, b) = (self.next_u64(), self.next_u64());
+        format!("{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+            a >> 32, (a >> 16) & 0xffff, a & 0xfff, (b >> 48) & 0x3fff | 0x8000, b & 0xffff_ffff_ffff)
+    }
+}
@@ -0,0 +1,172 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// Longest a smartctl run may take before it is killed; a disk that hangs
+/// on SMART commands is usually the one failing
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Block devices that have no SMART of their own
+const VIRTUAL: [&str; 9] = ["loop", "ram", "zram", "dm-", "md", "sr", "nbd", "rbd", "drbd"];
+
+/// ATA attributes whose normalized value counts the endurance left:
+/// Wear_Leveling_Count, SSD_Life_Left, Media_Wearout_Indicator
+const ATA_WEAR: [u64; 3] = [177, 231, 233];
+
+/// Health of one disk from its last smartctl run, by metric key
+type Health = BTreeMap<&'static str, f64>;
+
+/// SMART health of the node's disks from smartctl, which runs on a thread
+/// of its own every interval, one disk at a time, with none of the agent's
+/// environment but PATH, no stdin and a hard timeout. Collection only
+/// reports the last results, so a slow disk never holds up a cycle.
+pub struct SmartHealth {
+    results: Arc<Mutex<BTreeMap<String, Health>>>,
+}
+
+impl SmartHealth {
+    /// SMART_DEVICES names the disks (sda,nvme0n1), or "*" for every
+    /// physical one; SMARTCTL the binary and SMART_INTERVAL the seconds
+    /// between runs (600). None unless devices are named.
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn from_env() -> Option<Self> {
+        let devices: Vec<String> = env::var("SMART_DEVICES").unwrap_or_default()
+            .split(',').map(str::trim).filter(|d| !d.is_empty()).map(String::from).collect();
+        if devices.is_empty() {
+            return None;
+        }
+        let smartctl = env::var("SMARTCTL").unwrap_or_else(|_| "smartctl".to_string());
+        let interval = env::var("SMART_INTERVAL").ok().and_then(|v| v.parse().ok()).filter(|&s| s > 0)
+            .map_or(Duration::from_secs(600), Duration::from_secs);
+        info!("SMART health enabled | devices={:?} smartctl={} interval={:?}", devices, smartctl, interval);
+
+        let results: Arc<Mutex<BTreeMap<String, Health>>> = Arc::default();
+        let shared = results.clone();
+        std::thread::spawn(move || loop {
+            let started = Instant::now();
+            let disks = if devices.iter().any(|d| d == "*") { physical_disks() } else { devices.clone() };
+            let mut latest = BTreeMap::new();
+            for disk in disks {
+                match run(&smartctl, &disk) {
+                    Ok(health) => {
+                        latest.insert(disk, health);
+                    }
+                    Err(e) => debug!("SMART: no health for {}: {}", disk, e),
+                }
+            }
+            *shared.lock().unwrap() = latest;
+            std::thread::sleep(interval.saturating_sub(started.elapsed()));
+        });
+        Some(Self { results })
+    }
+
+    pub fn collect(&self, node_name: &str, sender: &mut MetricsSender) {
+        for (disk, health) in self.results.lock().unwrap().iter() {
+            info!("METRIC_TYPE=node_smart node={} device={} {}", node_name, disk, health.iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(" "));
+            let labels = Labels { device: Some(disk), ..Default::default() };
+            for (key, value) in health {
+                sender.add("node_smart", &labels, key, *value);
+            }
+        }
+    }
+}
+
+/// Whole disks in /sys/block backed by hardware
+fn physical_disks() -> Vec<String> {
+    fs::read_dir(host::path("/sys/block")).into_iter().flatten().flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| !VIRTUAL.iter().any(|v| name.starts_with(v)))
+        .filter(|name| host::path(&format!("/sys/block/{}/device", name)).exists())
+        .collect()
+}
+
+fn run(smartctl: &str, disk: &str) -> anyhow::Result<Health> {
+    let mut child = Command::new(smartctl)
+        .args(["--json=c", "--nocheck=standby", "-H", "-A", &format!("/dev/{}", disk)])
+        .env_clear()
+        // Only what finding the binary needs
+        .env("PATH", env::var_os("PATH").unwrap_or_default())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Read on another thread so a hung smartctl can still be killed
+    let mut stdout = child.stdout.take().expect("piped stdout");
+    let reader = std::thread::spawn(move || {
+        let mut out = Vec::new();
+        stdout.read_to_end(&mut out).map(|_| out)
+    });
+    let started = Instant::now();
+    while child.try_wait()?.is_none() {
+        if started.elapsed() >= TIMEOUT {
+            warn!("SMART: smartctl on {} still running after {:?}, killed", disk, TIMEOUT);
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("timed out");
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let out = reader.join().map_err(|_| anyhow::anyhow!("reader panicked"))??;
+
+    // The exit status is a bit mask that is also set for failing disks,
+    // so only the JSON tells whether smartctl got anywhere
+    let json: Value = serde_json::from_slice(&out)?;
+    let health = parse(&json);
+    if health.is_empty() {
+        anyhow::bail!("no SMART data: {}", json["smartctl"]["messages"]);
+    }
+    Ok(health)
+}
+
+/// The keys that tell a failing disk, from ATA attributes or the NVMe
+/// health log, whichever the disk has
+fn parse(json: &Value) -> Health {
+    let mut health = Health::new();
+    let mut put = |key, value: &Value| {
+        if let Some(v) = value.as_f64() {
+            health.insert(key, v);
+        }
+    };
+    if let Some(passed) = json["smart_status"]["passed"].as_bool() {
+        put("passed", &Value::from(passed as u8));
+    }
+    put("temperature_c", &json["temperature"]["current"]);
+    put("power_on_hours", &json["power_on_time"]["hours"]);
+
+    let nvme = &json["nvme_smart_health_information_log"];
+    put("media_errors", &nvme["media_errors"]);
+    put("critical_warning", &nvme["critical_warning"]);
+    put("available_spare_pct", &nvme["available_spare"]);
+    put("wear_used_pct", &nvme["percentage_used"]);
+    put("error_log_entries", &nvme["num_err_log_entries"]);
+
+    for attribute in json["ata_smart_attributes"]["table"].as_array().into_iter().flatten() {
+        let raw = &attribute["raw"]["value"];
+        match attribute["id"].as_u64() {
+            Some(5) => put("reallocated_sectors", raw),
+            Some(187) => put("reported_uncorrect", raw),
+            Some(197) => put("pending_sectors", raw),
+            Some(198) => put("offline_uncorrectable", raw),
+            Some(id) if ATA_WEAR.contains(&id) => {
+                if let Some(left) = attribute["value"].as_f64() {
+                    put("wear_used_pct", &Value::from(100.0 - left.min(100.0)));
+                }
+            }
+            _ => {}
+        }
+    }
+    health
+}
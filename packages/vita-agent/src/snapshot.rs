@@ -0,0 +1,221 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tar::{Builder, EntryType, Header};
+
+use crate::metrics_sender::get_timestamp;
+
+const MANIFEST: &str = "vita-snapshot.json";
+
+/// Host files the collectors read, and a few that explain the layout
+const HOST_FILES: &[&str] = &[
+    "/proc/stat", "/proc/meminfo", "/proc/diskstats", "/proc/net/dev", "/proc/net/ip_vs_stats", "/proc/net/ip_vs",
+    "/proc/net/arp", "/proc/net/softnet_stat", "/proc/mdstat", "/proc/sys/net/ipv4/neigh/default/gc_thresh1", "/proc/sys/net/ipv4/neigh/default/gc_thresh2",
+    "/proc/sys/net/ipv4/neigh/default/gc_thresh3", "/proc/loadavg", "/proc/sys/kernel/random/entropy_avail",
+    "/proc/sys/net/netfilter/nf_conntrack_count", "/proc/sys/net/netfilter/nf_conntrack_max", "/proc/sys/fs/file-nr",
+    "/proc/sys/kernel/pid_max", "/proc/sys/kernel/threads-max",
+    "/proc/cgroups", "/proc/mounts", "/proc/1/mountinfo", "/var/lib/kubelet/cpu_manager_state",
+    "/var/lib/kubelet/device-plugins/kubelet_internal_checkpoint",
+];
+
+/// Cgroup files above this size aren't stat files; they're left out
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    node: String,
+    recorded_at: i64,
+    version: String,
+}
+
+/// A snapshot unpacked for replay
+pub struct Snapshot {
+    pub root: PathBuf,
+    pub node: Option<String>,
+}
+
+/// `vita-agent record [file]`: archives what the collectors read on this
+/// host (the /proc files, CPU and device manager state, the kubepods and
+/// system.slice cgroup trees with their stat files, and the layout of
+/// /var/lib/kubelet/pods down to each volume, without volume contents) as
+/// a .tar.gz for `vita-agent replay`.
+pub fn record(output: Option<&str>, node_name: &str) -> Result<()> {
+    let recorded_at = get_timestamp();
+    let output = output.map(PathBuf::from)
+        .unwrap_or_else(|| format!("vita-snapshot-{}-{}.tar.gz", node_name, recorded_at).into());
+    let file = File::create(&output).with_context(|| format!("creating {}", output.display()))?;
+    let mut archive = Builder::new(GzEncoder::new(file, flate2::Compression::default()));
+    // Links are recorded as links so v1 controller aliases (cpu -> cpu,cpuacct) survive
+    archive.follow_symlinks(false);
+
+    let manifest = Manifest { node: node_name.to_string(), recorded_at, version: env!("CARGO_PKG_VERSION").to_string() };
+    append_bytes(&mut archive, MANIFEST, &serde_json::to_vec_pretty(&manifest)?)?;
+
+    let mut files = 0;
+    for path in HOST_FILES {
+        // /proc files report a size of 0, so they're read rather than copied
+        if let Ok(content) = fs::read(path) {
+            append_bytes(&mut archive, &path[1..], &content)?;
+            files += 1;
+        }
+    }
+    files += record_cgroups(&mut archive)?;
+    let volumes = record_kubelet_pods(&mut archive)?;
+
+    archive.into_inner()?.finish()?;
+    println!("Recorded {} files and {} volumes to {}", files, volumes, output.display());
+    Ok(())
+}
+
+fn append_bytes(archive: &mut Builder<impl io::Write>, path: &str, content: &[u8]) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(get_timestamp().max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, path, content)?;
+    Ok(())
+}
+
+fn append_dir(archive: &mut Builder<impl io::Write>, path: &Path) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Directory);
+    header.set_size(0);
+    header.set_mode(0o755);
+    header.set_cksum();
+    archive.append_data(&mut header, archive_path(path), io::empty())?;
+    Ok(())
+}
+
+fn archive_path(path: &Path) -> &Path {
+    path.strip_prefix("/").unwrap_or(path)
+}
+
+/// Cgroup trees recorded: the pods', and the node services' next to them
+const CGROUP_TREES: [&str; 3] = ["kubepods", "kubepods.slice", "system.slice"];
+
+/// Files at the cgroup root, and every recorded tree under it (v2) or
+/// under each controller (v1); returns the number of files
+fn record_cgroups(archive: &mut Builder<impl io::Write>) -> Result<usize> {
+    let root = Path::new("/sys/fs/cgroup");
+    let Ok(entries) = fs::read_dir(root) else {
+        return Ok(0);
+    };
+    let mut files = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = fs::symlink_metadata(&path) else { continue };
+        if meta.file_type().is_symlink() {
+            // Relative aliases only; an absolute target would leave the snapshot
+            if fs::read_link(&path).is_ok_and(|target| target.is_relative()) {
+                archive.append_path_with_name(&path, archive_path(&path))?;
+            }
+        } else if meta.is_file() {
+            files += record_file(archive, &path)? as usize;
+        } else if meta.is_dir() {
+            let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            if name.starts_with("kubepods") || name == "system.slice" {
+                files += record_tree(archive, &path)?;
+            } else {
+                for tree in CGROUP_TREES {
+                    let tree = path.join(tree);
+                    if tree.is_dir() {
+                        files += record_tree(archive, &tree)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn record_tree(archive: &mut Builder<impl io::Write>, dir: &Path) -> Result<usize> {
+    append_dir(archive, dir)?;
+    let mut files = 0;
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => files += record_tree(archive, &path)?,
+            Ok(t) if t.is_file() => files += record_file(archive, &path)? as usize,
+            _ => {}
+        }
+    }
+    Ok(files)
+}
+
+/// Copies one readable cgroup file; write-only and oversized ones are skipped
+fn record_file(archive: &mut Builder<impl io::Write>, path: &Path) -> Result<bool> {
+    let mut content = Vec::new();
+    let read = File::open(path).and_then(|f| f.take(MAX_FILE_BYTES + 1).read_to_end(&mut content));
+    if read.is_err() || content.len() as u64 > MAX_FILE_BYTES {
+        return Ok(false);
+    }
+    append_bytes(archive, &archive_path(path).to_string_lossy(), &content)?;
+    Ok(true)
+}
+
+/// Directories of pods/<uid>/volumes/<driver>/<volume>[/mount]; returns the
+/// number of volumes
+fn record_kubelet_pods(archive: &mut Builder<impl io::Write>) -> Result<usize> {
+    let pods = Path::new("/var/lib/kubelet/pods");
+    if !pods.is_dir() {
+        return Ok(0);
+    }
+    append_dir(archive, pods)?;
+    let mut volumes = 0;
+    for pod in subdirs(pods) {
+        append_dir(archive, &pod)?;
+        let volumes_dir = pod.join("volumes");
+        if !volumes_dir.is_dir() {
+            continue;
+        }
+        append_dir(archive, &volumes_dir)?;
+        for driver in subdirs(&volumes_dir) {
+            append_dir(archive, &driver)?;
+            for volume in subdirs(&driver) {
+                append_dir(archive, &volume)?;
+                if volume.join("mount").is_dir() {
+                    append_dir(archive, &volume.join("mount"))?;
+                }
+                volumes += 1;
+            }
+        }
+    }
+    Ok(volumes)
+}
+
+fn subdirs(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(dir).into_iter().flatten().flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+}
+
+/// Opens a snapshot for replay: a directory is used as is, an archive is
+/// unpacked under the temp directory first
+pub fn open(path: &str) -> Result<Snapshot> {
+    let path = Path::new(path);
+    let root = if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let root = std::env::temp_dir().join(format!("vita-replay-{}", std::process::id()));
+        if root.exists() {
+            fs::remove_dir_all(&root)?;
+        }
+        tar::Archive::new(GzDecoder::new(file)).unpack(&root)
+            .with_context(|| format!("unpacking {}", path.display()))?;
+        root
+    };
+    if !root.join("proc").is_dir() && !root.join("sys").is_dir() {
+        bail!("{} is not a vita-agent snapshot", path.display());
+    }
+
+    let node = fs::read(root.join(MANIFEST)).ok()
+        .and_then(|m| serde_json::from_slice::<Manifest>(&m).ok())
+        .map(|m| m.node);
+    Ok(Snapshot { root, node })
+}
@@ -0,0 +1,90 @@
+use std::fs::File;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+#[cfg(windows)]
+use std::os::windows::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+/// A /proc or cgroup file kept open across collection cycles. procfs and
+/// kernfs regenerate their contents on every read from offset 0, so a pread
+/// loop on a persistent fd returns fresh data without the open/close pair.
+pub struct StatFile {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl StatFile {
+    /// The file is opened lazily on first read, and reopened after an error
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), file: None }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the whole file into `buf`, which only grows, so steady-state
+    /// reads do not allocate
+    pub fn read<'b>(&mut self, buf: &'b mut Vec<u8>) -> io::Result<&'b str> {
+        let result = self.read_inner(buf);
+        if result.is_err() {
+            self.file = None;
+        }
+        let len = result?;
+        std::str::from_utf8(&buf[..len]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn read_inner(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.file.is_none() {
+            self.file = Some(File::open(&self.path)?);
+        }
+        let file = self.file.as_ref().unwrap();
+
+        let mut len = 0;
+        loop {
+            if len == buf.len() {
+                buf.resize((buf.len() * 2).max(4096), 0);
+            }
+            #[cfg(unix)]
+            let n = file.read_at(&mut buf[len..], len as u64)?;
+            #[cfg(windows)]
+            let n = file.seek_read(&mut buf[len..], len as u64)?;
+            if n == 0 {
+                return Ok(len);
+            }
+            len += n;
+        }
+    }
+}
+
+/// True if the error means the file (or its cgroup) no longer exists. Reads
+/// through an fd whose cgroup was removed fail with ENODEV rather than ENOENT.
+pub fn is_gone(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::NotFound || e.raw_os_error() == Some(libc::ENODEV)
+}
+
+/// Raises the soft RLIMIT_NOFILE to the hard limit; every tracked cgroup
+/// holds a few fds open, which can exceed the default 1024 on dense nodes
+#[cfg(unix)]
+pub fn raise_nofile_limit() -> Option<u64> {
+    unsafe {
+        let mut limit: libc::rlimit = std::mem::zeroed();
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) != 0 {
+            return None;
+        }
+        if limit.rlim_cur < limit.rlim_max {
+            limit.rlim_cur = limit.rlim_max;
+            if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+                return None;
+            }
+        }
+        Some(limit.rlim_cur as u64)
+    }
+}
+
+/// Windows has no such limit
+#[cfg(not(unix))]
+pub fn raise_nofile_limit() -> Option<u64> {
+    None
+}
@@ -0,0 +1,95 @@
+use std::env;
+use tracing::info;
+
+use crate::host;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::statfile::StatFile;
+
+/// Read unless SYSCTL_GAUGES names others
+#[cfg_attr(windows, allow(dead_code))]
+const DEFAULT_SYSCTLS: &str = "kernel.random.entropy_avail,\
+    net.netfilter.nf_conntrack_count,\
+    net.netfilter.nf_conntrack_max,\
+    fs.file-nr,\
+    kernel.pid_max,\
+    kernel.threads-max";
+
+/// Kernel knobs under /proc/sys reported as gauges, keyed by their dotted
+/// names. Files holding several numbers (fs.file-nr) report the first under
+/// the name and the rest as name.1, name.2 and so on. Where a count and its
+/// limit are both read, their ratio also goes out: conntrack_used_pct,
+/// files_used_pct and tasks_used_pct, the last against /proc/loadavg.
+pub struct SysctlGauges {
+    sysctls: Vec<(String, StatFile)>,
+    loadavg: StatFile,
+    buf: Vec<u8>,
+}
+
+impl SysctlGauges {
+    /// SYSCTL_GAUGES, comma-separated, as dotted names or paths under
+    /// /proc/sys; empty for none
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn from_env() -> Self {
+        let list = env::var("SYSCTL_GAUGES").unwrap_or_else(|_| DEFAULT_SYSCTLS.to_string());
+        let sysctls: Vec<(String, StatFile)> = list.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                // Paths keep dots that belong to a component, as in VLAN interface names
+                let path = match s.strip_prefix("/proc/sys/") {
+                    Some(path) => path.to_string(),
+                    None if s.contains('/') => s.to_string(),
+                    None => s.replace('.', "/"),
+                };
+                (path.replace('/', "."), StatFile::new(host::path(&format!("/proc/sys/{}", path))))
+            })
+            .collect();
+        info!("Sysctl gauges: {}", sysctls.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(" "));
+        Self { sysctls, loadavg: StatFile::new(host::path("/proc/loadavg")), buf: Vec::new() }
+    }
+
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        if self.sysctls.is_empty() {
+            return;
+        }
+        let labels = Labels::default();
+        let mut values: Vec<(String, f64)> = Vec::new();
+        for (name, file) in &mut self.sysctls {
+            // Missing ones, like conntrack before its module loads, are skipped
+            let Ok(content) = file.read(&mut self.buf) else { continue };
+            for (i, field) in content.split_ascii_whitespace().enumerate() {
+                let Ok(value) = field.parse::<f64>() else { continue };
+                let key = if i == 0 { name.clone() } else { format!("{}.{}", name, i) };
+                values.push((key, value));
+            }
+        }
+
+        let get = |values: &[(String, f64)], key: &str| values.iter().find(|(k, _)| k == key).map(|(_, v)| *v);
+        let pct = |used: Option<f64>, max: Option<f64>| Some(used? * 100.0 / max.filter(|&m| m > 0.0)?);
+        // Fourth field of /proc/loadavg: runnable/total tasks
+        let tasks = self.loadavg.read(&mut self.buf).ok()
+            .and_then(|c| c.split_ascii_whitespace().nth(3)?.split_once('/')?.1.parse::<f64>().ok());
+        let limit = match (get(&values, "kernel.pid_max"), get(&values, "kernel.threads-max")) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let derived = [
+            ("conntrack_used_pct", pct(get(&values, "net.netfilter.nf_conntrack_count"), get(&values, "net.netfilter.nf_conntrack_max"))),
+            ("files_used_pct", pct(get(&values, "fs.file-nr"), get(&values, "fs.file-nr.2"))),
+            ("tasks_used_pct", pct(tasks, limit)),
+        ];
+        for (key, value) in derived {
+            if let Some(value) = value {
+                values.push((key.to_string(), value));
+            }
+        }
+
+        info!("METRIC_TYPE=node_sysctl node={} {}", node_name, values.iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(" "));
+        for (key, value) in &values {
+            sender.add("node_sysctl", &labels, key, *value);
+        }
+    }
+}
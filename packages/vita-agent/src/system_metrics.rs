@@ -1,124 +1,488 @@
-use anyhow::Result;
+use std::collections::HashMap;
 use std::fs;
 use tracing::info;
 
-pub fn collect_system_metrics(node_name: &str, _sender: &mut crate::metrics_sender::MetricsSender) -> Result<()> {
-    collect_cpu_metrics(node_name)?;
-    collect_memory_metrics(node_name)?;
-    collect_disk_metrics(node_name)?;
-    collect_network_metrics(node_name)?;
+use crate::capabilities::Capabilities;
+use crate::certs::CertExpiry;
+use crate::errors::{CollectorError, Result};
+use crate::host;
+use crate::infiniband::InfinibandCounters;
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::parsers;
+#[cfg(target_os = "linux")]
+use crate::qdisc::{Qdisc, QdiscReader};
+use crate::smart::SmartHealth;
+use crate::statfile::StatFile;
+use crate::sysctl::SysctlGauges;
+#[cfg(target_os = "linux")]
+use crate::thin_pool::{ThinPool, ThinPoolReader};
 
-    Ok(())
+/// Node-wide metrics from /proc, read through persistent fds into one
+/// reusable buffer. Files that were unreadable at startup are skipped.
+pub struct SystemCollector {
+    stat: Option<StatFile>,
+    meminfo: Option<StatFile>,
+    diskstats: Option<StatFile>,
+    net_dev: Option<StatFile>,
+    // Present once kube-proxy in IPVS mode loads the module
+    ip_vs_stats: Option<StatFile>,
+    ip_vs: Option<StatFile>,
+    arp: Option<StatFile>,
+    softnet: Option<StatFile>,
+    // Present once the md module loads
+    mdstat: Option<StatFile>,
+    infiniband: Option<InfinibandCounters>,
+    sysctls: Option<SysctlGauges>,
+    smart: Option<SmartHealth>,
+    certs: Option<CertExpiry>,
+    // gc_thresh1-3 of the IPv4 neighbor table
+    gc_thresh: [StatFile; 3],
+    // operstate of each interface and whether it was up, when readable
+    links: Option<HashMap<String, (StatFile, Option<bool>)>>,
+    // Dropped if netlink fails, as it will again
+    #[cfg(target_os = "linux")]
+    qdiscs: Option<QdiscReader>,
+    // Needs /dev/mapper/control, so a privileged agent
+    #[cfg(target_os = "linux")]
+    thin_pools: Option<ThinPoolReader>,
+    buf: Vec<u8>,
+    // Configured name prefixes to skip, on top of the built-in ones
+    exclude_devices: Vec<String>,
+    exclude_interfaces: Vec<String>,
 }
 
-fn collect_cpu_metrics(node_name: &str) -> Result<()> {
-    // Manually parse /proc/stat
-    let content = fs::read_to_string("/proc/stat")?;
-    for line in content.lines() {
-        if line.starts_with("cpu ") {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            // cpu user nice system idle iowait irq softirq steal guest guest_nice
-            if parts.len() >= 5 {
-                let user: u64 = parts[1].parse().unwrap_or(0);
-                let system: u64 = parts[3].parse().unwrap_or(0);
-                let idle: u64 = parts[4].parse().unwrap_or(0);
-                let iowait: u64 = parts.get(5).and_then(|s| s.parse().ok()).unwrap_or(0);
-                
-                info!("METRIC_TYPE=node_cpu node={} user={} sys={} idle={} iowait={}", 
-                    node_name, user, system, idle, iowait);
+impl SystemCollector {
+    pub fn new(caps: &Capabilities) -> Self {
+        let file = |available: bool, path: &str| available.then(|| StatFile::new(host::path(path)));
+        Self {
+            stat: file(caps.proc_stat, "/proc/stat"),
+            meminfo: file(caps.meminfo, "/proc/meminfo"),
+            diskstats: file(caps.diskstats, "/proc/diskstats"),
+            net_dev: file(caps.net_dev, "/proc/net/dev"),
+            ip_vs_stats: file(caps.net_dev, "/proc/net/ip_vs_stats"),
+            ip_vs: file(caps.net_dev, "/proc/net/ip_vs"),
+            arp: file(caps.net_dev, "/proc/net/arp"),
+            softnet: file(caps.net_dev, "/proc/net/softnet_stat"),
+            mdstat: file(caps.diskstats, "/proc/mdstat"),
+            infiniband: None,
+            sysctls: None,
+            smart: None,
+            certs: None,
+            gc_thresh: [1, 2, 3].map(|n| StatFile::new(host::path(&format!("/proc/sys/net/ipv4/neigh/default/gc_thresh{}", n)))),
+            links: caps.net_dev.then(HashMap::new),
+            #[cfg(target_os = "linux")]
+            qdiscs: (caps.net_dev && !host::replaying()).then(QdiscReader::new).and_then(|r| r.map_err(|e| {
+                info!("Qdisc stats unavailable: {}", e);
+            }).ok()),
+            #[cfg(target_os = "linux")]
+            thin_pools: (caps.diskstats && !host::replaying()).then(ThinPoolReader::new).and_then(|r| r.map_err(|e| {
+                info!("Thin pool stats unavailable: {}", e);
+            }).ok()),
+            buf: Vec::new(),
+            exclude_devices: Vec::new(),
+            exclude_interfaces: Vec::new(),
+        }
+    }
+
+    // Only bench uses this collector on Windows
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn set_filters(&mut self, devices: &[String], interfaces: &[String]) {
+        self.exclude_devices = devices.to_vec();
+        self.exclude_interfaces = interfaces.to_vec();
+    }
+
+    /// Port counters of InfiniBand and RoCE adapters, off by default
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn set_infiniband(&mut self, enabled: bool) {
+        self.infiniband = enabled.then(InfinibandCounters::discover);
+    }
+
+    /// Kernel knobs under /proc/sys to report as gauges
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn set_sysctls(&mut self, sysctls: SysctlGauges) {
+        self.sysctls = Some(sysctls);
+    }
+
+    /// SMART health of the node's disks, from smartctl in the background
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn set_smart(&mut self, smart: Option<SmartHealth>) {
+        self.smart = smart;
+    }
+
+    /// Expiry of the kubelet's certificates and any others configured
+    #[cfg_attr(windows, allow(dead_code))]
+    pub fn set_certs(&mut self, certs: CertExpiry) {
+        self.certs = Some(certs);
+    }
+
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        self.collect_core(node_name, sender)?;
+        let ipvs = self.ip_vs_stats.as_mut().and_then(|f| parsers::ip_vs_stats(f.read(&mut self.buf).ok()?));
+        if let Some(stats) = ipvs {
+            let services = self.ip_vs.as_mut().and_then(|f| f.read(&mut self.buf).ok());
+            collect_ipvs_metrics(&stats, services, node_name, sender);
+        }
+        if let Some(Ok(content)) = self.arp.as_mut().map(|f| f.read(&mut self.buf)) {
+            let entries = parsers::arp_entries(content);
+            let buf = &mut self.buf;
+            let thresholds = self.gc_thresh.each_mut().map(|f| f.read(buf).ok().and_then(|v| v.trim().parse().ok()));
+            collect_neighbor_metrics(entries, thresholds, node_name, sender);
+        }
+        if let Some(Ok(content)) = self.softnet.as_mut().map(|f| f.read(&mut self.buf)) {
+            collect_softnet_metrics(content, node_name, sender);
+        }
+        if let Some(links) = &mut self.links {
+            check_link_states(links, &self.exclude_interfaces, &mut self.buf, sender);
+        }
+        if let Some(Ok(content)) = self.mdstat.as_mut().map(|f| f.read(&mut self.buf)) {
+            collect_mdraid_metrics(content, node_name, sender);
+        }
+        if let Some(smart) = &self.smart {
+            smart.collect(node_name, sender);
+        }
+        if let Some(certs) = &mut self.certs {
+            certs.collect(node_name, sender);
+        }
+        if let Some(sysctls) = &mut self.sysctls {
+            sysctls.collect(node_name, sender);
+        }
+        if let Some(infiniband) = &mut self.infiniband {
+            infiniband.collect(node_name, sender);
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(reader) = &mut self.qdiscs {
+            match reader.read() {
+                Ok(qdiscs) => collect_qdisc_metrics(&qdiscs, &self.exclude_interfaces, node_name, sender),
+                Err(e) => {
+                    info!("Qdisc stats unavailable, not reading them: {}", e);
+                    self.qdiscs = None;
+                }
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(reader) = &mut self.thin_pools {
+            match reader.read() {
+                Ok(pools) => collect_thin_pool_metrics(&pools, node_name, sender),
+                Err(e) => {
+                    info!("Thin pool stats unavailable, not reading them: {}", e);
+                    self.thin_pools = None;
+                }
             }
-            break;
         }
+
+        Ok(())
+    }
+
+    /// CPU, memory, disk and network counters alone, cheap enough to read
+    /// at burst rate
+    pub fn collect_core(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        if let Some(stat) = &mut self.stat {
+            let content = stat.read(&mut self.buf).map_err(|e| CollectorError::read(stat.path(), e))?;
+            collect_cpu_metrics(content, node_name, sender)?;
+        }
+        if let Some(meminfo) = &mut self.meminfo {
+            let content = meminfo.read(&mut self.buf).map_err(|e| CollectorError::read(meminfo.path(), e))?;
+            collect_memory_metrics(content, node_name, sender)?;
+        }
+        if let Some(Ok(content)) = self.diskstats.as_mut().map(|f| f.read(&mut self.buf)) {
+            collect_disk_metrics(content, &self.exclude_devices, node_name, sender);
+        }
+        if let Some(Ok(content)) = self.net_dev.as_mut().map(|f| f.read(&mut self.buf)) {
+            collect_network_metrics(content, &self.exclude_interfaces, node_name, sender);
+        }
+
+        Ok(())
+    }
+}
+
+fn collect_cpu_metrics(content: &str, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+    // Boot time changes across reboots, which reset every node counter
+    if let Some(btime) = parsers::proc_stat_btime(content) {
+        info!("METRIC_TYPE=node_boot node={} boot_time={}", node_name, btime);
+        sender.add("node_boot", &Labels::default(), "boot_time", btime as f64);
     }
+
+    let cpu = parsers::proc_stat_cpu(content)
+        .ok_or_else(|| CollectorError::Parse("the cpu line of /proc/stat".to_string()))?;
+    info!("METRIC_TYPE=node_cpu node={} user={} sys={} idle={} iowait={}", 
+        node_name, cpu.user, cpu.system, cpu.idle, cpu.iowait);
+
+    let labels = Labels::default();
+    sender.add_counter("node_cpu", &labels, "user", cpu.user as f64);
+    sender.add_counter("node_cpu", &labels, "sys", cpu.system as f64);
+    sender.add_counter("node_cpu", &labels, "idle", cpu.idle as f64);
+    sender.add_counter("node_cpu", &labels, "iowait", cpu.iowait as f64);
     Ok(())
 }
 
-fn collect_memory_metrics(node_name: &str) -> Result<()> {
-    let content = fs::read_to_string("/proc/meminfo")?;
-    let mut total = 0;
-    let mut free = 0;
-    let mut available = 0;
-    let mut swap_total = 0;
-    let mut swap_free = 0;
-
-    for line in content.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 2 {
-            let value: u64 = parts[1].parse().unwrap_or(0);
-            match parts[0] {
-                "MemTotal:" => total = value,
-                "MemFree:" => free = value,
-                "MemAvailable:" => available = value,
-                "SwapTotal:" => swap_total = value,
-                "SwapFree:" => swap_free = value,
-                _ => {}
-            }
-        }
+fn collect_memory_metrics(content: &str, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+    let parsers::MemInfo { total, free, available, swap_total, swap_free } = parsers::meminfo(content);
+    if total == 0 {
+        return Err(CollectorError::Parse("MemTotal in /proc/meminfo".to_string()));
     }
-    
+
     let used = total.saturating_sub(free);
     info!("METRIC_TYPE=node_mem node={} total_mb={} used_mb={} free_mb={} avail_mb={}", 
         node_name, total / 1024, used / 1024, free / 1024, available / 1024);
 
+    let labels = Labels::default();
+    sender.add("node_mem", &labels, "total_mb", (total / 1024) as f64);
+    sender.add("node_mem", &labels, "used_mb", (used / 1024) as f64);
+    sender.add("node_mem", &labels, "free_mb", (free / 1024) as f64);
+    sender.add("node_mem", &labels, "avail_mb", (available / 1024) as f64);
+
     if swap_total > 0 {
         let swap_used = swap_total.saturating_sub(swap_free);
         info!("METRIC_TYPE=node_swap node={} total_mb={} used_mb={}", 
             node_name, swap_total / 1024, swap_used / 1024);
-    }
 
+        sender.add("node_swap", &labels, "total_mb", (swap_total / 1024) as f64);
+        sender.add("node_swap", &labels, "used_mb", (swap_used / 1024) as f64);
+    }
     Ok(())
 }
 
-fn collect_disk_metrics(node_name: &str) -> Result<()> {
-    if let Ok(content) = fs::read_to_string("/proc/diskstats") {
-        for line in content.lines() {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            // major minor name reads_success reads_merged sectors_read time_read writes_success ...
-            if parts.len() >= 14 {
-                let name = parts[2];
-                if name.starts_with("loop") || name.starts_with("ram") { continue; }
-                
-                let reads: u64 = parts[3].parse().unwrap_or(0);
-                let sectors_read: u64 = parts[5].parse().unwrap_or(0);
-                let writes: u64 = parts[7].parse().unwrap_or(0);
-                let sectors_written: u64 = parts[9].parse().unwrap_or(0);
-
-                if reads > 0 || writes > 0 {
-                    info!("METRIC_TYPE=node_disk node={} device={} reads={} writes={} sectors_r={} sectors_w={}", 
-                        node_name, name, reads, writes, sectors_read, sectors_written);
-                }
-            }
+fn excluded(name: &str, prefixes: &[String]) -> bool {
+    prefixes.iter().any(|p| name.starts_with(p.as_str()))
+}
+
+fn collect_disk_metrics(content: &str, exclude: &[String], node_name: &str, sender: &mut MetricsSender) {
+    for disk in parsers::diskstats(content) {
+        let name = disk.name;
+        if name.starts_with("loop") || name.starts_with("ram") || excluded(name, exclude) { continue; }
+
+        if disk.reads > 0 || disk.writes > 0 {
+            info!("METRIC_TYPE=node_disk node={} device={} reads={} writes={} sectors_r={} sectors_w={}", 
+                node_name, name, disk.reads, disk.writes, disk.sectors_read, disk.sectors_written);
+
+            let labels = Labels { device: Some(name), ..Default::default() };
+            sender.add_counter("node_disk", &labels, "reads", disk.reads as f64);
+            sender.add_counter("node_disk", &labels, "writes", disk.writes as f64);
+            sender.add_counter("node_disk", &labels, "sectors_r", disk.sectors_read as f64);
+            sender.add_counter("node_disk", &labels, "sectors_w", disk.sectors_written as f64);
         }
     }
-    Ok(())
 }
 
-fn collect_network_metrics(node_name: &str) -> Result<()> {
-    // Manually parse /proc/net/dev
-    // Skip header lines
-    if let Ok(content) = fs::read_to_string("/proc/net/dev") {
-        for line in content.lines().skip(2) {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 17 {
-                let name = parts[0].trim_end_matches(':');
-                // Skip loopback and veth interfaces to reduce noise
-                if name == "lo" || name.starts_with("veth") { continue; }
-                
-                // rx_bytes packets errs drop fifo frame compressed multicast | tx_bytes packets ...
-                let rx_bytes: u64 = parts[1].parse().unwrap_or(0);
-                let rx_packets: u64 = parts[2].parse().unwrap_or(0);
-                let rx_errs: u64 = parts[3].parse().unwrap_or(0);
-                
-                let tx_bytes: u64 = parts[9].parse().unwrap_or(0);
-                let tx_packets: u64 = parts[10].parse().unwrap_or(0);
-                let tx_errs: u64 = parts[11].parse().unwrap_or(0);
-
-                if rx_bytes > 0 || tx_bytes > 0 {
-                    info!("METRIC_TYPE=node_net node={} interface={} rx_bytes={} tx_bytes={} rx_pkts={} tx_pkts={} rx_errs={} tx_errs={}", 
-                        node_name, name, rx_bytes, tx_bytes, rx_packets, tx_packets, rx_errs, tx_errs);
-                }
-            }
+fn collect_network_metrics(content: &str, exclude: &[String], node_name: &str, sender: &mut MetricsSender) {
+    for net in parsers::net_dev(content) {
+        let name = net.name;
+        // Skip loopback and veth interfaces to reduce noise
+        if name == "lo" || name.starts_with("veth") || excluded(name, exclude) { continue; }
+
+        if net.rx_bytes > 0 || net.tx_bytes > 0 {
+            info!("METRIC_TYPE=node_net node={} interface={} rx_bytes={} tx_bytes={} rx_pkts={} tx_pkts={} rx_errs={} tx_errs={}", 
+                node_name, name, net.rx_bytes, net.tx_bytes, net.rx_packets, net.tx_packets, net.rx_errs, net.tx_errs);
+
+            let labels = Labels { device: Some(name), ..Default::default() };
+            sender.add_counter("node_net", &labels, "rx_bytes", net.rx_bytes as f64);
+            sender.add_counter("node_net", &labels, "tx_bytes", net.tx_bytes as f64);
+            sender.add_counter("node_net", &labels, "rx_pkts", net.rx_packets as f64);
+            sender.add_counter("node_net", &labels, "tx_pkts", net.tx_packets as f64);
+            sender.add_counter("node_net", &labels, "rx_errs", net.rx_errs as f64);
+            sender.add_counter("node_net", &labels, "tx_errs", net.tx_errs as f64);
+        }
+    }
+}
+
+/// Interfaces whose operstate went down or came back since the last cycle.
+/// "unknown" counts as up: tun devices and bridges without carrier
+/// detection report it while passing traffic.
+fn check_link_states(links: &mut HashMap<String, (StatFile, Option<bool>)>, exclude: &[String], buf: &mut Vec<u8>, sender: &mut MetricsSender) {
+    let Ok(entries) = fs::read_dir(host::path("/sys/class/net")) else { return };
+    let names: Vec<String> = entries.flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| name != "lo" && !name.starts_with("veth") && !excluded(name, exclude))
+        .collect();
+    links.retain(|name, _| names.contains(name));
+
+    for name in names {
+        let (file, was_up) = links.entry(name.clone()).or_insert_with(|| {
+            (StatFile::new(host::path(&format!("/sys/class/net/{}/operstate", name))), None)
+        });
+        let Ok(state) = file.read(buf).map(|s| s.trim().to_string()) else { continue };
+        let up = !matches!(state.as_str(), "down" | "lowerlayerdown" | "notpresent");
+        if was_up.is_some_and(|was| was != up) {
+            let labels = Labels { device: Some(&name), ..Default::default() };
+            let kind = if up { "interface_up" } else { "interface_down" };
+            sender.event(kind, &labels, format!("{} is {}", name, state));
+        }
+        *was_up = Some(up);
+    }
+}
+
+/// Packet processing in softirq per CPU. Drops here happen before any
+/// socket sees the packet, when the backlog queue is full; time squeezes
+/// mean a CPU ran out of budget with packets still waiting. Both point at
+/// interrupt or RPS placement rather than the application. The node totals
+/// go out as node_softnet, each CPU as node_softnet_cpu.
+fn collect_softnet_metrics(content: &str, node_name: &str, sender: &mut MetricsSender) {
+    let (mut processed, mut dropped, mut squeezed) = (0, 0, 0);
+    for cpu in parsers::softnet_stat(content) {
+        processed += cpu.processed;
+        dropped += cpu.dropped;
+        squeezed += cpu.time_squeeze;
+
+        let name = format!("cpu{}", cpu.cpu);
+        let labels = Labels { device: Some(&name), ..Default::default() };
+        sender.add_counter("node_softnet_cpu", &labels, "processed", cpu.processed as f64);
+        sender.add_counter("node_softnet_cpu", &labels, "dropped", cpu.dropped as f64);
+        sender.add_counter("node_softnet_cpu", &labels, "time_squeeze", cpu.time_squeeze as f64);
+        sender.add_counter("node_softnet_cpu", &labels, "received_rps", cpu.received_rps as f64);
+        sender.add_counter("node_softnet_cpu", &labels, "flow_limit", cpu.flow_limit as f64);
+    }
+    info!("METRIC_TYPE=node_softnet node={} processed={} dropped={} time_squeeze={}",
+        node_name, processed, dropped, squeezed);
+
+    let labels = Labels::default();
+    sender.add_counter("node_softnet", &labels, "processed", processed as f64);
+    sender.add_counter("node_softnet", &labels, "dropped", dropped as f64);
+    sender.add_counter("node_softnet", &labels, "time_squeeze", squeezed as f64);
+}
+
+/// State of each md array. An array is degraded when fewer members are in
+/// sync than it has slots, and stays so through the rebuild; the sync
+/// progress goes out as sync_pct while a recovery, resync, reshape or check
+/// runs.
+fn collect_mdraid_metrics(content: &str, node_name: &str, sender: &mut MetricsSender) {
+    for array in parsers::mdstat(content) {
+        let degraded = !array.active || array.failed > 0
+            || matches!((array.disks, array.in_sync), (Some(disks), Some(in_sync)) if in_sync < disks);
+        let (action, sync_pct) = array.sync.unwrap_or(("idle", 100.0));
+        info!("METRIC_TYPE=node_mdraid node={} device={} level={} active={} degraded={} disks={} in_sync={} failed={} spares={} sync={} sync_pct={}",
+            node_name, array.name, array.level, array.active as u8, degraded as u8, array.disks.unwrap_or(array.members),
+            array.in_sync.unwrap_or(array.members - array.failed - array.spares), array.failed, array.spares, action, sync_pct);
+
+        let labels = Labels { device: Some(array.name), ..Default::default() };
+        sender.add("node_mdraid", &labels, "active", array.active as u8 as f64);
+        sender.add("node_mdraid", &labels, "degraded", degraded as u8 as f64);
+        sender.add("node_mdraid", &labels, "failed", array.failed as f64);
+        sender.add("node_mdraid", &labels, "spares", array.spares as f64);
+        if let (Some(disks), Some(in_sync)) = (array.disks, array.in_sync) {
+            sender.add("node_mdraid", &labels, "disks", disks as f64);
+            sender.add("node_mdraid", &labels, "in_sync", in_sync as f64);
+        }
+        sender.add("node_mdraid", &labels, "sync_pct", sync_pct);
+    }
+}
+
+/// Data and metadata usage of each thin pool. A pool that runs out of
+/// either starts failing writes, or queues them until it's extended, for
+/// every thin volume in it at once; `full` is set once it has.
+#[cfg(target_os = "linux")]
+fn collect_thin_pool_metrics(pools: &[ThinPool], node_name: &str, sender: &mut MetricsSender) {
+    let pct = |used: u64, total: u64| if total > 0 { used as f64 * 100.0 / total as f64 } else { 0.0 };
+    for pool in pools {
+        let data_pct = pct(pool.data_used, pool.data_total);
+        let metadata_pct = pct(pool.metadata_used, pool.metadata_total);
+        let full = pool.out_of_data_space || (!pool.failed && (data_pct >= 100.0 || metadata_pct >= 100.0));
+        info!("METRIC_TYPE=node_thinpool node={} device={} data_used_pct={:.1} metadata_used_pct={:.1} full={} read_only={} needs_check={} failed={}",
+            node_name, pool.name, data_pct, metadata_pct, full as u8, pool.read_only as u8, pool.needs_check as u8, pool.failed as u8);
+
+        let labels = Labels { device: Some(&pool.name), ..Default::default() };
+        if !pool.failed {
+            sender.add("node_thinpool", &labels, "data_used_pct", data_pct);
+            sender.add("node_thinpool", &labels, "metadata_used_pct", metadata_pct);
+        }
+        sender.add("node_thinpool", &labels, "full", full as u8 as f64);
+        sender.add("node_thinpool", &labels, "read_only", pool.read_only as u8 as f64);
+        sender.add("node_thinpool", &labels, "needs_check", pool.needs_check as u8 as f64);
+        sender.add("node_thinpool", &labels, "failed", pool.failed as u8 as f64);
+    }
+}
+
+/// Size of the IPv4 neighbor table against its garbage collection
+/// thresholds. Past gc_thresh3 the kernel refuses new entries ("neighbour
+/// table overflow"), and nodes talking to many pods or hosts on flat
+/// networks lose connectivity to some of them.
+fn collect_neighbor_metrics((complete, incomplete): (u64, u64), thresholds: [Option<u64>; 3], node_name: &str, sender: &mut MetricsSender) {
+    let entries = complete + incomplete;
+    let show = |t: Option<u64>| t.map_or("-".to_string(), |t| t.to_string());
+    info!("METRIC_TYPE=node_neigh node={} entries={} incomplete={} gc_thresh1={} gc_thresh2={} gc_thresh3={}",
+        node_name, entries, incomplete, show(thresholds[0]), show(thresholds[1]), show(thresholds[2]));
+
+    let labels = Labels::default();
+    sender.add("node_neigh", &labels, "entries", entries as f64);
+    sender.add("node_neigh", &labels, "incomplete", incomplete as f64);
+    for (key, threshold) in ["gc_thresh1", "gc_thresh2", "gc_thresh3"].into_iter().zip(thresholds) {
+        if let Some(threshold) = threshold {
+            sender.add("node_neigh", &labels, key, threshold as f64);
+        }
+    }
+    if let Some(max) = thresholds[2].filter(|&t| t > 0) {
+        sender.add("node_neigh", &labels, "used_pct", entries as f64 * 100.0 / max as f64);
+    }
+}
+
+/// IPVS totals and, for services with connections, their active and
+/// inactive connections and backends. kube-proxy in IPVS mode programs one
+/// virtual service per ClusterIP and port, so a saturated service shows up
+/// as its active connections climbing.
+fn collect_ipvs_metrics(stats: &parsers::IpvsStats, services: Option<&str>, node_name: &str, sender: &mut MetricsSender) {
+    let (mut count, mut active, mut inactive) = (0, 0, 0);
+    let mut busy = Vec::new();
+    for service in services.into_iter().flat_map(parsers::ip_vs) {
+        count += 1;
+        active += service.active_conns;
+        inactive += service.inactive_conns;
+        if service.active_conns > 0 || service.inactive_conns > 0 {
+            busy.push(service);
+        }
+    }
+    info!("METRIC_TYPE=node_ipvs node={} conns={} in_pkts={} out_pkts={} services={} active_conns={} inactive_conns={}",
+        node_name, stats.conns, stats.in_packets, stats.out_packets, count, active, inactive);
+
+    let labels = Labels::default();
+    sender.add_counter("node_ipvs", &labels, "conns", stats.conns as f64);
+    sender.add_counter("node_ipvs", &labels, "in_pkts", stats.in_packets as f64);
+    sender.add_counter("node_ipvs", &labels, "out_pkts", stats.out_packets as f64);
+    sender.add_counter("node_ipvs", &labels, "in_bytes", stats.in_bytes as f64);
+    sender.add_counter("node_ipvs", &labels, "out_bytes", stats.out_bytes as f64);
+    sender.add("node_ipvs", &labels, "services", count as f64);
+    sender.add("node_ipvs", &labels, "active_conns", active as f64);
+    sender.add("node_ipvs", &labels, "inactive_conns", inactive as f64);
+
+    for service in busy {
+        let name = format!("{} {}", service.protocol, ipvs_address(service.address));
+        let labels = Labels { device: Some(&name), ..Default::default() };
+        sender.add("ipvs_service", &labels, "active_conns", service.active_conns as f64);
+        sender.add("ipvs_service", &labels, "inactive_conns", service.inactive_conns as f64);
+        sender.add("ipvs_service", &labels, "backends", service.backends as f64);
+    }
+}
+
+/// 0A600001:01BB as 10.96.0.1:443; IPv6 addresses are printed readable
+/// already, with only the port in hex, and firewall marks are left alone
+fn ipvs_address(address: &str) -> String {
+    let Some((ip, port)) = address.rsplit_once(':') else { return address.to_string() };
+    let Ok(port) = u16::from_str_radix(port, 16) else { return address.to_string() };
+    match u32::from_str_radix(ip, 16) {
+        Ok(v4) if ip.len() == 8 => format!("{}:{}", std::net::Ipv4Addr::from(v4), port),
+        _ => format!("{}:{}", ip, port),
+    }
+}
+
+/// Drops and overlimits of the root qdisc of each interface, which byte
+/// counters don't show: shaping by the bandwidth CNI plugin, full fq_codel
+/// queues. Interfaces without a queue (noqueue, the veth default) are skipped.
+#[cfg(target_os = "linux")]
+fn collect_qdisc_metrics(qdiscs: &[Qdisc], exclude: &[String], node_name: &str, sender: &mut MetricsSender) {
+    for q in qdiscs {
+        let name = q.interface.as_str();
+        if name == "lo" || q.kind == "noqueue" || excluded(name, exclude) { continue; }
+
+        if q.packets > 0 || q.drops > 0 {
+            info!("METRIC_TYPE=node_qdisc node={} interface={} kind={} drops={} overlimits={} requeues={} backlog={} qlen={}",
+                node_name, name, q.kind, q.drops, q.overlimits, q.requeues, q.backlog, q.qlen);
+
+            let labels = Labels { device: Some(name), ..Default::default() };
+            sender.add_counter("node_qdisc", &labels, "bytes", q.bytes as f64);
+            sender.add_counter("node_qdisc", &labels, "packets", q.packets as f64);
+            sender.add_counter("node_qdisc", &labels, "drops", q.drops as f64);
+            sender.add_counter("node_qdisc", &labels, "overlimits", q.overlimits as f64);
+            sender.add_counter("node_qdisc", &labels, "requeues", q.requeues as f64);
+            sender.add("node_qdisc", &labels, "backlog_bytes", q.backlog as f64);
+            sender.add("node_qdisc", &labels, "qlen", q.qlen as f64);
         }
     }
-    Ok(())
 }
@@ -0,0 +1,119 @@
+use std::env;
+use std::fmt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Readiness, status and watchdog pings to systemd over NOTIFY_SOCKET, for
+/// a `Type=notify` unit. The watchdog is pinged from the collection loop,
+/// so a loop that hangs gets the agent restarted.
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    // Half of WatchdogSec, when the unit sets one for us
+    ping_every: Option<Duration>,
+    last_ping: Instant,
+    ready: bool,
+}
+
+impl Notifier {
+    /// None unless systemd started us with a notification socket
+    pub fn from_env() -> Option<Self> {
+        let path = env::var("NOTIFY_SOCKET").ok().filter(|p| !p.is_empty())?;
+        let addr = match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => return None,
+            None => SocketAddr::from_pathname(&path),
+        };
+        let (addr, socket) = match addr.and_then(|addr| Ok((addr, UnixDatagram::unbound()?))) {
+            Ok(bound) => bound,
+            Err(e) => {
+                tracing::warn!("Cannot notify systemd on {}: {}", path, e);
+                return None;
+            }
+        };
+
+        // WATCHDOG_PID names the process the watchdog is for, when set
+        let ours = env::var("WATCHDOG_PID").ok()
+            .and_then(|p| p.parse::<u32>().ok())
+            .is_none_or(|pid| pid == std::process::id());
+        let ping_every = env::var("WATCHDOG_USEC").ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|&usec| usec > 0 && ours)
+            .map(|usec| Duration::from_micros(usec / 2));
+        info!("systemd notifications on {} | watchdog={:?}", path, ping_every);
+        Some(Self { socket, addr, ping_every, last_ping: Instant::now(), ready: false })
+    }
+
+    fn notify(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            debug!("systemd notification failed: {}", e);
+        }
+    }
+
+    /// Tells systemd the agent is up, once its first cycle is through, and
+    /// what it is doing from then on
+    pub fn status(&mut self, status: &str) {
+        if self.ready {
+            self.notify(&format!("STATUS={}", status));
+        } else {
+            self.notify(&format!("READY=1\nSTATUS={}", status));
+            self.ready = true;
+        }
+    }
+
+    /// When the watchdog is next due a ping
+    pub fn next_ping(&self) -> Option<Instant> {
+        self.ping_every.map(|every| self.last_ping + every)
+    }
+
+    /// Pings the watchdog if it's due
+    pub fn ping(&mut self) {
+        if self.next_ping().is_some_and(|due| due <= Instant::now()) {
+            self.notify("WATCHDOG=1");
+            self.last_ping = Instant::now();
+        }
+    }
+}
+
+/// Whether file descriptor `fd` is the journal stream systemd connected,
+/// per JOURNAL_STREAM ("device:inode")
+pub fn logs_to_journal(fd: i32) -> bool {
+    let Ok(stream) = env::var("JOURNAL_STREAM") else { return false };
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+        return false;
+    }
+    stream == format!("{}:{}", stat.st_dev, stat.st_ino)
+}
+
+/// Log lines for journald, which stamps them itself and takes their
+/// priority from a `<N>` prefix: no timestamp, no colors, just the level
+/// as a syslog priority and the message
+pub struct JournalFormat;
+
+impl<S, N> FormatEvent<S, N> for JournalFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let priority = match *event.metadata().level() {
+            Level::ERROR => 3,
+            Level::WARN => 4,
+            Level::INFO => 6,
+            Level::DEBUG | Level::TRACE => 7,
+        };
+        write!(writer, "<{}>", priority)?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
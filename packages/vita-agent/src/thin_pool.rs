@@ -0,0 +1,167 @@
+use std::ffi::CStr;
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::AsRawFd;
+
+use crate::host;
+
+/// DM_TABLE_STATUS: _IOWR(0xfd, 12, struct dm_ioctl)
+const DM_TABLE_STATUS: libc::c_ulong = 0xc138_fd0c;
+const DM_VERSION: [u32; 3] = [4, 0, 0];
+/// Don't commit pool metadata just to report on it
+const DM_NOFLUSH_FLAG: u32 = 1 << 11;
+const DM_BUFFER_FULL_FLAG: u32 = 1 << 8;
+
+/// struct dm_ioctl, then the dm_target_spec records
+const DM_IOCTL_LEN: usize = 312;
+const DM_NAME_LEN: usize = 128;
+const TARGET_SPEC_LEN: usize = 40;
+
+/// Usage of one thin pool, from the thin-pool target's status line
+pub struct ThinPool {
+    pub name: String,
+    pub data_used: u64,
+    pub data_total: u64,
+    pub metadata_used: u64,
+    pub metadata_total: u64,
+    // Mode ro, which the pool falls back to when its metadata fails
+    pub read_only: bool,
+    pub out_of_data_space: bool,
+    pub needs_check: bool,
+    // Status "Fail": the pool is unusable
+    pub failed: bool,
+}
+
+/// Device-mapper thin pools, LVM's among them, through the ioctls dmsetup
+/// uses on /dev/mapper/control. Devices are listed from /sys/block each
+/// read, so pools created later are picked up.
+pub struct ThinPoolReader {
+    control: File,
+    buf: Vec<u8>,
+}
+
+impl ThinPoolReader {
+    pub fn new() -> io::Result<Self> {
+        let control = File::open(host::path("/dev/mapper/control"))?;
+        Ok(Self { control, buf: vec![0; 16 * 1024] })
+    }
+
+    pub fn read(&mut self) -> io::Result<Vec<ThinPool>> {
+        let mut pools = Vec::new();
+        for entry in fs::read_dir(host::path("/sys/block"))?.flatten() {
+            if !entry.file_name().as_encoded_bytes().starts_with(b"dm-") {
+                continue;
+            }
+            let Ok(name) = fs::read_to_string(entry.path().join("dm/name")) else { continue };
+            if let Some(pool) = self.status(name.trim())? {
+                pools.push(pool);
+            }
+        }
+        pools.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(pools)
+    }
+
+    /// Status of the named device; None unless it is a thin pool or it
+    /// went away since it was listed
+    fn status(&mut self, name: &str) -> io::Result<Option<ThinPool>> {
+        if name.len() >= DM_NAME_LEN {
+            return Ok(None);
+        }
+        loop {
+            let size = self.buf.len();
+            self.buf[..DM_IOCTL_LEN].fill(0);
+            for (i, v) in DM_VERSION.iter().enumerate() {
+                put_u32(&mut self.buf, i * 4, *v);
+            }
+            put_u32(&mut self.buf, 12, size as u32);
+            put_u32(&mut self.buf, 16, DM_IOCTL_LEN as u32);
+            put_u32(&mut self.buf, 28, DM_NOFLUSH_FLAG);
+            self.buf[48..48 + name.len()].copy_from_slice(name.as_bytes());
+
+            let result = unsafe { libc::ioctl(self.control.as_raw_fd(), DM_TABLE_STATUS as _, self.buf.as_mut_ptr()) };
+            if result < 0 {
+                let e = io::Error::last_os_error();
+                return match e.raw_os_error() {
+                    Some(libc::ENXIO) => Ok(None),
+                    _ => Err(e),
+                };
+            }
+            if u32_at(&self.buf, 28) & DM_BUFFER_FULL_FLAG != 0 {
+                let grown = self.buf.len() * 2;
+                self.buf.resize(grown, 0);
+                continue;
+            }
+            break;
+        }
+
+        let targets = u32_at(&self.buf, 20);
+        let start = u32_at(&self.buf, 16) as usize;
+        let end = (u32_at(&self.buf, 12) as usize).min(self.buf.len());
+        let data = &self.buf[start.min(end)..end];
+        // dm_target_spec: sector_start, length, status, next (offset of the
+        // next spec from the start of the data), target_type[16]; then
+        // the status line
+        let mut at = 0;
+        for _ in 0..targets {
+            let Some(spec) = data.get(at..at + TARGET_SPEC_LEN) else { break };
+            let next = u32_at(spec, 20) as usize;
+            let kind = CStr::from_bytes_until_nul(&spec[24..40]).ok();
+            if kind.is_some_and(|k| k.to_bytes() == b"thin-pool") {
+                let params = data.get(at + TARGET_SPEC_LEN..).unwrap_or_default();
+                let params = CStr::from_bytes_until_nul(params).ok();
+                return Ok(params.and_then(|p| parse(name, &p.to_string_lossy())));
+            }
+            if next <= at {
+                break;
+            }
+            at = next;
+        }
+        Ok(None)
+    }
+}
+
+/// Thin-pool status: `<transaction id> <used>/<total metadata blocks>
+/// <used>/<total data blocks> <held root> ro|rw|out_of_data_space
+/// [no_]discard_passdown [error|queue]_if_no_space needs_check|- ...`,
+/// or `Fail`
+fn parse(name: &str, status: &str) -> Option<ThinPool> {
+    let mut pool = ThinPool {
+        name: name.to_string(),
+        data_used: 0,
+        data_total: 0,
+        metadata_used: 0,
+        metadata_total: 0,
+        read_only: false,
+        out_of_data_space: false,
+        needs_check: false,
+        failed: false,
+    };
+    let mut fields = status.split_ascii_whitespace();
+    let first = fields.next()?;
+    if first == "Fail" || first == "Error" {
+        pool.failed = true;
+        return Some(pool);
+    }
+    let blocks = |field: Option<&str>| -> Option<(u64, u64)> {
+        let (used, total) = field?.split_once('/')?;
+        Some((used.parse().ok()?, total.parse().ok()?))
+    };
+    (pool.metadata_used, pool.metadata_total) = blocks(fields.next())?;
+    (pool.data_used, pool.data_total) = blocks(fields.next())?;
+    let mut fields = fields.skip(1);
+    match fields.next() {
+        Some("ro") => pool.read_only = true,
+        Some("out_of_data_space") => pool.out_of_data_space = true,
+        _ => {}
+    }
+    pool.needs_check = fields.nth(2) == Some("needs_check");
+    Some(pool)
+}
+
+fn put_u32(buf: &mut [u8], at: usize, value: u32) {
+    buf[at..at + 4].copy_from_slice(&value.to_ne_bytes());
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_ne_bytes(buf[at..at + 4].try_into().unwrap())
+}
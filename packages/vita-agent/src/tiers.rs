@@ -0,0 +1,82 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use tracing::warn;
+
+/// How much a metric type matters when the agent has to cut back. Ordered
+/// from the first to go to the last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tier {
+    BestEffort,
+    Standard,
+    Critical,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 3] = [Tier::BestEffort, Tier::Standard, Tier::Critical];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Tier::BestEffort => "best_effort",
+            Tier::Standard => "standard",
+            Tier::Critical => "critical",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "critical" => Some(Tier::Critical),
+            "standard" => Some(Tier::Standard),
+            "best-effort" | "best_effort" => Some(Tier::BestEffort),
+            _ => None,
+        }
+    }
+}
+
+/// Critical unless configured otherwise; the agent's own types always are
+const CRITICAL: &[&str] = &["node_cpu", "node_mem"];
+
+/// Best-effort unless configured otherwise: per-CPU detail of what other
+/// types already report for the node
+const BEST_EFFORT: &[&str] = &["node_softnet_cpu"];
+
+/// Tier of every metric type: configured ones, then the defaults, then standard
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Tiers {
+    configured: BTreeMap<String, Tier>,
+}
+
+impl Tiers {
+    /// METRIC_TIERS, as "type=tier" pairs separated by commas, e.g.
+    /// "node_disk=critical,container_faults=best-effort"
+    pub fn from_env() -> Self {
+        let mut tiers = Self::default();
+        for pair in env::var("METRIC_TIERS").unwrap_or_default().split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match pair.split_once('=').and_then(|(t, tier)| Some((t.trim(), Tier::parse(tier.trim())?))) {
+                Some((metric_type, tier)) => {
+                    tiers.configured.insert(metric_type.to_string(), tier);
+                }
+                None => warn!("Ignoring METRIC_TIERS entry {:?}", pair),
+            }
+        }
+        tiers
+    }
+
+    /// Later settings win over earlier ones for the same type
+    pub fn extend(&mut self, tiers: &BTreeMap<String, Tier>) {
+        self.configured.extend(tiers.iter().map(|(t, tier)| (t.clone(), *tier)));
+    }
+
+    pub fn tier(&self, metric_type: &str) -> Tier {
+        if metric_type.starts_with("agent_") {
+            return Tier::Critical;
+        }
+        match self.configured.get(metric_type) {
+            Some(&tier) => tier,
+            None if CRITICAL.contains(&metric_type) => Tier::Critical,
+            None if BEST_EFFORT.contains(&metric_type) => Tier::BestEffort,
+            None => Tier::Standard,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+/// Largest response read back; forwarders answer with small bodies
+const MAX_RESPONSE: u64 = 1024 * 1024;
+
+/// Sends `request` as HTTP/1.1 over a unix socket, one connection per
+/// request, for node-local forwarders listening on a hostPath socket. The
+/// URL only supplies the path and Host header.
+pub async fn send(socket: &Path, request: reqwest::Request) -> Result<reqwest::Response> {
+    let url = request.url();
+    let target = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let body = request.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        request.method(), target, url.host_str().unwrap_or("localhost"), body.len(),
+    ).into_bytes();
+    for (name, value) in request.headers() {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+
+    let mut stream = UnixStream::connect(socket).await
+        .with_context(|| format!("connecting to {}", socket.display()))?;
+    stream.write_all(&head).await?;
+    stream.write_all(body).await?;
+
+    let mut raw = Vec::new();
+    stream.take(MAX_RESPONSE).read_to_end(&mut raw).await?;
+    parse_response(&raw)
+}
+
+fn parse_response(raw: &[u8]) -> Result<reqwest::Response> {
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut parsed = httparse::Response::new(&mut headers);
+    let httparse::Status::Complete(head_len) = parsed.parse(raw)? else {
+        bail!("truncated HTTP response");
+    };
+
+    let mut builder = http::Response::builder().status(parsed.code.unwrap_or_default());
+    let mut chunked = false;
+    for header in parsed.headers.iter() {
+        if header.name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = header.value.eq_ignore_ascii_case(b"chunked");
+            continue;
+        }
+        builder = builder.header(header.name, header.value);
+    }
+
+    let body = &raw[head_len..];
+    let body = if chunked { dechunk(body)? } else { body.to_vec() };
+    Ok(builder.body(body)?.into())
+}
+
+fn dechunk(mut data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = data.windows(2).position(|w| w == b"\r\n").context("truncated chunk")?;
+        let size = std::str::from_utf8(&data[..line_end])?;
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16)?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if data.len() < size {
+            bail!("truncated chunk");
+        }
+        out.extend_from_slice(&data[..size]);
+        data = data.get(size + 2..).unwrap_or_default();
+    }
+}
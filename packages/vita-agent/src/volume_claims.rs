@@ -0,0 +1,87 @@
+use k8s_openapi::api::core::v1::{PersistentVolumeClaim, Pod};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::{Api, ListParams};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::errors::Result;
+use crate::parsers;
+
+/// Conditions set on a claim while its volume is being grown
+const RESIZING: [&str; 2] = ["Resizing", "FileSystemResizePending"];
+
+/// Size of one claim as the API server sees it
+pub struct Claim {
+    pub namespace: String,
+    pub name: String,
+    // spec.resources.requests.storage, raised to ask for an expansion
+    pub requested: u64,
+    // status.capacity.storage, what the storage backend provisioned
+    pub capacity: u64,
+    // Expansion started but not carried through to the filesystem yet
+    pub resizing: bool,
+}
+
+/// Claims mounted by pods on this node, keyed by the name of their bound
+/// volume, which is also the directory the kubelet mounts it at. Lets the
+/// filesystem size a pod sees be checked against what its claim asked for.
+pub struct VolumeClaims {
+    client: kube::Client,
+    node_name: String,
+    interval: Duration,
+    last: Option<Instant>,
+    claims: HashMap<String, Claim>,
+}
+
+impl VolumeClaims {
+    pub fn new(client: kube::Client, node_name: String, interval: Duration) -> Self {
+        Self { client, node_name, interval, last: None, claims: HashMap::new() }
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    pub async fn refresh(&mut self) -> Result<()> {
+        self.last = Some(Instant::now());
+
+        let params = ListParams::default().fields(&format!("spec.nodeName={}", self.node_name));
+        let pods = Api::<Pod>::all(self.client.clone()).list(&params).await?;
+        let names: BTreeSet<(String, String)> = pods.items.into_iter()
+            .filter_map(|pod| Some((pod.metadata.namespace?, pod.spec?)))
+            .flat_map(|(namespace, spec)| spec.volumes.into_iter().flatten()
+                .filter_map(|v| v.persistent_volume_claim)
+                .map(move |c| (namespace.clone(), c.claim_name)))
+            .collect();
+
+        let mut claims = HashMap::new();
+        for (namespace, name) in names {
+            let api = Api::<PersistentVolumeClaim>::namespaced(self.client.clone(), &namespace);
+            let Some(pvc) = api.get_opt(&name).await? else { continue };
+            let Some(volume) = pvc.spec.as_ref().and_then(|s| s.volume_name.clone()) else { continue };
+
+            let storage = |q: Option<&Quantity>| {
+                q.and_then(|q| parsers::quantity(&q.0)).unwrap_or(0.0) as u64
+            };
+            let requested = storage(pvc.spec.as_ref()
+                .and_then(|s| s.resources.as_ref())
+                .and_then(|r| r.requests.as_ref())
+                .and_then(|r| r.get("storage")));
+            let status = pvc.status.as_ref();
+            let capacity = storage(status.and_then(|s| s.capacity.as_ref()).and_then(|c| c.get("storage")));
+            let resizing = status.and_then(|s| s.conditions.as_ref()).into_iter().flatten()
+                .any(|c| c.status == "True" && RESIZING.contains(&c.type_.as_str()));
+
+            claims.insert(volume, Claim { namespace, name, requested, capacity, resizing });
+        }
+        debug!("Volume claims: {} bound claims mounted on this node", claims.len());
+        self.claims = claims;
+        Ok(())
+    }
+
+    /// Claim bound to the volume the kubelet mounts as `volume`
+    pub fn get(&self, volume: &str) -> Option<&Claim> {
+        self.claims.get(volume)
+    }
+}
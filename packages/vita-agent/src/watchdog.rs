@@ -0,0 +1,157 @@
+use std::env;
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::metrics_sender::{Labels, MetricsSender};
+use crate::parsers;
+use crate::statfile::StatFile;
+
+/// Highest degrade level: interval x8, volume and container collection off
+const MAX_LEVEL: u32 = 3;
+
+/// Cycles to wait after escalating before judging the effect
+const ESCALATE_COOLDOWN: u32 = 10;
+
+/// Consecutive cycles under RECOVER_RATIO of budget before stepping back
+const RECOVER_AFTER: u32 = 60;
+const RECOVER_RATIO: f64 = 0.8;
+
+/// Measures the agent's own CPU and RSS each cycle and, when over budget,
+/// degrades collection step by step instead of competing with workloads:
+///   1: interval x2
+///   2: interval x4, PVC collection off
+///   3: interval x8, container collection off
+/// It steps back one level after a sustained period well under budget.
+pub struct Watchdog {
+    cpu_millicores: Option<u64>,
+    rss_mb: Option<u64>,
+    stat: StatFile,
+    statm: StatFile,
+    buf: Vec<u8>,
+    last: Option<(Instant, u64)>,
+    clk_tck: f64,
+    page_size: u64,
+    level: u32,
+    cooldown: u32,
+    calm_cycles: u32,
+    violations: u64,
+}
+
+impl Watchdog {
+    /// Reads BUDGET_CPU_MILLICORES and BUDGET_RSS_MB; None if neither is set
+    pub fn from_env() -> Option<Self> {
+        let budget = |name: &str| {
+            env::var(name).ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|&v| v > 0)
+        };
+        let cpu_millicores = budget("BUDGET_CPU_MILLICORES");
+        let rss_mb = budget("BUDGET_RSS_MB");
+        if cpu_millicores.is_none() && rss_mb.is_none() {
+            return None;
+        }
+
+        #[cfg(unix)]
+        let (clk_tck, page_size) = unsafe {
+            (libc::sysconf(libc::_SC_CLK_TCK), libc::sysconf(libc::_SC_PAGESIZE))
+        };
+        // Without /proc/self the budget is never measured; these go unused
+        #[cfg(not(unix))]
+        let (clk_tck, page_size) = (0, 0);
+
+        Some(Self {
+            cpu_millicores,
+            rss_mb,
+            stat: StatFile::new("/proc/self/stat"),
+            statm: StatFile::new("/proc/self/statm"),
+            buf: Vec::new(),
+            last: None,
+            clk_tck: if clk_tck > 0 { clk_tck as f64 } else { 100.0 },
+            page_size: if page_size > 0 { page_size as u64 } else { 4096 },
+            level: 0,
+            cooldown: 0,
+            calm_cycles: 0,
+            violations: 0,
+        })
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn interval_multiplier(&self) -> u64 {
+        1 << self.level
+    }
+
+    pub fn collect_volumes(&self) -> bool {
+        self.level < 2
+    }
+
+    pub fn collect_containers(&self) -> bool {
+        self.level < 3
+    }
+
+    /// Samples own usage, adjusts the degrade level and reports both
+    pub fn check(&mut self, node_name: &str, sender: &mut MetricsSender) {
+        let now = Instant::now();
+        let ticks = self.stat.read(&mut self.buf).ok().and_then(parsers::proc_pid_cpu_ticks);
+        let rss_mb = self.statm.read(&mut self.buf).ok()
+            .and_then(parsers::statm_resident_pages)
+            .map(|pages| pages * self.page_size / 1024 / 1024);
+
+        // CPU is a rate, so the first sample only sets the baseline
+        let cpu = match (ticks, self.last) {
+            (Some(t), Some((at, prev))) => {
+                let wall = now.duration_since(at).as_secs_f64();
+                (wall > 0.0).then(|| t.saturating_sub(prev) as f64 / self.clk_tck / wall * 1000.0)
+            }
+            _ => None,
+        };
+        if let Some(t) = ticks {
+            self.last = Some((now, t));
+        }
+
+        // Worst usage as a fraction of its budget
+        let mut ratio: f64 = 0.0;
+        if let (Some(used), Some(budget)) = (cpu, self.cpu_millicores) {
+            ratio = ratio.max(used / budget as f64);
+        }
+        if let (Some(used), Some(budget)) = (rss_mb, self.rss_mb) {
+            ratio = ratio.max(used as f64 / budget as f64);
+        }
+
+        self.cooldown = self.cooldown.saturating_sub(1);
+        if ratio > 1.0 {
+            self.violations += 1;
+            self.calm_cycles = 0;
+            if self.cooldown == 0 && self.level < MAX_LEVEL {
+                self.level += 1;
+                self.cooldown = ESCALATE_COOLDOWN;
+                warn!("Over resource budget (cpu={:.0}m rss={}MB), degrading to level {}",
+                    cpu.unwrap_or(0.0), rss_mb.unwrap_or(0), self.level);
+            }
+        } else if ratio < RECOVER_RATIO {
+            self.calm_cycles += 1;
+            if self.calm_cycles >= RECOVER_AFTER && self.level > 0 {
+                self.level -= 1;
+                self.calm_cycles = 0;
+                info!("Back under resource budget, recovering to level {}", self.level);
+            }
+        } else {
+            self.calm_cycles = 0;
+        }
+
+        info!("METRIC_TYPE=agent_budget node={} cpu_millicores={:.0} rss_mb={} level={} violations={}",
+            node_name, cpu.unwrap_or(0.0), rss_mb.unwrap_or(0), self.level, self.violations);
+
+        let labels = Labels::default();
+        if let Some(cpu) = cpu {
+            sender.add("agent_budget", &labels, "agent_cpu_millicores", cpu);
+        }
+        if let Some(rss) = rss_mb {
+            sender.add("agent_budget", &labels, "agent_rss_mb", rss as f64);
+        }
+        sender.add("agent_budget", &labels, "agent_degrade_level", self.level as f64);
+        sender.add("agent_budget", &labels, "agent_budget_violations", self.violations as f64);
+    }
+}
@@ -0,0 +1,261 @@
+use std::env;
+use std::ffi::OsStr;
+use std::io;
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use std::time::Instant;
+use tracing::info;
+use windows_sys::Win32::Foundation::{CloseHandle, FILETIME, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::NetworkManagement::IpHelper::{FreeMibTable, GetIfTable2, MIB_IF_TABLE2};
+use windows_sys::Win32::Storage::FileSystem::{
+    CreateFileW, GetDiskFreeSpaceExW, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+use windows_sys::Win32::System::Ioctl::{DISK_PERFORMANCE, IOCTL_DISK_PERFORMANCE};
+use windows_sys::Win32::System::SystemInformation::{GetTickCount64, GlobalMemoryStatusEx, MEMORYSTATUSEX};
+use windows_sys::Win32::System::Threading::GetSystemTimes;
+use windows_sys::Win32::System::IO::DeviceIoControl;
+
+use crate::cri;
+use crate::errors::Result;
+use crate::metrics_sender::{get_timestamp, Labels, MetricsSender};
+use crate::pod_roles::{self, PodRoles};
+
+/// Physical drives probed; numbering can have gaps after hot-removal
+const MAX_DRIVES: u32 = 32;
+
+// IF_TYPE_SOFTWARE_LOOPBACK
+const LOOPBACK: u32 = 24;
+
+// Bit of MIB_IF_ROW2.InterfaceAndOperStatusFlags set on NDIS filter
+// instances, which repeat the counters of the adapter they sit on
+const FILTER_INTERFACE: u8 = 1 << 1;
+
+/// Node and container metrics on Windows nodes, which have neither /proc
+/// nor cgroups. Node CPU, memory, disk and network come from Win32 APIs and
+/// are reported under the same types and keys as on Linux, so dashboards
+/// work unchanged. Container usage comes from the CRI runtime, which reads
+/// it from HCS.
+pub struct WindowsCollector {
+    cri_endpoint: String,
+    exclude_devices: Vec<String>,
+    exclude_interfaces: Vec<String>,
+}
+
+impl WindowsCollector {
+    /// CRI_ENDPOINT overrides containerd's default pipe, as a path or the
+    /// npipe:// URL kubelet takes
+    pub fn new() -> Self {
+        let cri_endpoint = env::var("CRI_ENDPOINT").ok()
+            .filter(|e| !e.is_empty())
+            .map(|e| match e.strip_prefix("npipe://") {
+                Some(path) => path.replace('/', "\\"),
+                None => e,
+            })
+            .unwrap_or_else(|| cri::DEFAULT_ENDPOINT.to_string());
+        Self { cri_endpoint, exclude_devices: Vec::new(), exclude_interfaces: Vec::new() }
+    }
+
+    pub fn set_filters(&mut self, devices: &[String], interfaces: &[String]) {
+        self.exclude_devices = devices.to_vec();
+        self.exclude_interfaces = interfaces.to_vec();
+    }
+
+    pub fn collect(&mut self, node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+        collect_cpu_metrics(node_name, sender)?;
+        collect_memory_metrics(node_name, sender)?;
+        collect_disk_metrics(&self.exclude_devices, node_name, sender);
+        collect_network_metrics(&self.exclude_interfaces, node_name, sender)?;
+        Ok(())
+    }
+
+    /// With `roles`, containers are reported with their role. The runtime
+    /// lists no sandboxes here, so there are no pause containers to drop.
+    pub async fn collect_containers(&mut self, node_name: &str, roles: Option<&PodRoles>, sender: &mut MetricsSender) -> Result<()> {
+        for stats in cri::list_container_stats(&self.cri_endpoint).await? {
+            let role = roles.and_then(|r| r.role(&stats.id, Instant::now(), || None));
+
+            // Same pod_id form as systemd pod slices, which the consumer maps to the pod
+            let pod_id = format!("pod{}", stats.pod_uid.replace('-', "_"));
+            let cpu_ms = stats.cpu_ns / 1_000_000;
+            let mem_mb = stats.working_set_bytes / 1024 / 1024;
+            // Available is what remains under the limit; 0 without one
+            let mem_limit_mb = match stats.available_bytes {
+                0 => 0,
+                available => (stats.working_set_bytes + available) / 1024 / 1024,
+            };
+
+            info!("METRIC_TYPE=container node={} pod_id={} container_id={} cpu_ms={} mem_mb={} mem_limit_mb={}",
+                node_name, pod_id, stats.id, cpu_ms, mem_mb, mem_limit_mb);
+
+            let labels = Labels { pod_id: Some(&pod_id), container_id: Some(&stats.id), ..Default::default() };
+            sender.add_counter("container", &labels, "cpu_ms", cpu_ms as f64);
+            sender.add("container", &labels, "mem_mb", mem_mb as f64);
+            sender.add("container", &labels, "mem_limit_mb", mem_limit_mb as f64);
+            if let Some(role) = role {
+                pod_roles::report(role, &labels, sender);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Total and caller-available bytes of the volume holding `path`
+pub fn disk_space(path: &Path) -> io::Result<(u64, u64)> {
+    let path = wide(path.as_os_str());
+    let (mut available, mut total) = (0u64, 0u64);
+    if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, &mut total, std::ptr::null_mut()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((total, available))
+}
+
+fn collect_cpu_metrics(node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+    let labels = Labels::default();
+
+    // Uptime in ms; boot time changes across reboots, which reset every counter
+    let boot_time = get_timestamp() - (unsafe { GetTickCount64() } / 1000) as i64;
+    sender.add("node_boot", &labels, "boot_time", boot_time as f64);
+
+    let zero = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+    let (mut idle, mut kernel, mut user) = (zero, zero, zero);
+    if unsafe { GetSystemTimes(&mut idle, &mut kernel, &mut user) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // 100ns units, scaled to the 1/100 s USER_HZ ticks of /proc/stat.
+    // Kernel time includes idle time.
+    let ticks = |t: FILETIME| ((t.dwHighDateTime as u64) << 32 | t.dwLowDateTime as u64) / 100_000;
+    let (idle, kernel, user) = (ticks(idle), ticks(kernel), ticks(user));
+    let sys = kernel.saturating_sub(idle);
+
+    info!("METRIC_TYPE=node_cpu node={} user={} sys={} idle={}", node_name, user, sys, idle);
+    sender.add_counter("node_cpu", &labels, "user", user as f64);
+    sender.add_counter("node_cpu", &labels, "sys", sys as f64);
+    sender.add_counter("node_cpu", &labels, "idle", idle as f64);
+    Ok(())
+}
+
+fn collect_memory_metrics(node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+    let mut status: MEMORYSTATUSEX = unsafe { mem::zeroed() };
+    status.dwLength = mem::size_of::<MEMORYSTATUSEX>() as u32;
+    if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    // Windows has no separate free and page cache figures; standby pages
+    // count as available
+    let mb = |bytes: u64| bytes / 1024 / 1024;
+    let total = mb(status.ullTotalPhys);
+    let available = mb(status.ullAvailPhys);
+    let used = total.saturating_sub(available);
+    info!("METRIC_TYPE=node_mem node={} total_mb={} used_mb={} free_mb={} avail_mb={}",
+        node_name, total, used, available, available);
+
+    let labels = Labels::default();
+    sender.add("node_mem", &labels, "total_mb", total as f64);
+    sender.add("node_mem", &labels, "used_mb", used as f64);
+    sender.add("node_mem", &labels, "free_mb", available as f64);
+    sender.add("node_mem", &labels, "avail_mb", available as f64);
+
+    // The page file limit covers physical memory plus page files
+    let swap_total = mb(status.ullTotalPageFile).saturating_sub(total);
+    if swap_total > 0 {
+        let committed = mb(status.ullTotalPageFile.saturating_sub(status.ullAvailPageFile));
+        let swap_used = committed.saturating_sub(used).min(swap_total);
+        info!("METRIC_TYPE=node_swap node={} total_mb={} used_mb={}", node_name, swap_total, swap_used);
+
+        sender.add("node_swap", &labels, "total_mb", swap_total as f64);
+        sender.add("node_swap", &labels, "used_mb", swap_used as f64);
+    }
+    Ok(())
+}
+
+fn excluded(name: &str, prefixes: &[String]) -> bool {
+    prefixes.iter().any(|p| name.starts_with(p.as_str()))
+}
+
+fn collect_disk_metrics(exclude: &[String], node_name: &str, sender: &mut MetricsSender) {
+    for n in 0..MAX_DRIVES {
+        let name = format!("PhysicalDrive{}", n);
+        if excluded(&name, exclude) {
+            continue;
+        }
+        let Some(perf) = disk_performance(&name) else { continue };
+
+        // Sectors as in /proc/diskstats, which always counts 512 bytes
+        let sectors_r = perf.BytesRead as u64 / 512;
+        let sectors_w = perf.BytesWritten as u64 / 512;
+        info!("METRIC_TYPE=node_disk node={} device={} reads={} writes={} sectors_r={} sectors_w={}",
+            node_name, name, perf.ReadCount, perf.WriteCount, sectors_r, sectors_w);
+
+        let labels = Labels { device: Some(&name), ..Default::default() };
+        sender.add_counter("node_disk", &labels, "reads", perf.ReadCount as f64);
+        sender.add_counter("node_disk", &labels, "writes", perf.WriteCount as f64);
+        sender.add_counter("node_disk", &labels, "sectors_r", sectors_r as f64);
+        sender.add_counter("node_disk", &labels, "sectors_w", sectors_w as f64);
+    }
+}
+
+/// Cumulative I/O counters of \\.\<drive>, or None if there is no such drive
+fn disk_performance(drive: &str) -> Option<DISK_PERFORMANCE> {
+    let path = wide(OsStr::new(&format!(r"\\.\{}", drive)));
+    unsafe {
+        // No access rights are needed for the performance query
+        let handle = CreateFileW(
+            path.as_ptr(), 0, FILE_SHARE_READ | FILE_SHARE_WRITE,
+            std::ptr::null(), OPEN_EXISTING, 0, 0,
+        );
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let mut perf: DISK_PERFORMANCE = mem::zeroed();
+        let mut returned = 0u32;
+        let ok = DeviceIoControl(
+            handle, IOCTL_DISK_PERFORMANCE, std::ptr::null(), 0,
+            &mut perf as *mut _ as *mut _, mem::size_of::<DISK_PERFORMANCE>() as u32,
+            &mut returned, std::ptr::null_mut(),
+        );
+        CloseHandle(handle);
+        (ok != 0).then_some(perf)
+    }
+}
+
+fn collect_network_metrics(exclude: &[String], node_name: &str, sender: &mut MetricsSender) -> Result<()> {
+    let mut table: *mut MIB_IF_TABLE2 = std::ptr::null_mut();
+    let status = unsafe { GetIfTable2(&mut table) };
+    if status != 0 {
+        return Err(io::Error::from_raw_os_error(status as i32).into());
+    }
+
+    let rows = unsafe { std::slice::from_raw_parts((*table).Table.as_ptr(), (*table).NumEntries as usize) };
+    for row in rows {
+        if row.Type == LOOPBACK || row.InterfaceAndOperStatusFlags._bitfield & FILTER_INTERFACE != 0 {
+            continue;
+        }
+        let len = row.Alias.iter().position(|&c| c == 0).unwrap_or(row.Alias.len());
+        let name = String::from_utf16_lossy(&row.Alias[..len]);
+        if name.is_empty() || excluded(&name, exclude) {
+            continue;
+        }
+
+        let rx_pkts = row.InUcastPkts + row.InNUcastPkts;
+        let tx_pkts = row.OutUcastPkts + row.OutNUcastPkts;
+        info!("METRIC_TYPE=node_net node={} device={} rx_bytes={} tx_bytes={}",
+            node_name, name, row.InOctets, row.OutOctets);
+
+        let labels = Labels { device: Some(&name), ..Default::default() };
+        sender.add_counter("node_net", &labels, "rx_bytes", row.InOctets as f64);
+        sender.add_counter("node_net", &labels, "tx_bytes", row.OutOctets as f64);
+        sender.add_counter("node_net", &labels, "rx_pkts", rx_pkts as f64);
+        sender.add_counter("node_net", &labels, "tx_pkts", tx_pkts as f64);
+        sender.add_counter("node_net", &labels, "rx_errs", row.InErrors as f64);
+        sender.add_counter("node_net", &labels, "tx_errs", row.OutErrors as f64);
+    }
+
+    unsafe { FreeMibTable(table as *const _) };
+    Ok(())
+}
+
+fn wide(s: &OsStr) -> Vec<u16> {
+    s.encode_wide().chain(Some(0)).collect()
+}
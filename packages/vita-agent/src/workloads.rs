@@ -0,0 +1,113 @@
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::Resource;
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::cluster_metrics::list_all;
+use crate::errors::Result;
+use crate::metrics_sender::{Labels, MetricsSender};
+
+/// Replica counts of one Deployment, StatefulSet or DaemonSet
+struct Replicas {
+    desired: i32,
+    ready: i32,
+    updated: i32,
+    available: i32,
+    // The controller has seen the latest spec
+    observed: bool,
+}
+
+impl Replicas {
+    /// Every desired replica runs the latest spec and is ready
+    fn complete(&self) -> bool {
+        self.observed && self.updated >= self.desired && self.ready >= self.desired
+    }
+}
+
+/// Observed generation caught up with the spec's
+fn observed(meta: &ObjectMeta, observed_generation: Option<i64>) -> bool {
+    observed_generation >= meta.generation
+}
+
+fn deployment(d: &Deployment) -> Replicas {
+    let status = d.status.clone().unwrap_or_default();
+    Replicas {
+        desired: d.spec.as_ref().and_then(|s| s.replicas).unwrap_or(1),
+        ready: status.ready_replicas.unwrap_or(0),
+        updated: status.updated_replicas.unwrap_or(0),
+        available: status.available_replicas.unwrap_or(0),
+        observed: observed(&d.metadata, status.observed_generation),
+    }
+}
+
+fn statefulset(s: &StatefulSet) -> Replicas {
+    let status = s.status.clone().unwrap_or_default();
+    Replicas {
+        desired: s.spec.as_ref().and_then(|s| s.replicas).unwrap_or(1),
+        ready: status.ready_replicas.unwrap_or(0),
+        updated: status.updated_replicas.unwrap_or(0),
+        available: status.available_replicas.unwrap_or(0),
+        observed: observed(&s.metadata, status.observed_generation),
+    }
+}
+
+fn daemonset(d: &DaemonSet) -> Replicas {
+    let status = d.status.clone().unwrap_or_default();
+    Replicas {
+        desired: status.desired_number_scheduled,
+        ready: status.number_ready,
+        updated: status.updated_number_scheduled.unwrap_or(0),
+        available: status.number_available.unwrap_or(0),
+        observed: observed(&d.metadata, status.observed_generation),
+    }
+}
+
+/// Rollout health from the API server, kube-state-metrics style: desired
+/// against ready replicas of every Deployment, StatefulSet and DaemonSet,
+/// and pods by phase per namespace. Alert rules can then put a workload's
+/// rollout next to what its pods use.
+pub async fn collect(client: &kube::Client, pods: &[Pod], sender: &mut MetricsSender) -> Result<()> {
+    report("deployment", list_all::<Deployment>(client).await?, deployment, sender);
+    report("statefulset", list_all::<StatefulSet>(client).await?, statefulset, sender);
+    report("daemonset", list_all::<DaemonSet>(client).await?, daemonset, sender);
+
+    let mut phases: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+    for pod in pods {
+        let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref()).unwrap_or("Unknown");
+        let namespace = pod.metadata.namespace.clone().unwrap_or_default();
+        *phases.entry(namespace).or_default().entry(phase.to_ascii_lowercase()).or_default() += 1;
+    }
+    for (namespace, phases) in &phases {
+        info!("METRIC_TYPE=cluster_namespace namespace={} {}", namespace, phases.iter()
+            .map(|(phase, n)| format!("phase_{}={}", phase, n))
+            .collect::<Vec<_>>()
+            .join(" "));
+        let labels = Labels { device: Some(namespace), ..Default::default() };
+        for (phase, n) in phases {
+            sender.add("cluster_namespace", &labels, &format!("phase_{}", phase), *n as f64);
+        }
+    }
+    Ok(())
+}
+
+/// One `workload` series per object, labelled `<kind>/<namespace>/<name>`
+fn report<K: Resource>(kind: &str, items: Vec<K>, replicas: fn(&K) -> Replicas, sender: &mut MetricsSender) {
+    let mut complete = 0;
+    for item in &items {
+        let meta = item.meta();
+        let Some(name) = meta.name.as_deref() else { continue };
+        let r = replicas(item);
+        complete += r.complete() as usize;
+
+        let device = format!("{}/{}/{}", kind, meta.namespace.as_deref().unwrap_or_default(), name);
+        let labels = Labels { device: Some(&device), ..Default::default() };
+        sender.add("workload", &labels, "desired", r.desired as f64);
+        sender.add("workload", &labels, "ready", r.ready as f64);
+        sender.add("workload", &labels, "updated", r.updated as f64);
+        sender.add("workload", &labels, "available", r.available as f64);
+        sender.add("workload", &labels, "complete", r.complete() as u8 as f64);
+    }
+    info!("METRIC_TYPE=workload kind={} total={} complete={}", kind, items.len(), complete);
+}
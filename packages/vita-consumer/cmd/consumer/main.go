@@ -2,21 +2,145 @@ package main
 
 import (
 	"context"
+	"crypto/tls"
 	"log"
 	"net/http"
 	"os"
 	"os/signal"
 	"path/filepath"
+	"strconv"
+	"strings"
 	"syscall"
 	"time"
 
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/admin"
 	"github.com/nchanged/vitakube/packages/vita-consumer/internal/api"
 	"github.com/nchanged/vitakube/packages/vita-consumer/internal/buffer"
 	"github.com/nchanged/vitakube/packages/vita-consumer/internal/ingest"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/querycache"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/rules"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/secret"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/selfmetrics"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/shard"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/snapshot"
 	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
 	"github.com/nchanged/vitakube/packages/vita-consumer/internal/syncer"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/tenant"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/tier"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/trace"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/wal"
 )
 
+func envOr(key, fallback string) string {
+	if v := os.Getenv(key); v != "" {
+		return v
+	}
+	return fallback
+}
+
+func envInt(key string, fallback int) int {
+	if v, err := strconv.Atoi(os.Getenv(key)); err == nil {
+		return v
+	}
+	return fallback
+}
+
+func envDuration(key string, fallback time.Duration) time.Duration {
+	if v, err := time.ParseDuration(os.Getenv(key)); err == nil {
+		return v
+	}
+	return fallback
+}
+
+var (
+	flushDuration  = selfmetrics.NewHistogram("vitakube_flush_duration_seconds", "Time to write the buffer to DuckDB.", selfmetrics.DefaultBuckets)
+	flushedMetrics = selfmetrics.NewCounter("vitakube_flushed_metrics_total", "Metrics written from the buffer to DuckDB.")
+	flushFailures  = selfmetrics.NewCounter("vitakube_flush_failures_total", "Buffer flushes that failed and were left to WAL replay.")
+)
+
+// registerSelfMetrics exposes queue depths and storage sizes, read on scrape
+func registerSelfMetrics(ring *buffer.RingBuffer, batchLog *wal.WAL, ingestion *ingest.IngestionServer, dataDir string) {
+	selfmetrics.GaugeFunc("vitakube_buffer_metrics", "Metrics waiting in memory for the next flush.", func() float64 {
+		return float64(ring.Len())
+	})
+	selfmetrics.GaugeFunc("vitakube_buffer_capacity", "Metrics the buffer holds before dropping new ones.", func() float64 {
+		return float64(ring.Cap())
+	})
+	selfmetrics.CounterFunc("vitakube_buffer_dropped_total", "Metrics dropped because the buffer was full.", func() float64 {
+		return float64(ring.Dropped())
+	})
+	selfmetrics.GaugeFunc("vitakube_wal_segments", "Write-ahead log segments on disk, the active one included.", func() float64 {
+		n, _, _ := batchLog.Size()
+		return float64(n)
+	})
+	selfmetrics.GaugeFunc("vitakube_active_series", "Series seen in the last ten minutes.", func() float64 {
+		return float64(ingestion.ActiveSeries())
+	})
+	selfmetrics.GaugeFunc("vitakube_ingest_invalid_nodes", "Nodes that sent invalid metrics in the last hour; see /api/v1/status/validation.", func() float64 {
+		return float64(ingestion.InvalidNodes())
+	})
+	selfmetrics.LabeledGaugeFunc("vitakube_storage_bytes", "Bytes on disk by store.", "store", func() map[string]float64 {
+		_, walBytes, _ := batchLog.Size()
+		return map[string]float64{
+			"sqlite": float64(fileSizes(filepath.Join(dataDir, "meta.db"))),
+			"duckdb": float64(fileSizes(filepath.Join(dataDir, "metrics.duckdb"))),
+			"wal":    float64(walBytes),
+		}
+	})
+}
+
+// fileSizes adds up a database file and its -wal/-shm/.wal companions
+func fileSizes(path string) int64 {
+	var total int64
+	for _, suffix := range []string{"", "-wal", "-shm", ".wal"} {
+		if info, err := os.Stat(path + suffix); err == nil {
+			total += info.Size()
+		}
+	}
+	return total
+}
+
+// trackTenantStorage charges each tenant for its nodes' share of the rows
+// in DuckDB, times the database's size on disk; the cold tier isn't counted
+func trackTenantStorage(ctx context.Context, tenants *tenant.Registry, duck *store.DuckDBStore, dataDir string) {
+	ticker := time.NewTicker(5 * time.Minute)
+	defer ticker.Stop()
+	for {
+		rows, err := duck.RowsByNode()
+		if err != nil {
+			log.Printf("Failed to count rows per node: %v", err)
+		} else {
+			var total int64
+			for _, n := range rows {
+				total += n
+			}
+			size := fileSizes(filepath.Join(dataDir, "metrics.duckdb"))
+			bytes := make(map[string]int64, len(rows))
+			for node, n := range rows {
+				bytes[node] = int64(float64(size) * float64(n) / float64(total))
+			}
+			tenants.UpdateStorage(bytes)
+		}
+
+		select {
+		case <-ctx.Done():
+			return
+		case <-ticker.C:
+		}
+	}
+}
+
+// tracesEndpoint follows the OpenTelemetry exporter variables
+func tracesEndpoint() string {
+	if url := os.Getenv("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"); url != "" {
+		return url
+	}
+	if base := os.Getenv("OTEL_EXPORTER_OTLP_ENDPOINT"); base != "" {
+		return strings.TrimRight(base, "/") + "/v1/traces"
+	}
+	return ""
+}
+
 func main() {
 	// 0. Configuration
 	dataDir := os.Getenv("DATA_DIR")
@@ -28,6 +152,14 @@ func main() {
 	}
 	log.Printf("Using data directory: %s", dataDir)
 
+	// Restore a snapshot (directory or downloaded tar.gz) before the stores open;
+	// once restored, the same snapshot is skipped on later starts
+	if src := os.Getenv("RESTORE_SNAPSHOT"); src != "" {
+		if _, err := snapshot.Restore(src, dataDir); err != nil {
+			log.Fatalf("Failed to restore snapshot %s: %v", src, err)
+		}
+	}
+
 	// 1. Initialize Stores
 	sqlite, err := store.NewSQLiteStore(filepath.Join(dataDir, "meta.db"))
 	if err != nil {
@@ -64,14 +196,182 @@ func main() {
 	// 3. Initialize Buffer
 	ring := buffer.NewRingBuffer(10000) // Hold 10k metrics in RAM
 
-	// 4. Ingestion Server
-	ingestion := ingest.NewIngestionServer(ring, sync)
-	http.HandleFunc("/api/v1/ingest", ingestion.HandleIngest)
+	// 3b. Write-ahead log: replay whatever a previous run accepted but never flushed
+	batchLog, err := wal.Open(filepath.Join(dataDir, "wal"))
+	if err != nil {
+		log.Fatalf("Failed to open WAL: %v", err)
+	}
+	defer batchLog.Close()
+
+	replayed, err := batchLog.Replay(func(batch []buffer.Metric) {
+		if err := duck.BatchInsert(store.PointsFromBuffer(batch)); err != nil {
+			log.Fatalf("Failed to replay WAL into DuckDB: %v", err)
+		}
+	})
+	if err != nil {
+		log.Fatalf("Failed to replay WAL: %v", err)
+	}
+	for _, seq := range replayed {
+		if err := batchLog.Remove(seq); err != nil {
+			log.Printf("Failed to remove replayed WAL segment %d: %v", seq, err)
+		}
+	}
+
+	// 4. Ingestion Server, optionally sharded by node name across replicas
+	var peers []string
+	if v := os.Getenv("CONSUMER_PEERS"); v != "" {
+		peers = strings.Split(v, ",")
+	}
+	shards := shard.NewRing(os.Getenv("CONSUMER_SELF"), peers)
+	if shards.Enabled() {
+		log.Printf("Ingest sharding enabled: self=%s peers=%d", shards.Self(), len(shards.Peers()))
+	}
+
+	ingestion := ingest.NewIngestionServer(ring, sync, batchLog, shards)
+	// Tenants sharing the consumer, each with its token and limits (TENANTS_FILE)
+	tenants, err := tenant.FromEnv()
+	if err != nil {
+		log.Fatalf("Failed to load tenants: %v", err)
+	}
+	if tenants != nil {
+		ingestion.SetTenants(tenants)
+		log.Printf("Multi-tenancy enabled: %d tenants", len(tenants.Usage()))
+	}
+	http.HandleFunc("/api/v1/ingest", selfmetrics.Instrument("ingest", ingestion.HandleIngest))
+	if rate := envInt("INGEST_RATE", 0); rate > 0 {
+		ingestion.SetIngestLimit(float64(rate))
+	}
+	// Metric type families accepted besides the agent's, e.g. "myapp,legacy"
+	if extra := os.Getenv("INGEST_EXTRA_METRIC_TYPES"); extra != "" {
+		ingestion.SetExtraMetricTypes(strings.Split(extra, ","))
+	}
+	// Metrics stamped too far ahead (clock skew) or behind (spool replays)
+	// are accepted as stamped, clamped or rejected
+	futurePolicy, err := ingest.ParseTimestampPolicy(envOr("INGEST_FUTURE_POLICY", "reject"))
+	if err != nil {
+		log.Fatalf("Invalid INGEST_FUTURE_POLICY: %v", err)
+	}
+	latePolicy, err := ingest.ParseTimestampPolicy(envOr("INGEST_LATE_POLICY", "reject"))
+	if err != nil {
+		log.Fatalf("Invalid INGEST_LATE_POLICY: %v", err)
+	}
+	ingestion.SetFuturePolicy(envDuration("INGEST_FUTURE_TOLERANCE", 5*time.Minute), futurePolicy)
+	ingestion.SetLatePolicy(envDuration("INGEST_LATE_TOLERANCE", 24*time.Hour), latePolicy)
+	if url := tracesEndpoint(); url != "" {
+		ratio, err := strconv.ParseFloat(os.Getenv("OTEL_TRACES_SAMPLER_ARG"), 64)
+		if err != nil {
+			ratio = 1
+		}
+		tracer := trace.New(url, envOr("OTEL_SERVICE_NAME", "vita-consumer"), ratio)
+		go tracer.Run(ctx, 10*time.Second)
+		ingestion.SetTracer(tracer)
+		log.Printf("Exporting traces to %s (sampling %.2f)", url, ratio)
+	}
+	http.HandleFunc("/api/v1/handshake", ingestion.HandleHandshake)
+	http.HandleFunc("/api/v1/shards", ingestion.HandleShards)
+	http.HandleFunc("/api/v1/status/cardinality", ingestion.HandleCardinality)
+	http.HandleFunc("/api/v1/status/validation", ingestion.HandleValidation)
+
+	// Credentials come from NAME_FILE (re-read on rotation) or NAME
+	if token := secret.FromEnv("INGEST_TOKEN"); token != nil {
+		ingestion.SetToken(token)
+		log.Println("Ingest authentication enabled")
+	}
+	if key := secret.FromEnv("INGEST_HMAC_KEY"); key != nil {
+		ingestion.SetSigningKey(key)
+		log.Println("Ingest batch signatures required")
+	}
+
+	// Admin API (series deletion, ingest block rules, tenant usage); disabled without a token
+	adminToken := secret.FromEnv("ADMIN_TOKEN")
+	if adminToken != nil {
+		blocks := admin.NewBlocklist()
+		ingestion.SetBlocker(blocks)
+		adminServer := admin.NewServer(adminToken, sqlite, duck, ring, blocks)
+		adminServer.SetTenants(tenants)
+		adminServer.EnableSnapshots(snapshot.NewStore(filepath.Join(dataDir, "snapshots"), duck, sqlite))
+		adminServer.RegisterRoutes(http.DefaultServeMux)
+	}
+
+	// Replication: stored batches are forwarded to a standby consumer, which
+	// accepts them on /api/v1/replicate; both sides share REPLICATION_TOKEN
+	replicationToken := secret.FromEnv("REPLICATION_TOKEN")
+	ingestion.SetReplicationToken(replicationToken)
+	http.HandleFunc("/api/v1/replicate", selfmetrics.Instrument("replicate", ingestion.HandleReplicate))
+	if url := os.Getenv("REPLICATION_URL"); url != "" {
+		ingestion.EnableReplication(url, replicationToken, envInt("REPLICATION_QUEUE_METRICS", 1000000))
+		go ingestion.RunReplication(ctx)
+		log.Printf("Replicating ingested batches to %s", url)
+	}
+
+	// Backfill: spooled batches with old timestamps go straight to DuckDB
+	ingestion.EnableBackfill(duck, float64(envInt("BACKFILL_RATE", 5000)))
+	http.HandleFunc("/api/v1/ingest/backfill", selfmetrics.Instrument("ingest_backfill", ingestion.HandleBackfill))
+
+	// Kubernetes events from the agent elected for cluster-scoped collection
+	ingestion.EnableEvents(sqlite)
+	http.HandleFunc("/api/v1/ingest/events", selfmetrics.Instrument("ingest_events", ingestion.HandleEvents))
+
+	// State changes each agent sees on its node (OOM kills, containers, mounts, links)
+	ingestion.EnableNodeEvents(sqlite)
+	http.HandleFunc("/api/v1/ingest/node-events", selfmetrics.Instrument("ingest_node_events", ingestion.HandleNodeEvents))
+
+	// Agent heartbeats, shown with each node
+	ingestion.EnableHeartbeats(sqlite)
+	http.HandleFunc("/api/v1/ingest/heartbeat", selfmetrics.Instrument("ingest_heartbeat", ingestion.HandleHeartbeat))
 
 	// 5. API Server (Dashboard Endpoints)
-	apiServer := api.NewServer(sqlite, ring)
+	apiServer := api.NewServer(sqlite, duck, ring, dataDir)
+	// Aggregated query results, kept until new metrics or heartbeats land; 0 MB disables
+	if mb := envInt("QUERY_CACHE_MB", 64); mb > 0 {
+		align := time.Duration(envInt("QUERY_CACHE_ALIGN_SECONDS", 10)) * time.Second
+		cache := querycache.New(mb<<20, align, func() uint64 {
+			return duck.Version() + sqlite.HeartbeatVersion()
+		})
+		selfmetrics.GaugeFunc("vitakube_query_cache_bytes", "Bytes of API responses held in the query cache.", func() float64 {
+			return float64(cache.Bytes())
+		})
+		apiServer.SetQueryCache(cache)
+	}
+	if tenants != nil {
+		apiServer.SetTenants(tenants, adminToken)
+		go trackTenantStorage(ctx, tenants, duck, dataDir)
+	}
 	apiServer.RegisterRoutes(http.DefaultServeMux)
 
+	// Cold tier (optional): old hours are rolled up and moved to object storage
+	if bucket := os.Getenv("TIER_S3_BUCKET"); bucket != "" {
+		s3 := tier.NewS3Client(
+			envOr("TIER_S3_ENDPOINT", "https://s3.amazonaws.com"),
+			envOr("TIER_S3_REGION", "us-east-1"),
+			bucket,
+			secret.FromEnv("AWS_ACCESS_KEY_ID"),
+			secret.FromEnv("AWS_SECRET_ACCESS_KEY"),
+		)
+		retention := time.Duration(envInt("TIER_LOCAL_RETENTION_HOURS", 24)) * time.Hour
+
+		tierer, err := tier.NewTierer(duck, s3, os.Getenv("TIER_S3_PREFIX"), retention, dataDir)
+		if err != nil {
+			log.Fatalf("Failed to initialize cold tier: %v", err)
+		}
+		apiServer.SetSegmentSource(tierer)
+		go tierer.Run(ctx, 15*time.Minute)
+		log.Printf("Cold tier enabled: bucket=%s local_retention=%s", bucket, retention)
+	}
+
+	// Recording rules (optional), evaluated through the API's PromQL engine
+	recorder, err := rules.FromEnv(apiServer, duck, sqlite)
+	if err != nil {
+		log.Fatalf("Failed to load recording rules: %v", err)
+	}
+	if recorder != nil {
+		go recorder.Run(ctx)
+	}
+
+	// Self-metrics for Prometheus; request counters and latencies register themselves
+	registerSelfMetrics(ring, batchLog, ingestion, dataDir)
+	http.Handle("/metrics", selfmetrics.Handler())
+
 	// 5. Persist Worker (The Cold Path)
 	go func() {
 		ticker := time.NewTicker(60 * time.Second)
@@ -80,32 +380,60 @@ func main() {
 			case <-ctx.Done():
 				return
 			case <-ticker.C:
-				data := ring.Flush()
+				// Seal the WAL segment and flush the ring in one step; ingest
+				// waits meanwhile, so data is exactly the sealed segment's
+				// batches and the segment can go once data is in DuckDB.
+				var data []buffer.Metric
+				sealed, err := batchLog.Rotate(func() { data = ring.Flush() })
+				if err != nil {
+					log.Printf("Error rotating WAL: %v", err)
+					continue
+				}
+
 				if len(data) > 0 {
 					log.Printf("Flushing %d metrics to DuckDB...", len(data))
 
-					points := make([]store.MetricPoint, len(data))
-					for i, m := range data {
-						points[i] = store.MetricPoint{
-							Time:       m.Time,
-							ResourceID: m.ResourceID,
-							MetricType: m.Type,
-							Value:      m.Value,
-						}
-					}
-
-					if err := duck.BatchInsert(points); err != nil {
+					start := time.Now()
+					err := duck.BatchInsert(store.PointsFromBuffer(data))
+					flushDuration.Since(start)
+					if err != nil {
+						// Keep the segment; it is replayed on the next start
+						flushFailures.Inc()
 						log.Printf("Error flushing to DuckDB: %v", err)
+						continue
 					}
+					flushedMetrics.Add(float64(len(data)))
+				}
+
+				if err := batchLog.Remove(sealed); err != nil {
+					log.Printf("Error removing WAL segment %d: %v", sealed, err)
 				}
 			}
 		}
 	}()
 
-	// 6. Start HTTP Server
+	// 6. Start HTTP Server, with TLS if a key pair is mounted. The pair is
+	// reloaded when cert-manager or similar rotates it.
 	go func() {
-		log.Println("Starting Consumer on :8080")
-		if err := http.ListenAndServe(":8080", nil); err != nil {
+		certFile, keyFile := os.Getenv("TLS_CERT_FILE"), os.Getenv("TLS_KEY_FILE")
+		if certFile == "" || keyFile == "" {
+			log.Println("Starting Consumer on :8080")
+			if err := http.ListenAndServe(":8080", nil); err != nil {
+				log.Fatalf("HTTP Server failed: %v", err)
+			}
+			return
+		}
+
+		cert, err := secret.NewCertificate(certFile, keyFile)
+		if err != nil {
+			log.Fatalf("Failed to load TLS certificate: %v", err)
+		}
+		server := &http.Server{
+			Addr:      ":8080",
+			TLSConfig: &tls.Config{GetCertificate: cert.GetCertificate},
+		}
+		log.Println("Starting Consumer on :8080 (TLS)")
+		if err := server.ListenAndServeTLS("", ""); err != nil {
 			log.Fatalf("HTTP Server failed: %v", err)
 		}
 	}()
@@ -0,0 +1,128 @@
+package admin
+
+import (
+	"strconv"
+	"sync"
+	"sync/atomic"
+	"time"
+)
+
+// Matcher selects series. Empty fields match anything; at least one field
+// must be set for a matcher to be accepted by the admin API.
+type Matcher struct {
+	Node       string `json:"node,omitempty"`
+	MetricType string `json:"metric_type,omitempty"`
+	Namespace  string `json:"namespace,omitempty"`
+	ResourceID int64  `json:"resource_id,omitempty"`
+}
+
+func (m Matcher) Empty() bool {
+	return m.Node == "" && m.MetricType == "" && m.Namespace == "" && m.ResourceID == 0
+}
+
+func (m Matcher) Matches(node, metricType, namespace string, resourceID int64) bool {
+	return (m.Node == "" || m.Node == node) &&
+		(m.MetricType == "" || m.MetricType == metricType) &&
+		(m.Namespace == "" || m.Namespace == namespace) &&
+		(m.ResourceID == 0 || m.ResourceID == resourceID)
+}
+
+// BlockRule drops matching metrics at ingest until it expires
+type BlockRule struct {
+	ID        string    `json:"id"`
+	Matcher   Matcher   `json:"matcher"`
+	Reason    string    `json:"reason,omitempty"`
+	CreatedAt time.Time `json:"created_at"`
+	ExpiresAt time.Time `json:"expires_at"`
+	Dropped   int64     `json:"dropped"`
+}
+
+type activeRule struct {
+	BlockRule
+	dropped atomic.Int64
+}
+
+// Blocklist holds the active block rules. Rules live in memory only, which
+// suits their purpose as temporary relief; they are lost on restart.
+type Blocklist struct {
+	mu     sync.RWMutex
+	rules  []*activeRule
+	nextID int64
+}
+
+func NewBlocklist() *Blocklist {
+	return &Blocklist{}
+}
+
+func (b *Blocklist) Add(m Matcher, reason string, ttl time.Duration) BlockRule {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+
+	b.nextID++
+	now := time.Now()
+	rule := &activeRule{BlockRule: BlockRule{
+		ID:        strconv.FormatInt(b.nextID, 10),
+		Matcher:   m,
+		Reason:    reason,
+		CreatedAt: now,
+		ExpiresAt: now.Add(ttl),
+	}}
+	b.rules = append(b.rules, rule)
+	return rule.view()
+}
+
+func (b *Blocklist) Remove(id string) bool {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+
+	for i, r := range b.rules {
+		if r.ID == id {
+			b.rules = append(b.rules[:i], b.rules[i+1:]...)
+			return true
+		}
+	}
+	return false
+}
+
+// List returns the active rules, pruning expired ones
+func (b *Blocklist) List() []BlockRule {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+
+	now := time.Now()
+	active := b.rules[:0]
+	views := []BlockRule{}
+	for _, r := range b.rules {
+		if now.Before(r.ExpiresAt) {
+			active = append(active, r)
+			views = append(views, r.view())
+		}
+	}
+	b.rules = active
+	return views
+}
+
+// Blocked reports whether any active rule matches the metric, counting the drop
+func (b *Blocklist) Blocked(node, metricType, namespace string, resourceID int64) bool {
+	b.mu.RLock()
+	defer b.mu.RUnlock()
+
+	if len(b.rules) == 0 {
+		return false
+	}
+	now := time.Now()
+	for _, r := range b.rules {
+		if now.Before(r.ExpiresAt) && r.Matcher.Matches(node, metricType, namespace, resourceID) {
+			r.dropped.Add(1)
+			return true
+		}
+	}
+	return false
+}
+
+// view snapshots a rule, including its drop count, for JSON output
+func (r *activeRule) view() BlockRule {
+	v := r.BlockRule
+	v.Dropped = r.dropped.Load()
+	return v
+}
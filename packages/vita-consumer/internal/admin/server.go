@@ -0,0 +1,271 @@
+package admin
+
+import (
+	"encoding/json"
+	"log"
+	"net/http"
+	"strings"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/buffer"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/secret"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/snapshot"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/tenant"
+)
+
+// Upper bound for block rules; they are meant as temporary relief
+const maxBlockTTL = 7 * 24 * time.Hour
+
+// Server exposes the admin endpoints. Every request must carry
+// "Authorization: Bearer <ADMIN_TOKEN>".
+type Server struct {
+	token  *secret.Value
+	sqlite *store.SQLiteStore
+	duck   *store.DuckDBStore
+	ring   *buffer.RingBuffer
+	blocks *Blocklist
+	// Nil unless tenants are configured
+	tenants *tenant.Registry
+	// Nil unless snapshots are enabled
+	snapshots *snapshot.Store
+}
+
+func NewServer(token *secret.Value, sqlite *store.SQLiteStore, duck *store.DuckDBStore, ring *buffer.RingBuffer, blocks *Blocklist) *Server {
+	return &Server{
+		token:  token,
+		sqlite: sqlite,
+		duck:   duck,
+		ring:   ring,
+		blocks: blocks,
+	}
+}
+
+// SetTenants reports tenants' usage on /api/v1/admin/tenants
+func (s *Server) SetTenants(tenants *tenant.Registry) {
+	s.tenants = tenants
+}
+
+// EnableSnapshots serves snapshots of the local storage, kept in store
+func (s *Server) EnableSnapshots(snapshots *snapshot.Store) {
+	s.snapshots = snapshots
+}
+
+func (s *Server) RegisterRoutes(mux *http.ServeMux) {
+	mux.HandleFunc("/api/v1/admin/series/delete", s.authorized(s.handleDeleteSeries))
+	mux.HandleFunc("/api/v1/admin/blocks", s.authorized(s.handleBlocks))
+	mux.HandleFunc("/api/v1/admin/tenants", s.authorized(s.handleTenants))
+	if s.snapshots != nil {
+		mux.HandleFunc("/api/v1/admin/snapshots", s.authorized(s.handleSnapshots))
+		mux.HandleFunc("/api/v1/admin/snapshots/download", s.authorized(s.handleSnapshotDownload))
+	}
+}
+
+func (s *Server) authorized(next http.HandlerFunc) http.HandlerFunc {
+	return func(w http.ResponseWriter, r *http.Request) {
+		given := strings.TrimPrefix(r.Header.Get("Authorization"), "Bearer ")
+		if !s.token.Matches(given) {
+			writeError(w, "Unauthorized", http.StatusUnauthorized)
+			return
+		}
+		next(w, r)
+	}
+}
+
+type DeleteSeriesRequest struct {
+	Matcher Matcher `json:"matcher"`
+	Start   int64   `json:"start,omitempty"` // unix seconds, default: beginning of time
+	End     int64   `json:"end,omitempty"`   // unix seconds, default: now
+}
+
+type DeleteSeriesResponse struct {
+	DeletedStored   int64 `json:"deleted_stored"`
+	DeletedBuffered int   `json:"deleted_buffered"`
+}
+
+// handleDeleteSeries removes matching data from DuckDB and the ring buffer.
+// Segments already moved to the cold tier are immutable and not touched.
+func (s *Server) handleDeleteSeries(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodPost {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	var req DeleteSeriesRequest
+	if err := json.NewDecoder(r.Body).Decode(&req); err != nil {
+		writeError(w, "Invalid JSON", http.StatusBadRequest)
+		return
+	}
+	if req.Matcher.Empty() {
+		writeError(w, "matcher must set at least one field", http.StatusBadRequest)
+		return
+	}
+
+	f := store.MetricFilter{
+		Start:      time.Unix(req.Start, 0),
+		End:        time.Now(),
+		Node:       req.Matcher.Node,
+		ResourceID: req.Matcher.ResourceID,
+		MetricType: req.Matcher.MetricType,
+	}
+	if req.End > 0 {
+		f.End = time.Unix(req.End, 0)
+	}
+
+	var inNamespace map[int64]bool
+	if req.Matcher.Namespace != "" {
+		ids, err := s.sqlite.PodIDsInNamespace(req.Matcher.Namespace)
+		if err != nil {
+			writeError(w, err.Error(), http.StatusInternalServerError)
+			return
+		}
+		f.ResourceIDs = ids
+		inNamespace = make(map[int64]bool, len(ids))
+		for _, id := range ids {
+			inNamespace[id] = true
+		}
+	}
+
+	deleted, err := s.duck.DeleteMetrics(f)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	buffered := s.ring.RemoveIf(func(m buffer.Metric) bool {
+		if m.Time.Before(f.Start) || !m.Time.Before(f.End) {
+			return false
+		}
+		if inNamespace != nil && !inNamespace[m.ResourceID] {
+			return false
+		}
+		return req.Matcher.Matches(m.Node, m.Key, req.Matcher.Namespace, m.ResourceID)
+	})
+
+	writeJSON(w, DeleteSeriesResponse{DeletedStored: deleted, DeletedBuffered: buffered})
+}
+
+type CreateBlockRequest struct {
+	Matcher    Matcher `json:"matcher"`
+	TTLSeconds int64   `json:"ttl_seconds"`
+	Reason     string  `json:"reason,omitempty"`
+}
+
+// handleBlocks lists (GET), creates (POST) and removes (DELETE ?id=) block rules
+func (s *Server) handleBlocks(w http.ResponseWriter, r *http.Request) {
+	switch r.Method {
+	case http.MethodGet:
+		writeJSON(w, s.blocks.List())
+
+	case http.MethodPost:
+		var req CreateBlockRequest
+		if err := json.NewDecoder(r.Body).Decode(&req); err != nil {
+			writeError(w, "Invalid JSON", http.StatusBadRequest)
+			return
+		}
+		if req.Matcher.Empty() {
+			writeError(w, "matcher must set at least one field", http.StatusBadRequest)
+			return
+		}
+		ttl := time.Duration(req.TTLSeconds) * time.Second
+		if ttl <= 0 || ttl > maxBlockTTL {
+			writeError(w, "ttl_seconds must be between 1 and 604800", http.StatusBadRequest)
+			return
+		}
+
+		rule := s.blocks.Add(req.Matcher, req.Reason, ttl)
+		w.Header().Set("Content-Type", "application/json")
+		w.WriteHeader(http.StatusCreated)
+		json.NewEncoder(w).Encode(rule)
+
+	case http.MethodDelete:
+		if !s.blocks.Remove(r.URL.Query().Get("id")) {
+			writeError(w, "Block rule not found", http.StatusNotFound)
+			return
+		}
+		w.WriteHeader(http.StatusNoContent)
+
+	default:
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+	}
+}
+
+// handleTenants lists each tenant's nodes, active series, storage and
+// running queries next to its limits
+func (s *Server) handleTenants(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	if s.tenants == nil {
+		writeJSON(w, []tenant.Usage{})
+		return
+	}
+	writeJSON(w, s.tenants.Usage())
+}
+
+// handleSnapshots lists (GET), creates (POST) and removes (DELETE ?name=)
+// snapshots. Creating one copies both databases while ingest and queries
+// go on; metrics not yet flushed from the buffer are left out.
+func (s *Server) handleSnapshots(w http.ResponseWriter, r *http.Request) {
+	switch r.Method {
+	case http.MethodGet:
+		snapshots, err := s.snapshots.List()
+		if err != nil {
+			writeError(w, err.Error(), http.StatusInternalServerError)
+			return
+		}
+		writeJSON(w, snapshots)
+
+	case http.MethodPost:
+		m, err := s.snapshots.Create()
+		if err != nil {
+			writeError(w, err.Error(), http.StatusInternalServerError)
+			return
+		}
+		w.Header().Set("Content-Type", "application/json")
+		w.WriteHeader(http.StatusCreated)
+		json.NewEncoder(w).Encode(m)
+
+	case http.MethodDelete:
+		if err := s.snapshots.Delete(r.URL.Query().Get("name")); err != nil {
+			writeError(w, err.Error(), http.StatusNotFound)
+			return
+		}
+		w.WriteHeader(http.StatusNoContent)
+
+	default:
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+	}
+}
+
+// handleSnapshotDownload streams snapshot ?name= as a tar.gz, which
+// RESTORE_SNAPSHOT accepts as is
+func (s *Server) handleSnapshotDownload(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	name := r.URL.Query().Get("name")
+	if !s.snapshots.Has(name) {
+		writeError(w, "Snapshot not found", http.StatusNotFound)
+		return
+	}
+
+	w.Header().Set("Content-Type", "application/gzip")
+	w.Header().Set("Content-Disposition", `attachment; filename="vitakube-`+name+`.tar.gz"`)
+	if err := s.snapshots.WriteArchive(w, name); err != nil {
+		log.Printf("Snapshot download aborted: %v", err)
+	}
+}
+
+func writeJSON(w http.ResponseWriter, data interface{}) {
+	w.Header().Set("Content-Type", "application/json")
+	json.NewEncoder(w).Encode(data)
+}
+
+func writeError(w http.ResponseWriter, message string, code int) {
+	w.Header().Set("Content-Type", "application/json")
+	w.WriteHeader(code)
+	json.NewEncoder(w).Encode(map[string]string{"error": message})
+}
@@ -0,0 +1,253 @@
+package api
+
+import (
+	"net/http"
+	"sort"
+	"time"
+)
+
+// Buckets an availability query is split into at most, unless step is given
+const availabilityBuckets = 1000
+
+// Why a bucket has no data
+const (
+	// The node sent no metrics and no heartbeat: agent down or unreachable
+	gapAgentDown = "agent_down"
+	// Heartbeats arrived but no metrics: the agent ran, collection or sending failed
+	gapNoMetrics = "no_metrics"
+	// The pod's node reported, just not the pod
+	gapPodAbsent = "pod_absent"
+)
+
+// AvailabilityResponse is data coverage over a range, in step-long buckets
+type AvailabilityResponse struct {
+	Start int64              `json:"start"`
+	End   int64              `json:"end"`
+	Step  int64              `json:"step"` // seconds
+	Nodes []NodeAvailability `json:"nodes"`
+	Pods  []PodAvailability  `json:"pods"`
+}
+
+// NodeAvailability covers the buckets from a node's first metric or
+// heartbeat in the range to its end
+type NodeAvailability struct {
+	Node            string  `json:"node"`
+	Buckets         int     `json:"buckets"`
+	WithMetrics     int     `json:"with_metrics"`
+	WithHeartbeat   int     `json:"with_heartbeat"`
+	AvailabilityPct float64 `json:"availability_pct"`
+	Gaps            []Gap   `json:"gaps"`
+}
+
+// PodAvailability covers the buckets from a pod's first metric in the
+// range to its last; before and after, the pod wasn't there, which is not
+// missing data
+type PodAvailability struct {
+	PodID           int64   `json:"pod_id"`
+	Node            string  `json:"node"`
+	First           int64   `json:"first"`
+	Last            int64   `json:"last"`
+	Buckets         int     `json:"buckets"`
+	Present         int     `json:"present"`
+	AvailabilityPct float64 `json:"availability_pct"`
+	Gaps            []Gap   `json:"gaps"`
+}
+
+// Gap is a run of buckets without data, [Start, End) in unix seconds
+type Gap struct {
+	Start  int64  `json:"start"`
+	End    int64  `json:"end"`
+	Reason string `json:"reason"`
+}
+
+// gaps merges consecutive missing buckets with the same reason; reason
+// returns "" for a bucket that has data
+func gaps(first, last, step int64, reason func(bucket int64) string) []Gap {
+	out := []Gap{}
+	for b := first; b <= last; b += step {
+		r := reason(b)
+		if r == "" {
+			continue
+		}
+		if n := len(out); n > 0 && out[n-1].End == b && out[n-1].Reason == r {
+			out[n-1].End = b + step
+			continue
+		}
+		out = append(out, Gap{Start: b, End: b + step, Reason: r})
+	}
+	return out
+}
+
+func percent(part, whole int) float64 {
+	if whole == 0 {
+		return 0
+	}
+	return float64(part) * 100 / float64(whole)
+}
+
+// handleAvailability reports how much of a range each node and pod has
+// data for, and why the rest is missing, so a gap in a long-range chart
+// reads as a pod that wasn't running or as samples the agent missed.
+// Takes the metric filters of /api/v1/export (start, end, node,
+// resource_id for one pod, metric_type) and step in seconds, a multiple of
+// 60 sized for at most 1000 buckets by default. Heartbeats are kept for
+// seven days; older ranges can't tell no_metrics from agent_down.
+func (s *Server) handleAvailability(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	filter, err := s.parseMetricFilter(r)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusBadRequest)
+		return
+	}
+	step := int64(filter.End.Sub(filter.Start).Seconds())/availabilityBuckets + 1
+	if v, ok := getQueryInt(r, "step"); ok {
+		step = v
+	}
+	// Heartbeats are recorded by the minute
+	step = max((step+59)/60*60, 60)
+
+	first := filter.Start.Unix() / step * step
+	last := (filter.End.Unix() - 1) / step * step
+
+	segments, err := s.segmentsFor(filter.Start, filter.End)
+	if err != nil {
+		writeError(w, "cold tier unavailable: "+err.Error(), http.StatusBadGateway)
+		return
+	}
+
+	// One pod's availability still needs its node's other metrics
+	podID := filter.ResourceID
+	filter.ResourceID = 0
+	rows, err := s.duck.QueryPresence(filter, time.Duration(step)*time.Second, segments)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	defer rows.Close()
+
+	nodeBuckets := map[string]map[int64]bool{}
+	podBuckets := map[int64]map[int64]bool{}
+	podNode := map[int64]string{}
+	for rows.Next() {
+		var bucket, resourceID int64
+		var node string
+		if err := rows.Scan(&bucket, &node, &resourceID); err != nil {
+			writeError(w, err.Error(), http.StatusInternalServerError)
+			return
+		}
+		// Recording rule results belong to no node
+		if node == "" {
+			continue
+		}
+		if nodeBuckets[node] == nil {
+			nodeBuckets[node] = map[int64]bool{}
+		}
+		nodeBuckets[node][bucket] = true
+		if resourceID == 0 || (podID > 0 && resourceID != podID) {
+			continue
+		}
+		if podBuckets[resourceID] == nil {
+			podBuckets[resourceID] = map[int64]bool{}
+			podNode[resourceID] = node
+		}
+		podBuckets[resourceID][bucket] = true
+	}
+	if err := rows.Err(); err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	minutes, err := s.sqlite.HeartbeatMinutes(filter.Node, filter.Start, filter.End)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	heartbeats := map[string]map[int64]bool{}
+	for node, ms := range minutes {
+		if !filter.HasNode(node) {
+			continue
+		}
+		heartbeats[node] = map[int64]bool{}
+		for _, m := range ms {
+			heartbeats[node][m*60/step*step] = true
+		}
+		if nodeBuckets[node] == nil {
+			nodeBuckets[node] = map[int64]bool{}
+		}
+	}
+
+	resp := AvailabilityResponse{Start: filter.Start.Unix(), End: filter.End.Unix(), Step: step, Nodes: []NodeAvailability{}, Pods: []PodAvailability{}}
+
+	nodes := make([]string, 0, len(nodeBuckets))
+	for node := range nodeBuckets {
+		nodes = append(nodes, node)
+	}
+	sort.Strings(nodes)
+	for _, node := range nodes {
+		metrics, beats := nodeBuckets[node], heartbeats[node]
+		since := last + step
+		for b := first; b <= last; b += step {
+			if metrics[b] || beats[b] {
+				since = b
+				break
+			}
+		}
+		a := NodeAvailability{Node: node}
+		for b := since; b <= last; b += step {
+			a.Buckets++
+			if metrics[b] {
+				a.WithMetrics++
+			}
+			if beats[b] {
+				a.WithHeartbeat++
+			}
+		}
+		a.AvailabilityPct = percent(a.WithMetrics, a.Buckets)
+		a.Gaps = gaps(since, last, step, func(b int64) string {
+			switch {
+			case metrics[b]:
+				return ""
+			case beats[b]:
+				return gapNoMetrics
+			default:
+				return gapAgentDown
+			}
+		})
+		resp.Nodes = append(resp.Nodes, a)
+	}
+
+	pods := make([]int64, 0, len(podBuckets))
+	for id := range podBuckets {
+		pods = append(pods, id)
+	}
+	sort.Slice(pods, func(i, j int) bool { return pods[i] < pods[j] })
+	for _, id := range pods {
+		present, node := podBuckets[id], podNode[id]
+		a := PodAvailability{PodID: id, Node: node, First: last, Last: first}
+		for b := range present {
+			a.First, a.Last = min(a.First, b), max(a.Last, b)
+		}
+		a.Buckets = int((a.Last-a.First)/step) + 1
+		a.Present = len(present)
+		a.AvailabilityPct = percent(a.Present, a.Buckets)
+		a.Gaps = gaps(a.First, a.Last, step, func(b int64) string {
+			switch {
+			case present[b]:
+				return ""
+			case nodeBuckets[node][b]:
+				return gapPodAbsent
+			case heartbeats[node][b]:
+				return gapNoMetrics
+			default:
+				return gapAgentDown
+			}
+		})
+		resp.Pods = append(resp.Pods, a)
+	}
+
+	writeJSON(w, resp)
+}
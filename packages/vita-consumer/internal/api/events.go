@@ -0,0 +1,42 @@
+package api
+
+import (
+	"net/http"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// handleListEvents returns Kubernetes events, most recent first. Optional
+// filters: namespace, kind, name, type (Normal/Warning), start and end (unix
+// seconds, matched against the last occurrence) and limit (max 1000).
+func (s *Server) handleListEvents(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	q := r.URL.Query()
+	filter := store.EventFilter{
+		Namespace: q.Get("namespace"),
+		Kind:      q.Get("kind"),
+		Name:      q.Get("name"),
+		Type:      q.Get("type"),
+	}
+	if v, ok := getQueryInt(r, "start"); ok {
+		filter.Since = v
+	}
+	if v, ok := getQueryInt(r, "end"); ok {
+		filter.Until = v
+	}
+	if v, ok := getQueryInt(r, "limit"); ok {
+		filter.Limit = int(v)
+	}
+
+	events, err := s.sqlite.ListEvents(filter)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	writeJSON(w, events)
+}
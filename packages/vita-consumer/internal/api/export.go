@@ -0,0 +1,323 @@
+package api
+
+import (
+	"encoding/base64"
+	"encoding/csv"
+	"encoding/json"
+	"fmt"
+	"io"
+	"log"
+	"net/http"
+	"os"
+	"path/filepath"
+	"strconv"
+	"strings"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// Rows written between explicit flushes of a CSV export
+const exportFlushRows = 1000
+
+// maxExportRange caps a single export request
+const maxExportRange = 7 * 24 * time.Hour
+
+// handleExport returns raw rows matching the metric filters (see
+// parseMetricFilter) and workload, as csv (default), ndjson or parquet.
+// csv and ndjson stream as rows are read, and a read error midway drops
+// the connection rather than ending the body as if it were complete.
+// parquet is written to a temporary file under the data directory first,
+// so it needs the disk space of the whole result and sends nothing until
+// DuckDB finished; large ranges are better paged with limit or streamed.
+// With limit, the response is one page of about that many rows and
+// X-Next-Cursor, when more may follow, is passed back as cursor with the
+// same filters for the next page.
+func (s *Server) handleExport(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	filter, err := s.parseMetricFilter(r)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusBadRequest)
+		return
+	}
+
+	// All pods a workload has had, e.g. workload=deployment/shop/web
+	if key := r.URL.Query().Get("workload"); key != "" {
+		kind, namespace, name, err := store.ParseWorkloadKey(key)
+		if err != nil {
+			writeError(w, err.Error(), http.StatusBadRequest)
+			return
+		}
+		workloads, err := s.sqlite.Workloads(store.WorkloadFilter{Kind: kind, Namespace: namespace, Name: name})
+		if err != nil {
+			writeError(w, err.Error(), http.StatusInternalServerError)
+			return
+		}
+		filter.ResourceIDs = []int64{}
+		for _, wl := range workloads {
+			filter.ResourceIDs = append(filter.ResourceIDs, wl.PodIDs...)
+		}
+	}
+
+	format := r.URL.Query().Get("format")
+	if format == "" {
+		format = "csv"
+	}
+
+	if format != "csv" && format != "ndjson" && format != "parquet" {
+		writeError(w, "format must be csv, ndjson or parquet", http.StatusBadRequest)
+		return
+	}
+
+	if cursor := r.URL.Query().Get("cursor"); cursor != "" {
+		after, err := decodeCursor(cursor)
+		if err != nil {
+			writeError(w, "invalid cursor", http.StatusBadRequest)
+			return
+		}
+		filter.After = after
+	}
+
+	segments, err := s.segmentsFor(filter.Start, filter.End)
+	if err != nil {
+		writeError(w, "cold tier unavailable: "+err.Error(), http.StatusBadGateway)
+		return
+	}
+
+	if limit, ok := getQueryInt(r, "limit"); ok {
+		if limit <= 0 {
+			writeError(w, "limit must be positive", http.StatusBadRequest)
+			return
+		}
+		through, err := s.duck.PageEnd(filter, int(limit), segments)
+		if err != nil {
+			writeError(w, err.Error(), http.StatusInternalServerError)
+			return
+		}
+		// Without a page end this page holds the remaining rows
+		if through != nil {
+			filter.Through = through
+			w.Header().Set("X-Next-Cursor", encodeCursor(through))
+		}
+	}
+
+	switch format {
+	case "parquet":
+		s.exportParquet(w, filter, segments)
+	case "ndjson":
+		s.exportNDJSON(w, r, filter, segments)
+	default:
+		s.exportCSV(w, r, filter, segments)
+	}
+}
+
+// Cursors are the last row's key, opaque to clients
+func encodeCursor(k *store.RowKey) string {
+	b, _ := json.Marshal(k)
+	return base64.RawURLEncoding.EncodeToString(b)
+}
+
+func decodeCursor(s string) (*store.RowKey, error) {
+	b, err := base64.RawURLEncoding.DecodeString(s)
+	if err != nil {
+		return nil, err
+	}
+	var k store.RowKey
+	if err := json.Unmarshal(b, &k); err != nil {
+		return nil, err
+	}
+	return &k, nil
+}
+
+// parseMetricFilter reads start/end (unix seconds), node, resource_id,
+// metric_type (the key, e.g. cpu_ms), type (the agent's metric type, e.g.
+// container) and node labels (label.zone=eu-1a, ...) from the query string.
+// The range defaults to the last hour. A tenant's queries only match its
+// own nodes.
+func (s *Server) parseMetricFilter(r *http.Request) (store.MetricFilter, error) {
+	end := time.Now()
+	if v, ok := getQueryInt(r, "end"); ok {
+		end = time.Unix(v, 0)
+	}
+	start := end.Add(-time.Hour)
+	if v, ok := getQueryInt(r, "start"); ok {
+		start = time.Unix(v, 0)
+	}
+
+	if !start.Before(end) {
+		return store.MetricFilter{}, fmt.Errorf("start must be before end")
+	}
+	if end.Sub(start) > maxExportRange {
+		return store.MetricFilter{}, fmt.Errorf("time range must not exceed %s", maxExportRange)
+	}
+
+	f := store.MetricFilter{
+		Start:      start,
+		End:        end,
+		Node:       r.URL.Query().Get("node"),
+		Nodes:      s.tenantNodes(r),
+		MetricType: r.URL.Query().Get("metric_type"),
+		Type:       r.URL.Query().Get("type"),
+	}
+	if id, ok := getQueryInt(r, "resource_id"); ok {
+		f.ResourceID = id
+	}
+	for param, values := range r.URL.Query() {
+		if name, ok := strings.CutPrefix(param, "label."); ok && name != "" {
+			if f.Labels == nil {
+				f.Labels = map[string]string{}
+			}
+			f.Labels[name] = values[0]
+		}
+	}
+	return f, nil
+}
+
+// exportRow is one raw sample, as NDJSON exports write it. Type, key and
+// the series labels are named as agents send them.
+type exportRow struct {
+	Time        int64   `json:"time"`
+	Node        string  `json:"node"`
+	ResourceID  int64   `json:"resource_id"`
+	Type        string  `json:"type"`
+	Key         string  `json:"key"`
+	Device      string  `json:"device,omitempty"`
+	ContainerID string  `json:"container_id,omitempty"`
+	Volume      string  `json:"volume,omitempty"`
+	Value       float64 `json:"value"`
+}
+
+// streamRows passes rows straight from DuckDB to write, flushing the
+// client connection every exportFlushRows. Writes block on the connection,
+// so a slow reader throttles how fast rows are pulled, and memory stays
+// flat however large the result. flush pushes out what write buffered.
+func (s *Server) streamRows(w http.ResponseWriter, r *http.Request, f store.MetricFilter, segments []string, format string,
+	write func(exportRow) error, flush func() error) {
+	rows, err := s.duck.QueryMetrics(f, segments)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	defer rows.Close()
+
+	flusher, _ := w.(http.Flusher)
+	n := 0
+	for rows.Next() {
+		var row exportRow
+		var ts time.Time
+		if err := rows.Scan(&ts, &row.Node, &row.ResourceID, &row.Type, &row.Key, &row.Device, &row.ContainerID, &row.Volume, &row.Value); err != nil {
+			abortExport(format, n, err)
+		}
+		row.Time = ts.Unix()
+		if err := write(row); err != nil {
+			return
+		}
+
+		n++
+		if n%exportFlushRows == 0 {
+			if flush() != nil || r.Context().Err() != nil {
+				return
+			}
+			if flusher != nil {
+				flusher.Flush()
+			}
+		}
+	}
+	if err := rows.Err(); err != nil {
+		abortExport(format, n, err)
+	}
+	flush()
+}
+
+// abortExport logs why a streamed export stopped and drops the connection:
+// the 200 has usually gone out already, and ending the body normally would
+// pass a truncated export off as complete.
+func abortExport(format string, n int, err error) {
+	log.Printf("%s export aborted after %d rows: %v", format, n, err)
+	panic(http.ErrAbortHandler)
+}
+
+func (s *Server) exportCSV(w http.ResponseWriter, r *http.Request, f store.MetricFilter, segments []string) {
+	w.Header().Set("Content-Type", "text/csv")
+	w.Header().Set("Content-Disposition", exportFilename(f, "csv"))
+
+	cw := csv.NewWriter(w)
+	cw.Write([]string{"time", "node", "resource_id", "type", "key", "device", "container_id", "volume", "value"})
+	s.streamRows(w, r, f, segments, "CSV", func(row exportRow) error {
+		return cw.Write([]string{
+			strconv.FormatInt(row.Time, 10),
+			row.Node,
+			strconv.FormatInt(row.ResourceID, 10),
+			row.Type,
+			row.Key,
+			row.Device,
+			row.ContainerID,
+			row.Volume,
+			strconv.FormatFloat(row.Value, 'f', -1, 64),
+		})
+	}, func() error {
+		cw.Flush()
+		return cw.Error()
+	})
+}
+
+// exportNDJSON writes a JSON object per row and line, which clients can
+// parse as it arrives rather than after the whole array
+func (s *Server) exportNDJSON(w http.ResponseWriter, r *http.Request, f store.MetricFilter, segments []string) {
+	w.Header().Set("Content-Type", "application/x-ndjson")
+	w.Header().Set("Content-Disposition", exportFilename(f, "ndjson"))
+
+	enc := json.NewEncoder(w)
+	s.streamRows(w, r, f, segments, "NDJSON", func(row exportRow) error {
+		return enc.Encode(row)
+	}, func() error { return nil })
+}
+
+// exportParquet has DuckDB write a temporary file, then streams it out.
+// The file holds the whole result at once; maxExportRange is what bounds it.
+func (s *Server) exportParquet(w http.ResponseWriter, f store.MetricFilter, segments []string) {
+	tmpDir := filepath.Join(s.dataDir, "tmp")
+	if err := os.MkdirAll(tmpDir, 0755); err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	tmp, err := os.CreateTemp(tmpDir, "export-*.parquet")
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	path := tmp.Name()
+	tmp.Close()
+	defer os.Remove(path)
+
+	if err := s.duck.ExportParquet(f, segments, path); err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	file, err := os.Open(path)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	defer file.Close()
+
+	if info, err := file.Stat(); err == nil {
+		w.Header().Set("Content-Length", strconv.FormatInt(info.Size(), 10))
+	}
+	w.Header().Set("Content-Type", "application/vnd.apache.parquet")
+	w.Header().Set("Content-Disposition", exportFilename(f, "parquet"))
+
+	if _, err := io.Copy(w, file); err != nil {
+		log.Printf("Parquet export aborted: %v", err)
+	}
+}
+
+func exportFilename(f store.MetricFilter, ext string) string {
+	return fmt.Sprintf(`attachment; filename="metrics-%d-%d.%s"`, f.Start.Unix(), f.End.Unix(), ext)
+}
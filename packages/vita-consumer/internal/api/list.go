@@ -3,13 +3,28 @@ package api
 import (
 	"database/sql"
 	"net/http"
+	"strings"
+	"time"
 )
 
 // Node represents a cluster node
 type Node struct {
-	ID   int64  `json:"id"`
-	Name string `json:"name"`
-	UID  string `json:"uid"`
+	ID    int64        `json:"id"`
+	Name  string       `json:"name"`
+	UID   string       `json:"uid"`
+	Agent *AgentStatus `json:"agent,omitempty"`
+}
+
+// AgentStatus is the node's agent as last heard from. Status is "up" while
+// heartbeats arrive and "down" after three are missed; a node with no agent
+// heartbeat at all has none.
+type AgentStatus struct {
+	Status     string   `json:"status"`
+	Version    string   `json:"version"`
+	Collectors []string `json:"collectors"`
+	ConfigHash string   `json:"config_hash"`
+	UptimeSecs int64    `json:"uptime_secs"`
+	LastSeen   int64    `json:"last_seen"`
 }
 
 // Namespace represents a K8s namespace
@@ -55,19 +70,44 @@ func (s *Server) handleListNodes(w http.ResponseWriter, r *http.Request) {
 		return
 	}
 
-	rows, err := s.sqlite.Query("SELECT id, name, uid FROM nodes ORDER BY name")
+	rows, err := s.sqlite.Query(`
+		SELECT n.id, n.name, n.uid, a.version, a.collectors, a.config_hash, a.uptime_secs, a.interval_secs, a.last_seen
+		FROM nodes n
+		LEFT JOIN agents a ON a.node = n.name
+		ORDER BY n.name`)
 	if err != nil {
 		writeError(w, err.Error(), http.StatusInternalServerError)
 		return
 	}
 	defer rows.Close()
 
+	now := time.Now().Unix()
 	nodes := []Node{}
 	for rows.Next() {
 		var n Node
-		if err := rows.Scan(&n.ID, &n.Name, &n.UID); err != nil {
+		var version, collectors, configHash sql.NullString
+		var uptime, interval, lastSeen sql.NullInt64
+		if err := rows.Scan(&n.ID, &n.Name, &n.UID, &version, &collectors, &configHash, &uptime, &interval, &lastSeen); err != nil {
 			continue
 		}
+		if lastSeen.Valid {
+			status := "up"
+			if now-lastSeen.Int64 > 3*max(interval.Int64, 1) {
+				status = "down"
+			}
+			enabled := []string{}
+			if collectors.String != "" {
+				enabled = strings.Split(collectors.String, ",")
+			}
+			n.Agent = &AgentStatus{
+				Status:     status,
+				Version:    version.String,
+				Collectors: enabled,
+				ConfigHash: configHash.String,
+				UptimeSecs: uptime.Int64,
+				LastSeen:   lastSeen.Int64,
+			}
+		}
 		nodes = append(nodes, n)
 	}
 
@@ -25,10 +25,13 @@ type LivePod struct {
 
 // ContainerInfo represents container metrics
 type ContainerInfo struct {
-	ID         string  `json:"id"`
-	CPUms      float64 `json:"cpu_ms"`
-	MemMB      float64 `json:"mem_mb"`
-	MemLimitMB float64 `json:"mem_limit_mb"`
+	ID          string  `json:"id"`
+	CPUms       float64 `json:"cpu_ms"`
+	MemMB       float64 `json:"mem_mb"`
+	MemLimitMB  float64 `json:"mem_limit_mb"`
+	SwapMB      float64 `json:"swap_mb"`
+	SwapLimitMB float64 `json:"swap_limit_mb"`
+	ZswapMB     float64 `json:"zswap_mb"`
 }
 
 // PVCInfo represents PVC metrics
@@ -51,10 +54,19 @@ func (s *Server) handleLiveMetrics(w http.ResponseWriter, r *http.Request) {
 	cutoffTime := time.Now().Add(-5 * time.Second)
 	allMetrics := s.ring.ReadAll()
 
+	// A tenant only sees pods on its own nodes
+	var ownNodes map[string]bool
+	if nodes := s.tenantNodes(r); nodes != nil {
+		ownNodes = make(map[string]bool, len(nodes))
+		for _, node := range nodes {
+			ownNodes[node] = true
+		}
+	}
+
 	// Build pod ID set from recent metrics
 	activePodIDs := make(map[int64]bool)
 	for _, m := range allMetrics {
-		if m.Time.After(cutoffTime) && m.ResourceID > 0 {
+		if m.Time.After(cutoffTime) && m.ResourceID > 0 && (ownNodes == nil || ownNodes[m.Node]) {
 			activePodIDs[m.ResourceID] = true
 		}
 	}
@@ -119,29 +131,36 @@ func (s *Server) handleLiveMetrics(w http.ResponseWriter, r *http.Request) {
 		// Aggregate container and PVC metrics for this pod
 		containerMetrics := make(map[string]*ContainerInfo)
 		pvcMetrics := make(map[int64]*PVCInfo)
+		containerOf := func(id string) *ContainerInfo {
+			// Buffered before container IDs were kept
+			if id == "" {
+				id = "default"
+			}
+			if _, ok := containerMetrics[id]; !ok {
+				containerMetrics[id] = &ContainerInfo{ID: id}
+			}
+			return containerMetrics[id]
+		}
 
 		for _, m := range allMetrics {
 			if m.ResourceID != p.ID || m.Time.Before(cutoffTime) {
 				continue
 			}
 
-			// Container metrics (cpu_ms, mem_mb, mem_limit_mb)
-			switch m.Type {
+			// Container metrics (cpu_ms, mem_mb, mem_limit_mb, swap_mb, ...)
+			switch m.Key {
 			case "cpu_ms":
-				if _, ok := containerMetrics["default"]; !ok {
-					containerMetrics["default"] = &ContainerInfo{ID: "default"}
-				}
-				containerMetrics["default"].CPUms = m.Value
+				containerOf(m.ContainerID).CPUms = m.Value
 			case "mem_mb":
-				if _, ok := containerMetrics["default"]; !ok {
-					containerMetrics["default"] = &ContainerInfo{ID: "default"}
-				}
-				containerMetrics["default"].MemMB = m.Value
+				containerOf(m.ContainerID).MemMB = m.Value
 			case "mem_limit_mb":
-				if _, ok := containerMetrics["default"]; !ok {
-					containerMetrics["default"] = &ContainerInfo{ID: "default"}
-				}
-				containerMetrics["default"].MemLimitMB = m.Value
+				containerOf(m.ContainerID).MemLimitMB = m.Value
+			case "swap_mb":
+				containerOf(m.ContainerID).SwapMB = m.Value
+			case "swap_limit_mb":
+				containerOf(m.ContainerID).SwapLimitMB = m.Value
+			case "zswap_mb":
+				containerOf(m.ContainerID).ZswapMB = m.Value
 			case "total_mb", "used_mb", "free_mb":
 				// PVC metrics - resource_id points to PVC or pod
 				// We need to identify which PVC this belongs to
@@ -0,0 +1,43 @@
+package api
+
+import (
+	"net/http"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// handleListNodeEvents returns the state changes agents saw on their nodes,
+// most recent first. Optional filters: node, type (oom_kill,
+// container_appeared, ...), pod_uid, start and end (unix seconds) and limit
+// (max 1000).
+func (s *Server) handleListNodeEvents(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	q := r.URL.Query()
+	filter := store.NodeEventFilter{
+		Node:   q.Get("node"),
+		Type:   q.Get("type"),
+		PodUID: q.Get("pod_uid"),
+		Nodes:  s.tenantNodes(r),
+	}
+	if v, ok := getQueryInt(r, "start"); ok {
+		filter.Since = v
+	}
+	if v, ok := getQueryInt(r, "end"); ok {
+		filter.Until = v
+	}
+	if v, ok := getQueryInt(r, "limit"); ok {
+		filter.Limit = int(v)
+	}
+
+	events, err := s.sqlite.ListNodeEvents(filter)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	writeJSON(w, events)
+}
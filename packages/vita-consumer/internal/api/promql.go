@@ -0,0 +1,321 @@
+package api
+
+import (
+	"database/sql"
+	"encoding/json"
+	"errors"
+	"math"
+	"net/http"
+	"strconv"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/promql"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// Volume metrics may carry a PVC's ID rather than a pod's, so they get no
+// pod and namespace labels
+var volumeMetrics = map[string]bool{"pvc_usage": true, "total_mb": true, "used_mb": true, "free_mb": true}
+
+// promResponse is the envelope of Prometheus' HTTP API
+type promResponse struct {
+	Status    string      `json:"status"`
+	Data      interface{} `json:"data,omitempty"`
+	ErrorType string      `json:"errorType,omitempty"`
+	Error     string      `json:"error,omitempty"`
+}
+
+type promData struct {
+	ResultType string      `json:"resultType"`
+	Result     interface{} `json:"result"`
+}
+
+type promSample struct {
+	Metric promql.Labels  `json:"metric"`
+	Value  [2]interface{} `json:"value"`
+}
+
+type promSeries struct {
+	Metric promql.Labels    `json:"metric"`
+	Values [][2]interface{} `json:"values"`
+}
+
+func writePromError(w http.ResponseWriter, errorType, message string, code int) {
+	w.Header().Set("Content-Type", "application/json")
+	w.WriteHeader(code)
+	json.NewEncoder(w).Encode(promResponse{Status: "error", ErrorType: errorType, Error: message})
+}
+
+// promPoint renders a point as Prometheus does: [unix seconds, "value"]
+func promPoint(p promql.Sample) [2]interface{} {
+	v := strconv.FormatFloat(p.V, 'f', -1, 64)
+	switch {
+	case math.IsInf(p.V, 1):
+		v = "+Inf"
+	case math.IsInf(p.V, -1):
+		v = "-Inf"
+	}
+	return [2]interface{}{float64(p.T) / 1000, v}
+}
+
+// parsePromTime reads a time as unix seconds, fractions allowed, or RFC 3339
+func parsePromTime(s string, fallback time.Time) (time.Time, error) {
+	if s == "" {
+		return fallback, nil
+	}
+	if secs, err := strconv.ParseFloat(s, 64); err == nil {
+		return time.UnixMilli(int64(secs * 1000)), nil
+	}
+	return time.Parse(time.RFC3339Nano, s)
+}
+
+// handleQuery evaluates a PromQL expression at one instant, as Prometheus'
+// /api/v1/query does: query, and time (unix seconds or RFC 3339, default
+// now). GET or form POST.
+func (s *Server) handleQuery(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet && r.Method != http.MethodPost {
+		writePromError(w, "bad_data", "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	at, err := parsePromTime(r.FormValue("time"), time.Now())
+	if err != nil {
+		writePromError(w, "bad_data", "invalid time: "+err.Error(), http.StatusBadRequest)
+		return
+	}
+	expr, results, ok := s.evalPromQL(w, r, at, at, 0)
+	if !ok {
+		return
+	}
+
+	var data promData
+	if n, isNumber := expr.(*promql.Number); isNumber {
+		data = promData{ResultType: "scalar", Result: promPoint(promql.Sample{T: at.UnixMilli(), V: n.Value})}
+	} else {
+		samples := make([]promSample, len(results))
+		for i, res := range results {
+			samples[i] = promSample{Metric: res.Labels, Value: promPoint(res.Points[0])}
+		}
+		data = promData{ResultType: "vector", Result: samples}
+	}
+	writeJSON(w, promResponse{Status: "success", Data: data})
+}
+
+// handleQueryRange evaluates a PromQL expression at every step of a range,
+// as Prometheus' /api/v1/query_range does: query, start and end (unix
+// seconds or RFC 3339) and step (seconds or a duration like 30s).
+func (s *Server) handleQueryRange(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet && r.Method != http.MethodPost {
+		writePromError(w, "bad_data", "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	start, err := parsePromTime(r.FormValue("start"), time.Time{})
+	if err != nil || start.IsZero() {
+		writePromError(w, "bad_data", "start must be a time", http.StatusBadRequest)
+		return
+	}
+	end, err := parsePromTime(r.FormValue("end"), time.Time{})
+	if err != nil || end.IsZero() {
+		writePromError(w, "bad_data", "end must be a time", http.StatusBadRequest)
+		return
+	}
+	step, err := promql.ParseDuration(r.FormValue("step"))
+	if err != nil {
+		writePromError(w, "bad_data", "invalid step: "+err.Error(), http.StatusBadRequest)
+		return
+	}
+	if end.Before(start) {
+		writePromError(w, "bad_data", "end must not be before start", http.StatusBadRequest)
+		return
+	}
+	if end.Sub(start) > maxExportRange {
+		writePromError(w, "bad_data", "time range must not exceed "+maxExportRange.String(), http.StatusBadRequest)
+		return
+	}
+
+	_, results, ok := s.evalPromQL(w, r, start, end, step)
+	if !ok {
+		return
+	}
+	series := make([]promSeries, len(results))
+	for i, res := range results {
+		values := make([][2]interface{}, len(res.Points))
+		for j, p := range res.Points {
+			values[j] = promPoint(p)
+		}
+		series[i] = promSeries{Metric: res.Labels, Values: values}
+	}
+	writeJSON(w, promResponse{Status: "success", Data: promData{ResultType: "matrix", Result: series}})
+}
+
+// evalPromQL parses the query parameter and evaluates it, writing the
+// error response if either fails
+func (s *Server) evalPromQL(w http.ResponseWriter, r *http.Request, start, end time.Time, step time.Duration) (promql.Expr, []promql.Result, bool) {
+	expr, err := promql.Parse(r.FormValue("query"))
+	if err != nil {
+		writePromError(w, "bad_data", err.Error(), http.StatusBadRequest)
+		return nil, nil, false
+	}
+	results, err := promql.Eval(&promSource{server: s, nodes: s.tenantNodes(r)}, expr, start, end, step)
+	var tooExpensive *promql.Error
+	var unavailable *segmentsError
+	switch {
+	case errors.As(err, &tooExpensive):
+		writePromError(w, "execution", err.Error(), http.StatusUnprocessableEntity)
+	case errors.As(err, &unavailable):
+		writePromError(w, "unavailable", err.Error(), http.StatusBadGateway)
+	case err != nil:
+		writePromError(w, "internal", err.Error(), http.StatusInternalServerError)
+	default:
+		return expr, results, true
+	}
+	return nil, nil, false
+}
+
+// EvaluateInstant evaluates expr at one instant over every node's metrics,
+// as recording rules do
+func (s *Server) EvaluateInstant(expr promql.Expr, at time.Time) ([]promql.Result, error) {
+	return promql.Eval(&promSource{server: s}, expr, at, at, 0)
+}
+
+type segmentsError struct {
+	err error
+}
+
+func (e *segmentsError) Error() string { return "cold tier unavailable: " + e.err.Error() }
+
+// promSource serves PromQL selectors from storage. Series are a node's
+// resource's samples of a metric, one per agent metric type, device,
+// container and volume, labelled with __name__, node, resource_id, pod and
+// namespace, type, device, container_id and volume where set, and the
+// node's labels, or the results of a recording rule, which belong to no
+// node.
+type promSource struct {
+	server *Server
+	// A tenant's nodes, or nil for all
+	nodes []string
+	// Loaded when first needed
+	pods     map[int64]store.PodRef
+	recorded map[int64]string
+}
+
+func (p *promSource) Select(name string, matchers []promql.Matcher, start, end time.Time, resolution time.Duration) ([]promql.Series, error) {
+	f := store.MetricFilter{
+		Start:      start,
+		End:        end.Add(time.Millisecond), // end is inclusive
+		Nodes:      p.nodes,
+		MetricType: name,
+	}
+	for _, m := range matchers {
+		if m.Op != promql.MatchEqual {
+			continue
+		}
+		switch m.Name {
+		case "node":
+			f.Node = m.Value
+		case "type":
+			f.Type = m.Value
+		}
+	}
+	if p.pods == nil && !volumeMetrics[name] {
+		pods, err := p.server.sqlite.PodRefs()
+		if err != nil {
+			return nil, err
+		}
+		p.pods = pods
+	}
+
+	segments, err := p.server.segmentsFor(start, end)
+	if err != nil {
+		return nil, &segmentsError{err}
+	}
+	rows, err := p.server.duck.QuerySeries(f, resolution, segments)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	type seriesKey struct {
+		node        string
+		resourceID  int64
+		metricType  string
+		device      string
+		containerID string
+		volume      string
+	}
+	var all []promql.Series
+	seen := map[seriesKey]int{}
+	nodeLabels := map[seriesKey]string{}
+	for rows.Next() {
+		var (
+			t      int64
+			key    seriesKey
+			labels sql.NullString
+			value  float64
+		)
+		if err := rows.Scan(&t, &key.node, &key.resourceID, &key.metricType, &key.device, &key.containerID, &key.volume, &labels, &value); err != nil {
+			return nil, err
+		}
+		i, ok := seen[key]
+		if !ok {
+			i = len(all)
+			seen[key] = i
+			all = append(all, promql.Series{})
+		}
+		// Cold-tier rows carry no labels; the latest local row's are kept
+		if labels.String != "" {
+			nodeLabels[key] = labels.String
+		}
+		all[i].Samples = append(all[i].Samples, promql.Sample{T: t, V: value})
+	}
+	if err := rows.Err(); err != nil {
+		return nil, err
+	}
+
+	series := make([]promql.Series, 0, len(all))
+	for key, i := range seen {
+		labels := promql.Labels{}
+		if key.node == "" && key.resourceID < 0 {
+			// A recording rule's result, labelled as the rule's expression left it
+			if p.recorded == nil {
+				if p.recorded, err = p.server.sqlite.RecordedSeries(); err != nil {
+					return nil, err
+				}
+			}
+			json.Unmarshal([]byte(p.recorded[-key.resourceID]), &labels)
+		} else {
+			if raw, ok := nodeLabels[key]; ok {
+				json.Unmarshal([]byte(raw), &labels)
+			}
+			labels["node"] = key.node
+			labels["resource_id"] = strconv.FormatInt(key.resourceID, 10)
+			if pod, ok := p.pods[key.resourceID]; ok && !volumeMetrics[name] && key.metricType != "pvc_usage" {
+				labels["pod"] = pod.Name
+				labels["namespace"] = pod.Namespace
+			}
+			for label, value := range map[string]string{
+				"type":         key.metricType,
+				"device":       key.device,
+				"container_id": key.containerID,
+				"volume":       key.volume,
+			} {
+				if value != "" {
+					labels[label] = value
+				}
+			}
+		}
+		labels["__name__"] = name
+		if matchesAll(matchers, labels) {
+			series = append(series, promql.Series{Labels: labels, Samples: all[i].Samples})
+		}
+	}
+	return series, nil
+}
+
+func matchesAll(matchers []promql.Matcher, labels promql.Labels) bool {
+	for _, m := range matchers {
+		if !m.Matches(labels[m.Name]) {
+			return false
+		}
+	}
+	return true
+}
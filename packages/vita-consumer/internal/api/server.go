@@ -1,36 +1,152 @@
 package api
 
 import (
+	"context"
 	"encoding/json"
 	"net/http"
 	"strconv"
+	"strings"
+	"time"
 
 	"github.com/nchanged/vitakube/packages/vita-consumer/internal/buffer"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/querycache"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/secret"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/selfmetrics"
 	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/tenant"
 )
 
+// SegmentSource fetches cold-tier segments covering a time range and
+// returns local Parquet paths that can be queried alongside DuckDB.
+type SegmentSource interface {
+	Fetch(start, end time.Time) ([]string, error)
+}
+
 type Server struct {
-	sqlite *store.SQLiteStore
-	ring   *buffer.RingBuffer
+	sqlite   *store.SQLiteStore
+	duck     *store.DuckDBStore
+	ring     *buffer.RingBuffer
+	dataDir  string
+	segments SegmentSource
+	cache    *querycache.Cache
+	tenants  *tenant.Registry
+	admin    *secret.Value
 }
 
-func NewServer(sqlite *store.SQLiteStore, ring *buffer.RingBuffer) *Server {
+func NewServer(sqlite *store.SQLiteStore, duck *store.DuckDBStore, ring *buffer.RingBuffer, dataDir string) *Server {
 	return &Server{
-		sqlite: sqlite,
-		ring:   ring,
+		sqlite:  sqlite,
+		duck:    duck,
+		ring:    ring,
+		dataDir: dataDir,
+	}
+}
+
+// SetSegmentSource enables transparent reads from the cold tier
+func (s *Server) SetSegmentSource(src SegmentSource) {
+	s.segments = src
+}
+
+// SetQueryCache answers repeated aggregating queries from memory; call
+// before RegisterRoutes
+func (s *Server) SetQueryCache(c *querycache.Cache) {
+	s.cache = c
+}
+
+// cached serves a handler through the query cache, if one is set
+func (s *Server) cached(name string, h http.HandlerFunc) http.HandlerFunc {
+	if s.cache == nil {
+		return h
 	}
+	return s.cache.Wrap(name, h)
+}
+
+// SetTenants requires a tenant's token on every request, or the admin token
+// for access to everything; call before RegisterRoutes
+func (s *Server) SetTenants(tenants *tenant.Registry, admin *secret.Value) {
+	s.tenants = tenants
+	s.admin = admin
 }
 
+type tenantKey struct{}
+
+// guarded runs a handler for the tenant whose token the request carries,
+// within the tenant's concurrent query limit and with its metrics limited
+// to the tenant's nodes
+func (s *Server) guarded(h http.HandlerFunc) http.HandlerFunc {
+	if s.tenants == nil {
+		return h
+	}
+	return func(w http.ResponseWriter, r *http.Request) {
+		given := strings.TrimPrefix(r.Header.Get("Authorization"), "Bearer ")
+		if s.admin.Matches(given) {
+			h(w, r)
+			return
+		}
+		t := s.tenants.Lookup(given)
+		if t == nil {
+			writeError(w, "Unauthorized", http.StatusUnauthorized)
+			return
+		}
+		release, ok := t.AcquireQuery()
+		if !ok {
+			w.Header().Set("Retry-After", "1")
+			writeError(w, "Too many concurrent queries", http.StatusTooManyRequests)
+			return
+		}
+		defer release()
+
+		r = querycache.WithScope(r, t.Name)
+		h(w, r.WithContext(context.WithValue(r.Context(), tenantKey{}, t)))
+	}
+}
+
+// tenantNodes returns the nodes a request's results are limited to, or nil
+// for all of them
+func (s *Server) tenantNodes(r *http.Request) []string {
+	t, _ := r.Context().Value(tenantKey{}).(*tenant.Tenant)
+	if t == nil {
+		return nil
+	}
+	return s.tenants.Nodes(t)
+}
+
+// segmentsFor returns the cold-tier files needed for a range, if a tier is configured
+func (s *Server) segmentsFor(start, end time.Time) ([]string, error) {
+	if s.segments == nil {
+		return nil, nil
+	}
+	return s.segments.Fetch(start, end)
+}
+
+// RegisterRoutes mounts the dashboard API; each endpoint's latency shows on
+// /metrics. With tenants, every endpoint needs a tenant or the admin token.
 func (s *Server) RegisterRoutes(mux *http.ServeMux) {
 	// List endpoints
-	mux.HandleFunc("/api/v1/nodes", s.handleListNodes)
-	mux.HandleFunc("/api/v1/namespaces", s.handleListNamespaces)
-	mux.HandleFunc("/api/v1/deployments", s.handleListDeployments)
-	mux.HandleFunc("/api/v1/pods", s.handleListPods)
-	mux.HandleFunc("/api/v1/pvcs", s.handleListPVCs)
+	mux.HandleFunc("/api/v1/nodes", selfmetrics.Instrument("nodes", s.guarded(s.handleListNodes)))
+	mux.HandleFunc("/api/v1/namespaces", selfmetrics.Instrument("namespaces", s.guarded(s.handleListNamespaces)))
+	mux.HandleFunc("/api/v1/deployments", selfmetrics.Instrument("deployments", s.guarded(s.handleListDeployments)))
+	mux.HandleFunc("/api/v1/pods", selfmetrics.Instrument("pods", s.guarded(s.handleListPods)))
+	mux.HandleFunc("/api/v1/pvcs", selfmetrics.Instrument("pvcs", s.guarded(s.handleListPVCs)))
+	mux.HandleFunc("/api/v1/workloads", selfmetrics.Instrument("workloads", s.guarded(s.handleListWorkloads)))
+	mux.HandleFunc("/api/v1/events", selfmetrics.Instrument("events", s.guarded(s.handleListEvents)))
+	mux.HandleFunc("/api/v1/node-events", selfmetrics.Instrument("node_events", s.guarded(s.handleListNodeEvents)))
+
+	// Usage rolled up from pods to their workloads
+	mux.HandleFunc("/api/v1/workloads/usage", selfmetrics.Instrument("workload_usage", s.guarded(s.cached("workload_usage", s.handleWorkloadUsage))))
+
+	// How much of a range each node and pod has data for
+	mux.HandleFunc("/api/v1/availability", selfmetrics.Instrument("availability", s.guarded(s.cached("availability", s.handleAvailability))))
 
 	// Live metrics
-	mux.HandleFunc("/api/v1/metrics/live", s.handleLiveMetrics)
+	mux.HandleFunc("/api/v1/metrics/live", selfmetrics.Instrument("metrics_live", s.guarded(s.handleLiveMetrics)))
+
+	// Bulk export
+	mux.HandleFunc("/api/v1/export", selfmetrics.Instrument("export", s.guarded(s.handleExport)))
+
+	// Prometheus-compatible PromQL queries
+	mux.HandleFunc("/api/v1/query", selfmetrics.Instrument("query", s.guarded(s.handleQuery)))
+	mux.HandleFunc("/api/v1/query_range", selfmetrics.Instrument("query_range", s.guarded(s.cached("query_range", s.handleQueryRange))))
 }
 
 // Helper functions
@@ -0,0 +1,171 @@
+package api
+
+import (
+	"net/http"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// Default and smallest bucket of a workload usage query
+const (
+	defaultUsageStep = time.Minute
+	minUsageStep     = time.Second
+)
+
+// WorkloadInfo is a workload and how many pods it has had
+type WorkloadInfo struct {
+	Workload  string `json:"workload"` // <kind>/<namespace>/<name>
+	Kind      string `json:"kind"`
+	Namespace string `json:"namespace"`
+	Name      string `json:"name"`
+	Pods      int    `json:"pods"`
+}
+
+// WorkloadUsage is one metric of one workload over time
+type WorkloadUsage struct {
+	Workload   string       `json:"workload"`
+	MetricType string       `json:"metric_type"`
+	Points     []UsagePoint `json:"points"`
+}
+
+// UsagePoint is the sum over a workload's pods of each pod's average in a bucket
+type UsagePoint struct {
+	Time  int64   `json:"t"` // unix seconds, start of the bucket
+	Value float64 `json:"v"`
+}
+
+// workloadFilter reads workload (<kind>/<namespace>/<name>), or kind and
+// namespace, from the query string
+func workloadFilter(r *http.Request) (store.WorkloadFilter, error) {
+	q := r.URL.Query()
+	if key := q.Get("workload"); key != "" {
+		kind, namespace, name, err := store.ParseWorkloadKey(key)
+		if err != nil {
+			return store.WorkloadFilter{}, err
+		}
+		return store.WorkloadFilter{Kind: kind, Namespace: namespace, Name: name}, nil
+	}
+	return store.WorkloadFilter{Kind: q.Get("kind"), Namespace: q.Get("namespace")}, nil
+}
+
+// handleListWorkloads returns the Deployments, StatefulSets and DaemonSets
+// with pods the syncer has seen. Optional filters: kind and namespace.
+func (s *Server) handleListWorkloads(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	filter, err := workloadFilter(r)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusBadRequest)
+		return
+	}
+	workloads, err := s.sqlite.Workloads(filter)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	infos := make([]WorkloadInfo, len(workloads))
+	for i, wl := range workloads {
+		infos[i] = WorkloadInfo{Workload: wl.Key(), Kind: wl.Kind, Namespace: wl.Namespace, Name: wl.Name, Pods: len(wl.PodIDs)}
+	}
+	writeJSON(w, infos)
+}
+
+// handleWorkloadUsage rolls pod metrics up to their workloads, so a
+// Deployment reads as one series however often its pods were replaced.
+// Selects workloads like /api/v1/workloads, with the metric filters of
+// /api/v1/export (start, end, node, metric_type, label.*) and step, the
+// bucket in seconds (60). Each bucket sums every pod's average in it;
+// cumulative counters like cpu_ms drop whenever a pod goes away, so their
+// rates are best taken per pod.
+func (s *Server) handleWorkloadUsage(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		writeError(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	filter, err := s.parseMetricFilter(r)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusBadRequest)
+		return
+	}
+	step := defaultUsageStep
+	if v, ok := getQueryInt(r, "step"); ok {
+		step = max(time.Duration(v)*time.Second, minUsageStep)
+	}
+
+	selector, err := workloadFilter(r)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusBadRequest)
+		return
+	}
+	workloads, err := s.sqlite.Workloads(selector)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	workloadOf := map[int64]string{}
+	filter.ResourceIDs = []int64{}
+	for _, wl := range workloads {
+		for _, id := range wl.PodIDs {
+			workloadOf[id] = wl.Key()
+			filter.ResourceIDs = append(filter.ResourceIDs, id)
+		}
+	}
+
+	segments, err := s.segmentsFor(filter.Start, filter.End)
+	if err != nil {
+		writeError(w, "cold tier unavailable: "+err.Error(), http.StatusBadGateway)
+		return
+	}
+	rows, err := s.duck.QueryBuckets(filter, step, segments)
+	if err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	defer rows.Close()
+
+	type seriesKey struct{ workload, metricType string }
+	series := map[seriesKey]*WorkloadUsage{}
+	order := []seriesKey{}
+	for rows.Next() {
+		var (
+			bucket     time.Time
+			resourceID int64
+			metricType string
+			value      float64
+		)
+		if err := rows.Scan(&bucket, &resourceID, &metricType, &value); err != nil {
+			writeError(w, err.Error(), http.StatusInternalServerError)
+			return
+		}
+		key := seriesKey{workloadOf[resourceID], metricType}
+		usage, ok := series[key]
+		if !ok {
+			usage = &WorkloadUsage{Workload: key.workload, MetricType: metricType}
+			series[key] = usage
+			order = append(order, key)
+		}
+		// Rows come ordered by bucket, so a bucket's pods are adjacent per series
+		if n := len(usage.Points); n > 0 && usage.Points[n-1].Time == bucket.Unix() {
+			usage.Points[n-1].Value += value
+		} else {
+			usage.Points = append(usage.Points, UsagePoint{Time: bucket.Unix(), Value: value})
+		}
+	}
+	if err := rows.Err(); err != nil {
+		writeError(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+
+	result := make([]WorkloadUsage, len(order))
+	for i, key := range order {
+		result[i] = *series[key]
+	}
+	writeJSON(w, result)
+}
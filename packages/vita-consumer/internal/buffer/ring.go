@@ -2,14 +2,28 @@ package buffer
 
 import (
 	"sync"
+	"sync/atomic"
 	"time"
 )
 
 type Metric struct {
 	Time       time.Time
+	Node       string
 	ResourceID int64
-	Type       string
-	Value      float64
+	// What the agent collected it as, e.g. node_disk, and which of its
+	// values, e.g. reads
+	Type  string
+	Key   string
+	Value float64
+	// Series labels, "" where they don't apply: the block device or
+	// interface, the container and the volume
+	Device      string
+	ContainerID string
+	Volume      string
+	// Node labels as a JSON object, "" if the agent sent none
+	Labels string
+	// Microseconds into the agent's collection cycle the value was read at
+	Offset int64
 }
 
 // RingBuffer is a simplified circular buffer or slice-based buffer
@@ -17,6 +31,7 @@ type RingBuffer struct {
 	mu      sync.RWMutex
 	metrics []Metric
 	maxSize int
+	dropped atomic.Uint64
 }
 
 func NewRingBuffer(maxSize int) *RingBuffer {
@@ -37,11 +52,29 @@ func (rb *RingBuffer) Add(m Metric) {
 		// Drop oldest? or Block?
 		// For efficiency, let's just drop for now or expand.
 		// Dropping is safer for memory.
+		rb.dropped.Add(1)
 		return
 	}
 	rb.metrics = append(rb.metrics, m)
 }
 
+// Len is the number of metrics waiting for the next flush
+func (rb *RingBuffer) Len() int {
+	rb.mu.RLock()
+	defer rb.mu.RUnlock()
+	return len(rb.metrics)
+}
+
+// Cap is the most metrics held before new ones are dropped
+func (rb *RingBuffer) Cap() int {
+	return rb.maxSize
+}
+
+// Dropped counts metrics lost to a full buffer since start
+func (rb *RingBuffer) Dropped() uint64 {
+	return rb.dropped.Load()
+}
+
 func (rb *RingBuffer) Flush() []Metric {
 	rb.mu.Lock()
 	defer rb.mu.Unlock()
@@ -61,3 +94,19 @@ func (rb *RingBuffer) ReadAll() []Metric {
 	copy(result, rb.metrics)
 	return result
 }
+
+// RemoveIf drops buffered metrics matching pred and returns how many
+func (rb *RingBuffer) RemoveIf(pred func(Metric) bool) int {
+	rb.mu.Lock()
+	defer rb.mu.Unlock()
+
+	kept := rb.metrics[:0]
+	for _, m := range rb.metrics {
+		if !pred(m) {
+			kept = append(kept, m)
+		}
+	}
+	removed := len(rb.metrics) - len(kept)
+	rb.metrics = kept
+	return removed
+}
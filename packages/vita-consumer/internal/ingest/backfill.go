@@ -0,0 +1,97 @@
+package ingest
+
+import (
+	"encoding/json"
+	"log"
+	"net/http"
+	"sort"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// EnableBackfill turns on the backfill endpoint, writing to w at no more than
+// ratePerSec metrics per second (bursts of up to ten seconds' worth).
+func (s *IngestionServer) EnableBackfill(w PointWriter, ratePerSec float64) {
+	s.backfill = w
+	s.backfillLimit = newTokenBucket(ratePerSec, ratePerSec*10)
+}
+
+// HandleBackfill accepts the same batches as HandleIngest, for data spooled
+// by agents during an outage. Old timestamps are expected here: batches skip
+// the live ring buffer and are written straight to storage, which accepts
+// rows in any order. Live ingest is unaffected by backfill load because it
+// has its own rate limit.
+func (s *IngestionServer) HandleBackfill(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodPost {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	if s.backfill == nil {
+		http.Error(w, "Backfill disabled", http.StatusNotFound)
+		return
+	}
+	if !s.authorized(r) {
+		http.Error(w, "Unauthorized", http.StatusUnauthorized)
+		return
+	}
+	t := s.tenantOf(r)
+
+	if node := r.Header.Get(NodeHeader); node != "" && s.redirectToOwner(w, r, node) {
+		return
+	}
+
+	body, err := s.verifiedBody(r)
+	if err != nil {
+		http.Error(w, "Invalid signature", http.StatusUnauthorized)
+		return
+	}
+	if body, err = decodedBody(r, body); err != nil {
+		http.Error(w, "Unsupported encoding", http.StatusUnsupportedMediaType)
+		return
+	}
+
+	var req IngestRequest
+	if err := json.NewDecoder(body).Decode(&req); err != nil {
+		http.Error(w, "Invalid JSON", http.StatusBadRequest)
+		return
+	}
+	if s.signingKey != nil && req.NodeName != r.Header.Get(NodeHeader) {
+		http.Error(w, "Node mismatch", http.StatusUnauthorized)
+		return
+	}
+
+	if s.redirectToOwner(w, r, req.NodeName) {
+		return
+	}
+	if err := s.validateBatch(&req, "backfill"); err != nil {
+		writeBatchError(w, err)
+		return
+	}
+	if !s.admitTenant(w, t, req.NodeName) || !admitStorage(w, t) {
+		return
+	}
+
+	if ok, wait := s.backfillLimit.take(len(req.Metrics)); !ok {
+		backpressure(w, http.StatusTooManyRequests, wait, "Backfill rate limit exceeded")
+		return
+	}
+
+	// Only the future window applies: backfill takes history of any age
+	var resp IngestResponse
+	metrics := s.resolve(t, &req, "backfill", &resp)
+
+	// Sorting keeps inserted row groups time-clustered for range scans
+	sort.Slice(metrics, func(i, j int) bool { return metrics[i].Time.Before(metrics[j].Time) })
+
+	if err := s.backfill.BatchInsert(store.PointsFromBuffer(metrics)); err != nil {
+		log.Printf("Backfill insert failed: %v", err)
+		http.Error(w, "Storage unavailable", http.StatusServiceUnavailable)
+		return
+	}
+
+	s.replicate("backfill", &req)
+
+	resp.Accepted = len(metrics)
+	observeResult("backfill", &resp)
+	writeAccepted(w, &resp)
+}
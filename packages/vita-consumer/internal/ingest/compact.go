@@ -0,0 +1,109 @@
+package ingest
+
+import (
+	"encoding/json"
+	"errors"
+	"io"
+	"sync"
+	"time"
+)
+
+// CompactContentType marks batches that reference a per-session series
+// dictionary instead of repeating labels on every metric
+const CompactContentType = "application/x-vita-compact+json"
+
+// Sessions idle this long are forgotten; the agent resends its dictionary
+// when it gets a 409
+const sessionTTL = 10 * time.Minute
+
+var errUnknownSeries = errors.New("unknown series id")
+
+// CompactRequest is a batch in compact wire mode. Series carries labels for
+// ids not yet defined in the session; Points are [id, value, ts, offset].
+type CompactRequest struct {
+	NodeName   string            `json:"node"`
+	NodeLabels map[string]string `json:"node_labels,omitempty"`
+	Session    string            `json:"session"`
+	Series     []SeriesDef       `json:"series"`
+	Points     [][]float64       `json:"points"`
+}
+
+// SeriesDef binds a session-local id to a label set. Value, ts and offset
+// of the embedded metric are unused.
+type SeriesDef struct {
+	ID uint32 `json:"id"`
+	RawMetric
+}
+
+type session struct {
+	series   map[uint32]RawMetric
+	lastSeen time.Time
+}
+
+// sessionTable holds the series dictionaries of compact-mode agents
+type sessionTable struct {
+	mu        sync.Mutex
+	sessions  map[string]*session
+	lastSweep time.Time
+}
+
+func newSessionTable() *sessionTable {
+	return &sessionTable{sessions: make(map[string]*session)}
+}
+
+// decodeCompact reads a compact batch and expands it into a regular request
+func (t *sessionTable) decodeCompact(body io.Reader, req *IngestRequest) error {
+	var c CompactRequest
+	if err := json.NewDecoder(body).Decode(&c); err != nil {
+		return err
+	}
+	if c.Session == "" {
+		return errors.New("missing session")
+	}
+
+	now := time.Now()
+	key := c.NodeName + "/" + c.Session
+
+	t.mu.Lock()
+	defer t.mu.Unlock()
+
+	if now.Sub(t.lastSweep) > time.Minute {
+		for k, s := range t.sessions {
+			if now.Sub(s.lastSeen) > sessionTTL {
+				delete(t.sessions, k)
+			}
+		}
+		t.lastSweep = now
+	}
+
+	sess, ok := t.sessions[key]
+	if !ok {
+		sess = &session{series: make(map[uint32]RawMetric)}
+		t.sessions[key] = sess
+	}
+	sess.lastSeen = now
+
+	for _, def := range c.Series {
+		sess.series[def.ID] = def.RawMetric
+	}
+
+	req.NodeName = c.NodeName
+	req.NodeLabels = c.NodeLabels
+	req.Metrics = make([]RawMetric, 0, len(c.Points))
+	for _, p := range c.Points {
+		if len(p) < 3 {
+			return errors.New("malformed point")
+		}
+		m, ok := sess.series[uint32(p[0])]
+		if !ok {
+			return errUnknownSeries
+		}
+		m.Value = p[1]
+		m.Timestamp = int64(p[2])
+		if len(p) > 3 {
+			m.Offset = int64(p[3])
+		}
+		req.Metrics = append(req.Metrics, m)
+	}
+	return nil
+}
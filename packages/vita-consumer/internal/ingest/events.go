@@ -0,0 +1,78 @@
+package ingest
+
+import (
+	"encoding/json"
+	"log"
+	"net/http"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// EventWriter persists Kubernetes events shipped by the elected agent
+type EventWriter interface {
+	InsertEvents(events []store.Event) error
+}
+
+type EventBatch struct {
+	NodeName string        `json:"node"`
+	Events   []store.Event `json:"events"`
+}
+
+// EnableEvents turns on the events endpoint, writing to w
+func (s *IngestionServer) EnableEvents(w EventWriter) {
+	s.events = w
+}
+
+// HandleEvents accepts event batches from the agent running cluster-scoped
+// collectors. Events are small and rare next to metrics, so they skip the
+// ring buffer and WAL and are written straight to the metadata store.
+func (s *IngestionServer) HandleEvents(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodPost {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	if s.events == nil {
+		http.Error(w, "Events disabled", http.StatusNotFound)
+		return
+	}
+	if !s.authorized(r) {
+		http.Error(w, "Unauthorized", http.StatusUnauthorized)
+		return
+	}
+	t := s.tenantOf(r)
+
+	if node := r.Header.Get(NodeHeader); node != "" && s.redirectToOwner(w, r, node) {
+		return
+	}
+
+	body, err := s.verifiedBody(r)
+	if err != nil {
+		http.Error(w, "Invalid signature", http.StatusUnauthorized)
+		return
+	}
+
+	var req EventBatch
+	if err := json.NewDecoder(body).Decode(&req); err != nil {
+		http.Error(w, "Invalid JSON", http.StatusBadRequest)
+		return
+	}
+	if s.signingKey != nil && req.NodeName != r.Header.Get(NodeHeader) {
+		http.Error(w, "Node mismatch", http.StatusUnauthorized)
+		return
+	}
+
+	if s.redirectToOwner(w, r, req.NodeName) {
+		return
+	}
+	if !s.admitTenant(w, t, req.NodeName) {
+		return
+	}
+
+	if err := s.events.InsertEvents(req.Events); err != nil {
+		log.Printf("Event insert failed: %v", err)
+		http.Error(w, "Storage unavailable", http.StatusServiceUnavailable)
+		return
+	}
+
+	w.WriteHeader(http.StatusAccepted)
+}
@@ -0,0 +1,148 @@
+package ingest
+
+import (
+	"compress/gzip"
+	"encoding/json"
+	"errors"
+	"io"
+	"log"
+	"net/http"
+	"time"
+)
+
+// ProtocolVersion is the ingest protocol this consumer speaks. It changes
+// only when a batch means something different on the wire.
+const ProtocolVersion = 1
+
+// MaxBatchMetrics bounds one ingest request; agents split larger flushes
+const MaxBatchMetrics = 20000
+
+var errUnsupportedEncoding = errors.New("unsupported content encoding")
+
+// HandshakeRequest is what an agent offers at startup, each list in order
+// of preference
+type HandshakeRequest struct {
+	Node        string   `json:"node"`
+	Version     string   `json:"version"`
+	Protocols   []int    `json:"protocols"`
+	WireFormats []string `json:"wire_formats"`
+	Compression []string `json:"compression"`
+}
+
+// HandshakeResponse is what the agent must use from then on
+type HandshakeResponse struct {
+	Protocol        int    `json:"protocol"`
+	WireFormat      string `json:"wire_format"`
+	Compression     string `json:"compression"`
+	MaxBatchMetrics int    `json:"max_batch_metrics"`
+	// Payloads accepted besides metrics: "events", "node-events", "heartbeat", "backfill"
+	Kinds []string `json:"kinds"`
+	// Live metrics further behind are clamped or rejected; agents with
+	// backfill send older spooled metrics there. 0 while late metrics are
+	// accepted.
+	LateToleranceSecs int64 `json:"late_tolerance_secs,omitempty"`
+}
+
+// HandleHandshake agrees on a protocol version, wire format and compression
+// with an agent. Agents that get a 404 here talk to a consumer from before
+// the handshake and fall back to what it always accepted.
+func (s *IngestionServer) HandleHandshake(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodPost {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	if !s.authorized(r) {
+		http.Error(w, "Unauthorized", http.StatusUnauthorized)
+		return
+	}
+
+	body, err := s.verifiedBody(r)
+	if err != nil {
+		http.Error(w, "Invalid signature", http.StatusUnauthorized)
+		return
+	}
+
+	var req HandshakeRequest
+	if err := json.NewDecoder(body).Decode(&req); err != nil {
+		http.Error(w, "Invalid JSON", http.StatusBadRequest)
+		return
+	}
+
+	if !containsInt(req.Protocols, ProtocolVersion) {
+		log.Printf("Agent %s (%s) speaks protocols %v, not %d", req.Node, req.Version, req.Protocols, ProtocolVersion)
+		http.Error(w, "No common protocol version", http.StatusUpgradeRequired)
+		return
+	}
+
+	resp := HandshakeResponse{
+		Protocol:        ProtocolVersion,
+		WireFormat:      firstOf(req.WireFormats, "compact", "json"),
+		Compression:     firstOf(req.Compression, "gzip", "identity"),
+		MaxBatchMetrics: MaxBatchMetrics,
+		Kinds:           s.kinds(),
+	}
+	// Every agent can fall back to plain JSON and identity
+	if resp.WireFormat == "" {
+		resp.WireFormat = "json"
+	}
+	if resp.Compression == "" {
+		resp.Compression = "identity"
+	}
+	if s.late.policy != TimestampAccept {
+		resp.LateToleranceSecs = int64(s.late.tolerance / time.Second)
+	}
+
+	w.Header().Set("Content-Type", "application/json")
+	json.NewEncoder(w).Encode(resp)
+}
+
+// kinds lists the optional endpoints enabled on this consumer
+func (s *IngestionServer) kinds() []string {
+	kinds := []string{}
+	if s.events != nil {
+		kinds = append(kinds, "events")
+	}
+	if s.nodeEvents != nil {
+		kinds = append(kinds, "node-events")
+	}
+	if s.heartbeats != nil {
+		kinds = append(kinds, "heartbeat")
+	}
+	if s.backfill != nil {
+		kinds = append(kinds, "backfill")
+	}
+	return kinds
+}
+
+// decodedBody undoes the request's Content-Encoding. Signatures cover the
+// bytes as sent, so this runs after verifiedBody.
+func decodedBody(r *http.Request, body io.Reader) (io.Reader, error) {
+	switch r.Header.Get("Content-Encoding") {
+	case "", "identity":
+		return body, nil
+	case "gzip":
+		return gzip.NewReader(body)
+	}
+	return nil, errUnsupportedEncoding
+}
+
+// firstOf returns the first of offered that is also in supported
+func firstOf(offered []string, supported ...string) string {
+	for _, o := range offered {
+		for _, s := range supported {
+			if o == s {
+				return o
+			}
+		}
+	}
+	return ""
+}
+
+func containsInt(values []int, want int) bool {
+	for _, v := range values {
+		if v == want {
+			return true
+		}
+	}
+	return false
+}
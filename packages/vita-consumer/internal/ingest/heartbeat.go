@@ -0,0 +1,76 @@
+package ingest
+
+import (
+	"encoding/json"
+	"log"
+	"net/http"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// HeartbeatWriter records the latest heartbeat of each agent
+type HeartbeatWriter interface {
+	UpsertHeartbeat(h store.Heartbeat) error
+}
+
+// EnableHeartbeats turns on the heartbeat endpoint, writing to w
+func (s *IngestionServer) EnableHeartbeats(w HeartbeatWriter) {
+	s.heartbeats = w
+}
+
+// HandleHeartbeat accepts an agent's periodic self report. Agents send it
+// even when collection fails, so a node whose heartbeat is fresh but whose
+// metrics stopped is quiet or broken, not unreachable.
+func (s *IngestionServer) HandleHeartbeat(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodPost {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	if s.heartbeats == nil {
+		http.Error(w, "Heartbeats disabled", http.StatusNotFound)
+		return
+	}
+	if !s.authorized(r) {
+		http.Error(w, "Unauthorized", http.StatusUnauthorized)
+		return
+	}
+	t := s.tenantOf(r)
+
+	if node := r.Header.Get(NodeHeader); node != "" && s.redirectToOwner(w, r, node) {
+		return
+	}
+
+	body, err := s.verifiedBody(r)
+	if err != nil {
+		http.Error(w, "Invalid signature", http.StatusUnauthorized)
+		return
+	}
+
+	var hb store.Heartbeat
+	if err := json.NewDecoder(body).Decode(&hb); err != nil || hb.Node == "" {
+		http.Error(w, "Invalid JSON", http.StatusBadRequest)
+		return
+	}
+	if s.signingKey != nil && hb.Node != r.Header.Get(NodeHeader) {
+		http.Error(w, "Node mismatch", http.StatusUnauthorized)
+		return
+	}
+
+	if s.redirectToOwner(w, r, hb.Node) {
+		return
+	}
+	if !s.admitTenant(w, t, hb.Node) {
+		return
+	}
+
+	// Our clock, so staleness doesn't depend on agent clocks
+	hb.LastSeen = time.Now().Unix()
+	if err := s.heartbeats.UpsertHeartbeat(hb); err != nil {
+		log.Printf("Heartbeat from %s not stored: %v", hb.Node, err)
+		http.Error(w, "Storage unavailable", http.StatusServiceUnavailable)
+		return
+	}
+
+	w.WriteHeader(http.StatusAccepted)
+}
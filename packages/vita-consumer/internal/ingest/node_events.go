@@ -0,0 +1,77 @@
+package ingest
+
+import (
+	"encoding/json"
+	"log"
+	"net/http"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// NodeEventWriter persists the state changes agents see on their nodes
+type NodeEventWriter interface {
+	InsertNodeEvents(node string, events []store.NodeEvent) error
+}
+
+type NodeEventBatch struct {
+	NodeName string            `json:"node"`
+	Events   []store.NodeEvent `json:"events"`
+}
+
+// EnableNodeEvents turns on the node events endpoint, writing to w
+func (s *IngestionServer) EnableNodeEvents(w NodeEventWriter) {
+	s.nodeEvents = w
+}
+
+// HandleNodeEvents accepts the state changes an agent saw on its node since
+// its last flush. Like Kubernetes events they skip the ring buffer and WAL.
+func (s *IngestionServer) HandleNodeEvents(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodPost {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	if s.nodeEvents == nil {
+		http.Error(w, "Node events disabled", http.StatusNotFound)
+		return
+	}
+	if !s.authorized(r) {
+		http.Error(w, "Unauthorized", http.StatusUnauthorized)
+		return
+	}
+	t := s.tenantOf(r)
+
+	if node := r.Header.Get(NodeHeader); node != "" && s.redirectToOwner(w, r, node) {
+		return
+	}
+
+	body, err := s.verifiedBody(r)
+	if err != nil {
+		http.Error(w, "Invalid signature", http.StatusUnauthorized)
+		return
+	}
+
+	var req NodeEventBatch
+	if err := json.NewDecoder(body).Decode(&req); err != nil {
+		http.Error(w, "Invalid JSON", http.StatusBadRequest)
+		return
+	}
+	if s.signingKey != nil && req.NodeName != r.Header.Get(NodeHeader) {
+		http.Error(w, "Node mismatch", http.StatusUnauthorized)
+		return
+	}
+
+	if s.redirectToOwner(w, r, req.NodeName) {
+		return
+	}
+	if !s.admitTenant(w, t, req.NodeName) {
+		return
+	}
+
+	if err := s.nodeEvents.InsertNodeEvents(req.NodeName, req.Events); err != nil {
+		log.Printf("Node event insert failed: %v", err)
+		http.Error(w, "Storage unavailable", http.StatusServiceUnavailable)
+		return
+	}
+
+	w.WriteHeader(http.StatusAccepted)
+}
@@ -0,0 +1,70 @@
+package ingest
+
+import (
+	"encoding/json"
+	"math"
+	"net/http"
+	"strconv"
+	"sync"
+	"time"
+)
+
+// BackpressureHint is the body of a 429 or 503. Agents hold their data for
+// RetryAfterMs, which is finer than the Retry-After header's whole seconds.
+type BackpressureHint struct {
+	Error        string `json:"error"`
+	RetryAfterMs int64  `json:"retry_after_ms"`
+}
+
+// backpressure rejects a request, asking the agent to retry after wait
+func backpressure(w http.ResponseWriter, code int, wait time.Duration, message string) {
+	w.Header().Set("Retry-After", strconv.Itoa(int(math.Ceil(wait.Seconds()))))
+	w.Header().Set("Content-Type", "application/json")
+	w.WriteHeader(code)
+	json.NewEncoder(w).Encode(BackpressureHint{Error: message, RetryAfterMs: wait.Milliseconds()})
+}
+
+// tokenBucket limits metrics per second. A batch larger than the burst is
+// admitted once the bucket is full and leaves it in debt, so big batches are
+// slowed down rather than rejected forever.
+type tokenBucket struct {
+	mu     sync.Mutex
+	rate   float64
+	burst  float64
+	tokens float64
+	last   time.Time
+}
+
+func newTokenBucket(rate float64, burst float64) *tokenBucket {
+	return &tokenBucket{
+		rate:   rate,
+		burst:  burst,
+		tokens: burst,
+		last:   time.Now(),
+	}
+}
+
+// take consumes n tokens, or reports how long until the batch would fit
+func (b *tokenBucket) take(n int) (bool, time.Duration) {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+
+	now := time.Now()
+	b.tokens += now.Sub(b.last).Seconds() * b.rate
+	if b.tokens > b.burst {
+		b.tokens = b.burst
+	}
+	b.last = now
+
+	need := float64(n)
+	if need > b.burst {
+		need = b.burst
+	}
+	if b.tokens < need {
+		wait := time.Duration((need - b.tokens) / b.rate * float64(time.Second))
+		return false, wait
+	}
+
+	b.tokens -= float64(n)
+	return true, 0
+}
@@ -0,0 +1,258 @@
+package ingest
+
+import (
+	"bytes"
+	"compress/gzip"
+	"context"
+	"encoding/json"
+	"fmt"
+	"log"
+	"net/http"
+	"sort"
+	"strings"
+	"sync"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/secret"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/selfmetrics"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// Batches sent to the standby per request
+const replicateBatchesPerRequest = 50
+
+// Retry backoff while the standby is unreachable
+const (
+	replicateMinBackoff = time.Second
+	replicateMaxBackoff = 30 * time.Second
+)
+
+var (
+	replicatedBatches  = selfmetrics.NewCounter("vitakube_replication_sent_batches_total", "Batches replicated to the standby.")
+	replicationDropped = selfmetrics.NewCounter("vitakube_replication_dropped_batches_total", "Batches dropped unreplicated because the queue was full.")
+	replicationErrors  = selfmetrics.NewCounter("vitakube_replication_failures_total", "Requests to the standby that failed and were retried.")
+)
+
+// ReplicatedBatch is an ingest request as the primary received it, before
+// IDs were resolved: the standby resolves them against its own metadata.
+type ReplicatedBatch struct {
+	// "live" or "backfill"
+	Path    string        `json:"path"`
+	Request IngestRequest `json:"request"`
+}
+
+type queuedBatch struct {
+	ReplicatedBatch
+	seq    uint64
+	queued time.Time
+}
+
+// replicator forwards stored batches to a standby consumer in the background.
+// Its queue is bounded by metrics; when the standby falls that far behind,
+// the oldest batches are dropped, so the primary's memory stays bounded.
+type replicator struct {
+	url    string
+	token  *secret.Value
+	client *http.Client
+
+	mu         sync.Mutex
+	queue      []queuedBatch
+	nextSeq    uint64
+	metrics    int
+	maxMetrics int
+	wake       chan struct{}
+}
+
+// EnableReplication sends every stored batch to the standby consumer at url,
+// holding up to maxMetrics metrics while it's unreachable. token, if set, is
+// sent as the bearer token the standby's REPLICATION_TOKEN checks. Lag and
+// queue depth show on /metrics.
+func (s *IngestionServer) EnableReplication(url string, token *secret.Value, maxMetrics int) {
+	r := &replicator{
+		url:        strings.TrimRight(url, "/") + "/api/v1/replicate",
+		token:      token,
+		client:     &http.Client{Timeout: 30 * time.Second},
+		maxMetrics: maxMetrics,
+		wake:       make(chan struct{}, 1),
+	}
+	s.replica = r
+
+	selfmetrics.GaugeFunc("vitakube_replication_queue_batches", "Batches waiting to be replicated to the standby.", func() float64 {
+		r.mu.Lock()
+		defer r.mu.Unlock()
+		return float64(len(r.queue))
+	})
+	selfmetrics.GaugeFunc("vitakube_replication_lag_seconds", "Age of the oldest batch not yet replicated to the standby.", func() float64 {
+		r.mu.Lock()
+		defer r.mu.Unlock()
+		if len(r.queue) == 0 {
+			return 0
+		}
+		return time.Since(r.queue[0].queued).Seconds()
+	})
+}
+
+// RunReplication sends queued batches until ctx is done; a no-op without
+// EnableReplication
+func (s *IngestionServer) RunReplication(ctx context.Context) {
+	if s.replica != nil {
+		s.replica.run(ctx)
+	}
+}
+
+// replicate queues a stored batch for the standby, if replication is on
+func (s *IngestionServer) replicate(path string, req *IngestRequest) {
+	if s.replica == nil || len(req.Metrics) == 0 {
+		return
+	}
+	r := s.replica
+	r.mu.Lock()
+	r.nextSeq++
+	r.queue = append(r.queue, queuedBatch{ReplicatedBatch{Path: path, Request: *req}, r.nextSeq, time.Now()})
+	r.metrics += len(req.Metrics)
+	for r.metrics > r.maxMetrics && len(r.queue) > 1 {
+		r.metrics -= len(r.queue[0].Request.Metrics)
+		r.queue = r.queue[1:]
+		replicationDropped.Inc()
+	}
+	r.mu.Unlock()
+
+	select {
+	case r.wake <- struct{}{}:
+	default:
+	}
+}
+
+func (r *replicator) run(ctx context.Context) {
+	backoff := replicateMinBackoff
+	for {
+		r.mu.Lock()
+		n := min(len(r.queue), replicateBatchesPerRequest)
+		batches := make([]ReplicatedBatch, n)
+		for i := range batches {
+			batches[i] = r.queue[i].ReplicatedBatch
+		}
+		var last uint64
+		if n > 0 {
+			last = r.queue[n-1].seq
+		}
+		r.mu.Unlock()
+
+		if n == 0 {
+			select {
+			case <-ctx.Done():
+				return
+			case <-r.wake:
+			}
+			continue
+		}
+
+		if err := r.send(ctx, batches); err != nil {
+			replicationErrors.Inc()
+			log.Printf("Replication to %s failed, retrying in %s: %v", r.url, backoff, err)
+			select {
+			case <-ctx.Done():
+				return
+			case <-time.After(backoff):
+			}
+			backoff = min(backoff*2, replicateMaxBackoff)
+			continue
+		}
+		backoff = replicateMinBackoff
+		replicatedBatches.Add(float64(n))
+
+		// Batches may have been dropped from the front while sending
+		r.mu.Lock()
+		for len(r.queue) > 0 && r.queue[0].seq <= last {
+			r.metrics -= len(r.queue[0].Request.Metrics)
+			r.queue = r.queue[1:]
+		}
+		r.mu.Unlock()
+	}
+}
+
+func (r *replicator) send(ctx context.Context, batches []ReplicatedBatch) error {
+	var body bytes.Buffer
+	gz := gzip.NewWriter(&body)
+	if err := json.NewEncoder(gz).Encode(batches); err != nil {
+		return err
+	}
+	if err := gz.Close(); err != nil {
+		return err
+	}
+
+	req, err := http.NewRequestWithContext(ctx, http.MethodPost, r.url, &body)
+	if err != nil {
+		return err
+	}
+	req.Header.Set("Content-Type", "application/json")
+	req.Header.Set("Content-Encoding", "gzip")
+	if token := r.token.Get(); token != "" {
+		req.Header.Set("Authorization", "Bearer "+token)
+	}
+	resp, err := r.client.Do(req)
+	if err != nil {
+		return err
+	}
+	resp.Body.Close()
+	if resp.StatusCode != http.StatusAccepted {
+		return fmt.Errorf("standby answered %s", resp.Status)
+	}
+	return nil
+}
+
+// SetReplicationToken requires primaries replicating to this consumer to
+// send "Authorization: Bearer <token>"; without it they need an ingest token
+func (s *IngestionServer) SetReplicationToken(token *secret.Value) {
+	s.replicationToken = token
+}
+
+// HandleReplicate stores batches a primary consumer replicates, as though
+// its agents had sent them here: live batches go through the WAL and the
+// buffer, backfilled ones straight to storage. The primary already applied
+// rate limits and tenant quotas.
+func (s *IngestionServer) HandleReplicate(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodPost {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+	given := strings.TrimPrefix(r.Header.Get("Authorization"), "Bearer ")
+	if !s.replicationToken.Matches(given) && (s.replicationToken != nil || !s.authorized(r)) {
+		http.Error(w, "Unauthorized", http.StatusUnauthorized)
+		return
+	}
+	body, err := decodedBody(r, r.Body)
+	if err != nil {
+		http.Error(w, "Unsupported encoding", http.StatusUnsupportedMediaType)
+		return
+	}
+	var batches []ReplicatedBatch
+	if err := json.NewDecoder(body).Decode(&batches); err != nil {
+		http.Error(w, "Invalid JSON", http.StatusBadRequest)
+		return
+	}
+
+	for i := range batches {
+		var resp IngestResponse
+		metrics := s.resolve(nil, &batches[i].Request, "replica", &resp)
+		if batches[i].Path == "backfill" && s.backfill != nil {
+			sort.Slice(metrics, func(a, b int) bool { return metrics[a].Time.Before(metrics[b].Time) })
+			err = s.backfill.BatchInsert(store.PointsFromBuffer(metrics))
+		} else {
+			err = s.log.Append(metrics, func() {
+				for _, m := range metrics {
+					s.buffer.Add(m)
+				}
+			})
+		}
+		if err != nil {
+			// The primary resends the whole request; earlier batches are stored twice
+			log.Printf("Replicated batch from %s not stored: %v", batches[i].Request.NodeName, err)
+			backpressure(w, http.StatusServiceUnavailable, storageRetryAfter, "Storage unavailable")
+			return
+		}
+		resp.Accepted = len(metrics)
+		observeResult("replica", &resp)
+	}
+	w.WriteHeader(http.StatusAccepted)
+}
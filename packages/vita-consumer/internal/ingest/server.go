@@ -2,33 +2,106 @@ package ingest
 
 import (
 	"encoding/json"
+	"errors"
+	"log"
 	"net/http"
 	"regexp"
 	"strings"
 	"time"
 
 	"github.com/nchanged/vitakube/packages/vita-consumer/internal/buffer"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/secret"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/shard"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/tenant"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/trace"
 )
 
 type IDResolver interface {
 	GetResourceID(uid, rType string) (int64, bool)
+	GetNamespace(uid string) (string, bool)
+}
+
+// BatchLog durably records resolved batches before they are acknowledged,
+// running apply once a batch is recorded and before its segment is sealed
+type BatchLog interface {
+	Append(metrics []buffer.Metric, apply func()) error
+}
+
+// Blocker decides whether an ingest-time block rule drops a metric
+type Blocker interface {
+	Blocked(node, metricType, namespace string, resourceID int64) bool
+}
+
+// PointWriter persists metrics directly, bypassing the ring buffer
+type PointWriter interface {
+	BatchInsert(metrics []store.MetricPoint) error
 }
 
 type IngestionServer struct {
 	buffer   *buffer.RingBuffer
 	resolver IDResolver
+	log      BatchLog
+	shards   *shard.Ring
+	stats    *Stats
+	blocker  Blocker
+	sessions *sessionTable
+	token    *secret.Value
+	// Nil unless tenants are configured
+	tenants *tenant.Registry
+	// Accepted metric type families; nil for the agent's own
+	metricTypes []string
+	// What happens to metrics stamped too far ahead or behind
+	future, late timestampWindow
+
+	// Nil unless batches are replicated to a standby
+	replica          *replicator
+	replicationToken *secret.Value
+
+	signingKey *secret.Value
+
+	backfill      PointWriter
+	backfillLimit *tokenBucket
+
+	// Optional cap on live metrics per second
+	ingestLimit *tokenBucket
+
+	events     EventWriter
+	nodeEvents NodeEventWriter
+	heartbeats HeartbeatWriter
+
+	// Nil unless tracing is configured
+	tracer *trace.Tracer
 }
 
-func NewIngestionServer(buf *buffer.RingBuffer, res IDResolver) *IngestionServer {
+func NewIngestionServer(buf *buffer.RingBuffer, res IDResolver, batchLog BatchLog, shards *shard.Ring) *IngestionServer {
 	return &IngestionServer{
 		buffer:   buf,
 		resolver: res,
+		log:      batchLog,
+		shards:   shards,
+		stats:    NewStats(),
+		sessions: newSessionTable(),
+		future:   timestampWindow{defaultFutureTolerance, TimestampReject},
+		late:     timestampWindow{defaultLateTolerance, TimestampReject},
 	}
 }
 
+// Agents hold their batches this long when the WAL can't be written
+const storageRetryAfter = 5 * time.Second
+
+// Agents of a tenant over its storage quota retry this often
+const quotaRetryAfter = time.Minute
+
+// NodeHeader lets agents name their node up front so a sharded consumer can
+// redirect without decoding the body
+const NodeHeader = "X-Vita-Node"
+
 type IngestRequest struct {
-	NodeName string      `json:"node"`
-	Metrics  []RawMetric `json:"metrics"`
+	NodeName string `json:"node"`
+	// Topology labels of the node (zone, region, ...), stored on every metric
+	NodeLabels map[string]string `json:"node_labels,omitempty"`
+	Metrics    []RawMetric       `json:"metrics"`
 }
 
 type RawMetric struct {
@@ -37,9 +110,67 @@ type RawMetric struct {
 	PodUID      string  `json:"pod_uid,omitempty"` // For PVCs (pod using the volume)
 	Volume      string  `json:"volume,omitempty"`  // For PVCs (volume name, may contain pvc UID)
 	ContainerID string  `json:"container_id,omitempty"`
-	Key         string  `json:"key"` // "cpu_ms", "mem_mb", "total_mb", "used_mb", "free_mb"
-	Value       float64 `json:"value"`
-	Timestamp   int64   `json:"ts"` // unix epoch
+	// Block device or interface, for node_disk/node_net
+	Device    string  `json:"device,omitempty"`
+	Key       string  `json:"key"` // "cpu_ms", "mem_mb", "total_mb", "used_mb", "free_mb"
+	Value     float64 `json:"value"`
+	Timestamp int64   `json:"ts"` // unix epoch
+	// Microseconds into the agent's collection cycle the value was read at.
+	// Timestamp is the cycle's, shared by the whole batch, so series line up;
+	// the offset is stored alongside as offset_us.
+	Offset int64 `json:"off,omitempty"`
+}
+
+// Reasons a metric is dropped while the rest of its batch is stored, besides
+// failing validation
+const (
+	rejectBlocked          = "blocked"
+	rejectSeriesLimit      = "series_limit"
+	rejectMissingKey       = "missing_key"
+	rejectMissingTimestamp = "missing_timestamp"
+)
+
+// Rejections listed one by one per response; the rest are only counted
+const maxListedRejections = 100
+
+// IngestResponse is the body of a 202. Dropped metrics are counted by
+// reason, and the first of them listed by position in the batch.
+type IngestResponse struct {
+	Accepted   int            `json:"accepted"`
+	Rejected   int            `json:"rejected"`
+	Reasons    map[string]int `json:"reasons,omitempty"`
+	Rejections []Rejection    `json:"rejections,omitempty"`
+}
+
+type Rejection struct {
+	Index  int    `json:"index"`
+	Reason string `json:"reason"`
+	// The field that failed validation, e.g. ts or pod_id
+	Field string `json:"field,omitempty"`
+}
+
+// reject records a dropped metric; index is -1 when its position is unknown
+func (resp *IngestResponse) reject(index int, reason string) {
+	resp.rejectField(index, reason, "")
+}
+
+// rejectField records a metric dropped because of one of its fields
+func (resp *IngestResponse) rejectField(index int, reason, field string) {
+	resp.Rejected++
+	if resp.Reasons == nil {
+		resp.Reasons = make(map[string]int)
+	}
+	resp.Reasons[reason]++
+	if index >= 0 && len(resp.Rejections) < maxListedRejections {
+		resp.Rejections = append(resp.Rejections, Rejection{Index: index, Reason: reason, Field: field})
+	}
+}
+
+// writeAccepted acknowledges a stored batch
+func writeAccepted(w http.ResponseWriter, resp *IngestResponse) {
+	w.Header().Set("Content-Type", "application/json")
+	w.WriteHeader(http.StatusAccepted)
+	json.NewEncoder(w).Encode(resp)
 }
 
 var podSliceRegex = regexp.MustCompile(`pod([0-9a-fA-F_]+)(?:\.slice)?`)
@@ -50,20 +181,146 @@ func (s *IngestionServer) HandleIngest(w http.ResponseWriter, r *http.Request) {
 		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
 		return
 	}
+	if !s.authorized(r) {
+		http.Error(w, "Unauthorized", http.StatusUnauthorized)
+		return
+	}
+	t := s.tenantOf(r)
+
+	if node := r.Header.Get(NodeHeader); node != "" && s.redirectToOwner(w, r, node) {
+		return
+	}
+
+	span := s.tracer.StartRequest(r, "ingest")
+	defer span.End()
+
+	body, err := s.verifiedBody(r)
+	if err != nil {
+		http.Error(w, "Invalid signature", http.StatusUnauthorized)
+		return
+	}
+	if body, err = decodedBody(r, body); err != nil {
+		http.Error(w, "Unsupported encoding", http.StatusUnsupportedMediaType)
+		return
+	}
 
 	var req IngestRequest
-	if err := json.NewDecoder(r.Body).Decode(&req); err != nil {
+	if strings.HasPrefix(r.Header.Get("Content-Type"), CompactContentType) {
+		if err := s.sessions.decodeCompact(body, &req); err != nil {
+			if errors.Is(err, errUnknownSeries) {
+				// The agent resends its whole dictionary on 409
+				http.Error(w, "Unknown series, resend dictionary", http.StatusConflict)
+				return
+			}
+			http.Error(w, "Invalid JSON", http.StatusBadRequest)
+			return
+		}
+	} else if err := json.NewDecoder(body).Decode(&req); err != nil {
 		http.Error(w, "Invalid JSON", http.StatusBadRequest)
 		return
 	}
 
-	for _, raw := range req.Metrics {
+	if len(req.Metrics) > MaxBatchMetrics {
+		http.Error(w, "Batch too large", http.StatusRequestEntityTooLarge)
+		return
+	}
+
+	// The signature covers the node header; the batch must not claim another node
+	if s.signingKey != nil && req.NodeName != r.Header.Get(NodeHeader) {
+		http.Error(w, "Node mismatch", http.StatusUnauthorized)
+		return
+	}
+
+	if s.redirectToOwner(w, r, req.NodeName) {
+		return
+	}
+	if err := s.validateBatch(&req, "live"); err != nil {
+		writeBatchError(w, err)
+		return
+	}
+	if !s.admitTenant(w, t, req.NodeName) || !admitStorage(w, t) {
+		return
+	}
+
+	if s.ingestLimit != nil {
+		if ok, wait := s.ingestLimit.take(len(req.Metrics)); !ok {
+			backpressure(w, http.StatusTooManyRequests, wait, "Ingest rate limit exceeded")
+			return
+		}
+	}
+
+	span.SetString("node", req.NodeName)
+	span.SetInt("metrics", len(req.Metrics))
+
+	var resp IngestResponse
+	resolveSpan := span.Child("resolve")
+	metrics := s.resolve(t, &req, "live", &resp)
+	resolveSpan.SetInt("rejected", resp.Rejected)
+	resolveSpan.End()
+
+	// Only ack once the batch is on disk; the agent retries otherwise
+	walSpan := span.Child("wal.append")
+	err = s.log.Append(metrics, func() {
+		for _, m := range metrics {
+			s.buffer.Add(m)
+		}
+	})
+	walSpan.SetError(err)
+	walSpan.End()
+	if err != nil {
+		span.SetError(err)
+		log.Printf("WAL append failed: %v", err)
+		backpressure(w, http.StatusServiceUnavailable, storageRetryAfter, "Storage unavailable")
+		return
+	}
+
+	s.replicate("live", &req)
+
+	resp.Accepted = len(metrics)
+	observeResult("live", &resp)
+	writeAccepted(w, &resp)
+}
+
+// resolve maps raw agent metrics to buffered metrics with DB resource IDs,
+// recording in resp the ones that are dropped and why. t is the sending
+// tenant, nil without tenants; path is live, backfill or replica.
+//
+// Timestamps are checked against the tolerance windows except on replicas:
+// req is left as stored, clamped and without rejected timestamps, so the
+// standby stores what the primary did.
+func (s *IngestionServer) resolve(t *tenant.Tenant, req *IngestRequest, path string, resp *IngestResponse) []buffer.Metric {
+	s.stats.observeBatch(req.NodeName, len(req.Metrics))
+
+	labels := encodeLabels(req.NodeLabels)
+	now := time.Now()
+	// Positions of metrics rejected for their timestamps
+	var untimely []int
+
+	metrics := make([]buffer.Metric, 0, len(req.Metrics))
+	for i, raw := range req.Metrics {
+		if reason, field := s.validateMetric(&raw); reason != "" {
+			resp.rejectField(i, reason, field)
+			s.stats.observeInvalid(req.NodeName, reason, field, 1)
+			continue
+		}
+		if path != "replica" {
+			ts, reason := s.checkTimestamp(raw.Timestamp, path, now)
+			if reason != "" {
+				resp.rejectField(i, reason, "ts")
+				s.stats.observeInvalid(req.NodeName, reason, "ts", 1)
+				untimely = append(untimely, i)
+				continue
+			}
+			raw.Timestamp = ts
+			req.Metrics[i].Timestamp = ts
+		}
+
 		var resourceID int64
 		var uid string
 		var rType string = "pod" // default
 
 		// 1. Resolve UID and Type based on metric type
-		if raw.Key == "pvc_usage" || strings.Contains(raw.Key, "_mb") && raw.Volume != "" {
+		if raw.Type == "pvc_usage" || strings.Contains(raw.Key, "_mb") && raw.Volume != "" {
 			// PVC/Volume metrics
 			// First, check if the volume name indicates an actual PVC
 			if matches := pvcVolumeRegex.FindStringSubmatch(raw.Volume); len(matches) > 1 {
@@ -96,14 +353,166 @@ func (s *IngestionServer) HandleIngest(w http.ResponseWriter, r *http.Request) {
 			}
 		}
 
-		m := buffer.Metric{
-			Time:       time.Unix(raw.Timestamp, 0),
-			ResourceID: resourceID,
-			Type:       raw.Key,
-			Value:      raw.Value,
+		series := newSeriesKey(req.NodeName, raw)
+		s.stats.observeSeries(series, uid)
+
+		if s.blocker != nil {
+			namespace, _ := s.resolver.GetNamespace(uid)
+			if s.blocker.Blocked(req.NodeName, raw.Key, namespace, resourceID) {
+				resp.reject(i, rejectBlocked)
+				continue
+			}
 		}
-		s.buffer.Add(m)
+		if t != nil && !t.AdmitSeries(series) {
+			resp.reject(i, rejectSeriesLimit)
+			continue
+		}
+
+		metrics = append(metrics, buffer.Metric{
+			Time:        time.Unix(raw.Timestamp, 0),
+			Node:        req.NodeName,
+			ResourceID:  resourceID,
+			Type:        raw.Type,
+			Key:         raw.Key,
+			Value:       raw.Value,
+			Device:      raw.Device,
+			ContainerID: raw.ContainerID,
+			Volume:      raw.Volume,
+			Labels:      labels,
+			Offset:      raw.Offset,
+		})
 	}
 
-	w.WriteHeader(http.StatusAccepted)
+	if len(untimely) > 0 {
+		kept := make([]RawMetric, 0, len(req.Metrics)-len(untimely))
+		for i, raw := range req.Metrics {
+			if len(untimely) > 0 && untimely[0] == i {
+				untimely = untimely[1:]
+				continue
+			}
+			kept = append(kept, raw)
+		}
+		req.Metrics = kept
+	}
+	return metrics
+}
+
+// encodeLabels renders node labels as a JSON object with sorted keys, so
+// every metric of a node shares one identical string
+func encodeLabels(labels map[string]string) string {
+	if len(labels) == 0 {
+		return ""
+	}
+	b, err := json.Marshal(labels)
+	if err != nil {
+		return ""
+	}
+	return string(b)
+}
+
+// SetIngestLimit caps live ingest at ratePerSec metrics per second, with
+// bursts of up to ten seconds' worth. Agents over it are told to back off
+// and keep their data until then.
+func (s *IngestionServer) SetIngestLimit(ratePerSec float64) {
+	s.ingestLimit = newTokenBucket(ratePerSec, ratePerSec*10)
+}
+
+// SetTracer records a span per ingest request, continuing agent traces
+func (s *IngestionServer) SetTracer(t *trace.Tracer) {
+	s.tracer = t
+}
+
+// SetBlocker installs ingest-time block rules
+func (s *IngestionServer) SetBlocker(b Blocker) {
+	s.blocker = b
+}
+
+// SetToken requires agents to send "Authorization: Bearer <token>"
+func (s *IngestionServer) SetToken(token *secret.Value) {
+	s.token = token
+}
+
+// SetTenants lets each tenant's token in, enforcing its node claims,
+// series limit and storage quota
+func (s *IngestionServer) SetTenants(tenants *tenant.Registry) {
+	s.tenants = tenants
+}
+
+func (s *IngestionServer) authorized(r *http.Request) bool {
+	if s.token == nil && s.tenants == nil {
+		return true
+	}
+	given := strings.TrimPrefix(r.Header.Get("Authorization"), "Bearer ")
+	return s.token.Matches(given) || s.tenantOf(r) != nil
+}
+
+// tenantOf returns the tenant whose token the request carries, or nil
+func (s *IngestionServer) tenantOf(r *http.Request) *tenant.Tenant {
+	if s.tenants == nil {
+		return nil
+	}
+	return s.tenants.Lookup(strings.TrimPrefix(r.Header.Get("Authorization"), "Bearer "))
+}
+
+// admitTenant rejects requests for a node t may not send for; t is nil for
+// agents sending without a tenant token
+func (s *IngestionServer) admitTenant(w http.ResponseWriter, t *tenant.Tenant, node string) bool {
+	if t == nil {
+		return true
+	}
+	if !s.tenants.Claim(t, node) {
+		http.Error(w, "Node belongs to another tenant", http.StatusForbidden)
+		return false
+	}
+	return true
+}
+
+// admitStorage holds off a tenant's metrics while it is over its storage quota
+func admitStorage(w http.ResponseWriter, t *tenant.Tenant) bool {
+	if t != nil && t.OverStorage() {
+		backpressure(w, http.StatusTooManyRequests, quotaRetryAfter, "Storage quota exceeded")
+		return false
+	}
+	return true
+}
+
+// redirectToOwner sends a 307 to the replica owning node, if that isn't us.
+// 307 keeps the method and body, and agents stick to the new location.
+func (s *IngestionServer) redirectToOwner(w http.ResponseWriter, r *http.Request, node string) bool {
+	if s.shards.IsLocal(node) {
+		return false
+	}
+	owner := s.shards.Owner(node)
+	w.Header().Set("Location", owner+r.URL.Path)
+	w.WriteHeader(http.StatusTemporaryRedirect)
+	return true
+}
+
+// ShardsResponse describes the ingest sharding layout
+type ShardsResponse struct {
+	Self  string   `json:"self"`
+	Peers []string `json:"peers"`
+	Owner string   `json:"owner,omitempty"`
+}
+
+// HandleShards exposes the ring; ?node=<name> also resolves that node's owner
+func (s *IngestionServer) HandleShards(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	resp := ShardsResponse{
+		Self:  s.shards.Self(),
+		Peers: s.shards.Peers(),
+	}
+	if resp.Peers == nil {
+		resp.Peers = []string{}
+	}
+	if node := r.URL.Query().Get("node"); node != "" {
+		resp.Owner = s.shards.Owner(node)
+	}
+
+	w.Header().Set("Content-Type", "application/json")
+	json.NewEncoder(w).Encode(resp)
 }
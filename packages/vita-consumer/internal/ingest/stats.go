@@ -0,0 +1,288 @@
+package ingest
+
+import (
+	"encoding/json"
+	"net/http"
+	"sort"
+	"strconv"
+	"sync"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/selfmetrics"
+)
+
+// Series not seen for this long no longer count towards cardinality
+const seriesTTL = 10 * time.Minute
+
+// Ingest rates are averaged over this many one-second buckets
+const rateWindow = 60
+
+// Totals for /metrics, by path: "live" or "backfill"
+var (
+	ingestBatches  = selfmetrics.NewCounter("vitakube_ingest_batches_total", "Batches stored, by path.", "path")
+	ingestMetrics  = selfmetrics.NewCounter("vitakube_ingest_metrics_total", "Metrics stored, by path.", "path")
+	ingestRejected = selfmetrics.NewCounter("vitakube_ingest_rejected_total", "Metrics dropped from stored batches, by path and reason.", "path", "reason")
+)
+
+// observeResult counts a stored batch and what was dropped from it
+func observeResult(path string, resp *IngestResponse) {
+	ingestBatches.Inc(path)
+	ingestMetrics.Add(float64(resp.Accepted), path)
+	for reason, n := range resp.Reasons {
+		ingestRejected.Add(float64(n), path, reason)
+	}
+}
+
+type seriesKey struct {
+	node        string
+	family      string
+	metricType  string
+	podID       string
+	podUID      string
+	volume      string
+	containerID string
+	device      string
+}
+
+type seriesInfo struct {
+	uid      string
+	lastSeen time.Time
+}
+
+// rateCounter counts events in one-second buckets over the last minute
+type rateCounter struct {
+	buckets [rateWindow]int64
+	stamps  [rateWindow]int64 // unix second each bucket belongs to
+}
+
+func (c *rateCounter) add(now int64, n int64) {
+	i := now % rateWindow
+	if c.stamps[i] != now {
+		c.stamps[i] = now
+		c.buckets[i] = 0
+	}
+	c.buckets[i] += n
+}
+
+func (c *rateCounter) perSecond(now int64) float64 {
+	var total int64
+	for i := range c.buckets {
+		if now-c.stamps[i] < rateWindow {
+			total += c.buckets[i]
+		}
+	}
+	return float64(total) / rateWindow
+}
+
+// Stats tracks active series and ingest rates for the cardinality endpoint
+type Stats struct {
+	mu        sync.Mutex
+	series    map[seriesKey]*seriesInfo
+	metrics   rateCounter
+	batches   rateCounter
+	nodeRates map[string]*rateCounter
+	invalid   map[string]*NodeValidation
+	lastSweep time.Time
+}
+
+func NewStats() *Stats {
+	return &Stats{
+		series:    make(map[seriesKey]*seriesInfo),
+		nodeRates: make(map[string]*rateCounter),
+		invalid:   make(map[string]*NodeValidation),
+		lastSweep: time.Now(),
+	}
+}
+
+func (st *Stats) observeBatch(node string, n int) {
+	now := time.Now()
+
+	st.mu.Lock()
+	defer st.mu.Unlock()
+
+	sec := now.Unix()
+	st.metrics.add(sec, int64(n))
+	st.batches.add(sec, 1)
+
+	rc, ok := st.nodeRates[node]
+	if !ok {
+		rc = &rateCounter{}
+		st.nodeRates[node] = rc
+	}
+	rc.add(sec, int64(n))
+
+	if now.Sub(st.lastSweep) > time.Minute {
+		st.sweep(now)
+	}
+}
+
+func newSeriesKey(node string, raw RawMetric) seriesKey {
+	return seriesKey{
+		node:        node,
+		family:      raw.Type,
+		metricType:  raw.Key,
+		podID:       raw.PodID,
+		podUID:      raw.PodUID,
+		volume:      raw.Volume,
+		containerID: raw.ContainerID,
+		device:      raw.Device,
+	}
+}
+
+func (st *Stats) observeSeries(key seriesKey, uid string) {
+	now := time.Now()
+
+	st.mu.Lock()
+	defer st.mu.Unlock()
+
+	if info, ok := st.series[key]; ok {
+		info.lastSeen = now
+		info.uid = uid
+		return
+	}
+	st.series[key] = &seriesInfo{uid: uid, lastSeen: now}
+}
+
+// sweep drops expired series, idle nodes and old validation failures;
+// callers hold st.mu
+func (st *Stats) sweep(now time.Time) {
+	for k, info := range st.series {
+		if now.Sub(info.lastSeen) > seriesTTL {
+			delete(st.series, k)
+		}
+	}
+	sec := now.Unix()
+	for node, rc := range st.nodeRates {
+		if rc.perSecond(sec) == 0 {
+			delete(st.nodeRates, node)
+		}
+	}
+	for node, v := range st.invalid {
+		if !v.recent(now) {
+			delete(st.invalid, node)
+		}
+	}
+	st.lastSweep = now
+}
+
+// ActiveSeries is the number of series seen within the TTL
+func (s *IngestionServer) ActiveSeries() int {
+	s.stats.mu.Lock()
+	defer s.stats.mu.Unlock()
+	return len(s.stats.series)
+}
+
+// CardinalityEntry is one row of a top-N breakdown
+type CardinalityEntry struct {
+	Name   string `json:"name"`
+	Series int    `json:"series"`
+}
+
+type NodeRate struct {
+	Name          string  `json:"name"`
+	MetricsPerSec float64 `json:"metrics_per_sec"`
+}
+
+type IngestRates struct {
+	MetricsPerSec float64    `json:"metrics_per_sec"`
+	BatchesPerSec float64    `json:"batches_per_sec"`
+	TopNodes      []NodeRate `json:"top_nodes"`
+}
+
+type CardinalityResponse struct {
+	SeriesTotal int                `json:"series_total"`
+	TTLSeconds  int                `json:"ttl_seconds"`
+	MetricTypes []CardinalityEntry `json:"metric_types"`
+	Nodes       []CardinalityEntry `json:"nodes"`
+	Namespaces  []CardinalityEntry `json:"namespaces"`
+	Ingest      IngestRates        `json:"ingest"`
+}
+
+// snapshot aggregates the live series; namespaces are looked up via namespaceOf
+func (st *Stats) snapshot(limit int, namespaceOf func(uid string) (string, bool)) CardinalityResponse {
+	now := time.Now()
+
+	st.mu.Lock()
+	st.sweep(now)
+
+	byType := map[string]int{}
+	byNode := map[string]int{}
+	byNamespace := map[string]int{}
+	for k, info := range st.series {
+		byType[k.metricType]++
+		byNode[k.node]++
+
+		ns := "<unresolved>"
+		if info.uid != "" {
+			if name, ok := namespaceOf(info.uid); ok {
+				ns = name
+			}
+		} else {
+			ns = "<node>"
+		}
+		byNamespace[ns]++
+	}
+
+	sec := now.Unix()
+	rates := IngestRates{
+		MetricsPerSec: st.metrics.perSecond(sec),
+		BatchesPerSec: st.batches.perSecond(sec),
+		TopNodes:      []NodeRate{},
+	}
+	for node, rc := range st.nodeRates {
+		rates.TopNodes = append(rates.TopNodes, NodeRate{Name: node, MetricsPerSec: rc.perSecond(sec)})
+	}
+	total := len(st.series)
+	st.mu.Unlock()
+
+	sort.Slice(rates.TopNodes, func(i, j int) bool {
+		return rates.TopNodes[i].MetricsPerSec > rates.TopNodes[j].MetricsPerSec
+	})
+	if len(rates.TopNodes) > limit {
+		rates.TopNodes = rates.TopNodes[:limit]
+	}
+
+	return CardinalityResponse{
+		SeriesTotal: total,
+		TTLSeconds:  int(seriesTTL.Seconds()),
+		MetricTypes: topN(byType, limit),
+		Nodes:       topN(byNode, limit),
+		Namespaces:  topN(byNamespace, limit),
+		Ingest:      rates,
+	}
+}
+
+func topN(counts map[string]int, limit int) []CardinalityEntry {
+	entries := make([]CardinalityEntry, 0, len(counts))
+	for name, n := range counts {
+		entries = append(entries, CardinalityEntry{Name: name, Series: n})
+	}
+	sort.Slice(entries, func(i, j int) bool {
+		if entries[i].Series != entries[j].Series {
+			return entries[i].Series > entries[j].Series
+		}
+		return entries[i].Name < entries[j].Name
+	})
+	if len(entries) > limit {
+		entries = entries[:limit]
+	}
+	return entries
+}
+
+// HandleCardinality serves series counts per metric type, node and namespace
+// plus ingest rates; ?limit=N bounds each breakdown (default 20).
+func (s *IngestionServer) HandleCardinality(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	limit := 20
+	if v, err := strconv.Atoi(r.URL.Query().Get("limit")); err == nil && v > 0 {
+		limit = v
+	}
+
+	resp := s.stats.snapshot(limit, s.resolver.GetNamespace)
+	w.Header().Set("Content-Type", "application/json")
+	json.NewEncoder(w).Encode(resp)
+}
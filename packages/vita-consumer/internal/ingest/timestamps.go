@@ -0,0 +1,91 @@
+package ingest
+
+import (
+	"fmt"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/selfmetrics"
+)
+
+// TimestampPolicy says what happens to a sample stamped outside a tolerance
+// window around the consumer's clock
+type TimestampPolicy string
+
+const (
+	// Stored as stamped
+	TimestampAccept TimestampPolicy = "accept"
+	// Restamped at the window's edge, or now for samples from the future
+	TimestampClamp TimestampPolicy = "clamp"
+	// Dropped and reported in the response
+	TimestampReject TimestampPolicy = "reject"
+)
+
+// ParseTimestampPolicy reads accept, clamp or reject
+func ParseTimestampPolicy(s string) (TimestampPolicy, error) {
+	switch p := TimestampPolicy(s); p {
+	case TimestampAccept, TimestampClamp, TimestampReject:
+		return p, nil
+	}
+	return "", fmt.Errorf("timestamp policy must be accept, clamp or reject, not %q", s)
+}
+
+// Default windows: samples further ahead are clock errors; live samples
+// older are history, which belongs on the backfill path
+const (
+	defaultFutureTolerance = 5 * time.Minute
+	defaultLateTolerance   = 24 * time.Hour
+)
+
+// Reasons a metric is rejected for its timestamp
+const (
+	rejectFutureTimestamp = "future_timestamp"
+	rejectLateTimestamp   = "late_timestamp"
+)
+
+var ingestOutOfWindow = selfmetrics.NewCounter("vitakube_ingest_out_of_window_total", "Metrics stamped outside the ingest tolerance windows, by path, direction (future or late) and policy applied.", "path", "direction", "policy")
+
+type timestampWindow struct {
+	tolerance time.Duration
+	policy    TimestampPolicy
+}
+
+// SetFuturePolicy decides what happens to metrics stamped more than
+// tolerance ahead of the consumer's clock, on the live and backfill paths
+func (s *IngestionServer) SetFuturePolicy(tolerance time.Duration, policy TimestampPolicy) {
+	s.future = timestampWindow{tolerance, policy}
+}
+
+// SetLatePolicy decides what happens to live metrics stamped more than
+// tolerance behind the consumer's clock, e.g. a long spool replay. Backfill
+// takes history of any age.
+func (s *IngestionServer) SetLatePolicy(tolerance time.Duration, policy TimestampPolicy) {
+	s.late = timestampWindow{tolerance, policy}
+}
+
+// checkTimestamp applies the tolerance windows to a metric's timestamp on
+// path, returning the timestamp to store it at, or the reason it is rejected
+func (s *IngestionServer) checkTimestamp(ts int64, path string, now time.Time) (int64, string) {
+	var window timestampWindow
+	var direction, reason string
+	var edge time.Time
+	switch stamped := time.Unix(ts, 0); {
+	case stamped.After(now.Add(s.future.tolerance)):
+		window, direction, reason = s.future, "future", rejectFutureTimestamp
+		// Nothing is read after it arrives
+		edge = now
+	case path == "live" && stamped.Before(now.Add(-s.late.tolerance)):
+		window, direction, reason = s.late, "late", rejectLateTimestamp
+		edge = now.Add(-s.late.tolerance)
+	default:
+		return ts, ""
+	}
+
+	ingestOutOfWindow.Inc(path, direction, string(window.policy))
+	switch window.policy {
+	case TimestampAccept:
+		return ts, ""
+	case TimestampClamp:
+		return edge.Unix(), ""
+	}
+	return 0, reason
+}
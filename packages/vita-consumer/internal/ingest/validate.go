@@ -0,0 +1,232 @@
+package ingest
+
+import (
+	"encoding/json"
+	"fmt"
+	"log"
+	"net/http"
+	"regexp"
+	"sort"
+	"strings"
+	"time"
+	"unicode/utf8"
+)
+
+// Metric type families agents send: a type is one of these or starts with
+// one and an underscore (node_cpu, pvc_usage, plugin_redis, ...)
+var metricTypeFamilies = []string{
+	"agent", "cgroup", "cluster", "container", "docker", "image", "ipvs",
+	"mount", "node", "plugin", "pod", "probe", "pvc", "workload",
+}
+
+var metricKeyRegex = regexp.MustCompile(`^[A-Za-z0-9_.:-]+$`)
+var labelNameRegex = regexp.MustCompile(`^[A-Za-z_][A-Za-z0-9_.-]*$`)
+
+// Limits on what a batch may carry
+const (
+	maxNodeNameLen   = 253 // a Kubernetes node name
+	maxNodeLabels    = 32
+	maxLabelNameLen  = 128
+	maxLabelValueLen = 1024
+	maxKeyLen        = 128
+	// pod_id, volume, container_id, ...
+	maxFieldLen = 512
+)
+
+// Nodes whose agents sent nothing invalid for this long are forgotten
+const validationTTL = time.Hour
+
+// Reasons a metric fails validation
+const (
+	rejectUnknownType  = "unknown_type"
+	rejectInvalidKey   = "invalid_key"
+	rejectInvalidUTF8  = "invalid_utf8"
+	rejectFieldTooLong = "field_too_long"
+)
+
+// Reasons a whole batch fails validation
+const (
+	invalidNodeName   = "invalid_node_name"
+	invalidLabel      = "invalid_label"
+	tooManyNodeLabels = "too_many_labels"
+)
+
+// BatchError is the body of a 400 for a batch rejected as a whole
+type BatchError struct {
+	Reason string `json:"reason"`
+	Field  string `json:"field"`
+	Detail string `json:"detail"`
+}
+
+func writeBatchError(w http.ResponseWriter, e *BatchError) {
+	w.Header().Set("Content-Type", "application/json")
+	w.WriteHeader(http.StatusBadRequest)
+	json.NewEncoder(w).Encode(e)
+}
+
+// SetExtraMetricTypes accepts metric types of these families besides the
+// agent's own, e.g. for custom senders
+func (s *IngestionServer) SetExtraMetricTypes(families []string) {
+	s.metricTypes = append(append([]string{}, metricTypeFamilies...), families...)
+}
+
+func (s *IngestionServer) knownType(t string) bool {
+	families := s.metricTypes
+	if families == nil {
+		families = metricTypeFamilies
+	}
+	for _, f := range families {
+		if t == f || (strings.HasPrefix(t, f) && len(t) > len(f) && t[len(f)] == '_') {
+			return true
+		}
+	}
+	return false
+}
+
+// validText rejects invalid UTF-8; the JSON decoder turns it into U+FFFD,
+// which agents never send
+func validText(s string) bool {
+	return utf8.ValidString(s) && !strings.ContainsRune(s, utf8.RuneError)
+}
+
+// validateBatch checks what applies to every metric of a batch: the node
+// name and labels. The error names the offending field.
+func (s *IngestionServer) validateBatch(req *IngestRequest, path string) *BatchError {
+	var err *BatchError
+	switch {
+	case req.NodeName == "" || len(req.NodeName) > maxNodeNameLen || !validText(req.NodeName):
+		err = &BatchError{invalidNodeName, "node", fmt.Sprintf("node must be 1 to %d bytes of UTF-8", maxNodeNameLen)}
+	case len(req.NodeLabels) > maxNodeLabels:
+		err = &BatchError{tooManyNodeLabels, "node_labels", fmt.Sprintf("%d labels, at most %d allowed", len(req.NodeLabels), maxNodeLabels)}
+	default:
+		names := make([]string, 0, len(req.NodeLabels))
+		for name := range req.NodeLabels {
+			names = append(names, name)
+		}
+		sort.Strings(names)
+		for _, name := range names {
+			value := req.NodeLabels[name]
+			if len(name) > maxLabelNameLen || !labelNameRegex.MatchString(name) {
+				err = &BatchError{invalidLabel, "node_labels", fmt.Sprintf("label name %q must match %s, at most %d bytes", name, labelNameRegex, maxLabelNameLen)}
+			} else if len(value) > maxLabelValueLen || !validText(value) {
+				err = &BatchError{invalidLabel, "node_labels." + name, fmt.Sprintf("value must be at most %d bytes of UTF-8", maxLabelValueLen)}
+			}
+			if err != nil {
+				break
+			}
+		}
+	}
+	if err != nil {
+		ingestRejected.Add(float64(len(req.Metrics)), path, err.Reason)
+		s.stats.observeInvalid(req.NodeName, err.Reason, err.Field, len(req.Metrics))
+	}
+	return err
+}
+
+// validateMetric returns why a metric is invalid and the field at fault,
+// or "". Timestamps out of the tolerance windows are left to checkTimestamp.
+func (s *IngestionServer) validateMetric(raw *RawMetric) (reason, field string) {
+	switch {
+	case raw.Key == "":
+		return rejectMissingKey, "key"
+	case len(raw.Key) > maxKeyLen || !metricKeyRegex.MatchString(raw.Key):
+		return rejectInvalidKey, "key"
+	case !s.knownType(raw.Type):
+		return rejectUnknownType, "type"
+	case raw.Timestamp <= 0:
+		return rejectMissingTimestamp, "ts"
+	}
+	for _, f := range [...]struct{ name, value string }{
+		{"pod_id", raw.PodID}, {"pod_uid", raw.PodUID}, {"volume", raw.Volume},
+		{"container_id", raw.ContainerID}, {"device", raw.Device},
+	} {
+		if len(f.value) > maxFieldLen {
+			return rejectFieldTooLong, f.name
+		}
+		if !validText(f.value) {
+			return rejectInvalidUTF8, f.name
+		}
+	}
+	return "", ""
+}
+
+// NodeValidation sums up the invalid metrics a node's agent sent lately
+type NodeValidation struct {
+	Node    string         `json:"node"`
+	Invalid int            `json:"invalid"`
+	Reasons map[string]int `json:"reasons"`
+	// The latest failure, e.g. unknown_type on field type
+	LastReason string `json:"last_reason"`
+	LastField  string `json:"last_field"`
+	LastSeen   int64  `json:"last_seen"` // unix seconds
+}
+
+// observeInvalid records n metrics of node failing for reason
+func (st *Stats) observeInvalid(node, reason, field string, n int) {
+	st.mu.Lock()
+	defer st.mu.Unlock()
+	v, ok := st.invalid[node]
+	if !ok {
+		v = &NodeValidation{Node: node, Reasons: map[string]int{}}
+		st.invalid[node] = v
+		log.Printf("Node %s sent invalid metrics (%s in %s); see /api/v1/status/validation", node, reason, field)
+	}
+	v.Invalid += n
+	v.Reasons[reason] += n
+	v.LastReason, v.LastField, v.LastSeen = reason, field, time.Now().Unix()
+}
+
+// recent reports whether a node's latest failure is within validationTTL;
+// older ones linger until the next sweep
+func (v *NodeValidation) recent(now time.Time) bool {
+	return now.Unix()-v.LastSeen <= int64(validationTTL.Seconds())
+}
+
+// InvalidNodes is the number of nodes that sent invalid metrics lately
+func (s *IngestionServer) InvalidNodes() int {
+	now := time.Now()
+	s.stats.mu.Lock()
+	defer s.stats.mu.Unlock()
+	n := 0
+	for _, v := range s.stats.invalid {
+		if v.recent(now) {
+			n++
+		}
+	}
+	return n
+}
+
+// HandleValidation lists the nodes whose agents sent invalid metrics within
+// the last hour, most recent first, so an outdated or misbehaving agent
+// stands out
+func (s *IngestionServer) HandleValidation(w http.ResponseWriter, r *http.Request) {
+	if r.Method != http.MethodGet {
+		http.Error(w, "Method not allowed", http.StatusMethodNotAllowed)
+		return
+	}
+
+	now := time.Now()
+	s.stats.mu.Lock()
+	nodes := make([]NodeValidation, 0, len(s.stats.invalid))
+	for _, v := range s.stats.invalid {
+		if !v.recent(now) {
+			continue
+		}
+		c := *v
+		c.Reasons = make(map[string]int, len(v.Reasons))
+		for reason, n := range v.Reasons {
+			c.Reasons[reason] = n
+		}
+		nodes = append(nodes, c)
+	}
+	s.stats.mu.Unlock()
+
+	sort.Slice(nodes, func(i, j int) bool {
+		if nodes[i].LastSeen != nodes[j].LastSeen {
+			return nodes[i].LastSeen > nodes[j].LastSeen
+		}
+		return nodes[i].Node < nodes[j].Node
+	})
+	w.Header().Set("Content-Type", "application/json")
+	json.NewEncoder(w).Encode(nodes)
+}
@@ -0,0 +1,321 @@
+package promql
+
+import (
+	"fmt"
+	"math"
+	"sort"
+	"strings"
+	"time"
+)
+
+// Lookback is how far back an instant selector finds a series' latest sample
+const Lookback = 5 * time.Minute
+
+// MaxSteps bounds the points per series of a range query, as in Prometheus
+const MaxSteps = 11000
+
+// MaxSamples bounds the samples a query loads from storage
+const MaxSamples = 5000000
+
+// A range query loads the latest sample of every step/samplesPerStep rather
+// than every raw one; finer data wouldn't change the points it returns much
+const samplesPerStep = 4
+
+// Labels identify a series; __name__ holds the metric name
+type Labels map[string]string
+
+// key is the labels rendered in a fixed order, equal for equal labels
+func (l Labels) key() string {
+	names := make([]string, 0, len(l))
+	for name := range l {
+		names = append(names, name)
+	}
+	sort.Strings(names)
+	var b strings.Builder
+	for _, name := range names {
+		b.WriteString(name)
+		b.WriteByte('=')
+		b.WriteString(l[name])
+		b.WriteByte(0xff)
+	}
+	return b.String()
+}
+
+func (l Labels) without(names ...string) Labels {
+	out := make(Labels, len(l))
+	for name, v := range l {
+		out[name] = v
+	}
+	for _, name := range names {
+		delete(out, name)
+	}
+	return out
+}
+
+// Sample is a value at a time in unix milliseconds
+type Sample struct {
+	T int64
+	V float64
+}
+
+// Series is a labelled run of samples, oldest first
+type Series struct {
+	Labels  Labels
+	Samples []Sample
+}
+
+// Source loads raw series from storage
+type Source interface {
+	// Select returns the series of metric name whose labels match, with their
+	// samples in [start, end]; with a resolution over a second, only the
+	// latest sample of each resolution-long bucket
+	Select(name string, matchers []Matcher, start, end time.Time, resolution time.Duration) ([]Series, error)
+}
+
+// Result is one series of a query's result, with a point per step
+type Result struct {
+	Labels Labels
+	Points []Sample
+}
+
+// Error is a query that's valid but too expensive to run
+type Error struct {
+	msg string
+}
+
+func (e *Error) Error() string { return e.msg }
+
+type element struct {
+	labels Labels
+	v      float64
+}
+
+type evaluator struct {
+	data map[*Selector][]Series
+	// Labels of each selected series without __name__, as rates return them
+	unnamed map[*Selector][]Labels
+}
+
+// Eval evaluates e at every step from start through end. An instant query
+// evaluates at end only, with start equal to it.
+func Eval(src Source, e Expr, start, end time.Time, step time.Duration) ([]Result, error) {
+	steps := 1
+	resolution := time.Duration(0)
+	if start.Before(end) {
+		if step <= 0 {
+			return nil, fmt.Errorf("step must be positive")
+		}
+		steps = int(end.Sub(start)/step) + 1
+		if steps > MaxSteps {
+			return nil, fmt.Errorf("exceeded maximum resolution of %d points per series; raise the step", MaxSteps)
+		}
+		resolution = step / samplesPerStep
+	}
+
+	ev := &evaluator{data: map[*Selector][]Series{}, unnamed: map[*Selector][]Labels{}}
+	if err := ev.load(src, e, start, end, resolution, new(int)); err != nil {
+		return nil, err
+	}
+
+	byKey := map[string]*Result{}
+	for i := 0; i < steps; i++ {
+		t := start.Add(time.Duration(i) * step).UnixMilli()
+		for _, el := range ev.eval(e, t) {
+			if math.IsNaN(el.v) {
+				continue
+			}
+			key := el.labels.key()
+			r, ok := byKey[key]
+			if !ok {
+				r = &Result{Labels: el.labels}
+				byKey[key] = r
+			}
+			r.Points = append(r.Points, Sample{T: t, V: el.v})
+		}
+	}
+
+	keys := make([]string, 0, len(byKey))
+	for key := range byKey {
+		keys = append(keys, key)
+	}
+	sort.Strings(keys)
+	results := make([]Result, len(keys))
+	for i, key := range keys {
+		results[i] = *byKey[key]
+	}
+	return results, nil
+}
+
+// load fetches the series of every selector in e, covering what the steps
+// from start to end look back on
+func (ev *evaluator) load(src Source, e Expr, start, end time.Time, resolution time.Duration, loaded *int) error {
+	var sel *Selector
+	lookback := Lookback
+	switch e := e.(type) {
+	case *Aggregate:
+		return ev.load(src, e.Arg, start, end, resolution, loaded)
+	case *Call:
+		sel, lookback = e.Arg, e.Arg.Range
+		// Rates need a few samples inside each range
+		resolution = min(resolution, e.Arg.Range/samplesPerStep)
+	case *Selector:
+		sel = e
+	default:
+		return nil
+	}
+
+	series, err := src.Select(sel.Name, sel.Matchers, start.Add(-lookback), end, resolution)
+	if err != nil {
+		return err
+	}
+	for _, s := range series {
+		*loaded += len(s.Samples)
+	}
+	if *loaded > MaxSamples {
+		return &Error{fmt.Sprintf("query would load more than %d samples; narrow the selectors or the range", MaxSamples)}
+	}
+	ev.data[sel] = series
+	unnamed := make([]Labels, len(series))
+	for i, s := range series {
+		unnamed[i] = s.Labels.without("__name__")
+	}
+	ev.unnamed[sel] = unnamed
+	return nil
+}
+
+// window returns the samples with from < T <= to
+func window(samples []Sample, from, to int64) []Sample {
+	lo := sort.Search(len(samples), func(i int) bool { return samples[i].T > from })
+	hi := sort.Search(len(samples), func(i int) bool { return samples[i].T > to })
+	return samples[lo:hi]
+}
+
+func (ev *evaluator) eval(e Expr, t int64) []element {
+	switch e := e.(type) {
+	case *Number:
+		return []element{{labels: Labels{}, v: e.Value}}
+	case *Selector:
+		var vec []element
+		for _, s := range ev.data[e] {
+			if w := window(s.Samples, t-Lookback.Milliseconds(), t); len(w) > 0 {
+				vec = append(vec, element{labels: s.Labels, v: w[len(w)-1].V})
+			}
+		}
+		return vec
+	case *Call:
+		var vec []element
+		for i, s := range ev.data[e.Arg] {
+			if v, ok := rate(window(s.Samples, t-e.Arg.Range.Milliseconds(), t)); ok {
+				if e.Func == "increase" {
+					v *= e.Arg.Range.Seconds()
+				}
+				vec = append(vec, element{labels: ev.unnamed[e.Arg][i], v: v})
+			}
+		}
+		return vec
+	case *Aggregate:
+		return aggregate(e, ev.eval(e.Arg, t))
+	}
+	return nil
+}
+
+// rate is the per-second increase of a counter across the samples of a
+// range, a drop counting as a restart from zero. Taken between the first
+// and last sample, it holds for the whole range: increase extrapolates it.
+func rate(samples []Sample) (float64, bool) {
+	if len(samples) < 2 {
+		return 0, false
+	}
+	var increase float64
+	for i := 1; i < len(samples); i++ {
+		if d := samples[i].V - samples[i-1].V; d >= 0 {
+			increase += d
+		} else {
+			increase += samples[i].V
+		}
+	}
+	elapsed := float64(samples[len(samples)-1].T-samples[0].T) / 1000
+	if elapsed <= 0 {
+		return 0, false
+	}
+	return increase / elapsed, true
+}
+
+// groupLabels are the labels an element is aggregated under
+func groupLabels(a *Aggregate, l Labels) Labels {
+	if a.Without {
+		out := l.without(a.Grouping...)
+		delete(out, "__name__")
+		return out
+	}
+	out := Labels{}
+	for _, name := range a.Grouping {
+		if v, ok := l[name]; ok {
+			out[name] = v
+		}
+	}
+	return out
+}
+
+func aggregate(a *Aggregate, vec []element) []element {
+	type group struct {
+		labels   Labels
+		elements []element
+	}
+	groups := map[string]*group{}
+	var order []string
+	for _, el := range vec {
+		labels := groupLabels(a, el.labels)
+		key := labels.key()
+		g, ok := groups[key]
+		if !ok {
+			g = &group{labels: labels}
+			groups[key] = g
+			order = append(order, key)
+		}
+		g.elements = append(g.elements, el)
+	}
+
+	var out []element
+	for _, key := range order {
+		g := groups[key]
+		switch a.Op {
+		case "topk", "bottomk":
+			// Elements keep their own labels, as in Prometheus
+			sort.SliceStable(g.elements, func(i, j int) bool {
+				if a.Op == "topk" {
+					return g.elements[i].v > g.elements[j].v
+				}
+				return g.elements[i].v < g.elements[j].v
+			})
+			out = append(out, g.elements[:min(int(a.Param), len(g.elements))]...)
+		default:
+			out = append(out, element{labels: g.labels, v: reduce(a.Op, g.elements)})
+		}
+	}
+	return out
+}
+
+func reduce(op string, elements []element) float64 {
+	v := elements[0].v
+	switch op {
+	case "count":
+		return float64(len(elements))
+	case "max":
+		for _, el := range elements[1:] {
+			v = math.Max(v, el.v)
+		}
+	case "min":
+		for _, el := range elements[1:] {
+			v = math.Min(v, el.v)
+		}
+	default: // sum, avg
+		for _, el := range elements[1:] {
+			v += el.v
+		}
+		if op == "avg" {
+			v /= float64(len(elements))
+		}
+	}
+	return v
+}
@@ -0,0 +1,438 @@
+// Package promql evaluates a subset of PromQL against consumer storage, so
+// Prometheus dashboards and alert expressions carry over: instant and range
+// selectors with label matchers, rate and increase, the sum, avg, max, min
+// and count aggregations with by or without, and topk and bottomk. Metric
+// names are the agents' metric keys (cpu_ms, mem_mb, ...); each series is
+// labelled with its node, resource_id, pod and namespace and the node's
+// topology labels.
+package promql
+
+import (
+	"fmt"
+	"regexp"
+	"strconv"
+	"strings"
+	"time"
+	"unicode"
+)
+
+// Expr is a parsed expression
+type Expr interface {
+	expr()
+}
+
+// MatchOp compares a label against a value
+type MatchOp string
+
+const (
+	MatchEqual     MatchOp = "="
+	MatchNotEqual  MatchOp = "!="
+	MatchRegexp    MatchOp = "=~"
+	MatchNotRegexp MatchOp = "!~"
+)
+
+// Matcher selects series by one label; a missing label reads as ""
+type Matcher struct {
+	Name  string
+	Op    MatchOp
+	Value string
+	re    *regexp.Regexp
+}
+
+// Matches applies the matcher to a label value
+func (m Matcher) Matches(v string) bool {
+	switch m.Op {
+	case MatchEqual:
+		return v == m.Value
+	case MatchNotEqual:
+		return v != m.Value
+	case MatchRegexp:
+		return m.re.MatchString(v)
+	default:
+		return !m.re.MatchString(v)
+	}
+}
+
+// Selector is metric{matchers}, with Range set for metric{...}[5m]
+type Selector struct {
+	Name     string
+	Matchers []Matcher
+	Range    time.Duration
+}
+
+// Call is rate(...) or increase(...) over a range selector
+type Call struct {
+	Func string
+	Arg  *Selector
+}
+
+// Aggregate is sum, avg, max, min, count, topk or bottomk
+type Aggregate struct {
+	Op       string
+	Param    float64 // k of topk and bottomk
+	Grouping []string
+	Without  bool
+	Arg      Expr
+}
+
+// Number is a scalar literal
+type Number struct {
+	Value float64
+}
+
+func (*Selector) expr()  {}
+func (*Call) expr()      {}
+func (*Aggregate) expr() {}
+func (*Number) expr()    {}
+
+var functions = map[string]bool{"rate": true, "increase": true}
+
+var aggregations = map[string]bool{"sum": true, "avg": true, "max": true, "min": true, "count": true, "topk": true, "bottomk": true}
+
+// Parse reads an expression of the supported subset
+func Parse(input string) (Expr, error) {
+	p := &parser{src: input}
+	if err := p.lex(); err != nil {
+		return nil, err
+	}
+	e, err := p.parseExpr()
+	if err != nil {
+		return nil, err
+	}
+	if t := p.peek(); t.kind != tokEOF {
+		return nil, fmt.Errorf("unexpected %q at position %d", t.text, t.pos)
+	}
+	return e, nil
+}
+
+type tokenKind int
+
+const (
+	tokEOF tokenKind = iota
+	tokIdent
+	tokString
+	tokNumber
+	tokDuration
+	tokPunct // ( ) { } [ ] ,
+	tokOp    // = != =~ !~
+)
+
+type token struct {
+	kind tokenKind
+	text string
+	pos  int
+}
+
+type parser struct {
+	src    string
+	tokens []token
+	next   int
+}
+
+func isIdentStart(r rune) bool {
+	return r == '_' || r == ':' || unicode.IsLetter(r)
+}
+
+func isIdentChar(r rune) bool {
+	return isIdentStart(r) || unicode.IsDigit(r)
+}
+
+func (p *parser) lex() error {
+	src := []rune(p.src)
+	for i := 0; i < len(src); {
+		r := src[i]
+		switch {
+		case unicode.IsSpace(r):
+			i++
+		case strings.ContainsRune("(){}[],", r):
+			p.tokens = append(p.tokens, token{tokPunct, string(r), i})
+			i++
+		case r == '=' || r == '!':
+			op := string(r)
+			if i+1 < len(src) && (src[i+1] == '=' || src[i+1] == '~') {
+				op += string(src[i+1])
+			}
+			if op == "!" || op == "==" {
+				return fmt.Errorf("unsupported operator %q at position %d", op, i)
+			}
+			p.tokens = append(p.tokens, token{tokOp, op, i})
+			i += len(op)
+		case r == '"' || r == '\'' || r == '`':
+			j := i + 1
+			for j < len(src) && src[j] != r {
+				if src[j] == '\\' && r != '`' {
+					j++
+				}
+				j++
+			}
+			if j >= len(src) {
+				return fmt.Errorf("unterminated string at position %d", i)
+			}
+			text := string(src[i : j+1])
+			if r == '\'' {
+				// strconv only unquotes single-quoted runes; requote as a Go string
+				text = `"` + strings.ReplaceAll(string(src[i+1:j]), `"`, `\"`) + `"`
+			}
+			value, err := strconv.Unquote(text)
+			if err != nil {
+				return fmt.Errorf("invalid string at position %d", i)
+			}
+			p.tokens = append(p.tokens, token{tokString, value, i})
+			i = j + 1
+		case unicode.IsDigit(r) || r == '.':
+			j := i
+			for j < len(src) && (unicode.IsDigit(src[j]) || src[j] == '.' || unicode.IsLetter(src[j])) {
+				j++
+			}
+			text := string(src[i:j])
+			if _, err := strconv.ParseFloat(text, 64); err == nil {
+				p.tokens = append(p.tokens, token{tokNumber, text, i})
+			} else if _, err := parseDuration(text); err == nil {
+				p.tokens = append(p.tokens, token{tokDuration, text, i})
+			} else {
+				return fmt.Errorf("invalid number or duration %q at position %d", text, i)
+			}
+			i = j
+		case isIdentStart(r):
+			j := i
+			for j < len(src) && isIdentChar(src[j]) {
+				j++
+			}
+			p.tokens = append(p.tokens, token{tokIdent, string(src[i:j]), i})
+			i = j
+		default:
+			return fmt.Errorf("unexpected character %q at position %d", r, i)
+		}
+	}
+	p.tokens = append(p.tokens, token{tokEOF, "end of input", len(src)})
+	return nil
+}
+
+func (p *parser) peek() token {
+	return p.tokens[p.next]
+}
+
+func (p *parser) take() token {
+	t := p.tokens[p.next]
+	if t.kind != tokEOF {
+		p.next++
+	}
+	return t
+}
+
+func (p *parser) expect(text string) error {
+	if t := p.take(); t.text != text || (t.kind != tokPunct && t.kind != tokIdent) {
+		return fmt.Errorf("expected %q, got %q at position %d", text, t.text, t.pos)
+	}
+	return nil
+}
+
+func (p *parser) parseExpr() (Expr, error) {
+	t := p.peek()
+	switch {
+	case t.kind == tokNumber:
+		p.take()
+		v, _ := strconv.ParseFloat(t.text, 64)
+		return &Number{Value: v}, nil
+	case t.kind == tokIdent && aggregations[t.text] && p.followedByGroupingOrParen():
+		return p.parseAggregate()
+	case t.kind == tokIdent && functions[t.text] && p.tokens[p.next+1].text == "(":
+		return p.parseCall()
+	case t.kind == tokIdent || t.text == "{":
+		sel, err := p.parseSelector()
+		if err != nil {
+			return nil, err
+		}
+		if sel.Range > 0 {
+			return nil, fmt.Errorf("range selector %s[...] is only supported inside rate or increase", sel.Name)
+		}
+		return sel, nil
+	case t.text == "(":
+		p.take()
+		e, err := p.parseExpr()
+		if err != nil {
+			return nil, err
+		}
+		return e, p.expect(")")
+	}
+	return nil, fmt.Errorf("unexpected %q at position %d", t.text, t.pos)
+}
+
+// followedByGroupingOrParen tells sum(...) and sum by (...) from a metric named sum
+func (p *parser) followedByGroupingOrParen() bool {
+	next := p.tokens[p.next+1]
+	return next.text == "(" || (next.kind == tokIdent && (next.text == "by" || next.text == "without"))
+}
+
+func (p *parser) parseAggregate() (Expr, error) {
+	agg := &Aggregate{Op: p.take().text}
+	if err := p.parseGrouping(agg); err != nil {
+		return nil, err
+	}
+	if err := p.expect("("); err != nil {
+		return nil, err
+	}
+	if agg.Op == "topk" || agg.Op == "bottomk" {
+		t := p.take()
+		k, err := strconv.ParseFloat(t.text, 64)
+		if t.kind != tokNumber || err != nil || k < 1 {
+			return nil, fmt.Errorf("%s needs a positive number first, got %q", agg.Op, t.text)
+		}
+		agg.Param = k
+		if err := p.expect(","); err != nil {
+			return nil, err
+		}
+	}
+	arg, err := p.parseExpr()
+	if err != nil {
+		return nil, err
+	}
+	if _, ok := arg.(*Number); ok {
+		return nil, fmt.Errorf("%s needs a vector, got a number", agg.Op)
+	}
+	agg.Arg = arg
+	if err := p.expect(")"); err != nil {
+		return nil, err
+	}
+	// Grouping may also follow the arguments: sum(x) by (node)
+	if agg.Grouping == nil && !agg.Without {
+		if err := p.parseGrouping(agg); err != nil {
+			return nil, err
+		}
+	}
+	return agg, nil
+}
+
+func (p *parser) parseGrouping(agg *Aggregate) error {
+	t := p.peek()
+	if t.kind != tokIdent || (t.text != "by" && t.text != "without") {
+		return nil
+	}
+	p.take()
+	agg.Without = t.text == "without"
+	agg.Grouping = []string{}
+	if err := p.expect("("); err != nil {
+		return err
+	}
+	for p.peek().text != ")" {
+		label := p.take()
+		if label.kind != tokIdent {
+			return fmt.Errorf("expected a label name, got %q at position %d", label.text, label.pos)
+		}
+		agg.Grouping = append(agg.Grouping, label.text)
+		if p.peek().text == "," {
+			p.take()
+		}
+	}
+	p.take()
+	return nil
+}
+
+func (p *parser) parseCall() (Expr, error) {
+	call := &Call{Func: p.take().text}
+	if err := p.expect("("); err != nil {
+		return nil, err
+	}
+	sel, err := p.parseSelector()
+	if err != nil {
+		return nil, err
+	}
+	if sel.Range == 0 {
+		return nil, fmt.Errorf("%s needs a range selector, e.g. %s[5m]", call.Func, sel.Name)
+	}
+	call.Arg = sel
+	return call, p.expect(")")
+}
+
+func (p *parser) parseSelector() (*Selector, error) {
+	sel := &Selector{}
+	if p.peek().kind == tokIdent {
+		sel.Name = p.take().text
+	}
+	if p.peek().text == "{" {
+		p.take()
+		for p.peek().text != "}" {
+			name, op, value := p.take(), p.take(), p.take()
+			if name.kind != tokIdent || op.kind != tokOp || value.kind != tokString {
+				return nil, fmt.Errorf("invalid label matcher at position %d", name.pos)
+			}
+			m := Matcher{Name: name.text, Op: MatchOp(op.text), Value: value.text}
+			if m.Op == MatchRegexp || m.Op == MatchNotRegexp {
+				re, err := regexp.Compile("^(?:" + m.Value + ")$")
+				if err != nil {
+					return nil, fmt.Errorf("invalid regular expression %q: %v", m.Value, err)
+				}
+				m.re = re
+			}
+			if m.Name == "__name__" && m.Op == MatchEqual {
+				sel.Name = m.Value
+			} else {
+				sel.Matchers = append(sel.Matchers, m)
+			}
+			if p.peek().text == "," {
+				p.take()
+			}
+		}
+		p.take()
+	}
+	if sel.Name == "" {
+		return nil, fmt.Errorf("selectors need a metric name")
+	}
+	if p.peek().text == "[" {
+		p.take()
+		t := p.take()
+		d, err := parseDuration(t.text)
+		if err != nil {
+			return nil, fmt.Errorf("invalid range %q: %v", t.text, err)
+		}
+		sel.Range = d
+		if err := p.expect("]"); err != nil {
+			return nil, err
+		}
+	}
+	return sel, nil
+}
+
+var durationUnits = map[string]time.Duration{
+	"ms": time.Millisecond,
+	"s":  time.Second,
+	"m":  time.Minute,
+	"h":  time.Hour,
+	"d":  24 * time.Hour,
+	"w":  7 * 24 * time.Hour,
+	"y":  365 * 24 * time.Hour,
+}
+
+var durationPart = regexp.MustCompile(`^([0-9]+)(ms|s|m|h|d|w|y)`)
+
+// parseDuration reads Prometheus durations such as 30s, 5m or 1h30m
+func parseDuration(s string) (time.Duration, error) {
+	if s == "" {
+		return 0, fmt.Errorf("empty duration")
+	}
+	var d time.Duration
+	for s != "" {
+		m := durationPart.FindStringSubmatch(s)
+		if m == nil {
+			return 0, fmt.Errorf("not a duration")
+		}
+		n, _ := strconv.ParseInt(m[1], 10, 64)
+		d += time.Duration(n) * durationUnits[m[2]]
+		s = s[len(m[0]):]
+	}
+	if d <= 0 {
+		return 0, fmt.Errorf("duration must be positive")
+	}
+	return d, nil
+}
+
+// ParseDuration reads a step or range given as a duration or in seconds
+func ParseDuration(s string) (time.Duration, error) {
+	if secs, err := strconv.ParseFloat(s, 64); err == nil {
+		if secs <= 0 {
+			return 0, fmt.Errorf("duration must be positive")
+		}
+		return time.Duration(secs * float64(time.Second)), nil
+	}
+	return parseDuration(s)
+}
@@ -0,0 +1,189 @@
+// Package querycache keeps the responses of aggregating API endpoints in
+// memory, so dashboards refreshing every few seconds are answered without
+// re-aggregating the same raw data. Requested ranges are aligned to a fixed
+// step, which makes refreshes a few seconds apart the same query; entries
+// are tagged with the store's write version and dropped as soon as a flush,
+// backfill, rollup or deletion changes the data beneath them.
+package querycache
+
+import (
+	"bytes"
+	"container/list"
+	"context"
+	"net/http"
+	"strconv"
+	"sync"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/selfmetrics"
+)
+
+// A single response over this share of the cache is served but not kept
+const maxEntryShare = 8
+
+var (
+	hits   = selfmetrics.NewCounter("vitakube_query_cache_hits_total", "API queries answered from the query cache.", "handler")
+	misses = selfmetrics.NewCounter("vitakube_query_cache_misses_total", "API queries the query cache had to run.", "handler")
+)
+
+type scopeKey struct{}
+
+// WithScope answers r from the entries of scope only, e.g. the tenant the
+// response is limited to
+func WithScope(r *http.Request, scope string) *http.Request {
+	return r.WithContext(context.WithValue(r.Context(), scopeKey{}, scope))
+}
+
+type entry struct {
+	key         string
+	version     uint64
+	contentType string
+	body        []byte
+}
+
+// Cache is a size-bounded LRU of GET responses
+type Cache struct {
+	maxBytes int
+	align    time.Duration
+	version  func() uint64
+
+	mu      sync.Mutex
+	bytes   int
+	lru     *list.List // front is most recently used
+	entries map[string]*list.Element
+}
+
+// New caches up to maxBytes of responses. start and end are rounded down
+// to align; version must change whenever the data queried changes.
+func New(maxBytes int, align time.Duration, version func() uint64) *Cache {
+	return &Cache{
+		maxBytes: maxBytes,
+		align:    max(align, time.Second),
+		version:  version,
+		lru:      list.New(),
+		entries:  make(map[string]*list.Element),
+	}
+}
+
+// Bytes is the size of the responses held
+func (c *Cache) Bytes() int {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	return c.bytes
+}
+
+// Wrap serves GETs to next from the cache, counted under the given handler
+// name. The handler sees the aligned start and end, so what it computes is
+// what every request mapping to the same key would have got.
+func (c *Cache) Wrap(name string, next http.HandlerFunc) http.HandlerFunc {
+	return func(w http.ResponseWriter, r *http.Request) {
+		if r.Method != http.MethodGet {
+			next(w, r)
+			return
+		}
+		c.normalize(r)
+		scope, _ := r.Context().Value(scopeKey{}).(string)
+		key := scope + " " + r.URL.Path + "?" + r.URL.RawQuery
+		// Read before running, so data arriving mid-query marks the result stale
+		version := c.version()
+
+		if e, ok := c.get(key, version); ok {
+			hits.Inc(name)
+			w.Header().Set("Content-Type", e.contentType)
+			w.Header().Set("X-Cache", "hit")
+			w.Write(e.body)
+			return
+		}
+		misses.Inc(name)
+
+		rec := &recorder{ResponseWriter: w, code: http.StatusOK, limit: c.maxBytes / maxEntryShare}
+		w.Header().Set("X-Cache", "miss")
+		next(rec, r)
+		if rec.code == http.StatusOK && !rec.overflow {
+			c.put(&entry{key: key, version: version, contentType: w.Header().Get("Content-Type"), body: rec.buf.Bytes()})
+		}
+	}
+}
+
+// normalize rounds start and end down to the alignment, a missing end
+// becoming now, and sorts the query string so parameter order doesn't
+// matter. Unparseable values are left for the handler to reject.
+func (c *Cache) normalize(r *http.Request) {
+	q := r.URL.Query()
+	step := int64(c.align / time.Second)
+	if q.Get("end") == "" {
+		q.Set("end", strconv.FormatInt(time.Now().Unix(), 10))
+	}
+	for _, param := range []string{"start", "end"} {
+		if v, err := strconv.ParseInt(q.Get(param), 10, 64); err == nil {
+			q.Set(param, strconv.FormatInt(v/step*step, 10))
+		}
+	}
+	r.URL.RawQuery = q.Encode()
+}
+
+func (c *Cache) get(key string, version uint64) (*entry, bool) {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	el, ok := c.entries[key]
+	if !ok {
+		return nil, false
+	}
+	e := el.Value.(*entry)
+	if e.version != version {
+		c.remove(el)
+		return nil, false
+	}
+	c.lru.MoveToFront(el)
+	return e, true
+}
+
+func (c *Cache) put(e *entry) {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	// A newer write landed while the query ran; the result is already stale
+	if e.version != c.version() {
+		return
+	}
+	if el, ok := c.entries[e.key]; ok {
+		c.remove(el)
+	}
+	c.entries[e.key] = c.lru.PushFront(e)
+	c.bytes += len(e.body)
+	for c.bytes > c.maxBytes {
+		c.remove(c.lru.Back())
+	}
+}
+
+func (c *Cache) remove(el *list.Element) {
+	e := c.lru.Remove(el).(*entry)
+	delete(c.entries, e.key)
+	c.bytes -= len(e.body)
+}
+
+// recorder passes the response through while keeping a copy, given up
+// once it outgrows limit
+type recorder struct {
+	http.ResponseWriter
+	code     int
+	limit    int
+	buf      bytes.Buffer
+	overflow bool
+}
+
+func (r *recorder) WriteHeader(code int) {
+	r.code = code
+	r.ResponseWriter.WriteHeader(code)
+}
+
+func (r *recorder) Write(p []byte) (int, error) {
+	if !r.overflow {
+		if r.buf.Len()+len(p) > r.limit {
+			r.overflow = true
+			r.buf = bytes.Buffer{}
+		} else {
+			r.buf.Write(p)
+		}
+	}
+	return r.ResponseWriter.Write(p)
+}
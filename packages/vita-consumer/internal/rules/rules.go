@@ -0,0 +1,220 @@
+// Package rules evaluates recording rules: PromQL expressions run on an
+// interval, each result stored as a series under the rule's metric name, so
+// dashboards read precomputed aggregations like per-namespace CPU instead of
+// aggregating raw samples on every refresh.
+package rules
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"log"
+	"regexp"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/promql"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/secret"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/selfmetrics"
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// Interval of rules that don't set one, and the shortest allowed
+const (
+	defaultInterval = time.Minute
+	minInterval     = 10 * time.Second
+)
+
+// Rules are evaluated this far behind, so the samples of the instant
+// evaluated have been flushed from the ring buffer to storage
+const evalDelay = 90 * time.Second
+
+// How often due rules are looked for
+const tick = 5 * time.Second
+
+var metricName = regexp.MustCompile(`^[a-zA-Z_:][a-zA-Z0-9_:]*$`)
+
+var (
+	evaluations = selfmetrics.NewCounter("vitakube_rule_evaluations_total", "Recording rule evaluations.", "rule")
+	failures    = selfmetrics.NewCounter("vitakube_rule_evaluation_failures_total", "Recording rule evaluations that failed.", "rule")
+	duration    = selfmetrics.NewHistogram("vitakube_rule_evaluation_duration_seconds", "Time taken to evaluate a recording rule and store its results.", selfmetrics.DefaultBuckets, "rule")
+)
+
+// Rule is an entry of the rules file
+type Rule struct {
+	// Metric name the results are stored under, e.g. namespace:cpu_ms:rate5m
+	Record   string `json:"record"`
+	Expr     string `json:"expr"`
+	Interval string `json:"interval"` // e.g. 30s; one minute if empty
+}
+
+// Evaluator runs a PromQL expression at one instant over all metrics
+type Evaluator interface {
+	EvaluateInstant(expr promql.Expr, at time.Time) ([]promql.Result, error)
+}
+
+type rule struct {
+	Rule
+	expr     promql.Expr
+	interval time.Duration
+	// Next instant to evaluate
+	next time.Time
+}
+
+// Manager evaluates the rules of a file, re-read when it changes
+type Manager struct {
+	source *secret.Value
+	eval   Evaluator
+	duck   *store.DuckDBStore
+	sqlite *store.SQLiteStore
+
+	raw   string
+	rules []*rule
+	// Recorded series IDs by label set
+	ids map[string]int64
+}
+
+// FromEnv loads rules from the file named by RECORDING_RULES_FILE (or the
+// RECORDING_RULES variable), a JSON array of rules, or returns nil if
+// neither is set
+func FromEnv(eval Evaluator, duck *store.DuckDBStore, sqlite *store.SQLiteStore) (*Manager, error) {
+	source := secret.FromEnv("RECORDING_RULES")
+	if source == nil {
+		return nil, nil
+	}
+	m := &Manager{source: source, eval: eval, duck: duck, sqlite: sqlite, ids: make(map[string]int64)}
+	if err := m.load(source.Get()); err != nil {
+		return nil, err
+	}
+	return m, nil
+}
+
+// load applies a rules file; rules kept across a reload keep their schedule
+func (m *Manager) load(raw string) error {
+	var configs []Rule
+	if err := json.Unmarshal([]byte(raw), &configs); err != nil {
+		return err
+	}
+	previous := make(map[Rule]*rule, len(m.rules))
+	for _, r := range m.rules {
+		previous[r.Rule] = r
+	}
+
+	rules := make([]*rule, 0, len(configs))
+	for _, c := range configs {
+		if r, ok := previous[c]; ok {
+			rules = append(rules, r)
+			continue
+		}
+		if !metricName.MatchString(c.Record) {
+			return fmt.Errorf("rule %q: record must be a metric name", c.Record)
+		}
+		expr, err := promql.Parse(c.Expr)
+		if err != nil {
+			return fmt.Errorf("rule %s: %w", c.Record, err)
+		}
+		interval := defaultInterval
+		if c.Interval != "" {
+			if interval, err = promql.ParseDuration(c.Interval); err != nil {
+				return fmt.Errorf("rule %s: invalid interval: %w", c.Record, err)
+			}
+		}
+		if interval < minInterval {
+			return fmt.Errorf("rule %s: interval must be at least %s", c.Record, minInterval)
+		}
+		rules = append(rules, &rule{Rule: c, expr: expr, interval: interval})
+	}
+
+	m.raw = raw
+	m.rules = rules
+	log.Printf("Loaded %d recording rules", len(rules))
+	return nil
+}
+
+// Run evaluates each rule every interval until ctx is done. Instants are
+// aligned to the interval, so results line up across rules and restarts.
+func (m *Manager) Run(ctx context.Context) {
+	ticker := time.NewTicker(tick)
+	defer ticker.Stop()
+	for {
+		if raw := m.source.Get(); raw != m.raw {
+			// An invalid file keeps the previous rules
+			if err := m.load(raw); err != nil {
+				log.Printf("Ignoring invalid recording rules file: %v", err)
+				m.raw = raw
+			}
+		}
+
+		latest := time.Now().Add(-evalDelay)
+		for _, r := range m.rules {
+			if r.next.IsZero() {
+				r.next = latest.Truncate(r.interval)
+			}
+			// A rule that fell behind skips to its latest instant
+			if r.next.After(latest) {
+				continue
+			}
+			at := latest.Truncate(r.interval)
+			r.next = at.Add(r.interval)
+
+			start := time.Now()
+			evaluations.Inc(r.Record)
+			if err := m.evaluate(r, at); err != nil {
+				failures.Inc(r.Record)
+				log.Printf("Recording rule %s failed: %v", r.Record, err)
+			}
+			duration.Since(start, r.Record)
+		}
+
+		select {
+		case <-ctx.Done():
+			return
+		case <-ticker.C:
+		}
+	}
+}
+
+// evaluate stores the results of a rule at one instant
+func (m *Manager) evaluate(r *rule, at time.Time) error {
+	results, err := m.eval.EvaluateInstant(r.expr, at)
+	if err != nil {
+		return err
+	}
+	points := make([]store.MetricPoint, 0, len(results))
+	for _, res := range results {
+		id, err := m.seriesID(res.Labels)
+		if err != nil {
+			return err
+		}
+		// No node: results are aggregates, usually across nodes
+		points = append(points, store.MetricPoint{
+			Time:       at,
+			ResourceID: -id,
+			MetricType: r.Record,
+			Value:      res.Points[0].V,
+		})
+	}
+	if len(points) == 0 {
+		return nil
+	}
+	return m.duck.BatchInsert(points)
+}
+
+// seriesID returns the ID of a result's label set, recording it on first use
+func (m *Manager) seriesID(labels promql.Labels) (int64, error) {
+	delete(labels, "__name__")
+	// Marshalled maps have sorted keys, so equal label sets match
+	b, err := json.Marshal(labels)
+	if err != nil {
+		return 0, err
+	}
+	key := string(b)
+	if id, ok := m.ids[key]; ok {
+		return id, nil
+	}
+	id, err := m.sqlite.RecordedSeriesID(key)
+	if err != nil {
+		return 0, err
+	}
+	m.ids[key] = id
+	return id, nil
+}
@@ -0,0 +1,195 @@
+// Package secret loads credentials from mounted files and picks up rotated
+// values without a restart. Projected ServiceAccount tokens and Secret
+// volumes are swapped atomically by the kubelet, so a changed mtime means a
+// complete new value.
+package secret
+
+import (
+	"crypto/subtle"
+	"crypto/tls"
+	"log"
+	"os"
+	"strings"
+	"sync"
+	"time"
+)
+
+// How often a file is stat'ed for changes
+const recheckEvery = 10 * time.Second
+
+// How long the previous value is still accepted after a rotation, covering
+// peers whose copy of the mounted secret has not synced yet
+const rotationGrace = 5 * time.Minute
+
+// Value is a credential from the file named by NAME_FILE, re-read when it
+// changes, or else the NAME environment variable captured at startup. A nil
+// *Value means the credential isn't configured.
+type Value struct {
+	path string
+
+	mu       sync.Mutex
+	current  string
+	previous string
+	rotated  time.Time
+	modTime  time.Time
+	checked  time.Time
+}
+
+// FromEnv returns nil if neither NAME_FILE nor NAME is set
+func FromEnv(name string) *Value {
+	if path := os.Getenv(name + "_FILE"); path != "" {
+		v := &Value{path: path}
+		v.mu.Lock()
+		v.reload(time.Now())
+		v.mu.Unlock()
+		return v
+	}
+	if value := os.Getenv(name); value != "" {
+		return &Value{current: value}
+	}
+	return nil
+}
+
+// Get returns the current value, re-reading the file if it changed
+func (v *Value) Get() string {
+	if v == nil {
+		return ""
+	}
+	v.mu.Lock()
+	defer v.mu.Unlock()
+	v.refresh(time.Now())
+	return v.current
+}
+
+// Matches compares given against the current value, or the previous one
+// within the rotation grace period, in constant time
+func (v *Value) Matches(given string) bool {
+	if v == nil {
+		return false
+	}
+	v.mu.Lock()
+	defer v.mu.Unlock()
+	now := time.Now()
+	v.refresh(now)
+
+	if v.current != "" && subtle.ConstantTimeCompare([]byte(given), []byte(v.current)) == 1 {
+		return true
+	}
+	return v.previous != "" && now.Sub(v.rotated) < rotationGrace &&
+		subtle.ConstantTimeCompare([]byte(given), []byte(v.previous)) == 1
+}
+
+// Values returns the current value and, within the rotation grace period,
+// the previous one; for checks such as HMAC that can't use Matches
+func (v *Value) Values() []string {
+	if v == nil {
+		return nil
+	}
+	v.mu.Lock()
+	defer v.mu.Unlock()
+	now := time.Now()
+	v.refresh(now)
+
+	values := []string{v.current}
+	if v.previous != "" && now.Sub(v.rotated) < rotationGrace {
+		values = append(values, v.previous)
+	}
+	return values
+}
+
+func (v *Value) refresh(now time.Time) {
+	if v.path == "" || now.Sub(v.checked) < recheckEvery {
+		return
+	}
+	v.reload(now)
+}
+
+// reload re-reads the file if its mtime moved; a failed read keeps the old value
+func (v *Value) reload(now time.Time) {
+	v.checked = now
+	info, err := os.Stat(v.path)
+	if err != nil {
+		log.Printf("Secret file %s unavailable: %v", v.path, err)
+		return
+	}
+	if info.ModTime().Equal(v.modTime) {
+		return
+	}
+	data, err := os.ReadFile(v.path)
+	if err != nil {
+		log.Printf("Failed to read secret file %s: %v", v.path, err)
+		return
+	}
+	v.modTime = info.ModTime()
+
+	value := strings.TrimSpace(string(data))
+	if value == v.current {
+		return
+	}
+	if v.current != "" {
+		v.previous = v.current
+		v.rotated = now
+		log.Printf("Reloaded rotated secret from %s", v.path)
+	}
+	v.current = value
+}
+
+// Certificate serves a TLS key pair from files, reloading it when either
+// file changes. Use GetCertificate in a tls.Config.
+type Certificate struct {
+	certPath string
+	keyPath  string
+
+	mu      sync.Mutex
+	cert    *tls.Certificate
+	modTime time.Time
+	checked time.Time
+}
+
+func NewCertificate(certPath, keyPath string) (*Certificate, error) {
+	c := &Certificate{certPath: certPath, keyPath: keyPath}
+	if err := c.reload(time.Now()); err != nil {
+		return nil, err
+	}
+	return c, nil
+}
+
+func (c *Certificate) GetCertificate(*tls.ClientHelloInfo) (*tls.Certificate, error) {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	if now := time.Now(); now.Sub(c.checked) >= recheckEvery {
+		// Keep serving the old pair if the new one is unreadable or half-written
+		if err := c.reload(now); err != nil {
+			log.Printf("Failed to reload TLS certificate: %v", err)
+		}
+	}
+	return c.cert, nil
+}
+
+func (c *Certificate) reload(now time.Time) error {
+	c.checked = now
+	var latest time.Time
+	for _, path := range []string{c.certPath, c.keyPath} {
+		info, err := os.Stat(path)
+		if err != nil {
+			return err
+		}
+		if info.ModTime().After(latest) {
+			latest = info.ModTime()
+		}
+	}
+	if c.cert != nil && latest.Equal(c.modTime) {
+		return nil
+	}
+
+	cert, err := tls.LoadX509KeyPair(c.certPath, c.keyPath)
+	if err != nil {
+		return err
+	}
+	if c.cert != nil {
+		log.Printf("Reloaded TLS certificate from %s", c.certPath)
+	}
+	c.cert = &cert
+	c.modTime = latest
+	return nil
+}
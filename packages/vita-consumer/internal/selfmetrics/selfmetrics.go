@@ -0,0 +1,270 @@
+// Package selfmetrics exposes the consumer's own health in the Prometheus
+// text format on /metrics, so a standard Prometheus can watch the monitoring
+// system itself. Instruments register with the package-level registry when
+// created; values read at scrape time come from gauge functions.
+package selfmetrics
+
+import (
+	"fmt"
+	"math"
+	"net/http"
+	"sort"
+	"strconv"
+	"strings"
+	"sync"
+	"time"
+)
+
+// DefaultBuckets suit request and query latencies, in seconds
+var DefaultBuckets = []float64{.005, .01, .025, .05, .1, .25, .5, 1, 2.5, 5, 10}
+
+type sample struct {
+	suffix string
+	labels string
+	value  float64
+}
+
+type family struct {
+	name    string
+	help    string
+	kind    string
+	samples func() []sample
+}
+
+var (
+	mu       sync.Mutex
+	families []*family
+)
+
+func register(f *family) {
+	mu.Lock()
+	defer mu.Unlock()
+	families = append(families, f)
+}
+
+// Counter is a monotonically increasing value per label combination
+type Counter struct {
+	labels []string
+
+	mu     sync.Mutex
+	values map[string]float64
+}
+
+// NewCounter registers a counter; name should end in _total
+func NewCounter(name, help string, labels ...string) *Counter {
+	c := &Counter{labels: labels, values: make(map[string]float64)}
+	register(&family{name: name, help: help, kind: "counter", samples: c.samples})
+	return c
+}
+
+// Add increases the counter for the given label values, in label order
+func (c *Counter) Add(n float64, values ...string) {
+	key := labelString(c.labels, values)
+	c.mu.Lock()
+	c.values[key] += n
+	c.mu.Unlock()
+}
+
+func (c *Counter) Inc(values ...string) {
+	c.Add(1, values...)
+}
+
+func (c *Counter) samples() []sample {
+	c.mu.Lock()
+	defer c.mu.Unlock()
+	out := make([]sample, 0, len(c.values))
+	for labels, v := range c.values {
+		out = append(out, sample{labels: labels, value: v})
+	}
+	return out
+}
+
+// Histogram counts observations into cumulative buckets per label combination
+type Histogram struct {
+	labels  []string
+	buckets []float64
+
+	mu     sync.Mutex
+	series map[string]*histogramSeries
+}
+
+type histogramSeries struct {
+	counts []uint64
+	count  uint64
+	sum    float64
+}
+
+func NewHistogram(name, help string, buckets []float64, labels ...string) *Histogram {
+	h := &Histogram{labels: labels, buckets: buckets, series: make(map[string]*histogramSeries)}
+	register(&family{name: name, help: help, kind: "histogram", samples: h.samples})
+	return h
+}
+
+func (h *Histogram) Observe(v float64, values ...string) {
+	key := labelString(h.labels, values)
+	h.mu.Lock()
+	defer h.mu.Unlock()
+	s, ok := h.series[key]
+	if !ok {
+		s = &histogramSeries{counts: make([]uint64, len(h.buckets))}
+		h.series[key] = s
+	}
+	for i, le := range h.buckets {
+		if v <= le {
+			s.counts[i]++
+		}
+	}
+	s.count++
+	s.sum += v
+}
+
+// Since observes the seconds elapsed since start
+func (h *Histogram) Since(start time.Time, values ...string) {
+	h.Observe(time.Since(start).Seconds(), values...)
+}
+
+func (h *Histogram) samples() []sample {
+	h.mu.Lock()
+	defer h.mu.Unlock()
+	keys := make([]string, 0, len(h.series))
+	for labels := range h.series {
+		keys = append(keys, labels)
+	}
+	sort.Strings(keys)
+
+	var out []sample
+	for _, labels := range keys {
+		s := h.series[labels]
+		for i, le := range h.buckets {
+			out = append(out, sample{suffix: "_bucket", labels: withLabel(labels, "le", formatFloat(le)), value: float64(s.counts[i])})
+		}
+		out = append(out,
+			sample{suffix: "_bucket", labels: withLabel(labels, "le", "+Inf"), value: float64(s.count)},
+			sample{suffix: "_sum", labels: labels, value: s.sum},
+			sample{suffix: "_count", labels: labels, value: float64(s.count)},
+		)
+	}
+	return out
+}
+
+// GaugeFunc registers a gauge read from fn at scrape time
+func GaugeFunc(name, help string, fn func() float64) {
+	register(&family{name: name, help: help, kind: "gauge", samples: func() []sample {
+		return []sample{{value: fn()}}
+	}})
+}
+
+// CounterFunc registers a counter kept elsewhere, read from fn at scrape time
+func CounterFunc(name, help string, fn func() float64) {
+	register(&family{name: name, help: help, kind: "counter", samples: func() []sample {
+		return []sample{{value: fn()}}
+	}})
+}
+
+// LabeledGaugeFunc registers a gauge whose fn returns a value per label value
+func LabeledGaugeFunc(name, help, label string, fn func() map[string]float64) {
+	register(&family{name: name, help: help, kind: "gauge", samples: func() []sample {
+		values := fn()
+		out := make([]sample, 0, len(values))
+		for v, n := range values {
+			out = append(out, sample{labels: labelString([]string{label}, []string{v}), value: n})
+		}
+		return out
+	}})
+}
+
+// Handler serves every registered family in the text exposition format
+func Handler() http.Handler {
+	return http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		mu.Lock()
+		list := append([]*family(nil), families...)
+		mu.Unlock()
+		sort.Slice(list, func(i, j int) bool { return list[i].name < list[j].name })
+
+		var b strings.Builder
+		for _, f := range list {
+			samples := f.samples()
+			// Stable output for diffing scrapes; histograms keep bucket order
+			if f.kind != "histogram" {
+				sort.Slice(samples, func(i, j int) bool { return samples[i].labels < samples[j].labels })
+			}
+			fmt.Fprintf(&b, "# HELP %s %s\n# TYPE %s %s\n", f.name, f.help, f.name, f.kind)
+			for _, s := range samples {
+				b.WriteString(f.name + s.suffix)
+				if s.labels != "" {
+					b.WriteString("{" + s.labels + "}")
+				}
+				b.WriteString(" " + formatFloat(s.value) + "\n")
+			}
+		}
+		w.Header().Set("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+		w.Write([]byte(b.String()))
+	})
+}
+
+// Instrument counts requests to next by status code and times them, under
+// the given handler name
+func Instrument(name string, next http.HandlerFunc) http.HandlerFunc {
+	return func(w http.ResponseWriter, r *http.Request) {
+		start := time.Now()
+		rec := &statusRecorder{ResponseWriter: w, code: http.StatusOK}
+		next(rec, r)
+		httpRequests.Inc(name, strconv.Itoa(rec.code))
+		httpDuration.Since(start, name)
+	}
+}
+
+var (
+	httpRequests = NewCounter("vitakube_http_requests_total", "HTTP requests by handler and status code.", "handler", "code")
+	httpDuration = NewHistogram("vitakube_http_request_duration_seconds", "HTTP request latency by handler.", DefaultBuckets, "handler")
+)
+
+type statusRecorder struct {
+	http.ResponseWriter
+	code int
+}
+
+func (r *statusRecorder) WriteHeader(code int) {
+	r.code = code
+	r.ResponseWriter.WriteHeader(code)
+}
+
+// Flush keeps streaming handlers (live metrics, export) working
+func (r *statusRecorder) Flush() {
+	if f, ok := r.ResponseWriter.(http.Flusher); ok {
+		f.Flush()
+	}
+}
+
+func labelString(names, values []string) string {
+	var b strings.Builder
+	for i, name := range names {
+		v := ""
+		if i < len(values) {
+			v = values[i]
+		}
+		if i > 0 {
+			b.WriteByte(',')
+		}
+		b.WriteString(name + "=" + strconv.Quote(v))
+	}
+	return b.String()
+}
+
+func withLabel(labels, name, value string) string {
+	pair := name + "=" + strconv.Quote(value)
+	if labels == "" {
+		return pair
+	}
+	return labels + "," + pair
+}
+
+func formatFloat(v float64) string {
+	switch {
+	case math.IsInf(v, 1):
+		return "+Inf"
+	case math.IsInf(v, -1):
+		return "-Inf"
+	}
+	return strconv.FormatFloat(v, 'g', -1, 64)
+}
@@ -0,0 +1,84 @@
+package shard
+
+import (
+	"hash/fnv"
+	"sort"
+	"strconv"
+	"strings"
+)
+
+// Virtual nodes per peer; enough to keep the node-name spread even for a
+// handful of replicas
+const vnodesPerPeer = 128
+
+// Ring assigns agent node names to consumer replicas with consistent hashing,
+// so adding or removing a replica only moves a fraction of the nodes.
+// Every replica is configured with the same peer list and therefore computes
+// the same ring without coordinating.
+type Ring struct {
+	self   string
+	peers  []string
+	hashes []uint64
+	owners map[uint64]string
+}
+
+// NewRing builds a ring over peer base URLs (e.g. http://vita-consumer-0:8080).
+// self must be one of peers; an empty peer list means a single unsharded consumer.
+func NewRing(self string, peers []string) *Ring {
+	r := &Ring{
+		self:   strings.TrimRight(self, "/"),
+		owners: make(map[uint64]string),
+	}
+
+	for _, p := range peers {
+		p = strings.TrimRight(strings.TrimSpace(p), "/")
+		if p == "" {
+			continue
+		}
+		r.peers = append(r.peers, p)
+		for i := 0; i < vnodesPerPeer; i++ {
+			h := hash(p + "#" + strconv.Itoa(i))
+			r.hashes = append(r.hashes, h)
+			r.owners[h] = p
+		}
+	}
+	sort.Slice(r.hashes, func(i, j int) bool { return r.hashes[i] < r.hashes[j] })
+	return r
+}
+
+// Enabled reports whether more than one replica shares the ingest load
+func (r *Ring) Enabled() bool {
+	return len(r.peers) > 1
+}
+
+// Owner returns the base URL of the replica responsible for a node
+func (r *Ring) Owner(node string) string {
+	if len(r.hashes) == 0 {
+		return r.self
+	}
+	h := hash(node)
+	i := sort.Search(len(r.hashes), func(i int) bool { return r.hashes[i] >= h })
+	if i == len(r.hashes) {
+		i = 0
+	}
+	return r.owners[r.hashes[i]]
+}
+
+// IsLocal reports whether this replica owns the node
+func (r *Ring) IsLocal(node string) bool {
+	return !r.Enabled() || r.Owner(node) == r.self
+}
+
+func (r *Ring) Self() string {
+	return r.self
+}
+
+func (r *Ring) Peers() []string {
+	return r.peers
+}
+
+func hash(s string) uint64 {
+	h := fnv.New64a()
+	h.Write([]byte(s))
+	return h.Sum64()
+}
@@ -0,0 +1,314 @@
+// Package snapshot backs up and restores the consumer's local storage. A
+// snapshot is a directory holding a copy of each database and a manifest;
+// it can be downloaded as a tar.gz archive and restored from either form
+// when the consumer starts, e.g. onto a new volume when migrating.
+package snapshot
+
+import (
+	"archive/tar"
+	"compress/gzip"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"io"
+	"log"
+	"os"
+	"path/filepath"
+	"sort"
+	"strings"
+	"sync"
+	"time"
+)
+
+// Database files of the data directory, as snapshots name them too
+const (
+	MetadataFile = "meta.db"
+	MetricsFile  = "metrics.duckdb"
+	manifestFile = "manifest.json"
+	// Name of the last snapshot restored, so a restart doesn't restore again
+	restoredFile = "restored-snapshot"
+)
+
+// Source is a database that can copy itself to a new file
+type Source interface {
+	Snapshot(path string) error
+}
+
+// Manifest describes a snapshot
+type Manifest struct {
+	Name    string `json:"name"`
+	Created int64  `json:"created"` // unix seconds
+	Bytes   int64  `json:"bytes"`
+}
+
+// Store keeps snapshots in a directory
+type Store struct {
+	dir      string
+	metrics  Source
+	metadata Source
+
+	// One snapshot at a time
+	mu sync.Mutex
+}
+
+func NewStore(dir string, metrics, metadata Source) *Store {
+	return &Store{dir: dir, metrics: metrics, metadata: metadata}
+}
+
+// Create snapshots both databases. Metrics are copied first: the metadata
+// copy is then the newer one and knows every pod the metrics refer to.
+func (s *Store) Create() (Manifest, error) {
+	s.mu.Lock()
+	defer s.mu.Unlock()
+
+	now := time.Now().UTC()
+	m := Manifest{Name: now.Format("20060102-150405"), Created: now.Unix()}
+	final := filepath.Join(s.dir, m.Name)
+	if _, err := os.Stat(final); err == nil {
+		return Manifest{}, fmt.Errorf("snapshot %s already exists", m.Name)
+	}
+
+	// Written under a temporary name, so a failed snapshot never shows up
+	tmp := final + ".tmp"
+	if err := os.MkdirAll(tmp, 0755); err != nil {
+		return Manifest{}, err
+	}
+	if err := s.write(tmp, &m); err != nil {
+		os.RemoveAll(tmp)
+		return Manifest{}, err
+	}
+	if err := os.Rename(tmp, final); err != nil {
+		os.RemoveAll(tmp)
+		return Manifest{}, err
+	}
+	log.Printf("Created snapshot %s (%d bytes)", m.Name, m.Bytes)
+	return m, nil
+}
+
+func (s *Store) write(dir string, m *Manifest) error {
+	if err := s.metrics.Snapshot(filepath.Join(dir, MetricsFile)); err != nil {
+		return fmt.Errorf("metrics: %w", err)
+	}
+	if err := s.metadata.Snapshot(filepath.Join(dir, MetadataFile)); err != nil {
+		return fmt.Errorf("metadata: %w", err)
+	}
+	for _, name := range []string{MetricsFile, MetadataFile} {
+		info, err := os.Stat(filepath.Join(dir, name))
+		if err != nil {
+			return err
+		}
+		m.Bytes += info.Size()
+	}
+	data, err := json.Marshal(m)
+	if err != nil {
+		return err
+	}
+	return os.WriteFile(filepath.Join(dir, manifestFile), data, 0644)
+}
+
+// List returns the snapshots, oldest first
+func (s *Store) List() ([]Manifest, error) {
+	entries, err := os.ReadDir(s.dir)
+	if errors.Is(err, os.ErrNotExist) {
+		return []Manifest{}, nil
+	}
+	if err != nil {
+		return nil, err
+	}
+	snapshots := []Manifest{}
+	for _, e := range entries {
+		if !e.IsDir() || strings.HasSuffix(e.Name(), ".tmp") {
+			continue
+		}
+		m, err := readManifest(filepath.Join(s.dir, e.Name()))
+		if err != nil {
+			continue
+		}
+		snapshots = append(snapshots, m)
+	}
+	sort.Slice(snapshots, func(i, j int) bool { return snapshots[i].Created < snapshots[j].Created })
+	return snapshots, nil
+}
+
+// path returns a snapshot's directory, or an error if there's none by name
+func (s *Store) path(name string) (string, error) {
+	if name == "" || name != filepath.Base(name) || strings.HasPrefix(name, ".") {
+		return "", fmt.Errorf("invalid snapshot name %q", name)
+	}
+	dir := filepath.Join(s.dir, name)
+	if _, err := readManifest(dir); err != nil {
+		return "", fmt.Errorf("snapshot %s not found", name)
+	}
+	return dir, nil
+}
+
+// Has reports whether there's a snapshot by name
+func (s *Store) Has(name string) bool {
+	_, err := s.path(name)
+	return err == nil
+}
+
+// Delete removes a snapshot
+func (s *Store) Delete(name string) error {
+	dir, err := s.path(name)
+	if err != nil {
+		return err
+	}
+	return os.RemoveAll(dir)
+}
+
+// WriteArchive streams a snapshot as a tar.gz, its files under <name>/
+func (s *Store) WriteArchive(w io.Writer, name string) error {
+	dir, err := s.path(name)
+	if err != nil {
+		return err
+	}
+	gz := gzip.NewWriter(w)
+	tw := tar.NewWriter(gz)
+	for _, file := range []string{manifestFile, MetricsFile, MetadataFile} {
+		if err := addFile(tw, filepath.Join(dir, file), name+"/"+file); err != nil {
+			return err
+		}
+	}
+	if err := tw.Close(); err != nil {
+		return err
+	}
+	return gz.Close()
+}
+
+func addFile(tw *tar.Writer, path, name string) error {
+	f, err := os.Open(path)
+	if err != nil {
+		return err
+	}
+	defer f.Close()
+	info, err := f.Stat()
+	if err != nil {
+		return err
+	}
+	if err := tw.WriteHeader(&tar.Header{Name: name, Mode: 0644, Size: info.Size(), ModTime: info.ModTime()}); err != nil {
+		return err
+	}
+	_, err = io.Copy(tw, f)
+	return err
+}
+
+func readManifest(dir string) (Manifest, error) {
+	var m Manifest
+	data, err := os.ReadFile(filepath.Join(dir, manifestFile))
+	if err != nil {
+		return m, err
+	}
+	err = json.Unmarshal(data, &m)
+	return m, err
+}
+
+// Restore replaces the databases in dataDir with those of a snapshot, a
+// directory or a tar.gz from WriteArchive, unless that snapshot was
+// already restored. Call before the stores are opened. The write-ahead
+// log is cleared: its batches belong to the storage being replaced.
+func Restore(src, dataDir string) (bool, error) {
+	staging, err := os.MkdirTemp(dataDir, "restore-")
+	if err != nil {
+		return false, err
+	}
+	defer os.RemoveAll(staging)
+
+	dir := src
+	if !isDir(src) {
+		if dir, err = extract(src, staging); err != nil {
+			return false, fmt.Errorf("extract %s: %w", src, err)
+		}
+	}
+	m, err := readManifest(dir)
+	if err != nil {
+		return false, fmt.Errorf("read manifest: %w", err)
+	}
+	if previous, err := os.ReadFile(filepath.Join(dataDir, restoredFile)); err == nil && string(previous) == m.Name {
+		return false, nil
+	}
+
+	// Stage copies first, so a failure leaves the current databases in place
+	for _, file := range []string{MetricsFile, MetadataFile} {
+		if err := copyFile(filepath.Join(dir, file), filepath.Join(staging, file+".restore")); err != nil {
+			return false, err
+		}
+	}
+	for _, file := range []string{MetricsFile, MetadataFile} {
+		target := filepath.Join(dataDir, file)
+		for _, suffix := range []string{"-wal", "-shm", ".wal"} {
+			os.Remove(target + suffix)
+		}
+		if err := os.Rename(filepath.Join(staging, file+".restore"), target); err != nil {
+			return false, err
+		}
+	}
+	if err := os.RemoveAll(filepath.Join(dataDir, "wal")); err != nil {
+		return false, err
+	}
+	if err := os.WriteFile(filepath.Join(dataDir, restoredFile), []byte(m.Name), 0644); err != nil {
+		return false, err
+	}
+	log.Printf("Restored snapshot %s from %s", m.Name, src)
+	return true, nil
+}
+
+func isDir(path string) bool {
+	info, err := os.Stat(path)
+	return err == nil && info.IsDir()
+}
+
+// extract unpacks a snapshot archive into dir and returns the snapshot's
+// directory. Only the files a snapshot has are taken.
+func extract(archive, dir string) (string, error) {
+	f, err := os.Open(archive)
+	if err != nil {
+		return "", err
+	}
+	defer f.Close()
+	gz, err := gzip.NewReader(f)
+	if err != nil {
+		return "", err
+	}
+	tr := tar.NewReader(gz)
+	for {
+		h, err := tr.Next()
+		if err == io.EOF {
+			return dir, nil
+		}
+		if err != nil {
+			return "", err
+		}
+		switch base := filepath.Base(h.Name); base {
+		case manifestFile, MetricsFile, MetadataFile:
+			out, err := os.Create(filepath.Join(dir, base))
+			if err != nil {
+				return "", err
+			}
+			_, err = io.Copy(out, tr)
+			if closeErr := out.Close(); err == nil {
+				err = closeErr
+			}
+			if err != nil {
+				return "", err
+			}
+		}
+	}
+}
+
+func copyFile(src, dst string) error {
+	in, err := os.Open(src)
+	if err != nil {
+		return err
+	}
+	defer in.Close()
+	out, err := os.Create(dst)
+	if err != nil {
+		return err
+	}
+	if _, err := io.Copy(out, in); err != nil {
+		out.Close()
+		return err
+	}
+	return out.Close()
+}
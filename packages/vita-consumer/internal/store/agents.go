@@ -0,0 +1,95 @@
+package store
+
+import (
+	"strings"
+	"time"
+)
+
+// HeartbeatHistory is how far back heartbeat minutes are kept, as far as
+// metrics can be queried in one request
+const HeartbeatHistory = 7 * 24 * time.Hour
+
+// Heartbeat is what an agent last reported about itself
+type Heartbeat struct {
+	Node         string   `json:"node"`
+	Version      string   `json:"version"`
+	Collectors   []string `json:"collectors"`
+	ConfigHash   string   `json:"config_hash"`
+	UptimeSecs   int64    `json:"uptime_secs"`
+	IntervalSecs int64    `json:"interval_secs"`
+	LastSeen     int64    `json:"last_seen"` // unix seconds, set by the consumer
+}
+
+// UpsertHeartbeat records the latest heartbeat of a node's agent, and the
+// minute it arrived in
+func (s *SQLiteStore) UpsertHeartbeat(h Heartbeat) error {
+	tx, err := s.db.Begin()
+	if err != nil {
+		return err
+	}
+	defer tx.Rollback()
+
+	_, err = tx.Exec(`
+    INSERT INTO agents (node, version, collectors, config_hash, uptime_secs, interval_secs, last_seen)
+    VALUES (?, ?, ?, ?, ?, ?, ?)
+    ON CONFLICT(node) DO UPDATE SET
+        version = excluded.version,
+        collectors = excluded.collectors,
+        config_hash = excluded.config_hash,
+        uptime_secs = excluded.uptime_secs,
+        interval_secs = excluded.interval_secs,
+        last_seen = excluded.last_seen`,
+		h.Node, h.Version, strings.Join(h.Collectors, ","), h.ConfigHash, h.UptimeSecs, h.IntervalSecs, h.LastSeen)
+	if err != nil {
+		return err
+	}
+
+	minute := h.LastSeen / 60
+	res, err := tx.Exec(`INSERT OR IGNORE INTO heartbeat_minutes (node, minute) VALUES (?, ?)`, h.Node, minute)
+	if err != nil {
+		return err
+	}
+	cutoff := minute - int64(HeartbeatHistory/time.Minute)
+	if _, err := tx.Exec(`DELETE FROM heartbeat_minutes WHERE node = ? AND minute < ?`, h.Node, cutoff); err != nil {
+		return err
+	}
+	if err := tx.Commit(); err != nil {
+		return err
+	}
+	if n, _ := res.RowsAffected(); n > 0 {
+		s.heartbeats.Add(1)
+	}
+	return nil
+}
+
+// HeartbeatVersion changes whenever HeartbeatMinutes would return more
+func (s *SQLiteStore) HeartbeatVersion() uint64 {
+	return s.heartbeats.Load()
+}
+
+// HeartbeatMinutes returns, by node, the unix minutes in [start, end) that
+// an agent sent a heartbeat in; an empty node means all nodes
+func (s *SQLiteStore) HeartbeatMinutes(node string, start, end time.Time) (map[string][]int64, error) {
+	query := `SELECT node, minute FROM heartbeat_minutes WHERE minute >= ? AND minute < ?`
+	args := []interface{}{start.Unix() / 60, (end.Unix() + 59) / 60}
+	if node != "" {
+		query += " AND node = ?"
+		args = append(args, node)
+	}
+	rows, err := s.db.Query(query+" ORDER BY node, minute", args...)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	minutes := map[string][]int64{}
+	for rows.Next() {
+		var n string
+		var minute int64
+		if err := rows.Scan(&n, &minute); err != nil {
+			return nil, err
+		}
+		minutes[n] = append(minutes[n], minute)
+	}
+	return minutes, rows.Err()
+}
@@ -2,20 +2,61 @@ package store
 
 import (
 	"database/sql"
+	"fmt"
+	"slices"
+	"sort"
+	"strconv"
+	"strings"
+	"sync/atomic"
 	"time"
 
 	_ "github.com/marcboeker/go-duckdb"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/buffer"
 )
 
 type DuckDBStore struct {
 	db *sql.DB
+	// Bumped by every write, so cached query results can tell they're stale
+	version atomic.Uint64
 }
 
 type MetricPoint struct {
 	Time       time.Time
+	Node       string
 	ResourceID int64
-	MetricType string
-	Value      float64
+	// The agent's metric type, e.g. node_disk; MetricType is its key, e.g.
+	// reads, which names the metric in queries
+	Type        string
+	MetricType  string
+	Value       float64
+	Device      string
+	ContainerID string
+	Volume      string
+	Labels      string
+	// Microseconds into the agent's collection cycle; Time is the cycle's
+	Offset int64
+}
+
+// PointsFromBuffer converts buffered metrics into rows for BatchInsert
+func PointsFromBuffer(data []buffer.Metric) []MetricPoint {
+	points := make([]MetricPoint, len(data))
+	for i, m := range data {
+		points[i] = MetricPoint{
+			Time:        m.Time,
+			Node:        m.Node,
+			ResourceID:  m.ResourceID,
+			Type:        m.Type,
+			MetricType:  m.Key,
+			Value:       m.Value,
+			Device:      m.Device,
+			ContainerID: m.ContainerID,
+			Volume:      m.Volume,
+			Labels:      m.Labels,
+			Offset:      m.Offset,
+		}
+	}
+	return points
 }
 
 func NewDuckDBStore(path string) (*DuckDBStore, error) {
@@ -41,7 +82,39 @@ func initDuckDBSchema(db *sql.DB) error {
         agg_type TEXT DEFAULT 'raw'
     );
     `
-	_, err := db.Exec(query)
+	if _, err := db.Exec(query); err != nil {
+		return err
+	}
+
+	// Added after the initial schema; older databases are migrated in place
+	if _, err := db.Exec(`ALTER TABLE metrics ADD COLUMN IF NOT EXISTS node TEXT DEFAULT ''`); err != nil {
+		return err
+	}
+	// Node labels as JSON, e.g. {"region":"eu-1","zone":"eu-1a"}
+	if _, err := db.Exec(`ALTER TABLE metrics ADD COLUMN IF NOT EXISTS labels TEXT DEFAULT ''`); err != nil {
+		return err
+	}
+	// When within its collection cycle a raw value was read; rollups keep 0
+	if _, err := db.Exec(`ALTER TABLE metrics ADD COLUMN IF NOT EXISTS offset_us BIGINT DEFAULT 0`); err != nil {
+		return err
+	}
+	// What tells a node's or pod's series of one key apart: the agent's
+	// metric type (node_mem and node_swap both have total_mb) and the
+	// device, container and volume labels. Rows from before are ''.
+	for _, column := range seriesColumns {
+		if _, err := db.Exec(`ALTER TABLE metrics ADD COLUMN IF NOT EXISTS ` + column + ` TEXT DEFAULT ''`); err != nil {
+			return err
+		}
+	}
+
+	// Rollup segments moved to object storage by the cold tier
+	_, err := db.Exec(`
+    CREATE TABLE IF NOT EXISTS segments (
+        object_key TEXT PRIMARY KEY,
+        start_time TIMESTAMPTZ NOT NULL,
+        end_time TIMESTAMPTZ NOT NULL
+    );
+    `)
 	return err
 }
 
@@ -49,6 +122,11 @@ func (s *DuckDBStore) Close() error {
 	return s.db.Close()
 }
 
+// Version changes whenever rows are written or deleted
+func (s *DuckDBStore) Version() uint64 {
+	return s.version.Load()
+}
+
 func (s *DuckDBStore) BatchInsert(metrics []MetricPoint) error {
 	if len(metrics) == 0 {
 		return nil
@@ -63,18 +141,383 @@ func (s *DuckDBStore) BatchInsert(metrics []MetricPoint) error {
 	defer tx.Rollback()
 
 	// Prepared statement
-	stmt, err := tx.Prepare("INSERT INTO metrics (time, resource_id, metric_type, value, agg_type) VALUES (?, ?, ?, ?, 'raw')")
+	stmt, err := tx.Prepare("INSERT INTO metrics (time, node, resource_id, type, metric_type, value, device, container_id, volume, labels, offset_us, agg_type)" +
+		" VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'raw')")
 	if err != nil {
 		return err
 	}
 	defer stmt.Close()
 
 	for _, m := range metrics {
-		_, err := stmt.Exec(m.Time, m.ResourceID, m.MetricType, m.Value)
+		_, err := stmt.Exec(m.Time, m.Node, m.ResourceID, m.Type, m.MetricType, m.Value, m.Device, m.ContainerID, m.Volume, m.Labels, m.Offset)
 		if err != nil {
 			return err
 		}
 	}
 
+	defer s.version.Add(1)
 	return tx.Commit()
 }
+
+// MetricFilter selects raw metric rows for a time range
+type MetricFilter struct {
+	Start       time.Time
+	End         time.Time
+	Node        string
+	Nodes       []string // any of these, e.g. a tenant's nodes
+	ResourceID  int64
+	ResourceIDs []int64 // any of these, e.g. all pods of a namespace
+	MetricType  string
+	Type        string // the agent's metric type, e.g. node_disk
+	// Node labels that must all match, e.g. zone=eu-1a
+	Labels map[string]string
+	// Page bounds in row order: rows after After, up to and including Through
+	After   *RowKey
+	Through *RowKey
+}
+
+// RowKey is a row's place in the order QueryMetrics returns rows in.
+// Cursors from before the series columns leave them empty.
+type RowKey struct {
+	TimeMicros  int64  `json:"t"`
+	Node        string `json:"n"`
+	ResourceID  int64  `json:"r"`
+	MetricType  string `json:"m"`
+	Type        string `json:"y,omitempty"`
+	Device      string `json:"d,omitempty"`
+	ContainerID string `json:"c,omitempty"`
+	Volume      string `json:"v,omitempty"`
+}
+
+// keyColumns order rows after time, as the fields of a RowKey
+var keyColumns = []string{"node", "resource_id", "metric_type", "type", "device", "container_id", "volume"}
+
+// rowOrder sorts rows by time, then by the rest of their RowKey
+var rowOrder = "ORDER BY time, " + strings.Join(keyColumns, ", ")
+
+// afterKey matches rows past a RowKey, given its rendered time and the
+// rendered values of its keyColumns
+func afterKey(t string, values []string) string {
+	last := len(keyColumns) - 1
+	clause := keyColumns[last] + " > " + values[last]
+	for i := last - 1; i >= 0; i-- {
+		clause = fmt.Sprintf("%[1]s > %[2]s OR (%[1]s = %[2]s AND (%[3]s))", keyColumns[i], values[i], clause)
+	}
+	return fmt.Sprintf("(epoch_us(time) > %[1]s OR (epoch_us(time) = %[1]s AND (%[2]s)))", t, clause)
+}
+
+// keyParams renders afterKey with bind parameters, whose values args returns
+func keyParams() string {
+	params := make([]string, len(keyColumns))
+	for i := range params {
+		params[i] = "?"
+	}
+	return afterKey("?", params)
+}
+
+func (k RowKey) values() []interface{} {
+	return []interface{}{k.Node, k.ResourceID, k.MetricType, k.Type, k.Device, k.ContainerID, k.Volume}
+}
+
+// args are the bind parameters of keyParams, in order: every value but the
+// last is compared twice
+func (k RowKey) args() []interface{} {
+	args := []interface{}{k.TimeMicros, k.TimeMicros}
+	values := k.values()
+	for i, v := range values {
+		args = append(args, v)
+		if i < len(values)-1 {
+			args = append(args, v)
+		}
+	}
+	return args
+}
+
+func (k RowKey) literal() string {
+	values := make([]string, len(keyColumns))
+	for i, v := range k.values() {
+		switch v := v.(type) {
+		case int64:
+			values[i] = strconv.FormatInt(v, 10)
+		case string:
+			values[i] = quoteLiteral(v)
+		}
+	}
+	return afterKey(strconv.FormatInt(k.TimeMicros, 10), values)
+}
+
+func (f MetricFilter) where() (string, []interface{}) {
+	clause := "WHERE time >= ? AND time < ?"
+	args := []interface{}{f.Start, f.End}
+
+	if f.Node != "" {
+		clause += " AND node = ?"
+		args = append(args, f.Node)
+	}
+	if f.Nodes != nil {
+		clause += " AND node IN (" + nodeList(f.Nodes) + ")"
+	}
+	if f.ResourceID > 0 {
+		clause += " AND resource_id = ?"
+		args = append(args, f.ResourceID)
+	}
+	if f.ResourceIDs != nil {
+		clause += " AND resource_id IN (" + idList(f.ResourceIDs) + ")"
+	}
+	if f.MetricType != "" {
+		clause += " AND metric_type = ?"
+		args = append(args, f.MetricType)
+	}
+	if f.Type != "" {
+		clause += " AND type = ?"
+		args = append(args, f.Type)
+	}
+	for _, name := range sortedKeys(f.Labels) {
+		clause += " AND json_extract_string(labels, ?) = ?"
+		args = append(args, labelPath(name), f.Labels[name])
+	}
+	if f.After != nil {
+		clause += " AND " + keyParams()
+		args = append(args, f.After.args()...)
+	}
+	if f.Through != nil {
+		clause += " AND NOT " + keyParams()
+		args = append(args, f.Through.args()...)
+	}
+	return clause, args
+}
+
+// literalWhere renders the filter inline, for statements like COPY that
+// don't accept bind parameters.
+func (f MetricFilter) literalWhere() string {
+	clause := fmt.Sprintf("WHERE time >= to_timestamp(%d) AND time < to_timestamp(%d)", f.Start.Unix(), f.End.Unix())
+
+	if f.Node != "" {
+		clause += " AND node = " + quoteLiteral(f.Node)
+	}
+	if f.Nodes != nil {
+		clause += " AND node IN (" + nodeList(f.Nodes) + ")"
+	}
+	if f.ResourceID > 0 {
+		clause += fmt.Sprintf(" AND resource_id = %d", f.ResourceID)
+	}
+	if f.ResourceIDs != nil {
+		clause += " AND resource_id IN (" + idList(f.ResourceIDs) + ")"
+	}
+	if f.MetricType != "" {
+		clause += " AND metric_type = " + quoteLiteral(f.MetricType)
+	}
+	if f.Type != "" {
+		clause += " AND type = " + quoteLiteral(f.Type)
+	}
+	for _, name := range sortedKeys(f.Labels) {
+		clause += " AND json_extract_string(labels, " + quoteLiteral(labelPath(name)) + ") = " + quoteLiteral(f.Labels[name])
+	}
+	if f.After != nil {
+		clause += " AND " + f.After.literal()
+	}
+	if f.Through != nil {
+		clause += " AND NOT " + f.Through.literal()
+	}
+	return clause
+}
+
+// labelPath is the JSON path of a label; quoting allows any label name
+func labelPath(name string) string {
+	return `$."` + strings.ReplaceAll(name, `"`, `\"`) + `"`
+}
+
+func sortedKeys(m map[string]string) []string {
+	keys := make([]string, 0, len(m))
+	for k := range m {
+		keys = append(keys, k)
+	}
+	sort.Strings(keys)
+	return keys
+}
+
+// idList renders integer IDs inline; an empty list matches nothing
+func idList(ids []int64) string {
+	if len(ids) == 0 {
+		return "NULL"
+	}
+	parts := make([]string, len(ids))
+	for i, id := range ids {
+		parts[i] = strconv.FormatInt(id, 10)
+	}
+	return strings.Join(parts, ", ")
+}
+
+// nodeList renders node names inline; an empty list matches nothing
+func nodeList(nodes []string) string {
+	if len(nodes) == 0 {
+		return "NULL"
+	}
+	parts := make([]string, len(nodes))
+	for i, node := range nodes {
+		parts[i] = quoteLiteral(node)
+	}
+	return strings.Join(parts, ", ")
+}
+
+// HasNode reports whether rows of node can match the filter's node conditions
+func (f MetricFilter) HasNode(node string) bool {
+	if f.Node != "" && node != f.Node {
+		return false
+	}
+	return f.Nodes == nil || slices.Contains(f.Nodes, node)
+}
+
+func quoteLiteral(s string) string {
+	return "'" + strings.ReplaceAll(s, "'", "''") + "'"
+}
+
+// metricsSource is the FROM target for reads: the local table, plus any
+// Parquet segment files fetched back from the cold tier.
+func metricsSource(segments []string) string {
+	if len(segments) == 0 {
+		return "metrics"
+	}
+
+	files := make([]string, len(segments))
+	for i, path := range segments {
+		files[i] = quoteLiteral(path)
+	}
+	// Rollups carry no node labels, so label filters only match local rows.
+	// Segments written before the series columns existed lack them, which
+	// BY NAME fills with NULLs.
+	return `(SELECT time, node, resource_id, metric_type, value, coalesce(labels, '') AS labels, ` + coalesced(seriesColumns) + ` FROM (
+        SELECT time, node, resource_id, metric_type, value, labels, ` + strings.Join(seriesColumns, ", ") + ` FROM metrics
+        UNION ALL BY NAME
+        SELECT * FROM read_parquet([` + strings.Join(files, ", ") + `], union_by_name = true))) AS m`
+}
+
+// seriesColumns tell apart the series of one node or resource and key
+var seriesColumns = []string{"type", "device", "container_id", "volume"}
+
+// coalesced selects columns with '' in place of NULL
+func coalesced(columns []string) string {
+	parts := make([]string, len(columns))
+	for i, column := range columns {
+		parts[i] = "coalesce(" + column + ", '') AS " + column
+	}
+	return strings.Join(parts, ", ")
+}
+
+// exportColumns are the columns of QueryMetrics and ExportParquet rows; an
+// export names the metric by key, as agents send it
+const exportColumns = "time, node, resource_id, type, metric_type AS key, device, container_id, volume, value"
+
+// QueryMetrics returns rows of exportColumns in RowKey order. Rows are
+// produced lazily, so callers can stream them.
+func (s *DuckDBStore) QueryMetrics(f MetricFilter, segments []string) (*sql.Rows, error) {
+	where, args := f.where()
+	query := "SELECT " + exportColumns + " FROM " + metricsSource(segments) + " " + where + " " + rowOrder
+	return s.db.Query(query, args...)
+}
+
+// PageEnd returns the key of the n-th row QueryMetrics would return, or
+// nil if it returns fewer; with it as Through, a page holds n rows, more
+// only when rows share the last one's key.
+func (s *DuckDBStore) PageEnd(f MetricFilter, n int, segments []string) (*RowKey, error) {
+	where, args := f.where()
+	query := "SELECT epoch_us(time), " + strings.Join(keyColumns, ", ") + " FROM " + metricsSource(segments) + " " + where + " " +
+		rowOrder + fmt.Sprintf(" LIMIT 1 OFFSET %d", n-1)
+
+	var k RowKey
+	err := s.db.QueryRow(query, args...).Scan(&k.TimeMicros, &k.Node, &k.ResourceID, &k.MetricType, &k.Type, &k.Device, &k.ContainerID, &k.Volume)
+	if err == sql.ErrNoRows {
+		return nil, nil
+	}
+	if err != nil {
+		return nil, err
+	}
+	return &k, nil
+}
+
+// QueryBuckets averages each series' samples per step-long bucket and
+// returns rows (bucket, resource_id, metric_type, value) ordered by bucket,
+// with the averages of a resource's containers and volumes summed
+func (s *DuckDBStore) QueryBuckets(f MetricFilter, step time.Duration, segments []string) (*sql.Rows, error) {
+	where, args := f.where()
+	query := fmt.Sprintf(
+		"SELECT bucket, resource_id, metric_type, sum(value) FROM ("+
+			"SELECT time_bucket(INTERVAL '%d seconds', time) AS bucket, resource_id, metric_type, avg(value) AS value FROM %s %s"+
+			" GROUP BY bucket, resource_id, metric_type, %s) GROUP BY bucket, resource_id, metric_type ORDER BY bucket",
+		int64(step.Seconds()), metricsSource(segments), where, strings.Join(seriesColumns, ", "),
+	)
+	return s.db.Query(query, args...)
+}
+
+// QuerySeries returns rows (time as unix milliseconds, node, resource_id,
+// type, device, container_id, volume, labels, value) ordered by series and
+// time, a series being all but time, labels and value. With a resolution
+// over a second, each series has only its latest row per resolution-long
+// bucket.
+func (s *DuckDBStore) QuerySeries(f MetricFilter, resolution time.Duration, segments []string) (*sql.Rows, error) {
+	where, args := f.where()
+	series := "node, resource_id, " + strings.Join(seriesColumns, ", ")
+	query := "SELECT epoch_ms(time) AS t, " + series + ", labels, value FROM " + metricsSource(segments) + " " + where +
+		" ORDER BY " + series + ", t"
+	if secs := int64(resolution.Seconds()); secs > 1 {
+		query = fmt.Sprintf(
+			"SELECT max(epoch_ms(time)) AS t, %[1]s, arg_max(labels, time), arg_max(value, time) FROM %[2]s %[3]s"+
+				" GROUP BY %[1]s, CAST(epoch(time) AS BIGINT) // %[4]d ORDER BY %[1]s, t",
+			series, metricsSource(segments), where, secs,
+		)
+	}
+	return s.db.Query(query, args...)
+}
+
+// QueryPresence returns the distinct (bucket, node, resource_id) that have
+// rows, with buckets as unix seconds aligned to step
+func (s *DuckDBStore) QueryPresence(f MetricFilter, step time.Duration, segments []string) (*sql.Rows, error) {
+	where, args := f.where()
+	secs := int64(step.Seconds())
+	query := fmt.Sprintf(
+		"SELECT DISTINCT CAST(epoch(time) AS BIGINT) // %d * %d AS bucket, node, resource_id FROM %s %s ORDER BY bucket",
+		secs, secs, metricsSource(segments), where,
+	)
+	return s.db.Query(query, args...)
+}
+
+// ExportParquet writes the rows matching the filter to a Parquet file at path
+func (s *DuckDBStore) ExportParquet(f MetricFilter, segments []string, path string) error {
+	query := fmt.Sprintf(
+		"COPY (SELECT %s FROM %s %s %s) TO %s (FORMAT PARQUET)",
+		exportColumns, metricsSource(segments), f.literalWhere(), rowOrder, quoteLiteral(path),
+	)
+	_, err := s.db.Exec(query)
+	return err
+}
+
+// RowsByNode counts the local rows of each node
+func (s *DuckDBStore) RowsByNode() (map[string]int64, error) {
+	rows, err := s.db.Query("SELECT node, count(*) FROM metrics GROUP BY node")
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	counts := map[string]int64{}
+	for rows.Next() {
+		var node string
+		var n int64
+		if err := rows.Scan(&node, &n); err != nil {
+			return nil, err
+		}
+		counts[node] = n
+	}
+	return counts, rows.Err()
+}
+
+// DeleteMetrics removes local rows matching the filter and returns how many
+func (s *DuckDBStore) DeleteMetrics(f MetricFilter) (int64, error) {
+	where, args := f.where()
+	defer s.version.Add(1)
+	res, err := s.db.Exec("DELETE FROM metrics "+where, args...)
+	if err != nil {
+		return 0, err
+	}
+	return res.RowsAffected()
+}
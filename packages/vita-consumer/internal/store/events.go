@@ -0,0 +1,105 @@
+package store
+
+import "strings"
+
+// Event is one observation of a Kubernetes event: the involved object,
+// why it happened, and how often it has repeated so far
+type Event struct {
+	UID       string `json:"uid"`
+	Count     int64  `json:"count"`
+	Namespace string `json:"namespace"`
+	Kind      string `json:"kind"`
+	Name      string `json:"name"`
+	Reason    string `json:"reason"`
+	Message   string `json:"message"`
+	Type      string `json:"type"` // "Normal" or "Warning"
+	Source    string `json:"source"`
+	FirstSeen int64  `json:"first_seen"` // unix seconds
+	LastSeen  int64  `json:"last_seen"`
+}
+
+// InsertEvents stores events, ignoring (uid, count) pairs already stored
+// so a new leader re-sending recent events does not duplicate them
+func (s *SQLiteStore) InsertEvents(events []Event) error {
+	tx, err := s.db.Begin()
+	if err != nil {
+		return err
+	}
+	defer tx.Rollback()
+
+	stmt, err := tx.Prepare(`
+    INSERT OR IGNORE INTO events
+        (uid, count, namespace, kind, name, reason, message, type, source, first_seen, last_seen)
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)`)
+	if err != nil {
+		return err
+	}
+	defer stmt.Close()
+
+	for _, e := range events {
+		if _, err := stmt.Exec(e.UID, e.Count, e.Namespace, e.Kind, e.Name, e.Reason,
+			e.Message, e.Type, e.Source, e.FirstSeen, e.LastSeen); err != nil {
+			return err
+		}
+	}
+	return tx.Commit()
+}
+
+// EventFilter narrows ListEvents; zero values match everything
+type EventFilter struct {
+	Namespace string
+	Kind      string
+	Name      string
+	Type      string
+	Since     int64
+	Until     int64
+	Limit     int
+}
+
+// ListEvents returns matching events, most recent first
+func (s *SQLiteStore) ListEvents(f EventFilter) ([]Event, error) {
+	where := []string{"1=1"}
+	args := []interface{}{}
+	for _, c := range []struct {
+		column string
+		value  string
+	}{{"namespace", f.Namespace}, {"kind", f.Kind}, {"name", f.Name}, {"type", f.Type}} {
+		if c.value != "" {
+			where = append(where, c.column+" = ?")
+			args = append(args, c.value)
+		}
+	}
+	if f.Since > 0 {
+		where = append(where, "last_seen >= ?")
+		args = append(args, f.Since)
+	}
+	if f.Until > 0 {
+		where = append(where, "last_seen <= ?")
+		args = append(args, f.Until)
+	}
+	limit := f.Limit
+	if limit <= 0 || limit > 1000 {
+		limit = 1000
+	}
+	args = append(args, limit)
+
+	rows, err := s.db.Query(`
+		SELECT uid, count, namespace, kind, name, reason, message, type, source, first_seen, last_seen
+		FROM events WHERE `+strings.Join(where, " AND ")+`
+		ORDER BY last_seen DESC, id DESC LIMIT ?`, args...)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	events := []Event{}
+	for rows.Next() {
+		var e Event
+		if err := rows.Scan(&e.UID, &e.Count, &e.Namespace, &e.Kind, &e.Name, &e.Reason,
+			&e.Message, &e.Type, &e.Source, &e.FirstSeen, &e.LastSeen); err != nil {
+			return nil, err
+		}
+		events = append(events, e)
+	}
+	return events, rows.Err()
+}
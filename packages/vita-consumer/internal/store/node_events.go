@@ -0,0 +1,108 @@
+package store
+
+import "strings"
+
+// NodeEvent is a state change an agent saw on its node: a container
+// appearing or going away, an OOM kill, a volume mount, a link going down
+type NodeEvent struct {
+	Node        string `json:"node"`
+	Timestamp   int64  `json:"ts"` // unix seconds
+	Type        string `json:"type"`
+	PodID       string `json:"pod_id,omitempty"`
+	PodUID      string `json:"pod_uid,omitempty"`
+	Volume      string `json:"volume,omitempty"`
+	ContainerID string `json:"container_id,omitempty"`
+	Device      string `json:"device,omitempty"`
+	Message     string `json:"message,omitempty"`
+}
+
+// InsertNodeEvents stores the events one agent sent for its node
+func (s *SQLiteStore) InsertNodeEvents(node string, events []NodeEvent) error {
+	tx, err := s.db.Begin()
+	if err != nil {
+		return err
+	}
+	defer tx.Rollback()
+
+	stmt, err := tx.Prepare(`
+    INSERT INTO node_events
+        (node, ts, type, pod_id, pod_uid, volume, container_id, device, message)
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)`)
+	if err != nil {
+		return err
+	}
+	defer stmt.Close()
+
+	for _, e := range events {
+		if _, err := stmt.Exec(node, e.Timestamp, e.Type, e.PodID, e.PodUID, e.Volume,
+			e.ContainerID, e.Device, e.Message); err != nil {
+			return err
+		}
+	}
+	return tx.Commit()
+}
+
+// NodeEventFilter narrows ListNodeEvents; zero values match everything
+type NodeEventFilter struct {
+	Node   string
+	Nodes  []string // any of these, e.g. a tenant's nodes
+	Type   string
+	PodUID string
+	Since  int64
+	Until  int64
+	Limit  int
+}
+
+// ListNodeEvents returns matching node events, most recent first
+func (s *SQLiteStore) ListNodeEvents(f NodeEventFilter) ([]NodeEvent, error) {
+	where := []string{"1=1"}
+	args := []interface{}{}
+	for _, c := range []struct {
+		column string
+		value  string
+	}{{"node", f.Node}, {"type", f.Type}, {"pod_uid", f.PodUID}} {
+		if c.value != "" {
+			where = append(where, c.column+" = ?")
+			args = append(args, c.value)
+		}
+	}
+	if f.Nodes != nil {
+		where = append(where, "node IN (NULL"+strings.Repeat(", ?", len(f.Nodes))+")")
+		for _, node := range f.Nodes {
+			args = append(args, node)
+		}
+	}
+	if f.Since > 0 {
+		where = append(where, "ts >= ?")
+		args = append(args, f.Since)
+	}
+	if f.Until > 0 {
+		where = append(where, "ts <= ?")
+		args = append(args, f.Until)
+	}
+	limit := f.Limit
+	if limit <= 0 || limit > 1000 {
+		limit = 1000
+	}
+	args = append(args, limit)
+
+	rows, err := s.db.Query(`
+		SELECT node, ts, type, pod_id, pod_uid, volume, container_id, device, message
+		FROM node_events WHERE `+strings.Join(where, " AND ")+`
+		ORDER BY ts DESC, id DESC LIMIT ?`, args...)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	events := []NodeEvent{}
+	for rows.Next() {
+		var e NodeEvent
+		if err := rows.Scan(&e.Node, &e.Timestamp, &e.Type, &e.PodID, &e.PodUID, &e.Volume,
+			&e.ContainerID, &e.Device, &e.Message); err != nil {
+			return nil, err
+		}
+		events = append(events, e)
+	}
+	return events, rows.Err()
+}
@@ -0,0 +1,123 @@
+package store
+
+import (
+	"database/sql"
+	"fmt"
+	"strings"
+	"time"
+)
+
+// Segment is one compacted time range stored in object storage
+type Segment struct {
+	Key   string
+	Start time.Time
+	End   time.Time
+}
+
+// RawHoursBefore lists the hour buckets that still hold raw rows older than cutoff
+func (s *DuckDBStore) RawHoursBefore(cutoff time.Time) ([]time.Time, error) {
+	rows, err := s.db.Query(`
+		SELECT DISTINCT date_trunc('hour', time) AS hour
+		FROM metrics
+		WHERE time < ? AND agg_type = 'raw'
+		ORDER BY hour`, cutoff)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	hours := []time.Time{}
+	for rows.Next() {
+		var h time.Time
+		if err := rows.Scan(&h); err != nil {
+			return nil, err
+		}
+		hours = append(hours, h)
+	}
+	return hours, rows.Err()
+}
+
+// ExportRollup writes 1-minute averages of the rows in [start, end) to a
+// Parquet file, one row per minute, series and agg_type. Its columns are
+// time, node, resource_id, metric_type, the series columns, agg_type and
+// value; node labels and read offsets are not kept.
+//
+// The export runs in a transaction that stays open until the Rollup is
+// deleted or discarded, so the delete removes exactly the rows exported.
+// Rows written to the hour meanwhile (e.g. by a backfill) stay local for
+// the next upload.
+func (s *DuckDBStore) ExportRollup(start, end time.Time, path string) (*Rollup, error) {
+	query := fmt.Sprintf(`
+		COPY (
+			SELECT time_bucket(INTERVAL '1 minute', time) AS time, node, resource_id, metric_type, %s, agg_type, avg(value) AS value
+			FROM metrics
+			WHERE time >= to_timestamp(%d) AND time < to_timestamp(%d)
+			GROUP BY ALL
+			ORDER BY time
+		) TO %s (FORMAT PARQUET)`,
+		strings.Join(seriesColumns, ", "), start.Unix(), end.Unix(), quoteLiteral(path),
+	)
+	tx, err := s.db.Begin()
+	if err != nil {
+		return nil, err
+	}
+	if _, err := tx.Exec(query); err != nil {
+		tx.Rollback()
+		return nil, err
+	}
+	return &Rollup{s: s, tx: tx, start: start, end: end}, nil
+}
+
+// Rollup is an exported hour whose local rows are yet to be deleted
+type Rollup struct {
+	s          *DuckDBStore
+	tx         *sql.Tx
+	start, end time.Time
+}
+
+// Delete removes the local rows the rollup was exported from
+func (r *Rollup) Delete() error {
+	defer r.s.version.Add(1)
+	if _, err := r.tx.Exec("DELETE FROM metrics WHERE time >= ? AND time < ?", r.start, r.end); err != nil {
+		r.tx.Rollback()
+		return err
+	}
+	return r.tx.Commit()
+}
+
+// Discard keeps the local rows, e.g. when the upload failed. It is a no-op
+// after Delete.
+func (r *Rollup) Discard() {
+	r.tx.Rollback()
+}
+
+func (s *DuckDBStore) AddSegment(seg Segment) error {
+	defer s.version.Add(1)
+	_, err := s.db.Exec(`INSERT INTO segments (object_key, start_time, end_time) VALUES (?, ?, ?)
+		ON CONFLICT (object_key) DO UPDATE SET start_time = excluded.start_time, end_time = excluded.end_time`,
+		seg.Key, seg.Start, seg.End)
+	return err
+}
+
+// SegmentsOverlapping returns the segments intersecting [start, end)
+func (s *DuckDBStore) SegmentsOverlapping(start, end time.Time) ([]Segment, error) {
+	rows, err := s.db.Query(`
+		SELECT object_key, start_time, end_time
+		FROM segments
+		WHERE start_time < ? AND end_time > ?
+		ORDER BY start_time`, end, start)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	segments := []Segment{}
+	for rows.Next() {
+		var seg Segment
+		if err := rows.Scan(&seg.Key, &seg.Start, &seg.End); err != nil {
+			return nil, err
+		}
+		segments = append(segments, seg)
+	}
+	return segments, rows.Err()
+}
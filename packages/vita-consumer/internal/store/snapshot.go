@@ -0,0 +1,38 @@
+package store
+
+import (
+	"context"
+	"fmt"
+)
+
+// Snapshot writes a consistent copy of the database to a new file at path,
+// while reads and writes go on
+func (s *SQLiteStore) Snapshot(path string) error {
+	_, err := s.db.Exec("VACUUM INTO " + quoteLiteral(path))
+	return err
+}
+
+// Snapshot copies every table, as of one transaction, into a new DuckDB
+// file at path. Rows still in the ring buffer aren't included.
+func (s *DuckDBStore) Snapshot(path string) error {
+	ctx := context.Background()
+	// The snapshot stays attached between statements, so they share a connection
+	conn, err := s.db.Conn(ctx)
+	if err != nil {
+		return err
+	}
+	defer conn.Close()
+
+	var current string
+	if err := conn.QueryRowContext(ctx, "SELECT current_database()").Scan(&current); err != nil {
+		return err
+	}
+	if _, err := conn.ExecContext(ctx, "ATTACH "+quoteLiteral(path)+" AS snapshot"); err != nil {
+		return err
+	}
+	_, err = conn.ExecContext(ctx, fmt.Sprintf(`COPY FROM DATABASE "%s" TO snapshot`, current))
+	if _, detachErr := conn.ExecContext(ctx, "DETACH snapshot"); err == nil {
+		err = detachErr
+	}
+	return err
+}
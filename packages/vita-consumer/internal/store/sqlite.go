@@ -3,12 +3,15 @@ package store
 import (
 	"database/sql"
 	"fmt"
+	"sync/atomic"
 
 	_ "github.com/mattn/go-sqlite3"
 )
 
 type SQLiteStore struct {
 	db *sql.DB
+	// Bumped when a heartbeat lands in a new minute
+	heartbeats atomic.Uint64
 }
 
 func NewSQLiteStore(path string) (*SQLiteStore, error) {
@@ -106,7 +109,66 @@ func initSchema(db *sql.DB) error {
             FOREIGN KEY(namespace_id) REFERENCES namespaces(id)
         );`,
 
+		// Kubernetes events; one row per (event, count) so repeats keep history
+		`CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            uid TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            namespace TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            name TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            message TEXT NOT NULL,
+            type TEXT NOT NULL,
+            source TEXT NOT NULL,
+            first_seen INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL,
+            UNIQUE(uid, count)
+        );`,
+
+		// State changes agents saw on their nodes
+		`CREATE TABLE IF NOT EXISTS node_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            node TEXT NOT NULL,
+            ts INTEGER NOT NULL,
+            type TEXT NOT NULL,
+            pod_id TEXT NOT NULL,
+            pod_uid TEXT NOT NULL,
+            volume TEXT NOT NULL,
+            container_id TEXT NOT NULL,
+            device TEXT NOT NULL,
+            message TEXT NOT NULL
+        );`,
+
+		// Latest heartbeat of each node's agent
+		`CREATE TABLE IF NOT EXISTS agents (
+            node TEXT PRIMARY KEY,
+            version TEXT NOT NULL,
+            collectors TEXT NOT NULL,
+            config_hash TEXT NOT NULL,
+            uptime_secs INTEGER NOT NULL,
+            interval_secs INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL
+        );`,
+
+		// Minutes each agent sent a heartbeat in, for telling a silent agent
+		// from one whose collection failed
+		`CREATE TABLE IF NOT EXISTS heartbeat_minutes (
+            node TEXT NOT NULL,
+            minute INTEGER NOT NULL,
+            PRIMARY KEY(node, minute)
+        );`,
+
+		// Label sets of recording rule results; metric rows refer to them by
+		// negated ID, so they never collide with pod IDs
+		`CREATE TABLE IF NOT EXISTS recorded_series (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            labels TEXT UNIQUE NOT NULL
+        );`,
+
 		// Indexes
+		`CREATE INDEX IF NOT EXISTS idx_events_last_seen ON events(last_seen);`,
+		`CREATE INDEX IF NOT EXISTS idx_node_events_node_ts ON node_events(node, ts);`,
 		`CREATE INDEX IF NOT EXISTS idx_pods_uid ON pods(uid);`,
 		`CREATE INDEX IF NOT EXISTS idx_pvcs_uid ON pvcs(uid);`,
 	}
@@ -206,6 +268,85 @@ func (s *SQLiteStore) GetResourceID(table, uid string) (int64, error) {
 	return id, err
 }
 
+// PodIDsInNamespace returns the IDs of all known pods in a namespace
+func (s *SQLiteStore) PodIDsInNamespace(namespace string) ([]int64, error) {
+	rows, err := s.db.Query(`
+		SELECT p.id FROM pods p
+		JOIN namespaces ns ON p.namespace_id = ns.id
+		WHERE ns.name = ?`, namespace)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	ids := []int64{}
+	for rows.Next() {
+		var id int64
+		if err := rows.Scan(&id); err != nil {
+			return nil, err
+		}
+		ids = append(ids, id)
+	}
+	return ids, rows.Err()
+}
+
+// PodRef names a pod
+type PodRef struct {
+	Name      string
+	Namespace string
+}
+
+// PodRefs returns the name and namespace of every known pod by ID
+func (s *SQLiteStore) PodRefs() (map[int64]PodRef, error) {
+	rows, err := s.db.Query(`
+		SELECT p.id, p.name, ns.name FROM pods p
+		JOIN namespaces ns ON p.namespace_id = ns.id`)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	refs := map[int64]PodRef{}
+	for rows.Next() {
+		var id int64
+		var ref PodRef
+		if err := rows.Scan(&id, &ref.Name, &ref.Namespace); err != nil {
+			return nil, err
+		}
+		refs[id] = ref
+	}
+	return refs, rows.Err()
+}
+
+// RecordedSeriesID returns the ID of a recorded label set, given as JSON
+func (s *SQLiteStore) RecordedSeriesID(labels string) (int64, error) {
+	query := `INSERT INTO recorded_series (labels) VALUES (?)
+              ON CONFLICT(labels) DO UPDATE SET labels=labels RETURNING id`
+	var id int64
+	err := s.db.QueryRow(query, labels).Scan(&id)
+	return id, err
+}
+
+// RecordedSeries returns every recorded label set by ID
+func (s *SQLiteStore) RecordedSeries() (map[int64]string, error) {
+	rows, err := s.db.Query("SELECT id, labels FROM recorded_series")
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	series := map[int64]string{}
+	for rows.Next() {
+		var id int64
+		var labels string
+		if err := rows.Scan(&id, &labels); err != nil {
+			return nil, err
+		}
+		series[id] = labels
+	}
+	return series, rows.Err()
+}
+
 // Query executes a SQL query and returns rows
 func (s *SQLiteStore) Query(query string, args ...interface{}) (*sql.Rows, error) {
 	return s.db.Query(query, args...)
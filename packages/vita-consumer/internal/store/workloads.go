@@ -0,0 +1,97 @@
+package store
+
+import (
+	"fmt"
+	"strings"
+)
+
+// Workload is a Deployment, StatefulSet or DaemonSet with the IDs of the
+// pods the syncer linked to it, past pods included, so usage from before
+// a rollout still adds up under the workload.
+type Workload struct {
+	Kind      string  `json:"kind"` // deployment, statefulset or daemonset
+	Namespace string  `json:"namespace"`
+	Name      string  `json:"name"`
+	PodIDs    []int64 `json:"pod_ids"`
+}
+
+// Key names the workload as <kind>/<namespace>/<name>, as the agent's
+// workload metrics do
+func (w Workload) Key() string {
+	return w.Kind + "/" + w.Namespace + "/" + w.Name
+}
+
+// ParseWorkloadKey splits <kind>/<namespace>/<name>
+func ParseWorkloadKey(key string) (kind, namespace, name string, err error) {
+	parts := strings.SplitN(key, "/", 3)
+	if len(parts) != 3 || parts[0] == "" || parts[1] == "" || parts[2] == "" {
+		return "", "", "", fmt.Errorf("workload must be <kind>/<namespace>/<name>, got %q", key)
+	}
+	switch parts[0] {
+	case "deployment", "statefulset", "daemonset":
+	default:
+		return "", "", "", fmt.Errorf("workload kind must be deployment, statefulset or daemonset, got %q", parts[0])
+	}
+	return parts[0], parts[1], parts[2], nil
+}
+
+// WorkloadFilter narrows Workloads; zero values match everything
+type WorkloadFilter struct {
+	Kind      string
+	Namespace string
+	Name      string
+}
+
+// Workloads returns workloads with at least one known pod, ordered by key
+func (s *SQLiteStore) Workloads(f WorkloadFilter) ([]Workload, error) {
+	where := []string{"1=1"}
+	args := []interface{}{}
+	for _, c := range []struct {
+		column string
+		value  string
+	}{{"kind", f.Kind}, {"namespace", f.Namespace}, {"name", f.Name}} {
+		if c.value != "" {
+			where = append(where, c.column+" = ?")
+			args = append(args, c.value)
+		}
+	}
+
+	rows, err := s.db.Query(`
+		SELECT kind, namespace, name, pod_id FROM (
+			SELECT 'deployment' AS kind, ns.name AS namespace, d.name AS name, p.id AS pod_id
+			FROM pods p
+			JOIN deployments d ON p.deployment_id = d.id
+			JOIN namespaces ns ON d.namespace_id = ns.id
+			UNION ALL
+			SELECT 'statefulset', ns.name, st.name, p.id
+			FROM pods p
+			JOIN statefulsets st ON p.statefulset_id = st.id
+			JOIN namespaces ns ON st.namespace_id = ns.id
+			UNION ALL
+			SELECT 'daemonset', ns.name, ds.name, p.id
+			FROM pods p
+			JOIN daemonsets ds ON p.daemonset_id = ds.id
+			JOIN namespaces ns ON ds.namespace_id = ns.id
+		) WHERE `+strings.Join(where, " AND ")+`
+		ORDER BY kind, namespace, name, pod_id`, args...)
+	if err != nil {
+		return nil, err
+	}
+	defer rows.Close()
+
+	workloads := []Workload{}
+	for rows.Next() {
+		var w Workload
+		var podID int64
+		if err := rows.Scan(&w.Kind, &w.Namespace, &w.Name, &podID); err != nil {
+			return nil, err
+		}
+		if n := len(workloads); n > 0 && workloads[n-1].Key() == w.Key() {
+			workloads[n-1].PodIDs = append(workloads[n-1].PodIDs, podID)
+			continue
+		}
+		w.PodIDs = []int64{podID}
+		workloads = append(workloads, w)
+	}
+	return workloads, rows.Err()
+}
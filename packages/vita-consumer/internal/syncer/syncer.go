@@ -35,6 +35,8 @@ type ResourceSyncer struct {
 	nodes map[string]int64
 	// ReplicaSet UID -> Deployment ID (for Pod->Deployment resolution)
 	replicaSets map[string]int64
+	// Pod/PVC UID -> namespace name
+	namespaceOf map[string]string
 }
 
 func NewResourceSyncer(kubeConfigPath string, sqlite *store.SQLiteStore) (*ResourceSyncer, error) {
@@ -56,6 +58,7 @@ func NewResourceSyncer(kubeConfigPath string, sqlite *store.SQLiteStore) (*Resou
 		namespaces:  make(map[string]int64),
 		nodes:       make(map[string]int64),
 		replicaSets: make(map[string]int64),
+		namespaceOf: make(map[string]string),
 	}, nil
 }
 
@@ -269,6 +272,7 @@ func (s *ResourceSyncer) syncPod(pod *corev1.Pod) {
 
 	s.mu.Lock()
 	s.pods[uid] = id
+	s.namespaceOf[uid] = pod.Namespace
 	s.mu.Unlock()
 }
 
@@ -284,6 +288,7 @@ func (s *ResourceSyncer) syncPVC(pvc *corev1.PersistentVolumeClaim) {
 
 	s.mu.Lock()
 	s.pvcs[uid] = id
+	s.namespaceOf[uid] = pvc.Namespace
 	s.mu.Unlock()
 }
 
@@ -298,3 +303,11 @@ func (s *ResourceSyncer) GetResourceID(uid, rType string) (int64, bool) {
 	id, ok := s.pods[uid]
 	return id, ok
 }
+
+func (s *ResourceSyncer) GetNamespace(uid string) (string, bool) {
+	s.mu.RLock()
+	defer s.mu.RUnlock()
+
+	ns, ok := s.namespaceOf[uid]
+	return ns, ok
+}
@@ -0,0 +1,283 @@
+// Package tenant lets several clusters or teams share a consumer. Each
+// tenant has its own token and limits: how many active series its agents
+// may send, how much storage its nodes may take and how many API queries it
+// may run at once. A node belongs to the first tenant that sends data for
+// it, so tenants can neither write to nor read each other's nodes.
+package tenant
+
+import (
+	"crypto/subtle"
+	"encoding/json"
+	"log"
+	"path"
+	"sort"
+	"sync"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/secret"
+)
+
+// Series not seen for this long no longer count towards a tenant's limit
+const seriesTTL = 10 * time.Minute
+
+// Limits of one tenant; zero means unlimited
+type Limits struct {
+	MaxSeries            int   `json:"max_series,omitempty"`
+	MaxStorageMB         int64 `json:"max_storage_mb,omitempty"`
+	MaxConcurrentQueries int   `json:"max_concurrent_queries,omitempty"`
+}
+
+// Config is one entry of the tenants file, a JSON array
+type Config struct {
+	Name  string `json:"name"`
+	Token string `json:"token"`
+	// Node name patterns (path.Match syntax) the tenant may send for; any if empty
+	Nodes []string `json:"nodes,omitempty"`
+	Limits
+}
+
+// Tenant is a configured tenant and what it uses
+type Tenant struct {
+	Name string
+
+	mu        sync.Mutex
+	token     string
+	patterns  []string
+	limits    Limits
+	series    map[any]time.Time
+	lastSweep time.Time
+	storage   int64
+	queries   int
+}
+
+// Limits returns the tenant's current limits
+func (t *Tenant) Limits() Limits {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	return t.limits
+}
+
+// AdmitSeries records a series sent by the tenant; false means it's new and
+// the tenant is at its series limit, so its metrics should be dropped
+func (t *Tenant) AdmitSeries(key any) bool {
+	now := time.Now()
+	t.mu.Lock()
+	defer t.mu.Unlock()
+
+	if now.Sub(t.lastSweep) > time.Minute {
+		for k, seen := range t.series {
+			if now.Sub(seen) > seriesTTL {
+				delete(t.series, k)
+			}
+		}
+		t.lastSweep = now
+	}
+	if _, ok := t.series[key]; !ok && t.limits.MaxSeries > 0 && len(t.series) >= t.limits.MaxSeries {
+		return false
+	}
+	t.series[key] = now
+	return true
+}
+
+// OverStorage reports whether the tenant's nodes take more than its quota
+func (t *Tenant) OverStorage() bool {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	return t.limits.MaxStorageMB > 0 && t.storage > t.limits.MaxStorageMB<<20
+}
+
+// AcquireQuery takes one of the tenant's query slots; release returns it.
+// ok is false while all slots are taken.
+func (t *Tenant) AcquireQuery() (release func(), ok bool) {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	if t.limits.MaxConcurrentQueries > 0 && t.queries >= t.limits.MaxConcurrentQueries {
+		return nil, false
+	}
+	t.queries++
+	return func() {
+		t.mu.Lock()
+		t.queries--
+		t.mu.Unlock()
+	}, true
+}
+
+// Registry holds the tenants from a file, re-read when it changes
+type Registry struct {
+	source *secret.Value
+
+	mu      sync.Mutex
+	raw     string
+	tenants []*Tenant
+	owners  map[string]*Tenant // node -> tenant
+}
+
+// FromEnv loads tenants from the file named by TENANTS_FILE (or the
+// TENANTS variable), or returns nil if neither is set
+func FromEnv() (*Registry, error) {
+	source := secret.FromEnv("TENANTS")
+	if source == nil {
+		return nil, nil
+	}
+	r := &Registry{source: source, owners: make(map[string]*Tenant)}
+	if err := r.load(source.Get()); err != nil {
+		return nil, err
+	}
+	return r, nil
+}
+
+// current reloads the tenants if the file changed; callers hold r.mu. An
+// invalid file keeps the previous tenants.
+func (r *Registry) current() []*Tenant {
+	if raw := r.source.Get(); raw != r.raw {
+		if err := r.load(raw); err != nil {
+			log.Printf("Ignoring invalid tenants file: %v", err)
+			r.raw = raw
+		}
+	}
+	return r.tenants
+}
+
+// load applies a tenants file; tenants keep their usage across reloads
+func (r *Registry) load(raw string) error {
+	var configs []Config
+	if err := json.Unmarshal([]byte(raw), &configs); err != nil {
+		return err
+	}
+	byName := make(map[string]*Tenant, len(r.tenants))
+	for _, t := range r.tenants {
+		byName[t.Name] = t
+	}
+
+	tenants := make([]*Tenant, 0, len(configs))
+	for _, c := range configs {
+		if c.Name == "" || c.Token == "" {
+			continue
+		}
+		t, ok := byName[c.Name]
+		if !ok {
+			t = &Tenant{Name: c.Name, series: make(map[any]time.Time), lastSweep: time.Now()}
+		}
+		t.mu.Lock()
+		t.token, t.patterns, t.limits = c.Token, c.Nodes, c.Limits
+		t.mu.Unlock()
+		tenants = append(tenants, t)
+		delete(byName, c.Name)
+	}
+	// Nodes of removed tenants can be claimed again
+	for node, t := range r.owners {
+		if _, removed := byName[t.Name]; removed {
+			delete(r.owners, node)
+		}
+	}
+
+	r.raw = raw
+	r.tenants = tenants
+	return nil
+}
+
+// Lookup returns the tenant a token belongs to, or nil
+func (r *Registry) Lookup(token string) *Tenant {
+	if token == "" {
+		return nil
+	}
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	var found *Tenant
+	for _, t := range r.current() {
+		t.mu.Lock()
+		if subtle.ConstantTimeCompare([]byte(token), []byte(t.token)) == 1 {
+			found = t
+		}
+		t.mu.Unlock()
+	}
+	return found
+}
+
+// Claim gives node to t unless another tenant has it or t's node patterns
+// exclude it, and reports whether t may send for node
+func (r *Registry) Claim(t *Tenant, node string) bool {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	if owner, ok := r.owners[node]; ok {
+		return owner == t
+	}
+
+	t.mu.Lock()
+	allowed := len(t.patterns) == 0
+	for _, p := range t.patterns {
+		if ok, _ := path.Match(p, node); ok {
+			allowed = true
+			break
+		}
+	}
+	t.mu.Unlock()
+	if allowed {
+		r.owners[node] = t
+	}
+	return allowed
+}
+
+// Nodes returns the nodes t has claimed, sorted
+func (r *Registry) Nodes(t *Tenant) []string {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	nodes := []string{}
+	for node, owner := range r.owners {
+		if owner == t {
+			nodes = append(nodes, node)
+		}
+	}
+	sort.Strings(nodes)
+	return nodes
+}
+
+// UpdateStorage sets each tenant's storage to the total of its nodes
+func (r *Registry) UpdateStorage(bytesByNode map[string]int64) {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	totals := map[*Tenant]int64{}
+	for node, bytes := range bytesByNode {
+		if t, ok := r.owners[node]; ok {
+			totals[t] += bytes
+		}
+	}
+	for _, t := range r.current() {
+		t.mu.Lock()
+		t.storage = totals[t]
+		t.mu.Unlock()
+	}
+}
+
+// Usage is what a tenant uses against its limits, for the admin API
+type Usage struct {
+	Name          string   `json:"name"`
+	Nodes         []string `json:"nodes"`
+	Series        int      `json:"series"`
+	StorageBytes  int64    `json:"storage_bytes"`
+	ActiveQueries int      `json:"active_queries"`
+	Limits        Limits   `json:"limits"`
+}
+
+// Usage reports every tenant, in file order
+func (r *Registry) Usage() []Usage {
+	r.mu.Lock()
+	tenants := r.current()
+	r.mu.Unlock()
+
+	usage := make([]Usage, 0, len(tenants))
+	for _, t := range tenants {
+		nodes := r.Nodes(t)
+		t.mu.Lock()
+		usage = append(usage, Usage{
+			Name:          t.Name,
+			Nodes:         nodes,
+			Series:        len(t.series),
+			StorageBytes:  t.storage,
+			ActiveQueries: t.queries,
+			Limits:        t.limits,
+		})
+		t.mu.Unlock()
+	}
+	return usage
+}
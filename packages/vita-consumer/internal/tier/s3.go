@@ -0,0 +1,149 @@
+package tier
+
+import (
+	"bytes"
+	"crypto/hmac"
+	"crypto/sha256"
+	"encoding/hex"
+	"fmt"
+	"io"
+	"net/http"
+	"strings"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/secret"
+)
+
+// S3Client is a minimal client for S3-compatible object storage (AWS S3,
+// MinIO, GCS via its interoperability API). It only supports the calls the
+// tier needs and signs requests with AWS Signature Version 4. Keys are read
+// per request so rotated credentials are picked up.
+type S3Client struct {
+	endpoint  string // e.g. https://s3.amazonaws.com
+	region    string
+	bucket    string
+	accessKey *secret.Value
+	secretKey *secret.Value
+	http      *http.Client
+}
+
+func NewS3Client(endpoint, region, bucket string, accessKey, secretKey *secret.Value) *S3Client {
+	return &S3Client{
+		endpoint:  strings.TrimRight(endpoint, "/"),
+		region:    region,
+		bucket:    bucket,
+		accessKey: accessKey,
+		secretKey: secretKey,
+		http:      &http.Client{Timeout: 5 * time.Minute},
+	}
+}
+
+func (c *S3Client) PutObject(key string, body []byte) error {
+	req, err := c.newRequest(http.MethodPut, key, body)
+	if err != nil {
+		return err
+	}
+	resp, err := c.http.Do(req)
+	if err != nil {
+		return err
+	}
+	defer resp.Body.Close()
+
+	if resp.StatusCode/100 != 2 {
+		msg, _ := io.ReadAll(io.LimitReader(resp.Body, 1024))
+		return fmt.Errorf("put %s: HTTP %d: %s", key, resp.StatusCode, msg)
+	}
+	return nil
+}
+
+// GetObject streams the object into w
+func (c *S3Client) GetObject(key string, w io.Writer) error {
+	req, err := c.newRequest(http.MethodGet, key, nil)
+	if err != nil {
+		return err
+	}
+	resp, err := c.http.Do(req)
+	if err != nil {
+		return err
+	}
+	defer resp.Body.Close()
+
+	if resp.StatusCode/100 != 2 {
+		msg, _ := io.ReadAll(io.LimitReader(resp.Body, 1024))
+		return fmt.Errorf("get %s: HTTP %d: %s", key, resp.StatusCode, msg)
+	}
+	_, err = io.Copy(w, resp.Body)
+	return err
+}
+
+// newRequest builds a signed path-style request for bucket/key
+func (c *S3Client) newRequest(method, key string, body []byte) (*http.Request, error) {
+	canonicalURI := "/" + escapePath(c.bucket) + "/" + escapePath(key)
+	req, err := http.NewRequest(method, c.endpoint+canonicalURI, bytes.NewReader(body))
+	if err != nil {
+		return nil, err
+	}
+
+	sum := sha256.Sum256(body)
+	payloadHash := hex.EncodeToString(sum[:])
+	now := time.Now().UTC()
+	amzDate := now.Format("20060102T150405Z")
+	day := now.Format("20060102")
+
+	req.Header.Set("x-amz-content-sha256", payloadHash)
+	req.Header.Set("x-amz-date", amzDate)
+
+	signedHeaders := "host;x-amz-content-sha256;x-amz-date"
+	canonicalRequest := strings.Join([]string{
+		method,
+		canonicalURI,
+		"", // no query string
+		"host:" + req.URL.Host,
+		"x-amz-content-sha256:" + payloadHash,
+		"x-amz-date:" + amzDate,
+		"",
+		signedHeaders,
+		payloadHash,
+	}, "\n")
+
+	scope := day + "/" + c.region + "/s3/aws4_request"
+	crHash := sha256.Sum256([]byte(canonicalRequest))
+	stringToSign := "AWS4-HMAC-SHA256\n" + amzDate + "\n" + scope + "\n" + hex.EncodeToString(crHash[:])
+
+	signingKey := hmacSHA256([]byte("AWS4"+c.secretKey.Get()), day)
+	signingKey = hmacSHA256(signingKey, c.region)
+	signingKey = hmacSHA256(signingKey, "s3")
+	signingKey = hmacSHA256(signingKey, "aws4_request")
+	signature := hex.EncodeToString(hmacSHA256(signingKey, stringToSign))
+
+	req.Header.Set("Authorization", fmt.Sprintf(
+		"AWS4-HMAC-SHA256 Credential=%s/%s, SignedHeaders=%s, Signature=%s",
+		c.accessKey.Get(), scope, signedHeaders, signature,
+	))
+	return req, nil
+}
+
+func hmacSHA256(key []byte, data string) []byte {
+	mac := hmac.New(sha256.New, key)
+	mac.Write([]byte(data))
+	return mac.Sum(nil)
+}
+
+// escapePath URI-encodes p as SigV4 expects: every byte but the unreserved
+// A-Z a-z 0-9 - _ . ~ and the slashes between segments becomes %XX.
+// url.PathEscape leaves characters such as $&+,;=@: as they are, which S3
+// then signs encoded and answers with SignatureDoesNotMatch.
+func escapePath(p string) string {
+	var b strings.Builder
+	for i := 0; i < len(p); i++ {
+		c := p[i]
+		switch {
+		case 'A' <= c && c <= 'Z', 'a' <= c && c <= 'z', '0' <= c && c <= '9',
+			c == '-', c == '_', c == '.', c == '~', c == '/':
+			b.WriteByte(c)
+		default:
+			fmt.Fprintf(&b, "%%%02X", c)
+		}
+	}
+	return b.String()
+}
@@ -0,0 +1,174 @@
+package tier
+
+import (
+	"context"
+	"fmt"
+	"log"
+	"os"
+	"path/filepath"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/store"
+)
+
+// How long downloaded segments stay in the local read cache
+const cacheTTL = 24 * time.Hour
+
+// Tierer moves raw data older than the local retention window into 1-minute
+// rollup segments in object storage, and fetches them back for old-range reads.
+type Tierer struct {
+	duck      *store.DuckDBStore
+	s3        *S3Client
+	prefix    string
+	retention time.Duration
+	workDir   string
+	cacheDir  string
+}
+
+func NewTierer(duck *store.DuckDBStore, s3 *S3Client, prefix string, retention time.Duration, dataDir string) (*Tierer, error) {
+	t := &Tierer{
+		duck:      duck,
+		s3:        s3,
+		prefix:    prefix,
+		retention: retention,
+		workDir:   filepath.Join(dataDir, "tier", "upload"),
+		cacheDir:  filepath.Join(dataDir, "tier", "cache"),
+	}
+	for _, dir := range []string{t.workDir, t.cacheDir} {
+		if err := os.MkdirAll(dir, 0755); err != nil {
+			return nil, err
+		}
+	}
+	return t, nil
+}
+
+// Run compacts and uploads eligible hours every interval until ctx is done
+func (t *Tierer) Run(ctx context.Context, interval time.Duration) {
+	ticker := time.NewTicker(interval)
+	defer ticker.Stop()
+
+	for {
+		if err := t.compact(); err != nil {
+			log.Printf("Cold tier compaction failed: %v", err)
+		}
+		t.pruneCache()
+
+		select {
+		case <-ctx.Done():
+			return
+		case <-ticker.C:
+		}
+	}
+}
+
+func (t *Tierer) compact() error {
+	cutoff := time.Now().Add(-t.retention).Truncate(time.Hour)
+	hours, err := t.duck.RawHoursBefore(cutoff)
+	if err != nil {
+		return err
+	}
+
+	for _, start := range hours {
+		end := start.Add(time.Hour)
+		if err := t.uploadHour(start, end); err != nil {
+			return fmt.Errorf("hour %s: %w", start.UTC().Format(time.RFC3339), err)
+		}
+	}
+	return nil
+}
+
+func (t *Tierer) uploadHour(start, end time.Time) error {
+	// Hours can be uploaded more than once (e.g. after a backfill), so each
+	// segment gets a unique key and reads union all of them.
+	key := fmt.Sprintf("%s%s-%d.parquet", t.prefix, start.UTC().Format("2006/01/02/15"), time.Now().UnixNano())
+	path := filepath.Join(t.workDir, filepath.Base(key))
+	defer os.Remove(path)
+
+	rollup, err := t.duck.ExportRollup(start, end, path)
+	if err != nil {
+		return err
+	}
+	defer rollup.Discard()
+
+	body, err := os.ReadFile(path)
+	if err != nil {
+		return err
+	}
+	if err := t.s3.PutObject(key, body); err != nil {
+		return err
+	}
+
+	// Record the segment before dropping local rows: a crash in between
+	// leaves duplicated rather than missing data.
+	if err := t.duck.AddSegment(store.Segment{Key: key, Start: start, End: end}); err != nil {
+		return err
+	}
+	if err := rollup.Delete(); err != nil {
+		return err
+	}
+
+	log.Printf("Cold tier: uploaded %s (%d bytes)", key, len(body))
+	return nil
+}
+
+// Fetch makes the segments overlapping [start, end) available locally and
+// returns their paths. It is a no-op for ranges still held in DuckDB.
+func (t *Tierer) Fetch(start, end time.Time) ([]string, error) {
+	segments, err := t.duck.SegmentsOverlapping(start, end)
+	if err != nil {
+		return nil, err
+	}
+
+	paths := make([]string, 0, len(segments))
+	for _, seg := range segments {
+		path := filepath.Join(t.cacheDir, filepath.FromSlash(seg.Key))
+		if info, err := os.Stat(path); err == nil && info.Size() > 0 {
+			now := time.Now()
+			os.Chtimes(path, now, now)
+			paths = append(paths, path)
+			continue
+		}
+
+		if err := t.download(seg.Key, path); err != nil {
+			return nil, err
+		}
+		paths = append(paths, path)
+	}
+	return paths, nil
+}
+
+func (t *Tierer) download(key, path string) error {
+	if err := os.MkdirAll(filepath.Dir(path), 0755); err != nil {
+		return err
+	}
+
+	tmp := path + ".part"
+	f, err := os.Create(tmp)
+	if err != nil {
+		return err
+	}
+	if err := t.s3.GetObject(key, f); err != nil {
+		f.Close()
+		os.Remove(tmp)
+		return err
+	}
+	if err := f.Close(); err != nil {
+		os.Remove(tmp)
+		return err
+	}
+	return os.Rename(tmp, path)
+}
+
+// pruneCache drops cached segments that haven't been read recently
+func (t *Tierer) pruneCache() {
+	cutoff := time.Now().Add(-cacheTTL)
+	filepath.Walk(t.cacheDir, func(path string, info os.FileInfo, err error) error {
+		if err != nil || info.IsDir() {
+			return nil
+		}
+		if info.ModTime().Before(cutoff) {
+			os.Remove(path)
+		}
+		return nil
+	})
+}
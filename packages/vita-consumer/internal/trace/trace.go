@@ -0,0 +1,283 @@
+// Package trace records spans of ingest requests and exports them to an
+// OpenTelemetry collector as OTLP/HTTP JSON. Requests carrying a sampled W3C
+// traceparent from an agent continue that agent's trace.
+package trace
+
+import (
+	"bytes"
+	"context"
+	"crypto/rand"
+	"encoding/binary"
+	"encoding/hex"
+	"encoding/json"
+	"log"
+	"net/http"
+	"os"
+	"strconv"
+	"strings"
+	"sync"
+	"time"
+)
+
+// Spans kept while the collector is unreachable; newer ones are dropped
+const maxPending = 4096
+
+// OTLP enum values
+const (
+	kindInternal = 1
+	kindServer   = 2
+	statusError  = 2
+)
+
+// Tracer buffers finished spans for Run to export. A nil Tracer traces
+// nothing, so callers need no checks.
+type Tracer struct {
+	url      string
+	resource []attribute
+	ratio    float64
+	client   *http.Client
+
+	mu      sync.Mutex
+	pending []spanData
+}
+
+// New exports to url (e.g. http://otel-collector:4318/v1/traces). Requests
+// without a parent are sampled with probability ratio.
+func New(url, service string, ratio float64) *Tracer {
+	host, _ := os.Hostname()
+	res := []attribute{stringAttr("service.name", service), stringAttr("host.name", host)}
+	return &Tracer{
+		url:      url,
+		resource: res,
+		ratio:    ratio,
+		client:   &http.Client{Timeout: 5 * time.Second},
+	}
+}
+
+// Run exports pending spans every interval until ctx is done
+func (t *Tracer) Run(ctx context.Context, interval time.Duration) {
+	ticker := time.NewTicker(interval)
+	defer ticker.Stop()
+
+	for {
+		select {
+		case <-ctx.Done():
+			t.export()
+			return
+		case <-ticker.C:
+			t.export()
+		}
+	}
+}
+
+// StartRequest starts a server span for r, continuing the caller's trace
+// when its traceparent is sampled. Returns nil when the request isn't
+// traced; callers of an unsampled agent cycle aren't sampled here either.
+func (t *Tracer) StartRequest(r *http.Request, name string) *Span {
+	if t == nil {
+		return nil
+	}
+	span := &Span{t: t, name: name, kind: kindServer, start: time.Now()}
+	if header := r.Header.Get("traceparent"); header != "" {
+		traceID, parentID, sampled, ok := parseTraceparent(header)
+		if ok {
+			if !sampled {
+				return nil
+			}
+			span.traceID, span.parentID = traceID, parentID
+		}
+	}
+	if span.traceID == "" {
+		var id [16]byte
+		rand.Read(id[:])
+		if float64(binary.BigEndian.Uint64(id[8:])) >= t.ratio*(1<<64) {
+			return nil
+		}
+		span.traceID = hex.EncodeToString(id[:])
+	}
+	span.spanID = newSpanID()
+	return span
+}
+
+func (t *Tracer) finish(s spanData) {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	if len(t.pending) < maxPending {
+		t.pending = append(t.pending, s)
+	}
+}
+
+func (t *Tracer) export() {
+	t.mu.Lock()
+	spans := t.pending
+	t.pending = nil
+	t.mu.Unlock()
+	if len(spans) == 0 {
+		return
+	}
+
+	body, err := json.Marshal(exportRequest{ResourceSpans: []resourceSpans{{
+		Resource:   resource{Attributes: t.resource},
+		ScopeSpans: []scopeSpans{{Scope: scope{Name: "vita-consumer"}, Spans: spans}},
+	}}})
+	if err != nil {
+		return
+	}
+	resp, err := t.client.Post(t.url, "application/json", bytes.NewReader(body))
+	if err != nil {
+		log.Printf("Trace export failed: %v", err)
+		return
+	}
+	resp.Body.Close()
+	if resp.StatusCode >= 300 {
+		log.Printf("Trace export failed: HTTP %d", resp.StatusCode)
+	}
+}
+
+// Span is one timed operation; all methods are no-ops on a nil Span
+type Span struct {
+	t        *Tracer
+	traceID  string
+	spanID   string
+	parentID string
+	name     string
+	kind     int
+	start    time.Time
+	attrs    []attribute
+	err      string
+}
+
+// Child starts a span for part of the work of s
+func (s *Span) Child(name string) *Span {
+	if s == nil {
+		return nil
+	}
+	return &Span{
+		t:        s.t,
+		traceID:  s.traceID,
+		spanID:   newSpanID(),
+		parentID: s.spanID,
+		name:     name,
+		kind:     kindInternal,
+		start:    time.Now(),
+	}
+}
+
+func (s *Span) SetString(key, value string) {
+	if s != nil {
+		s.attrs = append(s.attrs, stringAttr(key, value))
+	}
+}
+
+func (s *Span) SetInt(key string, value int) {
+	if s != nil {
+		s.attrs = append(s.attrs, attribute{Key: key, Value: anyValue{Int: strconv.Itoa(value)}})
+	}
+}
+
+// SetError marks the span failed; a nil err is ignored
+func (s *Span) SetError(err error) {
+	if s != nil && err != nil {
+		s.err = err.Error()
+	}
+}
+
+func (s *Span) End() {
+	if s == nil {
+		return
+	}
+	data := spanData{
+		TraceID:      s.traceID,
+		SpanID:       s.spanID,
+		ParentSpanID: s.parentID,
+		Name:         s.name,
+		Kind:         s.kind,
+		Start:        strconv.FormatInt(s.start.UnixNano(), 10),
+		End:          strconv.FormatInt(time.Now().UnixNano(), 10),
+		Attributes:   s.attrs,
+	}
+	if s.err != "" {
+		data.Status = &status{Code: statusError, Message: s.err}
+	}
+	s.t.finish(data)
+}
+
+// parseTraceparent reads version-00 headers: 00-<trace>-<parent>-<flags>
+func parseTraceparent(header string) (traceID, parentID string, sampled, ok bool) {
+	parts := strings.Split(strings.TrimSpace(header), "-")
+	if len(parts) != 4 || parts[0] != "00" || len(parts[1]) != 32 || len(parts[2]) != 16 || len(parts[3]) != 2 {
+		return "", "", false, false
+	}
+	if _, err := hex.DecodeString(parts[1] + parts[2]); err != nil {
+		return "", "", false, false
+	}
+	if parts[1] == strings.Repeat("0", 32) || parts[2] == strings.Repeat("0", 16) {
+		return "", "", false, false
+	}
+	flags, err := hex.DecodeString(parts[3])
+	if err != nil {
+		return "", "", false, false
+	}
+	return parts[1], parts[2], flags[0]&1 == 1, true
+}
+
+func newSpanID() string {
+	var id [8]byte
+	rand.Read(id[:])
+	return hex.EncodeToString(id[:])
+}
+
+// OTLP/HTTP JSON encoding; ids are hex, times nanosecond strings
+
+type exportRequest struct {
+	ResourceSpans []resourceSpans `json:"resourceSpans"`
+}
+
+type resourceSpans struct {
+	Resource   resource     `json:"resource"`
+	ScopeSpans []scopeSpans `json:"scopeSpans"`
+}
+
+type resource struct {
+	Attributes []attribute `json:"attributes"`
+}
+
+type scopeSpans struct {
+	Scope scope      `json:"scope"`
+	Spans []spanData `json:"spans"`
+}
+
+type scope struct {
+	Name string `json:"name"`
+}
+
+type spanData struct {
+	TraceID      string      `json:"traceId"`
+	SpanID       string      `json:"spanId"`
+	ParentSpanID string      `json:"parentSpanId,omitempty"`
+	Name         string      `json:"name"`
+	Kind         int         `json:"kind"`
+	Start        string      `json:"startTimeUnixNano"`
+	End          string      `json:"endTimeUnixNano"`
+	Attributes   []attribute `json:"attributes,omitempty"`
+	Status       *status     `json:"status,omitempty"`
+}
+
+type status struct {
+	Code    int    `json:"code"`
+	Message string `json:"message,omitempty"`
+}
+
+type attribute struct {
+	Key   string   `json:"key"`
+	Value anyValue `json:"value"`
+}
+
+type anyValue struct {
+	String string `json:"stringValue,omitempty"`
+	Int    string `json:"intValue,omitempty"`
+}
+
+func stringAttr(key, value string) attribute {
+	return attribute{Key: key, Value: anyValue{String: value}}
+}
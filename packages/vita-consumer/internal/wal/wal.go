@@ -0,0 +1,396 @@
+package wal
+
+import (
+	"bufio"
+	"encoding/binary"
+	"errors"
+	"fmt"
+	"hash/crc32"
+	"io"
+	"log"
+	"math"
+	"os"
+	"path/filepath"
+	"sort"
+	"strings"
+	"sync"
+	"time"
+
+	"github.com/nchanged/vitakube/packages/vita-consumer/internal/buffer"
+)
+
+// WAL is an append-only log of accepted metrics, split into numbered
+// segments. Each ingest batch is one frame:
+//
+//	[u32 payload length][u32 CRC32 of payload][payload]
+//
+// Batches reach the ring buffer under the same lock that seals a segment,
+// and the persist worker flushes the ring while sealing, so a sealed
+// segment holds exactly the flushed batches. It is removed once the flush
+// has reached DuckDB.
+type WAL struct {
+	mu   sync.Mutex
+	dir  string
+	file *os.File
+	seq  uint64
+}
+
+const segmentPrefix = "wal-"
+const segmentSuffix = ".log"
+
+// Open starts a new segment after any existing ones. Existing segments are
+// left for Replay.
+func Open(dir string) (*WAL, error) {
+	if err := os.MkdirAll(dir, 0755); err != nil {
+		return nil, err
+	}
+
+	seqs, err := listSegments(dir)
+	if err != nil {
+		return nil, err
+	}
+
+	w := &WAL{dir: dir}
+	if len(seqs) > 0 {
+		w.seq = seqs[len(seqs)-1]
+	}
+	if err := w.openNext(); err != nil {
+		return nil, err
+	}
+	return w, nil
+}
+
+func (w *WAL) segmentPath(seq uint64) string {
+	return filepath.Join(w.dir, fmt.Sprintf("%s%016d%s", segmentPrefix, seq, segmentSuffix))
+}
+
+func (w *WAL) openNext() error {
+	w.seq++
+	f, err := os.OpenFile(w.segmentPath(w.seq), os.O_CREATE|os.O_WRONLY|os.O_APPEND, 0644)
+	if err != nil {
+		return err
+	}
+	w.file = f
+	return nil
+}
+
+// Append durably writes one batch, then runs apply, which buffers it, before
+// a Rotate can seal the segment. The caller must not ack the batch if this
+// fails; apply isn't run then.
+func (w *WAL) Append(metrics []buffer.Metric, apply func()) error {
+	if len(metrics) == 0 {
+		return nil
+	}
+	payload := encode(metrics)
+
+	frame := make([]byte, 8+len(payload))
+	binary.LittleEndian.PutUint32(frame[0:4], uint32(len(payload)))
+	binary.LittleEndian.PutUint32(frame[4:8], crc32.ChecksumIEEE(payload))
+	copy(frame[8:], payload)
+
+	w.mu.Lock()
+	defer w.mu.Unlock()
+
+	if _, err := w.file.Write(frame); err != nil {
+		return err
+	}
+	if err := w.file.Sync(); err != nil {
+		return err
+	}
+	apply()
+	return nil
+}
+
+// Rotate seals the current segment and returns its number. drain, which
+// flushes the buffer, runs before the next batch is appended, so it takes
+// every batch of the sealed segment and none of the next.
+func (w *WAL) Rotate(drain func()) (uint64, error) {
+	w.mu.Lock()
+	defer w.mu.Unlock()
+
+	sealed := w.seq
+	if err := w.file.Close(); err != nil {
+		return 0, err
+	}
+	if err := w.openNext(); err != nil {
+		return 0, err
+	}
+	drain()
+	return sealed, nil
+}
+
+// Remove deletes a sealed segment whose data has been persisted
+func (w *WAL) Remove(seq uint64) error {
+	err := os.Remove(w.segmentPath(seq))
+	if errors.Is(err, os.ErrNotExist) {
+		return nil
+	}
+	return err
+}
+
+// Size reports the segments on disk, the active one included, and their
+// total bytes
+func (w *WAL) Size() (int, int64, error) {
+	seqs, err := listSegments(w.dir)
+	if err != nil {
+		return 0, 0, err
+	}
+	var total int64
+	for _, seq := range seqs {
+		if info, err := os.Stat(w.segmentPath(seq)); err == nil {
+			total += info.Size()
+		}
+	}
+	return len(seqs), total, nil
+}
+
+// Replay feeds every batch from segments older than the active one to fn,
+// oldest first, and returns their numbers so they can be removed once the
+// replayed data is persisted. A torn or corrupt frame ends its segment.
+func (w *WAL) Replay(fn func([]buffer.Metric)) ([]uint64, error) {
+	seqs, err := listSegments(w.dir)
+	if err != nil {
+		return nil, err
+	}
+
+	replayed := []uint64{}
+	for _, seq := range seqs {
+		if seq >= w.seq {
+			continue
+		}
+		n, err := w.replaySegment(seq, fn)
+		if err != nil {
+			return replayed, err
+		}
+		log.Printf("WAL: replayed %d batches from segment %d", n, seq)
+		replayed = append(replayed, seq)
+	}
+	return replayed, nil
+}
+
+func (w *WAL) replaySegment(seq uint64, fn func([]buffer.Metric)) (int, error) {
+	f, err := os.Open(w.segmentPath(seq))
+	if err != nil {
+		return 0, err
+	}
+	defer f.Close()
+
+	r := bufio.NewReader(f)
+	header := make([]byte, 8)
+	batches := 0
+	for {
+		if _, err := io.ReadFull(r, header); err != nil {
+			if err != io.EOF {
+				log.Printf("WAL: segment %d has a torn frame header, stopping", seq)
+			}
+			return batches, nil
+		}
+
+		size := binary.LittleEndian.Uint32(header[0:4])
+		sum := binary.LittleEndian.Uint32(header[4:8])
+		payload := make([]byte, size)
+		if _, err := io.ReadFull(r, payload); err != nil {
+			log.Printf("WAL: segment %d has a torn frame, stopping", seq)
+			return batches, nil
+		}
+		if crc32.ChecksumIEEE(payload) != sum {
+			log.Printf("WAL: segment %d has a corrupt frame, stopping", seq)
+			return batches, nil
+		}
+
+		metrics, err := decode(payload)
+		if err != nil {
+			log.Printf("WAL: segment %d: %v, stopping", seq, err)
+			return batches, nil
+		}
+		fn(metrics)
+		batches++
+	}
+}
+
+func (w *WAL) Close() error {
+	w.mu.Lock()
+	defer w.mu.Unlock()
+	return w.file.Close()
+}
+
+func listSegments(dir string) ([]uint64, error) {
+	entries, err := os.ReadDir(dir)
+	if err != nil {
+		return nil, err
+	}
+
+	seqs := []uint64{}
+	for _, e := range entries {
+		name := e.Name()
+		if !strings.HasPrefix(name, segmentPrefix) || !strings.HasSuffix(name, segmentSuffix) {
+			continue
+		}
+		var seq uint64
+		if _, err := fmt.Sscanf(strings.TrimSuffix(strings.TrimPrefix(name, segmentPrefix), segmentSuffix), "%d", &seq); err != nil {
+			continue
+		}
+		seqs = append(seqs, seq)
+	}
+	sort.Slice(seqs, func(i, j int) bool { return seqs[i] < seqs[j] })
+	return seqs, nil
+}
+
+// Payload layout: [u32 count] then per metric
+// [i64 unix nanos][i64 resource id][f64 value][u16 len][node][u16 len][key]
+//
+// followed by a label trailer, since a batch shares a handful of label sets:
+// [u16 set count] then per set [u16 len][labels], then per metric [u16 set].
+// Frames written before labels existed end without the trailer.
+//
+// Read offsets follow as per metric [u32 microseconds], then the series
+// labels as per metric [u16 len][type], and the same for device, container
+// ID and volume. Frames written before either end without them.
+func encode(metrics []buffer.Metric) []byte {
+	buf := make([]byte, 4, 4+len(metrics)*50)
+	binary.LittleEndian.PutUint32(buf, uint32(len(metrics)))
+
+	for _, m := range metrics {
+		buf = binary.LittleEndian.AppendUint64(buf, uint64(m.Time.UnixNano()))
+		buf = binary.LittleEndian.AppendUint64(buf, uint64(m.ResourceID))
+		buf = binary.LittleEndian.AppendUint64(buf, math.Float64bits(m.Value))
+		buf = appendString(buf, m.Node)
+		buf = appendString(buf, m.Key)
+	}
+
+	sets := map[string]uint16{}
+	var order []string
+	index := make([]uint16, len(metrics))
+	for i, m := range metrics {
+		id, ok := sets[m.Labels]
+		if !ok {
+			if len(order) == math.MaxUint16 {
+				// Pathological batch; the rest keep the first set
+				continue
+			}
+			id = uint16(len(order))
+			sets[m.Labels] = id
+			order = append(order, m.Labels)
+		}
+		index[i] = id
+	}
+	buf = binary.LittleEndian.AppendUint16(buf, uint16(len(order)))
+	for _, labels := range order {
+		buf = appendString(buf, labels)
+	}
+	for _, id := range index {
+		buf = binary.LittleEndian.AppendUint16(buf, id)
+	}
+	for _, m := range metrics {
+		buf = binary.LittleEndian.AppendUint32(buf, uint32(m.Offset))
+	}
+	for _, m := range metrics {
+		buf = appendString(buf, m.Type)
+		buf = appendString(buf, m.Device)
+		buf = appendString(buf, m.ContainerID)
+		buf = appendString(buf, m.Volume)
+	}
+	return buf
+}
+
+func appendString(buf []byte, s string) []byte {
+	if len(s) > math.MaxUint16 {
+		s = s[:math.MaxUint16]
+	}
+	buf = binary.LittleEndian.AppendUint16(buf, uint16(len(s)))
+	return append(buf, s...)
+}
+
+var errShortPayload = errors.New("short payload")
+
+func decode(payload []byte) ([]buffer.Metric, error) {
+	if len(payload) < 4 {
+		return nil, errShortPayload
+	}
+	count := binary.LittleEndian.Uint32(payload)
+	p := payload[4:]
+
+	metrics := make([]buffer.Metric, 0, count)
+	for i := uint32(0); i < count; i++ {
+		if len(p) < 24 {
+			return nil, errShortPayload
+		}
+		var m buffer.Metric
+		m.Time = time.Unix(0, int64(binary.LittleEndian.Uint64(p[0:8])))
+		m.ResourceID = int64(binary.LittleEndian.Uint64(p[8:16]))
+		m.Value = math.Float64frombits(binary.LittleEndian.Uint64(p[16:24]))
+		p = p[24:]
+
+		var ok bool
+		if m.Node, p, ok = readString(p); !ok {
+			return nil, errShortPayload
+		}
+		if m.Key, p, ok = readString(p); !ok {
+			return nil, errShortPayload
+		}
+		metrics = append(metrics, m)
+	}
+
+	if len(p) == 0 {
+		return metrics, nil
+	}
+	if len(p) < 2 {
+		return nil, errShortPayload
+	}
+	sets := make([]string, binary.LittleEndian.Uint16(p))
+	p = p[2:]
+	for i := range sets {
+		var ok bool
+		if sets[i], p, ok = readString(p); !ok {
+			return nil, errShortPayload
+		}
+	}
+	if len(p) < 2*len(metrics) {
+		return nil, errShortPayload
+	}
+	for i := range metrics {
+		id := int(binary.LittleEndian.Uint16(p[2*i:]))
+		if id >= len(sets) {
+			return nil, errShortPayload
+		}
+		metrics[i].Labels = sets[id]
+	}
+	p = p[2*len(metrics):]
+
+	if len(p) == 0 {
+		return metrics, nil
+	}
+	if len(p) < 4*len(metrics) {
+		return nil, errShortPayload
+	}
+	for i := range metrics {
+		metrics[i].Offset = int64(binary.LittleEndian.Uint32(p[4*i:]))
+	}
+	p = p[4*len(metrics):]
+
+	if len(p) == 0 {
+		return metrics, nil
+	}
+	for i := range metrics {
+		m := &metrics[i]
+		for _, field := range []*string{&m.Type, &m.Device, &m.ContainerID, &m.Volume} {
+			var ok bool
+			if *field, p, ok = readString(p); !ok {
+				return nil, errShortPayload
+			}
+		}
+	}
+	return metrics, nil
+}
+
+func readString(p []byte) (string, []byte, bool) {
+	if len(p) < 2 {
+		return "", p, false
+	}
+	n := int(binary.LittleEndian.Uint16(p))
+	p = p[2:]
+	if len(p) < n {
+		return "", p, false
+	}
+	return string(p[:n]), p[n:], true
+}
@@ -0,0 +1,131 @@
+use k8s_openapi::api::core::v1::{ResourceRequirements, Toleration};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const GROUP: &str = "vitakube.io";
+pub const VERSION: &str = "v1alpha1";
+pub const KIND: &str = "VitaKube";
+pub const API_VERSION: &str = "vitakube.io/v1alpha1";
+
+/// `spec` of a VitaKube: one agent DaemonSet and one consumer in the
+/// resource's namespace
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VitaKubeSpec {
+    // Image tag of both components; changing it upgrades the consumer, then the agents
+    pub version: String,
+    pub image_pull_secrets: Vec<String>,
+    pub agent: AgentSpec,
+    pub consumer: ConsumerSpec,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AgentSpec {
+    pub enabled: bool,
+    pub image: String,
+    // Environment of the agent, e.g. COLLECTION_INTERVAL; a change rolls the agents
+    pub env: BTreeMap<String, String>,
+    pub resources: Option<ResourceRequirements>,
+    pub node_selector: BTreeMap<String, String>,
+    pub tolerations: Vec<Toleration>,
+    // As in the chart; false runs a locked-down container that may miss some host files
+    pub privileged: bool,
+    // Installed with the operator
+    pub cluster_role: String,
+}
+
+impl Default for AgentSpec {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            image: "nchanged/vita-agent".to_string(),
+            env: BTreeMap::new(),
+            resources: None,
+            node_selector: BTreeMap::new(),
+            tolerations: Vec::new(),
+            privileged: true,
+            cluster_role: "vita-agent".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConsumerSpec {
+    pub enabled: bool,
+    pub image: String,
+    pub replicas: i32,
+    // Environment of the consumer, e.g. INGEST_RATE; a change rolls the consumer
+    pub env: BTreeMap<String, String>,
+    pub resources: Option<ResourceRequirements>,
+    // A StatefulSet with a volume per replica; without it, a Deployment on emptyDir
+    pub storage: Option<StorageSpec>,
+    pub service: ServiceSpec,
+    pub cluster_role: String,
+}
+
+impl Default for ConsumerSpec {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            image: "nchanged/vita-consumer".to_string(),
+            replicas: 1,
+            env: BTreeMap::new(),
+            resources: None,
+            storage: None,
+            service: ServiceSpec::default(),
+            cluster_role: "vita-consumer".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StorageSpec {
+    pub size: String,
+    pub storage_class: Option<String>,
+}
+
+impl Default for StorageSpec {
+    fn default() -> Self {
+        Self { size: "10Gi".to_string(), storage_class: None }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServiceSpec {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub port: i32,
+}
+
+impl Default for ServiceSpec {
+    fn default() -> Self {
+        Self { type_: "ClusterIP".to_string(), port: 8080 }
+    }
+}
+
+/// `status` of a VitaKube, written by the operator
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VitaKubeStatus {
+    pub observed_generation: Option<i64>,
+    // Version every component runs; behind spec.version during an upgrade
+    pub version: Option<String>,
+    pub agent: ComponentStatus,
+    pub consumer: ComponentStatus,
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ComponentStatus {
+    pub version: Option<String>,
+    pub desired: i32,
+    pub ready: i32,
+    // Pods running the current template
+    pub updated: i32,
+}
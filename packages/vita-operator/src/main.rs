@@ -0,0 +1,54 @@
+use anyhow::{Context as _, Result};
+use futures::StreamExt;
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::{ConfigMap, Service};
+use kube::api::{Api, ApiResource, DynamicObject, GroupVersionKind};
+use kube::runtime::{watcher, Controller};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+mod crd;
+mod reconcile;
+mod render;
+
+use reconcile::Context;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"))
+        )
+        .with_target(false)
+        .compact()
+        .init();
+
+    let client = kube::Client::try_default().await.context("Failed to create Kubernetes client")?;
+    let resource = ApiResource::from_gvk(&GroupVersionKind::gvk(crd::GROUP, crd::VERSION, crd::KIND));
+    let vitakubes = Api::<DynamicObject>::all_with(client.clone(), &resource);
+
+    // Changes to the objects of an instance, e.g. pods becoming ready,
+    // reconcile it again; only the operator's own objects are watched
+    let owned = watcher::Config::default().labels("app.kubernetes.io/managed-by=vita-operator");
+
+    info!("Watching {} resources", crd::KIND);
+    Controller::new_with(vitakubes, watcher::Config::default(), resource.clone())
+        .owns(Api::<DaemonSet>::all(client.clone()), owned.clone())
+        .owns(Api::<Deployment>::all(client.clone()), owned.clone())
+        .owns(Api::<StatefulSet>::all(client.clone()), owned.clone())
+        .owns(Api::<Service>::all(client.clone()), owned.clone())
+        .owns(Api::<ConfigMap>::all(client.clone()), owned)
+        .shutdown_on_signal()
+        .run(reconcile::reconcile, reconcile::error_policy, Arc::new(Context { client, resource }))
+        .for_each(|result| async move {
+            match result {
+                Ok((obj, _)) => debug!("Reconciled {}", obj),
+                Err(e) => warn!("Reconcile failed: {}", e),
+            }
+        })
+        .await;
+
+    info!("Shutting down");
+    Ok(())
+}
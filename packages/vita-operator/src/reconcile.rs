@@ -0,0 +1,397 @@
+use chrono::Utc;
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::{ConfigMap, Service, ServiceAccount};
+use k8s_openapi::api::rbac::v1::ClusterRoleBinding;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, OwnerReference, Time};
+use kube::api::{Api, ApiResource, DeleteParams, DynamicObject, Patch, PatchParams};
+use kube::runtime::controller::Action;
+use kube::{Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+use crate::crd::{ComponentStatus, VitaKubeSpec, VitaKubeStatus, API_VERSION, KIND};
+use crate::render::{binding_name, Instance};
+
+/// Field manager of everything the operator applies
+const MANAGER: &str = "vita-operator";
+
+/// Held until the cluster role bindings, which can't be owned by a
+/// namespaced resource, are deleted
+const FINALIZER: &str = "vitakube.io/cluster-role-bindings";
+
+/// Rechecked this often while a rollout is in progress, besides the
+/// watches on the objects themselves
+const PROGRESS_REQUEUE: Duration = Duration::from_secs(10);
+const IDLE_REQUEUE: Duration = Duration::from_secs(300);
+const ERROR_REQUEUE: Duration = Duration::from_secs(30);
+
+pub struct Context {
+    pub client: kube::Client,
+    pub resource: ApiResource,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Kubernetes API: {0}")]
+    Api(#[from] kube::Error),
+    #[error("invalid spec: {0}")]
+    InvalidSpec(String),
+}
+
+/// Brings the objects of a VitaKube in line with its spec and reports how
+/// far the rollout got in its status
+pub async fn reconcile(obj: Arc<DynamicObject>, ctx: Arc<Context>) -> Result<Action, Error> {
+    let namespace = obj.namespace().unwrap_or_default();
+    let api = Api::<DynamicObject>::namespaced_with(ctx.client.clone(), &namespace, &ctx.resource);
+    let previous: VitaKubeStatus = obj
+        .data
+        .get("status")
+        .cloned()
+        .and_then(|s| serde_json::from_value(s).ok())
+        .unwrap_or_default();
+
+    if obj.metadata.deletion_timestamp.is_some() {
+        cleanup(&obj, &ctx, &api).await?;
+        return Ok(Action::await_change());
+    }
+
+    match apply_all(&obj, &ctx, &api, &previous).await {
+        Ok((status, progressing)) => {
+            write_status(&obj, &api, &previous, status).await?;
+            Ok(Action::requeue(if progressing { PROGRESS_REQUEUE } else { IDLE_REQUEUE }))
+        }
+        Err(e) => {
+            let mut status = previous.clone();
+            set_condition(&mut status.conditions, generation(&obj), "Degraded", true, "ReconcileFailed", &e.to_string());
+            write_status(&obj, &api, &previous, status).await?;
+            Err(e)
+        }
+    }
+}
+
+pub fn error_policy(_obj: Arc<DynamicObject>, _error: &Error, _ctx: Arc<Context>) -> Action {
+    Action::requeue(ERROR_REQUEUE)
+}
+
+fn generation(obj: &DynamicObject) -> Option<i64> {
+    obj.metadata.generation
+}
+
+/// Applies every object of the instance, returning the new status and
+/// whether a rollout is still under way
+async fn apply_all(
+    obj: &DynamicObject,
+    ctx: &Context,
+    api: &Api<DynamicObject>,
+    previous: &VitaKubeStatus,
+) -> Result<(VitaKubeStatus, bool), Error> {
+    let spec: VitaKubeSpec = serde_json::from_value(obj.data.get("spec").cloned().unwrap_or_default())
+        .map_err(|e| Error::InvalidSpec(e.to_string()))?;
+    if spec.version.is_empty() {
+        return Err(Error::InvalidSpec("version is required".to_string()));
+    }
+    if spec.consumer.replicas < 1 {
+        return Err(Error::InvalidSpec("consumer.replicas must be at least 1".to_string()));
+    }
+    add_finalizer(obj, api).await?;
+
+    let name = obj.name_any();
+    let namespace = obj.namespace().unwrap_or_default();
+    let instance = Instance {
+        name: &name,
+        namespace: &namespace,
+        owner: OwnerReference {
+            api_version: API_VERSION.to_string(),
+            kind: KIND.to_string(),
+            name: name.clone(),
+            uid: obj.uid().unwrap_or_default(),
+            controller: Some(true),
+            block_owner_deletion: Some(true),
+        },
+        spec: &spec,
+    };
+    let client = &ctx.client;
+
+    let consumer = reconcile_consumer(&instance, client, &previous.consumer).await?;
+    // Agents are upgraded once the consumer runs the new version, so it
+    // understands whatever they start sending
+    let consumer_upgraded = !spec.consumer.enabled || consumer.version.as_deref() == Some(spec.version.as_str());
+    let agent = reconcile_agent(&instance, client, &previous.agent, consumer_upgraded).await?;
+
+    let mut status = VitaKubeStatus {
+        observed_generation: generation(obj),
+        version: previous.version.clone(),
+        agent,
+        consumer,
+        conditions: previous.conditions.clone(),
+    };
+    let components = [(spec.consumer.enabled, &status.consumer), (spec.agent.enabled, &status.agent)];
+    let upgraded = components
+        .iter()
+        .all(|(enabled, c)| !enabled || c.version.as_deref() == Some(spec.version.as_str()));
+    let rolled_out = components
+        .iter()
+        .all(|(enabled, c)| !enabled || c.updated == c.desired && c.ready == c.desired);
+    if upgraded && rolled_out {
+        status.version = Some(spec.version.clone());
+    }
+
+    let gen = generation(obj);
+    let ready = format!(
+        "consumer {}/{} ready, agent {}/{} ready",
+        status.consumer.ready, status.consumer.desired, status.agent.ready, status.agent.desired,
+    );
+    let all_ready = components.iter().all(|(enabled, c)| !enabled || c.ready == c.desired);
+    set_condition(&mut status.conditions, gen, "Ready", all_ready, if all_ready { "AllReady" } else { "PodsNotReady" }, &ready);
+    let progressing = !upgraded || !rolled_out;
+    if !upgraded {
+        let message = format!("upgrading from {} to {}", status.version.as_deref().unwrap_or("nothing"), spec.version);
+        set_condition(&mut status.conditions, gen, "Progressing", true, "Upgrading", &message);
+    } else if !rolled_out {
+        set_condition(&mut status.conditions, gen, "Progressing", true, "RollingOut", &ready);
+    } else {
+        set_condition(&mut status.conditions, gen, "Progressing", false, "Complete", &format!("running {}", spec.version));
+    }
+    set_condition(&mut status.conditions, gen, "Degraded", false, "ReconcileSucceeded", "");
+    Ok((status, progressing))
+}
+
+async fn reconcile_consumer(instance: &Instance<'_>, client: &kube::Client, previous: &ComponentStatus) -> Result<ComponentStatus, Error> {
+    let spec = &instance.spec.consumer;
+    let name = instance.consumer_name();
+    let ns = instance.namespace;
+    let deployments = Api::<Deployment>::namespaced(client.clone(), ns);
+    let statefulsets = Api::<StatefulSet>::namespaced(client.clone(), ns);
+    let services = Api::<Service>::namespaced(client.clone(), ns);
+    let bindings = Api::<ClusterRoleBinding>::all(client.clone());
+
+    if !spec.enabled {
+        delete(&deployments, &name).await?;
+        delete(&statefulsets, &name).await?;
+        delete(&services, &name).await?;
+        delete(&services, &instance.headless_name()).await?;
+        delete(&Api::<ConfigMap>::namespaced(client.clone(), ns), &instance.consumer_config_name()).await?;
+        delete(&bindings, &instance.binding_name("consumer")).await?;
+        return Ok(ComponentStatus::default());
+    }
+
+    apply(&Api::<ServiceAccount>::namespaced(client.clone(), ns), &instance.service_account(name.clone(), "vita-consumer")).await?;
+    apply(&bindings, &instance.cluster_role_binding("consumer", name.clone(), &spec.cluster_role)).await?;
+    let config = apply(&Api::<ConfigMap>::namespaced(client.clone(), ns), &instance.consumer_config()).await?;
+    apply(&services, &instance.consumer_service()).await?;
+
+    let version = &instance.spec.version;
+    let mut status = if spec.storage.is_some() {
+        // Switching from a Deployment starts over on fresh volumes
+        delete(&deployments, &name).await?;
+        apply(&services, &instance.headless_service()).await?;
+        let set = apply(&statefulsets, &instance.consumer_statefulset(version, &config)).await?;
+        let s = set.status.clone().unwrap_or_default();
+        ComponentStatus {
+            version: None,
+            desired: spec.replicas,
+            ready: s.ready_replicas.unwrap_or(0),
+            updated: if observed(&set.metadata, s.observed_generation) { s.updated_replicas.unwrap_or(0) } else { 0 },
+        }
+    } else {
+        delete(&statefulsets, &name).await?;
+        delete(&services, &instance.headless_name()).await?;
+        let deployment = apply(&deployments, &instance.consumer_deployment(version, &config)).await?;
+        let s = deployment.status.clone().unwrap_or_default();
+        ComponentStatus {
+            version: None,
+            desired: spec.replicas,
+            ready: s.ready_replicas.unwrap_or(0),
+            updated: if observed(&deployment.metadata, s.observed_generation) { s.updated_replicas.unwrap_or(0) } else { 0 },
+        }
+    };
+    status.version = rolled_version(&status, version, previous);
+    Ok(status)
+}
+
+async fn reconcile_agent(
+    instance: &Instance<'_>,
+    client: &kube::Client,
+    previous: &ComponentStatus,
+    consumer_upgraded: bool,
+) -> Result<ComponentStatus, Error> {
+    let spec = &instance.spec.agent;
+    let name = instance.agent_name();
+    let ns = instance.namespace;
+    let daemonsets = Api::<DaemonSet>::namespaced(client.clone(), ns);
+    let bindings = Api::<ClusterRoleBinding>::all(client.clone());
+
+    if !spec.enabled {
+        delete(&daemonsets, &name).await?;
+        delete(&Api::<ConfigMap>::namespaced(client.clone(), ns), &instance.agent_config_name()).await?;
+        delete(&bindings, &instance.binding_name("agent")).await?;
+        return Ok(ComponentStatus::default());
+    }
+
+    // Until the consumer is upgraded, agents stay on the version they run;
+    // a first install starts them right away
+    let running = daemonsets.get_opt(&name).await?.and_then(|ds| image_tag(&ds));
+    let version = match running {
+        Some(tag) if !consumer_upgraded => tag,
+        _ => instance.spec.version.clone(),
+    };
+
+    apply(&Api::<ServiceAccount>::namespaced(client.clone(), ns), &instance.service_account(name.clone(), "vita-agent")).await?;
+    apply(&bindings, &instance.cluster_role_binding("agent", name.clone(), &spec.cluster_role)).await?;
+    let config = apply(&Api::<ConfigMap>::namespaced(client.clone(), ns), &instance.agent_config()).await?;
+    let ds = apply(&daemonsets, &instance.agent_daemonset(&version, &config)).await?;
+
+    let s = ds.status.clone().unwrap_or_default();
+    let mut status = ComponentStatus {
+        version: None,
+        desired: s.desired_number_scheduled,
+        ready: s.number_ready,
+        updated: if observed(&ds.metadata, s.observed_generation) { s.updated_number_scheduled.unwrap_or(0) } else { 0 },
+    };
+    status.version = rolled_version(&status, &version, previous);
+    Ok(status)
+}
+
+/// Whether the controller of a workload has seen its latest spec, without
+/// which its counts describe the previous one
+fn observed(meta: &kube::api::ObjectMeta, observed_generation: Option<i64>) -> bool {
+    observed_generation.is_some() && observed_generation >= meta.generation
+}
+
+/// The version a component runs: the applied one once every pod runs it,
+/// else the one it ran before
+fn rolled_version(status: &ComponentStatus, version: &str, previous: &ComponentStatus) -> Option<String> {
+    if status.updated == status.desired && status.ready == status.desired {
+        Some(version.to_string())
+    } else {
+        previous.version.clone()
+    }
+}
+
+fn image_tag(ds: &DaemonSet) -> Option<String> {
+    let image = ds.spec.as_ref()?.template.spec.as_ref()?.containers.first()?.image.as_ref()?;
+    Some(tag_of(image))
+}
+
+/// The tag of an image reference. A ':' followed by a '/' separates a
+/// registry port, not a tag, and an untagged image runs `latest`.
+fn tag_of(image: &str) -> String {
+    let name = image.split_once('@').map_or(image, |(name, _)| name);
+    match name.rsplit_once(':') {
+        Some((_, tag)) if !tag.contains('/') => tag.to_string(),
+        _ => "latest".to_string(),
+    }
+}
+
+/// Server-side applies obj, taking over fields set by hand
+async fn apply<K>(api: &Api<K>, obj: &K) -> Result<K, Error>
+where
+    K: Resource + Clone + Serialize + DeserializeOwned + Debug,
+{
+    let name = obj.meta().name.clone().unwrap_or_default();
+    let params = PatchParams::apply(MANAGER).force();
+    Ok(api.patch(&name, &params, &Patch::Apply(obj)).await?)
+}
+
+/// Deletes an object the spec no longer calls for, if it exists
+async fn delete<K>(api: &Api<K>, name: &str) -> Result<(), Error>
+where
+    K: Resource + Clone + DeserializeOwned + Debug,
+    K::DynamicType: Default,
+{
+    match api.delete(name, &DeleteParams::background()).await {
+        Ok(_) => {
+            info!("Deleted {} {}", K::kind(&Default::default()), name);
+            Ok(())
+        }
+        Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn add_finalizer(obj: &DynamicObject, api: &Api<DynamicObject>) -> Result<(), Error> {
+    let finalizers = obj.finalizers();
+    if finalizers.iter().any(|f| f == FINALIZER) {
+        return Ok(());
+    }
+    let mut finalizers = finalizers.to_vec();
+    finalizers.push(FINALIZER.to_string());
+    let patch = json!({ "metadata": { "finalizers": finalizers } });
+    api.patch(&obj.name_any(), &PatchParams::default(), &Patch::Merge(&patch)).await?;
+    Ok(())
+}
+
+/// Deletes what garbage collection won't, then lets the resource go
+async fn cleanup(obj: &DynamicObject, ctx: &Context, api: &Api<DynamicObject>) -> Result<(), Error> {
+    if !obj.finalizers().iter().any(|f| f == FINALIZER) {
+        return Ok(());
+    }
+    let name = obj.name_any();
+    let namespace = obj.namespace().unwrap_or_default();
+    let bindings = Api::<ClusterRoleBinding>::all(ctx.client.clone());
+    for component in ["agent", "consumer"] {
+        delete(&bindings, &binding_name(&namespace, &name, component)).await?;
+    }
+
+    let finalizers: Vec<&String> = obj.finalizers().iter().filter(|f| *f != FINALIZER).collect();
+    let patch = json!({ "metadata": { "finalizers": finalizers } });
+    api.patch(&name, &PatchParams::default(), &Patch::Merge(&patch)).await?;
+    info!("Removed {} {}/{}", KIND, namespace, name);
+    Ok(())
+}
+
+async fn write_status(obj: &DynamicObject, api: &Api<DynamicObject>, previous: &VitaKubeStatus, status: VitaKubeStatus) -> Result<(), Error> {
+    if *previous == status {
+        return Ok(());
+    }
+    if previous.version != status.version {
+        if let Some(version) = &status.version {
+            info!("{} {} runs version {}", KIND, obj.name_any(), version);
+        }
+    }
+    let patch = json!({ "status": status });
+    api.patch_status(&obj.name_any(), &PatchParams::default(), &Patch::Merge(&patch)).await?;
+    Ok(())
+}
+
+/// Sets a condition, keeping its transition time unless the status flips
+fn set_condition(conditions: &mut Vec<Condition>, generation: Option<i64>, type_: &str, status: bool, reason: &str, message: &str) {
+    let status = if status { "True" } else { "False" }.to_string();
+    let existing = conditions.iter_mut().find(|c| c.type_ == type_);
+    let transition = match &existing {
+        Some(c) if c.status == status => c.last_transition_time.clone(),
+        _ => Time(Utc::now()),
+    };
+    let condition = Condition {
+        type_: type_.to_string(),
+        status,
+        reason: reason.to_string(),
+        message: message.to_string(),
+        observed_generation: generation,
+        last_transition_time: transition,
+    };
+    match existing {
+        Some(c) => *c = condition,
+        None => conditions.push(condition),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tag_of;
+
+    #[test]
+    fn image_tags() {
+        assert_eq!(tag_of("ghcr.io/nchanged/vita-agent:0.4.1"), "0.4.1");
+        assert_eq!(tag_of("vita-agent"), "latest");
+        assert_eq!(tag_of("registry:5000/vita-agent"), "latest");
+        assert_eq!(tag_of("registry:5000/vita-agent:0.4.1"), "0.4.1");
+        assert_eq!(tag_of("registry:5000/vita-agent:0.4.1@sha256:3f1c"), "0.4.1");
+        assert_eq!(tag_of("vita-agent@sha256:3f1c"), "latest");
+    }
+}
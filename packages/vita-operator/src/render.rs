@@ -0,0 +1,419 @@
+use k8s_openapi::api::apps::v1::{
+    DaemonSet, DaemonSetSpec, Deployment, DeploymentSpec, StatefulSet, StatefulSetSpec,
+};
+use k8s_openapi::api::core::v1::{
+    Capabilities, ConfigMap, ConfigMapEnvSource, Container, ContainerPort, EnvFromSource, EnvVar, EnvVarSource,
+    HostPathVolumeSource, LocalObjectReference, ObjectFieldSelector, PersistentVolumeClaim,
+    PersistentVolumeClaimSpec, PodSpec, PodTemplateSpec, Probe, SecurityContext, Service,
+    ServiceAccount, ServicePort, ServiceSpec, TCPSocketAction, Volume, VolumeMount,
+    VolumeResourceRequirements,
+};
+use k8s_openapi::api::rbac::v1::{ClusterRoleBinding, RoleRef, Subject};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta, OwnerReference};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use crate::crd::VitaKubeSpec;
+
+const MANAGER: &str = "vita-operator";
+
+/// Pod template annotation holding a hash of the component's config, so a
+/// config change rolls its pods
+pub const CONFIG_HASH: &str = "vitakube.io/config-hash";
+
+/// Port the consumer listens on
+const CONSUMER_PORT: i32 = 8080;
+
+/// A VitaKube resource and what it renders to, all in its namespace and
+/// owned by it, but for the cluster role bindings
+pub struct Instance<'a> {
+    pub name: &'a str,
+    pub namespace: &'a str,
+    pub owner: OwnerReference,
+    pub spec: &'a VitaKubeSpec,
+}
+
+impl Instance<'_> {
+    pub fn agent_name(&self) -> String {
+        format!("{}-agent", self.name)
+    }
+
+    pub fn consumer_name(&self) -> String {
+        format!("{}-consumer", self.name)
+    }
+
+    pub fn headless_name(&self) -> String {
+        format!("{}-consumer-headless", self.name)
+    }
+
+    pub fn agent_config_name(&self) -> String {
+        format!("{}-agent-config", self.name)
+    }
+
+    pub fn consumer_config_name(&self) -> String {
+        format!("{}-consumer-config", self.name)
+    }
+
+    pub fn binding_name(&self, component: &str) -> String {
+        binding_name(self.namespace, self.name, component)
+    }
+
+    fn meta(&self, name: String, app: &str) -> ObjectMeta {
+        ObjectMeta {
+            name: Some(name),
+            namespace: Some(self.namespace.to_string()),
+            labels: Some(self.labels(app)),
+            owner_references: Some(vec![self.owner.clone()]),
+            ..Default::default()
+        }
+    }
+
+    pub fn selector(&self, app: &str) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("app.kubernetes.io/name".to_string(), app.to_string()),
+            ("app.kubernetes.io/instance".to_string(), self.name.to_string()),
+        ])
+    }
+
+    fn labels(&self, app: &str) -> BTreeMap<String, String> {
+        let mut labels = self.selector(app);
+        labels.insert("app.kubernetes.io/part-of".to_string(), "vitakube".to_string());
+        labels.insert("app.kubernetes.io/managed-by".to_string(), MANAGER.to_string());
+        labels
+    }
+
+    fn pull_secrets(&self) -> Option<Vec<LocalObjectReference>> {
+        let secrets = &self.spec.image_pull_secrets;
+        (!secrets.is_empty()).then(|| {
+            secrets.iter().map(|name| LocalObjectReference { name: name.clone() }).collect()
+        })
+    }
+
+    pub fn service_account(&self, name: String, app: &str) -> ServiceAccount {
+        ServiceAccount { metadata: self.meta(name, app), ..Default::default() }
+    }
+
+    /// Binds a component's service account to the cluster role installed
+    /// with the operator
+    pub fn cluster_role_binding(&self, component: &str, service_account: String, role: &str) -> ClusterRoleBinding {
+        ClusterRoleBinding {
+            metadata: ObjectMeta {
+                name: Some(self.binding_name(component)),
+                labels: Some(self.labels(&format!("vita-{}", component))),
+                ..Default::default()
+            },
+            role_ref: RoleRef {
+                api_group: "rbac.authorization.k8s.io".to_string(),
+                kind: "ClusterRole".to_string(),
+                name: role.to_string(),
+            },
+            subjects: Some(vec![Subject {
+                kind: "ServiceAccount".to_string(),
+                name: service_account,
+                namespace: Some(self.namespace.to_string()),
+                ..Default::default()
+            }]),
+        }
+    }
+
+    /// The agent's environment: where the consumer is, unless set otherwise
+    pub fn agent_config(&self) -> ConfigMap {
+        let mut data = BTreeMap::from([(
+            "CONSUMER_ENDPOINT".to_string(),
+            format!(
+                "http://{}.{}.svc:{}/api/v1/ingest",
+                self.consumer_name(),
+                self.namespace,
+                self.spec.consumer.service.port
+            ),
+        )]);
+        data.extend(self.spec.agent.env.clone());
+        ConfigMap {
+            metadata: self.meta(self.agent_config_name(), "vita-agent"),
+            data: Some(data),
+            ..Default::default()
+        }
+    }
+
+    pub fn consumer_config(&self) -> ConfigMap {
+        let mut data = BTreeMap::from([("DATA_DIR".to_string(), "/data".to_string())]);
+        data.extend(self.spec.consumer.env.clone());
+        ConfigMap {
+            metadata: self.meta(self.consumer_config_name(), "vita-consumer"),
+            data: Some(data),
+            ..Default::default()
+        }
+    }
+
+    /// The agent DaemonSet running version, which trails spec.version until
+    /// the consumer has been upgraded
+    pub fn agent_daemonset(&self, version: &str, config: &ConfigMap) -> DaemonSet {
+        let agent = &self.spec.agent;
+        let security = if agent.privileged {
+            SecurityContext { privileged: Some(true), ..Default::default() }
+        } else {
+            SecurityContext {
+                privileged: Some(false),
+                allow_privilege_escalation: Some(false),
+                read_only_root_filesystem: Some(true),
+                capabilities: Some(Capabilities {
+                    drop: Some(vec!["ALL".to_string()]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        };
+        // The collectors read the host's /proc, cgroups and pod volumes
+        let host_paths = [
+            ("proc", "/proc"),
+            ("sys", "/sys"),
+            ("cgroup", "/sys/fs/cgroup"),
+            ("kubelet", "/var/lib/kubelet"),
+        ];
+
+        let container = Container {
+            name: "vita-agent".to_string(),
+            image: Some(format!("{}:{}", agent.image, version)),
+            // The namespace holds the leader election lease, with LEADER_ELECTION
+            env: Some(vec![field_env("NODE_NAME", "spec.nodeName"), field_env("POD_NAMESPACE", "metadata.namespace")]),
+            env_from: Some(vec![config_env(config)]),
+            security_context: Some(security),
+            volume_mounts: Some(
+                host_paths
+                    .iter()
+                    .map(|(name, path)| VolumeMount {
+                        name: name.to_string(),
+                        mount_path: path.to_string(),
+                        read_only: Some(true),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            resources: agent.resources.clone(),
+            ..Default::default()
+        };
+        let pod = PodSpec {
+            service_account_name: Some(self.agent_name()),
+            host_network: Some(true),
+            host_pid: Some(true),
+            containers: vec![container],
+            volumes: Some(
+                host_paths
+                    .iter()
+                    .map(|(name, path)| Volume {
+                        name: name.to_string(),
+                        host_path: Some(HostPathVolumeSource { path: path.to_string(), ..Default::default() }),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            node_selector: (!agent.node_selector.is_empty()).then(|| agent.node_selector.clone()),
+            tolerations: (!agent.tolerations.is_empty()).then(|| agent.tolerations.clone()),
+            image_pull_secrets: self.pull_secrets(),
+            ..Default::default()
+        };
+
+        DaemonSet {
+            metadata: self.meta(self.agent_name(), "vita-agent"),
+            spec: Some(DaemonSetSpec {
+                selector: LabelSelector {
+                    match_labels: Some(self.selector("vita-agent")),
+                    ..Default::default()
+                },
+                template: self.template("vita-agent", config, pod),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn consumer_pod(&self, version: &str, config: &ConfigMap, data: Option<Volume>) -> PodSpec {
+        let consumer = &self.spec.consumer;
+        let container = Container {
+            name: "vita-consumer".to_string(),
+            image: Some(format!("{}:{}", consumer.image, version)),
+            ports: Some(vec![ContainerPort {
+                name: Some("http".to_string()),
+                container_port: CONSUMER_PORT,
+                protocol: Some("TCP".to_string()),
+                ..Default::default()
+            }]),
+            env_from: Some(vec![config_env(config)]),
+            // Holds the rollout until the new consumer accepts connections
+            readiness_probe: Some(Probe {
+                tcp_socket: Some(TCPSocketAction {
+                    port: IntOrString::String("http".to_string()),
+                    ..Default::default()
+                }),
+                period_seconds: Some(5),
+                ..Default::default()
+            }),
+            volume_mounts: Some(vec![VolumeMount {
+                name: "data".to_string(),
+                mount_path: "/data".to_string(),
+                ..Default::default()
+            }]),
+            resources: consumer.resources.clone(),
+            ..Default::default()
+        };
+        PodSpec {
+            service_account_name: Some(self.consumer_name()),
+            containers: vec![container],
+            volumes: data.map(|v| vec![v]),
+            image_pull_secrets: self.pull_secrets(),
+            ..Default::default()
+        }
+    }
+
+    /// The consumer without storage: data lives on an emptyDir and is lost
+    /// with the pod
+    pub fn consumer_deployment(&self, version: &str, config: &ConfigMap) -> Deployment {
+        let data = Volume {
+            name: "data".to_string(),
+            empty_dir: Some(Default::default()),
+            ..Default::default()
+        };
+        let pod = self.consumer_pod(version, config, Some(data));
+        Deployment {
+            metadata: self.meta(self.consumer_name(), "vita-consumer"),
+            spec: Some(DeploymentSpec {
+                replicas: Some(self.spec.consumer.replicas),
+                selector: LabelSelector {
+                    match_labels: Some(self.selector("vita-consumer")),
+                    ..Default::default()
+                },
+                template: self.template("vita-consumer", config, pod),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// The consumer with storage: a volume claim per replica, kept across
+    /// upgrades and restarts
+    pub fn consumer_statefulset(&self, version: &str, config: &ConfigMap) -> StatefulSet {
+        let storage = self.spec.consumer.storage.clone().unwrap_or_default();
+        let claim = PersistentVolumeClaim {
+            metadata: ObjectMeta {
+                name: Some("data".to_string()),
+                ..Default::default()
+            },
+            spec: Some(PersistentVolumeClaimSpec {
+                access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+                resources: Some(VolumeResourceRequirements {
+                    requests: Some(BTreeMap::from([("storage".to_string(), Quantity(storage.size))])),
+                    ..Default::default()
+                }),
+                storage_class_name: storage.storage_class,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let pod = self.consumer_pod(version, config, None);
+        StatefulSet {
+            metadata: self.meta(self.consumer_name(), "vita-consumer"),
+            spec: Some(StatefulSetSpec {
+                replicas: Some(self.spec.consumer.replicas),
+                service_name: self.headless_name(),
+                selector: LabelSelector {
+                    match_labels: Some(self.selector("vita-consumer")),
+                    ..Default::default()
+                },
+                template: self.template("vita-consumer", config, pod),
+                volume_claim_templates: Some(vec![claim]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// The consumer's Service, for agents and queries
+    pub fn consumer_service(&self) -> Service {
+        let service = &self.spec.consumer.service;
+        Service {
+            metadata: self.meta(self.consumer_name(), "vita-consumer"),
+            spec: Some(ServiceSpec {
+                type_: Some(service.type_.clone()),
+                selector: Some(self.selector("vita-consumer")),
+                ports: Some(vec![ServicePort {
+                    name: Some("http".to_string()),
+                    port: service.port,
+                    target_port: Some(IntOrString::String("http".to_string())),
+                    protocol: Some("TCP".to_string()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Gives each StatefulSet replica a stable DNS name
+    pub fn headless_service(&self) -> Service {
+        Service {
+            metadata: self.meta(self.headless_name(), "vita-consumer"),
+            spec: Some(ServiceSpec {
+                cluster_ip: Some("None".to_string()),
+                selector: Some(self.selector("vita-consumer")),
+                ports: Some(vec![ServicePort {
+                    name: Some("http".to_string()),
+                    port: CONSUMER_PORT,
+                    target_port: Some(IntOrString::String("http".to_string())),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn template(&self, app: &str, config: &ConfigMap, spec: PodSpec) -> PodTemplateSpec {
+        PodTemplateSpec {
+            metadata: Some(ObjectMeta {
+                labels: Some(self.selector(app)),
+                annotations: Some(BTreeMap::from([(CONFIG_HASH.to_string(), config_hash(config))])),
+                ..Default::default()
+            }),
+            spec: Some(spec),
+        }
+    }
+}
+
+/// Cluster role bindings are cluster-scoped, so named after the namespace too
+pub fn binding_name(namespace: &str, name: &str, component: &str) -> String {
+    format!("vitakube-{}-{}-{}", namespace, name, component)
+}
+
+fn field_env(name: &str, path: &str) -> EnvVar {
+    EnvVar {
+        name: name.to_string(),
+        value_from: Some(EnvVarSource {
+            field_ref: Some(ObjectFieldSelector {
+                field_path: path.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn config_env(config: &ConfigMap) -> EnvFromSource {
+    EnvFromSource {
+        config_map_ref: Some(ConfigMapEnvSource {
+            name: config.metadata.name.clone().unwrap_or_default(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// The data is a sorted map, so equal configs hash alike
+fn config_hash(config: &ConfigMap) -> String {
+    let mut hasher = DefaultHasher::new();
+    config.data.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
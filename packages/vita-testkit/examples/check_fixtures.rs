@@ -0,0 +1,36 @@
+//! Replays every fixture through the agent and prints what it reported.
+//! `cargo run --example check_fixtures [path/to/vita-agent]`; the agent
+//! defaults to the debug build next to this crate.
+
+use std::time::Duration;
+use vita_testkit::{Agent, FakeConsumer, Fixture};
+
+fn main() {
+    let binary = std::env::args().nth(1)
+        .unwrap_or_else(|| concat!(env!("CARGO_MANIFEST_DIR"), "/../vita-agent/target/debug/vita-agent").to_string());
+
+    let mut failed = false;
+    for fixture in Fixture::all() {
+        let consumer = FakeConsumer::start().expect("starting the fake consumer");
+        let mut agent = Agent::replay(&binary, &fixture, &consumer).expect("starting the agent");
+        let received = consumer.wait_for_batches(2, Duration::from_secs(15));
+        let running = agent.running();
+        drop(agent);
+
+        let interfaces = consumer.label_values("node_net", "device");
+        println!("{}: batches={} node_cpu={} interfaces={:?} pods={} volumes={}",
+            fixture.name(),
+            consumer.batches().len(),
+            !consumer.find("node_cpu", "user").is_empty(),
+            interfaces,
+            consumer.pod_ids().len(),
+            consumer.label_values("pvc_usage", "volume").len());
+        if !received || !running {
+            println!("  agent {} before sending two batches", if running { "timed out" } else { "exited" });
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
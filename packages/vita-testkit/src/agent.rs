@@ -0,0 +1,51 @@
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+use crate::consumer::FakeConsumer;
+use crate::fixture::Fixture;
+
+/// A vita-agent process replaying a fixture into a consumer every second.
+/// Killed when dropped.
+pub struct Agent {
+    child: Child,
+}
+
+impl Agent {
+    /// Starts `binary replay <fixture>` sending to `consumer`. Agent logs go
+    /// to the caller's output at RUST_LOG level, warn by default.
+    pub fn replay(binary: impl AsRef<Path>, fixture: &Fixture, consumer: &FakeConsumer) -> io::Result<Self> {
+        Self::replay_with(binary, fixture, consumer, &[])
+    }
+
+    /// Same as `replay`, with extra environment for the agent
+    pub fn replay_with(
+        binary: impl AsRef<Path>,
+        fixture: &Fixture,
+        consumer: &FakeConsumer,
+        env: &[(&str, &str)],
+    ) -> io::Result<Self> {
+        let mut command = Command::new(binary.as_ref());
+        command.arg("replay").arg(fixture.path())
+            .env("CONSUMER_ENDPOINT", consumer.endpoint())
+            .env("COLLECTION_INTERVAL", "1")
+            .env("RUST_LOG", std::env::var("RUST_LOG").unwrap_or_else(|_| "warn".to_string()))
+            .stdin(Stdio::null());
+        for (name, value) in env {
+            command.env(name, value);
+        }
+        Ok(Self { child: command.spawn()? })
+    }
+
+    /// Whether the agent is still running; it exits early on bad arguments
+    pub fn running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
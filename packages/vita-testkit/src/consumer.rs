@@ -0,0 +1,285 @@
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What the agent agrees to with this consumer: plain JSON, uncompressed,
+/// so every request can be read back without the agent's encoders
+const AGREEMENT: &str = r#"{"protocol":1,"wire_format":"json","compression":"identity","max_batch_metrics":0,"kinds":["heartbeat","events"]}"#;
+
+/// One metric of a batch, with its labels by name (pod_id, device, ...)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub metric_type: String,
+    pub key: String,
+    pub value: f64,
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub node: String,
+    pub metrics: Vec<Metric>,
+}
+
+#[derive(Default)]
+struct State {
+    batches: Vec<Batch>,
+    heartbeats: Vec<Value>,
+    events: Vec<Value>,
+    // Status ingest requests get instead of 200, to exercise backoff
+    status: Option<u16>,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+/// An ingest endpoint on localhost that answers the handshake and records
+/// every batch, heartbeat and event posted to it. Stops when dropped.
+pub struct FakeConsumer {
+    addr: SocketAddr,
+    state: Shared,
+    stop: Arc<AtomicBool>,
+}
+
+impl FakeConsumer {
+    /// Listens on a free port of 127.0.0.1
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state: Shared = Arc::default();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (accept_state, accept_stop) = (state.clone(), stop.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accept_stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let state = accept_state.clone();
+                    thread::spawn(move || serve(stream, state));
+                }
+            }
+        });
+
+        Ok(Self { addr, state, stop })
+    }
+
+    /// Value for the agent's CONSUMER_ENDPOINT
+    pub fn endpoint(&self) -> String {
+        format!("http://{}/api/v1/ingest", self.addr)
+    }
+
+    /// Answers ingest requests with `status` from now on (429 and 503 come
+    /// with a retry hint); None goes back to accepting
+    pub fn respond_with(&self, status: Option<u16>) {
+        self.state.0.lock().unwrap().status = status;
+    }
+
+    pub fn batches(&self) -> Vec<Batch> {
+        self.state.0.lock().unwrap().batches.clone()
+    }
+
+    /// Every metric received so far, in arrival order
+    pub fn metrics(&self) -> Vec<Metric> {
+        self.batches().into_iter().flat_map(|b| b.metrics).collect()
+    }
+
+    pub fn heartbeats(&self) -> Vec<Value> {
+        self.state.0.lock().unwrap().heartbeats.clone()
+    }
+
+    pub fn events(&self) -> Vec<Value> {
+        self.state.0.lock().unwrap().events.clone()
+    }
+
+    /// Forgets everything received so far
+    pub fn clear(&self) {
+        let mut state = self.state.0.lock().unwrap();
+        state.batches.clear();
+        state.heartbeats.clear();
+        state.events.clear();
+    }
+
+    /// Metrics of one type and key
+    pub fn find(&self, metric_type: &str, key: &str) -> Vec<Metric> {
+        self.metrics().into_iter()
+            .filter(|m| m.metric_type == metric_type && m.key == key)
+            .collect()
+    }
+
+    /// Distinct pod_id labels of container metrics
+    pub fn pod_ids(&self) -> BTreeSet<String> {
+        self.label_values("container", "pod_id")
+    }
+
+    /// Distinct values of `label` on metrics of `metric_type`
+    pub fn label_values(&self, metric_type: &str, label: &str) -> BTreeSet<String> {
+        self.metrics().into_iter()
+            .filter(|m| m.metric_type == metric_type)
+            .filter_map(|m| m.labels.get(label).cloned())
+            .collect()
+    }
+
+    /// Waits until at least `n` batches arrived; false on timeout
+    pub fn wait_for_batches(&self, n: usize, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let (lock, arrived) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        while state.batches.len() < n {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            state = arrived.wait_timeout(state, left).unwrap().0;
+        }
+        true
+    }
+
+    /// The last metric of this type and key; panics listing what did
+    /// arrive if there is none
+    pub fn assert_metric(&self, metric_type: &str, key: &str) -> Metric {
+        match self.find(metric_type, key).pop() {
+            Some(metric) => metric,
+            None => {
+                let seen: BTreeSet<String> = self.metrics().iter()
+                    .map(|m| format!("{}/{}", m.metric_type, m.key))
+                    .collect();
+                panic!("no {}/{} metric received; got {:?}", metric_type, key, seen);
+            }
+        }
+    }
+
+    /// Panics unless no metric of this type arrived
+    pub fn assert_no_metric(&self, metric_type: &str) {
+        let found: Vec<Metric> = self.metrics().into_iter().filter(|m| m.metric_type == metric_type).collect();
+        assert!(found.is_empty(), "expected no {} metrics, got {:?}", metric_type, found);
+    }
+}
+
+impl Drop for FakeConsumer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wakes the accept loop so it sees the flag
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+/// Serves requests on one keep-alive connection until it closes
+fn serve(stream: TcpStream, state: Shared) {
+    let mut writer = match stream.try_clone() {
+        Ok(w) => w,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    while let Ok(Some((path, body))) = read_request(&mut reader) {
+        let (status, reply) = handle(&path, &body, &state);
+        let response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            status, reason(status), reply.len(), reply);
+        if writer.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Path and body of the next request; None when the connection closed
+fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<Option<(String, Vec<u8>)>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let path = line.split_whitespace().nth(1).unwrap_or_default().to_string();
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some((path, body)))
+}
+
+fn handle(path: &str, body: &[u8], state: &Shared) -> (u16, String) {
+    let (lock, arrived) = &**state;
+    let json: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+    let mut state = lock.lock().unwrap();
+
+    if path.ends_with("/handshake") {
+        return (200, AGREEMENT.to_string());
+    }
+    if path.ends_with("/heartbeat") {
+        state.heartbeats.push(json);
+        return (200, "{}".to_string());
+    }
+    if path.ends_with("/events") {
+        if let Some(events) = json.get("events").and_then(Value::as_array) {
+            state.events.extend(events.iter().cloned());
+        }
+        return (200, "{}".to_string());
+    }
+
+    match state.status {
+        Some(status @ (429 | 503)) => return (status, r#"{"retry_after_ms":1000}"#.to_string()),
+        Some(status) => return (status, "{}".to_string()),
+        None => {}
+    }
+    state.batches.push(parse_batch(&json));
+    arrived.notify_all();
+    (200, "{}".to_string())
+}
+
+fn parse_batch(json: &Value) -> Batch {
+    let metrics = json.get("metrics").and_then(Value::as_array).map(|metrics| {
+        metrics.iter().map(|m| {
+            let text = |name: &str| m.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+            let mut labels = BTreeMap::new();
+            if let Some(fields) = m.as_object() {
+                for (name, value) in fields {
+                    if let (false, Some(value)) = (matches!(name.as_str(), "type" | "key"), value.as_str()) {
+                        labels.insert(name.clone(), value.to_string());
+                    }
+                }
+            }
+            Metric {
+                metric_type: text("type"),
+                key: text("key"),
+                value: m.get("value").and_then(Value::as_f64).unwrap_or_default(),
+                labels,
+            }
+        }).collect()
+    }).unwrap_or_default();
+
+    Batch {
+        node: json.get("node").and_then(Value::as_str).unwrap_or_default().to_string(),
+        metrics,
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        429 => "Too Many Requests",
+        503 => "Service Unavailable",
+        _ => "Error",
+    }
+}
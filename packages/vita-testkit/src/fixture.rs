@@ -0,0 +1,69 @@
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// A host filesystem in the layout `vita-agent record` writes: the /proc
+/// files, the kubepods cgroup trees and the kubelet volume directories of
+/// one node, relative to the fixture root. `vita-agent replay <path>` runs
+/// the collectors against it.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    name: String,
+    path: PathBuf,
+}
+
+impl Fixture {
+    /// Names of the fixtures shipped with this crate
+    pub const NAMES: &'static [&'static str] = &[
+        "kubeadm-v1-cgroupfs",
+        "gke-v2-systemd",
+        "eks-v1-systemd",
+        "k3s-v2-cgroupfs",
+    ];
+
+    /// A shipped fixture by name
+    pub fn get(name: &str) -> io::Result<Self> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name);
+        if !path.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no fixture named {}", name)));
+        }
+        Ok(Self { name: name.to_string(), path })
+    }
+
+    /// Every shipped fixture
+    pub fn all() -> Vec<Self> {
+        Self::NAMES.iter().filter_map(|name| Self::get(name).ok()).collect()
+    }
+
+    /// A snapshot directory from elsewhere, such as an unpacked bug report
+    pub fn from_dir(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        Self { name, path }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Node name the snapshot was recorded on
+    pub fn node(&self) -> Option<String> {
+        Some(self.manifest()?.get("node")?.as_str()?.to_string())
+    }
+
+    /// What the layout is and where it comes from
+    pub fn description(&self) -> String {
+        self.manifest()
+            .and_then(|m| Some(m.get("description")?.as_str()?.to_string()))
+            .unwrap_or_default()
+    }
+
+    fn manifest(&self) -> Option<Value> {
+        serde_json::from_slice(&fs::read(self.path.join("vita-snapshot.json")).ok()?).ok()
+    }
+}
@@ -0,0 +1,24 @@
+//! Test support for vita-agent: host filesystems captured from common
+//! Kubernetes distributions, a fake consumer that records what the agent
+//! sends, and a way to run the agent binary against both.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use vita_testkit::{Agent, FakeConsumer, Fixture};
+//!
+//! let consumer = FakeConsumer::start().unwrap();
+//! let fixture = Fixture::get("gke-v2-systemd").unwrap();
+//! let _agent = Agent::replay("target/debug/vita-agent", &fixture, &consumer).unwrap();
+//!
+//! assert!(consumer.wait_for_batches(2, Duration::from_secs(10)));
+//! consumer.assert_metric("node_cpu", "user");
+//! assert!(!consumer.pod_ids().is_empty());
+//! ```
+
+mod agent;
+mod consumer;
+mod fixture;
+
+pub use agent::Agent;
+pub use consumer::{Batch, FakeConsumer, Metric};
+pub use fixture::Fixture;
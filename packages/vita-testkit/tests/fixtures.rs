@@ -0,0 +1,136 @@
+//! Replays each fixture through the agent and checks what the fake consumer
+//! receives. The agent under test is $VITA_AGENT if set, otherwise the debug
+//! build of packages/vita-agent, built first so a collector change is what
+//! gets tested.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::Duration;
+use vita_testkit::{Agent, FakeConsumer, Fixture};
+
+const VOLUMES: &[&str] = &[
+    "cache",
+    "kube-api-access-14c25",
+    "kube-api-access-4b5e5",
+    "kube-api-access-4bb24",
+    "pvc-9cadd1bb-6170-4dec-addd-96a659e8c304",
+];
+
+fn agent_binary() -> &'static PathBuf {
+    static BINARY: OnceLock<PathBuf> = OnceLock::new();
+    BINARY.get_or_init(|| {
+        if let Some(binary) = std::env::var_os("VITA_AGENT") {
+            return PathBuf::from(binary);
+        }
+        let agent = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../vita-agent");
+        let mut build = Command::new(env!("CARGO"));
+        build.arg("build").current_dir(&agent);
+        // What cargo sets for this test would change the agent's build
+        // fingerprints, and the next plain `cargo build` would rebuild
+        for (name, _) in std::env::vars_os() {
+            if name.to_str().is_some_and(|n| n.starts_with("CARGO_") && n != "CARGO_HOME") {
+                build.env_remove(name);
+            }
+        }
+        let status = build.status()
+            .expect("running cargo build for vita-agent");
+        assert!(status.success(), "building vita-agent failed");
+        agent.join("target/debug/vita-agent")
+    })
+}
+
+/// Runs the agent against `name` until two batches arrived
+fn replay(name: &str) -> (Fixture, FakeConsumer) {
+    let fixture = Fixture::get(name).unwrap();
+    let consumer = FakeConsumer::start().expect("starting the fake consumer");
+    let mut agent = Agent::replay(agent_binary(), &fixture, &consumer).expect("starting the agent");
+    let received = consumer.wait_for_batches(2, Duration::from_secs(15));
+    assert!(agent.running(), "agent exited replaying {}", name);
+    assert!(received, "agent sent {} batches replaying {}", consumer.batches().len(), name);
+    (fixture, consumer)
+}
+
+fn set(values: &[&str]) -> BTreeSet<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+/// What every layout reports: the node's name, its /proc metrics and the
+/// kubelet volumes
+fn assert_node(fixture: &Fixture, consumer: &FakeConsumer, disks: &[&str], interfaces: &[&str]) {
+    let node = fixture.node().expect("fixture names its node");
+    assert!(consumer.batches().iter().all(|b| b.node == node));
+
+    for key in ["user", "sys", "idle", "iowait"] {
+        consumer.assert_metric("node_cpu", key);
+    }
+    let total = consumer.assert_metric("node_mem", "total_mb");
+    assert!(total.value > 0.0);
+    consumer.assert_metric("node_boot", "boot_time");
+    assert_eq!(consumer.label_values("node_disk", "device"), set(disks));
+    assert_eq!(consumer.label_values("node_net", "device"), set(interfaces));
+
+    for key in ["total_mb", "used_mb", "free_mb"] {
+        consumer.assert_metric("pvc_usage", key);
+    }
+    assert_eq!(consumer.label_values("pvc_usage", "volume"), set(VOLUMES));
+}
+
+#[test]
+fn kubeadm_v1_cgroupfs() {
+    let (fixture, consumer) = replay("kubeadm-v1-cgroupfs");
+    assert_node(&fixture, &consumer, &["sda", "sda1", "sdb"], &["cni0", "eth0"]);
+    assert_eq!(consumer.pod_ids(), set(&[
+        "pod5c414c03-3a9b-481b-afbe-4cf8b6b249b3",
+        "pod65f9a74d-76b8-4c55-aa9a-982f716a57f6",
+        "pod9c3a4c09-58e8-4a56-ae3c-6495ffcae1c1",
+    ]));
+    consumer.assert_metric("container", "cpu_ms");
+    consumer.assert_metric("container", "mem_mb");
+    consumer.assert_metric("container_cpuset", "pinned");
+}
+
+#[test]
+fn gke_v2_systemd() {
+    let (fixture, consumer) = replay("gke-v2-systemd");
+    assert_node(&fixture, &consumer, &["sda", "sda1"], &["eth0", "gke3f2a9c1b7e4"]);
+    // Guaranteed pods sit directly under kubepods.slice, the others under
+    // their QoS slice
+    assert_eq!(consumer.pod_ids(), set(&[
+        "kubepods-besteffort-pod1d86cdb0_9262_4206_ac6f_07533501b20b.slice",
+        "kubepods-burstable-podb6a6d2b0_c12a_42ad_ad9e_449eaf5acd20.slice",
+        "kubepods-podda6f0192_8024_4d85_a67c_809bc8e80d14.slice",
+    ]));
+    consumer.assert_metric("container", "cpu_ms");
+    consumer.assert_metric("node_device", "registered");
+    consumer.assert_metric("node_device", "allocated");
+    consumer.assert_metric("pod_device", "nvidia.com/gpu");
+}
+
+#[test]
+fn eks_v1_systemd() {
+    let (fixture, consumer) = replay("eks-v1-systemd");
+    assert_node(&fixture, &consumer, &["nvme0n1", "nvme0n1p1", "nvme1n1", "nvme1n1p1"], &["eni1a2b3c4d5e6", "eth0", "eth1"]);
+    assert_eq!(consumer.pod_ids(), set(&[
+        "kubepods-besteffort-pod3a623325_62bd_4536_a0c1_684709f466b8.slice",
+        "kubepods-burstable-pod2e001763_1275_4ef4_ab5a_8384df841527.slice",
+        "kubepods-podb4db0197_0719_492c_ae4d_e9cbc10a0ca8.slice",
+    ]));
+    consumer.assert_metric("container", "cpu_ms");
+}
+
+#[test]
+fn k3s_v2_cgroupfs() {
+    let (fixture, consumer) = replay("k3s-v2-cgroupfs");
+    assert_node(&fixture, &consumer, &["vda", "vda1"], &["cni0", "enp1s0", "flannel.1"]);
+    // The cgroupfs driver names pod cgroups pod<uid>, under kubepods/ and
+    // its QoS directories
+    assert_eq!(consumer.pod_ids(), set(&[
+        "pod22f891fd-d473-41a2-ae39-9028bc74bf29",
+        "podb1f30a28-567a-4295-ab43-eff996654964",
+        "poddea77171-4c64-4320-a966-8e7e6934dc96",
+    ]));
+    consumer.assert_metric("container", "cpu_ms");
+    consumer.assert_metric("container", "mem_mb");
+}
@@ -0,0 +1,26 @@
+//! Prints the workloads using the most memory over the last hour.
+//! `cargo run --example top_workloads [consumer URL]`; VITAKUBE_TOKEN is
+//! sent as the tenant token if set.
+
+use vitakube_client::{Client, MetricFilter, WorkloadFilter};
+
+#[tokio::main]
+async fn main() -> vitakube_client::Result<()> {
+    let url = std::env::args().nth(1).unwrap_or_else(|| "http://localhost:8080".to_string());
+    let mut client = Client::new(&url)?;
+    if let Ok(token) = std::env::var("VITAKUBE_TOKEN") {
+        client = client.with_token(token);
+    }
+
+    let filter = MetricFilter { metric_type: Some("mem_mb".into()), ..Default::default() };
+    let usage = client.workload_usage(&WorkloadFilter::default(), &filter, None).await?;
+
+    let mut peaks = usage.iter()
+        .map(|u| (u.points.iter().map(|p| p.value).fold(0.0, f64::max), &u.workload))
+        .collect::<Vec<_>>();
+    peaks.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (peak, workload) in peaks.iter().take(10) {
+        println!("{:>10.1} MB  {}", peak, workload);
+    }
+    Ok(())
+}
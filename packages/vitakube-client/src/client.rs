@@ -0,0 +1,307 @@
+use bytes::Bytes;
+use futures_util::stream::{self, Stream};
+use reqwest::{Response, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::models::*;
+
+/// The dashboard API of a consumer: lists, live metrics, exports and
+/// PromQL. Cheap to clone; clones share connections.
+#[derive(Clone, Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    base: Url,
+    token: Option<String>,
+}
+
+impl Client {
+    /// `base_url` is the consumer's address, e.g. `http://vita-consumer:8080`
+    pub fn new(base_url: &str) -> Result<Self> {
+        let mut base = Url::parse(base_url).map_err(|e| Error::InvalidUrl(format!("{}: {}", base_url, e)))?;
+        if base.cannot_be_a_base() {
+            return Err(Error::InvalidUrl(base_url.to_string()));
+        }
+        // Joined paths would otherwise replace the last segment
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        Ok(Self { http: reqwest::Client::new(), base, token: None })
+    }
+
+    /// Sends a tenant's token, or the admin token, as the consumer requires
+    /// once tenants are configured
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Uses `http` for requests, e.g. to trust a private CA or set timeouts
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub async fn nodes(&self) -> Result<Vec<Node>> {
+        self.get_json("api/v1/nodes", &[]).await
+    }
+
+    pub async fn namespaces(&self) -> Result<Vec<Namespace>> {
+        self.get_json("api/v1/namespaces", &[]).await
+    }
+
+    /// Deployments, in one namespace if given by ID
+    pub async fn deployments(&self, namespace: Option<i64>) -> Result<Vec<Deployment>> {
+        let filter = PodFilter { namespace, ..Default::default() };
+        self.get_json("api/v1/deployments", &filter.params()).await
+    }
+
+    pub async fn pods(&self, filter: &PodFilter) -> Result<Vec<Pod>> {
+        self.get_json("api/v1/pods", &filter.params()).await
+    }
+
+    /// PersistentVolumeClaims, in one namespace if given by ID
+    pub async fn pvcs(&self, namespace: Option<i64>) -> Result<Vec<Pvc>> {
+        let filter = PodFilter { namespace, ..Default::default() };
+        self.get_json("api/v1/pvcs", &filter.params()).await
+    }
+
+    pub async fn workloads(&self, filter: &WorkloadFilter) -> Result<Vec<Workload>> {
+        self.get_json("api/v1/workloads", &filter.params()).await
+    }
+
+    /// Pod metrics rolled up to their workloads, in buckets of `step`
+    /// (a minute by default)
+    pub async fn workload_usage(&self, workloads: &WorkloadFilter, filter: &MetricFilter, step: Option<Duration>) -> Result<Vec<WorkloadUsage>> {
+        let mut params = filter.params();
+        params.retain(|(name, _)| name != "workload");
+        params.extend(workloads.params());
+        if let Some(step) = step {
+            params.push(("step".to_string(), step.as_secs().to_string()));
+        }
+        self.get_json("api/v1/workloads/usage", &params).await
+    }
+
+    /// How much of a range each node and pod has data for, in buckets of
+    /// `step` (rounded up to whole minutes)
+    pub async fn availability(&self, filter: &MetricFilter, step: Option<Duration>) -> Result<Availability> {
+        let mut params = filter.params();
+        if let Some(step) = step {
+            params.push(("step".to_string(), step.as_secs().to_string()));
+        }
+        self.get_json("api/v1/availability", &params).await
+    }
+
+    pub async fn events(&self, filter: &EventFilter) -> Result<Vec<Event>> {
+        self.get_json("api/v1/events", &filter.params()).await
+    }
+
+    pub async fn node_events(&self, filter: &NodeEventFilter) -> Result<Vec<NodeEvent>> {
+        self.get_json("api/v1/node-events", &filter.params()).await
+    }
+
+    /// The pods with samples in the last five seconds
+    pub async fn live(&self, filter: &PodFilter) -> Result<LiveMetrics> {
+        self.get_json("api/v1/metrics/live", &filter.params()).await
+    }
+
+    /// Every raw sample matching `filter`, parsed as the consumer streams
+    /// them, so memory stays flat however large the range
+    pub async fn stream(&self, filter: &MetricFilter) -> Result<impl Stream<Item = Result<Row>>> {
+        let mut params = filter.params();
+        params.push(("format".to_string(), "ndjson".to_string()));
+        let response = self.get("api/v1/export", &params).await?;
+        Ok(rows(response))
+    }
+
+    /// One page of about `limit` raw samples, continuing after `cursor`
+    /// from the previous page with the same filter
+    pub async fn export_page(&self, filter: &MetricFilter, limit: usize, cursor: Option<&str>) -> Result<Page> {
+        let mut params = filter.params();
+        params.push(("format".to_string(), "ndjson".to_string()));
+        params.push(("limit".to_string(), limit.to_string()));
+        if let Some(cursor) = cursor {
+            params.push(("cursor".to_string(), cursor.to_string()));
+        }
+        let response = self.get("api/v1/export", &params).await?;
+        let next_cursor = response.headers().get("X-Next-Cursor")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = response.bytes().await?;
+        let mut rows = VecDeque::new();
+        parse_lines(&mut body.to_vec(), &mut rows, true)?;
+        Ok(Page { rows: rows.into(), next_cursor })
+    }
+
+    /// The raw samples matching `filter` as a file in `format`, in chunks
+    /// as they arrive
+    pub async fn export(&self, filter: &MetricFilter, format: ExportFormat) -> Result<impl Stream<Item = Result<Bytes>>> {
+        let mut params = filter.params();
+        params.push(("format".to_string(), format.as_str().to_string()));
+        let response = self.get("api/v1/export", &params).await?;
+        Ok(stream::try_unfold(response, |mut response| async move {
+            Ok(response.chunk().await?.map(|chunk| (chunk, response)))
+        }))
+    }
+
+    /// Evaluates a PromQL expression at `time` (unix seconds, now if None)
+    pub async fn query(&self, query: &str, time: Option<f64>) -> Result<QueryResult> {
+        let mut params = vec![("query".to_string(), query.to_string())];
+        if let Some(time) = time {
+            params.push(("time".to_string(), time.to_string()));
+        }
+        let data = self.prom("api/v1/query", &params).await?;
+        match data.result_type.as_str() {
+            "scalar" => Ok(QueryResult::Scalar(point(&data.result)?)),
+            "vector" => {
+                let samples: Vec<PromSample> = decode(data.result)?;
+                samples.into_iter()
+                    .map(|s| Ok(Sample { point: point(&s.value)?, metric: s.metric }))
+                    .collect::<Result<_>>()
+                    .map(QueryResult::Vector)
+            }
+            other => Err(Error::Decode(format!("unknown result type {}", other))),
+        }
+    }
+
+    /// Evaluates a PromQL expression at every `step` from `start` to `end`
+    /// (unix seconds), at most seven days apart
+    pub async fn query_range(&self, query: &str, start: f64, end: f64, step: Duration) -> Result<Vec<Series>> {
+        let params = [
+            ("query".to_string(), query.to_string()),
+            ("start".to_string(), start.to_string()),
+            ("end".to_string(), end.to_string()),
+            ("step".to_string(), step.as_secs_f64().to_string()),
+        ];
+        let data = self.prom("api/v1/query_range", &params).await?;
+        let series: Vec<PromSeries> = decode(data.result)?;
+        series.into_iter()
+            .map(|s| Ok(Series { points: s.values.iter().map(point).collect::<Result<_>>()?, metric: s.metric }))
+            .collect()
+    }
+
+    async fn send(&self, path: &str, params: &[(String, String)]) -> Result<Response> {
+        let url = self.base.join(path).map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        let mut request = self.http.get(url).query(params);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        Ok(request.send().await?)
+    }
+
+    /// A successful response, or the consumer's error message
+    async fn get(&self, path: &str, params: &[(String, String)]) -> Result<Response> {
+        let response = self.send(path, params).await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<Value>(&body).ok()
+            .and_then(|v| v.get("error")?.as_str().map(str::to_string))
+            .unwrap_or(body);
+        Err(Error::Api { status: status.as_u16(), message })
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, path: &str, params: &[(String, String)]) -> Result<T> {
+        let body = self.get(path, params).await?.bytes().await?;
+        serde_json::from_slice(&body).map_err(|e| Error::Decode(e.to_string()))
+    }
+
+    /// The data of a Prometheus API response, which carries its errors in
+    /// the envelope
+    async fn prom(&self, path: &str, params: &[(String, String)]) -> Result<PromData> {
+        let response = self.send(path, params).await?;
+        let status = response.status().as_u16();
+        let body = response.bytes().await?;
+        let envelope: PromResponse = match serde_json::from_slice(&body) {
+            Ok(envelope) => envelope,
+            // e.g. a tenant's 401, answered before the query is looked at
+            Err(_) if status >= 400 => return Err(Error::Api { status, message: String::from_utf8_lossy(&body).into_owned() }),
+            Err(e) => return Err(Error::Decode(e.to_string())),
+        };
+        match (envelope.status.as_str(), envelope.data) {
+            ("success", Some(data)) => Ok(data),
+            _ => Err(Error::Query { status, error_type: envelope.error_type, message: envelope.error }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PromResponse {
+    status: String,
+    data: Option<PromData>,
+    #[serde(default, rename = "errorType")]
+    error_type: String,
+    #[serde(default)]
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct PromData {
+    #[serde(rename = "resultType")]
+    result_type: String,
+    result: Value,
+}
+
+#[derive(Deserialize)]
+struct PromSample {
+    metric: Labels,
+    value: Value,
+}
+
+#[derive(Deserialize)]
+struct PromSeries {
+    metric: Labels,
+    values: Vec<Value>,
+}
+
+fn decode<T: DeserializeOwned>(value: Value) -> Result<T> {
+    serde_json::from_value(value).map_err(|e| Error::Decode(e.to_string()))
+}
+
+/// A point as Prometheus writes it: [unix seconds, "value"]
+fn point(value: &Value) -> Result<Point> {
+    let invalid = || Error::Decode(format!("invalid point {}", value));
+    let time = value.get(0).and_then(Value::as_f64).ok_or_else(invalid)?;
+    // Parses +Inf, -Inf and NaN too
+    let value = value.get(1).and_then(Value::as_str).and_then(|v| v.parse().ok()).ok_or_else(invalid)?;
+    Ok(Point { time, value })
+}
+
+/// The rows of an NDJSON export as its chunks arrive
+fn rows(response: Response) -> impl Stream<Item = Result<Row>> {
+    stream::try_unfold((response, Vec::new(), VecDeque::new(), false), |(mut response, mut buf, mut ready, mut done)| async move {
+        loop {
+            if let Some(row) = ready.pop_front() {
+                return Ok(Some((row, (response, buf, ready, done))));
+            }
+            if done {
+                return Ok(None);
+            }
+            match response.chunk().await? {
+                Some(chunk) => buf.extend_from_slice(&chunk),
+                None => done = true,
+            }
+            parse_lines(&mut buf, &mut ready, done)?;
+        }
+    })
+}
+
+/// Moves the complete lines of `buf` into `rows`, and the rest too at the
+/// end of the body
+fn parse_lines(buf: &mut Vec<u8>, rows: &mut VecDeque<Row>, end: bool) -> Result<()> {
+    let complete = if end { buf.len() } else { buf.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1) };
+    for line in buf[..complete].split(|&b| b == b'\n') {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        rows.push_back(serde_json::from_slice(line).map_err(|e| Error::Decode(e.to_string()))?);
+    }
+    buf.drain(..complete);
+    Ok(())
+}
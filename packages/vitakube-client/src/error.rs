@@ -0,0 +1,24 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The consumer answered with an error status and message
+    #[error("consumer answered {status}: {message}")]
+    Api { status: u16, message: String },
+
+    /// A PromQL query was rejected or failed; `error_type` is bad_data,
+    /// execution (too expensive), unavailable or internal, as Prometheus has
+    #[error("query failed ({error_type}): {message}")]
+    Query { status: u16, error_type: String, message: String },
+
+    #[error("unexpected response: {0}")]
+    Decode(String),
+
+    #[error("invalid consumer URL: {0}")]
+    InvalidUrl(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
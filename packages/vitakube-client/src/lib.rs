@@ -0,0 +1,36 @@
+//! Typed async client for the VitaKube consumer API: the node, pod,
+//! workload and event lists, live metrics, raw exports and PromQL queries.
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use vitakube_client::{Client, MetricFilter, QueryResult};
+//!
+//! # async fn run() -> vitakube_client::Result<()> {
+//! let client = Client::new("http://vita-consumer:8080")?;
+//!
+//! if let QueryResult::Vector(samples) = client.query("sum by (node) (mem_mb)", None).await? {
+//!     for sample in samples {
+//!         println!("{}: {} MB", sample.metric["node"], sample.point.value);
+//!     }
+//! }
+//!
+//! let filter = MetricFilter { metric_type: Some("cpu_ms".into()), ..Default::default() };
+//! let mut rows = Box::pin(client.stream(&filter).await?);
+//! while let Some(row) = rows.next().await {
+//!     let row = row?;
+//!     println!("{} {} {} {} {}", row.time, row.node, row.key, row.device, row.value);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The consumer evaluates recording rules but not alerting rules, so there
+//! is no alerts API; alert on the results of [`Client::query`] instead.
+
+mod client;
+mod error;
+mod models;
+
+pub use client::Client;
+pub use error::{Error, Result};
+pub use models::*;
@@ -0,0 +1,438 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A cluster node, with its agent as last heard from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub id: i64,
+    pub name: String,
+    pub uid: String,
+    /// None for a node whose agent never sent a heartbeat
+    #[serde(default)]
+    pub agent: Option<AgentStatus>,
+}
+
+/// `status` is "up" while heartbeats arrive and "down" after three are missed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentStatus {
+    pub status: String,
+    pub version: String,
+    pub collectors: Vec<String>,
+    pub config_hash: String,
+    pub uptime_secs: i64,
+    /// Unix seconds
+    pub last_seen: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Namespace {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deployment {
+    pub id: i64,
+    pub name: String,
+    pub uid: String,
+    pub namespace_id: i64,
+    pub namespace: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pod {
+    pub id: i64,
+    pub name: String,
+    pub uid: String,
+    pub namespace_id: i64,
+    pub namespace: String,
+    pub node_id: i64,
+    pub node: String,
+    #[serde(default)]
+    pub deployment_id: Option<i64>,
+    #[serde(default)]
+    pub deployment: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pvc {
+    pub id: i64,
+    pub name: String,
+    pub uid: String,
+    pub namespace_id: i64,
+    pub namespace: String,
+}
+
+/// A Deployment, StatefulSet or DaemonSet with pods the consumer has seen
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Workload {
+    /// `<kind>/<namespace>/<name>`
+    pub workload: String,
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    pub pods: usize,
+}
+
+/// One metric of a workload, summed over its pods per bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkloadUsage {
+    pub workload: String,
+    pub metric_type: String,
+    pub points: Vec<UsagePoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct UsagePoint {
+    /// Unix seconds, start of the bucket
+    #[serde(rename = "t")]
+    pub time: i64,
+    #[serde(rename = "v")]
+    pub value: f64,
+}
+
+/// A Kubernetes event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub uid: String,
+    pub count: i64,
+    pub namespace: String,
+    pub kind: String,
+    pub name: String,
+    pub reason: String,
+    pub message: String,
+    /// "Normal" or "Warning"
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub source: String,
+    /// Unix seconds
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+/// A state change an agent saw on its node, e.g. an OOM kill
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeEvent {
+    pub node: String,
+    /// Unix seconds
+    #[serde(rename = "ts")]
+    pub timestamp: i64,
+    /// oom_kill, container_appeared, ...
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(default)]
+    pub pod_id: String,
+    #[serde(default)]
+    pub pod_uid: String,
+    #[serde(default)]
+    pub volume: String,
+    #[serde(default)]
+    pub container_id: String,
+    #[serde(default)]
+    pub device: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+/// The pods with samples in the last five seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveMetrics {
+    /// Unix seconds
+    pub timestamp: i64,
+    pub pods: Vec<LivePod>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LivePod {
+    pub id: i64,
+    pub name: String,
+    pub uid: String,
+    pub namespace: String,
+    pub node: String,
+    #[serde(default)]
+    pub deployment: Option<String>,
+    #[serde(default)]
+    pub containers: Vec<LiveContainer>,
+    #[serde(default)]
+    pub pvcs: Vec<LivePvc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveContainer {
+    pub id: String,
+    pub cpu_ms: f64,
+    pub mem_mb: f64,
+    pub mem_limit_mb: f64,
+    pub swap_mb: f64,
+    pub swap_limit_mb: f64,
+    pub zswap_mb: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LivePvc {
+    pub id: i64,
+    pub name: String,
+    pub volume_name: String,
+    pub total_mb: f64,
+    pub used_mb: f64,
+    pub free_mb: f64,
+}
+
+/// How much of a range each node and pod has data for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Availability {
+    pub start: i64,
+    pub end: i64,
+    /// Seconds
+    pub step: i64,
+    pub nodes: Vec<NodeAvailability>,
+    pub pods: Vec<PodAvailability>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeAvailability {
+    pub node: String,
+    pub buckets: usize,
+    pub with_metrics: usize,
+    pub with_heartbeat: usize,
+    pub availability_pct: f64,
+    pub gaps: Vec<Gap>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodAvailability {
+    pub pod_id: i64,
+    pub node: String,
+    pub first: i64,
+    pub last: i64,
+    pub buckets: usize,
+    pub present: usize,
+    pub availability_pct: f64,
+    pub gaps: Vec<Gap>,
+}
+
+/// Missing buckets with the same reason (no_metrics, agent_down, ...)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gap {
+    pub start: i64,
+    pub end: i64,
+    pub reason: String,
+}
+
+/// One raw sample of an export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Row {
+    /// Unix seconds
+    pub time: i64,
+    pub node: String,
+    pub resource_id: i64,
+    /// What the agent collected, e.g. node_disk; empty for recording rules
+    #[serde(rename = "type", default)]
+    pub type_: String,
+    /// Which of its values, e.g. reads, as `MetricFilter::metric_type` matches
+    pub key: String,
+    #[serde(default)]
+    pub device: String,
+    #[serde(default)]
+    pub container_id: String,
+    #[serde(default)]
+    pub volume: String,
+    pub value: f64,
+}
+
+/// One page of an export; pass `next_cursor` back for the next
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub rows: Vec<Row>,
+    /// None on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// A PromQL series' labels: __name__, node, resource_id, pod, namespace
+/// and the node's labels
+pub type Labels = BTreeMap<String, String>;
+
+/// A point of a PromQL result
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    /// Unix seconds
+    pub time: f64,
+    pub value: f64,
+}
+
+/// The value of an instant query
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryResult {
+    /// A number expression, e.g. `1 + 1`
+    Scalar(Point),
+    Vector(Vec<Sample>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub metric: Labels,
+    pub point: Point,
+}
+
+/// A series of a range query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub metric: Labels,
+    pub points: Vec<Point>,
+}
+
+/// Filters of the export, usage and availability endpoints. The range
+/// defaults to the last hour and may span at most seven days.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricFilter {
+    /// Unix seconds
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub node: Option<String>,
+    pub resource_id: Option<i64>,
+    /// The key, e.g. cpu_ms
+    pub metric_type: Option<String>,
+    /// The agent's metric type, e.g. container
+    pub type_: Option<String>,
+    /// Node labels, e.g. zone=eu-1a
+    pub labels: BTreeMap<String, String>,
+    /// All pods a workload has had, `<kind>/<namespace>/<name>`
+    pub workload: Option<String>,
+}
+
+impl MetricFilter {
+    pub(crate) fn params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        push(&mut params, "start", self.start);
+        push(&mut params, "end", self.end);
+        push(&mut params, "node", self.node.as_ref());
+        push(&mut params, "resource_id", self.resource_id);
+        push(&mut params, "metric_type", self.metric_type.as_ref());
+        push(&mut params, "type", self.type_.as_ref());
+        push(&mut params, "workload", self.workload.as_ref());
+        for (name, value) in &self.labels {
+            params.push((format!("label.{}", name), value.clone()));
+        }
+        params
+    }
+}
+
+/// Filters of the Kubernetes event list, most recent first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    pub namespace: Option<String>,
+    pub kind: Option<String>,
+    pub name: Option<String>,
+    /// Normal or Warning
+    pub type_: Option<String>,
+    /// Unix seconds, matched against the last occurrence
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    /// At most 1000
+    pub limit: Option<usize>,
+}
+
+impl EventFilter {
+    pub(crate) fn params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        push(&mut params, "namespace", self.namespace.as_ref());
+        push(&mut params, "kind", self.kind.as_ref());
+        push(&mut params, "name", self.name.as_ref());
+        push(&mut params, "type", self.type_.as_ref());
+        push(&mut params, "start", self.start);
+        push(&mut params, "end", self.end);
+        push(&mut params, "limit", self.limit);
+        params
+    }
+}
+
+/// Filters of the node event list, most recent first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeEventFilter {
+    pub node: Option<String>,
+    pub type_: Option<String>,
+    pub pod_uid: Option<String>,
+    /// Unix seconds
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    /// At most 1000
+    pub limit: Option<usize>,
+}
+
+impl NodeEventFilter {
+    pub(crate) fn params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        push(&mut params, "node", self.node.as_ref());
+        push(&mut params, "type", self.type_.as_ref());
+        push(&mut params, "pod_uid", self.pod_uid.as_ref());
+        push(&mut params, "start", self.start);
+        push(&mut params, "end", self.end);
+        push(&mut params, "limit", self.limit);
+        params
+    }
+}
+
+/// Filters of the pod list and live metrics, by ID
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PodFilter {
+    pub namespace: Option<i64>,
+    pub node: Option<i64>,
+    pub deployment: Option<i64>,
+    /// Live metrics only
+    pub pod: Option<i64>,
+}
+
+impl PodFilter {
+    pub(crate) fn params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        push(&mut params, "namespace", self.namespace);
+        push(&mut params, "node", self.node);
+        push(&mut params, "deployment", self.deployment);
+        push(&mut params, "pod", self.pod);
+        params
+    }
+}
+
+/// Selects workloads by key, or by kind and namespace
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorkloadFilter {
+    /// `<kind>/<namespace>/<name>`
+    pub workload: Option<String>,
+    pub kind: Option<String>,
+    pub namespace: Option<String>,
+}
+
+impl WorkloadFilter {
+    pub(crate) fn params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        push(&mut params, "workload", self.workload.as_ref());
+        push(&mut params, "kind", self.kind.as_ref());
+        push(&mut params, "namespace", self.namespace.as_ref());
+        params
+    }
+}
+
+/// Formats of a raw export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+fn push(params: &mut Vec<(String, String)>, name: &str, value: Option<impl ToString>) {
+    if let Some(value) = value {
+        params.push((name.to_string(), value.to_string()));
+    }
+}